@@ -0,0 +1,56 @@
+//! Deploy a package from a throwaway repository and inspect the report.
+//!
+//! Run with `cargo run --example deploy`.
+
+use dotr::{Config, Context, DeployUpdateArgs, InitPreset, Package};
+
+fn main() -> Result<(), anyhow::Error> {
+    let repo = std::env::temp_dir().join(format!("dotr_example_{}", std::process::id()));
+    let home = repo.join("home");
+    std::fs::create_dir_all(&repo)?;
+    Config::init(&repo, InitPreset::Minimal)?;
+    std::fs::write(
+        repo.join("dotfiles/f_gitconfig"),
+        "[user]\n    name = {{ NAME }}\n",
+    )?;
+
+    let mut table = toml::Table::new();
+    table.insert("src".into(), "dotfiles/f_gitconfig".into());
+    table.insert(
+        "dest".into(),
+        home.join(".gitconfig").to_string_lossy().as_ref().into(),
+    );
+    let mut config = Config::from_path(&repo)?;
+    config
+        .variables
+        .insert("NAME".into(), "Example User".into());
+    config.packages.insert(
+        "f_gitconfig".into(),
+        Package::from_table("f_gitconfig", &table)?,
+    );
+    config.save(&repo)?;
+
+    let ctx = Context::from_config(&repo, &config)?;
+    let args = DeployUpdateArgs {
+        no_git_check: true,
+        ..Default::default()
+    };
+    // Fails with the error of the package that failed, if any
+    let report = config.deploy_packages(&ctx, &args)?.into_result()?;
+    for package in report.packages.iter() {
+        println!("{}:", package.name);
+        for dest in package.deployed.iter() {
+            println!("  deployed {}", dest.display());
+        }
+        for dest in package.skipped.iter() {
+            println!("  up to date {}", dest.display());
+        }
+    }
+
+    // A second run finds nothing to do
+    let report = config.deploy_packages(&ctx, &args)?.into_result()?;
+    assert!(report.packages.iter().all(|p| p.deployed.is_empty()));
+
+    std::fs::remove_dir_all(&repo)?;
+    Ok(())
+}
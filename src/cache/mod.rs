@@ -0,0 +1,123 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+use toml::Table;
+
+use crate::{manifest::sha256_bytes, package::TEMPLATES_DIR};
+
+pub const CACHE_DIR: &str = ".dotr";
+pub const CACHE_FILE: &str = "cache.toml";
+
+// Remembers what each deployed file looked like, so an unchanged file can be
+// skipped without reading or rendering its source.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct DeployCache {
+    config_hash: String, // Hash of config.toml, its included files and templates/, the whole cache is dropped when they change
+    #[serde(default)]
+    entries: BTreeMap<String, CacheEntry>, // The key is the dest path
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CacheEntry {
+    pub src_mtime: String,
+    pub vars_hash: Option<String>, // Only set for templated sources
+    pub content_hash: String,      // Hash of the content written to dest
+}
+
+impl DeployCache {
+    /// Load the cache for a working directory. A missing or unreadable cache,
+    /// or one recorded for a different config.toml, `included` files or
+    /// shared templates, gives an empty cache.
+    pub fn load(working_dir: &Path, included: &[PathBuf]) -> Self {
+        let config_hash = config_hash(working_dir, included);
+        let cache = std::fs::read_to_string(cache_path(working_dir))
+            .ok()
+            .and_then(|content| toml::from_str::<DeployCache>(&content).ok());
+        match cache {
+            Some(cache) if cache.config_hash == config_hash => cache,
+            _ => Self {
+                config_hash,
+                entries: BTreeMap::new(),
+            },
+        }
+    }
+
+    pub fn save(&self, working_dir: &Path) -> Result<(), anyhow::Error> {
+        let path = cache_path(working_dir);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, dest: &Path) -> Option<&CacheEntry> {
+        self.entries.get(dest.to_string_lossy().as_ref())
+    }
+
+    pub fn insert(&mut self, dest: &Path, entry: CacheEntry) {
+        self.entries
+            .insert(dest.to_string_lossy().to_string(), entry);
+    }
+
+    pub fn remove(&mut self, dest: &Path) {
+        self.entries.remove(dest.to_string_lossy().as_ref());
+    }
+}
+
+pub fn cache_path(working_dir: &Path) -> PathBuf {
+    working_dir.join(CACHE_DIR).join(CACHE_FILE)
+}
+
+/// Hash of a file, or of every file under a directory with its relative
+/// path, to tell whether anything in it changed.
+pub fn hash_path(path: &Path) -> Result<String, anyhow::Error> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut files: Vec<PathBuf> = walkdir::WalkDir::new(path)
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .collect();
+    files.sort();
+    for file in files {
+        bytes.extend(file.strip_prefix(path)?.to_string_lossy().as_bytes());
+        bytes.push(0);
+        bytes.extend(std::fs::read(&file)?);
+        bytes.push(0);
+    }
+    Ok(sha256_bytes(&bytes))
+}
+
+pub fn hash_variables(variables: &Table) -> String {
+    sha256_bytes(toml::to_string(variables).unwrap_or_default().as_bytes())
+}
+
+/// Modification time of a file as `secs.nanos`, if it can be read.
+pub fn file_mtime(path: &Path) -> Option<String> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    let since_epoch = modified.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!(
+        "{}.{:09}",
+        since_epoch.as_secs(),
+        since_epoch.subsec_nanos()
+    ))
+}
+
+fn config_hash(working_dir: &Path, included: &[PathBuf]) -> String {
+    let mut content = std::fs::read(working_dir.join("config.toml")).unwrap_or_default();
+    for file in included {
+        content.extend(std::fs::read(working_dir.join(file)).unwrap_or_default());
+    }
+    // Any template can include or import the shared ones
+    let templates_dir = working_dir.join(TEMPLATES_DIR);
+    if templates_dir.is_dir() {
+        content.extend(hash_path(&templates_dir).unwrap_or_default().as_bytes());
+    }
+    sha256_bytes(&content)
+}
@@ -0,0 +1,218 @@
+//! Shell completion scripts.
+//!
+//! The static part comes from clap_complete. Values of `--packages` and
+//! `--profile` are then wired to `dotr __complete <kind>`, which reads the
+//! names from config.toml at tab time.
+
+use std::path::Path;
+
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+use super::{Cli, CompleteKind};
+use crate::config::Config;
+
+// A flag whose values are package or profile names
+struct DynamicArg {
+    // Subcommand path joined by ';', e.g. "dotr;deploy"
+    path: String,
+    flags: Vec<String>,
+    value_name: String,
+    kind: &'static str,
+}
+
+pub fn generate_script(shell: Shell) -> Result<String, anyhow::Error> {
+    let mut cmd = visible_command();
+    let mut buf = Vec::new();
+    clap_complete::generate(shell, &mut cmd, "dotr", &mut buf);
+    let script = String::from_utf8(buf)?;
+
+    let mut dynamic_args = Vec::new();
+    collect_dynamic_args(&cmd, "dotr", &mut dynamic_args);
+    Ok(match shell {
+        Shell::Bash => hook_bash(&script, &dynamic_args),
+        Shell::Zsh => hook_zsh(&script, &dynamic_args),
+        Shell::Fish => hook_fish(&script, &dynamic_args),
+        Shell::PowerShell => hook_powershell(&script, &dynamic_args),
+        _ => script,
+    })
+}
+
+/// Package or profile names from config.toml, sorted. Empty when there is
+/// no readable config, so completion never prints errors.
+pub fn complete_names(working_dir: &Path, kind: CompleteKind) -> Vec<String> {
+    let Ok(config) = Config::from_path(working_dir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = match kind {
+        CompleteKind::Packages => config.packages.into_keys().collect(),
+        CompleteKind::Profiles => config.profiles.into_keys().collect(),
+    };
+    names.sort();
+    names
+}
+
+// clap_complete lists hidden subcommands for some shells, so rebuild the
+// command without them.
+fn visible_command() -> clap::Command {
+    let full = Cli::command();
+    let mut cmd = clap::Command::new("dotr").version(env!("CARGO_PKG_VERSION"));
+    for arg in full.get_arguments() {
+        cmd = cmd.arg(arg.clone());
+    }
+    for sub in full.get_subcommands().filter(|s| !s.is_hide_set()) {
+        cmd = cmd.subcommand(sub.clone());
+    }
+    cmd.build();
+    cmd
+}
+
+fn collect_dynamic_args(cmd: &clap::Command, path: &str, out: &mut Vec<DynamicArg>) {
+    for arg in cmd.get_arguments() {
+        let kind = match arg.get_id().as_str() {
+            "packages" => "packages",
+            "profile" => "profiles",
+            _ => continue,
+        };
+        let mut flags = Vec::new();
+        if let Some(long) = arg.get_long() {
+            flags.push(format!("--{}", long));
+        }
+        if let Some(short) = arg.get_short() {
+            flags.push(format!("-{}", short));
+        }
+        if flags.is_empty() {
+            continue;
+        }
+        let value_name = arg
+            .get_value_names()
+            .and_then(|names| names.first())
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| arg.get_id().as_str().to_uppercase());
+        out.push(DynamicArg {
+            path: path.to_string(),
+            flags,
+            value_name,
+            kind,
+        });
+    }
+    for sub in cmd.get_subcommands() {
+        collect_dynamic_args(sub, &format!("{};{}", path, sub.get_name()), out);
+    }
+}
+
+// Each subcommand has a `case "${prev}"` block; swap the file completion of
+// our flags for the names from `dotr __complete`.
+fn hook_bash(script: &str, dynamic_args: &[DynamicArg]) -> String {
+    let mut out = String::with_capacity(script.len());
+    let mut section = String::new();
+    let mut pending: Option<&str> = None;
+    for line in script.lines() {
+        let trimmed = line.trim();
+        // Section labels sit at a fixed indent, e.g. `        dotr__subcmd__deploy)`
+        if line.starts_with("        dotr")
+            && let Some(label) = trimmed.strip_suffix(')')
+        {
+            section = label.replace("__subcmd__", ";");
+        }
+        if let Some(kind) = pending.take()
+            && trimmed.starts_with("COMPREPLY=($(compgen -f")
+        {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            out.push_str(&format!(
+                "{}COMPREPLY=($(compgen -W \"$(dotr __complete {} 2>/dev/null)\" -- \"${{cur}}\"))\n",
+                indent, kind
+            ));
+            continue;
+        }
+        if let Some(flag) = trimmed.strip_suffix(')') {
+            pending = dynamic_args
+                .iter()
+                .find(|a| a.path == section && a.flags.iter().any(|f| f == flag))
+                .map(|a| a.kind);
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+fn hook_zsh(script: &str, dynamic_args: &[DynamicArg]) -> String {
+    let mut out = script.to_string();
+    for arg in dynamic_args {
+        out = out.replace(
+            &format!(":{}:_default", arg.value_name),
+            &format!(":{}:_dotr_names {}", arg.value_name, arg.kind),
+        );
+    }
+    let helper = r#"
+_dotr_names() {
+    local -a names
+    names=(${(f)"$(dotr __complete $1 2>/dev/null)"})
+    compadd -a names
+}
+"#;
+    match out.split_once('\n') {
+        Some((first, rest)) => format!("{}\n{}{}", first, helper, rest),
+        None => out,
+    }
+}
+
+fn hook_fish(script: &str, dynamic_args: &[DynamicArg]) -> String {
+    let mut out = String::with_capacity(script.len());
+    for line in script.lines() {
+        out.push_str(line);
+        let kind = dynamic_args.iter().find_map(|arg| {
+            let long = arg.flags.iter().find(|f| f.starts_with("--"))?;
+            line.ends_with(&format!(" -l {} -r", &long[2..]))
+                .then_some(arg.kind)
+        });
+        if let Some(kind) = kind {
+            out.push_str(&format!(
+                " -f -a \"(dotr __complete {} 2>/dev/null)\"",
+                kind
+            ));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// PowerShell completions only offer flag names, so answer for our flags
+// before the generated switch runs.
+fn hook_powershell(script: &str, dynamic_args: &[DynamicArg]) -> String {
+    let mut cases = String::new();
+    for arg in dynamic_args {
+        for flag in &arg.flags {
+            cases.push_str(&format!(
+                "        '{};{}' {{ '{}' }}\n",
+                arg.path, flag, arg.kind
+            ));
+        }
+    }
+    let hook = format!(
+        r#"
+    $previous = $commandElements[$commandElements.Count - 1]
+    if ($wordToComplete -ne '' -and $commandElements.Count -gt 1) {{
+        $previous = $commandElements[$commandElements.Count - 2]
+    }}
+    $kind = switch -CaseSensitive ("$command;$previous") {{
+{}    }}
+    if ($kind) {{
+        dotr __complete $kind 2>$null |
+            Where-Object {{ $_ -like "$wordToComplete*" }} |
+            ForEach-Object {{ [CompletionResult]::new($_, $_, [CompletionResultType]::ParameterValue, $_) }}
+        return
+    }}
+"#,
+        cases
+    );
+    let anchor = "    }) -join ';'\n";
+    match script.find(anchor) {
+        Some(pos) => {
+            let split = pos + anchor.len();
+            format!("{}{}{}", &script[..split], hook, &script[split..])
+        }
+        None => script.to_string(),
+    }
+}
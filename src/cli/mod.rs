@@ -1,20 +1,92 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 use crate::{
-    config::{self, Config},
-    context::Context,
+    cache::hash_path,
+    config::{self, Config, ProfileSelection, ProfileSource, presets::InitPreset},
+    context::{
+        Context, SYSTEM_VARIABLES, check_reserved_variables, lookup_path, print_variable,
+        toml_to_json,
+    },
+    doctor::{config_failure, run_doctor},
+    lock::WorkdirLock,
+    manifest::manifest_path,
+    package::{Package, compile_string, print_line_diff},
+    plan::{Plan, PlanAction},
     profile::Profile,
+    prompts::Prompt,
+    report::{
+        FileDiff, Report,
+        output::{Document, GroupEntry, OutputFormat, PackageEntry},
+    },
+    secrets,
+    stage::DEFAULT_STAGE_DIR,
+    state::DeployState,
+    utils::{
+        Cell, Color, ExitCodeError, LogFormat, LogLevel, can_prompt, can_show_progress,
+        color_enabled, cprintln, ecprintln, enable_color, enable_progress, find_editor,
+        init_logger, log_to_stderr, prompt, render_table, resolve_path, run_editor, wants_color,
+    },
 };
 
-#[derive(Debug, Parser)]
+mod completions;
+
+pub use completions::{complete_names, generate_script};
+
+// Pins the repository when --working-dir isn't given
+pub const DOTR_DIR_VAR: &str = "DOTR_DIR";
+
+#[derive(Debug, Parser, Default)]
 #[command(version)]
 pub struct Cli {
     #[clap(subcommand)]
     pub command: Option<Command>,
+    /// The repository, by default $DOTR_DIR or the closest directory up from here with a config.toml
     #[clap(short, long, global = true)]
     pub working_dir: Option<String>,
+
+    /// Show more detail; -vv adds skipped files and the actions being run
+    #[clap(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Only show warnings and errors
+    #[clap(short, long, global = true)]
+    pub quiet: bool,
+
+    #[clap(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Print a line per step instead of drawing progress bars
+    #[clap(long, global = true)]
+    pub no_progress: bool,
+
+    /// Never color the output, as when NO_COLOR is set or stdout isn't a terminal
+    #[clap(long, global = true)]
+    pub no_color: bool,
+
+    /// Fail right away when secrets_file can't be decrypted
+    #[clap(long, global = true)]
+    pub strict_secrets: bool,
+
+    /// Treat unknown keys in config.toml and files written by two packages as errors, like `strict = true`
+    #[clap(long, global = true)]
+    pub strict: bool,
+
+    /// Never ask for prompted variables: use their defaults, or fail listing those without one
+    #[clap(long, global = true)]
+    pub no_input: bool,
+
+    /// Use downloaded remote sources as cached, failing for those never fetched
+    #[clap(long, global = true)]
+    pub offline: bool,
+
+    /// Seconds to wait for another dotr working in the same directory, 0 fails right away
+    #[clap(long, global = true, value_name = "SECONDS", default_value_t = 0)]
+    pub lock_timeout: u64,
 }
 
 #[derive(Debug, Subcommand)]
@@ -22,36 +94,381 @@ pub enum Command {
     Init(InitArgs),
     Import(ImportArgs),
     Deploy(DeployUpdateArgs),
-    Update(DeployUpdateArgs),
+    Update(UpdateArgs),
     Diff(DeployUpdateArgs),
+    Clean(CleanArgs),
+    Edit(EditArgs),
+    Verify(VerifyArgs),
+    Fetch(FetchArgs),
+    Status(StatusArgs),
+    List(ListArgs),
+    Doctor(DoctorArgs),
+    Rename(RenameArgs),
+    Why(WhyArgs),
     PrintVars(PrintVarsArgs),
+    Profile(ProfileArgs),
+    Secrets(SecretsArgs),
+    Completions(CompletionsArgs),
+    #[command(name = "__complete", hide = true)]
+    Complete(CompleteArgs),
 }
 
-#[derive(Debug, Args)]
+impl Command {
+    /// Whether the command writes to the working directory, and so must hold its lock.
+    pub fn is_mutating(&self) -> bool {
+        match self {
+            Command::Import(_) | Command::Deploy(_) | Command::Secrets(_) => true,
+            Command::Update(args) => !args.dry_run,
+            Command::Clean(args) => !args.dry_run,
+            Command::Edit(args) => !args.no_deploy,
+            Command::Verify(args) => args.update_manifest,
+            Command::Fetch(_) => true,
+            Command::Rename(args) => !args.dry_run,
+            Command::Profile(args) => !matches!(
+                args.command,
+                ProfileCommand::List | ProfileCommand::Show { .. }
+            ),
+            Command::Init(_)
+            | Command::Diff(_)
+            | Command::Status(_)
+            | Command::List(_)
+            | Command::Doctor(_)
+            | Command::Why(_)
+            | Command::PrintVars(_)
+            | Command::Completions(_)
+            | Command::Complete(_) => false,
+        }
+    }
+
+    /// How the command prints what it found, text unless it takes `--output`.
+    pub fn output_format(&self) -> OutputFormat {
+        match self {
+            Command::Diff(args) => args.output,
+            Command::Verify(args) => args.output,
+            Command::Status(args) => args.output,
+            Command::List(args) => args.output,
+            _ => OutputFormat::Text,
+        }
+    }
+}
+
+#[derive(Debug, Args, Default)]
 #[command(name = "init", about = "Intialize dotfiles repository.")]
-pub struct InitArgs {}
+pub struct InitArgs {
+    /// What to start with: `standard` adds home and work profiles, an example
+    /// package entry and a README, `full` also a templated sample package
+    #[arg(long, value_enum, default_value_t)]
+    pub preset: InitPreset,
+}
 
-#[derive(Debug, Args)]
+#[derive(Debug, Args, Default)]
 #[command(name = "print-vars", about = "Print all user variables.")]
 pub struct PrintVarsArgs {
     #[arg(short, long)]
     pub profile: Option<String>,
+
+    /// Show which file or layer each variable comes from, and what it overrides.
+    /// With -v, also the overridden values
+    #[arg(long)]
+    pub sources: bool,
+
+    /// Show the variables the templates of this package see
+    #[arg(long, value_name = "NAME")]
+    pub package: Option<String>,
+
+    #[arg(long, value_enum, default_value_t = VarsFormat::Pretty)]
+    pub format: VarsFormat,
+
+    /// Print only the raw value of one variable, nested keys separated by '.'
+    #[arg(long, value_name = "KEY")]
+    pub only: Option<String>,
+
+    /// Print secret values instead of masking them
+    #[arg(long)]
+    pub show_secrets: bool,
 }
 
 #[derive(Debug, Args)]
+#[command(name = "profile", about = "Manage profiles.")]
+pub struct ProfileArgs {
+    #[command(subcommand)]
+    pub command: ProfileCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProfileCommand {
+    /// List profiles with their dependency counts
+    List,
+    /// Show a profile's variables, prompts and dependencies
+    Show { name: String },
+    /// Add an empty profile
+    Create { name: String },
+    /// Add packages to a profile's dependencies
+    Add {
+        name: String,
+        #[arg(required = true, num_args(1..))]
+        packages: Vec<String>,
+    },
+    /// Remove packages from a profile's dependencies
+    Remove {
+        name: String,
+        #[arg(required = true, num_args(1..))]
+        packages: Vec<String>,
+    },
+    /// Delete a profile
+    Delete {
+        name: String,
+        /// Delete even if package targets refer to the profile, dropping those targets
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, Args)]
+#[command(name = "secrets", about = "Manage the encrypted secrets file.")]
+pub struct SecretsArgs {
+    #[command(subcommand)]
+    pub command: SecretsCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SecretsCommand {
+    /// Decrypt the secrets into $EDITOR and encrypt them again on save
+    Edit,
+    /// Set one secret variable
+    Set { key: String, value: String },
+}
+
+#[derive(Debug, Args)]
+#[command(
+    name = "completions",
+    about = "Print a shell completion script.",
+    after_help = "Example: dotr completions zsh > ~/.zfunc/_dotr"
+)]
+pub struct CompletionsArgs {
+    pub shell: clap_complete::Shell,
+}
+
+/// Names offered by the completion scripts for `--packages` and `--profile`.
+#[derive(Debug, Args)]
+pub struct CompleteArgs {
+    pub kind: CompleteKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompleteKind {
+    Packages,
+    Profiles,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum VarsFormat {
+    #[default]
+    Pretty,
+    Toml,
+    Json,
+}
+
+#[derive(Debug, Args, Default)]
 #[command(name = "import", about = "Import dotfile and update configuration.")]
 pub struct ImportArgs {
-    #[arg(value_name = "IMPORT_PATH")]
-    pub path: String,
+    #[arg(
+        value_name = "IMPORT_PATH",
+        num_args(1..),
+        required_unless_present = "stdin",
+        conflicts_with = "stdin"
+    )]
+    pub paths: Vec<String>,
+
+    /// Import what is piped in instead of a path, like the output of a tool
+    /// that generates its config
+    #[arg(long, requires = "dest", conflicts_with = "adopt")]
+    pub stdin: bool,
+
+    /// Where the package piped in with --stdin deploys to
+    #[arg(long, value_name = "PATH", conflicts_with = "paths")]
+    pub dest: Option<String>,
+
+    /// Octal mode of the copy stored from --stdin, like 600 for secrets
+    #[arg(long, value_name = "MODE", conflicts_with = "paths")]
+    pub mode: Option<String>,
 
     #[arg(short, long)]
     pub name: Option<String>,
 
     #[arg(short, long)]
     pub profile: Option<String>,
+
+    /// Replace an existing package with the same name
+    #[arg(long)]
+    pub overwrite: bool,
+
+    /// Stop at the first path that fails to import
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Turn the imported copy into a template, replacing the home directory
+    /// and username with {{ HOME }} and {{ USER }}
+    #[arg(long)]
+    pub as_template: bool,
+
+    /// Also replace the current value of this variable with its placeholder (repeatable)
+    #[arg(long = "substitute", value_name = "VAR", requires = "as_template")]
+    pub substitute: Vec<String>,
+
+    /// Deploy the imported copy right away, so the one in dotfiles/ is what
+    /// the original is generated from
+    #[arg(long)]
+    pub adopt: bool,
+
+    /// Add the new packages to this included file instead of config.toml
+    #[arg(long, value_name = "FILE")]
+    pub into: Option<String>,
+
+    /// Copy everything, even what .dotrignore or the directory's .gitignore exclude
+    #[arg(long)]
+    pub no_ignore: bool,
+
+    /// Copy files bigger than max_file_size, and more files than max_files
+    #[arg(long)]
+    pub ignore_limits: bool,
 }
 
-#[derive(Debug, Args)]
+#[derive(Debug, Args, Default)]
+#[command(name = "clean", about = "Remove the files dotr deployed.")]
+pub struct CleanArgs {
+    /// Only clean these packages, by default every package with deployed files
+    #[arg(num_args(0..), short, long)]
+    pub packages: Option<Vec<String>>,
+
+    /// Also remove files that were edited since the last deploy
+    #[arg(long)]
+    pub force: bool,
+
+    /// List the files that would be removed without removing them
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args, Default)]
+#[command(
+    name = "verify",
+    about = "Check that deployed files still match what dotr wrote."
+)]
+pub struct VerifyArgs {
+    /// Only verify these packages, by default every deployed package
+    #[arg(num_args(0..), short, long)]
+    pub packages: Option<Vec<String>>,
+
+    /// Take the files as they are now as the new baseline
+    #[arg(long)]
+    pub update_manifest: bool,
+
+    /// Print a single JSON document for tools instead of text
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+}
+
+#[derive(Debug, Args, Default)]
+#[command(
+    name = "fetch",
+    about = "Download the remote sources of packages again."
+)]
+pub struct FetchArgs {
+    /// Only fetch these packages, by default every package with a remote src
+    #[arg(num_args(0..), short, long)]
+    pub packages: Option<Vec<String>>,
+}
+
+#[derive(Debug, Args, Default)]
+#[command(
+    name = "status",
+    about = "Show when each package was last deployed on this machine."
+)]
+pub struct StatusArgs {
+    /// Only show these packages, by default every package
+    #[arg(num_args(0..), short, long)]
+    pub packages: Option<Vec<String>>,
+
+    /// Print a single JSON document for tools instead of text
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+}
+
+#[derive(Debug, Args, Default)]
+#[command(name = "list", about = "List the packages, or the package groups.")]
+pub struct ListArgs {
+    /// List the groups and their members instead, flagging members that don't exist
+    #[arg(long)]
+    pub groups: bool,
+
+    /// Print a single JSON document for tools instead of text
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+}
+
+#[derive(Debug, Args, Default)]
+#[command(
+    name = "rename",
+    about = "Rename a package and update every reference to it."
+)]
+pub struct RenameArgs {
+    /// The package to rename, by name or alias
+    pub old: String,
+
+    /// Its new name
+    pub new: String,
+
+    /// Print what would change without changing it
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args, Default)]
+#[command(name = "why", about = "Explain why a package is or isn't selected.")]
+pub struct WhyArgs {
+    /// The package to explain, by name or alias
+    pub package: String,
+
+    /// Select as deploy would with these packages
+    #[arg(num_args(0..), short, long)]
+    pub packages: Option<Vec<String>>,
+
+    /// Select as deploy would with this profile
+    #[arg(short = 'P', long)]
+    pub profile: Option<String>,
+}
+
+#[derive(Debug, Args, Default)]
+#[command(
+    name = "doctor",
+    about = "Check the configuration and this machine for problems, changing nothing."
+)]
+pub struct DoctorArgs {
+    /// Render templates for this profile instead of the one that would be selected
+    #[arg(short, long)]
+    pub profile: Option<String>,
+}
+
+#[derive(Debug, Args, Default)]
+#[command(name = "edit", about = "Open a package's source in your editor.")]
+pub struct EditArgs {
+    /// The package to edit: its file, or its directory for `d_` packages
+    pub package: String,
+
+    #[arg(short, long)]
+    pub profile: Option<String>,
+
+    /// Deploy the package after editing without asking
+    #[arg(long, conflicts_with = "no_deploy")]
+    pub and_deploy: bool,
+
+    /// Never offer to deploy after editing
+    #[arg(long)]
+    pub no_deploy: bool,
+}
+
+#[derive(Debug, Args, Default)]
 #[command(name = "deploy", about = "Deploy dotfiles from repository.")]
 pub struct DeployUpdateArgs {
     #[arg(num_args(0..), short, long)]
@@ -59,6 +476,169 @@ pub struct DeployUpdateArgs {
 
     #[arg(short = 'P', long)]
     pub profile: Option<String>,
+
+    /// Remove files at dest that were removed from the package source
+    #[arg(long)]
+    pub prune: bool,
+
+    /// Compare every file in full instead of trusting the deploy cache
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Set a user variable for this run only, without saving it (repeatable).
+    /// VALUE is read as TOML when it parses, like 8080 or true, else as a string
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub set: Vec<String>,
+
+    /// Don't warn about uncommitted changes under dotfiles/ before deploying
+    #[arg(long)]
+    pub no_git_check: bool,
+
+    /// Don't check package paths for overlaps before deploying
+    #[arg(long)]
+    pub skip_validation: bool,
+
+    /// Deploy only: ask what to do with each dest that was edited since the last deploy
+    #[arg(short, long)]
+    pub interactive: bool,
+
+    /// Deploy only: rewrite and back up every dest, even those already up to date
+    /// and those modified more recently than their source
+    #[arg(long)]
+    pub force: bool,
+
+    /// Deploy only: replace dests that are symlinks instead of refusing to write
+    /// through them, backing up what they point to first
+    #[arg(long)]
+    pub overwrite_symlinks: bool,
+
+    /// Deploy only: allow dests outside the home directory and under protected_paths
+    #[arg(long)]
+    pub allow_system_paths: bool,
+
+    /// Deploy only: back up and replace a dest that is a directory where the
+    /// src is a file, or a file where it is a directory
+    #[arg(long)]
+    pub replace_mismatched: bool,
+
+    /// Deploy only: copy binary files byte by byte instead of as reflinks
+    /// sharing their blocks, on filesystems that support those
+    #[arg(long)]
+    pub no_reflink: bool,
+
+    /// Deploy only: stop a package at its first file that fails instead of
+    /// trying the rest and listing the failures at the end
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Deploy only: deploy packages with files bigger than max_file_size or
+    /// more files than max_files
+    #[arg(long)]
+    pub ignore_limits: bool,
+
+    /// Deploy only: copy files without running any pre or post actions
+    #[arg(long, conflicts_with = "actions_only")]
+    pub skip_actions: bool,
+
+    /// Deploy only: run the pre and post actions without copying any files
+    #[arg(long)]
+    pub actions_only: bool,
+
+    /// Diff only: print a single JSON document for tools, with the changed
+    /// lines of each file, instead of text
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// Deploy only: write what deploy would do with each file to FILE as JSON,
+    /// touching no dest, for --apply to carry out later
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["apply", "actions_only"])]
+    pub plan: Option<String>,
+
+    /// Deploy only: write the files planned in FILE by --plan and nothing else,
+    /// refusing when a dest changed since planning
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["packages", "profile", "actions_only"])]
+    pub apply: Option<String>,
+}
+
+#[derive(Debug, Args, Default)]
+#[command(
+    name = "update",
+    about = "Copy deployed files back into the repository.",
+    long_about = "Copy deployed files back into the repository: the files at each package's \
+                  dest are copied over its src under dotfiles/, the opposite direction of \
+                  deploy. Templated sources are never overwritten."
+)]
+pub struct UpdateArgs {
+    #[arg(num_args(0..), short, long)]
+    pub packages: Option<Vec<String>>,
+
+    #[arg(short = 'P', long)]
+    pub profile: Option<String>,
+
+    /// Set a user variable for this run only, without saving it (repeatable).
+    /// VALUE is read as TOML when it parses, like 8080 or true, else as a string
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub set: Vec<String>,
+
+    /// Don't check package paths for overlaps before updating
+    #[arg(long)]
+    pub skip_validation: bool,
+
+    /// Stop a package at its first file that fails instead of trying the rest
+    /// and listing the failures at the end
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Copy back packages with files bigger than max_file_size or more files
+    /// than max_files
+    #[arg(long)]
+    pub ignore_limits: bool,
+
+    /// Copy files back byte by byte instead of as reflinks sharing their
+    /// blocks, on filesystems that support those
+    #[arg(long)]
+    pub no_reflink: bool,
+
+    /// Also copy files found at dest that src doesn't have yet, the default
+    #[arg(long, overrides_with = "no_include_new")]
+    pub include_new: bool,
+
+    /// Only copy back files that src already has
+    #[arg(long, overrides_with = "include_new")]
+    pub no_include_new: bool,
+
+    /// Remove files from src that are no longer at dest. Templates are never removed
+    #[arg(long)]
+    pub prune_missing: bool,
+
+    /// List the files that would be copied back or removed without touching them,
+    /// exiting with 1 when there are any
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Show the diff of each file listed by --dry-run
+    #[arg(long, requires = "dry_run")]
+    pub diff: bool,
+
+    /// Copy files into DIR to review instead of over the sources, as
+    /// DIR/<package>/<path in src>. DIR is .dotr/staged when not given
+    #[arg(
+        long,
+        value_name = "DIR",
+        num_args = 0..=1,
+        default_missing_value = DEFAULT_STAGE_DIR,
+        conflicts_with = "prune_missing"
+    )]
+    pub stage_to: Option<String>,
+
+    /// Copy the files staged with --stage-to over their sources and clear the
+    /// stage. Refused when a source changed since it was staged, unless --force
+    #[arg(long, conflicts_with_all = ["dry_run", "prune_missing", "no_include_new"])]
+    pub apply_staged: bool,
+
+    /// With --apply-staged, overwrite sources that changed since they were staged
+    #[arg(long, requires = "apply_staged")]
+    pub force: bool,
 }
 
 const BANNER: &str = r#"
@@ -70,15 +650,45 @@ const BANNER: &str = r#"
 ╚═════╝  ╚═════╝    ╚═╝   ╚═╝  ╚═╝
 "#;
 
+// Lines of a custom banner shown at most, the rest is cut
+pub const BANNER_MAX_LINES: usize = 30;
+
 pub fn run_cli(args: Cli) -> Result<(), anyhow::Error> {
-    let mut working_dir = std::env::current_dir()?;
-    if let Some(wd) = args.working_dir {
-        working_dir = PathBuf::from(wd);
-        // Only canonicalize if the path exists
-        if working_dir.exists() {
-            working_dir = working_dir.canonicalize()?;
+    let verbose = args.verbose;
+    init_logger(
+        LogLevel::threshold(args.verbose, args.quiet),
+        args.log_format,
+    );
+    enable_progress(!args.no_progress && !args.quiet && can_show_progress());
+    // A JSON document owns stdout, the log goes to stderr next to it
+    let json_output = args
+        .command
+        .as_ref()
+        .is_some_and(|command| command.output_format() == OutputFormat::Json);
+    log_to_stderr(json_output);
+    // JSON is for machines, keep escape codes out of it
+    enable_color(args.log_format != LogFormat::Json && !json_output && wants_color(args.no_color));
+    match &args.command {
+        Some(Command::Completions(completion_args)) => {
+            print!("{}", generate_script(completion_args.shell)?);
+            return Ok(());
         }
+        Some(Command::Complete(complete_args)) => {
+            // Runs on every tab press: never let an error reach the prompt
+            init_logger(LogLevel::FATAL, args.log_format);
+            let working_dir =
+                locate_working_dir(args.working_dir.as_deref(), false).unwrap_or_default();
+            for name in complete_names(&working_dir, complete_args.kind) {
+                println!("{}", name);
+            }
+            return Ok(());
+        }
+        _ => {}
     }
+    let working_dir = locate_working_dir(
+        args.working_dir.as_deref(),
+        matches!(args.command, Some(Command::Init(_))),
+    )?;
 
     // For Init command, we allow non-existent directories
     if !working_dir.exists() && !matches!(args.command, Some(Command::Init(_))) {
@@ -93,65 +703,241 @@ pub fn run_cli(args: Cli) -> Result<(), anyhow::Error> {
     // Print working directory
     // Print full working directory path
     match args.command {
-        Some(Command::Init(_)) => {
-            println!("Initializing configuration...");
-            Config::init(&working_dir)?;
-            println!("Configuration initialized successfully.");
+        Some(Command::Init(init_args)) => {
+            cprintln("Initializing configuration...", &LogLevel::INFO);
+            Config::init(&working_dir, init_args.preset)?;
+            cprintln("Configuration initialized successfully.", &LogLevel::INFO);
+        }
+        // A summary of the repository when there is one
+        None if working_dir.join("config.toml").is_file() => {
+            let conf = config::Config::load(&working_dir, args.strict)?;
+            let ctx = Context::from_config(&working_dir, &conf)?;
+            let selection = conf.get_profile_details(&None, &ctx)?;
+            print!(
+                "{}",
+                conf.render_summary(&ctx, &selection, color_enabled())?
+            );
         }
         None => {
             println!("No command provided. Use --help for more information.");
         }
         Some(_) => {
-            let mut conf = config::Config::from_path(&working_dir)?;
-            if conf.banner {
-                println!("{}", BANNER);
+            // Held until the command returns, so two writers never interleave
+            let _lock = match &args.command {
+                Some(command) if command.is_mutating() => Some(WorkdirLock::acquire(
+                    &working_dir,
+                    Duration::from_secs(args.lock_timeout),
+                )?),
+                _ => None,
+            };
+            let mut conf = match config::Config::load(&working_dir, args.strict) {
+                Err(e) if matches!(args.command, Some(Command::Doctor(_))) => {
+                    return Err(config_failure(e));
+                }
+                loaded => loaded?,
+            };
+            if args.command.as_ref().is_some_and(Command::is_mutating) {
+                conf.check_schema_writable()?;
+            }
+            let mut ctx = Context::from_config(&working_dir, &conf)?;
+            // The banner is decoration, keep it out of quiet and machine-readable output
+            if conf.banner && !args.quiet && args.log_format == LogFormat::Text && !json_output {
+                eprintln!("{}", render_banner(&conf, &ctx));
+            }
+            ctx.set_no_input(args.no_input);
+            ctx.set_offline(args.offline);
+            if !matches!(args.command, Some(Command::Secrets(_))) {
+                ctx.load_secrets(&conf, args.strict_secrets)?;
             }
-            // Start with environment variables from Context::new()
-            let mut ctx = Context::new(&working_dir)?;
-            ctx.extend_variables(conf.variables.clone());
-            let context_vars = ctx.get_context_variables();
 
-            // Merge config variables, which override environment variables
             match args.command {
                 Some(Command::Import(args)) => {
-                    let (profile_name, profile) =
-                        conf.get_profile_details(&args.profile, &context_vars);
-                    ctx.set_profile(profile);
-                    conf.import_package(&args, &ctx, &profile_name)?;
+                    let selection = conf.get_profile_details(&args.profile, &ctx)?;
+                    announce_profile(&selection);
+                    ctx.set_profile(selection.profile)?;
+                    ctx.set_ignore_limits(args.ignore_limits);
+                    if args.stdin {
+                        conf.import_from_reader(
+                            &mut std::io::stdin().lock(),
+                            &args,
+                            &ctx,
+                            &selection.name,
+                        )?;
+                    } else {
+                        conf.import_packages(&args, &ctx, &selection.name)?;
+                    }
+                }
+                Some(Command::Deploy(args)) if args.apply.is_some() => {
+                    ctx.set_user_variables(parse_set_values(&args.set)?);
+                    let plan = Plan::load(&resolve_path(
+                        args.apply.as_deref().unwrap_or_default(),
+                        &working_dir,
+                    ))?;
+                    // Rendered as planned, `--profile ""` being no profile
+                    let planned = Some(plan.profile.clone().unwrap_or_default());
+                    let profile = require_profile(conf.get_profile_details(&planned, &ctx)?)?;
+                    ctx.set_profile(profile)?;
+                    ctx.set_overwrite_symlinks(args.overwrite_symlinks);
+                    ctx.get_prompted_variables(&conf, &Some(plan.package_names()))?;
+                    let report = conf.apply_plan(&ctx, &plan)?;
+                    cprintln(&report.summary("Applied"), &LogLevel::INFO);
+                    report.into_result()?;
                 }
                 Some(Command::Deploy(args)) => {
-                    let (profile_name, profile) =
-                        conf.get_profile_details(&args.profile, &context_vars);
-                    validate_profile_exists(&profile_name, &profile)?;
-                    ctx.set_profile(profile);
+                    ctx.set_user_variables(parse_set_values(&args.set)?);
+                    if !args.no_git_check && args.plan.is_none() {
+                        conf.git.check_clean(&working_dir);
+                    }
+                    if args.interactive && !can_prompt() {
+                        anyhow::bail!("--interactive needs a terminal to ask on");
+                    }
+                    let profile = require_profile(conf.get_profile_details(&args.profile, &ctx)?)?;
+                    ctx.set_profile(profile)?;
+                    ctx.set_interactive(args.interactive);
+                    ctx.set_force(args.force);
+                    ctx.set_overwrite_symlinks(args.overwrite_symlinks);
+                    ctx.set_no_reflink(args.no_reflink);
+                    ctx.set_replace_mismatched(args.replace_mismatched);
+                    ctx.set_allow_system_paths(args.allow_system_paths);
+                    ctx.set_fail_fast(args.fail_fast);
+                    ctx.set_ignore_limits(args.ignore_limits);
 
                     ctx.get_prompted_variables(&conf, &args.packages)?;
-                    conf.deploy_packages(&ctx, &args)?;
+                    if let Some(path) = &args.plan {
+                        let plan = conf.plan_packages(&ctx, &args)?;
+                        plan.save(&resolve_path(path, &working_dir))?;
+                        cprintln(
+                            &format!(
+                                "Planned {} file(s): {} to create, {} to update, {} unchanged. Apply with `dotr deploy --apply {}`",
+                                plan.files.len(),
+                                plan.count(PlanAction::Create),
+                                plan.count(PlanAction::Update),
+                                plan.count(PlanAction::Skip),
+                                path
+                            ),
+                            &LogLevel::INFO,
+                        );
+                        return Ok(());
+                    }
+                    let report = conf.deploy_packages(&ctx, &args)?;
+                    cprintln(&report.summary("Deployed"), &LogLevel::INFO);
+                    report.into_result()?;
+                }
+                Some(Command::Update(args)) if args.apply_staged => {
+                    conf.apply_staged(&ctx, &args)?;
+                    conf.git.commit_update(&ctx)?;
                 }
                 Some(Command::Update(args)) => {
-                    let (profile_name, profile) =
-                        conf.get_profile_details(&args.profile, &context_vars);
-                    validate_profile_exists(&profile_name, &profile)?;
-                    ctx.set_profile(profile);
+                    ctx.set_user_variables(parse_set_values(&args.set)?);
+                    ctx.set_fail_fast(args.fail_fast);
+                    ctx.set_skip_new(args.no_include_new);
+                    ctx.set_prune_missing(args.prune_missing);
+                    ctx.set_ignore_limits(args.ignore_limits);
+                    ctx.set_no_reflink(args.no_reflink);
+                    let profile = require_profile(conf.get_profile_details(&args.profile, &ctx)?)?;
+                    ctx.set_profile(profile)?;
 
                     ctx.get_prompted_variables(&conf, &args.packages)?;
-                    conf.backup_packages(&ctx, &args)?;
+                    let report = conf.backup_packages(&ctx, &args)?;
+                    if args.dry_run {
+                        print_update_preview(&report, &working_dir, args.diff);
+                    } else if args.stage_to.is_some() {
+                        cprintln(&report.summary("Staged"), &LogLevel::INFO);
+                    } else {
+                        cprintln(&report.summary("Updated"), &LogLevel::INFO);
+                        // Recorded even when a later package failed, its files are gone
+                        if conf.record_removed(&ctx, &report) {
+                            conf.save(&working_dir)?;
+                        }
+                    }
+                    let report = report.into_result()?;
+                    let changed = report.backed_up_count() + report.pruned_count();
+                    if args.dry_run {
+                        if changed > 0 {
+                            anyhow::bail!("Update would change {} file(s)", changed);
+                        }
+                        cprintln("Nothing to update", &LogLevel::INFO);
+                    } else if args.stage_to.is_none() {
+                        conf.git.commit_update(&ctx)?;
+                    }
                 }
                 Some(Command::Diff(args)) => {
-                    let (profile_name, profile) =
-                        conf.get_profile_details(&args.profile, &context_vars);
-                    validate_profile_exists(&profile_name, &profile)?;
-                    ctx.set_profile(profile);
+                    ctx.set_user_variables(parse_set_values(&args.set)?);
+                    let profile = require_profile(conf.get_profile_details(&args.profile, &ctx)?)?;
+                    ctx.set_profile(profile)?;
                     ctx.get_prompted_variables(&conf, &args.packages)?;
-                    conf.diff_packages(&ctx, &args)?;
+                    cprintln("Checking differences...", &LogLevel::INFO);
+                    let report = conf.diff_packages(&ctx, &args)?;
+                    match args.output {
+                        OutputFormat::Text => print_diff_report(&report),
+                        OutputFormat::Json => {
+                            let profile = ctx.profile.as_ref().map(|p| p.name.clone());
+                            let mut document = Document::new(&working_dir, profile);
+                            document.packages =
+                                report.packages.iter().map(PackageEntry::from).collect();
+                            println!("{}", document.to_json()?);
+                        }
+                    }
+                    report.into_result()?;
+                }
+                Some(Command::Clean(args)) => conf.clean_packages(&ctx, &args)?,
+                Some(Command::Edit(args)) => edit_package(&conf, &mut ctx, &args)?,
+                Some(Command::Verify(args)) => conf.verify_packages(&ctx, &args)?,
+                Some(Command::Fetch(args)) => conf.fetch_packages(&ctx, &args)?,
+                Some(Command::Status(args)) => conf.print_status(&ctx, &args)?,
+                Some(Command::List(args)) => match args.output {
+                    OutputFormat::Text => print!("{}", render_list(&conf, &args, color_enabled())),
+                    OutputFormat::Json => {
+                        let profile = conf.get_profile_details(&None, &ctx)?.name;
+                        let document =
+                            list_document(&conf, &args, Document::new(&working_dir, profile));
+                        println!("{}", document.to_json()?);
+                    }
+                },
+                Some(Command::Doctor(args)) => {
+                    // A profile that doesn't resolve is reported by the checks
+                    if let Ok(selection) = conf.get_profile_details(&args.profile, &ctx) {
+                        ctx.set_profile(optional_profile(selection)?)?;
+                    }
+                    run_doctor(&conf, &ctx)?;
+                }
+                Some(Command::Why(args)) => {
+                    let profile = require_profile(conf.get_profile_details(&args.profile, &ctx)?)?;
+                    ctx.set_profile(profile)?;
+                    print!(
+                        "{}",
+                        conf.explain_selection(&ctx, &args.package, &args.packages)?
+                    );
                 }
                 Some(Command::PrintVars(args)) => {
-                    let (profile_name, profile) =
-                        conf.get_profile_details(&args.profile, &context_vars);
-                    validate_profile_exists(&profile_name, &profile)?;
-                    ctx.set_profile(profile);
-                    ctx.print_variables();
+                    let selection = conf.get_profile_details(&args.profile, &ctx)?;
+                    // Machine-readable output must stay clean
+                    if args.format == VarsFormat::Pretty && args.only.is_none() {
+                        announce_profile(&selection);
+                    }
+                    let profile = optional_profile(selection)?;
+                    ctx.set_profile(profile)?;
+                    if !args.show_secrets {
+                        let mut secrets = conf.secrets.clone();
+                        secrets.extend(ctx.get_secret_variables().keys().cloned());
+                        ctx.set_secrets(secrets);
+                    }
+                    let package = match &args.package {
+                        Some(name) => Some(conf.resolve_package(name)?),
+                        None => None,
+                    };
+                    print_vars(&ctx, &args, package, verbose > 0)?;
+                }
+                Some(Command::Profile(args)) => {
+                    manage_profile(&mut conf, &working_dir, &args.command)?;
                 }
+                Some(Command::Rename(args)) => rename_package(&mut conf, &working_dir, &args)?,
+                Some(Command::Secrets(args)) => match args.command {
+                    SecretsCommand::Edit => secrets::edit_secrets(&conf, &ctx)?,
+                    SecretsCommand::Set { key, value } => {
+                        secrets::set_secret(&conf, &ctx, &key, &value)?
+                    }
+                },
                 _ => {
                     println!("Unknown command. Use --help for more information.");
                 }
@@ -161,15 +947,516 @@ pub fn run_cli(args: Cli) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-fn validate_profile_exists(
-    profile_name: &Option<String>,
-    profile: &Option<Profile>,
-) -> Result<(), anyhow::Error> {
-    if profile_name.is_some() && profile.is_none() {
+/// Open a package's source in the editor, and offer to deploy it if it changed.
+fn edit_package(conf: &Config, ctx: &mut Context, args: &EditArgs) -> Result<(), anyhow::Error> {
+    let package = conf.resolve_package(&args.package)?;
+    if let Some(remote) = package.remote_source() {
         anyhow::bail!(
-            "Profile '{}' not found in configuration",
-            profile_name.as_ref().unwrap()
+            "Package '{}' is downloaded from {} and read-only, edit it there",
+            package.name,
+            remote.url
         );
     }
+    let src = match package.resolve_src(ctx) {
+        Some(src) => src,
+        None => anyhow::bail!("Package '{}' has no src to edit", package.name),
+    };
+    if !src.exists() {
+        anyhow::bail!("{} does not exist", src.display());
+    }
+    let editor = find_editor().ok_or_else(|| {
+        anyhow::anyhow!("Neither $VISUAL nor $EDITOR is set, set one to the editor to use")
+    })?;
+
+    let before = hash_path(&src)?;
+    let status = run_editor(&editor, &src)?;
+    if !status.success() {
+        return Err(ExitCodeError {
+            code: status.code().unwrap_or(1),
+            message: format!("Editor exited with {:?}", status.code()),
+        }
+        .into());
+    }
+    if hash_path(&src)? == before {
+        cprintln(
+            &format!("'{}' unchanged, nothing to deploy", package.name),
+            &LogLevel::INFO,
+        );
+        return Ok(());
+    }
+
+    let deploy = if args.and_deploy {
+        true
+    } else if args.no_deploy {
+        false
+    } else if can_prompt() {
+        let answer = prompt(&format!("Deploy '{}' now? [y/N]", package.name))?;
+        matches!(answer.to_lowercase().as_str(), "y" | "yes")
+    } else {
+        cprintln(
+            &format!("Run `dotr deploy -p {}` to apply the changes", package.name),
+            &LogLevel::INFO,
+        );
+        false
+    };
+    if !deploy {
+        return Ok(());
+    }
+
+    let profile = require_profile(conf.get_profile_details(&args.profile, ctx)?)?;
+    ctx.set_profile(profile)?;
+    let deploy_args = DeployUpdateArgs {
+        packages: Some(vec![package.name.clone()]),
+        profile: args.profile.clone(),
+        ..Default::default()
+    };
+    ctx.get_prompted_variables(conf, &deploy_args.packages)?;
+    let report = conf.deploy_packages(ctx, &deploy_args)?;
+    cprintln(&report.summary("Deployed"), &LogLevel::INFO);
+    report.into_result()?;
     Ok(())
 }
+
+/// The repository to work in: `--working-dir`, then `DOTR_DIR`, then the
+/// closest directory with a config.toml up from the current one, and the
+/// current directory itself when there is none. `init` takes `--working-dir`
+/// or the current directory as they are.
+fn locate_working_dir(flag: Option<&str>, is_init: bool) -> Result<PathBuf, anyhow::Error> {
+    let pinned = match flag {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None if is_init => None,
+        None => std::env::var_os(DOTR_DIR_VAR)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from),
+    };
+    if let Some(dir) = pinned {
+        // Only canonicalize if the path exists
+        return Ok(if dir.exists() {
+            dir.canonicalize()?
+        } else {
+            dir
+        });
+    }
+    let cwd = std::env::current_dir()?;
+    if is_init {
+        return Ok(cwd);
+    }
+    match find_config_dir(&cwd, std::env::home_dir().as_deref()) {
+        Some(dir) => {
+            if dir != cwd {
+                cprintln(
+                    &format!("Using the repository at {}", dir.display()),
+                    &LogLevel::DEBUG,
+                );
+            }
+            Ok(dir)
+        }
+        None => Ok(cwd),
+    }
+}
+
+/// The closest of `start` and its parents that holds a config.toml, like git
+/// finds its repository. The search stops after `stop`, or at the root.
+pub fn find_config_dir(start: &Path, stop: Option<&Path>) -> Option<PathBuf> {
+    for dir in start.ancestors() {
+        if dir.join("config.toml").is_file() {
+            return Some(dir.to_path_buf());
+        }
+        if Some(dir) == stop {
+            break;
+        }
+    }
+    None
+}
+
+/// List the files an update dry run would copy back, `M` for modified and
+/// `A` for new, each followed by its diff when `show_diff`, and `D` for the
+/// sources `--prune-missing` would remove.
+fn print_update_preview(report: &Report, working_dir: &Path, show_diff: bool) {
+    for file in report.packages.iter().flat_map(|p| p.diffs.iter()) {
+        let marker = if file.dest.exists() { "M" } else { "A" };
+        cprintln(
+            &format!(
+                "{} {}",
+                marker,
+                file.dest
+                    .strip_prefix(working_dir)
+                    .unwrap_or(&file.dest)
+                    .display()
+            ),
+            &LogLevel::INFO,
+        );
+        if show_diff {
+            print_file_diff(file);
+        }
+    }
+    for path in report.packages.iter().flat_map(|p| p.pruned.iter()) {
+        cprintln(
+            &format!(
+                "D {}",
+                path.strip_prefix(working_dir).unwrap_or(path).display()
+            ),
+            &LogLevel::INFO,
+        );
+    }
+}
+
+fn print_diff_report(report: &Report) {
+    for pkg in report.packages.iter() {
+        cprintln(&format!("Package: {}", pkg.name), &LogLevel::INFO);
+        for file in pkg.diffs.iter() {
+            let file_name = file.src.file_name().unwrap_or_default().to_string_lossy();
+            if !file.is_changed() {
+                cprintln(&format!("No changes in {}", file_name), &LogLevel::INFO);
+                continue;
+            }
+            cprintln(
+                &format!("Changes in {} -> {}:", file_name, file.dest.display()),
+                &LogLevel::INFO,
+            );
+            print_file_diff(file);
+        }
+        for path in pkg.removed.iter() {
+            cprintln(
+                &format!(
+                    "{} was deleted from the repository, deploy removes it",
+                    path.display()
+                ),
+                &LogLevel::INFO,
+            );
+        }
+    }
+}
+
+fn print_file_diff(file: &FileDiff) {
+    match (&file.old, &file.new) {
+        (Some(old), Some(new)) => print_line_diff(old, new),
+        _ => println!("Binary files differ"),
+    }
+}
+
+fn print_vars(
+    ctx: &Context,
+    args: &PrintVarsArgs,
+    package: Option<&Package>,
+    verbose: bool,
+) -> Result<(), anyhow::Error> {
+    // Show why a variable is left unresolved rather than printing it as written
+    match package {
+        Some(package) => package.resolved_variables(ctx).map(|_| ())?,
+        None => ctx.resolved_variables(None).map(|_| ())?,
+    }
+    let variables = ctx.get_package_display_variables(package);
+    if let Some(key) = &args.only {
+        let value = lookup_path(&variables, key)
+            .ok_or_else(|| anyhow::anyhow!("Variable '{}' not found", key))?;
+        println!("{}", format_raw_value(value, args.format)?);
+        return Ok(());
+    }
+    match args.format {
+        VarsFormat::Pretty if args.sources => ctx.print_variables_with_sources(package, verbose),
+        VarsFormat::Pretty => ctx.print_variables(package),
+        VarsFormat::Toml => print!("{}", toml::to_string(&variables)?),
+        VarsFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&toml_to_json(&toml::Value::Table(variables)))?
+        ),
+    }
+    Ok(())
+}
+
+/// Render a single value for shell consumption: scalars are printed without quotes,
+/// tables and arrays as TOML when requested, JSON otherwise.
+fn format_raw_value(value: &toml::Value, format: VarsFormat) -> Result<String, anyhow::Error> {
+    Ok(match value {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Integer(i) => i.to_string(),
+        toml::Value::Float(f) => f.to_string(),
+        toml::Value::Boolean(b) => b.to_string(),
+        toml::Value::Datetime(d) => d.to_string(),
+        toml::Value::Table(t) if format == VarsFormat::Toml => toml::to_string(t)?,
+        _ => serde_json::to_string(&toml_to_json(value))?,
+    })
+}
+
+fn rename_package(
+    conf: &mut Config,
+    working_dir: &Path,
+    args: &RenameArgs,
+) -> Result<(), anyhow::Error> {
+    let old = conf.resolve_package_name(&args.old)?;
+    let plan = conf.rename_package(working_dir, &old, &args.new)?;
+    if args.dry_run {
+        for change in plan.changes.iter() {
+            cprintln(&format!("Would: {}", change), &LogLevel::INFO);
+        }
+        return Ok(());
+    }
+    conf.save_renamed(working_dir, &plan.moves)?;
+    for change in plan.changes.iter() {
+        cprintln(change, &LogLevel::INFO);
+    }
+    // What deploy recorded follows the package, so clean and status still find it
+    let mut state = DeployState::load(working_dir)?;
+    if state.package(&old).is_some() {
+        state.rename_package(&old, &args.new);
+        state.save(working_dir)?;
+    }
+    let manifest = manifest_path(working_dir, &old);
+    if manifest.exists() {
+        std::fs::rename(&manifest, manifest_path(working_dir, &args.new))?;
+    }
+    Ok(())
+}
+
+/// A row per package with where it goes, or with `--groups` a row per group
+/// with its members, those that don't exist marked as missing.
+pub fn render_list(conf: &Config, args: &ListArgs, color: bool) -> String {
+    let mut rows = Vec::new();
+    if args.groups {
+        let mut names: Vec<&String> = conf.groups.keys().collect();
+        names.sort();
+        if names.is_empty() {
+            return "No groups defined\n".to_string();
+        }
+        for name in names {
+            let missing = conf.missing_group_members(name);
+            let members = conf.groups[name]
+                .iter()
+                .map(|member| {
+                    if missing.contains(&member) {
+                        format!("{} (missing)", member)
+                    } else {
+                        member.clone()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let flag = if missing.is_empty() {
+                Cell::plain("")
+            } else {
+                Cell::colored(format!("{} missing", missing.len()), Color::Yellow)
+            };
+            rows.push(vec![
+                Cell::colored(format!("@{}", name), Color::Blue),
+                Cell::plain(members),
+                flag,
+            ]);
+        }
+    } else {
+        let mut names: Vec<&String> = conf.packages.keys().collect();
+        names.sort();
+        if names.is_empty() {
+            return "No packages defined\n".to_string();
+        }
+        for name in names {
+            let package = &conf.packages[name];
+            rows.push(vec![
+                Cell::colored(name.as_str(), Color::Blue),
+                Cell::plain(package.dest.clone().unwrap_or_default()),
+            ]);
+        }
+    }
+    render_table(&rows, color)
+}
+
+/// What `list --output json` prints: an entry per package with its dest as
+/// configured, or with `--groups` an entry per group.
+pub fn list_document(conf: &Config, args: &ListArgs, mut document: Document) -> Document {
+    if args.groups {
+        let mut names: Vec<&String> = conf.groups.keys().collect();
+        names.sort();
+        document.groups = names
+            .into_iter()
+            .map(|name| GroupEntry {
+                name: name.clone(),
+                members: conf.groups[name].clone(),
+                missing: conf
+                    .missing_group_members(name)
+                    .into_iter()
+                    .cloned()
+                    .collect(),
+            })
+            .collect();
+        return document;
+    }
+    let mut names: Vec<&String> = conf.packages.keys().collect();
+    names.sort();
+    document.packages = names
+        .into_iter()
+        .map(|name| PackageEntry {
+            dest: conf.packages[name].dest.clone(),
+            ..PackageEntry::new(name)
+        })
+        .collect();
+    document
+}
+
+fn manage_profile(
+    conf: &mut Config,
+    working_dir: &Path,
+    command: &ProfileCommand,
+) -> Result<(), anyhow::Error> {
+    match command {
+        ProfileCommand::List => {
+            let mut names: Vec<&String> = conf.profiles.keys().collect();
+            names.sort();
+            if names.is_empty() {
+                println!("No profiles defined");
+            }
+            let mut rows = Vec::new();
+            for name in names {
+                let profile = conf
+                    .resolve_profile(name)?
+                    .unwrap_or_else(|| Profile::new(name));
+                let extends = profile
+                    .extends
+                    .as_ref()
+                    .map(|parent| format!("extends {}", parent))
+                    .unwrap_or_default();
+                rows.push(vec![
+                    Cell::colored(name.as_str(), Color::Blue),
+                    Cell::plain(format!("{} packages", profile.dependencies.len())),
+                    Cell::plain(extends),
+                ]);
+            }
+            print!("{}", render_table(&rows, color_enabled()));
+            return Ok(());
+        }
+        ProfileCommand::Show { name } => {
+            let profile = conf
+                .resolve_profile(name)?
+                .ok_or_else(|| anyhow::anyhow!("Profile '{}' not found in configuration", name))?;
+            println!("Profile: {}", profile.name);
+            if let Some(parent) = &profile.extends {
+                println!("Extends: {}", parent);
+            }
+            println!("Dependencies:");
+            for dep in profile.dependencies.iter() {
+                println!("  {}", dep);
+            }
+            println!("Variables:");
+            for (key, value) in profile.variables.iter() {
+                print_variable(key, value, 1);
+            }
+            println!("Prompts:");
+            let mut prompts: Vec<(&String, &Prompt)> = profile.prompts.iter().collect();
+            prompts.sort_by_key(|(key, _)| *key);
+            for (key, prompt) in prompts {
+                println!("  {} = {}", key, prompt.display_message());
+            }
+            return Ok(());
+        }
+        ProfileCommand::Create { name } => conf.create_profile(name)?,
+        ProfileCommand::Add { name, packages } => conf.add_profile_packages(name, packages)?,
+        ProfileCommand::Remove { name, packages } => {
+            conf.remove_profile_packages(name, packages)?
+        }
+        ProfileCommand::Delete { name, force } => conf.delete_profile(name, *force)?,
+    }
+    conf.save(working_dir)
+}
+
+/// Parse `--set KEY=VALUE` arguments into variables. A value that reads as
+/// TOML keeps its type, anything else is a string.
+pub fn parse_set_values(values: &[String]) -> Result<toml::Table, anyhow::Error> {
+    let mut variables = toml::Table::new();
+    for item in values {
+        let Some((key, value)) = item.split_once('=').filter(|(k, _)| !k.trim().is_empty()) else {
+            anyhow::bail!("--set expects KEY=VALUE, got '{}'", item);
+        };
+        let value = toml::from_str::<toml::Table>(&format!("v = {}", value))
+            .ok()
+            .and_then(|mut t| t.remove("v"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+        variables.insert(key.trim().to_string(), value);
+    }
+    check_reserved_variables(&variables, "--set")?;
+    Ok(variables)
+}
+
+/// The banner to show: `banner_text` or the content of `banner_file`
+/// rendered with the global variables, `dotr.profile` naming the profile
+/// selected without --profile. Falls back to the built-in art, with a
+/// warning when the custom one can't be read or rendered.
+pub fn render_banner(conf: &Config, ctx: &Context) -> String {
+    let template = match (&conf.banner_text, &conf.banner_file) {
+        (Some(text), _) => Ok(text.clone()),
+        (None, Some(file)) => std::fs::read_to_string(resolve_path(file, &ctx.working_dir))
+            .map_err(|e| anyhow::anyhow!("Failed to read banner_file '{}': {}", file, e)),
+        (None, None) => return BANNER.to_string(),
+    };
+    let mut variables = ctx.get_context_variables();
+    ctx.insert_system_variables(&mut variables);
+    if let Some(toml::Value::Table(system)) = variables.get_mut(SYSTEM_VARIABLES) {
+        let profile = conf
+            .get_profile_details(&None, ctx)
+            .ok()
+            .and_then(|selection| selection.name)
+            .unwrap_or_default();
+        system.insert("profile".to_string(), toml::Value::String(profile));
+    }
+    match template.and_then(|template| compile_string(&template, &variables)) {
+        Ok(banner) => {
+            let lines: Vec<&str> = banner.lines().collect();
+            if lines.len() > BANNER_MAX_LINES {
+                cprintln(
+                    &format!(
+                        "The banner has {} lines, showing the first {}",
+                        lines.len(),
+                        BANNER_MAX_LINES
+                    ),
+                    &LogLevel::DEBUG,
+                );
+            }
+            lines[..lines.len().min(BANNER_MAX_LINES)].join("\n")
+        }
+        Err(e) => {
+            ecprintln(
+                &format!("Failed to render the banner, showing the default: {:#}", e),
+                &LogLevel::WARNING,
+            );
+            BANNER.to_string()
+        }
+    }
+}
+
+fn announce_profile(selection: &ProfileSelection) {
+    if let (Some(name), Some(source)) = (&selection.name, &selection.source) {
+        cprintln(
+            &format!("Using profile '{}' (from {})", name, source),
+            &LogLevel::INFO,
+        );
+    }
+}
+
+/// The selected profile for commands that apply it: a name that isn't configured is an error.
+fn require_profile(selection: ProfileSelection) -> Result<Option<Profile>, anyhow::Error> {
+    announce_profile(&selection);
+    if let (Some(name), None) = (&selection.name, &selection.profile) {
+        anyhow::bail!(
+            "Profile '{}' not found in configuration (from {})",
+            name,
+            selection.source.unwrap_or(ProfileSource::Cli)
+        );
+    }
+    Ok(selection.profile)
+}
+
+/// Like `require_profile`, but a missing `default_profile` only warns.
+fn optional_profile(selection: ProfileSelection) -> Result<Option<Profile>, anyhow::Error> {
+    if selection.source == Some(ProfileSource::Default) && selection.profile.is_none() {
+        cprintln(
+            &format!(
+                "default_profile '{}' not found in configuration, continuing without a profile",
+                selection.name.as_deref().unwrap_or_default()
+            ),
+            &LogLevel::WARNING,
+        );
+        return Ok(None);
+    }
+    if let (Some(name), None) = (&selection.name, &selection.profile) {
+        anyhow::bail!("Profile '{}' not found in configuration", name);
+    }
+    Ok(selection.profile)
+}
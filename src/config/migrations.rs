@@ -0,0 +1,107 @@
+use toml::{Table, Value};
+
+/// The config.toml schema this dotr reads and writes.
+pub const SCHEMA_VERSION: u32 = 2;
+
+// One step from `from` to `from + 1`, applied to the raw table so anything it
+// doesn't know about is left as written. `apply` tells whether it changed anything.
+struct Migration {
+    from: u32,
+    description: &'static str,
+    apply: fn(&mut Table) -> bool,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "dependencies given as a single name are now arrays",
+        apply: dependencies_to_arrays,
+    },
+    Migration {
+        from: 1,
+        description: "the 'prune' package key is now 'prune_dest'",
+        apply: rename_prune,
+    },
+];
+
+/// The schema version of a raw config table, 0 when it has none.
+pub fn schema_version(table: &Table) -> Result<u32, anyhow::Error> {
+    match table.get("schema_version") {
+        Some(value) => value
+            .as_integer()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| anyhow::anyhow!("schema_version must be a non-negative integer")),
+        None => Ok(0),
+    }
+}
+
+/// Bring `table` from schema `from` up to `SCHEMA_VERSION`, in order.
+/// Returns the description of each step that changed something.
+pub fn migrate(table: &mut Table, from: u32) -> Vec<&'static str> {
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= from) {
+        if (migration.apply)(table) {
+            applied.push(migration.description);
+        }
+    }
+    table.insert(
+        "schema_version".to_string(),
+        Value::Integer(SCHEMA_VERSION as i64),
+    );
+    applied
+}
+
+// Each package's table, however packages are listed
+fn package_tables(table: &mut Table) -> Vec<&mut Table> {
+    match table.get_mut("packages") {
+        Some(Value::Table(packages)) => packages
+            .iter_mut()
+            .map(|(_, value)| value)
+            .filter_map(Value::as_table_mut)
+            .collect(),
+        Some(Value::Array(packages)) => packages
+            .iter_mut()
+            .filter_map(Value::as_table_mut)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn dependencies_to_arrays(table: &mut Table) -> bool {
+    let mut changed = false;
+    for package in package_tables(table) {
+        changed |= single_to_array(package, "dependencies");
+    }
+    if let Some(Value::Table(profiles)) = table.get_mut("profiles") {
+        for profile in profiles
+            .iter_mut()
+            .map(|(_, value)| value)
+            .filter_map(Value::as_table_mut)
+        {
+            changed |= single_to_array(profile, "dependencies");
+        }
+    }
+    changed
+}
+
+fn single_to_array(owner: &mut Table, key: &str) -> bool {
+    let Some(Value::String(single)) = owner.get(key) else {
+        return false;
+    };
+    let array = Value::Array(vec![Value::String(single.clone())]);
+    owner.insert(key.to_string(), array);
+    true
+}
+
+fn rename_prune(table: &mut Table) -> bool {
+    let mut changed = false;
+    for package in package_tables(table) {
+        if !package.contains_key("prune_dest")
+            && let Some(prune) = package.remove("prune")
+        {
+            package.insert("prune_dest".to_string(), prune);
+            changed = true;
+        }
+    }
+    changed
+}
@@ -1,25 +1,191 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Read,
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
+use indicatif::HumanBytes;
 use serde::{Deserialize, Serialize};
 use toml::{Table, Value, map::Map};
 
+pub mod migrations;
+pub mod presets;
+
 use crate::{
-    cli::{DeployUpdateArgs, ImportArgs},
-    context::Context,
-    package::Package,
-    profile::Profile,
-    utils::{LogLevel, cprintln},
+    cache::DeployCache,
+    cli::{CleanArgs, DeployUpdateArgs, FetchArgs, ImportArgs, StatusArgs, UpdateArgs, VerifyArgs},
+    config::{
+        migrations::{SCHEMA_VERSION, migrate, schema_version},
+        presets::{InitPreset, preset_config, preset_config_text, preset_files},
+    },
+    context::{Context, check_reserved_variables, current_os},
+    git::{GIT_KEYS, GitConfig},
+    manifest::{FileStatus, Manifest, manifest_names, remove_manifest, sha256_bytes},
+    package::{
+        DOTRIGNORE_FILE, DeploySteps, PACKAGE_KEYS, Package, TEMPLATES_DIR, VARIANT_SEPARATOR,
+        parse_octal_mode, set_mode, validate_package_name,
+    },
+    plan::{Plan, PlanAction},
+    profile::{PROFILE_KEYS, Profile},
+    prompts::Prompt,
+    remote::report_fetch,
+    report::{
+        PackageReport, Report,
+        output::{Document, FileEntry, FileState, OutputFormat, PackageEntry, PackageStatus},
+    },
+    stage::{DEFAULT_STAGE_DIR, Stage},
+    state::{DeployState, LastDeploy},
+    utils::{
+        Cell, Color, LogLevel, Progress, color_enabled, count_field, cprintln, edit_distance,
+        format_size, normalize_home_path, normalize_path, render_table, resolve_path, size_field,
+        to_path_base_form, unknown_keys,
+    },
 };
 
+/// What renaming a package changes: a line per change to the config, and
+/// the files to move on disk.
+#[derive(Debug, Clone, Default)]
+pub struct RenamePlan {
+    pub changes: Vec<String>,
+    pub moves: Vec<(PathBuf, PathBuf)>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Config {
     pub banner: bool,
+    #[serde(default)]
+    pub banner_text: Option<String>, // A template shown instead of the built-in banner
+    #[serde(default)]
+    pub banner_file: Option<String>, // Like banner_text, read from a file in the repository
     pub packages: HashMap<String, Package>,
     pub profiles: HashMap<String, Profile>,
+    #[serde(default)]
+    pub groups: HashMap<String, Vec<String>>, // Named sets of packages, selected as `--packages @name`
     pub variables: Table,
-    pub prompts: HashMap<String, String>, // The key of variable, and the value is the prompt message
+    pub prompts: HashMap<String, Prompt>, // The key of variable, and the value is how to ask for it
+    #[serde(default)]
+    pub secrets: Vec<String>, // Variable paths (dotted for nested tables) masked when printed
+    #[serde(default)]
+    pub shell: Option<String>, // Shell for actions, overridable per package
+    #[serde(default)]
+    pub default_profile: Option<String>, // Profile used when nothing else selects one
+    #[serde(default)]
+    pub git: GitConfig,
+    #[serde(default)]
+    pub secrets_file: Option<String>, // age-encrypted TOML of variables, decrypted with DOTR_AGE_KEY
+    #[serde(default)]
+    pub paths: HashMap<String, String>, // Named bases that dests can start from, as `@name/...`
+    #[serde(default)]
+    pub action_timeout_secs: Option<u64>, // Kill a pre or post action that runs longer
+    #[serde(default)]
+    pub ignore: Vec<String>, // Patterns ignored in every package, before the package's own
+    #[serde(default)]
+    pub strict: bool, // Unknown keys in config.toml are errors instead of warnings
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>, // Glob patterns of files whose packages, profiles and variables are merged in
+    #[serde(default)]
+    pub env_allowlist: Option<Vec<String>>, // Glob patterns of environment variables templates see at the top level
+    #[serde(default)]
+    pub protected_paths: Vec<String>, // Dests under these need --allow-system-paths, whatever the package says
+    #[serde(default)]
+    pub allow_template_io: bool, // Templates may call read_file and cmd
+    #[serde(default)]
+    pub export_env: bool, // Pass the variables to actions as DOTR_VAR_* environment variables
+    #[serde(default)]
+    pub export_env_separator: Option<String>, // Joins exported arrays, ':' when unset
+    #[serde(default)]
+    pub backup_dir: Option<String>, // Backups go in a tree under it instead of beside each dest
+    #[serde(default)]
+    pub max_file_size: Option<u64>, // Bytes, for packages that don't set their own
+    #[serde(default)]
+    pub max_files: Option<usize>, // For packages that don't set their own
+    #[serde(default)]
+    pub schema_version: u32, // After migrations, only above SCHEMA_VERSION for a config from a newer dotr
+    #[serde(skip)]
+    origins: Origins,
+    #[serde(skip)]
+    strict_flag: bool, // --strict, which isn't saved to config.toml
+    #[serde(skip)]
+    aliases: HashMap<String, String>, // Alias to the name of the package it stands for
+    #[serde(skip)]
+    packages_as_array: bool, // config.toml lists packages as [[packages]] with a name each
+}
+
+/// The included file each package, profile and variable was loaded from,
+/// relative to the working directory. Anything not listed is from config.toml.
+#[derive(Debug, Clone, Default)]
+struct Origins {
+    packages: HashMap<String, PathBuf>,
+    profiles: HashMap<String, PathBuf>,
+    variables: HashMap<String, PathBuf>,
+}
+
+impl Origins {
+    fn section(&self, section: &str) -> &HashMap<String, PathBuf> {
+        match section {
+            "packages" => &self.packages,
+            "profiles" => &self.profiles,
+            _ => &self.variables,
+        }
+    }
+
+    fn section_mut(&mut self, section: &str) -> &mut HashMap<String, PathBuf> {
+        match section {
+            "packages" => &mut self.packages,
+            "profiles" => &mut self.profiles,
+            _ => &mut self.variables,
+        }
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self
+            .packages
+            .values()
+            .chain(self.profiles.values())
+            .chain(self.variables.values())
+            .cloned()
+            .collect();
+        files.sort();
+        files.dedup();
+        files
+    }
 }
 
+// The sections an included file can hold
+pub const INCLUDE_KEYS: &[&str] = &["packages", "profiles", "variables"];
+
+// The top-level keys config.toml understands
+pub const CONFIG_KEYS: &[&str] = &[
+    "action_timeout_secs",
+    "allow_template_io",
+    "backup_dir",
+    "banner",
+    "banner_file",
+    "banner_text",
+    "default_profile",
+    "env_allowlist",
+    "export_env",
+    "export_env_separator",
+    "git",
+    "groups",
+    "ignore",
+    "include",
+    "max_file_size",
+    "max_files",
+    "packages",
+    "paths",
+    "profiles",
+    "prompts",
+    "protected_paths",
+    "schema_version",
+    "secrets",
+    "secrets_file",
+    "shell",
+    "strict",
+    "variables",
+];
+
 impl Default for Config {
     fn default() -> Self {
         Self::new()
@@ -28,33 +194,383 @@ impl Default for Config {
 
 impl Config {
     pub fn from_path(cwd: &Path) -> Result<Self, anyhow::Error> {
+        Self::load(cwd, false)
+    }
+
+    /// Load config.toml from `cwd`. Unknown keys are warned about, or are an
+    /// error with `strict` or when the config sets `strict = true`.
+    pub fn load(cwd: &Path, strict: bool) -> Result<Self, anyhow::Error> {
         let config_path = cwd.join("config.toml");
         if !config_path.exists() {
             anyhow::bail!("config.toml not found in the current directory");
         }
         let config_content = std::fs::read_to_string(config_path)?;
-        let conf_table = config_content.parse::<Table>()?;
-        Self::from_table(&conf_table)
+        let mut conf_table = config_content.parse::<Table>()?;
+        let packages_as_array = packages_from_array(&mut conf_table)?;
+        let origins = merge_includes(cwd, &mut conf_table)?;
+        let mut conf = Self::from_table_strict(&conf_table, strict)?;
+        conf.origins = origins;
+        conf.strict_flag = strict;
+        conf.packages_as_array = packages_as_array;
+        Ok(conf)
     }
 
+    /// Write config.toml, leaving out what came from included files. Those
+    /// are only rewritten when one of their packages, profiles or variables changed.
+    /// Entries of a package's `removed` that are back in its sources are
+    /// dropped first, with a warning.
     pub fn save(&self, cwd: &Path) -> Result<(), anyhow::Error> {
-        let table = self.to_table();
+        let mut conf = self.clone();
+        for package in conf.packages.values_mut() {
+            let revived = package.revived_removals(cwd);
+            if revived.is_empty() {
+                continue;
+            }
+            cprintln(
+                &format!(
+                    "Dropping {} from the removed files of '{}', it is back in its src",
+                    revived.join(", "),
+                    package.name
+                ),
+                &LogLevel::WARNING,
+            );
+            package.removed.retain(|entry| !revived.contains(entry));
+        }
+        conf.write(cwd)
+    }
+
+    fn write(&self, cwd: &Path) -> Result<(), anyhow::Error> {
+        let mut table = self.to_table();
+        for section in INCLUDE_KEYS {
+            if let Some(Value::Table(entries)) = table.get_mut(*section) {
+                entries.retain(|name, _| !self.origins.section(section).contains_key(name));
+                if entries.is_empty() {
+                    table.remove(*section);
+                }
+            }
+        }
+        if self.packages_as_array {
+            packages_to_array(&mut table);
+        }
         let config_content = table.to_string();
         std::fs::write(cwd.join("config.toml"), config_content)?;
+        for file in self.origins.files() {
+            self.save_include(cwd, &file)?;
+        }
+        Ok(())
+    }
+
+    /// The keys of config.toml and its includes that dotr doesn't know, as
+    /// `load` warns about them.
+    pub fn unknown_keys(cwd: &Path) -> Result<Vec<String>, anyhow::Error> {
+        let mut conf_table = std::fs::read_to_string(cwd.join("config.toml"))?.parse::<Table>()?;
+        packages_from_array(&mut conf_table)?;
+        merge_includes(cwd, &mut conf_table)?;
+        Ok(unknown_config_keys(&conf_table))
+    }
+
+    /// What is wrong with how packages and profiles refer to each other:
+    /// dependencies that aren't packages, profiles that don't resolve,
+    /// dependency cycles and an unknown `default_profile`.
+    pub fn dependency_problems(&self) -> Vec<String> {
+        let mut problems: Vec<String> = Vec::new();
+        let mut packages = self.packages.clone();
+        for pkg in packages.values_mut() {
+            for dep in pkg.dependencies.iter_mut().flatten() {
+                match self.resolve_package_name(dep) {
+                    Ok(name) => *dep = name,
+                    Err(e) => {
+                        problems.push(format!("{} (a dependency of package '{}')", e, pkg.name))
+                    }
+                }
+            }
+        }
+        let mut names: Vec<&String> = packages.keys().collect();
+        names.sort();
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut ordered: Vec<Package> = Vec::new();
+        for name in names {
+            let mut visiting: Vec<String> = Vec::new();
+            if let Err(e) =
+                visit_package(name, &packages, &mut visited, &mut visiting, &mut ordered)
+            {
+                problems.push(e.to_string());
+                break;
+            }
+        }
+        let mut profile_names: Vec<&String> = self.profiles.keys().collect();
+        profile_names.sort();
+        for name in profile_names {
+            if let Err(e) = self.resolve_profile(name) {
+                problems.push(e.to_string());
+            }
+            for dep in self.profiles[name].dependencies.iter() {
+                if let Err(e) = self.resolve_package_name(dep) {
+                    problems.push(format!("{} (a dependency of profile '{}')", e, name));
+                }
+            }
+        }
+        let mut group_names: Vec<&String> = self.groups.keys().collect();
+        group_names.sort();
+        for name in group_names {
+            if let Err(e) = self.group_packages(name, &mut Vec::new()) {
+                problems.push(e.to_string());
+            }
+        }
+        if let Some(default_profile) = &self.default_profile
+            && !self.profiles.contains_key(default_profile)
+        {
+            problems.push(format!(
+                "default_profile '{}' is not a defined profile",
+                default_profile
+            ));
+        }
+        problems
+    }
+
+    /// Refuse to go on when config.toml is from a newer dotr: saving it would
+    /// drop the settings this one doesn't know about.
+    pub fn check_schema_writable(&self) -> Result<(), anyhow::Error> {
+        if self.schema_version > SCHEMA_VERSION {
+            anyhow::bail!(
+                "config.toml uses schema v{}, this dotr only knows up to v{}: upgrade dotr to run commands that change anything",
+                self.schema_version,
+                SCHEMA_VERSION
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether problems that are otherwise warnings are errors, from `strict = true` or `--strict`.
+    pub fn is_strict(&self) -> bool {
+        self.strict || self.strict_flag
+    }
+
+    /// The files merged into config.toml through `include`.
+    pub fn included_files(&self) -> Vec<PathBuf> {
+        self.origins.files()
+    }
+
+    /// The name of the package `name` refers to: itself, or the package it
+    /// is an alias of. Unknown names suggest the closest package.
+    pub fn resolve_package_name(&self, name: &str) -> Result<String, anyhow::Error> {
+        if self.packages.contains_key(name) {
+            return Ok(name.to_string());
+        }
+        if let Some(canonical) = self.aliases.get(name) {
+            return Ok(canonical.clone());
+        }
+        match self.closest_package(name) {
+            Some(close) => anyhow::bail!(
+                "Package '{}' not found in configuration, did you mean '{}'?",
+                name,
+                close
+            ),
+            None => anyhow::bail!("Package '{}' not found in configuration", name),
+        }
+    }
+
+    // Package names with aliases resolved. Names that aren't configured are
+    // kept, what was deployed under them may still be around.
+    fn canonical_names(&self, names: &[String]) -> Vec<String> {
+        names
+            .iter()
+            .map(|name| self.aliases.get(name).unwrap_or(name).clone())
+            .collect()
+    }
+
+    /// Replace each `@group` in `names` with the packages of the group, and
+    /// of the groups it names in turn. Other names are kept as given.
+    pub fn expand_groups(&self, names: &[String]) -> Result<Vec<String>, anyhow::Error> {
+        let mut expanded: Vec<String> = Vec::new();
+        for name in names {
+            match name.strip_prefix('@') {
+                Some(group) => expanded.extend(self.group_packages(group, &mut Vec::new())?),
+                None => expanded.push(name.clone()),
+            }
+        }
+        Ok(expanded)
+    }
+
+    // The package names of group `name`, failing on an undefined group or
+    // package and on groups that end up including themselves
+    fn group_packages(
+        &self,
+        name: &str,
+        visiting: &mut Vec<String>,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        if visiting.iter().any(|visited| visited == name) {
+            visiting.push(name.to_string());
+            anyhow::bail!("Circular group reference: @{}", visiting.join(" -> @"));
+        }
+        let members = self.groups.get(name).ok_or_else(|| {
+            let mut known: Vec<String> = self.groups.keys().map(|g| format!("@{}", g)).collect();
+            known.sort();
+            if known.is_empty() {
+                anyhow::anyhow!(
+                    "Group '{}' is not defined, config.toml has no [groups]",
+                    name
+                )
+            } else {
+                anyhow::anyhow!(
+                    "Group '{}' is not defined, the groups are: {}",
+                    name,
+                    known.join(", ")
+                )
+            }
+        })?;
+        visiting.push(name.to_string());
+        let mut packages: Vec<String> = Vec::new();
+        for member in members {
+            match member.strip_prefix('@') {
+                Some(group) => packages.extend(self.group_packages(group, visiting)?),
+                None => packages.push(
+                    self.resolve_package_name(member)
+                        .map_err(|e| anyhow::anyhow!("{} (a member of group '{}')", e, name))?,
+                ),
+            }
+        }
+        visiting.pop();
+        Ok(packages)
+    }
+
+    /// The members of group `name` that aren't a package or a defined group.
+    pub fn missing_group_members(&self, name: &str) -> Vec<&String> {
+        self.groups[name]
+            .iter()
+            .filter(|member| match member.strip_prefix('@') {
+                Some(group) => !self.groups.contains_key(group),
+                None => self.resolve_package_name(member).is_err(),
+            })
+            .collect()
+    }
+
+    /// Like `resolve_package_name`, returning the package itself.
+    pub fn resolve_package(&self, name: &str) -> Result<&Package, anyhow::Error> {
+        let name = self.resolve_package_name(name)?;
+        Ok(&self.packages[&name])
+    }
+
+    // The package whose name, name without its f_/d_ prefix, or alias is
+    // closest to `name`, if any is close enough to be a typo
+    fn closest_package(&self, name: &str) -> Option<&str> {
+        let threshold = (name.chars().count() / 3).max(2);
+        self.packages
+            .values()
+            .flat_map(|pkg| {
+                let short = pkg
+                    .name
+                    .strip_prefix("f_")
+                    .or_else(|| pkg.name.strip_prefix("d_"));
+                std::iter::once(pkg.name.as_str())
+                    .chain(short)
+                    .chain(pkg.aliases.iter().map(String::as_str))
+                    .map(move |candidate| (edit_distance(name, candidate), pkg.name.as_str()))
+            })
+            .filter(|(distance, _)| *distance <= threshold)
+            .min()
+            .map(|(_, name)| name)
+    }
+
+    /// The included file a package was loaded from, None for config.toml.
+    pub fn package_origin(&self, name: &str) -> Option<&Path> {
+        self.origins.packages.get(name).map(PathBuf::as_path)
+    }
+
+    // Rewrite the entries of `file` that changed, keeping the others as written
+    fn save_include(&self, cwd: &Path, file: &Path) -> Result<(), anyhow::Error> {
+        let path = cwd.join(file);
+        let on_disk = if path.exists() {
+            std::fs::read_to_string(&path)?.parse::<Table>()?
+        } else {
+            Table::new()
+        };
+        let full = self.to_table();
+        let mut table = on_disk.clone();
+        for section in INCLUDE_KEYS {
+            let previous = on_disk.get(*section).and_then(|v| v.as_table());
+            let mut entries = Table::new();
+            if let Some(current) = full.get(*section).and_then(|v| v.as_table()) {
+                for (name, value) in current.iter() {
+                    if self
+                        .origins
+                        .section(section)
+                        .get(name)
+                        .map(PathBuf::as_path)
+                        != Some(file)
+                    {
+                        continue;
+                    }
+                    let unchanged = previous
+                        .and_then(|p| p.get(name))
+                        .filter(|old| normalize_entry(section, name, old).as_ref() == Some(value));
+                    entries.insert(name.clone(), unchanged.unwrap_or(value).clone());
+                }
+            }
+            if entries.is_empty() {
+                table.remove(*section);
+            } else {
+                table.insert(section.to_string(), Value::Table(entries));
+            }
+        }
+        if table != on_disk {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, table.to_string())?;
+        }
         Ok(())
     }
 
     pub fn from_table(table: &Table) -> Result<Self, anyhow::Error> {
+        Self::from_table_strict(table, false)
+    }
+
+    /// Like `from_table`, checking for unknown keys as `load` does.
+    pub fn from_table_strict(table: &Table, strict: bool) -> Result<Self, anyhow::Error> {
+        let strict = match table.get("strict") {
+            Some(v) => {
+                v.as_bool()
+                    .ok_or_else(|| anyhow::anyhow!("The 'strict' field must be a boolean"))?
+                    || strict
+            }
+            None => strict,
+        };
+        let mut table = table.clone();
+        let schema_version = schema_version(&table)?;
+        if schema_version < SCHEMA_VERSION {
+            let applied = migrate(&mut table, schema_version);
+            if !applied.is_empty() {
+                let mut summary = format!(
+                    "Migrated config.toml from schema v{} to v{}, written on the next save:",
+                    schema_version, SCHEMA_VERSION
+                );
+                for step in applied {
+                    summary.push_str(&format!("\n  {}", step));
+                }
+                cprintln(&summary, &LogLevel::INFO);
+            }
+        }
+        let packages_as_array = packages_from_array(&mut table)?;
+        let table = &table;
+        check_unknown_keys(table, strict)?;
+        // Every bad package and profile is reported at once, and none of the
+        // good ones are loaded without them, so a save can't drop the bad ones
+        let mut invalid: Vec<String> = Vec::new();
         let mut packages: HashMap<String, Package> = HashMap::new();
-        // Iter on packages value as key value
         let package_confs = table.get("packages").and_then(|v| v.as_table());
         if let Some(pkg_confs) = package_confs {
             for (key, val) in pkg_confs.iter() {
-                let pkg_val = val
+                let parsed = val
                     .as_table()
-                    .ok_or_else(|| anyhow::anyhow!("Package '{}' must be a table", key))?;
-                let pkg = Package::from_table(key, pkg_val)?;
-                packages.insert(pkg.name.clone(), pkg);
+                    .ok_or_else(|| anyhow::anyhow!("Package '{}' must be a table", key))
+                    .and_then(|pkg_val| Package::from_table(key, pkg_val));
+                match parsed {
+                    Ok(pkg) => {
+                        packages.insert(pkg.name.clone(), pkg);
+                    }
+                    Err(e) => invalid.push(entry_error("package", key, &e)),
+                }
             }
         }
 
@@ -62,11 +578,49 @@ impl Config {
         let profile_confs = table.get("profiles").and_then(|v| v.as_table());
         if let Some(prof_confs) = profile_confs {
             for (key, val) in prof_confs.iter() {
-                let prof_val = val
+                let parsed = val
                     .as_table()
-                    .ok_or_else(|| anyhow::anyhow!("Profile '{}' must be a table", key))?;
-                let profile = Profile::from_table(key, prof_val)?;
-                profiles.insert(profile.name.clone(), profile);
+                    .ok_or_else(|| anyhow::anyhow!("Profile '{}' must be a table", key))
+                    .and_then(|prof_val| Profile::from_table(key, prof_val));
+                match parsed {
+                    Ok(profile) => {
+                        profiles.insert(profile.name.clone(), profile);
+                    }
+                    Err(e) => invalid.push(entry_error("profile", key, &e)),
+                }
+            }
+        }
+        match invalid.as_slice() {
+            [] => {}
+            [only] => anyhow::bail!("Invalid configuration: {}", only),
+            _ => anyhow::bail!(
+                "{} invalid entries in the configuration:\n  {}",
+                invalid.len(),
+                invalid.join("\n  ")
+            ),
+        }
+        // Catch missing parents and cycles up front
+        for name in profiles.keys() {
+            profile_chain(&profiles, name)?;
+        }
+        let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+        if let Some(group_confs) = table.get("groups") {
+            let group_confs = group_confs
+                .as_table()
+                .ok_or_else(|| anyhow::anyhow!("The 'groups' field must be a table"))?;
+            for (key, val) in group_confs.iter() {
+                let members = val
+                    .as_array()
+                    .and_then(|array| {
+                        array
+                            .iter()
+                            .map(|v| v.as_str().map(|s| s.to_string()))
+                            .collect::<Option<Vec<String>>>()
+                    })
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Group '{}' must be an array of package names", key)
+                    })?;
+                groups.insert(key.clone(), members);
             }
         }
         let mut variables: Table = Table::new();
@@ -76,28 +630,239 @@ impl Config {
                 variables.insert(k.clone(), v.clone());
             }
         }
-        let mut prompts: HashMap<String, String> = HashMap::new();
+        check_reserved_variables(&variables, "config variables")?;
+        let mut secrets: Vec<String> = Vec::new();
+        if let Some(secrets_val) = table.get("secrets") {
+            let array = secrets_val
+                .as_array()
+                .ok_or_else(|| anyhow::anyhow!("The 'secrets' field must be an array"))?;
+            for v in array {
+                let key = v
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Secret variable name must be a string"))?;
+                secrets.push(key.to_string());
+            }
+        }
+        let git = match table.get("git") {
+            Some(git_val) => GitConfig::from_table(
+                git_val
+                    .as_table()
+                    .ok_or_else(|| anyhow::anyhow!("The 'git' field must be a table"))?,
+            )?,
+            None => GitConfig::default(),
+        };
+        let action_timeout_secs = match table.get("action_timeout_secs") {
+            Some(v) => Some(v.as_integer().filter(|secs| *secs > 0).ok_or_else(|| {
+                anyhow::anyhow!("action_timeout_secs must be a positive number of seconds")
+            })? as u64),
+            None => None,
+        };
+        let mut ignore: Vec<String> = Vec::new();
+        if let Some(ignore_val) = table.get("ignore") {
+            let array = ignore_val
+                .as_array()
+                .ok_or_else(|| anyhow::anyhow!("The 'ignore' field must be an array"))?;
+            for v in array {
+                let pattern = v
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Ignore pattern must be a string"))?;
+                ignore.push(pattern.to_string());
+            }
+        }
+        let mut protected_paths: Vec<String> = Vec::new();
+        if let Some(protected_val) = table.get("protected_paths") {
+            let array = protected_val
+                .as_array()
+                .ok_or_else(|| anyhow::anyhow!("The 'protected_paths' field must be an array"))?;
+            for v in array {
+                let path = v
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Protected paths must be strings"))?;
+                protected_paths.push(path.to_string());
+            }
+        }
+        let mut paths: HashMap<String, String> = HashMap::new();
+        if let Some(paths_val) = table.get("paths") {
+            let paths_table = paths_val
+                .as_table()
+                .ok_or_else(|| anyhow::anyhow!("The 'paths' field must be a table"))?;
+            for (name, base) in paths_table.iter() {
+                if !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+                {
+                    anyhow::bail!(
+                        "Invalid path base name '{}', use letters, digits, '-' and '_'",
+                        name
+                    );
+                }
+                let base = base
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("paths.{} must be a string", name))?;
+                if base.is_empty() || base.starts_with('@') {
+                    anyhow::bail!("paths.{} must be a directory, not '{}'", name, base);
+                }
+                paths.insert(name.clone(), base.to_string());
+            }
+        }
+        let mut prompts: HashMap<String, Prompt> = HashMap::new();
         if let Some(prompts_table) = table.get("prompts").and_then(|v| v.as_table()) {
             for (k, v) in prompts_table.iter() {
-                if let Some(prompt_str) = v.as_str() {
-                    prompts.insert(k.clone(), prompt_str.to_string());
+                prompts.insert(k.clone(), Prompt::from_value(k, v)?);
+                // Long form only: { message = "...", secret = true }
+                let secret = v.get("secret").and_then(|s| s.as_bool()).unwrap_or(false);
+                if secret && !secrets.contains(k) {
+                    secrets.push(k.clone());
                 }
             }
         }
+        let env_allowlist = match table.get("env_allowlist") {
+            Some(value) => Some(
+                value
+                    .as_array()
+                    .ok_or_else(|| anyhow::anyhow!("The 'env_allowlist' field must be an array"))?
+                    .iter()
+                    .map(|v| {
+                        v.as_str().map(|s| s.to_string()).ok_or_else(|| {
+                            anyhow::anyhow!("env_allowlist patterns must be strings")
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            None => None,
+        };
+        let allow_template_io = match table.get("allow_template_io") {
+            Some(v) => v.as_bool().ok_or_else(|| {
+                anyhow::anyhow!("The 'allow_template_io' field must be a boolean")
+            })?,
+            None => false,
+        };
+        let export_env = match table.get("export_env") {
+            Some(v) => v
+                .as_bool()
+                .ok_or_else(|| anyhow::anyhow!("The 'export_env' field must be a boolean"))?,
+            None => false,
+        };
+        let export_env_separator = match table.get("export_env_separator") {
+            Some(v) => Some(
+                v.as_str()
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("The 'export_env_separator' field must be a string")
+                    })?
+                    .to_string(),
+            ),
+            None => None,
+        };
+        let backup_dir = match table.get("backup_dir") {
+            Some(v) => Some(
+                v.as_str()
+                    .ok_or_else(|| anyhow::anyhow!("The 'backup_dir' field must be a string"))?
+                    .to_string(),
+            ),
+            None => None,
+        };
+        let banner_text = match table.get("banner_text") {
+            Some(v) => Some(
+                v.as_str()
+                    .ok_or_else(|| anyhow::anyhow!("The 'banner_text' field must be a string"))?
+                    .to_string(),
+            ),
+            None => None,
+        };
+        let banner_file = match table.get("banner_file") {
+            Some(v) => Some(
+                v.as_str()
+                    .ok_or_else(|| anyhow::anyhow!("The 'banner_file' field must be a string"))?
+                    .to_string(),
+            ),
+            None => None,
+        };
+        if banner_text.is_some() && banner_file.is_some() {
+            anyhow::bail!("Set either 'banner_text' or 'banner_file', not both");
+        }
+        let max_file_size = size_field(table, "max_file_size")?;
+        let max_files = count_field(table, "max_files")?;
+        let mut include: Vec<String> = Vec::new();
+        if let Some(include_val) = table.get("include") {
+            let array = include_val
+                .as_array()
+                .ok_or_else(|| anyhow::anyhow!("The 'include' field must be an array"))?;
+            for v in array {
+                let pattern = v
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Include pattern must be a string"))?;
+                include.push(pattern.to_string());
+            }
+        }
+        let aliases = package_aliases(&packages)?;
         Ok(Self {
             banner: table
                 .get("banner")
                 .and_then(|v| v.as_bool())
                 .unwrap_or(false),
+            banner_text,
+            banner_file,
             packages,
             profiles,
+            groups,
             variables,
             prompts,
+            secrets,
+            shell: table
+                .get("shell")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string()),
+            default_profile: table
+                .get("default_profile")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string()),
+            git,
+            secrets_file: table
+                .get("secrets_file")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string()),
+            paths,
+            action_timeout_secs,
+            ignore,
+            strict: table
+                .get("strict")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false),
+            include,
+            env_allowlist,
+            protected_paths,
+            allow_template_io,
+            export_env,
+            export_env_separator,
+            backup_dir,
+            max_file_size,
+            max_files,
+            schema_version: schema_version.max(SCHEMA_VERSION),
+            origins: Origins::default(),
+            strict_flag: false,
+            aliases,
+            packages_as_array,
         })
     }
     pub fn to_table(&self) -> Table {
         let mut table = Table::new();
+        table.insert(
+            "schema_version".to_string(),
+            Value::Integer(self.schema_version as i64),
+        );
         table.insert("banner".to_string(), toml::Value::Boolean(self.banner));
+        if let Some(banner_text) = &self.banner_text {
+            table.insert(
+                "banner_text".to_string(),
+                Value::String(banner_text.clone()),
+            );
+        }
+        if let Some(banner_file) = &self.banner_file {
+            table.insert(
+                "banner_file".to_string(),
+                Value::String(banner_file.clone()),
+            );
+        }
         if !self.variables.is_empty() {
             table.insert(
                 "variables".to_string(),
@@ -118,169 +883,1669 @@ impl Config {
             });
             table.insert("profiles".to_string(), profiles_table.into());
         }
+        if !self.groups.is_empty() {
+            let mut groups_table: Map<String, Value> = Map::new();
+            self.groups.iter().for_each(|(name, members)| {
+                let members = members.iter().map(|m| Value::String(m.clone())).collect();
+                groups_table.insert(name.clone(), Value::Array(members));
+            });
+            table.insert("groups".to_string(), groups_table.into());
+        }
         if !self.prompts.is_empty() {
             let mut prompts_table: Map<String, Value> = Map::new();
             self.prompts.iter().for_each(|(key, prompt)| {
-                prompts_table.insert(key.clone(), Value::String(prompt.clone()));
+                prompts_table.insert(key.clone(), prompt.to_value());
             });
             table.insert("prompts".to_string(), prompts_table.into());
         }
+        if !self.secrets.is_empty() {
+            let secrets: Vec<Value> = self
+                .secrets
+                .iter()
+                .map(|s| Value::String(s.clone()))
+                .collect();
+            table.insert("secrets".to_string(), Value::Array(secrets));
+        }
+        if let Some(shell) = &self.shell {
+            table.insert("shell".to_string(), Value::String(shell.clone()));
+        }
+        if let Some(default_profile) = &self.default_profile {
+            table.insert(
+                "default_profile".to_string(),
+                Value::String(default_profile.clone()),
+            );
+        }
+        if self.git != GitConfig::default() {
+            table.insert("git".to_string(), Value::Table(self.git.to_table()));
+        }
+        if let Some(secrets_file) = &self.secrets_file {
+            table.insert(
+                "secrets_file".to_string(),
+                Value::String(secrets_file.clone()),
+            );
+        }
+        if let Some(secs) = self.action_timeout_secs {
+            table.insert(
+                "action_timeout_secs".to_string(),
+                Value::Integer(secs as i64),
+            );
+        }
+        if !self.paths.is_empty() {
+            let mut paths_table: Map<String, Value> = Map::new();
+            self.paths.iter().for_each(|(name, base)| {
+                paths_table.insert(name.clone(), Value::String(base.clone()));
+            });
+            table.insert("paths".to_string(), paths_table.into());
+        }
+        if !self.ignore.is_empty() {
+            let ignore: Vec<Value> = self
+                .ignore
+                .iter()
+                .map(|s| Value::String(s.clone()))
+                .collect();
+            table.insert("ignore".to_string(), Value::Array(ignore));
+        }
+        if self.strict {
+            table.insert("strict".to_string(), Value::Boolean(true));
+        }
+        if let Some(allowlist) = &self.env_allowlist {
+            let allowlist: Vec<Value> =
+                allowlist.iter().map(|s| Value::String(s.clone())).collect();
+            table.insert("env_allowlist".to_string(), Value::Array(allowlist));
+        }
+        if !self.protected_paths.is_empty() {
+            let protected: Vec<Value> = self
+                .protected_paths
+                .iter()
+                .map(|s| Value::String(s.clone()))
+                .collect();
+            table.insert("protected_paths".to_string(), Value::Array(protected));
+        }
+        if self.allow_template_io {
+            table.insert("allow_template_io".to_string(), Value::Boolean(true));
+        }
+        if self.export_env {
+            table.insert("export_env".to_string(), Value::Boolean(true));
+        }
+        if let Some(separator) = &self.export_env_separator {
+            table.insert(
+                "export_env_separator".to_string(),
+                Value::String(separator.clone()),
+            );
+        }
+        if let Some(backup_dir) = &self.backup_dir {
+            table.insert("backup_dir".to_string(), Value::String(backup_dir.clone()));
+        }
+        if let Some(size) = self.max_file_size {
+            table.insert(
+                "max_file_size".to_string(),
+                Value::String(format_size(size)),
+            );
+        }
+        if let Some(count) = self.max_files {
+            table.insert("max_files".to_string(), Value::Integer(count as i64));
+        }
+        if !self.include.is_empty() {
+            let include: Vec<Value> = self
+                .include
+                .iter()
+                .map(|s| Value::String(s.clone()))
+                .collect();
+            table.insert("include".to_string(), Value::Array(include));
+        }
         table
     }
 
-    pub fn import_package(
+    /// Import every path in `args`, in order, and save the configuration once.
+    /// Failed paths are reported at the end, or abort the run with `--fail-fast`.
+    pub fn import_packages(
         &mut self,
         args: &ImportArgs,
         ctx: &Context,
         profile_name: &Option<String>,
     ) -> Result<(), anyhow::Error> {
-        cprintln(&format!("Importing from {}", args.path), &LogLevel::INFO);
-        let mut package = Package::from_path(args, &ctx.working_dir)?;
-        let pkg_name = package.name.clone();
-        package.backup(ctx)?;
-        if let Some(p_name) = profile_name {
-            let profile = self.profiles.entry(p_name.clone()).or_insert_with(|| {
-                cprintln(
-                    &format!("Profile '{}' not found, creating new", p_name),
-                    &LogLevel::WARNING,
-                );
-                Profile::new(p_name)
-            });
-            profile.dependencies.push(pkg_name.clone());
-            package.skip = true;
-            package.targets.insert(p_name.clone(), package.dest.clone());
+        if args.name.is_some() && args.paths.len() > 1 {
+            anyhow::bail!("--name can only be used when importing a single path");
         }
-        self.packages.insert(pkg_name.clone(), package);
-        self.save(&ctx.working_dir)?;
-        cprintln(&format!("Package '{}' imported", pkg_name), &LogLevel::INFO);
-        Ok(())
-    }
-
-    pub fn backup_packages(
-        &self,
-        ctx: &Context,
-        args: &DeployUpdateArgs,
-    ) -> Result<(), anyhow::Error> {
-        for (_, pkg) in self.filter_packages(ctx, &args.packages)?.iter() {
-            pkg.backup(ctx)?;
+        self.check_import_into(args)?;
+        let mut imported: Vec<String> = Vec::new();
+        let mut failed: Vec<(String, anyhow::Error)> = Vec::new();
+        for path in args.paths.iter() {
+            match self.import_package(path, args, ctx, profile_name) {
+                Ok(pkg_name) => imported.push(pkg_name),
+                Err(e) => {
+                    failed.push((path.clone(), e));
+                    if args.fail_fast {
+                        break;
+                    }
+                }
+            }
+        }
+        if !imported.is_empty() {
+            self.save(&ctx.working_dir)?;
+            self.print_imported(&imported);
+            if args.adopt {
+                self.adopt_packages(&imported, args.as_template, ctx)?;
+            }
+        }
+        if !failed.is_empty() {
+            let details = failed
+                .iter()
+                .map(|(path, e)| format!("  {}: {}", path, e))
+                .collect::<Vec<_>>()
+                .join("\n");
+            anyhow::bail!("Failed to import {} path(s):\n{}", failed.len(), details);
         }
         Ok(())
     }
 
-    pub fn filter_packages(
-        &self,
+    /// Import a single path as a package. The configuration is not saved.
+    pub fn import_package(
+        &mut self,
+        path: &str,
+        args: &ImportArgs,
         ctx: &Context,
-        names: &Option<Vec<String>>,
-    ) -> Result<HashMap<String, Package>, anyhow::Error> {
-        let mut packages: HashMap<String, Package> = HashMap::new();
-        if let Some(pkg_names) = names {
-            for name in pkg_names {
-                if let Some(pkg) = self.packages.get(name) {
-                    packages.insert(name.clone(), pkg.clone());
-                } else {
-                    return Err(anyhow::anyhow!("Package '{}' not found", name));
-                }
-            }
-        } else if let Some(profile) = &ctx.profile {
-            for dep in &profile.dependencies {
-                if let Some(pkg) = self.packages.get(dep) {
-                    packages.insert(dep.clone(), pkg.clone());
-                } else {
-                    return Err(anyhow::anyhow!(
-                        "Package '{}' not found for profile '{}'",
-                        dep,
-                        profile.name
-                    ));
-                }
-            }
-        } else {
-            // Insert to packages if skip is false
-            for (name, pkg) in self.packages.iter() {
-                if !pkg.skip {
-                    packages.insert(name.clone(), pkg.clone());
-                }
+        profile_name: &Option<String>,
+    ) -> Result<String, anyhow::Error> {
+        cprintln(&format!("Importing from {}", path), &LogLevel::INFO);
+        let mut package = Package::from_path(path, &args.name, &ctx.working_dir)?;
+        self.claim_import_name(&mut package, args, ctx)?;
+        let pkg_name = package.name.clone();
+        if !args.no_ignore {
+            let (files, size) = package.ignore_excluded_files(ctx)?;
+            if files > 0 {
+                cprintln(
+                    &format!(
+                        "Skipped {} file(s) ({}) matched by {} or .gitignore, added to the ignore list of '{}'",
+                        files,
+                        HumanBytes(size),
+                        DOTRIGNORE_FILE,
+                        pkg_name
+                    ),
+                    &LogLevel::INFO,
+                );
             }
         }
-        // Now resolve packages dependencies
-        let mut dependencies: HashMap<String, Package> = HashMap::new();
-        for (_, pkg) in packages.iter() {
-            if let Some(deps) = &pkg.dependencies {
-                for dep in deps {
-                    if let Some(dep_pkg) = self.packages.get(dep) {
-                        dependencies.insert(dep.clone(), dep_pkg.clone());
-                    } else {
-                        anyhow::bail!("Dependency package '{}' not found in configuration", dep);
-                    }
-                }
-            }
+        // Limits from the config, kept out of the new package's table
+        let mut limited = package.clone();
+        self.apply_limits(&mut limited);
+        limited.backup(ctx, false, &mut PackageReport::new(&package.name))?;
+        if args.as_template {
+            let substitutions = template_substitutions(ctx, &args.substitute)?;
+            package.templatize(ctx, &substitutions)?;
         }
-        packages.extend(dependencies);
-        Ok(packages)
+        self.add_imported(package, args, profile_name);
+        Ok(pkg_name)
     }
 
-    pub fn deploy_packages(
-        &self,
+    /// Import what `reader` holds as a new file package deploying to
+    /// `--dest`, for configs generated by other tools. The content is stored
+    /// byte for byte, and there is no original to back up. Saves the
+    /// configuration.
+    pub fn import_from_reader(
+        &mut self,
+        reader: &mut impl Read,
+        args: &ImportArgs,
         ctx: &Context,
-        args: &DeployUpdateArgs,
+        profile_name: &Option<String>,
     ) -> Result<(), anyhow::Error> {
-        cprintln("Deploying packages...", &LogLevel::INFO);
-        for (_, pkg) in self.filter_packages(ctx, &args.packages)?.iter() {
-            pkg.deploy(ctx)?;
+        let dest = args
+            .dest
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Importing from stdin needs --dest"))?;
+        let mode = match &args.mode {
+            Some(mode) => Some(parse_octal_mode(mode).ok_or_else(|| {
+                anyhow::anyhow!("--mode must be an octal mode like 600, got '{}'", mode)
+            })?),
+            None => None,
+        };
+        self.check_import_into(args)?;
+        let mut content = Vec::new();
+        reader
+            .read_to_end(&mut content)
+            .map_err(|e| anyhow::anyhow!("Failed to read stdin: {}", e))?;
+
+        let mut package = Package::from_content(dest, &args.name, &ctx.working_dir)?;
+        self.claim_import_name(&mut package, args, ctx)?;
+        let src = ctx
+            .working_dir
+            .join(package.src.as_deref().unwrap_or_default());
+        if let Some(parent) = src.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&src, &content)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", src.display(), e))?;
+        if let Some(mode) = mode {
+            set_mode(&src, mode)?;
+        }
+        if args.as_template {
+            let substitutions = template_substitutions(ctx, &args.substitute)?;
+            package.templatize(ctx, &substitutions)?;
         }
+        let pkg_name = self.add_imported(package, args, profile_name);
+        self.save(&ctx.working_dir)?;
+        self.print_imported(&[pkg_name]);
         Ok(())
     }
 
-    pub fn diff_packages(
-        &self,
-        ctx: &Context,
-        args: &DeployUpdateArgs,
-    ) -> Result<(), anyhow::Error> {
-        cprintln("Checking differences...", &LogLevel::INFO);
-        for (_, pkg) in self.filter_packages(ctx, &args.packages)?.iter() {
-            cprintln(&format!("Package: {}", pkg.name), &LogLevel::INFO);
-            pkg.diff(ctx)?;
+    // Refuse an --into file that no include pattern loads
+    fn check_import_into(&self, args: &ImportArgs) -> Result<(), anyhow::Error> {
+        if let Some(into) = &args.into {
+            let into = into.trim_start_matches("./");
+            if !self
+                .include
+                .iter()
+                .any(|pattern| glob_match::glob_match(pattern.trim_start_matches("./"), into))
+            {
+                anyhow::bail!(
+                    "{} is not matched by any include pattern in config.toml, so it wouldn't be loaded",
+                    into
+                );
+            }
         }
         Ok(())
     }
 
+    // Settle the name and dest of a package being imported, refusing to
+    // replace an existing package without --overwrite
+    fn claim_import_name(
+        &mut self,
+        package: &mut Package,
+        args: &ImportArgs,
+        ctx: &Context,
+    ) -> Result<(), anyhow::Error> {
+        // Keep dests portable between machines that lay out their home differently
+        if let Some(dest) = &package.dest
+            && let Some(based) = to_path_base_form(
+                &resolve_path(dest, &ctx.working_dir),
+                &self.paths,
+                &ctx.working_dir,
+            )
+        {
+            package.dest = Some(based);
+        }
+        if args.name.is_none() {
+            self.avoid_name_collision(package);
+        }
+        if let Some(existing) = self.packages.get(&package.name) {
+            if !args.overwrite {
+                anyhow::bail!(
+                    "Package '{}' already exists. Use --name to import under a different name or --overwrite to replace it",
+                    package.name
+                );
+            }
+            set_aside_imported_src(existing, ctx)?;
+        }
+        Ok(())
+    }
+
+    // Add an imported package to the config, and to the profile it was
+    // imported for. Returns its name.
+    fn add_imported(
+        &mut self,
+        mut package: Package,
+        args: &ImportArgs,
+        profile_name: &Option<String>,
+    ) -> String {
+        let pkg_name = package.name.clone();
+        if let Some(p_name) = profile_name {
+            let profile = self.profiles.entry(p_name.clone()).or_insert_with(|| {
+                cprintln(
+                    &format!("Profile '{}' not found, creating new", p_name),
+                    &LogLevel::WARNING,
+                );
+                Profile::new(p_name)
+            });
+            if !profile.dependencies.contains(&pkg_name) {
+                profile.dependencies.push(pkg_name.clone());
+            }
+            package.skip = true;
+            if let Some(dest) = package.dest.clone() {
+                package.targets.insert(p_name.clone(), vec![dest]);
+            }
+        }
+        self.packages.insert(pkg_name.clone(), package);
+        // A replaced package stays in the file it was in, unless told otherwise
+        if let Some(into) = &args.into {
+            self.origins.packages.insert(
+                pkg_name.clone(),
+                PathBuf::from(into.trim_start_matches("./")),
+            );
+        }
+        cprintln(&format!("Package '{}' imported", pkg_name), &LogLevel::INFO);
+        pkg_name
+    }
+
+    fn print_imported(&self, imported: &[String]) {
+        let mut summary = "Imported packages:".to_string();
+        for pkg_name in imported.iter() {
+            let pkg = &self.packages[pkg_name];
+            summary.push_str(&format!(
+                "\n  {}: {} -> {}",
+                pkg_name,
+                pkg.src.as_deref().unwrap_or_default(),
+                pkg.dest.as_deref().unwrap_or_default()
+            ));
+        }
+        cprintln(&summary, &LogLevel::INFO);
+    }
+
+    // The config-level max_file_size and max_files, for a package that
+    // doesn't set its own
+    fn apply_limits(&self, pkg: &mut Package) {
+        pkg.max_file_size = pkg.max_file_size.or(self.max_file_size);
+        pkg.max_files = pkg.max_files.or(self.max_files);
+    }
+
+    /// Deploy freshly imported packages from their dotfiles/ copy. When a
+    /// deploy fails, the originals are restored before the error is returned.
+    fn adopt_packages(
+        &self,
+        names: &[String],
+        templated: bool,
+        ctx: &Context,
+    ) -> Result<(), anyhow::Error> {
+        let args = DeployUpdateArgs {
+            packages: Some(names.to_vec()),
+            skip_actions: true,
+            no_git_check: true,
+            ..Default::default()
+        };
+        let report = self
+            .deploy_packages(ctx, &args)
+            .map_err(|e| anyhow::anyhow!("Failed to adopt the imported packages: {}", e))?;
+        for pkg_report in report.packages.iter() {
+            let Some(e) = &pkg_report.error else {
+                continue;
+            };
+            self.packages[&pkg_report.name].restore_imported(ctx, pkg_report, templated)?;
+            anyhow::bail!(
+                "Failed to adopt '{}', the original was left in place: {}",
+                pkg_report.name,
+                e
+            );
+        }
+        cprintln(
+            &format!(
+                "Adopted {}, edit them under dotfiles/ from now on",
+                names.join(", ")
+            ),
+            &LogLevel::INFO,
+        );
+        Ok(())
+    }
+
+    // A derived name that's taken by a package for another dest gets a
+    // numeric suffix. Re-importing the same dest keeps the name, so
+    // --overwrite still applies.
+    fn avoid_name_collision(&self, package: &mut Package) {
+        let taken = |name: &str| {
+            self.packages
+                .get(name)
+                .is_some_and(|existing| existing.dest != package.dest)
+        };
+        if !taken(&package.name) {
+            return;
+        }
+        let base = package.name.clone();
+        let name = (2..)
+            .map(|n| format!("{}_{}", base, n))
+            .find(|name| !taken(name))
+            .expect("Ran out of package names");
+        cprintln(
+            &format!(
+                "Package '{}' already exists for another path, importing as '{}' (use --name to choose)",
+                base, name
+            ),
+            &LogLevel::WARNING,
+        );
+        package.src = Some(format!("dotfiles/{}", name));
+        package.name = name;
+    }
+
+    /// Copy deployed files back into the repository, or with `--dry-run` only
+    /// compare them. The report lists the files that were, or would be, copied,
+    /// and stops at the first package that fails.
+    pub fn backup_packages(
+        &self,
+        ctx: &Context,
+        args: &UpdateArgs,
+    ) -> Result<Report, anyhow::Error> {
+        let started = Instant::now();
+        let packages = self.ordered_packages(ctx, &args.packages)?;
+        if !args.skip_validation {
+            validate_package_paths(&packages, ctx)?;
+            check_shared_files(&packages, ctx, self.is_strict())?;
+        }
+        if let Some(dir) = args.stage_to.as_ref().filter(|_| !args.dry_run) {
+            ctx.enable_stage(Stage::load(&resolve_path(dir, &ctx.working_dir))?);
+        }
+        let mut report = Report::default();
+        let progress = Progress::new(packages.len(), "packages");
+        for pkg in packages.iter() {
+            progress.set_message(&pkg.name);
+            let mut pkg_report = PackageReport::new(&pkg.name);
+            pkg_report.error = pkg.backup(ctx, args.dry_run, &mut pkg_report).err();
+            report.packages.push(pkg_report);
+            progress.inc();
+            if report.has_error() {
+                break;
+            }
+        }
+        drop(progress);
+        report.elapsed = started.elapsed();
+        // Saved even when a package failed, the files staged before it are there
+        if let Some(stage) = ctx.take_stage()
+            && !stage.is_empty()
+        {
+            stage.save()?;
+            cprintln(
+                &format!(
+                    "{} file(s) staged in {}, review them and apply with `dotr update --apply-staged`",
+                    stage.files.len(),
+                    stage.dir().display()
+                ),
+                &LogLevel::INFO,
+            );
+        }
+        Ok(report)
+    }
+
+    /// Copy the files `update --stage-to` staged over their sources and
+    /// clear the stage. Sources changed since staging need `--force`.
+    pub fn apply_staged(&self, ctx: &Context, args: &UpdateArgs) -> Result<(), anyhow::Error> {
+        let dir = args.stage_to.as_deref().unwrap_or(DEFAULT_STAGE_DIR);
+        let stage = Stage::load(&resolve_path(dir, &ctx.working_dir))?;
+        if stage.is_empty() {
+            cprintln(
+                &format!("Nothing staged in {}", stage.dir().display()),
+                &LogLevel::INFO,
+            );
+            return Ok(());
+        }
+        let conflicts = stage.conflicts(&ctx.working_dir)?;
+        if !conflicts.is_empty() && !args.force {
+            anyhow::bail!(
+                "These sources changed since they were staged, use --force to overwrite them:\n  {}",
+                conflicts
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n  ")
+            );
+        }
+        let applied = stage.apply(&ctx.working_dir)?;
+        stage.clear()?;
+        cprintln(
+            &format!("Applied {} staged file(s)", applied.len()),
+            &LogLevel::INFO,
+        );
+        Ok(())
+    }
+
+    /// Add the sources `update --prune-missing` deleted to the `removed`
+    /// list of their package, so deploy deletes them on other machines too.
+    /// Returns whether any package changed.
+    pub fn record_removed(&mut self, ctx: &Context, report: &Report) -> bool {
+        let mut changed = false;
+        for pkg_report in report.packages.iter() {
+            let Some(package) = self.packages.get_mut(&pkg_report.name) else {
+                continue;
+            };
+            let Some(src) = package.resolve_src(ctx) else {
+                continue;
+            };
+            for path in pkg_report.pruned.iter() {
+                let Ok(relative_path) = path.strip_prefix(&src) else {
+                    continue;
+                };
+                let entry = relative_path
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                if !package.removed.contains(&entry) {
+                    package.removed.push(entry);
+                    changed = true;
+                }
+            }
+            if !pkg_report.pruned.is_empty() {
+                package.removed.sort();
+            }
+        }
+        changed
+    }
+
+    /// The packages a command works on: those named in `names` (groups
+    /// expanded), else the dependencies of the active profile, else every
+    /// package without `skip`, plus their dependencies, minus those for
+    /// another OS or whose `when` condition is false.
+    pub fn filter_packages(
+        &self,
+        ctx: &Context,
+        names: &Option<Vec<String>>,
+    ) -> Result<HashMap<String, Package>, anyhow::Error> {
+        let selected = self.select_packages(ctx, names)?;
+        let mut dropped: Vec<(&String, &Exclusion)> = selected.excluded.iter().collect();
+        dropped.sort();
+        for (name, exclusion) in dropped {
+            let reason = match exclusion {
+                Exclusion::Os(os) => format!("not targeted at {}", os),
+                Exclusion::Condition => "its when condition is false".to_string(),
+                _ => continue,
+            };
+            cprintln(
+                &format!("Skipping package '{}', {}", name, reason),
+                &LogLevel::INFO,
+            );
+        }
+        Ok(selected.packages)
+    }
+
+    /// `filter_packages` with the reason each package was picked or left
+    /// out, for `dotr why`.
+    pub fn select_packages(
+        &self,
+        ctx: &Context,
+        names: &Option<Vec<String>>,
+    ) -> Result<SelectedPackages, anyhow::Error> {
+        let mut selected = SelectedPackages::default();
+        if let Some(pkg_names) = names {
+            for name in self.expand_groups(pkg_names)? {
+                let pkg = self.resolve_package(&name)?;
+                selected.include(pkg, Inclusion::Explicit);
+            }
+        } else if let Some(profile) = &ctx.profile {
+            for dep in &profile.dependencies {
+                let pkg = self.resolve_package(dep).map_err(|e| {
+                    anyhow::anyhow!("{} (a dependency of profile '{}')", e, profile.name)
+                })?;
+                selected.include(pkg, Inclusion::Profile(profile.name.clone()));
+            }
+        } else {
+            for pkg in self.packages.values().filter(|pkg| !pkg.skip) {
+                selected.include(pkg, Inclusion::Default);
+            }
+        }
+        // Now resolve packages dependencies, transitively
+        let mut pending: Vec<(String, String)> = selected
+            .packages
+            .values()
+            .flat_map(|pkg| {
+                pkg.dependencies
+                    .iter()
+                    .flatten()
+                    .map(|dep| (dep.clone(), pkg.name.clone()))
+            })
+            .collect();
+        while let Some((dep, dependent)) = pending.pop() {
+            let dep_pkg = self
+                .resolve_package(&dep)
+                .map_err(|e| anyhow::anyhow!("{} (a package dependency)", e))?;
+            if selected.packages.contains_key(&dep_pkg.name) {
+                continue;
+            }
+            pending.extend(
+                dep_pkg
+                    .dependencies
+                    .iter()
+                    .flatten()
+                    .map(|next| (next.clone(), dep_pkg.name.clone())),
+            );
+            selected.include(dep_pkg, Inclusion::DependencyOf(dependent));
+        }
+        // Dependencies may name a package by an alias, order them by its name
+        for pkg in selected.packages.values_mut() {
+            if let Some(deps) = pkg.dependencies.as_mut() {
+                for dep in deps.iter_mut() {
+                    if let Some(canonical) = self.aliases.get(dep) {
+                        *dep = canonical.clone();
+                    }
+                }
+            }
+        }
+        // Drop packages that don't apply to this OS, and those whose `when`
+        // condition doesn't hold
+        let os = current_os();
+        let mut picked: Vec<String> = selected.packages.keys().cloned().collect();
+        picked.sort();
+        for name in picked {
+            let pkg = &selected.packages[&name];
+            let exclusion = if !pkg.applies_to_os(&os) {
+                Exclusion::Os(os.clone())
+            } else if !pkg.condition_holds(ctx)? {
+                Exclusion::Condition
+            } else {
+                continue;
+            };
+            selected.packages.remove(&name);
+            selected.excluded.insert(name, exclusion);
+        }
+        // What none of the above picked
+        for name in self.packages.keys() {
+            if selected.included.contains_key(name) {
+                continue;
+            }
+            let exclusion = match (names.is_some(), &ctx.profile) {
+                (true, _) => Exclusion::NotRequested,
+                (false, Some(profile)) => Exclusion::NotInProfile(profile.name.clone()),
+                (false, None) => Exclusion::Skipped,
+            };
+            selected.excluded.insert(name.clone(), exclusion);
+        }
+        Ok(selected)
+    }
+
+    /// Why `name` is or isn't among the packages a command would work on,
+    /// with what pulled it in, or what would.
+    pub fn explain_selection(
+        &self,
+        ctx: &Context,
+        name: &str,
+        names: &Option<Vec<String>>,
+    ) -> Result<String, anyhow::Error> {
+        let package = self.resolve_package(name)?;
+        let selected = self.select_packages(ctx, names)?;
+        let mut lines = Vec::new();
+        match selected.excluded.get(&package.name) {
+            None => {
+                lines.push(format!("{}: included", package.name));
+                let mut current = package.name.clone();
+                let mut depth = 1;
+                while let Some(inclusion) = selected.included.get(&current) {
+                    lines.push(format!("{}{}", "  ".repeat(depth), inclusion));
+                    let Inclusion::DependencyOf(dependent) = inclusion else {
+                        break;
+                    };
+                    current = dependent.clone();
+                    depth += 1;
+                }
+            }
+            Some(exclusion) => {
+                let reason = match exclusion {
+                    Exclusion::NotInProfile(profile) if package.skip => format!(
+                        "skip = true and not listed in the dependencies of profile '{}'",
+                        profile
+                    ),
+                    Exclusion::Condition => format!(
+                        "its when condition `{}` is false",
+                        package.when.as_deref().unwrap_or_default()
+                    ),
+                    _ => exclusion.to_string(),
+                };
+                lines.push(format!("{}: excluded, {}", package.name, reason));
+                if let Some(inclusion) = selected.included.get(&package.name) {
+                    lines.push(format!("  otherwise {}", inclusion));
+                }
+                let hint = match exclusion {
+                    Exclusion::NotInProfile(profile) => format!(
+                        "passed with --packages or added to the dependencies of profile '{}'",
+                        profile
+                    ),
+                    Exclusion::Skipped => {
+                        "passed with --packages or added to a profile's dependencies".to_string()
+                    }
+                    Exclusion::NotRequested => "passed with --packages".to_string(),
+                    Exclusion::Os(_) => "its only_on and except_on allowed this OS".to_string(),
+                    Exclusion::Condition => "its when condition held".to_string(),
+                };
+                lines.push(format!("  would be included if {}", hint));
+            }
+        }
+        Ok(lines.join("\n") + "\n")
+    }
+
+    /// Filter packages like `filter_packages`, and order them so that every
+    /// package comes after its dependencies. Ties are broken by name. The
+    /// global ignore patterns are put in front of each package's own.
+    pub fn ordered_packages(
+        &self,
+        ctx: &Context,
+        names: &Option<Vec<String>>,
+    ) -> Result<Vec<Package>, anyhow::Error> {
+        let mut packages = self.filter_packages(ctx, names)?;
+        if !self.ignore.is_empty() {
+            for pkg in packages.values_mut() {
+                pkg.ignore = [self.ignore.clone(), std::mem::take(&mut pkg.ignore)].concat();
+            }
+        }
+        for pkg in packages.values_mut() {
+            self.apply_limits(pkg);
+        }
+        let mut names: Vec<&String> = packages.keys().collect();
+        names.sort();
+
+        let mut ordered: Vec<Package> = Vec::new();
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut visiting: Vec<String> = Vec::new();
+        for name in names {
+            visit_package(name, &packages, &mut visited, &mut visiting, &mut ordered)?;
+        }
+        Ok(ordered)
+    }
+
+    /// Deploy the selected packages in dependency order. The report says what
+    /// was done for each, and stops at the first package that fails.
+    pub fn deploy_packages(
+        &self,
+        ctx: &Context,
+        args: &DeployUpdateArgs,
+    ) -> Result<Report, anyhow::Error> {
+        let steps = if args.actions_only {
+            DeploySteps::ActionsOnly
+        } else if args.skip_actions {
+            DeploySteps::FilesOnly
+        } else {
+            DeploySteps::All
+        };
+        if steps == DeploySteps::ActionsOnly {
+            cprintln("Running package actions...", &LogLevel::INFO);
+        } else {
+            cprintln("Deploying packages...", &LogLevel::INFO);
+        }
+        // Actions only touch no files, so leave the cache as it is
+        if !args.no_cache && steps != DeploySteps::ActionsOnly {
+            ctx.enable_cache(DeployCache::load(&ctx.working_dir, &self.included_files()));
+        }
+        if steps != DeploySteps::ActionsOnly {
+            ctx.enable_state(DeployState::load(&ctx.working_dir)?);
+        }
+        let started = Instant::now();
+        let packages = self.ordered_packages(ctx, &args.packages)?;
+        if !args.skip_validation {
+            validate_package_paths(&packages, ctx)?;
+            check_shared_files(&packages, ctx, self.is_strict())?;
+        }
+        let mut report = Report::default();
+        let progress = Progress::new(packages.len(), "packages");
+        for mut pkg in packages {
+            progress.set_message(&pkg.name);
+            if args.prune {
+                pkg.prune_dest = true;
+            }
+            if pkg.shell.is_none() {
+                pkg.shell = self.shell.clone();
+            }
+            let mut pkg_report = PackageReport::new(&pkg.name);
+            pkg_report.error = pkg.deploy(ctx, steps, &mut pkg_report).err();
+            if pkg_report.error.is_none() {
+                let profile = ctx.profile.as_ref().map(|profile| profile.name.as_str());
+                let written = pkg_report.deployed.len();
+                ctx.with_state(|state| state.record_deploy(&pkg.name, profile, written));
+            }
+            report.packages.push(pkg_report);
+            progress.inc();
+            if report.has_error() {
+                break;
+            }
+        }
+        drop(progress);
+        report.elapsed = started.elapsed();
+        if args
+            .packages
+            .as_ref()
+            .is_some_and(|names| !names.is_empty())
+        {
+            let deployed: Vec<&str> = report.packages.iter().map(|p| p.name.as_str()).collect();
+            ctx.with_state(|state| self.report_stale_packages(ctx, state, &deployed));
+        }
+        // Saved even when a package failed, the files written before it are deployed
+        if let Some(state) = ctx.take_state() {
+            state.save(&ctx.working_dir)?;
+            self.save_manifests(&ctx.working_dir, &state, &report)?;
+        }
+        let cache = ctx.take_cache();
+        if let Some(cache) = cache.filter(|_| !report.has_error()) {
+            cache.save(&ctx.working_dir)?;
+        }
+        Ok(report)
+    }
+
+    /// Render the selected packages and note what deploying them would do
+    /// with each file, without touching any dest or running any action.
+    pub fn plan_packages(
+        &self,
+        ctx: &Context,
+        args: &DeployUpdateArgs,
+    ) -> Result<Plan, anyhow::Error> {
+        let packages = self.ordered_packages(ctx, &args.packages)?;
+        if !args.skip_validation {
+            validate_package_paths(&packages, ctx)?;
+            check_shared_files(&packages, ctx, self.is_strict())?;
+        }
+        let mut plan = Plan::new(ctx.profile.as_ref().map(|p| p.name.clone()));
+        for pkg in packages.iter() {
+            pkg.plan(ctx, &mut plan)?;
+        }
+        Ok(plan)
+    }
+
+    /// Write the files of a plan made by `deploy --plan`, and nothing else:
+    /// no actions run and no other file is touched. Refused before anything
+    /// is written when a dest changed since planning, or a src no longer
+    /// renders to what was planned.
+    pub fn apply_plan(&self, ctx: &Context, plan: &Plan) -> Result<Report, anyhow::Error> {
+        let drifted = plan.drifted()?;
+        if !drifted.is_empty() {
+            anyhow::bail!(
+                "These dests changed since the plan was made, plan again:\n  {}",
+                drifted
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n  ")
+            );
+        }
+        let mut packages: HashMap<String, Package> = HashMap::new();
+        for name in plan.package_names() {
+            let mut pkg = self.resolve_package(&name)?.clone();
+            self.apply_limits(&mut pkg);
+            packages.insert(name, pkg);
+        }
+        let mut contents = Vec::new();
+        for file in plan.files.iter().filter(|f| f.action != PlanAction::Skip) {
+            let content = packages[&file.package].render_file(&file.src, &file.dest, ctx)?;
+            if sha256_bytes(&content) != file.hash {
+                anyhow::bail!(
+                    "{} no longer renders to what was planned for {}, plan again",
+                    file.src.display(),
+                    file.dest.display()
+                );
+            }
+            contents.push((file, content));
+        }
+
+        ctx.enable_state(DeployState::load(&ctx.working_dir)?);
+        let started = Instant::now();
+        let mut report = Report::default();
+        for name in plan.package_names() {
+            let pkg = &packages[&name];
+            let mut pkg_report = PackageReport::new(&name);
+            for (file, content) in contents.iter().filter(|(f, _)| f.package == name) {
+                let applied =
+                    pkg.apply_planned_file(&file.src, &file.dest, content, ctx, &mut pkg_report);
+                if let Err(e) = applied {
+                    pkg_report.error = Some(e);
+                    break;
+                }
+            }
+            pkg_report.skipped.extend(
+                plan.files
+                    .iter()
+                    .filter(|f| f.package == name && f.action == PlanAction::Skip)
+                    .map(|f| f.dest.clone()),
+            );
+            if pkg_report.error.is_none() {
+                let written = pkg_report.deployed.len();
+                ctx.with_state(|state| {
+                    state.record_deploy(&name, plan.profile.as_deref(), written)
+                });
+            }
+            report.packages.push(pkg_report);
+            if report.has_error() {
+                break;
+            }
+        }
+        report.elapsed = started.elapsed();
+        if let Some(state) = ctx.take_state() {
+            state.save(&ctx.working_dir)?;
+            self.save_manifests(&ctx.working_dir, &state, &report)?;
+        }
+        Ok(report)
+    }
+
+    // Point out packages left out of a filtered deploy whose sources changed
+    // since they were last deployed
+    fn report_stale_packages(&self, ctx: &Context, state: &DeployState, deployed: &[&str]) {
+        let mut names: Vec<&String> = self.packages.keys().collect();
+        names.sort();
+        for name in names
+            .into_iter()
+            .filter(|name| !deployed.contains(&name.as_str()))
+        {
+            let Some(last_deploy) = state.last_deploy(name) else {
+                continue;
+            };
+            if self.packages[name]
+                .latest_src_change(ctx)
+                .is_some_and(|changed| last_deploy.precedes(changed))
+            {
+                cprintln(
+                    &format!(
+                        "'{}' changed since it was {}, but wasn't deployed this time",
+                        name,
+                        last_deploy.describe()
+                    ),
+                    &LogLevel::INFO,
+                );
+            }
+        }
+    }
+
+    /// Write the manifest of every package deployed without an error, and
+    /// drop those of packages no longer in the configuration.
+    fn save_manifests(
+        &self,
+        working_dir: &Path,
+        state: &DeployState,
+        report: &Report,
+    ) -> Result<(), anyhow::Error> {
+        for pkg_report in report.packages.iter().filter(|p| p.error.is_none()) {
+            match state.package(&pkg_report.name) {
+                Some(pkg_state) => {
+                    Manifest::from_state(pkg_state)?.save(working_dir, &pkg_report.name)?
+                }
+                None => remove_manifest(working_dir, &pkg_report.name)?,
+            }
+        }
+        for name in manifest_names(working_dir)? {
+            if !self.packages.contains_key(&name) {
+                remove_manifest(working_dir, &name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Download the remote sources of the selected packages, or of every
+    /// package that has one, again.
+    pub fn fetch_packages(&self, ctx: &Context, args: &FetchArgs) -> Result<(), anyhow::Error> {
+        if ctx.is_offline() {
+            anyhow::bail!("Can't fetch with --offline");
+        }
+        let mut names: Vec<String> = match &args.packages {
+            Some(names) if !names.is_empty() => names
+                .iter()
+                .map(|name| self.resolve_package_name(name))
+                .collect::<Result<_, _>>()?,
+            _ => self
+                .packages
+                .values()
+                .filter(|pkg| pkg.remote_source().is_some())
+                .map(|pkg| pkg.name.clone())
+                .collect(),
+        };
+        names.sort();
+        if names.is_empty() {
+            cprintln("No package has a remote src", &LogLevel::INFO);
+            return Ok(());
+        }
+        for name in names.iter() {
+            let remote = self.packages[name]
+                .remote_source()
+                .ok_or_else(|| anyhow::anyhow!("Package '{}' has no remote src", name))?;
+            let outcome = remote
+                .fetch(&ctx.working_dir)
+                .map_err(|e| anyhow::anyhow!("Package '{}': {}", name, e))?;
+            report_fetch(name, &remote, outcome);
+        }
+        Ok(())
+    }
+
+    /// Print when the selected packages, or all of them, were last deployed
+    /// here, and whether their sources changed since.
+    pub fn print_status(&self, ctx: &Context, args: &StatusArgs) -> Result<(), anyhow::Error> {
+        match args.output {
+            OutputFormat::Text => print!("{}", self.render_status(ctx, args, color_enabled())?),
+            OutputFormat::Json => println!("{}", self.status_document(ctx, args)?.to_json()?),
+        }
+        Ok(())
+    }
+
+    /// The status table: a row per package with its name, whether it was
+    /// deployed and changed since, where it goes and when it was deployed.
+    /// Files waiting in the default stage are mentioned below it.
+    pub fn render_status(
+        &self,
+        ctx: &Context,
+        args: &StatusArgs,
+        color: bool,
+    ) -> Result<String, anyhow::Error> {
+        let mut rows = Vec::new();
+        for entry in self.status_entries(ctx, args)? {
+            let status = entry.status.unwrap_or(PackageStatus::NeverDeployed);
+            let color = match status {
+                PackageStatus::NeverDeployed => Color::Grey,
+                PackageStatus::Deployed => Color::Green,
+                PackageStatus::ChangedSince | PackageStatus::OutOfSync => Color::Yellow,
+            };
+            let dest = entry
+                .dests
+                .iter()
+                .map(|dest| display_dest(dest, &ctx.working_dir))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let mut detail = String::new();
+            if let Some(last_deploy) = &entry.last_deploy {
+                detail = format!(
+                    "{}, {} file(s) written",
+                    last_deploy.describe(),
+                    last_deploy.files_written
+                );
+                let stale = entry
+                    .files
+                    .iter()
+                    .filter(|file| file.state == FileState::Removed)
+                    .count();
+                if stale > 0 {
+                    detail.push_str(&format!(", {} removed file(s) still there", stale));
+                }
+            }
+            rows.push(vec![
+                Cell::plain(entry.name),
+                Cell::colored(status.to_string(), color),
+                Cell::plain(dest),
+                Cell::plain(detail),
+            ]);
+        }
+        let mut output = render_table(&rows, color);
+        let stage = Stage::load(&ctx.working_dir.join(DEFAULT_STAGE_DIR))?;
+        if !stage.is_empty() {
+            output.push_str(&format!(
+                "{} file(s) staged in {}, apply them with `dotr update --apply-staged`\n",
+                stage.files.len(),
+                DEFAULT_STAGE_DIR
+            ));
+        }
+        Ok(output)
+    }
+
+    /// What `status --output json` prints, see `status_entries`.
+    pub fn status_document(
+        &self,
+        ctx: &Context,
+        args: &StatusArgs,
+    ) -> Result<Document, anyhow::Error> {
+        let mut document =
+            Document::new(&ctx.working_dir, self.get_profile_details(&None, ctx)?.name);
+        document.packages = self.status_entries(ctx, args)?;
+        Ok(document)
+    }
+
+    /// An entry per package, sorted by name, with whether it was deployed and
+    /// changed since. Its files are those deploy recorded, compared with what
+    /// was written, and those deleted from its src that are still at dest.
+    pub fn status_entries(
+        &self,
+        ctx: &Context,
+        args: &StatusArgs,
+    ) -> Result<Vec<PackageEntry>, anyhow::Error> {
+        let state = DeployState::load(&ctx.working_dir)?;
+        let mut names = match &args.packages {
+            Some(names) if !names.is_empty() => names
+                .iter()
+                .map(|name| self.resolve_package_name(name))
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => self.packages.keys().cloned().collect(),
+        };
+        names.sort();
+        let mut entries = Vec::new();
+        for name in names.iter() {
+            let package = &self.packages[name];
+            let mut entry = PackageEntry::new(name);
+            entry.dests = package.resolve_dests(ctx)?;
+            entry.status = Some(PackageStatus::NeverDeployed);
+            if let Some(last_deploy) = state.last_deploy(name) {
+                let (stale, changed) = drift(package, &entry.dests, last_deploy, ctx)?;
+                entry.status = Some(if !stale.is_empty() {
+                    PackageStatus::OutOfSync
+                } else if changed {
+                    PackageStatus::ChangedSince
+                } else {
+                    PackageStatus::Deployed
+                });
+                entry.last_deploy = Some(last_deploy.clone());
+                if let Some(recorded) = state.package(name) {
+                    for (dest, status) in recorded.verify()? {
+                        entry.files.push(FileEntry {
+                            src: None,
+                            dest,
+                            state: status.into(),
+                            hunks: Vec::new(),
+                        });
+                    }
+                }
+                entry.files.extend(stale.into_iter().map(|dest| FileEntry {
+                    src: None,
+                    dest,
+                    state: FileState::Removed,
+                    hunks: Vec::new(),
+                }));
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// What bare `dotr` shows: the repository, the profile that would be
+    /// used, how many packages there are and how many of those that were
+    /// deployed have drifted since.
+    pub fn render_summary(
+        &self,
+        ctx: &Context,
+        selection: &ProfileSelection,
+        color: bool,
+    ) -> Result<String, anyhow::Error> {
+        let profile = match (&selection.name, &selection.source) {
+            (Some(name), Some(source)) => format!("{} (from {})", name, source),
+            _ => "none".to_string(),
+        };
+        let gated = self
+            .packages
+            .values()
+            .filter(|package| package.skip)
+            .count();
+        let mut packages = self.packages.len().to_string();
+        if gated > 0 {
+            packages.push_str(&format!(", {} only deployed through a profile", gated));
+        }
+        let mut rows = vec![
+            vec![
+                Cell::colored("Repository", Color::Grey),
+                Cell::plain(ctx.working_dir.display().to_string()),
+            ],
+            vec![Cell::colored("Profile", Color::Grey), Cell::plain(profile)],
+            vec![
+                Cell::colored("Packages", Color::Grey),
+                Cell::plain(packages),
+            ],
+        ];
+        let state = DeployState::load(&ctx.working_dir)?;
+        let mut deployed = 0;
+        let mut drifted = 0;
+        for (name, package) in self.packages.iter() {
+            let Some(last_deploy) = state.last_deploy(name) else {
+                continue;
+            };
+            deployed += 1;
+            let (stale, changed) = drift(package, &package.resolve_dests(ctx)?, last_deploy, ctx)?;
+            if !stale.is_empty() || changed {
+                drifted += 1;
+            }
+        }
+        if deployed > 0 {
+            let sync = if drifted > 0 {
+                Cell::colored(
+                    format!(
+                        "{} of {} deployed package(s), see `dotr status`",
+                        drifted, deployed
+                    ),
+                    Color::Yellow,
+                )
+            } else {
+                Cell::colored(
+                    format!("none of {} deployed package(s)", deployed),
+                    Color::Green,
+                )
+            };
+            rows.push(vec![Cell::colored("Out of sync", Color::Grey), sync]);
+        }
+        let mut output = render_table(&rows, color);
+        output.push_str("Run `dotr --help` for the commands.\n");
+        Ok(output)
+    }
+
+    /// Check the dests of the selected packages, or of every package with a
+    /// manifest, against the hashes deploy last wrote. With `update_manifest`
+    /// their current content becomes the new baseline instead.
+    pub fn verify_packages(&self, ctx: &Context, args: &VerifyArgs) -> Result<(), anyhow::Error> {
+        let names = match &args.packages {
+            Some(names) if !names.is_empty() => self.canonical_names(names),
+            _ => manifest_names(&ctx.working_dir)?,
+        };
+        let text = args.output == OutputFormat::Text;
+        if names.is_empty() && text {
+            cprintln("Nothing deployed to verify", &LogLevel::INFO);
+            return Ok(());
+        }
+        let mut failed = 0;
+        let mut entries = Vec::new();
+        for name in names.iter() {
+            let manifest = Manifest::load(&ctx.working_dir, name)?.ok_or_else(|| {
+                anyhow::anyhow!("Package '{}' has no manifest, deploy it first", name)
+            })?;
+            let mut entry = PackageEntry::new(name);
+            for (path, status) in manifest.verify()? {
+                let level = if status == FileStatus::Ok {
+                    LogLevel::INFO
+                } else {
+                    failed += 1;
+                    LogLevel::WARNING
+                };
+                if text {
+                    cprintln(&format!("{:<8} {}", status, path.display()), &level);
+                }
+                entry.files.push(FileEntry {
+                    src: None,
+                    dest: path,
+                    state: status.into(),
+                    hunks: Vec::new(),
+                });
+            }
+            if args.update_manifest {
+                manifest.refresh()?.save(&ctx.working_dir, name)?;
+                cprintln(
+                    &format!("Updated the manifest of '{}'", name),
+                    &LogLevel::INFO,
+                );
+            }
+            entries.push(entry);
+        }
+        if !text {
+            let mut document =
+                Document::new(&ctx.working_dir, self.get_profile_details(&None, ctx)?.name);
+            document.packages = entries;
+            println!("{}", document.to_json()?);
+        }
+        if failed > 0 && !args.update_manifest {
+            anyhow::bail!("{} file(s) changed since they were deployed", failed);
+        }
+        Ok(())
+    }
+
+    /// Remove the files deploy recorded for `names`, or for every deployed
+    /// package. Edited files are kept unless `force`; it is an error if any were.
+    pub fn clean_packages(&self, ctx: &Context, args: &CleanArgs) -> Result<(), anyhow::Error> {
+        let mut state = DeployState::load(&ctx.working_dir)?;
+        let names = match &args.packages {
+            Some(names) if !names.is_empty() => {
+                let names = self.canonical_names(names);
+                for name in names.iter() {
+                    if state.package(name).is_none() {
+                        anyhow::bail!("Package '{}' has no deployed files", name);
+                    }
+                }
+                names
+            }
+            _ => state.package_names(),
+        };
+        if names.is_empty() {
+            cprintln("Nothing to clean", &LogLevel::INFO);
+            return Ok(());
+        }
+        cprintln("Cleaning packages...", &LogLevel::INFO);
+        let mut kept = 0;
+        for name in names.iter() {
+            kept += state.clean_package(name, args.force, args.dry_run)?;
+        }
+        if !args.dry_run {
+            state.save(&ctx.working_dir)?;
+            // Edited files that were kept stay in the manifest, and verify flags them
+            for name in names.iter().filter(|name| state.package(name).is_none()) {
+                remove_manifest(&ctx.working_dir, name)?;
+            }
+        }
+        if kept > 0 {
+            anyhow::bail!(
+                "Kept {} file(s) edited since the last deploy, pass --force to remove them",
+                kept
+            );
+        }
+        Ok(())
+    }
+
+    /// Compare what deploying the selected packages would write with their
+    /// dests, without touching anything.
+    pub fn diff_packages(
+        &self,
+        ctx: &Context,
+        args: &DeployUpdateArgs,
+    ) -> Result<Report, anyhow::Error> {
+        let packages = self.ordered_packages(ctx, &args.packages)?;
+        if !args.skip_validation {
+            check_shared_files(&packages, ctx, self.is_strict())?;
+        }
+        let mut report = Report::default();
+        for pkg in packages.iter() {
+            let mut pkg_report = PackageReport::new(&pkg.name);
+            pkg_report.error = pkg.diff(ctx, &mut pkg_report).err();
+            report.packages.push(pkg_report);
+            if report.has_error() {
+                break;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Pick the active profile. The first of these wins: `--profile`,
+    /// `DOTR_PROFILE` in .uservariables.toml, `DOTR_PROFILE` from the environment
+    /// or config variables, and `default_profile`. `--profile ""` selects no profile.
     pub fn get_profile_details(
         &self,
         pname: &Option<String>,
-        vars: &Table,
-    ) -> (Option<String>, Option<Profile>) {
-        let mut profile_name = pname.clone();
-        if profile_name.is_none()
-            && let Ok(env_p_name) = vars
-                .get("DOTR_PROFILE")
+        ctx: &Context,
+    ) -> Result<ProfileSelection, anyhow::Error> {
+        let dotr_profile = |value: Option<&Value>| {
+            value
                 .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow::anyhow!("DOTR_PROFILE variable must be a string"))
+                .filter(|name| !name.is_empty())
+                .map(|name| name.to_string())
+        };
+        let (name, source) = if let Some(name) = pname {
+            (Some(name.clone()), ProfileSource::Cli)
+        } else if let Some(name) = dotr_profile(ctx.get_user_variable("DOTR_PROFILE")) {
+            (Some(name), ProfileSource::UserVariables)
+        } else if let Some(name) = dotr_profile(ctx.get_variable("DOTR_PROFILE")) {
+            (Some(name), ProfileSource::Environment)
+        } else {
+            (self.default_profile.clone(), ProfileSource::Default)
+        };
+        let name = name.filter(|n| !n.is_empty());
+        let profile = match &name {
+            Some(name) => self.resolve_profile(name)?,
+            None => None,
+        };
+        Ok(ProfileSelection {
+            source: name.as_ref().map(|_| source),
+            name,
+            profile,
+        })
+    }
+
+    pub fn create_profile(&mut self, name: &str) -> Result<(), anyhow::Error> {
+        if name.is_empty() {
+            anyhow::bail!("Profile name can't be empty");
+        }
+        if self.profiles.contains_key(name) {
+            anyhow::bail!("Profile '{}' already exists", name);
+        }
+        self.profiles.insert(name.to_string(), Profile::new(name));
+        cprintln(&format!("Profile '{}' created", name), &LogLevel::INFO);
+        Ok(())
+    }
+
+    /// Append packages to a profile's dependencies, skipping ones already there.
+    pub fn add_profile_packages(
+        &mut self,
+        name: &str,
+        packages: &[String],
+    ) -> Result<(), anyhow::Error> {
+        let packages = packages
+            .iter()
+            .map(|pkg| self.resolve_package_name(pkg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let profile = self.get_profile_mut(name)?;
+        for pkg in packages.iter() {
+            if profile.dependencies.contains(pkg) {
+                cprintln(
+                    &format!("Package '{}' is already in profile '{}'", pkg, name),
+                    &LogLevel::WARNING,
+                );
+            } else {
+                profile.dependencies.push(pkg.clone());
+                cprintln(
+                    &format!("Added '{}' to profile '{}'", pkg, name),
+                    &LogLevel::INFO,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove_profile_packages(
+        &mut self,
+        name: &str,
+        packages: &[String],
+    ) -> Result<(), anyhow::Error> {
+        let profile = self.get_profile_mut(name)?;
+        for pkg in packages {
+            if let Some(pos) = profile.dependencies.iter().position(|d| d == pkg) {
+                profile.dependencies.remove(pos);
+                cprintln(
+                    &format!("Removed '{}' from profile '{}'", pkg, name),
+                    &LogLevel::INFO,
+                );
+            } else {
+                cprintln(
+                    &format!("Package '{}' is not in profile '{}'", pkg, name),
+                    &LogLevel::WARNING,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete a profile. Profiles extending it always block the deletion;
+    /// package targets for it do too, unless `force` is set, which drops them.
+    pub fn delete_profile(&mut self, name: &str, force: bool) -> Result<(), anyhow::Error> {
+        self.get_profile_mut(name)?;
+        let mut children: Vec<&String> = self
+            .profiles
+            .values()
+            .filter(|p| p.extends.as_deref() == Some(name))
+            .map(|p| &p.name)
+            .collect();
+        if !children.is_empty() {
+            children.sort();
+            anyhow::bail!(
+                "Profile '{}' is extended by: {}",
+                name,
+                children
+                    .iter()
+                    .map(|c| c.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        let mut targeting: Vec<String> = self
+            .packages
+            .values()
+            .filter(|p| p.targets.contains_key(name))
+            .map(|p| p.name.clone())
+            .collect();
+        if !targeting.is_empty() {
+            targeting.sort();
+            if !force {
+                anyhow::bail!(
+                    "Profile '{}' is used by targets in packages: {}. Use --force to delete it anyway",
+                    name,
+                    targeting.join(", ")
+                );
+            }
+            for pkg in targeting.iter() {
+                if let Some(package) = self.packages.get_mut(pkg) {
+                    package.targets.remove(name);
+                    package.subpath_targets.remove(name);
+                }
+            }
+        }
+        self.profiles.remove(name);
+        cprintln(&format!("Profile '{}' deleted", name), &LogLevel::INFO);
+        Ok(())
+    }
+
+    /// Rename a package along with every reference to it: the dependencies
+    /// of other packages and profiles, and `dotfiles/<old>` when src is that
+    /// default path. Nothing is saved or moved here, see `save_renamed`.
+    pub fn rename_package(
+        &mut self,
+        cwd: &Path,
+        old: &str,
+        new: &str,
+    ) -> Result<RenamePlan, anyhow::Error> {
+        let old = self.resolve_package_name(old)?;
+        validate_package_name(new)?;
+        if self.packages.contains_key(new) {
+            anyhow::bail!("Package '{}' already exists", new);
+        }
+        if let Some(owner) = self.aliases.get(new)
+            && *owner != old
         {
-            profile_name = Some(env_p_name.to_string());
+            anyhow::bail!("'{}' is already an alias of package '{}'", new, owner);
         }
-        let profile = match &profile_name {
-            Some(name) => self.profiles.get(name).cloned(),
-            None => None,
+        let mut changes = vec![format!("Package '{}' becomes '{}'", old, new)];
+        let mut moves = Vec::new();
+        let mut package = self.packages.remove(&old).expect("resolved above");
+        package.name = new.to_string();
+        if let Some(pos) = package.aliases.iter().position(|alias| alias == new) {
+            package.aliases.remove(pos);
+            changes.push(format!("Alias '{}' dropped, it is the name now", new));
+        }
+        let default_src = format!("dotfiles/{}", old);
+        if package.remote.is_none() && package.src.as_deref() == Some(default_src.as_str()) {
+            let new_src = format!("dotfiles/{}", new);
+            moves = src_moves(cwd, &default_src, &new_src)?;
+            for (from, to) in moves.iter() {
+                changes.push(format!(
+                    "Move {} to {}",
+                    display_dest(from, cwd),
+                    display_dest(to, cwd)
+                ));
+            }
+            package.src = Some(new_src);
+        }
+        self.packages.insert(new.to_string(), package);
+        if let Some(file) = self.origins.packages.remove(&old) {
+            self.origins.packages.insert(new.to_string(), file);
+        }
+
+        let mut names: Vec<String> = self.packages.keys().cloned().collect();
+        names.sort();
+        for name in names.iter() {
+            let pkg = self.packages.get_mut(name).expect("listed above");
+            for dep in pkg.dependencies.iter_mut().flatten() {
+                if *dep == old {
+                    *dep = new.to_string();
+                    changes.push(format!("Dependency of package '{}' updated", name));
+                }
+            }
+        }
+        let mut profile_names: Vec<String> = self.profiles.keys().cloned().collect();
+        profile_names.sort();
+        for name in profile_names.iter() {
+            let profile = self.profiles.get_mut(name).expect("listed above");
+            for dep in profile.dependencies.iter_mut() {
+                if *dep == old {
+                    *dep = new.to_string();
+                    changes.push(format!("Dependency of profile '{}' updated", name));
+                }
+            }
+        }
+        let mut group_names: Vec<String> = self.groups.keys().cloned().collect();
+        group_names.sort();
+        for name in group_names.iter() {
+            let members = self.groups.get_mut(name).expect("listed above");
+            for member in members.iter_mut() {
+                if *member == old {
+                    *member = new.to_string();
+                    changes.push(format!("Member of group '{}' updated", name));
+                }
+            }
+        }
+        self.aliases = package_aliases(&self.packages)?;
+        Ok(RenamePlan { changes, moves })
+    }
+
+    /// Make the `moves` of a rename and save. When either fails, the files
+    /// moved so far go back and config.toml and its includes are restored,
+    /// so the rename happens whole or not at all.
+    pub fn save_renamed(
+        &self,
+        cwd: &Path,
+        moves: &[(PathBuf, PathBuf)],
+    ) -> Result<(), anyhow::Error> {
+        let mut files = vec![PathBuf::from("config.toml")];
+        files.extend(self.included_files());
+        let mut saved: Vec<(PathBuf, Option<Vec<u8>>)> = Vec::new();
+        for file in files {
+            let path = cwd.join(file);
+            let content = std::fs::read(&path).ok();
+            saved.push((path, content));
+        }
+        let mut moved: Vec<&(PathBuf, PathBuf)> = Vec::new();
+        let result = moves
+            .iter()
+            .try_for_each(|entry| {
+                let (from, to) = entry;
+                std::fs::rename(from, to).map_err(|e| {
+                    anyhow::anyhow!(
+                        "Failed to move {} to {}: {}",
+                        from.display(),
+                        to.display(),
+                        e
+                    )
+                })?;
+                moved.push(entry);
+                Ok(())
+            })
+            .and_then(|_| self.save(cwd));
+        let Err(e) = result else {
+            return Ok(());
         };
-        (profile_name, profile)
+        for (from, to) in moved.into_iter().rev() {
+            if let Err(undo) = std::fs::rename(to, from) {
+                cprintln(
+                    &format!(
+                        "Failed to move {} back to {}: {}",
+                        to.display(),
+                        from.display(),
+                        undo
+                    ),
+                    &LogLevel::ERROR,
+                );
+            }
+        }
+        for (path, content) in saved {
+            let restored = match content {
+                Some(content) => std::fs::write(&path, content),
+                None => std::fs::remove_file(&path).or(Ok(())),
+            };
+            if let Err(undo) = restored {
+                cprintln(
+                    &format!("Failed to restore {}: {}", path.display(), undo),
+                    &LogLevel::ERROR,
+                );
+            }
+        }
+        Err(e)
+    }
+
+    fn get_profile_mut(&mut self, name: &str) -> Result<&mut Profile, anyhow::Error> {
+        self.profiles
+            .get_mut(name)
+            .ok_or_else(|| anyhow::anyhow!("Profile '{}' not found in configuration", name))
+    }
+
+    /// Get a profile with everything it inherits through `extends` merged in.
+    /// The stored profiles stay unmerged so `save` keeps `extends` as written.
+    pub fn resolve_profile(&self, name: &str) -> Result<Option<Profile>, anyhow::Error> {
+        if !self.profiles.contains_key(name) {
+            return Ok(None);
+        }
+        let chain = profile_chain(&self.profiles, name)?;
+        let mut resolved = self.profiles[chain[0]].clone();
+        for child in chain.iter().skip(1) {
+            resolved = self.profiles[*child].merged_onto(&resolved);
+        }
+        Ok(Some(resolved))
     }
 
-    pub fn init(cwd: &Path) -> Result<Self, anyhow::Error> {
+    pub fn init(cwd: &Path, preset: InitPreset) -> Result<Self, anyhow::Error> {
         // If config.toml already exists, do nothing
         let config_path = cwd.join("config.toml");
         if config_path.exists() {
             cprintln("config.toml exists, skipping", &LogLevel::WARNING);
             return Self::from_path(cwd);
         }
-        // Here you would add the logic to create a default config file
-        let default_config = Config::new();
-        let toml_string = toml::to_string(&default_config)?;
-        std::fs::write(config_path, toml_string)?;
+        let default_config = preset_config(preset);
+        std::fs::write(config_path, preset_config_text(preset, &default_config)?)?;
         std::fs::create_dir_all(cwd.join("dotfiles"))?;
+        std::fs::create_dir_all(cwd.join(TEMPLATES_DIR))?;
 
-        // Create .gitignore to ignore .uservariables.toml
-        let gitignore_path = cwd.join(".gitignore");
-        let gitignore_content = ".uservariables.toml\n";
-        std::fs::write(gitignore_path, gitignore_content)?;
+        // The .gitignore is always written, other files only where there's nothing yet
+        for (path, content) in preset_files(preset, &default_config) {
+            let path = cwd.join(path);
+            if path.exists() && !path.ends_with(".gitignore") {
+                cprintln(
+                    &format!("{} exists, skipping", path.display()),
+                    &LogLevel::WARNING,
+                );
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, content)?;
+        }
 
         cprintln("Repository initialized", &LogLevel::INFO);
         Ok(default_config)
@@ -289,10 +2554,688 @@ impl Config {
     pub fn new() -> Self {
         Self {
             banner: true,
+            banner_text: None,
+            banner_file: None,
             packages: HashMap::new(),
             variables: Table::new(),
             profiles: HashMap::new(),
+            groups: HashMap::new(),
             prompts: HashMap::new(),
+            secrets: Vec::new(),
+            shell: None,
+            default_profile: None,
+            git: GitConfig::default(),
+            secrets_file: None,
+            paths: HashMap::new(),
+            action_timeout_secs: None,
+            ignore: Vec::new(),
+            strict: false,
+            include: Vec::new(),
+            env_allowlist: None,
+            protected_paths: Vec::new(),
+            allow_template_io: false,
+            export_env: false,
+            export_env_separator: None,
+            backup_dir: None,
+            max_file_size: None,
+            max_files: None,
+            schema_version: SCHEMA_VERSION,
+            origins: Origins::default(),
+            strict_flag: false,
+            aliases: HashMap::new(),
+            packages_as_array: false,
         }
     }
 }
+
+// One line for an entry of `kind` that failed to parse, naming it once:
+// `package 'd_nvim': 'dependencies' should be an array`
+fn entry_error(kind: &str, name: &str, e: &anyhow::Error) -> String {
+    let message = e.to_string();
+    let mut chars = kind.chars();
+    let capitalized: String = chars
+        .next()
+        .map(|c| c.to_uppercase().chain(chars).collect())
+        .unwrap_or_default();
+    match message.strip_prefix(&format!("{} '{}'", capitalized, name)) {
+        Some(rest) => format!("{} '{}'{}", kind, name, rest),
+        None => format!("{} '{}': {}", kind, name, message),
+    }
+}
+
+/// Turn `[[packages]]` entries, each with a `name`, into the `[packages.name]`
+/// table the rest of the config uses. Returns whether they were an array.
+fn packages_from_array(table: &mut Table) -> Result<bool, anyhow::Error> {
+    let Some(Value::Array(entries)) = table.get("packages") else {
+        return Ok(false);
+    };
+    let mut packages = Table::new();
+    for entry in entries {
+        let mut entry = entry
+            .as_table()
+            .ok_or_else(|| anyhow::anyhow!("Each [[packages]] entry must be a table"))?
+            .clone();
+        let name = match entry.remove("name") {
+            Some(Value::String(name)) => name,
+            Some(_) => anyhow::bail!("The 'name' of a [[packages]] entry must be a string"),
+            None => anyhow::bail!("Each [[packages]] entry needs a 'name'"),
+        };
+        if packages.contains_key(&name) {
+            anyhow::bail!("Package '{}' is defined twice in [[packages]]", name);
+        }
+        packages.insert(name, Value::Table(entry));
+    }
+    table.insert("packages".to_string(), Value::Table(packages));
+    Ok(true)
+}
+
+// The reverse of `packages_from_array`, ordered by name
+fn packages_to_array(table: &mut Table) {
+    let Some(Value::Table(packages)) = table.remove("packages") else {
+        return;
+    };
+    let mut names: Vec<&String> = packages.keys().collect();
+    names.sort();
+    let entries = names
+        .into_iter()
+        .map(|name| {
+            let mut entry = Table::new();
+            entry.insert("name".to_string(), Value::String(name.clone()));
+            if let Some(Value::Table(fields)) = packages.get(name) {
+                entry.extend(fields.clone());
+            }
+            Value::Table(entry)
+        })
+        .collect();
+    table.insert("packages".to_string(), Value::Array(entries));
+}
+
+/// Map each package alias to the package's name. An alias can't be the name
+/// of another package, nor belong to two packages.
+fn package_aliases(
+    packages: &HashMap<String, Package>,
+) -> Result<HashMap<String, String>, anyhow::Error> {
+    let mut aliases: HashMap<String, String> = HashMap::new();
+    let mut names: Vec<&String> = packages.keys().collect();
+    names.sort();
+    for name in names {
+        for alias in packages[name].aliases.iter() {
+            if packages.contains_key(alias) {
+                anyhow::bail!(
+                    "Alias '{}' of package '{}' is also the name of a package",
+                    alias,
+                    name
+                );
+            }
+            if let Some(other) = aliases.insert(alias.clone(), name.clone())
+                && other != *name
+            {
+                anyhow::bail!(
+                    "Alias '{}' is used by both packages '{}' and '{}'",
+                    alias,
+                    other,
+                    name
+                );
+            }
+        }
+    }
+    Ok(aliases)
+}
+
+/// Report keys of config.toml, its packages, profiles and [git] table that
+/// nothing reads, usually typos. They are warnings, or one error with `strict`.
+fn check_unknown_keys(table: &Table, strict: bool) -> Result<(), anyhow::Error> {
+    let problems = unknown_config_keys(table);
+    if problems.is_empty() {
+        return Ok(());
+    }
+    if strict {
+        anyhow::bail!(
+            "config.toml has unknown keys (prefix your own with 'x-'):\n  {}",
+            problems.join("\n  ")
+        );
+    }
+    for problem in problems {
+        cprintln(&format!("config.toml: {}", problem), &LogLevel::WARNING);
+    }
+    Ok(())
+}
+
+fn unknown_config_keys(table: &Table) -> Vec<String> {
+    let mut problems: Vec<String> = unknown_keys(table, CONFIG_KEYS);
+    for (section, label, known) in [
+        ("packages", "package", PACKAGE_KEYS),
+        ("profiles", "profile", PROFILE_KEYS),
+    ] {
+        let Some(entries) = table.get(section).and_then(|v| v.as_table()) else {
+            continue;
+        };
+        for (name, entry) in entries.iter() {
+            if let Some(entry) = entry.as_table() {
+                problems.extend(
+                    unknown_keys(entry, known)
+                        .into_iter()
+                        .map(|p| format!("{} '{}': {}", label, name, p)),
+                );
+            }
+        }
+    }
+    if let Some(git) = table.get("git").and_then(|v| v.as_table()) {
+        problems.extend(
+            unknown_keys(git, GIT_KEYS)
+                .into_iter()
+                .map(|p| format!("[git]: {}", p)),
+        );
+    }
+    problems
+}
+
+/// Merge the packages, profiles and variables of the files matched by the
+/// `include` patterns into `table`. A name defined twice is an error.
+fn merge_includes(cwd: &Path, table: &mut Table) -> Result<Origins, anyhow::Error> {
+    let mut origins = Origins::default();
+    let Some(patterns) = table.get("include").and_then(|v| v.as_array()).cloned() else {
+        return Ok(origins);
+    };
+    let mut files: Vec<PathBuf> = Vec::new();
+    for pattern in patterns.iter().filter_map(|p| p.as_str()) {
+        for file in expand_include(cwd, pattern)? {
+            if !files.contains(&file) && file != Path::new("config.toml") {
+                files.push(file);
+            }
+        }
+    }
+    for file in files {
+        let content = std::fs::read_to_string(cwd.join(&file))
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", file.display(), e))?;
+        let fragment = content
+            .parse::<Table>()
+            .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", file.display(), e))?;
+        if let Some(problem) = unknown_keys(&fragment, INCLUDE_KEYS).first() {
+            anyhow::bail!(
+                "{}: {} (included files can only hold packages, profiles and variables)",
+                file.display(),
+                problem
+            );
+        }
+        for section in INCLUDE_KEYS {
+            let Some(entries) = fragment.get(*section) else {
+                continue;
+            };
+            let entries = entries.as_table().ok_or_else(|| {
+                anyhow::anyhow!("{}: '{}' must be a table", file.display(), section)
+            })?;
+            let merged = table
+                .entry(section.to_string())
+                .or_insert_with(|| Value::Table(Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| anyhow::anyhow!("The '{}' field must be a table", section))?;
+            for (name, value) in entries.iter() {
+                if merged.contains_key(name) {
+                    let label = match *section {
+                        "packages" => "Package",
+                        "profiles" => "Profile",
+                        _ => "Variable",
+                    };
+                    let first = origins
+                        .section(section)
+                        .get(name)
+                        .map(|f| f.display().to_string())
+                        .unwrap_or_else(|| "config.toml".to_string());
+                    anyhow::bail!(
+                        "{} '{}' is defined in both {} and {}",
+                        label,
+                        name,
+                        first,
+                        file.display()
+                    );
+                }
+                merged.insert(name.clone(), value.clone());
+                origins
+                    .section_mut(section)
+                    .insert(name.clone(), file.clone());
+            }
+        }
+    }
+    Ok(origins)
+}
+
+/// The files matching an include pattern, relative to `cwd` and sorted.
+/// A pattern without wildcards must name an existing file.
+fn expand_include(cwd: &Path, pattern: &str) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let pattern = pattern.trim_start_matches("./");
+    let is_glob = |part: &str| part.contains(['*', '?', '[', '{']);
+    if !is_glob(pattern) {
+        if !cwd.join(pattern).is_file() {
+            anyhow::bail!("Included file {} not found", pattern);
+        }
+        return Ok(vec![PathBuf::from(pattern)]);
+    }
+    // Only walk below the part of the pattern without wildcards
+    let base: PathBuf = pattern
+        .split('/')
+        .take_while(|part| !is_glob(part))
+        .collect();
+    let mut files: Vec<PathBuf> = Vec::new();
+    for entry in walkdir::WalkDir::new(cwd.join(&base)) {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(cwd)?;
+        let relative_str = relative.to_string_lossy().replace('\\', "/");
+        if glob_match::glob_match(pattern, &relative_str) {
+            files.push(relative.to_path_buf());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// An entry of an included file as `to_table` would write it, to tell whether it changed
+fn normalize_entry(section: &str, name: &str, value: &Value) -> Option<Value> {
+    match section {
+        "packages" => Package::from_table(name, value.as_table()?)
+            .ok()
+            .map(|p| Value::Table(p.to_table())),
+        "profiles" => Profile::from_table(name, value.as_table()?)
+            .ok()
+            .map(|p| Value::Table(p.to_table())),
+        _ => Some(value.clone()),
+    }
+}
+
+/// The (value, placeholder) pairs for `import --as-template`: HOME and USER
+/// when they are set, plus every variable named with `--substitute`.
+fn template_substitutions(
+    ctx: &Context,
+    extra: &[String],
+) -> Result<Vec<(String, String)>, anyhow::Error> {
+    let mut substitutions: Vec<(String, String)> = Vec::new();
+    let defaults = ["HOME", "USER"].map(String::from);
+    for (i, name) in defaults.iter().chain(extra.iter()).enumerate() {
+        let value = match ctx.lookup_variable(name) {
+            Some(Value::String(s)) => s,
+            Some(Value::Integer(i)) => i.to_string(),
+            Some(_) => anyhow::bail!(
+                "Variable '{}' can't be substituted, it is not a string",
+                name
+            ),
+            None if i < defaults.len() => continue,
+            None => anyhow::bail!("Variable '{}' given to --substitute is not defined", name),
+        };
+        if value.is_empty() || substitutions.iter().any(|(v, _)| *v == value) {
+            continue;
+        }
+        substitutions.push((value, format!("{{{{ {} }}}}", name)));
+    }
+    Ok(substitutions)
+}
+
+/// The names from the root ancestor down to `name`, following `extends`.
+fn profile_chain<'a>(
+    profiles: &'a HashMap<String, Profile>,
+    name: &'a str,
+) -> Result<Vec<&'a str>, anyhow::Error> {
+    let mut chain: Vec<&str> = vec![name];
+    let mut current = &profiles[name];
+    while let Some(parent) = &current.extends {
+        if chain.contains(&parent.as_str()) {
+            chain.push(parent);
+            anyhow::bail!("Circular profile inheritance: {}", chain.join(" extends "));
+        }
+        current = profiles.get(parent).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Profile '{}' extends '{}', which is not defined",
+                current.name,
+                parent
+            )
+        })?;
+        chain.push(parent);
+    }
+    chain.reverse();
+    Ok(chain)
+}
+
+/// Where the active profile was chosen from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileSource {
+    Cli,
+    UserVariables,
+    Environment,
+    Default,
+}
+
+impl std::fmt::Display for ProfileSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let source = match self {
+            ProfileSource::Cli => "--profile",
+            ProfileSource::UserVariables => "DOTR_PROFILE in .uservariables.toml",
+            ProfileSource::Environment => "DOTR_PROFILE",
+            ProfileSource::Default => "default_profile",
+        };
+        write!(f, "{}", source)
+    }
+}
+
+/// Why `select_packages` picked a package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inclusion {
+    Explicit,             // Named with --packages, or through a group
+    Profile(String),      // A dependency of the active profile
+    Default,              // Without a profile or --packages, every package without skip
+    DependencyOf(String), // A dependency of another picked package
+}
+
+impl std::fmt::Display for Inclusion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Inclusion::Explicit => write!(f, "passed with --packages"),
+            Inclusion::Profile(profile) => {
+                write!(f, "a dependency of profile '{}'", profile)
+            }
+            Inclusion::Default => write!(
+                f,
+                "no profile or --packages given, so every package without skip = true"
+            ),
+            Inclusion::DependencyOf(dependent) => write!(f, "a dependency of {}", dependent),
+        }
+    }
+}
+
+/// Why `select_packages` left a package out.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Exclusion {
+    Skipped,              // skip = true, with neither a profile nor --packages
+    NotInProfile(String), // Not a dependency of the active profile, nor of what it lists
+    NotRequested,         // Not named with --packages, nor a dependency of what is
+    Os(String),           // only_on or except_on rule out this OS
+    Condition,            // Its when condition is false
+}
+
+impl std::fmt::Display for Exclusion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Exclusion::Skipped => write!(f, "skip = true"),
+            Exclusion::NotInProfile(profile) => {
+                write!(f, "not listed in the dependencies of profile '{}'", profile)
+            }
+            Exclusion::NotRequested => write!(f, "not among the packages passed with --packages"),
+            Exclusion::Os(os) => write!(f, "not targeted at {}", os),
+            Exclusion::Condition => write!(f, "its when condition is false"),
+        }
+    }
+}
+
+/// The packages `select_packages` picked, and the reasons for every
+/// package it considered.
+#[derive(Debug, Default)]
+pub struct SelectedPackages {
+    pub packages: HashMap<String, Package>,
+    pub included: HashMap<String, Inclusion>, // Also for those excluded afterwards, by OS or condition
+    pub excluded: HashMap<String, Exclusion>,
+}
+
+impl SelectedPackages {
+    fn include(&mut self, package: &Package, inclusion: Inclusion) {
+        self.included.insert(package.name.clone(), inclusion);
+        self.packages.insert(package.name.clone(), package.clone());
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfileSelection {
+    pub name: Option<String>,
+    pub profile: Option<Profile>, // None when the name isn't a configured profile
+    pub source: Option<ProfileSource>,
+}
+
+// How far the dests of `package` drifted from its last deploy: the files
+// deleted from the repository that are still there, and whether its src
+// changed after the deploy
+fn drift(
+    package: &Package,
+    dests: &[PathBuf],
+    last_deploy: &LastDeploy,
+    ctx: &Context,
+) -> Result<(Vec<PathBuf>, bool), anyhow::Error> {
+    let mut stale = Vec::new();
+    for dest in dests.iter() {
+        stale.extend(package.stale_files(dest, ctx)?);
+    }
+    let changed = package
+        .latest_src_change(ctx)
+        .is_some_and(|changed| last_deploy.precedes(changed));
+    Ok((stale, changed))
+}
+
+/// Move the src of a package that is about to be overwritten by an import
+/// to `<src>.imported-<timestamp>`, so its contents are not lost.
+fn set_aside_imported_src(pkg: &Package, ctx: &Context) -> Result<(), anyhow::Error> {
+    let Some(src) = pkg.resolve_src(ctx) else {
+        return Ok(());
+    };
+    if src.symlink_metadata().is_err() {
+        return Ok(());
+    }
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut aside = src.as_os_str().to_os_string();
+    aside.push(format!(".imported-{}", timestamp));
+    std::fs::rename(&src, &aside)?;
+    cprintln(
+        &format!(
+            "Existing contents of '{}' moved to {}",
+            pkg.name,
+            std::path::Path::new(&aside).display()
+        ),
+        &LogLevel::WARNING,
+    );
+    Ok(())
+}
+
+/// Reject package paths that would make deploy and update feed on each other:
+/// a dest (or profile target) inside dotfiles/ or overlapping the package's own
+/// src, and two packages deploying to the same place.
+fn validate_package_paths(packages: &[Package], ctx: &Context) -> Result<(), anyhow::Error> {
+    let dotfiles = normalize_path(&ctx.working_dir.join("dotfiles"));
+    let mut problems: Vec<String> = Vec::new();
+    let mut owners: HashMap<PathBuf, Vec<&str>> = HashMap::new();
+    for pkg in packages {
+        let srcs: Vec<PathBuf> = pkg
+            .resolve_srcs(ctx)?
+            .iter()
+            .map(|p| normalize_path(p))
+            .collect();
+        let mut dests: Vec<&String> = pkg.dest.iter().collect();
+        let mut targets: Vec<&String> = pkg
+            .targets
+            .values()
+            .flatten()
+            .chain(
+                pkg.subpath_targets
+                    .values()
+                    .flat_map(|subpaths| subpaths.values()),
+            )
+            .collect();
+        targets.sort();
+        dests.extend(targets);
+        for dest in dests {
+            let dest = normalize_path(&ctx.resolve_package_path(dest)?);
+            if dest.starts_with(&dotfiles) {
+                problems.push(format!(
+                    "{}: dest {} is inside the repository's dotfiles/ directory",
+                    pkg.name,
+                    dest.display()
+                ));
+            } else if let Some(src) = srcs
+                .iter()
+                .find(|src| src.starts_with(&dest) || dest.starts_with(src))
+            {
+                problems.push(format!(
+                    "{}: dest {} overlaps its src {}",
+                    pkg.name,
+                    dest.display(),
+                    src.display()
+                ));
+            }
+        }
+        for dest in pkg.resolve_dests(ctx)? {
+            let names = owners.entry(normalize_path(&dest)).or_default();
+            // Listing the same dest twice in one target isn't a clash
+            if !names.contains(&pkg.name.as_str()) {
+                names.push(&pkg.name);
+            }
+        }
+    }
+    let mut shared: Vec<_> = owners
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .collect();
+    shared.sort();
+    for (dest, mut names) in shared {
+        names.sort();
+        problems.push(format!(
+            "{}: all deploy to {}",
+            names.join(", "),
+            dest.display()
+        ));
+    }
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "Invalid package paths (use --skip-validation to ignore):\n  {}",
+            problems.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+/// Warn about files that more than one package writes, like a single file
+/// inside a directory package's dest: whichever deploys last wins. An error
+/// when `strict`.
+fn check_shared_files(
+    packages: &[Package],
+    ctx: &Context,
+    strict: bool,
+) -> Result<(), anyhow::Error> {
+    let mut owners: BTreeMap<PathBuf, Vec<&str>> = BTreeMap::new();
+    for pkg in packages {
+        for file in pkg.dest_files(ctx)? {
+            let names = owners.entry(normalize_path(&file)).or_default();
+            if !names.contains(&pkg.name.as_str()) {
+                names.push(&pkg.name);
+            }
+        }
+    }
+    // One line per group of packages, however many files they share
+    let mut shared: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for (file, mut names) in owners.into_iter().filter(|(_, names)| names.len() > 1) {
+        names.sort();
+        shared.entry(names.join(", ")).or_default().push(file);
+    }
+    if shared.is_empty() {
+        return Ok(());
+    }
+    let problems: Vec<String> = shared
+        .iter()
+        .map(|(names, files)| {
+            let more = match files.len() {
+                1 => String::new(),
+                n => format!(" and {} more", n - 1),
+            };
+            format!("{} all write {}{}", names, files[0].display(), more)
+        })
+        .collect();
+    if strict {
+        anyhow::bail!(
+            "Packages write the same files (use --skip-validation to ignore):\n  {}",
+            problems.join("\n  ")
+        );
+    }
+    for problem in problems {
+        cprintln(
+            &format!("{}, the package deployed last wins", problem),
+            &LogLevel::WARNING,
+        );
+    }
+    Ok(())
+}
+
+/// Depth-first visit used to order packages after their dependencies.
+fn visit_package(
+    name: &str,
+    packages: &HashMap<String, Package>,
+    visited: &mut HashSet<String>,
+    visiting: &mut Vec<String>,
+    ordered: &mut Vec<Package>,
+) -> Result<(), anyhow::Error> {
+    if visited.contains(name) {
+        return Ok(());
+    }
+    if visiting.iter().any(|n| n == name) {
+        visiting.push(name.to_string());
+        anyhow::bail!("Circular package dependency: {}", visiting.join(" -> "));
+    }
+    let Some(pkg) = packages.get(name) else {
+        return Ok(());
+    };
+    visiting.push(name.to_string());
+    let mut deps = pkg.dependencies.clone().unwrap_or_default();
+    deps.sort();
+    for dep in deps.iter() {
+        visit_package(dep, packages, visited, visiting, ordered)?;
+    }
+    visiting.pop();
+    visited.insert(name.to_string());
+    ordered.push(pkg.clone());
+    Ok(())
+}
+
+// The moves renaming `old_src` to `new_src` in the working directory, along
+// with its `##` variants next to it. None when it doesn't exist on disk.
+fn src_moves(
+    cwd: &Path,
+    old_src: &str,
+    new_src: &str,
+) -> Result<Vec<(PathBuf, PathBuf)>, anyhow::Error> {
+    let old_path = cwd.join(old_src);
+    let (Some(parent), Some(old_name), Some(new_name)) = (
+        old_path.parent(),
+        old_path.file_name().and_then(|n| n.to_str()),
+        Path::new(new_src).file_name().and_then(|n| n.to_str()),
+    ) else {
+        return Ok(Vec::new());
+    };
+    if !parent.is_dir() {
+        return Ok(Vec::new());
+    }
+    let variant_prefix = format!("{}{}", old_name, VARIANT_SEPARATOR);
+    let mut moves = Vec::new();
+    for entry in std::fs::read_dir(parent)? {
+        let file_name = entry?.file_name().to_string_lossy().to_string();
+        let renamed = if file_name == old_name {
+            new_name.to_string()
+        } else if let Some(condition) = file_name.strip_prefix(&variant_prefix) {
+            format!("{}{}{}", new_name, VARIANT_SEPARATOR, condition)
+        } else {
+            continue;
+        };
+        let to = parent.join(&renamed);
+        if to.symlink_metadata().is_ok() {
+            anyhow::bail!("{} already exists", to.display());
+        }
+        moves.push((parent.join(&file_name), to));
+    }
+    moves.sort();
+    Ok(moves)
+}
+
+// A dest as the status table shows it: relative to the repository when it's
+// inside, with ~ for the home directory otherwise
+fn display_dest(dest: &Path, working_dir: &Path) -> String {
+    match dest.strip_prefix(working_dir) {
+        Ok(relative) => relative.display().to_string(),
+        Err(_) => normalize_home_path(&dest.to_string_lossy()),
+    }
+}
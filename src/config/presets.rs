@@ -0,0 +1,148 @@
+//! Starting points for `dotr init --preset`. They are built from the same
+//! types config.toml is read into, so they follow the schema as it changes.
+
+use std::path::PathBuf;
+
+use toml::{Table, Value};
+
+use crate::{
+    cache::CACHE_DIR, config::Config, lock::LOCK_FILE, package::Package, profile::Profile,
+    prompts::Prompt,
+};
+
+/// What `dotr init` sets up next to an empty repository.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum InitPreset {
+    /// An empty config with the dotfiles and templates directories
+    #[default]
+    Minimal,
+    /// Also home and work profiles, a commented example package and a README
+    Standard,
+    /// Also a templated sample package with a variable and a prompt
+    Full,
+}
+
+// The profiles standard and full start with
+const PROFILES: &[&str] = &["home", "work"];
+
+// The sample package of the full preset
+const EXAMPLE_PACKAGE: &str = "d_example";
+const EXAMPLE_SRC: &str = "dotfiles/example";
+const EXAMPLE_DEST: &str = "~/.config/dotr-example";
+
+/// The config `preset` starts with.
+pub fn preset_config(preset: InitPreset) -> Config {
+    let mut conf = Config::new();
+    if preset == InitPreset::Minimal {
+        return conf;
+    }
+    for name in PROFILES {
+        conf.profiles.insert(name.to_string(), Profile::new(name));
+    }
+    if preset == InitPreset::Full {
+        let package = example_package();
+        conf.packages.insert(package.name.clone(), package);
+    }
+    conf
+}
+
+/// config.toml for `conf` as `preset` writes it. Past minimal, it ends with
+/// a commented package entry to start from.
+pub fn preset_config_text(preset: InitPreset, conf: &Config) -> Result<String, anyhow::Error> {
+    if preset == InitPreset::Minimal {
+        return Ok(toml::to_string(conf)?);
+    }
+    let mut text = conf.to_table().to_string();
+    let example = Package::imported("d_nvim".to_string(), "~/.config/nvim".to_string());
+    let mut packages = Table::new();
+    packages.insert(example.name.clone(), Value::Table(example.to_table()));
+    let mut entry = Table::new();
+    entry.insert("packages".to_string(), Value::Table(packages));
+    text.push_str("\n# `dotr import ~/.config/nvim` adds a package like this one\n");
+    for line in entry.to_string().lines() {
+        text.push_str(&format!("# {}\n", line));
+    }
+    Ok(text)
+}
+
+/// The files `preset` creates besides config.toml, relative to the
+/// repository, with their content.
+pub fn preset_files(preset: InitPreset, conf: &Config) -> Vec<(PathBuf, String)> {
+    let mut files = vec![(PathBuf::from(".gitignore"), gitignore(preset))];
+    if preset != InitPreset::Minimal {
+        files.push((PathBuf::from("README.md"), readme(conf)));
+    }
+    if preset == InitPreset::Full {
+        files.push((
+            PathBuf::from(EXAMPLE_SRC).join("example.conf"),
+            "# Deployed by dotr from '{{ dotr_package.name }}'\ngreeting = \"{{ greeting }}, {{ name }}!\"\n"
+                .to_string(),
+        ));
+    }
+    files
+}
+
+// A templated directory package with a variable of its own and a prompt
+fn example_package() -> Package {
+    let mut package = Package::imported(EXAMPLE_PACKAGE.to_string(), EXAMPLE_DEST.to_string());
+    package.src = Some(EXAMPLE_SRC.to_string());
+    package
+        .variables
+        .insert("greeting".to_string(), Value::String("Hello".to_string()));
+    package.prompts.insert(
+        "name".to_string(),
+        Prompt {
+            default: Some(Value::String("friend".to_string())),
+            ..Prompt::from("Who should the example greet?")
+        },
+    );
+    package
+}
+
+// What init keeps out of git. Full explains each group with a comment.
+fn gitignore(preset: InitPreset) -> String {
+    let groups = [
+        (
+            "Prompt answers and overrides, they belong to this machine",
+            vec![
+                ".uservariables.toml".to_string(),
+                ".uservariables.*.toml".to_string(),
+            ],
+        ),
+        (
+            "Deploy state, cache, manifests and backups, and the lock taken while running",
+            vec![format!("{}/", CACHE_DIR), LOCK_FILE.to_string()],
+        ),
+    ];
+    let mut content = String::new();
+    for (comment, patterns) in groups {
+        if preset == InitPreset::Full {
+            if !content.is_empty() {
+                content.push('\n');
+            }
+            content.push_str(&format!("# {}\n", comment));
+        }
+        for pattern in patterns {
+            content.push_str(&pattern);
+            content.push('\n');
+        }
+    }
+    content
+}
+
+// A README naming the profiles and the commands to start with
+fn readme(conf: &Config) -> String {
+    let mut profiles: Vec<&String> = conf.profiles.keys().collect();
+    profiles.sort();
+    let mut readme = format!(
+        "# Dotfiles\n\nManaged with [dotr]({}): the files under `dotfiles/` are deployed where `config.toml` says.\n\n## Profiles\n\n",
+        env!("CARGO_PKG_REPOSITORY")
+    );
+    for name in profiles {
+        readme.push_str(&format!("- `{}`: `dotr deploy --profile {}`\n", name, name));
+    }
+    readme.push_str(
+        "\n## Usage\n\n- `dotr import <path>` adds a dotfile\n- `dotr deploy` writes them to this machine\n- `dotr update` copies changes made there back here\n",
+    );
+    readme
+}
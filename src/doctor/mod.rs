@@ -0,0 +1,291 @@
+use std::{fmt, path::Path};
+
+use crate::{
+    config::Config,
+    context::{Context, current_os},
+    git::is_repository,
+    package::{HOST_TARGET_PREFIX, Package},
+    utils::{Color, paint},
+};
+
+/// How a check went. The worst finding of a check is its status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+impl CheckStatus {
+    // The status as printed, colored when color is on
+    fn painted(&self) -> String {
+        let color = match self {
+            CheckStatus::Pass => Color::Green,
+            CheckStatus::Warn => Color::Yellow,
+            CheckStatus::Fail => Color::Red,
+        };
+        paint(&self.to_string(), color)
+    }
+}
+
+/// Something a check found wrong.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+impl Finding {
+    pub fn warn(message: String) -> Self {
+        Self {
+            status: CheckStatus::Warn,
+            message,
+        }
+    }
+
+    pub fn fail(message: String) -> Self {
+        Self {
+            status: CheckStatus::Fail,
+            message,
+        }
+    }
+}
+
+/// One check `dotr doctor` runs. It reads the config and the context and
+/// must not change anything; no findings means it passed.
+pub struct Check {
+    pub name: &'static str,
+    pub run: fn(&Config, &Context) -> Vec<Finding>,
+}
+
+/// Every check, in the order they run. A feature that can be misconfigured
+/// adds its own here.
+pub fn checks() -> Vec<Check> {
+    vec![
+        Check {
+            name: "config",
+            run: check_config,
+        },
+        Check {
+            name: "sources",
+            run: check_sources,
+        },
+        Check {
+            name: "dependencies",
+            run: check_dependencies,
+        },
+        Check {
+            name: "targets",
+            run: check_targets,
+        },
+        Check {
+            name: "templates",
+            run: check_templates,
+        },
+        Check {
+            name: "dest permissions",
+            run: check_dest_permissions,
+        },
+        Check {
+            name: "git",
+            run: check_git,
+        },
+    ]
+}
+
+/// Run every check, printing PASS, WARN or FAIL for each with what it found.
+/// Any FAIL is an error.
+pub fn run_doctor(conf: &Config, ctx: &Context) -> Result<(), anyhow::Error> {
+    let mut counts = [0; 3];
+    for check in checks() {
+        let findings = (check.run)(conf, ctx);
+        let status = findings
+            .iter()
+            .map(|finding| finding.status)
+            .max()
+            .unwrap_or(CheckStatus::Pass);
+        counts[status as usize] += 1;
+        println!("{}  {}", status.painted(), check.name);
+        for finding in findings.iter() {
+            println!("      {}", finding.message);
+        }
+    }
+    let [passed, warned, failed] = counts;
+    println!(
+        "{} passed, {} warning(s), {} failed",
+        passed, warned, failed
+    );
+    if failed > 0 {
+        anyhow::bail!("{} check(s) failed", failed);
+    }
+    Ok(())
+}
+
+/// Report a config.toml that doesn't load as the failed config check.
+pub fn config_failure(e: anyhow::Error) -> anyhow::Error {
+    println!("{}  config", CheckStatus::Fail.painted());
+    println!("      {}", e);
+    anyhow::anyhow!("config.toml doesn't load, nothing else was checked")
+}
+
+// Packages in name order, leaving out those for another OS
+fn applicable_packages(conf: &Config) -> Vec<&Package> {
+    let os = current_os();
+    let mut packages: Vec<&Package> = conf
+        .packages
+        .values()
+        .filter(|pkg| pkg.applies_to_os(&os))
+        .collect();
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    packages
+}
+
+fn check_config(_conf: &Config, ctx: &Context) -> Vec<Finding> {
+    match Config::unknown_keys(&ctx.working_dir) {
+        Ok(problems) => problems
+            .into_iter()
+            .map(|problem| Finding::warn(format!("unknown key, {}", problem)))
+            .collect(),
+        Err(e) => vec![Finding::fail(e.to_string())],
+    }
+}
+
+fn check_sources(conf: &Config, ctx: &Context) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for pkg in applicable_packages(conf) {
+        if let Some(remote) = pkg.remote_source() {
+            if !remote.path(&ctx.working_dir).exists() {
+                findings.push(Finding::warn(format!(
+                    "package '{}': its remote src isn't downloaded yet, run dotr fetch",
+                    pkg.name
+                )));
+            }
+            continue;
+        }
+        match pkg.resolve_srcs(ctx) {
+            Ok(srcs) => findings.extend(srcs.iter().filter(|src| !src.exists()).map(|src| {
+                Finding::fail(format!(
+                    "package '{}': src {} does not exist",
+                    pkg.name,
+                    src.display()
+                ))
+            })),
+            Err(e) => findings.push(Finding::fail(e.to_string())),
+        }
+    }
+    findings
+}
+
+fn check_dependencies(conf: &Config, _ctx: &Context) -> Vec<Finding> {
+    conf.dependency_problems()
+        .into_iter()
+        .map(Finding::fail)
+        .collect()
+}
+
+fn check_targets(conf: &Config, _ctx: &Context) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for pkg in applicable_packages(conf) {
+        let mut profiles: Vec<&String> = pkg
+            .targets
+            .keys()
+            .filter(|key| !key.starts_with(HOST_TARGET_PREFIX))
+            .collect();
+        profiles.sort();
+        for profile in profiles {
+            if !conf.profiles.contains_key(profile) {
+                findings.push(Finding::fail(format!(
+                    "package '{}' has targets for profile '{}', which is not defined",
+                    pkg.name, profile
+                )));
+            }
+        }
+    }
+    findings
+}
+
+fn check_templates(conf: &Config, ctx: &Context) -> Vec<Finding> {
+    applicable_packages(conf)
+        .into_iter()
+        .filter_map(|pkg| {
+            pkg.check_templates(ctx)
+                .err()
+                .map(|e| Finding::fail(format!("package '{}': {}", pkg.name, e)))
+        })
+        .collect()
+}
+
+fn check_dest_permissions(conf: &Config, ctx: &Context) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for pkg in applicable_packages(conf) {
+        let dests = match pkg.resolve_dests(ctx) {
+            Ok(dests) => dests,
+            Err(e) => {
+                findings.push(Finding::fail(e.to_string()));
+                continue;
+            }
+        };
+        for dest in dests {
+            if let Err(problem) = check_writable_parent(&dest) {
+                findings.push(Finding::fail(format!(
+                    "package '{}': {}",
+                    pkg.name, problem
+                )));
+            }
+        }
+    }
+    findings
+}
+
+// Deploy creates missing parents, so it's the closest existing one that has
+// to be a writable directory. Read from its metadata, nothing is written.
+fn check_writable_parent(dest: &Path) -> Result<(), String> {
+    let Some(mut parent) = dest.parent() else {
+        return Ok(());
+    };
+    while parent.symlink_metadata().is_err() {
+        match parent.parent() {
+            Some(up) => parent = up,
+            None => return Ok(()),
+        }
+    }
+    let metadata = parent
+        .metadata()
+        .map_err(|e| format!("can't read {}: {}", parent.display(), e))?;
+    if !metadata.is_dir() {
+        return Err(format!(
+            "{} can't be created, {} is not a directory",
+            dest.display(),
+            parent.display()
+        ));
+    }
+    if metadata.permissions().readonly() {
+        return Err(format!(
+            "{} can't be written, {} is read-only",
+            dest.display(),
+            parent.display()
+        ));
+    }
+    Ok(())
+}
+
+fn check_git(conf: &Config, ctx: &Context) -> Vec<Finding> {
+    if conf.git.auto_commit && !is_repository(&ctx.working_dir) {
+        return vec![Finding::fail(format!(
+            "git.auto_commit is set, but {} is not a git repository",
+            ctx.working_dir.display()
+        ))];
+    }
+    Vec::new()
+}
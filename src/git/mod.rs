@@ -0,0 +1,188 @@
+use std::{
+    path::Path,
+    process::{Command, Output},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::{
+    context::Context,
+    package::compile_string,
+    utils::{LogLevel, cprintln},
+};
+
+pub const DEFAULT_COMMIT_MESSAGE: &str = "dotr update {{ date }}";
+// What `dotr update` writes to, and so what gets committed and checked
+const TRACKED_PATHS: [&str; 2] = ["dotfiles", "config.toml"];
+// The keys the [git] table understands, see `Config::load`
+pub const GIT_KEYS: &[&str] = &["auto_commit", "commit_message"];
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct GitConfig {
+    pub auto_commit: bool, // Commit dotfiles/ and config.toml after update, warn about changes before deploy
+    pub commit_message: String, // Tera template, with `date` on top of the usual variables
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            auto_commit: false,
+            commit_message: DEFAULT_COMMIT_MESSAGE.to_string(),
+        }
+    }
+}
+
+impl GitConfig {
+    pub fn from_table(table: &Table) -> Result<Self, anyhow::Error> {
+        let auto_commit = match table.get("auto_commit") {
+            Some(v) => v
+                .as_bool()
+                .ok_or_else(|| anyhow::anyhow!("git.auto_commit must be a boolean"))?,
+            None => false,
+        };
+        let commit_message = match table.get("commit_message") {
+            Some(v) => v
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("git.commit_message must be a string"))?
+                .to_string(),
+            None => DEFAULT_COMMIT_MESSAGE.to_string(),
+        };
+        Ok(Self {
+            auto_commit,
+            commit_message,
+        })
+    }
+
+    pub fn to_table(&self) -> Table {
+        let mut table = Table::new();
+        table.insert("auto_commit".to_string(), Value::Boolean(self.auto_commit));
+        if self.commit_message != DEFAULT_COMMIT_MESSAGE {
+            table.insert(
+                "commit_message".to_string(),
+                Value::String(self.commit_message.clone()),
+            );
+        }
+        table
+    }
+
+    /// Commit what `dotr update` brought back into the repository. Does nothing
+    /// when auto_commit is off, outside a git repository, or when nothing changed.
+    pub fn commit_update(&self, ctx: &Context) -> Result<(), anyhow::Error> {
+        if !self.auto_commit {
+            return Ok(());
+        }
+        if !is_repository(&ctx.working_dir) {
+            cprintln(
+                "Not a git repository, skipping auto-commit",
+                &LogLevel::WARNING,
+            );
+            return Ok(());
+        }
+        let paths = existing_paths(&ctx.working_dir);
+        git(
+            &ctx.working_dir,
+            &[&["add", "-A", "--"], &paths[..]].concat(),
+        )?;
+        let staged = run(
+            &ctx.working_dir,
+            &[&["diff", "--cached", "--quiet", "--"], &paths[..]].concat(),
+        )?;
+        if staged.status.success() {
+            cprintln("Nothing to commit", &LogLevel::DEBUG);
+            return Ok(());
+        }
+        let mut variables = ctx.resolved_variables(None)?;
+        variables.insert("date".to_string(), Value::String(today()));
+        let message = compile_string(&self.commit_message, &variables)?;
+        // Limit the commit to our paths so unrelated staged work stays staged
+        git(
+            &ctx.working_dir,
+            &[&["commit", "-m", message.as_str(), "--"], &paths[..]].concat(),
+        )?;
+        cprintln(&format!("Committed: {}", message), &LogLevel::INFO);
+        Ok(())
+    }
+
+    /// Warn when dotfiles/ has changes that aren't committed yet, since a
+    /// deploy would spread them to this machine.
+    pub fn check_clean(&self, working_dir: &Path) {
+        if !self.auto_commit || !is_repository(working_dir) {
+            return;
+        }
+        let Ok(output) = run(working_dir, &["status", "--porcelain", "--", "dotfiles"]) else {
+            return;
+        };
+        let changed = String::from_utf8_lossy(&output.stdout).lines().count();
+        if output.status.success() && changed > 0 {
+            cprintln(
+                &format!(
+                    "{} uncommitted change(s) under dotfiles/ (use --no-git-check to silence)",
+                    changed
+                ),
+                &LogLevel::WARNING,
+            );
+        }
+    }
+}
+
+/// Whether `working_dir` is inside a git work tree. A missing git binary counts as no.
+pub fn is_repository(working_dir: &Path) -> bool {
+    run(working_dir, &["rev-parse", "--is-inside-work-tree"])
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// The current UTC date as YYYY-MM-DD.
+pub fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Days since 1970-01-01 to a (year, month, day) in the proleptic Gregorian calendar.
+// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn existing_paths(working_dir: &Path) -> Vec<&'static str> {
+    TRACKED_PATHS
+        .into_iter()
+        .filter(|p| working_dir.join(p).exists())
+        .collect()
+}
+
+fn run(working_dir: &Path, args: &[&str]) -> Result<Output, anyhow::Error> {
+    Command::new("git")
+        .args(args)
+        .current_dir(working_dir)
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run git: {}", e))
+}
+
+// Like `run`, but a non-zero exit is an error carrying git's own message
+fn git(working_dir: &Path, args: &[&str]) -> Result<Output, anyhow::Error> {
+    let output = run(working_dir, args)?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output)
+}
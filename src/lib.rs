@@ -1,6 +1,36 @@
+//! dotr keeps dotfiles in a repository and deploys them, rendering Tera
+//! templates on the way.
+//!
+//! The `dotr` binary is a thin layer over this library: load a [`Config`],
+//! build a [`Context`] for it, and call [`Config::deploy_packages`],
+//! [`Config::backup_packages`] or [`Config::diff_packages`], which return a
+//! [`Report`] of what was done for each package. Nothing is printed unless
+//! logging is turned on with [`utils::init_logger`].
+//!
+//! See `examples/deploy.rs` for a complete run.
+
+pub mod cache;
 pub mod cli;
 pub mod config;
 pub mod context;
+pub mod doctor;
+pub mod git;
+pub mod lock;
+pub mod manifest;
 pub mod package;
+pub mod plan;
 pub mod profile;
+pub mod prompts;
+pub mod remote;
+pub mod report;
+pub mod secrets;
+pub mod stage;
+pub mod state;
 pub mod utils;
+
+pub use cli::{DeployUpdateArgs, UpdateArgs};
+pub use config::{Config, presets::InitPreset};
+pub use context::Context;
+pub use package::{DeploySteps, Package};
+pub use profile::Profile;
+pub use report::{FileDiff, PackageReport, Report};
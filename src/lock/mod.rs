@@ -0,0 +1,91 @@
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use crate::utils::{LogLevel, cprintln};
+
+pub const LOCK_FILE: &str = ".dotr.lock";
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An advisory lock on the working directory, held until dropped.
+///
+/// The lock belongs to the open file, not to the file's existence, so the
+/// OS releases it when a crashed process exits and a leftover `.dotr.lock`
+/// never blocks anyone.
+#[derive(Debug)]
+pub struct WorkdirLock {
+    file: File,
+}
+
+impl WorkdirLock {
+    /// Take the lock, waiting up to `timeout` for another dotr to finish.
+    /// A zero timeout fails right away when the lock is held.
+    pub fn acquire(working_dir: &Path, timeout: Duration) -> Result<Self, anyhow::Error> {
+        let path = working_dir.join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path.display(), e))?;
+        let started = Instant::now();
+        let mut announced = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => {
+                    let holder = holder_pid(&mut file);
+                    if started.elapsed() >= timeout {
+                        anyhow::bail!(
+                            "Another dotr process{} is working in {}; try again later or pass --lock-timeout",
+                            holder
+                                .map(|pid| format!(" (PID {})", pid))
+                                .unwrap_or_default(),
+                            working_dir.display()
+                        );
+                    }
+                    if !announced {
+                        cprintln(
+                            &format!(
+                                "Waiting for another dotr process{} to finish",
+                                holder
+                                    .map(|pid| format!(" (PID {})", pid))
+                                    .unwrap_or_default()
+                            ),
+                            &LogLevel::WARNING,
+                        );
+                        announced = true;
+                    }
+                    sleep(RETRY_INTERVAL);
+                }
+                Err(TryLockError::Error(e)) => {
+                    anyhow::bail!("Failed to lock {}: {}", path.display(), e)
+                }
+            }
+        }
+        // Leave our PID for whoever has to wait on us
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        writeln!(file, "{}", std::process::id())?;
+        file.flush()?;
+        Ok(Self { file })
+    }
+}
+
+impl Drop for WorkdirLock {
+    fn drop(&mut self) {
+        self.file.unlock().ok();
+    }
+}
+
+fn holder_pid(file: &mut File) -> Option<u32> {
+    let mut content = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
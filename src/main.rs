@@ -1,14 +1,21 @@
 use clap::Parser;
 use dotr::{
     cli::{Cli, run_cli},
-    utils::{LogLevel, cprintln},
+    utils::{ExitCodeError, LogLevel, cprintln, install_interrupt_handler},
 };
 
 fn main() {
     let args = Cli::parse();
+    if let Err(e) = install_interrupt_handler() {
+        cprintln(
+            &format!("Ctrl-C will stop dotr right away: {}", e),
+            &LogLevel::WARNING,
+        );
+    }
     if let Err(e) = run_cli(args) {
         let error = format!("{}", e);
         cprintln(&error, &LogLevel::ERROR);
-        std::process::exit(1);
+        let code = e.downcast_ref::<ExitCodeError>().map_or(1, |e| e.code);
+        std::process::exit(code);
     }
 }
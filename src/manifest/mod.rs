@@ -0,0 +1,162 @@
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{cache::CACHE_DIR, state::PackageState};
+
+pub const MANIFESTS_DIR: &str = "manifests";
+
+// The SHA-256 of every file deploy last wrote for a package, so `verify` can
+// tell whether a dest was changed since without rendering anything again.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    #[serde(default)]
+    pub files: BTreeMap<String, String>, // Dest path to its SHA-256, hex encoded
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    Ok,
+    Modified,
+    Missing,
+}
+
+impl std::fmt::Display for FileStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            FileStatus::Ok => "OK",
+            FileStatus::Modified => "MODIFIED",
+            FileStatus::Missing => "MISSING",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+impl Manifest {
+    /// Hash the files deploy recorded for a package, as they are at dest now.
+    pub fn from_state(state: &PackageState) -> Result<Self, anyhow::Error> {
+        Self::hash_files(state.files.keys())
+    }
+
+    /// Hash the same files again, taking their current content as the
+    /// baseline. Files that are gone are dropped.
+    pub fn refresh(&self) -> Result<Self, anyhow::Error> {
+        Self::hash_files(self.files.keys())
+    }
+
+    fn hash_files<'a>(dests: impl Iterator<Item = &'a String>) -> Result<Self, anyhow::Error> {
+        let mut files = BTreeMap::new();
+        for dest in dests {
+            let path = Path::new(dest);
+            if path.is_file() {
+                files.insert(dest.clone(), sha256_file(path)?);
+            }
+        }
+        Ok(Self { files })
+    }
+
+    /// Load the manifest of `package`, `None` when it was never deployed.
+    pub fn load(working_dir: &Path, package: &str) -> Result<Option<Self>, anyhow::Error> {
+        let path = manifest_path(working_dir, package);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        toml::from_str(&content)
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    /// Write the manifest next to a temp file and rename it into place, so a
+    /// crash never leaves half a manifest behind.
+    pub fn save(&self, working_dir: &Path, package: &str) -> Result<(), anyhow::Error> {
+        let path = manifest_path(working_dir, package);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temp = path.with_extension("toml.tmp");
+        let mut file = std::fs::File::create(&temp)?;
+        file.write_all(toml::to_string(self)?.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&temp, &path)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+        Ok(())
+    }
+
+    /// Compare each file with its hash, in dest order.
+    pub fn verify(&self) -> Result<Vec<(PathBuf, FileStatus)>, anyhow::Error> {
+        let mut results = Vec::new();
+        for (dest, hash) in self.files.iter() {
+            let path = PathBuf::from(dest);
+            let status = if !path.is_file() {
+                FileStatus::Missing
+            } else if sha256_file(&path)? == *hash {
+                FileStatus::Ok
+            } else {
+                FileStatus::Modified
+            };
+            results.push((path, status));
+        }
+        Ok(results)
+    }
+}
+
+/// Remove the manifest of `package`, if it has one.
+pub fn remove_manifest(working_dir: &Path, package: &str) -> Result<(), anyhow::Error> {
+    let path = manifest_path(working_dir, package);
+    match std::fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(anyhow::anyhow!(
+            "Failed to remove {}: {}",
+            path.display(),
+            e
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The packages that have a manifest, by name.
+pub fn manifest_names(working_dir: &Path) -> Result<Vec<String>, anyhow::Error> {
+    let dir = working_dir.join(CACHE_DIR).join(MANIFESTS_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "toml")
+            && let Some(stem) = path.file_stem()
+        {
+            names.push(stem.to_string_lossy().to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+pub fn manifest_path(working_dir: &Path, package: &str) -> PathBuf {
+    working_dir
+        .join(CACHE_DIR)
+        .join(MANIFESTS_DIR)
+        .join(format!("{}.toml", package))
+}
+
+/// SHA-256 of a file, read in chunks so large files never sit in memory whole.
+pub fn sha256_file(path: &Path) -> Result<String, anyhow::Error> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// SHA-256 of content in memory, hex encoded like `sha256_file`.
+pub fn sha256_bytes(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
@@ -0,0 +1,203 @@
+//! Running pre and post actions with their output streamed to the log.
+
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Read},
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+    ExitCodeError, INTERRUPTED_EXIT_CODE, LogLevel, catch_interrupts, cprintln, interrupted,
+};
+
+// Lines of output kept for the error message of a failed action
+const TAIL_LINES: usize = 20;
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// How long to keep reading after the action exits. Anything it left running
+// in the background may hold the pipes open for good.
+const DRAIN_GRACE: Duration = Duration::from_millis(500);
+
+/// One pre or post action: a command line run through the package's shell,
+/// or an argv array whose first element is run directly with the rest as
+/// its arguments, with no shell and so no quoting to get right.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Action {
+    Shell(String),
+    Argv(Vec<String>),
+}
+
+impl Action {
+    /// Parse one entry of the actions array `key`: a string, or a non-empty
+    /// array of strings.
+    pub fn from_value(value: &toml::Value, key: &str) -> anyhow::Result<Self> {
+        match value {
+            toml::Value::String(line) => Ok(Action::Shell(line.clone())),
+            toml::Value::Array(items) => {
+                let argv = items
+                    .iter()
+                    .map(|item| item.as_str().map(str::to_string))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("An argv action in '{}' must only hold strings", key)
+                    })?;
+                if argv.is_empty() {
+                    anyhow::bail!("An argv action in '{}' needs at least a program", key);
+                }
+                Ok(Action::Argv(argv))
+            }
+            _ => anyhow::bail!(
+                "Each entry of '{}' must be a string or an array of strings",
+                key
+            ),
+        }
+    }
+
+    pub fn to_value(&self) -> toml::Value {
+        match self {
+            Action::Shell(line) => toml::Value::String(line.clone()),
+            Action::Argv(argv) => toml::Value::Array(
+                argv.iter()
+                    .map(|arg| toml::Value::String(arg.clone()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// A shell action as written, an argv action as a quoted array, like
+/// `["ln", "-sf", "a b"]`.
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Shell(line) => write!(f, "{}", line),
+            Action::Argv(argv) => {
+                let quoted: Vec<String> = argv.iter().map(|arg| format!("{:?}", arg)).collect();
+                write!(f, "[{}]", quoted.join(", "))
+            }
+        }
+    }
+}
+
+impl From<&str> for Action {
+    fn from(line: &str) -> Self {
+        Action::Shell(line.to_string())
+    }
+}
+
+impl From<String> for Action {
+    fn from(line: String) -> Self {
+        Action::Shell(line)
+    }
+}
+
+/// Run an action's command, logging each line of its stdout and stderr
+/// prefixed with the package name. A failure or timeout error ends with
+/// the last lines of output. Ctrl-C kills it.
+pub fn run_action(
+    mut command: Command,
+    package: &str,
+    action: &str,
+    timeout: Option<Duration>,
+) -> anyhow::Result<()> {
+    let _interrupts = catch_interrupts();
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to start action '{}': {}", action, e))?;
+
+    let (tx, rx) = mpsc::channel();
+    if let Some(stdout) = child.stdout.take() {
+        forward_lines(stdout, tx.clone());
+    }
+    if let Some(stderr) = child.stderr.take() {
+        forward_lines(stderr, tx);
+    }
+
+    let started = Instant::now();
+    let mut tail: VecDeque<String> = VecDeque::with_capacity(TAIL_LINES);
+    let mut exited: Option<(std::process::ExitStatus, Instant)> = None;
+    loop {
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => {
+                cprintln(&format!("[{}] {}", package, line), &LogLevel::INFO);
+                if tail.len() == TAIL_LINES {
+                    tail.pop_front();
+                }
+                tail.push_back(line);
+                continue;
+            }
+            // Both pipes closed, the output is complete
+            Err(mpsc::RecvTimeoutError::Disconnected) if exited.is_some() => break,
+            Err(mpsc::RecvTimeoutError::Disconnected) => thread::sleep(POLL_INTERVAL),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+        }
+        match exited {
+            Some((_, at)) if at.elapsed() >= DRAIN_GRACE => break,
+            Some(_) => {}
+            None => {
+                if let Some(status) = child.try_wait()? {
+                    exited = Some((status, Instant::now()));
+                } else if interrupted() {
+                    child.kill().ok();
+                    child.wait().ok();
+                    return Err(ExitCodeError {
+                        code: INTERRUPTED_EXIT_CODE,
+                        message: format!("Interrupted while running action '{}'", action),
+                    }
+                    .into());
+                } else if let Some(limit) = timeout
+                    && started.elapsed() >= limit
+                {
+                    child.kill().ok();
+                    child.wait().ok();
+                    anyhow::bail!(
+                        "Action '{}' timed out after {}s{}",
+                        action,
+                        limit.as_secs_f64(),
+                        format_tail(&tail)
+                    );
+                }
+            }
+        }
+    }
+    let (status, _) = exited.expect("Loop only ends once the action exited");
+    if !status.success() {
+        anyhow::bail!(
+            "Action '{}' failed with exit code: {:?}{}",
+            action,
+            status.code(),
+            format_tail(&tail)
+        );
+    }
+    Ok(())
+}
+
+fn forward_lines(pipe: impl Read + Send + 'static, tx: mpsc::Sender<String>) {
+    thread::spawn(move || {
+        for line in BufReader::new(pipe).lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+fn format_tail(tail: &VecDeque<String>) -> String {
+    if tail.is_empty() {
+        return String::new();
+    }
+    let mut out = String::from("\nLast output:");
+    for line in tail {
+        out.push_str("\n  ");
+        out.push_str(line);
+    }
+    out
+}
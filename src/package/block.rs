@@ -0,0 +1,77 @@
+//! Managed-block deploy strategy for files other tools write to as well.
+//!
+//! Only the lines between `<comment> >>> dotr:<package> >>>` and
+//! `<comment> <<< dotr:<package> <<<` belong to the package; everything
+//! around them is left as it is.
+
+// Used when a package doesn't set block_comment
+pub const DEFAULT_BLOCK_COMMENT: &str = "#";
+
+fn markers(package: &str, comment: &str) -> (String, String) {
+    (
+        format!("{} >>> dotr:{} >>>", comment, package),
+        format!("{} <<< dotr:{} <<<", comment, package),
+    )
+}
+
+// The line indexes of the begin and end markers, None when there is no block
+fn find_block(
+    lines: &[&str],
+    package: &str,
+    comment: &str,
+) -> Result<Option<(usize, usize)>, anyhow::Error> {
+    let (begin, end) = markers(package, comment);
+    let Some(start) = lines.iter().position(|line| line.trim_end() == begin) else {
+        return Ok(None);
+    };
+    match lines[start + 1..]
+        .iter()
+        .position(|line| line.trim_end() == end)
+    {
+        Some(offset) => Ok(Some((start, start + 1 + offset))),
+        None => anyhow::bail!("'{}' is never closed by '{}'", begin, end),
+    }
+}
+
+/// Put `block` between the markers of `package` in `existing`, replacing
+/// the block that is there or appending one when there is none.
+pub fn splice_block(
+    existing: Option<&str>,
+    block: &str,
+    package: &str,
+    comment: &str,
+) -> Result<String, anyhow::Error> {
+    let (begin, end) = markers(package, comment);
+    let mut managed = format!("{}\n{}", begin, block);
+    if !block.is_empty() && !block.ends_with('\n') {
+        managed.push('\n');
+    }
+    managed.push_str(&end);
+    managed.push('\n');
+
+    let existing = existing.unwrap_or_default();
+    let lines: Vec<&str> = existing.split_inclusive('\n').collect();
+    match find_block(&lines, package, comment)? {
+        Some((start, stop)) => Ok(format!(
+            "{}{}{}",
+            lines[..start].concat(),
+            managed,
+            lines[stop + 1..].concat()
+        )),
+        None if existing.is_empty() || existing.ends_with('\n') => {
+            Ok(format!("{}{}", existing, managed))
+        }
+        None => Ok(format!("{}\n{}", existing, managed)),
+    }
+}
+
+/// The lines between the markers of `package` in `content`, None when it
+/// has no block.
+pub fn extract_block(
+    content: &str,
+    package: &str,
+    comment: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    Ok(find_block(&lines, package, comment)?.map(|(start, stop)| lines[start + 1..stop].concat()))
+}
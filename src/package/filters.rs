@@ -0,0 +1,73 @@
+//! Extra Tera filters available in file templates and action strings.
+//!
+//! All of them operate on strings. Any other input (numbers, booleans, arrays,
+//! tables) is an error naming the filter and the type it received.
+
+use std::{collections::HashMap, path::Path};
+
+use tera::{Tera, Value};
+
+pub fn register_filters(tera: &mut Tera) {
+    tera.register_filter("homedir", homedir);
+    tera.register_filter("shell_quote", shell_quote);
+    tera.register_filter("dirname", dirname);
+    tera.register_filter("basename", basename);
+}
+
+/// Expand a leading `~` to the home directory: `"~/.config" | homedir`.
+fn homedir(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let s = expect_str("homedir", value)?;
+    if s != "~" && !s.starts_with("~/") {
+        return Ok(Value::String(s.to_string()));
+    }
+    let home = std::env::home_dir()
+        .ok_or_else(|| tera::Error::msg("Filter 'homedir' could not find the home directory"))?;
+    Ok(Value::String(format!(
+        "{}{}",
+        home.to_string_lossy(),
+        &s[1..]
+    )))
+}
+
+/// Quote a value for POSIX shells by wrapping it in single quotes.
+fn shell_quote(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let s = expect_str("shell_quote", value)?;
+    Ok(Value::String(format!("'{}'", s.replace('\'', r"'\''"))))
+}
+
+/// The parent directory of a path, or an empty string for a bare file name.
+fn dirname(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let s = expect_str("dirname", value)?;
+    let parent = match Path::new(s).parent() {
+        Some(parent) => parent.to_string_lossy().to_string(),
+        None => s.to_string(), // "/" and "" are their own dirname
+    };
+    Ok(Value::String(parent))
+}
+
+/// The last component of a path, ignoring a trailing slash.
+fn basename(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let s = expect_str("basename", value)?;
+    let name = Path::new(s)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(Value::String(name))
+}
+
+fn expect_str<'a>(filter: &str, value: &'a Value) -> tera::Result<&'a str> {
+    value.as_str().ok_or_else(|| {
+        let kind = match value {
+            Value::Null => "null",
+            Value::Bool(_) => "a boolean",
+            Value::Number(_) => "a number",
+            Value::String(_) => "a string",
+            Value::Array(_) => "an array",
+            Value::Object(_) => "a table",
+        };
+        tera::Error::msg(format!(
+            "Filter '{}' expected a string but got {}: {}",
+            filter, kind, value
+        ))
+    })
+}
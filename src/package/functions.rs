@@ -0,0 +1,151 @@
+//! Tera functions that reach outside the template, available in file
+//! templates: `read_file(path, default)` reads a file of the repository and
+//! `cmd(command, default)` runs a command and takes its output.
+//!
+//! Both are refused unless `allow_template_io = true`, so rendering an
+//! untrusted repository can't read or run anything, and `read_file` never
+//! reads outside the repository. A failure falls back to `default` when
+//! given. Each file and command is read or run once per run.
+//!
+//! Their output isn't part of what the deploy cache compares, so every call
+//! is counted and a render that made any is never cached.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use tera::{Tera, Value};
+
+use super::shell_command;
+use crate::utils::normalize_path;
+
+// Why a function has no output. Only a failure falls back to the default.
+#[derive(Clone)]
+enum Failure {
+    Failed(String),
+    Refused(String),
+}
+
+// What a function returned for its argument, or why it didn't
+type Results = Arc<Mutex<HashMap<(&'static str, String), Result<String, Failure>>>>;
+
+/// How many times templates called `read_file` or `cmd`.
+pub type IoCalls = Arc<AtomicUsize>;
+
+pub fn register_io_functions(
+    tera: &mut Tera,
+    working_dir: &Path,
+    shell: Option<&str>,
+    allowed: bool,
+    calls: IoCalls,
+) {
+    let results: Results = Arc::default();
+    let root = working_dir.to_path_buf();
+    tera.register_function(
+        "read_file",
+        io_function(
+            "read_file",
+            "path",
+            allowed,
+            results.clone(),
+            calls.clone(),
+            move |path| read_file(&root, path),
+        ),
+    );
+    let root = working_dir.to_path_buf();
+    let shell = shell.map(str::to_string);
+    tera.register_function(
+        "cmd",
+        io_function("cmd", "command", allowed, results, calls, move |command| {
+            run_command(&root, shell.as_deref(), command)
+        }),
+    );
+}
+
+// The Tera function `name`, taking its argument from `arg` and an optional
+// `default`, with `io` doing the work
+fn io_function(
+    name: &'static str,
+    arg: &'static str,
+    allowed: bool,
+    results: Results,
+    calls: IoCalls,
+    io: impl Fn(&str) -> Result<String, Failure> + Send + Sync,
+) -> impl Fn(&HashMap<String, Value>) -> tera::Result<Value> + Send + Sync {
+    move |args| {
+        calls.fetch_add(1, Ordering::Relaxed);
+        if !allowed {
+            return Err(tera::Error::msg(format!(
+                "Function '{}' is disabled, set allow_template_io = true in config.toml to use it",
+                name
+            )));
+        }
+        let value = args.get(arg).and_then(Value::as_str).ok_or_else(|| {
+            tera::Error::msg(format!("Function '{}' needs a string '{}'", name, arg))
+        })?;
+        let default = match args.get("default") {
+            Some(Value::String(default)) => Some(default.clone()),
+            Some(_) => {
+                return Err(tera::Error::msg(format!(
+                    "The default of function '{}' must be a string",
+                    name
+                )));
+            }
+            None => None,
+        };
+        let result = results
+            .lock()
+            .expect("template IO results poisoned")
+            .entry((name, value.to_string()))
+            .or_insert_with(|| io(value))
+            .clone();
+        match (result, default) {
+            (Ok(output), _) => Ok(Value::String(output)),
+            (Err(Failure::Failed(_)), Some(default)) => Ok(Value::String(default)),
+            (Err(Failure::Failed(e) | Failure::Refused(e)), _) => {
+                Err(tera::Error::msg(format!("{}('{}'): {}", name, value, e)))
+            }
+        }
+    }
+}
+
+// A file under the repository; anything outside it is never read
+fn read_file(root: &Path, path: &str) -> Result<String, Failure> {
+    let root = root.canonicalize().unwrap_or_else(|_| normalize_path(root));
+    let full = normalize_path(&root.join(path));
+    // Through symlinks too, when the file is there
+    let resolved: PathBuf = full.canonicalize().unwrap_or(full);
+    if !resolved.starts_with(&root) {
+        return Err(Failure::Refused(
+            "the path is outside the repository".to_string(),
+        ));
+    }
+    std::fs::read_to_string(&resolved).map_err(|e| Failure::Failed(e.to_string()))
+}
+
+// The trimmed stdout of `command`, run like an action from the repository
+fn run_command(root: &Path, shell: Option<&str>, command: &str) -> Result<String, Failure> {
+    let mut shell = shell_command(shell);
+    let program = shell.remove(0);
+    let output = std::process::Command::new(program)
+        .args(shell)
+        .arg(command)
+        .current_dir(root)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .map_err(|e| Failure::Failed(e.to_string()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Failure::Failed(format!(
+            "{}: {}",
+            output.status,
+            stderr.trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,115 @@
+//! Deep-merge deploy strategy for structured config files.
+//!
+//! All formats are merged through `serde_json::Value`: source keys win,
+//! nested tables are merged recursively, and anything else (arrays included)
+//! is replaced wholesale.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl MergeFormat {
+    pub fn parse(s: &str) -> Result<Self, anyhow::Error> {
+        match s {
+            "json" => Ok(MergeFormat::Json),
+            "yaml" => Ok(MergeFormat::Yaml),
+            "toml" => Ok(MergeFormat::Toml),
+            _ => anyhow::bail!("Unknown merge format '{}', expected json, yaml or toml", s),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            MergeFormat::Json => "json",
+            MergeFormat::Yaml => "yaml",
+            MergeFormat::Toml => "toml",
+        }
+    }
+
+    fn load(&self, content: &str, path: &Path) -> Result<Value, anyhow::Error> {
+        // An empty file merges like an empty table
+        if content.trim().is_empty() {
+            return Ok(Value::Object(serde_json::Map::new()));
+        }
+        let parsed = match self {
+            MergeFormat::Json => serde_json::from_str(content).map_err(anyhow::Error::from),
+            MergeFormat::Yaml => serde_yaml::from_str(content).map_err(anyhow::Error::from),
+            MergeFormat::Toml => toml::from_str::<toml::Table>(content)
+                .map_err(anyhow::Error::from)
+                .and_then(|t| serde_json::to_value(t).map_err(anyhow::Error::from)),
+        };
+        parsed.map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to parse {} as {}: {}",
+                path.display(),
+                self.as_str(),
+                e
+            )
+        })
+    }
+
+    fn dump(&self, value: &Value, path: &Path) -> Result<String, anyhow::Error> {
+        let dumped = match self {
+            MergeFormat::Json => serde_json::to_string_pretty(value)
+                .map(|s| s + "\n")
+                .map_err(anyhow::Error::from),
+            MergeFormat::Yaml => serde_yaml::to_string(value).map_err(anyhow::Error::from),
+            MergeFormat::Toml => serde_json::from_value::<toml::Table>(value.clone())
+                .map_err(anyhow::Error::from)
+                .and_then(|t| toml::to_string_pretty(&t).map_err(anyhow::Error::from)),
+        };
+        dumped.map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to write merged {} for {}: {}",
+                self.as_str(),
+                path.display(),
+                e
+            )
+        })
+    }
+}
+
+/// Merge the rendered source into the current content of `dest`, returning
+/// what should be written to `dest`.
+pub fn merge_into_dest(
+    format: MergeFormat,
+    src: &Path,
+    src_content: &str,
+    dest: &Path,
+) -> Result<String, anyhow::Error> {
+    let managed = format.load(src_content, src)?;
+    let mut merged = if dest.exists() {
+        format.load(&std::fs::read_to_string(dest)?, dest)?
+    } else {
+        Value::Object(serde_json::Map::new())
+    };
+    deep_merge(&mut merged, managed);
+    format.dump(&merged, dest)
+}
+
+/// Merge `src` into `dest`. Objects merge key by key; any other value in
+/// `src`, or a type mismatch, replaces what is in `dest`.
+pub fn deep_merge(dest: &mut Value, src: Value) {
+    match (dest, src) {
+        (Value::Object(dest_map), Value::Object(src_map)) => {
+            for (key, value) in src_map {
+                match dest_map.get_mut(&key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        dest_map.insert(key, value);
+                    }
+                }
+            }
+        }
+        (dest, src) => *dest = src,
+    }
+}
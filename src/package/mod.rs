@@ -1,76 +1,289 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
     ffi::OsStr,
     path::{Path, PathBuf},
-    sync::LazyLock,
+    sync::{Arc, LazyLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use ignore::gitignore::GitignoreBuilder;
 use serde::{Deserialize, Serialize};
+use tera::ast::{Expr, ExprVal, FunctionCall, Node};
 use toml::Table;
 
 use crate::{
-    cli::ImportArgs,
-    context::Context,
-    utils::{BACKUP_EXT, LogLevel, cprintln, normalize_home_path, resolve_path},
+    cache::{CacheEntry, file_mtime, hash_variables},
+    context::{Context, PACKAGE_VARIABLES, check_reserved_variables, lookup_path},
+    manifest::{sha256_bytes, sha256_file},
+    plan::{Plan, PlanAction, PlannedFile},
+    prompts::Prompt,
+    remote::RemoteSource,
+    report::{FileDiff, PackageReport},
+    utils::{
+        BACKUP_EXT, Color, ExitCodeError, INTERRUPTED_EXIT_CODE, LogLevel, Progress,
+        catch_interrupts, count_field, cprintln, edit_distance, format_size, normalize_home_path,
+        normalize_path, paint, prompt, resolve_path, size_field,
+    },
 };
 
+mod actions;
+mod block;
+mod filters;
+mod functions;
+mod merge;
+mod reflink;
+
+pub use actions::Action;
+use actions::run_action;
+pub use block::{DEFAULT_BLOCK_COMMENT, extract_block, splice_block};
+pub use functions::{IoCalls, register_io_functions};
+pub use merge::{MergeFormat, deep_merge, merge_into_dest};
+
+const ONE_OFF_TEMPLATE: &str = "__tera_one_off";
+// How much newer than its src a dest must be to look edited, for clock skew
+// and coarse filesystem timestamps
+const MTIME_SKEW: Duration = Duration::from_secs(2);
+pub const TEMPLATES_DIR: &str = "templates";
+// Sources named like `init.lua.tera` are always rendered, and deploy without it
+pub const TEMPLATE_EXT: &str = "tera";
+// Gitignore-style patterns in the working directory, left out of imported directories
+pub const DOTRIGNORE_FILE: &str = ".dotrignore";
+// Placeholder that keeps an otherwise empty source directory in git. It is
+// never deployed, the directory holding it is.
+pub const KEEP_FILE: &str = ".dotrkeep";
+// Starts the `targets` keys chosen by the machine's hostname instead of the profile
+pub const HOST_TARGET_PREFIX: &str = "host:";
+// The keys a package table understands, see `Config::load`
+pub const PACKAGE_KEYS: &[&str] = &[
+    "aliases",
+    "allow_outside_home",
+    "autoescape",
+    "block",
+    "block_comment",
+    "dependencies",
+    "dest",
+    "dir_mode",
+    "except_on",
+    "file_mode",
+    "files",
+    "group",
+    "ignore",
+    "keep_dirs",
+    "lenient",
+    "max_depth",
+    "max_file_size",
+    "max_files",
+    "merge",
+    "only_on",
+    "overwrite_newer",
+    "owner",
+    "post_actions",
+    "pre_actions",
+    "prompts",
+    "prune_dest",
+    "remote",
+    "removed",
+    "rename",
+    "shell",
+    "skip",
+    "src",
+    "targets",
+    "template",
+    "variables",
+    "when",
+];
+
 static TEMPLATE_REGEX: LazyLock<regex::Regex> = LazyLock::new(|| {
     regex::Regex::new(r"(\{\{[-]?|[-]?\}\}|\{[%][-]?|[-]?%\}|\{[#][-]?|[-]?#\})").unwrap()
 });
 
+/// Which parts of a package `deploy` runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeploySteps {
+    #[default]
+    All,
+    FilesOnly,   // --skip-actions
+    ActionsOnly, // --actions-only
+}
+
+/// When the files of a package are rendered through Tera.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TemplateMode {
+    #[default]
+    Auto, // `.tera` files, and files whose template markers parse
+    Always, // Every text file, markers or not
+    Never,  // Copied as they are, and backed up like any other file
+}
+
+impl TemplateMode {
+    pub fn parse(s: &str) -> Result<Self, anyhow::Error> {
+        match s {
+            "auto" => Ok(TemplateMode::Auto),
+            "always" => Ok(TemplateMode::Always),
+            "never" => Ok(TemplateMode::Never),
+            _ => anyhow::bail!(
+                "Unknown template mode '{}', expected auto, always or never",
+                s
+            ),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            TemplateMode::Auto => "auto",
+            TemplateMode::Always => "always",
+            TemplateMode::Never => "never",
+        }
+    }
+}
+
 // A package represents a dotfile package with its source, destination, and dependencies.
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct Package {
     pub name: String,
-    pub src: String,
-    pub dest: String,
+    pub src: Option<String>, // Absent for meta packages that only carry actions and dependencies
+    pub dest: Option<String>,
     pub dependencies: Option<Vec<String>>,
     pub variables: Table,
-    pub pre_actions: Vec<String>,
-    pub post_actions: Vec<String>,
-    pub targets: HashMap<String, String>, // The key is profile name, the value is dest to override.
+    pub pre_actions: Vec<Action>, // Command lines run through the shell, or argv arrays run directly
+    pub post_actions: Vec<Action>,
+    pub targets: HashMap<String, Vec<String>>, // The key is a profile name or `host:<hostname>`, the value is the dests to deploy to instead.
+    #[serde(default)]
+    pub subpath_targets: HashMap<String, BTreeMap<String, String>>, // For a `targets` key given as a table, subpaths of the package mapped to the dests they deploy to instead.
     pub skip: bool,
     #[serde(default)]
-    pub prompts: HashMap<String, String>, // Package-level prompts
+    pub prompts: HashMap<String, Prompt>, // Package-level prompts
     #[serde(default)]
     pub ignore: Vec<String>, // Patterns to ignore during deployment
+    #[serde(default)]
+    pub prune_dest: bool, // Remove files at dest that no longer exist in src
+    #[serde(default)]
+    pub overwrite_newer: bool, // Overwrite dests modified more recently than their src without --force
+    #[serde(default)]
+    pub lenient: bool, // Skip the undefined-variable check before rendering templates
+    #[serde(default)]
+    pub autoescape: bool, // HTML-escape variables in rendered templates, for packages templating HTML
+    #[serde(default)]
+    pub shell: Option<String>, // Shell for actions, falls back to the config-level shell
+    #[serde(default)]
+    pub only_on: Vec<String>, // Deploy only on these OSes (linux, macos, windows, ...)
+    #[serde(default)]
+    pub except_on: Vec<String>, // Never deploy on these OSes
+    #[serde(default)]
+    pub merge: Option<MergeFormat>, // Deep-merge into the existing dest instead of overwriting it
+    #[serde(default)]
+    pub block: bool, // Manage only a marked block of the dest, leaving the rest of it alone
+    #[serde(default)]
+    pub block_comment: Option<String>, // Comment prefix of the block markers, `#` by default
+    #[serde(default)]
+    pub template: TemplateMode, // Which files are rendered through Tera
+    #[serde(default)]
+    pub when: Option<String>, // Tera condition, the package is left out when it's false
+    #[serde(default)]
+    pub file_mode: Option<u32>, // Mode set on every deployed file instead of the src's
+    #[serde(default)]
+    pub dir_mode: Option<u32>, // Mode set on every directory deploy creates or fills
+    #[serde(default)]
+    pub owner: Option<String>, // User name or uid to chown deployed paths to, Unix only
+    #[serde(default)]
+    pub group: Option<String>, // Group name or gid to chown deployed paths to, Unix only
+    #[serde(default)]
+    pub aliases: Vec<String>, // Other names the package can be selected by
+    #[serde(default)]
+    pub overlays: Vec<String>, // Sources laid over src in order, from an array src
+    #[serde(default)]
+    pub remote: Option<RemoteSource>, // Downloaded src, read-only, from a `remote` table
+    #[serde(default)]
+    pub allow_outside_home: bool, // Deploy to dests outside the home and working directories
+    #[serde(default)]
+    pub keep_dirs: Vec<String>, // Directories under dest that deploy creates and update keeps, even when empty
+    #[serde(default)]
+    pub removed: Vec<String>, // Files deleted from the repository, deploy deletes them under dest too
+    #[serde(default)]
+    pub rename: BTreeMap<String, String>, // Src path -> dest path, both relative, for files named differently at dest
+    #[serde(default)]
+    pub max_file_size: Option<u64>, // Bytes, a bigger file stops deploy and update of the package
+    #[serde(default)]
+    pub max_files: Option<usize>, // More files than this stops deploy and update of the package
+    #[serde(default)]
+    pub max_depth: Option<usize>, // Levels under a directory src to deploy files from, 1 for its own files only
+    #[serde(default)]
+    pub files: Vec<String>, // Paths under a directory src, when set the only files the package has
 }
 
 impl Package {
     // Create a new Package from a given path, used to import dotfiles.
     // The path can be absolute or relative to the current working directory.
-    // That path must exist and it will be set to the dest field.
-    pub fn from_path(args: &ImportArgs, cwd: &Path) -> Result<Self, anyhow::Error> {
-        let resolved_path = resolve_path(&args.path, cwd);
+    // That path must exist and it will be set to the dest field. Trailing
+    // slashes and `.`/`..` are resolved first, so `nvim/` imports like `nvim`.
+    pub fn from_path(path: &str, name: &Option<String>, cwd: &Path) -> Result<Self, anyhow::Error> {
+        let resolved_path = normalize_path(&resolve_path(path, cwd));
         if !resolved_path.exists() {
             anyhow::bail!("Path '{}' does not exist", resolved_path.display());
         }
-        let package_name = get_package_name(args, cwd);
-        let dest_path_str = format!("dotfiles/{}", package_name);
+        let working_dir = normalize_path(&resolve_path(".", cwd));
+        if working_dir.starts_with(&resolved_path) {
+            anyhow::bail!(
+                "Can't import '{}', it is or contains the working directory",
+                resolved_path.display()
+            );
+        }
+        if resolved_path.starts_with(working_dir.join("dotfiles")) {
+            anyhow::bail!(
+                "Can't import '{}', it is already inside dotfiles/",
+                resolved_path.display()
+            );
+        }
+        if let Some(custom_name) = name {
+            validate_package_name(custom_name)?;
+        }
+        let package_name = get_package_name(path, name, cwd)?;
 
-        // Normalize the path: if it already starts with ~, keep it; otherwise convert if in home dir
-        let path_str = if args.path.starts_with('~') {
-            args.path.to_string()
-        } else {
-            let resolved_str = resolved_path
-                .to_str()
-                .ok_or_else(|| anyhow::anyhow!("Invalid path: contains non-UTF-8 characters"))?;
-            normalize_home_path(resolved_str)
-        };
-
-        Ok(Self {
-            name: package_name.clone(),
-            dest: path_str,
-            src: dest_path_str.clone(),
-            dependencies: None,
-            variables: Table::new(),
-            pre_actions: Vec::new(),
-            post_actions: Vec::new(),
-            targets: HashMap::new(),
-            skip: false,
-            prompts: HashMap::new(),
-            ignore: Vec::new(),
-        })
+        // Store the resolved path, with ~ for the home dir, rather than what was typed
+        let resolved_str = resolved_path
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("Invalid path: contains non-UTF-8 characters"))?;
+        let path_str = normalize_home_path(resolved_str);
+
+        Ok(Self::imported(package_name, path_str))
+    }
+
+    /// A file package for content that isn't read from a file, like a
+    /// config piped from another tool, deploying to `dest`. Named after dest
+    /// unless `name` is given.
+    pub fn from_content(
+        dest: &str,
+        name: &Option<String>,
+        cwd: &Path,
+    ) -> Result<Self, anyhow::Error> {
+        let resolved_path = normalize_path(&resolve_path(dest, cwd));
+        if resolved_path.is_dir() {
+            anyhow::bail!(
+                "Dest '{}' is a directory, only a file can be imported from content",
+                resolved_path.display()
+            );
+        }
+        if let Some(custom_name) = name {
+            validate_package_name(custom_name)?;
+        }
+        let package_name = get_package_name(dest, name, cwd)?;
+        let resolved_str = resolved_path
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("Invalid path: contains non-UTF-8 characters"))?;
+        Ok(Self::imported(
+            package_name,
+            normalize_home_path(resolved_str),
+        ))
+    }
+
+    /// A new package copied into dotfiles/<name>, deploying to `dest`.
+    pub fn imported(package_name: String, dest: String) -> Self {
+        Self {
+            src: Some(format!("dotfiles/{}", package_name)),
+            name: package_name,
+            dest: Some(dest),
+            ..Self::default()
+        }
     }
 
     pub fn from_table(pkg_name: &str, pkg_val: &Table) -> Result<Self, anyhow::Error> {
@@ -78,7 +291,7 @@ impl Package {
             Some(deps) => {
                 let array = deps
                     .as_array()
-                    .ok_or_else(|| anyhow::anyhow!("Dependencies should be an array"))?;
+                    .ok_or_else(|| anyhow::anyhow!("The 'dependencies' field must be an array"))?;
                 let d = array
                     .iter()
                     .map(|d| {
@@ -99,6 +312,7 @@ impl Package {
                 .ok_or_else(|| anyhow::anyhow!("The 'variables' field must be a table"))?
                 .clone();
         }
+        check_reserved_variables(&variables, &format!("package '{}'", pkg_name))?;
 
         let mut pre_actions = Vec::new();
         if let Some(pre_block) = pkg_val.get("pre_actions") {
@@ -107,11 +321,7 @@ impl Package {
                 .ok_or_else(|| anyhow::anyhow!("The 'pre_actions' field must be an array"))?;
             pre_actions = array
                 .iter()
-                .map(|v| {
-                    v.as_str()
-                        .ok_or_else(|| anyhow::anyhow!("Pre-action must be a string"))
-                        .map(|s| s.to_string())
-                })
+                .map(|v| Action::from_value(v, "pre_actions"))
                 .collect::<Result<Vec<_>, _>>()?;
         }
 
@@ -122,40 +332,81 @@ impl Package {
                 .ok_or_else(|| anyhow::anyhow!("The 'post_actions' field must be an array"))?;
             post_actions = array
                 .iter()
-                .map(|v| {
-                    v.as_str()
-                        .ok_or_else(|| anyhow::anyhow!("Post-action must be a string"))
-                        .map(|s| s.to_string())
-                })
+                .map(|v| Action::from_value(v, "post_actions"))
                 .collect::<Result<Vec<_>, _>>()?;
         }
 
         let mut targets = HashMap::new();
+        let mut subpath_targets = HashMap::new();
         if let Some(targets_block) = pkg_val.get("targets") {
             let targets_table = targets_block
                 .as_table()
                 .ok_or_else(|| anyhow::anyhow!("The 'targets' field must be a table"))?;
             for (key, value) in targets_table {
-                let dest_str = value
-                    .as_str()
-                    .ok_or_else(|| anyhow::anyhow!("Target dest must be a string"))?;
-                targets.insert(key.clone(), dest_str.to_string());
+                // A single dest, an array to deploy to several places, or a
+                // table giving some subpaths dests of their own
+                let dests = match value {
+                    toml::Value::String(dest) => vec![dest.clone()],
+                    toml::Value::Table(table) => {
+                        let (dest, subpaths) = parse_subpath_target(key, table)?;
+                        subpath_targets.insert(key.clone(), subpaths);
+                        vec![dest]
+                    }
+                    toml::Value::Array(array) if !array.is_empty() => array
+                        .iter()
+                        .map(|v| {
+                            v.as_str()
+                                .map(|s| s.to_string())
+                                .ok_or_else(|| anyhow::anyhow!("Target dest must be a string"))
+                        })
+                        .collect::<Result<Vec<_>, _>>()?,
+                    _ => anyhow::bail!(
+                        "Target '{}' must be a dest, a non-empty array of dests or a table of subpath dests",
+                        key
+                    ),
+                };
+                targets.insert(key.clone(), dests);
             }
         }
 
-        let src = pkg_val
-            .get("src")
-            .ok_or_else(|| anyhow::anyhow!("Package src is required"))?
-            .as_str()
-            .ok_or_else(|| anyhow::anyhow!("Package src must be a string"))?
-            .to_string();
+        // An array src is a base followed by the overlays laid over it
+        let (src, overlays) = match pkg_val.get("src") {
+            Some(toml::Value::String(src)) => (Some(src.clone()), Vec::new()),
+            Some(toml::Value::Array(_)) => {
+                let mut sources = string_array(pkg_val, "src")?;
+                if sources.is_empty() {
+                    anyhow::bail!("Package src must not be an empty array");
+                }
+                let base = sources.remove(0);
+                (Some(base), sources)
+            }
+            Some(_) => anyhow::bail!("Package src must be a string or an array of strings"),
+            None => (None, Vec::new()),
+        };
 
-        let dest = pkg_val
-            .get("dest")
-            .ok_or_else(|| anyhow::anyhow!("Package dest is required"))?
-            .as_str()
-            .ok_or_else(|| anyhow::anyhow!("Package dest must be a string"))?
-            .to_string();
+        let remote = match pkg_val.get("remote") {
+            Some(v) => Some(RemoteSource::from_table(v.as_table().ok_or_else(
+                || anyhow::anyhow!("The 'remote' field must be a table"),
+            )?)?),
+            None => None,
+        };
+        if remote.is_some() && src.is_some() {
+            anyhow::bail!("Package '{}' has both src and remote, use one", pkg_name);
+        }
+        if let Some(remote) = src.as_deref().and_then(RemoteSource::from_url) {
+            remote
+                .validate()
+                .map_err(|e| anyhow::anyhow!("Package '{}': {}", pkg_name, e))?;
+        }
+
+        let dest = match pkg_val.get("dest") {
+            Some(v) => Some(
+                v.as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Package dest must be a string"))?
+                    .to_string(),
+            ),
+            None => None,
+        };
 
         let skip = pkg_val
             .get("skip")
@@ -168,10 +419,9 @@ impl Package {
                 .as_table()
                 .ok_or_else(|| anyhow::anyhow!("The 'prompts' field must be a table"))?;
             for (key, value) in prompts_table {
-                let prompt_str = value
-                    .as_str()
-                    .ok_or_else(|| anyhow::anyhow!("Prompt message must be a string"))?;
-                prompts.insert(key.clone(), prompt_str.to_string());
+                let prompt = Prompt::from_value(key, value)
+                    .map_err(|e| anyhow::anyhow!("Package '{}': {}", pkg_name, e))?;
+                prompts.insert(key.clone(), prompt);
             }
         }
         let mut ignore = Vec::new();
@@ -189,7 +439,124 @@ impl Package {
                 .collect::<Result<Vec<_>, _>>()?;
         }
 
-        Ok(Self {
+        let prune_dest = pkg_val
+            .get("prune_dest")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let overwrite_newer = pkg_val
+            .get("overwrite_newer")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let lenient = pkg_val
+            .get("lenient")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let autoescape = pkg_val
+            .get("autoescape")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let allow_outside_home = pkg_val
+            .get("allow_outside_home")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let shell = pkg_val
+            .get("shell")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+
+        let only_on = string_array(pkg_val, "only_on")?;
+        let aliases = string_array(pkg_val, "aliases")?;
+        for alias in aliases.iter() {
+            validate_package_name(alias)
+                .map_err(|e| anyhow::anyhow!("Package '{}': {}", pkg_name, e))?;
+        }
+        let except_on = string_array(pkg_val, "except_on")?;
+        let keep_dirs = string_array(pkg_val, "keep_dirs")?;
+        let removed = string_array(pkg_val, "removed")?;
+        let files = string_array(pkg_val, "files")?;
+        for (key, paths, root) in [
+            ("keep_dirs", &keep_dirs, "dest"),
+            ("removed", &removed, "dest"),
+            ("files", &files, "src"),
+        ] {
+            for entry in paths.iter() {
+                if !is_inner_path(entry) {
+                    anyhow::bail!(
+                        "Package '{}': {} entry '{}' must be a path inside {}",
+                        pkg_name,
+                        key,
+                        entry,
+                        root
+                    );
+                }
+            }
+        }
+        let rename =
+            parse_rename(pkg_val).map_err(|e| anyhow::anyhow!("Package '{}': {}", pkg_name, e))?;
+        let merge = match pkg_val.get("merge") {
+            Some(format) => {
+                Some(MergeFormat::parse(format.as_str().ok_or_else(|| {
+                    anyhow::anyhow!("The 'merge' field must be a string")
+                })?)?)
+            }
+            None => None,
+        };
+        let block = match pkg_val.get("block") {
+            Some(v) => v
+                .as_bool()
+                .ok_or_else(|| anyhow::anyhow!("The 'block' field must be a boolean"))?,
+            None => false,
+        };
+        let block_comment = optional_string(pkg_val, "block_comment")?;
+        if block_comment
+            .as_ref()
+            .is_some_and(|c| c.trim().is_empty() || c.contains('\n'))
+        {
+            anyhow::bail!(
+                "Package '{}': block_comment must be a non-empty single line",
+                pkg_name
+            );
+        }
+        if block && merge.is_some() {
+            anyhow::bail!("Package '{}' sets both block and merge, use one", pkg_name);
+        }
+        if block && !overlays.is_empty() {
+            anyhow::bail!(
+                "Package '{}' sets block, which takes a single file src, not an array",
+                pkg_name
+            );
+        }
+        let template = match pkg_val.get("template") {
+            Some(mode) => TemplateMode::parse(
+                mode.as_str()
+                    .ok_or_else(|| anyhow::anyhow!("The 'template' field must be a string"))?,
+            )?,
+            None => TemplateMode::Auto,
+        };
+        let when = match pkg_val.get("when") {
+            Some(condition) => Some(
+                condition
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("The 'when' field must be a string"))?
+                    .to_string(),
+            ),
+            None => None,
+        };
+
+        let file_mode = parse_mode(pkg_val, "file_mode")?;
+        let dir_mode = parse_mode(pkg_val, "dir_mode")?;
+        let owner = optional_string(pkg_val, "owner")?;
+        let group = optional_string(pkg_val, "group")?;
+        let max_file_size = size_field(pkg_val, "max_file_size")?;
+        let max_files = count_field(pkg_val, "max_files")?;
+        let max_depth = count_field(pkg_val, "max_depth")?;
+
+        let package = Self {
             name: pkg_name.to_string(),
             src,
             dest,
@@ -199,15 +566,121 @@ impl Package {
             pre_actions,
             post_actions,
             targets,
+            subpath_targets,
             prompts,
             ignore,
-        })
+            prune_dest,
+            overwrite_newer,
+            lenient,
+            autoescape,
+            shell,
+            only_on,
+            except_on,
+            merge,
+            block,
+            block_comment,
+            template,
+            when,
+            file_mode,
+            dir_mode,
+            owner,
+            group,
+            aliases,
+            overlays,
+            remote,
+            allow_outside_home,
+            keep_dirs,
+            removed,
+            rename,
+            max_file_size,
+            max_files,
+            max_depth,
+            files,
+        };
+        package.validate()?;
+        Ok(package)
+    }
+
+    /// Check that the package carries something to do.
+    /// A package needs either both src and dest, or at least one action or dependency.
+    /// The latter form is a meta package: it deploys no files.
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        let src = self.src.as_ref().or(self.remote.as_ref().map(|r| &r.url));
+        match (src, &self.dest) {
+            (Some(_), None) => {
+                anyhow::bail!("Package '{}' has src but no dest", self.name)
+            }
+            (None, Some(_)) => {
+                anyhow::bail!("Package '{}' has dest but no src", self.name)
+            }
+            (Some(_), Some(_)) => Ok(()),
+            (None, None) => {
+                let has_dependencies = self
+                    .dependencies
+                    .as_ref()
+                    .is_some_and(|deps| !deps.is_empty());
+                if self.pre_actions.is_empty() && self.post_actions.is_empty() && !has_dependencies
+                {
+                    anyhow::bail!(
+                        "Package '{}' is empty: it needs src and dest, actions, or dependencies",
+                        self.name
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether `only_on`/`except_on` allow this package on the given OS.
+    pub fn applies_to_os(&self, os: &str) -> bool {
+        (self.only_on.is_empty() || self.only_on.iter().any(|o| o == os))
+            && !self.except_on.iter().any(|o| o == os)
+    }
+
+    /// Evaluate the `when` condition against the package's variables, as the
+    /// test of a Tera `if`. A package without one always applies.
+    pub fn condition_holds(&self, ctx: &Context) -> Result<bool, anyhow::Error> {
+        let Some(condition) = &self.when else {
+            return Ok(true);
+        };
+        let invalid = |e: String| {
+            anyhow::anyhow!("Invalid 'when' condition of package '{}': {}", self.name, e)
+        };
+        if condition.contains("%}") || condition.trim().is_empty() {
+            return Err(invalid(format!("'{}' is not an expression", condition)));
+        }
+        let template = format!("{{% if {} %}}true{{% endif %}}", condition);
+        let rendered = compile_string(&template, &*self.resolved_variables(ctx)?)
+            .map_err(|e| invalid(e.to_string()))?;
+        Ok(rendered == "true")
+    }
+
+    /// A meta package has no files, only actions and/or dependencies.
+    pub fn is_meta(&self) -> bool {
+        self.src.is_none() && self.remote.is_none() && self.dest.is_none()
     }
 
     pub fn to_table(&self) -> Table {
         let mut pkg_table = Table::new();
-        pkg_table.insert("src".to_string(), toml::Value::String(self.src.clone()));
-        pkg_table.insert("dest".to_string(), toml::Value::String(self.dest.clone()));
+        if let Some(src) = &self.src {
+            let value = if self.overlays.is_empty() {
+                toml::Value::String(src.clone())
+            } else {
+                toml::Value::Array(
+                    std::iter::once(src)
+                        .chain(self.overlays.iter())
+                        .map(|s| toml::Value::String(s.clone()))
+                        .collect(),
+                )
+            };
+            pkg_table.insert("src".to_string(), value);
+        }
+        if let Some(remote) = &self.remote {
+            pkg_table.insert("remote".to_string(), toml::Value::Table(remote.to_table()));
+        }
+        if let Some(dest) = &self.dest {
+            pkg_table.insert("dest".to_string(), toml::Value::String(dest.clone()));
+        }
         if let Some(deps) = &self.dependencies {
             let deps_val: Vec<toml::Value> = deps
                 .iter()
@@ -222,22 +695,16 @@ impl Package {
             );
         }
         if !self.pre_actions.is_empty() {
-            let pre_actions_val: Vec<toml::Value> = self
-                .pre_actions
-                .iter()
-                .map(|a| toml::Value::String(a.clone()))
-                .collect();
+            let pre_actions_val: Vec<toml::Value> =
+                self.pre_actions.iter().map(Action::to_value).collect();
             pkg_table.insert(
                 "pre_actions".to_string(),
                 toml::Value::Array(pre_actions_val),
             );
         }
         if !self.post_actions.is_empty() {
-            let post_actions_val: Vec<toml::Value> = self
-                .post_actions
-                .iter()
-                .map(|a| toml::Value::String(a.clone()))
-                .collect();
+            let post_actions_val: Vec<toml::Value> =
+                self.post_actions.iter().map(Action::to_value).collect();
             pkg_table.insert(
                 "post_actions".to_string(),
                 toml::Value::Array(post_actions_val),
@@ -245,8 +712,25 @@ impl Package {
         }
         if !self.targets.is_empty() {
             let mut targets_table = Table::new();
-            for (key, value) in &self.targets {
-                targets_table.insert(key.clone(), toml::Value::String(value.clone()));
+            for (key, dests) in &self.targets {
+                let value = match (dests.as_slice(), self.subpath_targets.get(key)) {
+                    ([dest], Some(subpaths)) => {
+                        let mut table = Table::new();
+                        table.insert(String::new(), toml::Value::String(dest.clone()));
+                        for (subpath, dest) in subpaths {
+                            table.insert(subpath.clone(), toml::Value::String(dest.clone()));
+                        }
+                        toml::Value::Table(table)
+                    }
+                    ([dest], None) => toml::Value::String(dest.clone()),
+                    _ => toml::Value::Array(
+                        dests
+                            .iter()
+                            .map(|d| toml::Value::String(d.clone()))
+                            .collect(),
+                    ),
+                };
+                targets_table.insert(key.clone(), value);
             }
             pkg_table.insert("targets".to_string(), toml::Value::Table(targets_table));
         }
@@ -256,7 +740,7 @@ impl Package {
         if !self.prompts.is_empty() {
             let mut prompts_table = Table::new();
             for (key, value) in &self.prompts {
-                prompts_table.insert(key.clone(), toml::Value::String(value.clone()));
+                prompts_table.insert(key.clone(), value.to_value());
             }
             pkg_table.insert("prompts".to_string(), toml::Value::Table(prompts_table));
         }
@@ -268,269 +752,2793 @@ impl Package {
                 .collect();
             pkg_table.insert("ignore".to_string(), toml::Value::Array(ignore_val));
         }
+        if self.prune_dest {
+            pkg_table.insert("prune_dest".to_string(), toml::Value::Boolean(true));
+        }
+        if self.overwrite_newer {
+            pkg_table.insert("overwrite_newer".to_string(), toml::Value::Boolean(true));
+        }
+        if self.lenient {
+            pkg_table.insert("lenient".to_string(), toml::Value::Boolean(true));
+        }
+        if self.autoescape {
+            pkg_table.insert("autoescape".to_string(), toml::Value::Boolean(true));
+        }
+        if self.allow_outside_home {
+            pkg_table.insert("allow_outside_home".to_string(), toml::Value::Boolean(true));
+        }
+        if let Some(shell) = &self.shell {
+            pkg_table.insert("shell".to_string(), toml::Value::String(shell.clone()));
+        }
+        for (key, values) in [
+            ("only_on", &self.only_on),
+            ("except_on", &self.except_on),
+            ("aliases", &self.aliases),
+            ("keep_dirs", &self.keep_dirs),
+            ("removed", &self.removed),
+            ("files", &self.files),
+        ] {
+            if !values.is_empty() {
+                let array = values
+                    .iter()
+                    .map(|v| toml::Value::String(v.clone()))
+                    .collect();
+                pkg_table.insert(key.to_string(), toml::Value::Array(array));
+            }
+        }
+        if !self.rename.is_empty() {
+            let rename_table = self
+                .rename
+                .iter()
+                .map(|(src, dest)| (src.clone(), toml::Value::String(dest.clone())))
+                .collect();
+            pkg_table.insert("rename".to_string(), toml::Value::Table(rename_table));
+        }
+        if let Some(merge) = &self.merge {
+            pkg_table.insert(
+                "merge".to_string(),
+                toml::Value::String(merge.as_str().to_string()),
+            );
+        }
+        if self.block {
+            pkg_table.insert("block".to_string(), toml::Value::Boolean(true));
+        }
+        if self.template != TemplateMode::Auto {
+            pkg_table.insert(
+                "template".to_string(),
+                toml::Value::String(self.template.as_str().to_string()),
+            );
+        }
+        if let Some(comment) = &self.block_comment {
+            pkg_table.insert(
+                "block_comment".to_string(),
+                toml::Value::String(comment.clone()),
+            );
+        }
+        if let Some(when) = &self.when {
+            pkg_table.insert("when".to_string(), toml::Value::String(when.clone()));
+        }
+        for (key, mode) in [("file_mode", self.file_mode), ("dir_mode", self.dir_mode)] {
+            if let Some(mode) = mode {
+                pkg_table.insert(
+                    key.to_string(),
+                    toml::Value::String(format!("{:04o}", mode)),
+                );
+            }
+        }
+        for (key, id) in [("owner", &self.owner), ("group", &self.group)] {
+            if let Some(id) = id {
+                pkg_table.insert(key.to_string(), toml::Value::String(id.clone()));
+            }
+        }
+        if let Some(size) = self.max_file_size {
+            pkg_table.insert(
+                "max_file_size".to_string(),
+                toml::Value::String(format_size(size)),
+            );
+        }
+        if let Some(count) = self.max_files {
+            pkg_table.insert("max_files".to_string(), toml::Value::Integer(count as i64));
+        }
+        if let Some(depth) = self.max_depth {
+            pkg_table.insert("max_depth".to_string(), toml::Value::Integer(depth as i64));
+        }
         pkg_table
     }
 
+    /// Run one action with `env` added to its environment: a command line
+    /// through the package's shell, an argv array directly, each element
+    /// rendered on its own. `timeout` kills it when it runs longer.
     pub fn execute_action(
         &self,
-        action: &str,
+        action: &Action,
         variables: &Table,
+        env: &[(String, String)],
         working_dir: &Path,
+        timeout: Option<Duration>,
     ) -> anyhow::Result<()> {
-        let compiled_action = compile_string(action, variables)?;
-        // Get SHELL environment variable or default to /bin/sh
-        let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
-        let status = std::process::Command::new(shell)
-            .arg("-c")
-            .arg(compiled_action)
-            .current_dir(working_dir)
-            .status()?;
-        if !status.success() {
-            let msg = format!(
-                "Action '{}' failed with exit code: {:?}",
-                action,
-                status.code()
-            );
-            cprintln(&msg, &LogLevel::ERROR);
-            return Err(anyhow::anyhow!(msg));
-        }
-        Ok(())
+        let mut process = match action {
+            Action::Shell(line) => {
+                let compiled_action = compile_string(line, variables)?;
+                cprintln(&format!("Running: {}", compiled_action), &LogLevel::TRACE);
+                let mut command = shell_command(self.shell.as_deref());
+                let program = command.remove(0);
+                let mut process = std::process::Command::new(program);
+                process.args(command).arg(compiled_action);
+                process
+            }
+            Action::Argv(argv) => {
+                let compiled = argv
+                    .iter()
+                    .map(|arg| compile_string(arg, variables))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                cprintln(
+                    &format!("Running: {}", Action::Argv(compiled.clone())),
+                    &LogLevel::TRACE,
+                );
+                let Some((program, args)) = compiled.split_first() else {
+                    anyhow::bail!("Action '{}' has no program to run", action);
+                };
+                let mut process = std::process::Command::new(program);
+                process.args(args);
+                process
+            }
+        };
+        process
+            .envs(env.iter().map(|(key, value)| (key, value)))
+            .current_dir(working_dir);
+        run_action(process, &self.name, &action.to_string(), timeout).inspect_err(|e| {
+            cprintln(&e.to_string(), &LogLevel::ERROR);
+        })
     }
 
-    pub fn execute_pre_actions(&self, ctx: &Context) -> anyhow::Result<()> {
-        let vars = self.get_context_variables(ctx);
+    pub fn execute_pre_actions(
+        &self,
+        ctx: &Context,
+        report: &mut PackageReport,
+    ) -> anyhow::Result<()> {
+        let vars = self.resolved_variables(ctx)?;
+        let env = ctx.action_env(&vars);
         for action in &self.pre_actions {
-            self.execute_action(action, &vars, &ctx.working_dir)?;
+            self.execute_action(action, &vars, &env, &ctx.working_dir, ctx.action_timeout())
+                .map_err(|e| ctx.explain_missing_variable(e))?;
+            report.actions.push(action.to_string());
         }
         Ok(())
     }
 
-    pub fn execute_post_actions(&self, ctx: &Context) -> anyhow::Result<()> {
-        let vars = self.get_context_variables(ctx);
+    pub fn execute_post_actions(
+        &self,
+        ctx: &Context,
+        report: &mut PackageReport,
+    ) -> anyhow::Result<()> {
+        let vars = self.resolved_variables(ctx)?;
+        let env = ctx.action_env(&vars);
         for action in &self.post_actions {
-            self.execute_action(action, &vars, &ctx.working_dir)?;
+            self.execute_action(action, &vars, &env, &ctx.working_dir, ctx.action_timeout())
+                .map_err(|e| ctx.explain_missing_variable(e))?;
+            report.actions.push(action.to_string());
         }
         Ok(())
     }
 
+    /// The variables this package's templates and actions see, with the
+    /// reserved `dotr_package` table describing the package itself.
+    /// Variables that fail to resolve are left as written.
     pub fn get_context_variables(&self, ctx: &Context) -> Table {
-        let mut vars = ctx.get_variables().clone();
-        vars.extend(self.variables.clone());
-        if let Some(profile) = &ctx.profile {
-            vars.extend(profile.variables.clone());
+        (*self.context_variables(ctx)).clone()
+    }
+
+    // `get_context_variables`, shared rather than copied
+    fn context_variables(&self, ctx: &Context) -> Arc<Table> {
+        self.resolved_variables(ctx)
+            .unwrap_or_else(|_| Arc::new(self.layered_variables(ctx)))
+    }
+
+    /// Like `get_context_variables`, failing when a variable can't be resolved.
+    /// The table is computed once and shared until the context changes.
+    pub fn resolved_variables(&self, ctx: &Context) -> Result<Arc<Table>, anyhow::Error> {
+        ctx.package_variables(self, self.metadata_variables(ctx))
+    }
+
+    fn layered_variables(&self, ctx: &Context) -> Table {
+        let mut variables = ctx.layered_variables(Some(self));
+        variables.insert(
+            PACKAGE_VARIABLES.to_string(),
+            toml::Value::Table(self.metadata_variables(ctx)),
+        );
+        variables
+    }
+
+    // `name`, `src` and `dest` resolved for the active profile. A package
+    // with several targets also gets them all as `dests`.
+    fn metadata_variables(&self, ctx: &Context) -> Table {
+        let mut metadata = Table::new();
+        metadata.insert("name".to_string(), toml::Value::String(self.name.clone()));
+        if let Some(src) = self.resolve_src(ctx) {
+            metadata.insert(
+                "src".to_string(),
+                toml::Value::String(src.to_string_lossy().to_string()),
+            );
+        }
+        // An unknown path base fails the deploy elsewhere, show the dest as written meanwhile
+        let dests: Vec<String> = match self.resolve_dests(ctx) {
+            Ok(dests) => dests
+                .iter()
+                .map(|dest| dest.to_string_lossy().to_string())
+                .collect(),
+            Err(_) => self.dest.iter().cloned().collect(),
+        };
+        if let Some(dest) = dests.first() {
+            metadata.insert("dest".to_string(), toml::Value::String(dest.clone()));
+        }
+        if dests.len() > 1 {
+            metadata.insert(
+                "dests".to_string(),
+                toml::Value::Array(dests.into_iter().map(toml::Value::String).collect()),
+            );
         }
-        vars.extend(ctx.get_user_variables().clone());
-        vars
+        metadata
     }
 
+    /// Whether `rel_path` is left out of the package: deeper than
+    /// `max_depth`, neither one of `files` nor a directory holding one, or
+    /// matching the ignore patterns. Of those the last matching pattern wins,
+    /// so a later `!pattern` re-includes what an earlier one ignored.
     pub fn should_ignore(&self, rel_path: &Path) -> bool {
+        if self
+            .max_depth
+            .is_some_and(|depth| rel_path.components().count() > depth)
+        {
+            return true;
+        }
+        if !self.files.is_empty() && !self.lists(rel_path) {
+            return true;
+        }
+        self.matches_ignore(rel_path)
+    }
+
+    // Whether `rel_path` is one of `files`, as in src or as deployed without
+    // its .tera extension, or a directory on the way to one
+    fn lists(&self, rel_path: &Path) -> bool {
+        self.files.iter().map(Path::new).any(|file| {
+            file == rel_path || strip_tera_ext(file) == rel_path || file.starts_with(rel_path)
+        })
+    }
+
+    fn matches_ignore(&self, rel_path: &Path) -> bool {
         let rel_path_str = rel_path.to_string_lossy();
+        let mut ignored = false;
         for pattern in &self.ignore {
-            if glob_match::glob_match(pattern, &rel_path_str) {
-                return true;
+            match pattern.strip_prefix('!') {
+                Some(negated) if glob_match::glob_match(negated, &rel_path_str) => ignored = false,
+                Some(_) => {}
+                None if glob_match::glob_match(pattern, &rel_path_str) => ignored = true,
+                None => {}
             }
         }
-        false
+        ignored
     }
 
-    /// Backup the package by copying files from dest to a backup location, recursively.
-    pub fn backup(&self, ctx: &Context) -> anyhow::Result<()> {
-        if self.package_is_templated(&ctx.working_dir) {
-            cprintln(
-                &format!("Skipping backup for templated '{}'", self.name),
-                &LogLevel::WARNING,
-            );
-            return Ok(());
+    /// Where the src file at `relative_path` deploys under dest: its `rename`
+    /// entry, or the path itself without a `.tera` extension.
+    pub fn dest_relative(&self, relative_path: &Path) -> PathBuf {
+        let target = strip_tera_ext(relative_path);
+        self.rename
+            .iter()
+            .find(|(src, _)| Path::new(src) == relative_path || Path::new(src) == target)
+            .map(|(_, dest)| PathBuf::from(dest))
+            .unwrap_or(target)
+    }
+
+    /// The src file the dest file at `relative_path` is deployed from,
+    /// without a `.tera` extension: the reverse of `dest_relative`. None for
+    /// a src that `rename` deploys under another name.
+    pub fn src_relative(&self, relative_path: &Path) -> Option<PathBuf> {
+        let renamed = |src: &String| strip_tera_ext(Path::new(src));
+        if let Some((src, _)) = self
+            .rename
+            .iter()
+            .find(|(_, dest)| Path::new(dest) == relative_path)
+        {
+            return Some(renamed(src));
         }
-        let copy_from = self.resolve_dest(ctx);
-        let copy_to = ctx.working_dir.join(self.src.clone());
-        if copy_from.is_dir() {
-            // Recursively copy directory contents, avoiding files ending with BACKUP_EXT
-            for entry in walkdir::WalkDir::new(&copy_from) {
+        if self.rename.keys().any(|src| renamed(src) == relative_path) {
+            return None;
+        }
+        Some(relative_path.to_path_buf())
+    }
+
+    /// Whether `rel_path` is inside one of `keep_dirs`, whose contents belong
+    /// to the programs using them rather than to the package.
+    fn is_under_kept_dir(&self, rel_path: &Path) -> bool {
+        self.keep_dirs
+            .iter()
+            .any(|dir| rel_path != Path::new(dir) && rel_path.starts_with(dir))
+    }
+
+    // The directories deploy creates under each dest even when empty: the
+    // `keep_dirs`, and those holding a KEEP_FILE in one of `srcs`
+    fn kept_dirs(&self, srcs: &[PathBuf]) -> Result<BTreeSet<PathBuf>, anyhow::Error> {
+        let mut dirs: BTreeSet<PathBuf> = self.keep_dirs.iter().map(PathBuf::from).collect();
+        for src in srcs.iter().filter(|src| src.is_dir()) {
+            for entry in walkdir::WalkDir::new(src) {
                 let entry = entry?;
-                let relative_path = entry.path().strip_prefix(&copy_from)?;
-                if self.should_ignore(relative_path) {
+                if entry.file_name() != KEEP_FILE {
                     continue;
                 }
-                let dest_path = copy_to.clone().join(relative_path);
-                if entry.path().is_dir() {
-                    std::fs::create_dir_all(&dest_path)?;
-                } else if entry.path().extension() != Some(OsStr::new(BACKUP_EXT)) {
-                    if let Some(parent) = dest_path.parent() {
-                        std::fs::create_dir_all(parent)?;
-                    }
-                    std::fs::copy(entry.path(), &dest_path)?;
+                if let Some(dir) = entry.path().strip_prefix(src)?.parent()
+                    && !self.should_ignore(dir)
+                {
+                    dirs.insert(dir.to_path_buf());
                 }
             }
-        } else {
-            std::fs::copy(&copy_from, &copy_to)?;
         }
-        Ok(())
+        Ok(dirs)
     }
 
-    pub fn resolve_dest(&self, ctx: &Context) -> PathBuf {
-        if let Some(profile) = &ctx.profile
-            && let Some(target_dest) = self.targets.get(profile.name.as_str())
-        {
-            return resolve_path(target_dest, &ctx.working_dir);
+    /// Before a directory is first imported, add what `.dotrignore` in the
+    /// working directory and the directory's own `.gitignore` exclude to the
+    /// ignore patterns, so neither the import nor later updates copy it.
+    /// Returns the number of files left out and their total size.
+    pub fn ignore_excluded_files(&mut self, ctx: &Context) -> Result<(usize, u64), anyhow::Error> {
+        let Some(dest) = self.resolve_dests(ctx)?.into_iter().next() else {
+            return Ok((0, 0));
+        };
+        if !dest.is_dir() {
+            return Ok((0, 0));
+        }
+        let mut builder = GitignoreBuilder::new(&dest);
+        for file in [
+            ctx.working_dir.join(DOTRIGNORE_FILE),
+            dest.join(".gitignore"),
+        ] {
+            if file.is_file()
+                && let Some(e) = builder.add(&file)
+            {
+                anyhow::bail!("Failed to read {}: {}", file.display(), e);
+            }
+        }
+        let matcher = builder.build()?;
+        if matcher.is_empty() {
+            return Ok((0, 0));
+        }
+
+        let (mut files, mut size) = (0, 0);
+        let mut walker = walkdir::WalkDir::new(&dest)
+            .follow_links(false)
+            .sort_by_file_name()
+            .into_iter();
+        while let Some(entry) = walker.next() {
+            let entry = entry?;
+            let is_dir = entry.file_type().is_dir();
+            if entry.depth() == 0 || !matcher.matched(entry.path(), is_dir).is_ignore() {
+                continue;
+            }
+            let relative_path = entry.path().strip_prefix(&dest)?.to_string_lossy();
+            if is_dir {
+                // Everything under it is excluded too, as in git
+                walker.skip_current_dir();
+                for inner in walkdir::WalkDir::new(entry.path()).follow_links(false) {
+                    let inner = inner?;
+                    if inner.file_type().is_file() {
+                        files += 1;
+                        size += inner.metadata()?.len();
+                    }
+                }
+                self.ignore.push(format!("{}/**", relative_path));
+            } else {
+                files += 1;
+                size += entry.metadata()?.len();
+                self.ignore.push(relative_path.to_string());
+            }
         }
-        resolve_path(&self.dest, &ctx.working_dir)
+        Ok((files, size))
     }
 
-    pub fn diff_file(
+    /// Backup the package by copying files from dest to a backup location, recursively.
+    /// Files whose content already matches are left alone. With `dry_run`, nothing is
+    /// copied and each differing file is added to the report's diffs instead.
+    /// The files that were, or would be, copied go in `report.backed_up`.
+    /// A file that can't be copied doesn't stop the others, unless
+    /// `--fail-fast` is given; the error at the end lists every one of them.
+    pub fn backup(
         &self,
-        src: &PathBuf,
-        dest: &PathBuf,
         ctx: &Context,
+        dry_run: bool,
+        report: &mut PackageReport,
+    ) -> anyhow::Result<()> {
+        self.backup_files(ctx, dry_run, report)?;
+        self.failed_files_error(report, "copied back", report.backed_up.len())
+    }
+
+    fn backup_files(
+        &self,
+        ctx: &Context,
+        dry_run: bool,
+        report: &mut PackageReport,
+    ) -> anyhow::Result<()> {
+        let _interrupts = catch_interrupts();
+        self.check_interrupt(ctx, 0)?;
+        let dests = self.resolve_dests(ctx)?;
+        let (Some(copy_to), Some(copy_from)) = (self.resolve_src(ctx), dests.first().cloned())
+        else {
+            // Meta packages have no files to back up
+            return Ok(());
+        };
+        // Update pulls from the first target only
+        for other in dests.iter().skip(1) {
+            if !same_content(&copy_from, other) {
+                cprintln(
+                    &format!(
+                        "{} differs from {}, only the first is copied back for '{}'",
+                        other.display(),
+                        copy_from.display(),
+                        self.name
+                    ),
+                    &LogLevel::WARNING,
+                );
+            }
+        }
+        // A layered package still takes changes to its local layers
+        if let Some(remote) = self.remote_source().filter(|_| !self.is_layered()) {
+            cprintln(
+                &format!(
+                    "Skipping backup for '{}', its src is downloaded from {} and read-only",
+                    self.name, remote.url
+                ),
+                &LogLevel::WARNING,
+            );
+            return Ok(());
+        }
+        // Copying a directory over a file src, or into one, goes nowhere good
+        if let Some((expected, found)) = kind_mismatch(&copy_to, &copy_from) {
+            anyhow::bail!(
+                "Package '{}' expects a {} at {} but found a {}, nothing was copied back",
+                self.name,
+                expected,
+                normalize_home_path(&copy_from.to_string_lossy()),
+                found
+            );
+        }
+        let merged_view = self.has_merged_view(ctx);
+        // Layered packages skip their templated files one by one
+        if !merged_view && self.package_is_templated(&ctx.working_dir) {
+            cprintln(
+                &format!("Skipping backup for templated '{}'", self.name),
+                &LogLevel::WARNING,
+            );
+            return Ok(());
+        }
+        if self.merge.is_some() {
+            // The dest holds more than what we manage, copying it back would pollute src
+            cprintln(
+                &format!("Skipping backup for merged '{}'", self.name),
+                &LogLevel::WARNING,
+            );
+            return Ok(());
+        }
+        self.check_limits(&copy_from, ctx)?;
+        if self.block {
+            self.check_block_src(ctx)?;
+            let copied = self.copy_back_block(ctx, &copy_from, &copy_to, dry_run, report);
+            return self.attempt_file(ctx, &copy_to, copied, report);
+        }
+        if merged_view && ctx.is_staging() {
+            cprintln(
+                &format!(
+                    "Skipping backup for layered '{}', its files can't be staged",
+                    self.name
+                ),
+                &LogLevel::WARNING,
+            );
+            return Ok(());
+        }
+        if merged_view {
+            if ctx.prunes_missing() {
+                cprintln(
+                    &format!(
+                        "Not pruning '{}', --prune-missing leaves layered packages alone",
+                        self.name
+                    ),
+                    &LogLevel::WARNING,
+                );
+            }
+            return self.backup_layered(&copy_from, ctx, dry_run, report);
+        }
+        if copy_from.is_dir() {
+            let subpaths = self.subpath_dests(ctx)?;
+            // Recursively copy directory contents, avoiding files ending with BACKUP_EXT
+            for (relative_path, entry) in self.dest_entries(&copy_from, &subpaths)? {
+                self.check_interrupt(ctx, report.backed_up.len())?;
+                let relative_path = relative_path.as_path();
+                // A linked dir may lead anywhere, outside of what the package manages
+                if entry.depth() > 0 && entry.path_is_symlink() && entry.path().is_dir() {
+                    cprintln(
+                        &format!("Skipping symlinked directory {}", entry.path().display()),
+                        &LogLevel::WARNING,
+                    );
+                    continue;
+                }
+                if self.should_ignore(relative_path) {
+                    if dry_run {
+                        cprintln(
+                            &format!("  {} (ignored)", entry.path().display()),
+                            &LogLevel::INFO,
+                        );
+                    }
+                    continue;
+                }
+                if self.is_under_kept_dir(relative_path) {
+                    continue;
+                }
+                let Some(src_relative) = self.src_relative(relative_path) else {
+                    cprintln(
+                        &format!(
+                            "Skipping {}, its src deploys under another name",
+                            entry.path().display()
+                        ),
+                        &LogLevel::TRACE,
+                    );
+                    continue;
+                };
+                let dest_path = copy_to.join(src_relative);
+                if entry.depth() > 0
+                    && (self.skips_new_file(ctx, &dest_path)
+                        || self.skips_removed_file(relative_path, &dest_path))
+                {
+                    continue;
+                }
+                if entry.path().is_dir() {
+                    if entry.depth() > 0
+                        && (self
+                            .keep_dirs
+                            .iter()
+                            .any(|dir| relative_path == Path::new(dir))
+                            || is_empty_dir(entry.path()))
+                    {
+                        let kept = self.keep_dir(ctx, &dest_path, &copy_to, dry_run, report);
+                        self.attempt_file(ctx, &dest_path, kept, report)?;
+                    } else if !dry_run && !ctx.is_staging() {
+                        let created = std::fs::create_dir_all(&dest_path).map_err(|e| {
+                            anyhow::anyhow!("Failed to create {}: {}", dest_path.display(), e)
+                        });
+                        self.attempt_file(ctx, &dest_path, created, report)?;
+                    }
+                } else if entry.path().extension() != Some(OsStr::new(BACKUP_EXT)) {
+                    // Rendered from a .tera template, which can't be rebuilt from it
+                    let template = with_tera_ext(&dest_path);
+                    if template.exists() {
+                        cprintln(
+                            &format!(
+                                "Skipping {}, rendered from {}",
+                                entry.path().display(),
+                                template.display()
+                            ),
+                            &LogLevel::TRACE,
+                        );
+                        continue;
+                    }
+                    let copied =
+                        self.copy_back(ctx, entry.path(), &dest_path, &copy_to, dry_run, report);
+                    self.attempt_file(ctx, &dest_path, copied, report)?;
+                }
+            }
+            if ctx.prunes_missing() {
+                self.prune_missing_srcs(&copy_to, &copy_from, ctx, dry_run, report)?;
+            }
+        } else if !self.skips_new_file(ctx, &copy_to) {
+            let copied = self.copy_back(ctx, &copy_from, &copy_to, &copy_to, dry_run, report);
+            self.attempt_file(ctx, &copy_to, copied, report)?;
+        }
+        Ok(())
+    }
+
+    // Copy a deployed file back over `src`, or into the stage with
+    // --stage-to. `src_root` is the src of the package `src` is in.
+    fn copy_back(
+        &self,
+        ctx: &Context,
+        from: &Path,
+        src: &Path,
+        src_root: &Path,
+        dry_run: bool,
+        report: &mut PackageReport,
+    ) -> anyhow::Result<()> {
+        if dry_run || !ctx.is_staging() {
+            return backup_file(from, src, dry_run, ctx.uses_reflink(), report);
+        }
+        let content = std::fs::read(from)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", from.display(), e))?;
+        if content_matches(src, &content)? {
+            return Ok(());
+        }
+        let staged = self.stage_file(ctx, &content, src, src_root, report)?;
+        copy_permissions(from, &staged)?;
+        Ok(())
+    }
+
+    // Copy only the block of the deployed file back over `src`, or into the
+    // stage with --stage-to. A dest without the block is left alone
+    fn copy_back_block(
+        &self,
+        ctx: &Context,
+        from: &Path,
+        src: &Path,
+        dry_run: bool,
+        report: &mut PackageReport,
+    ) -> anyhow::Result<()> {
+        let content = std::fs::read_to_string(from)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", from.display(), e))?;
+        let Some(block) = extract_block(&content, &self.name, self.block_comment())
+            .map_err(|e| anyhow::anyhow!("{}: {}", from.display(), e))?
+        else {
+            cprintln(
+                &format!(
+                    "Skipping backup for '{}', {} has no block of it",
+                    self.name,
+                    from.display()
+                ),
+                &LogLevel::WARNING,
+            );
+            return Ok(());
+        };
+        if content_matches(src, block.as_bytes())? {
+            return Ok(());
+        }
+        if ctx.is_staging() && !dry_run {
+            self.stage_file(ctx, block.as_bytes(), src, src, report)?;
+            return Ok(());
+        }
+        report.backed_up.push(src.to_path_buf());
+        if dry_run {
+            report.diffs.push(FileDiff {
+                src: from.to_path_buf(),
+                dest: src.to_path_buf(),
+                old: std::fs::read_to_string(src).ok(),
+                new: Some(block),
+            });
+            return Ok(());
+        }
+        create_parent_dirs(src)?;
+        write_atomic(src, block.as_bytes(), None)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", src.display(), e))
+    }
+
+    // Keep an empty directory of dest in `src`, or stage its placeholder
+    // with --stage-to
+    fn keep_dir(
+        &self,
+        ctx: &Context,
+        dir: &Path,
+        src_root: &Path,
+        dry_run: bool,
+        report: &mut PackageReport,
+    ) -> anyhow::Result<()> {
+        if dry_run || !ctx.is_staging() {
+            return keep_src_dir(dir, dry_run, report);
+        }
+        let placeholder = dir.join(KEEP_FILE);
+        if placeholder.exists() || (dir.exists() && !is_empty_dir(dir)) {
+            return Ok(());
+        }
+        self.stage_file(ctx, b"", &placeholder, src_root, report)?;
+        Ok(())
+    }
+
+    // Write `content` to where `src` is staged and note it in the stage.
+    // Returns the staged path.
+    fn stage_file(
+        &self,
+        ctx: &Context,
+        content: &[u8],
+        src: &Path,
+        src_root: &Path,
+        report: &mut PackageReport,
+    ) -> anyhow::Result<PathBuf> {
+        let relative_path = src.strip_prefix(src_root)?;
+        let staged = ctx
+            .with_stage(|stage| stage.staged_path(&self.name, relative_path))
+            .ok_or_else(|| anyhow::anyhow!("Not staging '{}'", self.name))?;
+        report.backed_up.push(staged.clone());
+        create_parent_dirs(&staged)?;
+        write_atomic(&staged, content, None)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", staged.display(), e))?;
+        ctx.with_stage(|stage| stage.record(&self.name, relative_path, src, &ctx.working_dir))
+            .unwrap_or(Ok(()))?;
+        Ok(staged)
+    }
+
+    // The files deploy would copy from the sources, checked against the
+    // package's limits before anything is written
+    fn check_src_limits(&self, ctx: &Context) -> anyhow::Result<()> {
+        if ctx.ignores_limits() || (self.max_file_size.is_none() && self.max_files.is_none()) {
+            return Ok(());
+        }
+        if self.has_merged_view(ctx) {
+            let files: Vec<PathBuf> = self.layered_files(ctx)?.into_values().collect();
+            return self.check_file_limits(&files);
+        }
+        match self.resolve_src(ctx) {
+            Some(src) => self.check_limits(&src, ctx),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail when `root` holds a file bigger than `max_file_size`, or more
+    /// files than `max_files`, leaving out what the package ignores. With
+    /// --ignore-limits there is no check.
+    pub fn check_limits(&self, root: &Path, ctx: &Context) -> anyhow::Result<()> {
+        if ctx.ignores_limits() || (self.max_file_size.is_none() && self.max_files.is_none()) {
+            return Ok(());
+        }
+        if root.is_file() {
+            return self.check_file_limits(&[root.to_path_buf()]);
+        }
+        if !root.is_dir() {
+            return Ok(());
+        }
+        let mut files: Vec<PathBuf> = Vec::new();
+        for entry in sorted_entries(root)? {
+            let relative_path = entry.path().strip_prefix(root)?;
+            if entry.file_type().is_file() && !self.should_ignore(relative_path) {
+                files.push(entry.into_path());
+            }
+        }
+        self.check_file_limits(&files)
+    }
+
+    fn check_file_limits(&self, files: &[PathBuf]) -> anyhow::Result<()> {
+        if let Some(max_files) = self.max_files
+            && files.len() > max_files
+        {
+            anyhow::bail!(
+                "Package '{}' has {} files, more than max_files = {}. Ignore what it shouldn't \
+                 hold, raise the limit, or pass --ignore-limits",
+                self.name,
+                files.len(),
+                max_files
+            );
+        }
+        let Some(max_size) = self.max_file_size else {
+            return Ok(());
+        };
+        for file in files {
+            let size = std::fs::metadata(file)?.len();
+            if size > max_size {
+                anyhow::bail!(
+                    "{} in '{}' is {}, over max_file_size = \"{}\". Add it to the package's \
+                     ignore list, raise the limit, or pass --ignore-limits",
+                    file.display(),
+                    self.name,
+                    indicatif::HumanBytes(size),
+                    format_size(max_size)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // With --no-include-new, what src doesn't have yet stays at dest
+    fn skips_new_file(&self, ctx: &Context, src: &Path) -> bool {
+        let skip = ctx.skips_new() && src.symlink_metadata().is_err();
+        if skip {
+            cprintln(
+                &format!(
+                    "Skipping {}, it isn't in '{}' yet",
+                    src.display(),
+                    self.name
+                ),
+                &LogLevel::TRACE,
+            );
+        }
+        skip
+    }
+
+    // A stale copy of a file listed in `removed` isn't brought back into the
+    // repository, unless src has it again
+    fn skips_removed_file(&self, relative_path: &Path, src: &Path) -> bool {
+        let skip = src.symlink_metadata().is_err()
+            && self
+                .removed
+                .iter()
+                .any(|entry| relative_path == Path::new(entry));
+        if skip {
+            cprintln(
+                &format!(
+                    "Skipping {}, it was deleted from '{}'",
+                    src.display(),
+                    self.name
+                ),
+                &LogLevel::TRACE,
+            );
+        }
+        skip
+    }
+
+    // Remove the files of `src` that are gone from `dest`, for update
+    // --prune-missing. Templates, and what the package ignores or keeps, stay.
+    fn prune_missing_srcs(
+        &self,
+        src: &Path,
+        dest: &Path,
+        ctx: &Context,
+        dry_run: bool,
+        report: &mut PackageReport,
+    ) -> anyhow::Result<()> {
+        let subpaths = self.subpath_dests(ctx)?;
+        for entry in sorted_entries(src)? {
+            let relative_path = entry.path().strip_prefix(src)?;
+            if entry.file_type().is_dir()
+                || entry.file_name() == KEEP_FILE
+                || self.should_ignore(relative_path)
+                || self.is_under_kept_dir(relative_path)
+                || place(dest, &self.dest_relative(relative_path), &subpaths)
+                    .symlink_metadata()
+                    .is_ok()
+            {
+                continue;
+            }
+            if self.is_template(entry.path()) {
+                cprintln(
+                    &format!(
+                        "Keeping {}, a template is never pruned",
+                        entry.path().display()
+                    ),
+                    &LogLevel::TRACE,
+                );
+                continue;
+            }
+            if dry_run {
+                report.pruned.push(entry.path().to_path_buf());
+                continue;
+            }
+            let removed = std::fs::remove_file(entry.path())
+                .map_err(|e| anyhow::anyhow!("Failed to remove {}: {}", entry.path().display(), e));
+            if removed.is_ok() {
+                cprintln(
+                    &format!("Removed {}, it is gone from dest", entry.path().display()),
+                    &LogLevel::INFO,
+                );
+                report.pruned.push(entry.path().to_path_buf());
+                remove_empty_parents(entry.path(), src);
+            }
+            self.attempt_file(ctx, entry.path(), removed, report)?;
+        }
+        Ok(())
+    }
+
+    /// Copy the dest of a layered package back, each file into the source
+    /// file it was deployed from, and files none of them have into the last
+    /// source. Files whose only sources are variants for other hosts are left
+    /// out rather than added as plain files.
+    fn backup_layered(
+        &self,
+        copy_from: &Path,
+        ctx: &Context,
+        dry_run: bool,
+        report: &mut PackageReport,
+    ) -> anyhow::Result<()> {
+        let srcs = self.resolve_srcs(ctx)?;
+        let merged = self.merged_sources(&srcs, ctx)?;
+        let variants = self.variant_targets(&srcs)?;
+        let Some(last) = srcs.last() else {
+            return Ok(());
+        };
+        let remote = self.remote_source().map(|r| r.path(&ctx.working_dir));
+        let files: Vec<(PathBuf, PathBuf)> = if copy_from.is_dir() {
+            let mut files = Vec::new();
+            let subpaths = self.subpath_dests(ctx)?;
+            for (relative_path, entry) in self.dest_entries(copy_from, &subpaths)? {
+                let relative_path = relative_path.as_path();
+                if entry.depth() > 0 && entry.path_is_symlink() && entry.path().is_dir() {
+                    cprintln(
+                        &format!("Skipping symlinked directory {}", entry.path().display()),
+                        &LogLevel::WARNING,
+                    );
+                    continue;
+                }
+                if self.should_ignore(relative_path) || self.is_under_kept_dir(relative_path) {
+                    continue;
+                }
+                if entry.path().is_dir() {
+                    if entry.depth() > 0
+                        && (self
+                            .keep_dirs
+                            .iter()
+                            .any(|dir| relative_path == Path::new(dir))
+                            || is_empty_dir(entry.path()))
+                        && !srcs
+                            .iter()
+                            .any(|src| src.join(relative_path).join(KEEP_FILE).exists())
+                        && (!ctx.skips_new()
+                            || srcs.iter().any(|src| src.join(relative_path).is_dir()))
+                    {
+                        let src_dir = last.join(relative_path);
+                        let kept = keep_src_dir(&src_dir, dry_run, report);
+                        self.attempt_file(ctx, &src_dir, kept, report)?;
+                    }
+                    continue;
+                }
+                if entry.path().extension() == Some(OsStr::new(BACKUP_EXT)) {
+                    continue;
+                }
+                files.push((relative_path.to_path_buf(), entry.path().to_path_buf()));
+            }
+            files
+        } else {
+            vec![(PathBuf::new(), copy_from.to_path_buf())]
+        };
+        for (relative_path, dest_path) in files {
+            self.check_interrupt(ctx, report.backed_up.len())?;
+            let Some(src) = merged.get(&relative_path) else {
+                if variants.contains(&relative_path) {
+                    cprintln(
+                        &format!(
+                            "Skipping {}, its source only has variants for other hosts",
+                            dest_path.display()
+                        ),
+                        &LogLevel::WARNING,
+                    );
+                    continue;
+                }
+                let to = join_relative(last, &relative_path);
+                if self.skips_new_file(ctx, &to) || self.skips_removed_file(&relative_path, &to) {
+                    continue;
+                }
+                let copied = backup_file(&dest_path, &to, dry_run, ctx.uses_reflink(), report);
+                self.attempt_file(ctx, &to, copied, report)?;
+                continue;
+            };
+            if remote.as_ref().is_some_and(|root| src.starts_with(root)) {
+                if same_content(&dest_path, src) {
+                    continue;
+                }
+                cprintln(
+                    &format!(
+                        "Skipping {}, it comes from the downloaded {}",
+                        dest_path.display(),
+                        src.display()
+                    ),
+                    &LogLevel::WARNING,
+                );
+                continue;
+            }
+            // Rendered from a template, which can't be rebuilt from it
+            if self.is_template(src) {
+                cprintln(
+                    &format!(
+                        "Skipping {}, rendered from {}",
+                        dest_path.display(),
+                        src.display()
+                    ),
+                    &LogLevel::TRACE,
+                );
+                continue;
+            }
+            let copied = backup_file(&dest_path, src, dry_run, ctx.uses_reflink(), report);
+            self.attempt_file(ctx, src, copied, report)?;
+        }
+        Ok(())
+    }
+
+    // Stop between two files once Ctrl-C was pressed, `done` of them went
+    // through whole
+    fn check_interrupt(&self, ctx: &Context, done: usize) -> Result<(), anyhow::Error> {
+        if !ctx.is_interrupted() {
+            return Ok(());
+        }
+        Err(ExitCodeError {
+            code: INTERRUPTED_EXIT_CODE,
+            message: format!(
+                "Interrupted after {} file(s) of package '{}'",
+                done, self.name
+            ),
+        }
+        .into())
+    }
+
+    // A file that fails is noted in the report and the next one is tried,
+    // unless --fail-fast is given
+    fn attempt_file(
+        &self,
+        ctx: &Context,
+        path: &Path,
+        result: Result<(), anyhow::Error>,
+        report: &mut PackageReport,
+    ) -> Result<(), anyhow::Error> {
+        match result {
+            Err(e) if !ctx.is_fail_fast() => {
+                cprintln(&format!("{}: {}", path.display(), e), &LogLevel::DEBUG);
+                report.failed.push((path.to_path_buf(), e.to_string()));
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    // The error for the files `attempt_file` noted, after `done` others went through
+    fn failed_files_error(
+        &self,
+        report: &PackageReport,
+        verb: &str,
+        done: usize,
+    ) -> Result<(), anyhow::Error> {
+        if report.failed.is_empty() {
+            return Ok(());
+        }
+        let mut message = format!(
+            "Package '{}': {} {}, failed {}:",
+            self.name,
+            verb,
+            done,
+            report.failed.len()
+        );
+        for (path, cause) in report.failed.iter() {
+            message.push_str(&format!("\n  {}: {}", path.display(), cause));
+        }
+        Err(anyhow::anyhow!(message))
+    }
+
+    /// Whether deploying renders `src`, whose text is `content`, as the
+    /// package's `template` mode says. In auto mode, markers that don't
+    /// parse as a template are copied as they are, with a warning.
+    fn renders(&self, src: &Path, content: &str) -> bool {
+        match self.template {
+            TemplateMode::Never => false,
+            TemplateMode::Always => true,
+            TemplateMode::Auto if is_tera_file(src) => true,
+            TemplateMode::Auto if !is_templated_str(content) => false,
+            TemplateMode::Auto => match tera::Template::new("", None, content) {
+                Ok(_) => true,
+                Err(e) => {
+                    cprintln(
+                        &format!(
+                            "Copying {} as it is, its template markers don't parse: {}",
+                            src.display(),
+                            tera_error_chain(&e)
+                        ),
+                        &LogLevel::WARNING,
+                    );
+                    false
+                }
+            },
+        }
+    }
+
+    // `renders` for a file not read yet, without the warning
+    fn is_template(&self, path: &Path) -> bool {
+        match self.template {
+            TemplateMode::Never => false,
+            _ if is_tera_file(path) => true,
+            TemplateMode::Always => std::fs::read_to_string(path).is_ok(),
+            TemplateMode::Auto => {
+                std::fs::read_to_string(path).is_ok_and(|text| parses_as_template(&text))
+            }
+        }
+    }
+
+    /// Render a templated source file, checking for undefined variables first
+    /// unless the package is lenient. Variables are written as they are, or
+    /// HTML-escaped when the package sets `autoescape`.
+    fn render(&self, src: &Path, content: &str, ctx: &Context) -> Result<String, anyhow::Error> {
+        let variables = self.resolved_variables(ctx)?;
+        if !self.lenient {
+            validate_template_str(&src.display().to_string(), content, &variables)
+                .map_err(|e| ctx.explain_missing_variable(e))?;
+        }
+        ctx.with_templates(|tera| {
+            if self.autoescape {
+                // Every template name ends with the empty suffix
+                tera.autoescape_on(vec![""]);
+            }
+            let rendered = render_with(tera, &src.display().to_string(), content, &variables);
+            tera.autoescape_on(vec![]);
+            rendered
+        })
+        .map_err(|e| ctx.explain_missing_variable(e))
+    }
+
+    /// Render the templates deploying would render, writing nothing. Returns
+    /// how many there were; the first that fails is the error.
+    pub fn check_templates(&self, ctx: &Context) -> Result<usize, anyhow::Error> {
+        let mut rendered = 0;
+        for src in self.merged_sources(&self.resolve_srcs(ctx)?, ctx)?.values() {
+            // Binary files are copied as they are
+            let Ok(content) = std::fs::read_to_string(src) else {
+                continue;
+            };
+            if self.renders(src, &content) {
+                self.render(src, &content, ctx)?;
+                rendered += 1;
+            }
+        }
+        Ok(rendered)
+    }
+
+    /// The base src, rendered and resolved. A src that fails to render is
+    /// resolved as written, deploy reports the error through `resolve_srcs`.
+    pub fn resolve_src(&self, ctx: &Context) -> Option<PathBuf> {
+        if let Some(remote) = self.remote_source() {
+            return Some(remote.path(&ctx.working_dir));
+        }
+        self.src.as_ref().map(|src| {
+            let rendered = self.render_src(src, ctx).unwrap_or_else(|_| src.clone());
+            resolve_path(&rendered, &ctx.working_dir)
+        })
+    }
+
+    /// Every source of the package, the base src first and then its
+    /// overlays, each rendered through the variables and resolved.
+    pub fn resolve_srcs(&self, ctx: &Context) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let mut srcs = Vec::new();
+        if let Some(remote) = self.remote_source() {
+            srcs.push(remote.path(&ctx.working_dir));
+        } else if let Some(src) = &self.src {
+            srcs.push(resolve_path(&self.render_src(src, ctx)?, &ctx.working_dir));
+        }
+        for overlay in self.overlays.iter() {
+            srcs.push(resolve_path(
+                &self.render_src(overlay, ctx)?,
+                &ctx.working_dir,
+            ));
+        }
+        Ok(srcs)
+    }
+
+    /// Where a downloaded src comes from: the `remote` table, or a src
+    /// written as a URL.
+    pub fn remote_source(&self) -> Option<RemoteSource> {
+        self.remote
+            .clone()
+            .or_else(|| self.src.as_deref().and_then(RemoteSource::from_url))
+    }
+
+    /// Download the remote src unless it's cached.
+    pub fn ensure_remote(&self, ctx: &Context) -> Result<(), anyhow::Error> {
+        match self.remote_source() {
+            Some(remote) => remote
+                .ensure(&ctx.working_dir, ctx.is_offline())
+                .map_err(|e| anyhow::anyhow!("Package '{}': {}", self.name, e)),
+            None => Ok(()),
+        }
+    }
+
+    // The package's own metadata isn't available here, it's built from src
+    fn render_src(&self, src: &str, ctx: &Context) -> Result<String, anyhow::Error> {
+        if !is_templated_str(src) {
+            return Ok(src.to_string());
+        }
+        compile_string(src, &ctx.merge_variables(Some(self)))
+            .map_err(|e| anyhow::anyhow!("Package '{}': invalid src '{}': {}", self.name, src, e))
+    }
+
+    pub fn is_layered(&self) -> bool {
+        !self.overlays.is_empty()
+    }
+
+    /// Whether the package deploys through the merged view of its sources:
+    /// it has overlays, or host-specific variants like `config##os.linux`.
+    pub fn has_merged_view(&self, ctx: &Context) -> bool {
+        if self.is_layered() {
+            return true;
+        }
+        // Any name with the separator, so a malformed variant fails the deploy
+        // instead of deploying under its own name
+        let srcs = self.resolve_srcs(ctx).unwrap_or_default();
+        srcs.iter().filter(|src| src.is_dir()).any(|src| {
+            walkdir::WalkDir::new(src)
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+                .any(|entry| {
+                    entry
+                        .file_name()
+                        .to_string_lossy()
+                        .contains(VARIANT_SEPARATOR)
+                })
+        })
+    }
+
+    /// The merged view of a package's sources: each relative path, without a
+    /// `.tera` extension or variant suffix, mapped to the file deployed there.
+    /// The variant for this host beats the one for its os, which beats the one
+    /// for the profile, which beats the plain file; between equals the last
+    /// source wins. A single-file source maps the empty path. Sources that
+    /// don't exist are skipped.
+    fn merged_sources(
+        &self,
+        srcs: &[PathBuf],
+        ctx: &Context,
+    ) -> Result<BTreeMap<PathBuf, PathBuf>, anyhow::Error> {
+        let mut ranked: BTreeMap<PathBuf, (usize, PathBuf)> = BTreeMap::new();
+        for src in srcs {
+            if src.is_file() {
+                ranked.insert(PathBuf::new(), (0, src.clone()));
+            } else if src.is_dir() {
+                for entry in sorted_entries(src)? {
+                    let relative_path = entry.path().strip_prefix(src)?;
+                    if entry.file_type().is_dir()
+                        || entry.file_name() == KEEP_FILE
+                        || self.should_ignore(relative_path)
+                    {
+                        continue;
+                    }
+                    let Some((target, rank)) = select_variant(relative_path, ctx)? else {
+                        continue;
+                    };
+                    let target = self.dest_relative(&target);
+                    if self.should_ignore(&target)
+                        || ranked.get(&target).is_some_and(|(best, _)| *best > rank)
+                    {
+                        continue;
+                    }
+                    ranked.insert(target, (rank, entry.path().to_path_buf()));
+                }
+            } else {
+                cprintln(
+                    &format!("Source {} of '{}' is missing", src.display(), self.name),
+                    &LogLevel::DEBUG,
+                );
+            }
+        }
+        Ok(ranked
+            .into_iter()
+            .map(|(target, (_, src))| (target, src))
+            .collect())
+    }
+
+    // The paths the variants in `srcs` deploy as, whichever host they're for
+    fn variant_targets(&self, srcs: &[PathBuf]) -> Result<BTreeSet<PathBuf>, anyhow::Error> {
+        let mut targets = BTreeSet::new();
+        for src in srcs.iter().filter(|src| src.is_dir()) {
+            for entry in walkdir::WalkDir::new(src) {
+                let entry = entry?;
+                if entry.file_type().is_dir() {
+                    continue;
+                }
+                if let Some((target, _)) = parse_variant(entry.path().strip_prefix(src)?)? {
+                    targets.insert(self.dest_relative(&target));
+                }
+            }
+        }
+        Ok(targets)
+    }
+
+    /// `merged_sources` for deploying, where finding nothing at all is an error.
+    fn layered_files(&self, ctx: &Context) -> Result<BTreeMap<PathBuf, PathBuf>, anyhow::Error> {
+        let merged = self.merged_sources(&self.resolve_srcs(ctx)?, ctx)?;
+        if merged.is_empty() {
+            anyhow::bail!("Package '{}' has no files in any of its sources", self.name);
+        }
+        Ok(merged)
+    }
+
+    /// The dests for this machine and the active profile, with any `@name/`
+    /// base expanded: the `host:<hostname>` targets matching this machine
+    /// when there are some, then the profile's targets, otherwise `dest`.
+    /// Empty for packages without a dest. Errors when a base isn't known.
+    pub fn resolve_dests(&self, ctx: &Context) -> Result<Vec<PathBuf>, anyhow::Error> {
+        if self.dest.is_none() {
+            return Ok(Vec::new());
+        }
+        let dests: Vec<&String> = match self.active_target(ctx) {
+            Some(key) => self.targets[key].iter().collect(),
+            None => self.dest.iter().collect(),
+        };
+        dests
+            .into_iter()
+            .map(|dest| {
+                ctx.resolve_package_path(dest)
+                    .map_err(|e| anyhow::anyhow!("Package '{}': {}", self.name, e))
+            })
+            .collect()
+    }
+
+    /// The subpaths of the package the active target deploys elsewhere than
+    /// the rest, with their dests expanded, most specific first. Empty
+    /// unless that target is a table.
+    pub fn subpath_dests(&self, ctx: &Context) -> Result<Vec<(PathBuf, PathBuf)>, anyhow::Error> {
+        let Some(subpaths) = self
+            .active_target(ctx)
+            .and_then(|key| self.subpath_targets.get(key))
+        else {
+            return Ok(Vec::new());
+        };
+        let mut dests = subpaths
+            .iter()
+            .map(|(subpath, dest)| {
+                let dest = ctx
+                    .resolve_package_path(dest)
+                    .map_err(|e| anyhow::anyhow!("Package '{}': {}", self.name, e))?;
+                Ok((PathBuf::from(subpath), dest))
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+        dests.sort_by_key(|(subpath, _)| std::cmp::Reverse(subpath.components().count()));
+        Ok(dests)
+    }
+
+    /// Where the src file at `relative_path` deploys for `dest`, one of
+    /// `resolve_dests`: under the dest of the longest subpath of the active
+    /// target holding it, otherwise under `dest`, named as `dest_relative`
+    /// says.
+    pub fn resolve_dest_for(
+        &self,
+        dest: &Path,
+        relative_path: &Path,
+        ctx: &Context,
+    ) -> Result<PathBuf, anyhow::Error> {
+        Ok(place(
+            dest,
+            &self.dest_relative(relative_path),
+            &self.subpath_dests(ctx)?,
+        ))
+    }
+
+    // The `targets` key that applies on this machine: the `host:` key
+    // matching its hostname, else the active profile's
+    fn active_target(&self, ctx: &Context) -> Option<&str> {
+        self.host_target(ctx.hostname()).or_else(|| {
+            let profile = ctx.profile.as_ref()?;
+            let (key, _) = self.targets.get_key_value(&profile.name)?;
+            Some(key.as_str())
+        })
+    }
+
+    // The `host:` key naming `hostname`, or else the first `host:` glob
+    // matching it, in key order
+    fn host_target(&self, hostname: &str) -> Option<&str> {
+        let exact = format!("{}{}", HOST_TARGET_PREFIX, hostname);
+        if let Some((key, _)) = self.targets.get_key_value(&exact) {
+            return Some(key);
+        }
+        let mut patterns: Vec<(&str, &str)> = self
+            .targets
+            .keys()
+            .filter_map(|key| {
+                key.strip_prefix(HOST_TARGET_PREFIX)
+                    .map(|pattern| (pattern, key.as_str()))
+            })
+            .collect();
+        patterns.sort();
+        patterns
+            .into_iter()
+            .find(|(pattern, _)| glob_match::glob_match(pattern, hostname))
+            .map(|(_, key)| key)
+    }
+
+    // The entries under `dest` and under the dests of `subpaths`, each with
+    // its path relative to the package, as update and prune walk them.
+    // What lies at dest under a subpath deployed elsewhere isn't the
+    // package's, and neither is another subpath's dest inside this one.
+    fn dest_entries(
+        &self,
+        dest: &Path,
+        subpaths: &[(PathBuf, PathBuf)],
+    ) -> Result<Vec<(PathBuf, walkdir::DirEntry)>, anyhow::Error> {
+        let mut entries = Vec::new();
+        let roots = std::iter::once((PathBuf::new(), dest.to_path_buf())).chain(
+            subpaths
+                .iter()
+                .filter(|(_, root)| root.symlink_metadata().is_ok())
+                .cloned(),
+        );
+        for (subpath, root) in roots {
+            for entry in sorted_entries(&root)? {
+                let relative_path = join_relative(&subpath, entry.path().strip_prefix(&root)?);
+                let owner = subpaths
+                    .iter()
+                    .find(|(other, _)| relative_path.starts_with(other))
+                    .map_or(Path::new(""), |(other, _)| other.as_path());
+                let nested = subpaths
+                    .iter()
+                    .any(|(_, other)| *other != root && entry.path().starts_with(other));
+                if owner == subpath && !nested {
+                    entries.push((relative_path, entry));
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Refuse dests outside the home and working directories unless the
+    /// package sets `allow_outside_home` or `--allow-system-paths` is given.
+    /// A dest under one of the `protected_paths` always needs the flag.
+    pub fn check_dest_locations(&self, ctx: &Context) -> Result<(), anyhow::Error> {
+        if ctx.allows_system_paths() {
+            return Ok(());
+        }
+        let home = std::env::home_dir().map(|home| normalize_path(&home));
+        let working_dir = normalize_path(&ctx.working_dir);
+        let subpath_dests = self.subpath_dests(ctx)?.into_iter().map(|(_, dest)| dest);
+        for dest in self.resolve_dests(ctx)?.into_iter().chain(subpath_dests) {
+            let dest = normalize_path(&dest);
+            if let Some(protected) = ctx.protected_paths().iter().find(|p| dest.starts_with(p)) {
+                anyhow::bail!(
+                    "Package '{}' deploys to {}, under the protected path {}. Pass --allow-system-paths to deploy there",
+                    self.name,
+                    dest.display(),
+                    protected.display()
+                );
+            }
+            let inside_home = home.as_ref().is_some_and(|home| dest.starts_with(home));
+            if !inside_home && !dest.starts_with(&working_dir) && !self.allow_outside_home {
+                anyhow::bail!(
+                    "Package '{}' deploys to {}, outside your home directory. Set allow_outside_home = true on the package or pass --allow-system-paths if that's intended",
+                    self.name,
+                    dest.display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The newest modification time among the package's source files, `None`
+    /// when it has none (or they can't be read).
+    pub fn latest_src_change(&self, ctx: &Context) -> Option<SystemTime> {
+        let srcs = self.resolve_srcs(ctx).ok()?;
+        srcs.iter()
+            .filter(|src| src.exists())
+            .flat_map(|src| walkdir::WalkDir::new(src).follow_links(false))
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| entry.metadata().ok()?.modified().ok())
+            .max()
+    }
+
+    /// The files deploying would write, without rendering anything: every
+    /// file of a directory src that isn't ignored, under each dest.
+    pub fn dest_files(&self, ctx: &Context) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let Some(src) = self.resolve_src(ctx) else {
+            return Ok(Vec::new());
+        };
+        let dests = self.resolve_dests(ctx)?;
+        let subpaths = self.subpath_dests(ctx)?;
+        if self.has_merged_view(ctx) {
+            let merged = self.merged_sources(&self.resolve_srcs(ctx)?, ctx)?;
+            return Ok(dests
+                .iter()
+                .flat_map(|dest| merged.keys().map(|file| place(dest, file, &subpaths)))
+                .collect());
+        }
+        if !src.is_dir() {
+            return Ok(dests);
+        }
+        let mut relative_files: Vec<PathBuf> = Vec::new();
+        for entry in walkdir::WalkDir::new(&src) {
+            let entry = entry?;
+            let relative_path = entry.path().strip_prefix(&src)?;
+            if !entry.file_type().is_dir()
+                && entry.file_name() != KEEP_FILE
+                && !self.should_ignore(relative_path)
+            {
+                relative_files.push(self.dest_relative(relative_path));
+            }
+        }
+        Ok(dests
+            .iter()
+            .flat_map(|dest| {
+                relative_files
+                    .iter()
+                    .map(|file| place(dest, file, &subpaths))
+            })
+            .collect())
+    }
+
+    /// Compare what deploying `src` would write with `dest`, adding the
+    /// result to the report when dest exists.
+    pub fn diff_file(
+        &self,
+        src: &PathBuf,
+        dest: &PathBuf,
+        ctx: &Context,
+        report: &mut PackageReport,
+    ) -> Result<(), anyhow::Error> {
+        if let Ok(src_content) = std::fs::read_to_string(src) {
+            let compiled_content = if self.renders(src, &src_content) {
+                self.render(src, &src_content, ctx)?
+            } else {
+                src_content
+            };
+            let compiled_content = self.fit_into_dest(src, compiled_content, dest)?;
+
+            if dest.exists() {
+                let existing_content = std::fs::read_to_string(dest).unwrap_or_default();
+                report.diffs.push(FileDiff {
+                    src: src.clone(),
+                    dest: dest.clone(),
+                    old: Some(existing_content),
+                    new: Some(compiled_content),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn diff(&self, ctx: &Context, report: &mut PackageReport) -> Result<(), anyhow::Error> {
+        let Some(src) = self.resolve_src(ctx) else {
+            return Ok(());
+        };
+        self.ensure_remote(ctx)?;
+        self.check_block_src(ctx)?;
+        if self.has_merged_view(ctx) {
+            let merged = self.layered_files(ctx)?;
+            let subpaths = self.subpath_dests(ctx)?;
+            for dest in self.resolve_dests(ctx)? {
+                for (relative_path, src) in merged.iter() {
+                    self.diff_file(src, &place(&dest, relative_path, &subpaths), ctx, report)?;
+                }
+                report.removed.extend(self.stale_files(&dest, ctx)?);
+            }
+            return Ok(());
+        }
+        for dest in self.resolve_dests(ctx)? {
+            self.diff_dest(&src, &dest, ctx, report)?;
+            report.removed.extend(self.stale_files(&dest, ctx)?);
+        }
+        Ok(())
+    }
+
+    fn diff_dest(
+        &self,
+        src: &PathBuf,
+        dest: &Path,
+        ctx: &Context,
+        report: &mut PackageReport,
+    ) -> Result<(), anyhow::Error> {
+        if src.is_dir() {
+            let subpaths = self.subpath_dests(ctx)?;
+            // Recursively diff directory contents
+            for entry in walkdir::WalkDir::new(src) {
+                let entry = entry?;
+                let relative_path = entry.path().strip_prefix(src)?;
+                if self.should_ignore(relative_path) {
+                    continue;
+                }
+                let dest_path = place(dest, &self.dest_relative(relative_path), &subpaths);
+                if entry.path().is_file() && entry.file_name() != KEEP_FILE {
+                    self.diff_file(&entry.path().to_path_buf(), &dest_path, ctx, report)?;
+                }
+            }
+        } else {
+            self.diff_file(src, &dest.to_path_buf(), ctx, report)?;
+        }
+        Ok(())
+    }
+
+    /// What to write to `dest` for the rendered `content` of `src`: merged
+    /// into what dest holds with `merge`, spliced into its block with
+    /// `block`, and otherwise the content itself.
+    fn fit_into_dest(&self, src: &Path, content: String, dest: &Path) -> anyhow::Result<String> {
+        if let Some(format) = self.merge {
+            return merge_into_dest(format, src, &content, dest);
+        }
+        if !self.block {
+            return Ok(content);
+        }
+        let existing = match std::fs::read_to_string(dest) {
+            Ok(existing) => Some(existing),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => anyhow::bail!("Failed to read {}: {}", dest.display(), e),
+        };
+        splice_block(
+            existing.as_deref(),
+            &content,
+            &self.name,
+            self.block_comment(),
+        )
+        .map_err(|e| anyhow::anyhow!("{}: {}", dest.display(), e))
+    }
+
+    pub fn block_comment(&self) -> &str {
+        self.block_comment
+            .as_deref()
+            .unwrap_or(DEFAULT_BLOCK_COMMENT)
+    }
+
+    // A block is spliced into one file, a directory src has no single block
+    fn check_block_src(&self, ctx: &Context) -> anyhow::Result<()> {
+        if self.block && self.resolve_src(ctx).is_some_and(|src| src.is_dir()) {
+            anyhow::bail!(
+                "Package '{}' sets block, which only works with a file src, not a directory",
+                self.name
+            );
+        }
+        Ok(())
+    }
+
+    // Every path in `files` must be in a source, one that isn't is likely a typo
+    fn check_listed_files(&self, ctx: &Context) -> anyhow::Result<()> {
+        if self.files.is_empty() {
+            return Ok(());
+        }
+        let srcs = self.resolve_srcs(ctx)?;
+        if !srcs.iter().any(|src| src.is_dir()) {
+            anyhow::bail!(
+                "Package '{}' sets files, which only works with a directory src",
+                self.name
+            );
+        }
+        for file in self.files.iter() {
+            if !srcs.iter().any(|src| src.join(file).is_file()) {
+                anyhow::bail!(
+                    "Package '{}' lists '{}' in files, but its src has no such file",
+                    self.name,
+                    file
+                );
+            }
+        }
+        if self
+            .files
+            .iter()
+            .all(|file| self.matches_ignore(Path::new(file)))
+        {
+            cprintln(
+                &format!(
+                    "Every file listed in files of '{}' is ignored, it deploys nothing",
+                    self.name
+                ),
+                &LogLevel::WARNING,
+            );
+        }
+        Ok(())
+    }
+
+    /// Stop at a dest that is a directory where src is a file, or a file where
+    /// src is a directory, before anything is written. With
+    /// `--replace-mismatched` the dest is moved aside instead: a file to
+    /// `<dest>.dotrbak`, a directory to `<dest>.dotrbak.d`.
+    fn check_dest_kinds(&self, ctx: &Context, report: &mut PackageReport) -> anyhow::Result<()> {
+        let Some(src) = self.resolve_src(ctx) else {
+            return Ok(());
+        };
+        for dest in self.resolve_dests(ctx)? {
+            let Some((expected, found)) = kind_mismatch(&src, &dest) else {
+                continue;
+            };
+            if !ctx.replaces_mismatched() {
+                anyhow::bail!(
+                    "Package '{}' expects a {} at {} but found a {}, deploy with --replace-mismatched to back it up and replace it",
+                    self.name,
+                    expected,
+                    normalize_home_path(&dest.to_string_lossy()),
+                    found
+                );
+            }
+            let backup_path = if dest.is_dir() {
+                let mut backup_path = create_backup_path(&dest).into_os_string();
+                backup_path.push(".d");
+                let backup_path = PathBuf::from(backup_path);
+                if backup_path.symlink_metadata().is_ok() {
+                    anyhow::bail!(
+                        "Can't move {} aside, {} already exists",
+                        dest.display(),
+                        backup_path.display()
+                    );
+                }
+                std::fs::rename(&dest, &backup_path).map_err(|e| {
+                    anyhow::anyhow!("Failed to move {} aside: {}", dest.display(), e)
+                })?;
+                backup_path
+            } else {
+                let backup_path = self.backup_dest(&dest, ctx)?;
+                std::fs::remove_file(&dest)
+                    .map_err(|e| anyhow::anyhow!("Failed to remove {}: {}", dest.display(), e))?;
+                backup_path
+            };
+            cprintln(
+                &format!(
+                    "Replacing the {} at {} with a {}, it was moved to {}",
+                    found,
+                    dest.display(),
+                    expected,
+                    backup_path.display()
+                ),
+                &LogLevel::WARNING,
+            );
+            report.backups.push(backup_path);
+        }
+        Ok(())
+    }
+
+    /// Each file deploying would write and the dest it goes to, in the order
+    /// deploy writes them, rendering nothing.
+    pub fn file_pairs(&self, ctx: &Context) -> Result<Vec<(PathBuf, PathBuf)>, anyhow::Error> {
+        let mut pairs = Vec::new();
+        let subpaths = self.subpath_dests(ctx)?;
+        if self.has_merged_view(ctx) {
+            let merged = self.layered_files(ctx)?;
+            for dest in self.resolve_dests(ctx)? {
+                for (relative_path, src) in merged.iter() {
+                    pairs.push((src.clone(), place(&dest, relative_path, &subpaths)));
+                }
+            }
+            return Ok(pairs);
+        }
+        let Some(src) = self.resolve_src(ctx) else {
+            return Ok(pairs);
+        };
+        for dest in self.resolve_dests(ctx)? {
+            if !src.is_dir() {
+                pairs.push((src.clone(), dest));
+                continue;
+            }
+            for entry in sorted_entries(&src)? {
+                let relative_path = entry.path().strip_prefix(&src)?;
+                if !entry.file_type().is_dir()
+                    && entry.file_name() != KEEP_FILE
+                    && !self.should_ignore(relative_path)
+                {
+                    pairs.push((
+                        entry.path().to_path_buf(),
+                        place(&dest, &self.dest_relative(relative_path), &subpaths),
+                    ));
+                }
+            }
+        }
+        Ok(pairs)
+    }
+
+    /// What deploying `src` writes to `dest`: rendered when it is a template
+    /// and merged into dest with `merge`, or its bytes as they are when it
+    /// isn't UTF-8.
+    pub fn render_file(&self, src: &Path, dest: &Path, ctx: &Context) -> anyhow::Result<Vec<u8>> {
+        let src_content = match std::fs::read_to_string(src) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData && is_tera_file(src) => {
+                anyhow::bail!("Template {} is not valid UTF-8", src.display())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                return std::fs::read(src)
+                    .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", src.display(), e));
+            }
+            Err(e) => anyhow::bail!("Failed to read {}: {}", src.display(), e),
+        };
+        let compiled_content = if self.renders(src, &src_content) {
+            self.render(src, &src_content, ctx)?
+        } else {
+            src_content
+        };
+        let compiled_content = self.fit_into_dest(src, compiled_content, dest)?;
+        Ok(compiled_content.into_bytes())
+    }
+
+    /// Add what deploying the package would do with each of its files to
+    /// `plan`, touching no dest.
+    pub fn plan(&self, ctx: &Context, plan: &mut Plan) -> Result<(), anyhow::Error> {
+        self.check_dest_locations(ctx)?;
+        self.check_block_src(ctx)?;
+        self.ensure_remote(ctx)?;
+        self.check_listed_files(ctx)?;
+        self.check_src_limits(ctx)?;
+        for (src, dest) in self.file_pairs(ctx)? {
+            let content = self.render_file(&src, &dest, ctx)?;
+            let replaces = if dest.is_file() {
+                Some(sha256_file(&dest)?)
+            } else {
+                None
+            };
+            let hash = sha256_bytes(&content);
+            let action = match &replaces {
+                None => PlanAction::Create,
+                Some(current) if *current == hash && !ctx.is_forced() => PlanAction::Skip,
+                Some(_) => PlanAction::Update,
+            };
+            plan.files.push(PlannedFile {
+                package: self.name.clone(),
+                src,
+                dest,
+                action,
+                hash,
+                replaces,
+            });
+        }
+        Ok(())
+    }
+
+    /// Write `content`, rendered and checked by `deploy --apply`, over
+    /// `dest` as deploy would, backing up what was there.
+    pub fn apply_planned_file(
+        &self,
+        src: &Path,
+        dest: &Path,
+        content: &[u8],
+        ctx: &Context,
+        report: &mut PackageReport,
+    ) -> Result<(), anyhow::Error> {
+        self.unlink_dest(dest, ctx)?;
+        self.record_state(dest, content, ctx);
+        if dest.exists() {
+            report.backups.push(self.backup_dest(dest, ctx)?);
+        }
+        self.create_parent_dirs(dest, ctx)?;
+        write_atomic(dest, content, self.target_mode(src))
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", dest.display(), e))?;
+        self.apply_file_attrs(src, dest)?;
+        cprintln(&format!("Deployed to {}", dest.display()), &LogLevel::DEBUG);
+        report.deployed.push(dest.to_path_buf());
+        Ok(())
+    }
+
+    pub fn deploy_file(
+        &self,
+        src: &PathBuf,
+        dest: &Path,
+        ctx: &Context,
+        backup: bool,
+        report: &mut PackageReport,
+    ) -> Result<(), anyhow::Error> {
+        self.unlink_dest(dest, ctx)?;
+        // Nothing changed since the last deploy, skip reading and rendering
+        if !ctx.is_forced() && self.is_cached(src, dest, ctx) {
+            cprintln(
+                &format!("Skipping {}, unchanged since last deploy", dest.display()),
+                &LogLevel::TRACE,
+            );
+            if self.apply_file_attrs(src, dest)? {
+                cprintln(
+                    &format!("Updated permissions only for {}", dest.display()),
+                    &LogLevel::INFO,
+                );
+            }
+            if let Some(hash) = ctx
+                .with_cache(|cache| cache.get(dest).map(|e| e.content_hash.clone()))
+                .flatten()
+            {
+                ctx.with_state(|state| state.record_file(&self.name, dest, hash));
+            }
+            report.skipped.push(dest.to_path_buf());
+            return Ok(());
+        }
+        let src_content = match std::fs::read_to_string(src) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData && is_tera_file(src) => {
+                anyhow::bail!("Template {} is not valid UTF-8", src.display())
+            }
+            // Not UTF-8, so a binary file
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                return self.deploy_binary_file(src, dest, ctx, backup, report);
+            }
+            Err(e) => anyhow::bail!("Failed to read {}: {}", src.display(), e),
+        };
+        let templated = self.renders(src, &src_content);
+        let io_calls = ctx.template_io_calls();
+        let compiled_content = if templated {
+            self.render(src, &src_content, ctx)?
+        } else {
+            src_content
+        };
+        // What read_file or cmd returned can change while src doesn't
+        let cacheable = ctx.template_io_calls() == io_calls;
+        let compiled_content = self.fit_into_dest(src, compiled_content, dest)?;
+
+        // Compare before touching dest, so a no-op deploy leaves its mtime alone
+        let unchanged = content_matches(dest, compiled_content.as_bytes())?;
+        if !unchanged
+            && !self.resolve_conflict(src, dest, compiled_content.as_bytes(), templated, ctx)?
+        {
+            report.skipped.push(dest.to_path_buf());
+            return Ok(());
+        }
+        if cacheable {
+            self.record_cache(src, dest, templated, compiled_content.as_bytes(), ctx);
+        } else {
+            ctx.with_cache(|cache| cache.remove(dest));
+        }
+        self.record_state(dest, compiled_content.as_bytes(), ctx);
+        if unchanged && !ctx.is_forced() {
+            report.skipped.push(dest.to_path_buf());
+            return self.skip_up_to_date(src, dest);
+        }
+        if backup && dest.exists() {
+            report.backups.push(self.backup_dest(dest, ctx)?);
+        }
+        self.create_parent_dirs(dest, ctx)?;
+        write_atomic(dest, compiled_content.as_bytes(), self.target_mode(src))
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", dest.display(), e))?;
+        self.apply_file_attrs(src, dest)?;
+        cprintln(&format!("Deployed to {}", dest.display()), &LogLevel::DEBUG);
+        report.deployed.push(dest.to_path_buf());
+        Ok(())
+    }
+
+    // Binary files are copied as-is, and only when their bytes differ
+    fn deploy_binary_file(
+        &self,
+        src: &Path,
+        dest: &Path,
+        ctx: &Context,
+        backup: bool,
+        report: &mut PackageReport,
     ) -> Result<(), anyhow::Error> {
-        if let Ok(src_content) = std::fs::read_to_string(src) {
-            let compiled_content = if is_templated_str(&src_content) {
-                compile_string(&src_content, &self.get_context_variables(ctx))?
-            } else {
-                src_content
-            };
+        let src_content = std::fs::read(src)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", src.display(), e))?;
+        let unchanged = content_matches(dest, &src_content)?;
+        if !unchanged && !self.resolve_conflict(src, dest, &src_content, false, ctx)? {
+            report.skipped.push(dest.to_path_buf());
+            return Ok(());
+        }
+        self.record_cache(src, dest, false, &src_content, ctx);
+        self.record_state(dest, &src_content, ctx);
+        if unchanged && !ctx.is_forced() {
+            report.skipped.push(dest.to_path_buf());
+            return self.skip_up_to_date(src, dest);
+        }
+        if backup && dest.exists() {
+            report.backups.push(self.backup_dest(dest, ctx)?);
+        }
+        self.create_parent_dirs(dest, ctx)?;
+        copy_atomic(
+            src,
+            dest,
+            &src_content,
+            self.target_mode(src),
+            ctx.uses_reflink(),
+        )
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", dest.display(), e))?;
+        self.apply_file_attrs(src, dest)?;
+        cprintln(&format!("Deployed to {}", dest.display()), &LogLevel::DEBUG);
+        report.deployed.push(dest.to_path_buf());
+        Ok(())
+    }
 
-            let mut should_diff = false;
-            if dest.exists() {
-                let existing_content = std::fs::read_to_string(dest).unwrap_or_default();
-                if existing_content != compiled_content {
-                    should_diff = true;
+    /// With `--interactive`, ask what to do about a dest that was edited since
+    /// the last deploy (or, without a cache entry, that differs at all).
+    /// Otherwise keep a dest that looks edited after its src was, see
+    /// `keeps_newer_dest`. Returns whether `content` should be written.
+    fn resolve_conflict(
+        &self,
+        src: &Path,
+        dest: &Path,
+        content: &[u8],
+        templated: bool,
+        ctx: &Context,
+    ) -> Result<bool, anyhow::Error> {
+        if !dest.exists() {
+            return Ok(true);
+        }
+        if !ctx.is_interactive() {
+            return Ok(!self.keeps_newer_dest(src, dest, ctx));
+        }
+        let local = std::fs::read(dest)?;
+        let deployed_hash = ctx
+            .with_cache(|cache| cache.get(dest).map(|e| e.content_hash.clone()))
+            .flatten();
+        let reason = match deployed_hash {
+            Some(hash) if hash == sha256_bytes(&local) => return Ok(true),
+            Some(_) => "was edited since the last deploy",
+            None => "differs from the source",
+        };
+        // Copying dest back would flatten a template or pull in unmanaged keys
+        let can_merge = !templated && self.merge.is_none() && !self.block;
+        let choices = if can_merge {
+            "[k]eep local, [o]verwrite, [m]erge into source, [d]iff"
+        } else {
+            "[k]eep local, [o]verwrite, [d]iff"
+        };
+        cprintln(
+            &format!("Conflict: {} {}", dest.display(), reason),
+            &LogLevel::WARNING,
+        );
+        loop {
+            match prompt(choices)?.to_lowercase().as_str() {
+                "k" | "keep" => {
+                    cprintln(&format!("Kept local {}", dest.display()), &LogLevel::INFO);
+                    return Ok(false);
                 }
-                if !should_diff {
-                    cprintln(
-                        &format!(
-                            "No changes in {}",
-                            src.file_name().unwrap_or_default().to_string_lossy()
-                        ),
-                        &LogLevel::INFO,
-                    );
-                } else {
+                "o" | "overwrite" => return Ok(true),
+                "m" | "merge" if can_merge => {
+                    std::fs::copy(dest, src).map_err(|e| {
+                        anyhow::anyhow!(
+                            "Failed to copy {} to {}: {}",
+                            dest.display(),
+                            src.display(),
+                            e
+                        )
+                    })?;
                     cprintln(
-                        &format!(
-                            "Changes in {} -> {}:",
-                            src.file_name().unwrap_or_default().to_string_lossy(),
-                            dest.display()
-                        ),
+                        &format!("Copied {} back to {}", dest.display(), src.display()),
                         &LogLevel::INFO,
                     );
-                    // Print line-by-line diff, with - for removed lines, + for added lines, and space for unchanged lines. Add colors if possible.
-                    for diff in diff::lines(&existing_content, &compiled_content) {
-                        match diff {
-                            diff::Result::Left(l) => {
-                                let s = format!("-{}", l);
-                                print_with_color(s.as_str(), RED);
-                            }
-                            diff::Result::Both(l, _) => {
-                                println!(" {}", l);
-                            }
-                            diff::Result::Right(r) => {
-                                let s = format!("+{}", r);
-                                print_with_color(s.as_str(), GREEN);
-                            }
-                        };
-                    }
+                    return Ok(false);
                 }
+                "d" | "diff" => match (std::str::from_utf8(&local), std::str::from_utf8(content)) {
+                    (Ok(old), Ok(new)) => print_line_diff(old, new),
+                    _ => println!("Binary files differ"),
+                },
+                other => cprintln(&format!("Unknown choice '{}'", other), &LogLevel::WARNING),
             }
         }
-        Ok(())
     }
 
-    pub fn diff(&self, ctx: &Context) -> Result<(), anyhow::Error> {
-        let src = resolve_path(&self.src, &ctx.working_dir);
-        let dest = self.resolve_dest(ctx);
-        if src.is_dir() {
-            // Recursively diff directory contents
-            for entry in walkdir::WalkDir::new(&src) {
-                let entry = entry?;
-                let relative_path = entry.path().strip_prefix(&src)?;
-                if self.should_ignore(relative_path) {
+    // Whether a dest that differs from what deploy would write was modified
+    // more recently than its src, so writing it may lose local changes. A
+    // dest still as the last deploy wrote it is never kept, however stale
+    // its src, nor one the package only merges or splices into. --force and
+    // overwrite_newer write it anyway.
+    fn keeps_newer_dest(&self, src: &Path, dest: &Path, ctx: &Context) -> bool {
+        if ctx.is_forced() || self.overwrite_newer || self.merge.is_some() || self.block {
+            return false;
+        }
+        let key = dest.to_string_lossy().to_string();
+        let written = ctx
+            .with_state(|state| {
+                state
+                    .package(&self.name)
+                    .and_then(|package| package.files.get(&key).cloned())
+            })
+            .flatten()
+            .or_else(|| {
+                ctx.with_cache(|cache| cache.get(dest).map(|e| e.content_hash.clone()))
+                    .flatten()
+            });
+        if written.is_some_and(|hash| std::fs::read(dest).is_ok_and(|c| sha256_bytes(&c) == hash)) {
+            return false;
+        }
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let (Some(src_time), Some(dest_time)) = (modified(src), modified(dest)) else {
+            return false;
+        };
+        if dest_time <= src_time + MTIME_SKEW {
+            return false;
+        }
+        cprintln(
+            &format!(
+                "{} was modified more recently than its source, local changes may be lost. Run 'dotr update' first, or deploy with --force to overwrite it",
+                normalize_home_path(&dest.to_string_lossy())
+            ),
+            &LogLevel::WARNING,
+        );
+        true
+    }
+
+    // A dest is up to date when its src mtime, the variables it was rendered
+    // with, and its own content all match what the cache recorded.
+    fn is_cached(&self, src: &Path, dest: &Path, ctx: &Context) -> bool {
+        ctx.with_cache(|cache| {
+            let Some(entry) = cache.get(dest) else {
+                return false;
+            };
+            if file_mtime(src).as_ref() != Some(&entry.src_mtime) {
+                return false;
+            }
+            if let Some(vars_hash) = &entry.vars_hash
+                && *vars_hash != self.render_hash(ctx)
+            {
+                return false;
+            }
+            match std::fs::read(dest) {
+                Ok(content) => sha256_bytes(&content) == entry.content_hash,
+                Err(_) => false,
+            }
+        })
+        .unwrap_or(false)
+    }
+
+    fn record_cache(
+        &self,
+        src: &Path,
+        dest: &Path,
+        templated: bool,
+        content: &[u8],
+        ctx: &Context,
+    ) {
+        let Some(src_mtime) = file_mtime(src) else {
+            return;
+        };
+        let vars_hash = templated.then(|| self.render_hash(ctx));
+        ctx.with_cache(|cache| {
+            cache.insert(
+                dest,
+                CacheEntry {
+                    src_mtime,
+                    vars_hash,
+                    content_hash: sha256_bytes(content),
+                },
+            )
+        });
+    }
+
+    // What a rendered dest depends on besides its src: the variables, and
+    // whether they were escaped
+    fn render_hash(&self, ctx: &Context) -> String {
+        let vars_hash = hash_variables(&self.context_variables(ctx));
+        if self.autoescape {
+            return sha256_bytes(format!("autoescape:{}", vars_hash).as_bytes());
+        }
+        vars_hash
+    }
+
+    // Note in the deploy state that this package owns dest. A merged dest
+    // also holds keys the user manages, and a block dest lines around the
+    // block, so clean must never remove either
+    fn record_state(&self, dest: &Path, content: &[u8], ctx: &Context) {
+        if self.merge.is_some() || self.block {
+            return;
+        }
+        ctx.with_state(|state| state.record_file(&self.name, dest, sha256_bytes(content)));
+    }
+
+    /// Copy `dest` aside before deploy overwrites or removes it, and return
+    /// where it went: beside it as `<dest>.dotrbak`, or with `backup_dir` set,
+    /// to `<backup_dir>/<package>/<dest path>/<timestamp>`. The dest path is
+    /// taken from the home directory when it is under it.
+    fn backup_dest(&self, dest: &Path, ctx: &Context) -> Result<PathBuf, anyhow::Error> {
+        let Some(dir) = self.backup_tree(dest, ctx) else {
+            let backup_path = create_backup_path(dest);
+            copy_to_backup(dest, &backup_path)?;
+            return Ok(backup_path);
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        // Two backups in the same second both stay
+        let mut backup_path = dir.join(now.to_string());
+        let mut count = 0;
+        while backup_path.symlink_metadata().is_ok() {
+            count += 1;
+            backup_path = dir.join(format!("{}-{}", now, count));
+        }
+        copy_to_backup(dest, &backup_path)?;
+        Ok(backup_path)
+    }
+
+    // The directory holding the backups of `dest`, None without a backup_dir
+    fn backup_tree(&self, dest: &Path, ctx: &Context) -> Option<PathBuf> {
+        let backup_dir = ctx.backup_dir()?;
+        let dest = normalize_path(dest);
+        let relative_path = std::env::home_dir()
+            .and_then(|home| dest.strip_prefix(normalize_path(&home)).ok())
+            .unwrap_or(&dest);
+        let mut dir = backup_dir.join(&self.name);
+        dir.extend(
+            relative_path
+                .components()
+                .filter(|c| matches!(c, std::path::Component::Normal(_))),
+        );
+        Some(dir)
+    }
+
+    /// The newest backup of `dest`: the last one under `backup_dir` when it
+    /// is set, otherwise, or when it has none, `<dest>.dotrbak` if it exists.
+    pub fn latest_backup(
+        &self,
+        dest: &Path,
+        ctx: &Context,
+    ) -> Result<Option<PathBuf>, anyhow::Error> {
+        if let Some(dir) = self.backup_tree(dest, ctx).filter(|dir| dir.is_dir()) {
+            let mut latest: Option<((u64, u64), PathBuf)> = None;
+            for entry in std::fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if !path.is_file() {
                     continue;
                 }
-                let dest_path = dest.join(relative_path);
-                if entry.path().is_file() {
-                    self.diff_file(&entry.path().to_path_buf(), &dest_path, ctx)?;
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let (secs, count) = name.split_once('-').unwrap_or((&name, "0"));
+                let (Ok(secs), Ok(count)) = (secs.parse::<u64>(), count.parse::<u64>()) else {
+                    continue;
+                };
+                if latest.as_ref().is_none_or(|(key, _)| *key < (secs, count)) {
+                    latest = Some(((secs, count), path));
                 }
             }
-        } else {
-            self.diff_file(&src, &dest, ctx)?;
+            if let Some((_, path)) = latest {
+                return Ok(Some(path));
+            }
         }
-        Ok(())
+        let sibling = create_backup_path(dest);
+        Ok(sibling.is_file().then_some(sibling))
     }
 
-    pub fn deploy_file(
+    /// Undo a failed deploy of a package that was just imported: copy the
+    /// dotfiles/ copy back over its dest, or when that copy was turned into a
+    /// template, move back the backups of the files that were written.
+    pub fn restore_imported(
         &self,
-        src: &PathBuf,
-        dest: &PathBuf,
         ctx: &Context,
-        backup: bool,
+        report: &PackageReport,
+        templated: bool,
     ) -> Result<(), anyhow::Error> {
-        if let Ok(src_content) = std::fs::read_to_string(src) {
-            let compiled_content = if is_templated_str(&src_content) {
-                compile_string(&src_content, &self.get_context_variables(ctx))?
+        if templated {
+            for dest in report.deployed.iter() {
+                if let Some(backup_path) = self.latest_backup(dest, ctx)? {
+                    std::fs::copy(&backup_path, dest)?;
+                    std::fs::remove_file(&backup_path)?;
+                    cprintln(&format!("Restored {}", dest.display()), &LogLevel::INFO);
+                }
+            }
+            return Ok(());
+        }
+        let (Some(src), Some(dest)) = (
+            self.resolve_src(ctx),
+            self.resolve_dests(ctx)?.into_iter().next(),
+        ) else {
+            return Ok(());
+        };
+        for entry in walkdir::WalkDir::new(&src) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let dest_path = if src.is_dir() {
+                self.resolve_dest_for(&dest, entry.path().strip_prefix(&src)?, ctx)?
             } else {
-                src_content
+                dest.clone()
             };
+            let content = std::fs::read(entry.path())?;
+            if content_matches(&dest_path, &content)? {
+                continue;
+            }
+            create_parent_dirs(&dest_path)?;
+            std::fs::write(&dest_path, content)
+                .map_err(|e| anyhow::anyhow!("Failed to restore {}: {}", dest_path.display(), e))?;
+            copy_permissions(entry.path(), &dest_path)?;
+            cprintln(
+                &format!("Restored {}", dest_path.display()),
+                &LogLevel::INFO,
+            );
+        }
+        Ok(())
+    }
+
+    /// Create the missing parents of `dest`, recording them as deployed and
+    /// applying `dir_mode` and the owner to each.
+    fn create_parent_dirs(&self, dest: &Path, ctx: &Context) -> Result<(), anyhow::Error> {
+        let Some(parent) = dest.parent() else {
+            return Ok(());
+        };
+        let missing = missing_dirs(parent);
+        self.record_created_dirs(parent, ctx);
+        create_parent_dirs(dest)?;
+        for dir in missing.iter().rev() {
+            self.apply_dir_attrs(dir)?;
+        }
+        Ok(())
+    }
+
+    /// Set `file_mode`, or else the mode of `src`, and the owner on a
+    /// deployed file. Returns true if its mode was changed.
+    // The mode apply_file_attrs leaves a dest of `src` with
+    fn target_mode(&self, src: &Path) -> Option<u32> {
+        self.file_mode.or_else(|| file_mode_of(src))
+    }
+
+    fn apply_file_attrs(&self, src: &Path, dest: &Path) -> Result<bool, anyhow::Error> {
+        let changed = match self.file_mode {
+            Some(mode) => set_mode(dest, mode)?,
+            None => copy_permissions(src, dest)?,
+        };
+        self.apply_owner(dest)?;
+        Ok(changed)
+    }
+
+    fn apply_dir_attrs(&self, dir: &Path) -> Result<(), anyhow::Error> {
+        if let Some(mode) = self.dir_mode {
+            set_mode(dir, mode)?;
+        }
+        self.apply_owner(dir)
+    }
 
-            let mut should_copy = false;
-            if !dest.exists() {
-                should_copy = true;
+    #[cfg(unix)]
+    fn apply_owner(&self, path: &Path) -> Result<(), anyhow::Error> {
+        use std::os::unix::fs::MetadataExt;
+
+        if self.owner.is_none() && self.group.is_none() {
+            return Ok(());
+        }
+        let uid = self
+            .owner
+            .as_deref()
+            .map(|o| lookup_id("/etc/passwd", "user", o))
+            .transpose()?;
+        let gid = self
+            .group
+            .as_deref()
+            .map(|g| lookup_id("/etc/group", "group", g))
+            .transpose()?;
+        let metadata = std::fs::symlink_metadata(path)?;
+        if uid.is_none_or(|u| u == metadata.uid()) && gid.is_none_or(|g| g == metadata.gid()) {
+            return Ok(());
+        }
+        std::os::unix::fs::chown(path, uid, gid).map_err(|e| {
+            let hint = if e.kind() == std::io::ErrorKind::PermissionDenied {
+                " (changing the owner needs root, run dotr with sudo)"
             } else {
-                let existing_content = std::fs::read_to_string(dest)?;
-                if existing_content != compiled_content {
-                    should_copy = true;
+                ""
+            };
+            anyhow::anyhow!(
+                "Failed to change the owner of {} for package '{}': {}{}",
+                path.display(),
+                self.name,
+                e,
+                hint
+            )
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn apply_owner(&self, _path: &Path) -> Result<(), anyhow::Error> {
+        if self.owner.is_some() || self.group.is_some() {
+            anyhow::bail!(
+                "Package '{}': owner and group are only supported on Unix",
+                self.name
+            );
+        }
+        Ok(())
+    }
+
+    fn skip_up_to_date(&self, src: &Path, dest: &Path) -> anyhow::Result<()> {
+        cprintln(
+            &format!("Skipping {}, already up to date", dest.display()),
+            &LogLevel::TRACE,
+        );
+        if self.apply_file_attrs(src, dest)? {
+            cprintln(
+                &format!("Updated permissions only for {}", dest.display()),
+                &LogLevel::INFO,
+            );
+        }
+        Ok(())
+    }
+
+    /// Writing to a symlinked dest would change whatever it points to. A
+    /// dangling link is removed; a live one is refused unless symlinks may be
+    /// overwritten, in which case the target is backed up and the link removed.
+    fn unlink_dest(&self, dest: &Path, ctx: &Context) -> Result<(), anyhow::Error> {
+        let is_symlink = std::fs::symlink_metadata(dest)
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false);
+        if !is_symlink {
+            return Ok(());
+        }
+        let target = std::fs::read_link(dest)?;
+        let remove_link = || {
+            std::fs::remove_file(dest)
+                .or_else(|_| std::fs::remove_dir(dest))
+                .map_err(|e| anyhow::anyhow!("Failed to remove symlink {}: {}", dest.display(), e))
+        };
+        let Ok(real) = std::fs::canonicalize(dest) else {
+            remove_link()?;
+            cprintln(
+                &format!(
+                    "Removed dangling symlink {} -> {}",
+                    dest.display(),
+                    target.display()
+                ),
+                &LogLevel::INFO,
+            );
+            return Ok(());
+        };
+        if !ctx.overwrites_symlinks() {
+            anyhow::bail!(
+                "{} is a symlink to {}, refusing to write through it (pass --overwrite-symlinks to replace the link)",
+                dest.display(),
+                target.display()
+            );
+        }
+        // The target itself is never written to, the backup is only in case
+        // the link was what kept it in use
+        if real.is_file() {
+            self.backup_dest(&real, ctx)?;
+        }
+        remove_link()?;
+        cprintln(
+            &format!("Replaced symlink {} -> {}", dest.display(), real.display()),
+            &LogLevel::WARNING,
+        );
+        Ok(())
+    }
+
+    // Note in the deploy state each missing directory up to `dir`, before
+    // it is created, so clean can remove them again
+    fn record_created_dirs(&self, dir: &Path, ctx: &Context) {
+        ctx.with_state(|state| {
+            for ancestor in dir.ancestors() {
+                if ancestor.as_os_str().is_empty() || ancestor.exists() {
+                    break;
                 }
+                state.record_dir(&self.name, ancestor);
             }
-            if !should_copy {
-                return Ok(());
-            }
-            if backup && dest.exists() {
-                let backup_path = create_backup_path(dest);
-                std::fs::copy(dest, &backup_path)?;
+        });
+    }
+
+    /// Deploy the package by copying files from src to dest, between its
+    /// pre and post actions. `steps` leaves out either the files or the actions.
+    /// What was done goes in `report`, also when an error stops it halfway.
+    pub fn deploy(
+        &self,
+        ctx: &Context,
+        steps: DeploySteps,
+        report: &mut PackageReport,
+    ) -> Result<(), anyhow::Error> {
+        let run_actions = steps != DeploySteps::FilesOnly;
+        let actions = self.pre_actions.len() + self.post_actions.len();
+        if !run_actions && actions > 0 {
+            cprintln(
+                &format!("Skipping {} action(s) of '{}'", actions, self.name),
+                &LogLevel::INFO,
+            );
+        }
+        let _interrupts = catch_interrupts();
+        self.check_interrupt(ctx, 0)?;
+        // Fail before the pre actions when the src can't be downloaded
+        if steps != DeploySteps::ActionsOnly {
+            self.check_dest_locations(ctx)?;
+            self.check_block_src(ctx)?;
+            self.ensure_remote(ctx)?;
+            self.check_listed_files(ctx)?;
+            self.check_src_limits(ctx)?;
+            self.check_dest_kinds(ctx, report)?;
+        }
+        if run_actions {
+            self.execute_pre_actions(ctx, report)?;
+        }
+        if steps != DeploySteps::ActionsOnly {
+            if !self.ignore.is_empty() {
+                cprintln(
+                    &format!(
+                        "Ignore patterns of '{}': {}",
+                        self.name,
+                        self.ignore.join(", ")
+                    ),
+                    &LogLevel::DEBUG,
+                );
             }
-            std::fs::write(dest, compiled_content)?;
-        } else {
-            // It can be a binary file, copy as-is and return Ok
-            if backup && dest.exists() {
-                let backup_path = create_backup_path(dest);
-                std::fs::copy(dest, &backup_path)?;
+            if self.has_merged_view(ctx) {
+                let srcs = self.resolve_srcs(ctx)?;
+                let merged = self.layered_files(ctx)?;
+                for copy_to in self.resolve_dests(ctx)? {
+                    self.deploy_layered(&merged, &copy_to, ctx, report)?;
+                    if !merged.contains_key(Path::new("")) {
+                        self.deploy_kept_dirs(&srcs, &copy_to, ctx)?;
+                    }
+                    self.remove_stale_files(&copy_to, ctx, report)?;
+                    if self.prune_dest && copy_to.is_dir() {
+                        self.prune_where(&copy_to, ctx, |relative| merged.contains_key(relative))?;
+                    }
+                }
+            } else if let Some(copy_from) = self.resolve_src(ctx) {
+                for copy_to in self.resolve_dests(ctx)? {
+                    self.deploy_files(&copy_from, &copy_to, ctx, report)?;
+                    if copy_from.is_dir() {
+                        self.deploy_kept_dirs(std::slice::from_ref(&copy_from), &copy_to, ctx)?;
+                        self.remove_stale_files(&copy_to, ctx, report)?;
+                    }
+                    if self.prune_dest && copy_from.is_dir() && copy_to.is_dir() {
+                        self.prune(std::slice::from_ref(&copy_from), &copy_to, ctx)?;
+                    }
+                }
             }
-            std::fs::copy(src, dest)?;
-            return Ok(());
+            // Files that failed keep what the state knew of them, for clean
+            self.failed_files_error(
+                report,
+                "deployed",
+                report.deployed.len() + report.skipped.len(),
+            )?;
+            ctx.with_state(|state| state.prune_package(&self.name));
+            cprintln(
+                &format!(
+                    "Package '{}' deployed: {} file(s) written, {} unchanged",
+                    self.name,
+                    report.deployed.len(),
+                    report.skipped.len()
+                ),
+                &LogLevel::DEBUG,
+            );
+        }
+        if run_actions {
+            self.execute_post_actions(ctx, report)?;
         }
-        cprintln(&format!("Deployed to {}", dest.display()), &LogLevel::INFO);
         Ok(())
     }
 
-    /// Deploy the package by copying files from src to dest.
-    pub fn deploy(&self, ctx: &Context) -> Result<(), anyhow::Error> {
-        self.execute_pre_actions(ctx)?;
-        let copy_from = resolve_path(&self.src, &ctx.working_dir);
-        let copy_to = self.resolve_dest(ctx);
+    fn deploy_files(
+        &self,
+        copy_from: &PathBuf,
+        copy_to: &Path,
+        ctx: &Context,
+        report: &mut PackageReport,
+    ) -> Result<(), anyhow::Error> {
         if copy_from.is_dir() {
+            let entries = sorted_entries(copy_from)?;
+            let files = entries
+                .iter()
+                .filter(|entry| !entry.file_type().is_dir())
+                .count();
+            let progress = Progress::new(files, "files");
+            // Nothing is written under a directory that failed, it may lead elsewhere
+            let mut failed_dirs: Vec<PathBuf> = Vec::new();
+            let subpaths = self.subpath_dests(ctx)?;
             // Recursively copy directory contents
-            for entry in walkdir::WalkDir::new(&copy_from) {
-                let entry = entry?;
-                let relative_path = entry.path().strip_prefix(&copy_from)?;
+            for entry in entries {
+                self.check_interrupt(ctx, report.deployed.len() + report.skipped.len())?;
+                if !entry.file_type().is_dir() {
+                    progress.set_message(&entry.path().strip_prefix(copy_from)?.to_string_lossy());
+                    progress.inc();
+                }
+                let relative_path = entry.path().strip_prefix(copy_from)?;
                 if self.should_ignore(relative_path) {
+                    cprintln(
+                        &format!("Ignoring {}", entry.path().display()),
+                        &LogLevel::DEBUG,
+                    );
+                    continue;
+                }
+                if failed_dirs.iter().any(|dir| relative_path.starts_with(dir)) {
                     continue;
                 }
-                let dest_path = copy_to.join(relative_path);
+                let dest_path = place(copy_to, relative_path, &subpaths);
                 if entry.path().is_dir() {
-                    std::fs::create_dir_all(&dest_path)?;
-                } else {
-                    self.deploy_file(&entry.path().to_path_buf(), &dest_path, ctx, true)?;
+                    // Everything under a directory at max_depth is past it
+                    if self
+                        .max_depth
+                        .is_some_and(|depth| relative_path.components().count() >= depth)
+                    {
+                        continue;
+                    }
+                    let created = self.deploy_dir(&dest_path, ctx);
+                    if created.is_err() {
+                        failed_dirs.push(relative_path.to_path_buf());
+                    }
+                    self.attempt_file(ctx, &dest_path, created, report)?;
+                } else if entry.file_name() != KEEP_FILE {
+                    let dest_path = place(copy_to, &self.dest_relative(relative_path), &subpaths);
+                    let deployed = self.deploy_file(
+                        &entry.path().to_path_buf(),
+                        &dest_path,
+                        ctx,
+                        true,
+                        report,
+                    );
+                    self.attempt_file(ctx, &dest_path, deployed, report)?;
                 }
             }
         } else {
-            self.deploy_file(&copy_from, &copy_to, ctx, true)?;
+            let deployed = self.deploy_file(copy_from, copy_to, ctx, true, report);
+            self.attempt_file(ctx, copy_to, deployed, report)?;
+        }
+        Ok(())
+    }
+
+    // Create a directory of the src at dest
+    fn deploy_dir(&self, dest_path: &Path, ctx: &Context) -> Result<(), anyhow::Error> {
+        self.unlink_dest(dest_path, ctx)?;
+        let missing = missing_dirs(dest_path);
+        self.record_created_dirs(dest_path, ctx);
+        std::fs::create_dir_all(dest_path)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", dest_path.display(), e))?;
+        // The package's own dirs follow dir_mode even when they existed
+        for dir in missing.iter().skip(1).rev() {
+            self.apply_dir_attrs(dir)?;
+        }
+        self.apply_dir_attrs(dest_path)
+    }
+
+    // Create the kept directories under `copy_to`, recorded like the ones
+    // deploy creates for files so clean removes them once empty
+    fn deploy_kept_dirs(
+        &self,
+        srcs: &[PathBuf],
+        copy_to: &Path,
+        ctx: &Context,
+    ) -> Result<(), anyhow::Error> {
+        let subpaths = self.subpath_dests(ctx)?;
+        for dir in self.kept_dirs(srcs)? {
+            let dest_dir = place(copy_to, &dir, &subpaths);
+            let missing = missing_dirs(&dest_dir);
+            self.record_created_dirs(&dest_dir, ctx);
+            std::fs::create_dir_all(&dest_dir)?;
+            for created in missing.iter().rev() {
+                self.apply_dir_attrs(created)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Each file of the merged view comes from the last source that has it
+    fn deploy_layered(
+        &self,
+        merged: &BTreeMap<PathBuf, PathBuf>,
+        copy_to: &Path,
+        ctx: &Context,
+        report: &mut PackageReport,
+    ) -> Result<(), anyhow::Error> {
+        let progress = Progress::new(merged.len(), "files");
+        let subpaths = self.subpath_dests(ctx)?;
+        for (relative_path, src) in merged.iter() {
+            self.check_interrupt(ctx, report.deployed.len() + report.skipped.len())?;
+            progress.set_message(&relative_path.to_string_lossy());
+            progress.inc();
+            let dest_path = place(copy_to, relative_path, &subpaths);
+            let deployed = self.deploy_file(src, &dest_path, ctx, true, report);
+            self.attempt_file(ctx, &dest_path, deployed, report)?;
+        }
+        Ok(())
+    }
+
+    /// The files listed in `removed` that are still under `dest`, left there
+    /// by a deploy from before they were deleted from the repository. Those
+    /// a source of the package has again are not stale.
+    pub fn stale_files(&self, dest: &Path, ctx: &Context) -> Result<Vec<PathBuf>, anyhow::Error> {
+        if self.removed.is_empty() || !dest.is_dir() {
+            return Ok(Vec::new());
+        }
+        let merged = self.merged_sources(&self.resolve_srcs(ctx)?, ctx)?;
+        let subpaths = self.subpath_dests(ctx)?;
+        Ok(self
+            .removed
+            .iter()
+            .map(PathBuf::from)
+            .filter(|relative_path| !merged.contains_key(relative_path))
+            .map(|relative_path| place(dest, &relative_path, &subpaths))
+            .filter(|path| path.symlink_metadata().is_ok_and(|m| !m.is_dir()))
+            .collect())
+    }
+
+    // Delete the stale files under `copy_to`, backing each up first as
+    // deploy does before overwriting
+    fn remove_stale_files(
+        &self,
+        copy_to: &Path,
+        ctx: &Context,
+        report: &mut PackageReport,
+    ) -> Result<(), anyhow::Error> {
+        for path in self.stale_files(copy_to, ctx)? {
+            let backup = if path.is_file() && !path.is_symlink() {
+                self.backup_dest(&path, ctx).map(Some)
+            } else {
+                Ok(None)
+            };
+            let removed = backup.and_then(|backup| {
+                std::fs::remove_file(&path)
+                    .map_err(|e| anyhow::anyhow!("Failed to remove {}: {}", path.display(), e))?;
+                Ok(backup)
+            });
+            if let Ok(backup) = &removed {
+                cprintln(
+                    &format!(
+                        "Removed {}, it was deleted from '{}'",
+                        path.display(),
+                        self.name
+                    ),
+                    &LogLevel::INFO,
+                );
+                report.backups.extend(backup.clone());
+                report.removed.push(path.clone());
+            }
+            let removed = removed.map(|_| ());
+            self.attempt_file(ctx, &path, removed, report)?;
+        }
+        Ok(())
+    }
+
+    /// The entries of `removed` that are back in the package's sources, as
+    /// far as can be told without rendering: templated sources are skipped.
+    pub fn revived_removals(&self, cwd: &Path) -> Vec<String> {
+        let srcs: Vec<PathBuf> = self
+            .src
+            .iter()
+            .chain(self.overlays.iter())
+            .filter(|src| !is_templated_str(src) && RemoteSource::from_url(src).is_none())
+            .map(|src| resolve_path(src, cwd))
+            .collect();
+        self.removed
+            .iter()
+            .filter(|entry| {
+                srcs.iter().any(|src| {
+                    let src_path = src.join(entry.as_str());
+                    src_path.symlink_metadata().is_ok()
+                        || with_tera_ext(&src_path).symlink_metadata().is_ok()
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Rewrite the package's source files, replacing each value in
+    /// `substitutions` with its template placeholder. Binary files are left
+    /// alone. Returns the number of replacements per changed file.
+    pub fn templatize(
+        &self,
+        ctx: &Context,
+        substitutions: &[(String, String)],
+    ) -> Result<Vec<(PathBuf, usize)>, anyhow::Error> {
+        let Some(src) = self.resolve_src(ctx) else {
+            return Ok(Vec::new());
+        };
+        let mut changed: Vec<(PathBuf, usize)> = Vec::new();
+        for entry in walkdir::WalkDir::new(&src) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let Ok(content) = std::fs::read_to_string(entry.path()) else {
+                continue;
+            };
+            let (templated, count) = substitute_values(&content, substitutions);
+            if count > 0 {
+                std::fs::write(entry.path(), templated)?;
+                changed.push((entry.path().to_path_buf(), count));
+            }
+        }
+        if changed.is_empty() {
+            cprintln(
+                &format!("Nothing to template in '{}'", self.name),
+                &LogLevel::INFO,
+            );
+        } else {
+            let mut summary = format!("Templated '{}':", self.name);
+            for (path, count) in changed.iter() {
+                summary.push_str(&format!(
+                    "\n  {}: {} replacement(s)",
+                    path.strip_prefix(&ctx.working_dir)
+                        .unwrap_or(path)
+                        .display(),
+                    count
+                ));
+            }
+            cprintln(&summary, &LogLevel::INFO);
+        }
+        Ok(changed)
+    }
+
+    /// Remove files under dest that have no counterpart in src.
+    /// Backup files and ignored paths are kept, and symlinks are removed
+    /// without being followed. Returns the pruned paths.
+    pub fn prune(
+        &self,
+        srcs: &[PathBuf],
+        dest: &Path,
+        ctx: &Context,
+    ) -> Result<Vec<PathBuf>, anyhow::Error> {
+        self.prune_where(dest, ctx, |relative_path| {
+            let Some(relative_path) = self.src_relative(relative_path) else {
+                return false;
+            };
+            srcs.iter().any(|src| {
+                let src_path = src.join(&relative_path);
+                src_path.symlink_metadata().is_ok()
+                    || with_tera_ext(&src_path).symlink_metadata().is_ok()
+            })
+        })
+    }
+
+    // `prune` with the files to keep decided by `in_src`, given their path under dest
+    fn prune_where(
+        &self,
+        dest: &Path,
+        ctx: &Context,
+        in_src: impl Fn(&Path) -> bool,
+    ) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let mut pruned = Vec::new();
+        for (relative_path, entry) in self.dest_entries(dest, &self.subpath_dests(ctx)?)? {
+            if entry.file_type().is_dir() {
+                continue;
+            }
+            let relative_path = relative_path.as_path();
+            if entry.path().extension() == Some(OsStr::new(BACKUP_EXT))
+                || self.should_ignore(relative_path)
+                || self.is_under_kept_dir(relative_path)
+            {
+                continue;
+            }
+            if in_src(relative_path) {
+                continue;
+            }
+            if entry.file_type().is_file() {
+                self.backup_dest(entry.path(), ctx)?;
+            }
+            std::fs::remove_file(entry.path())?;
+            pruned.push(entry.path().to_path_buf());
+        }
+        if !pruned.is_empty() {
+            let mut summary = format!("Pruned {} file(s) from '{}':", pruned.len(), self.name);
+            for path in pruned.iter() {
+                summary.push_str(&format!("\n  {}", path.display()));
+            }
+            cprintln(&summary, &LogLevel::INFO);
         }
-
-        cprintln(
-            &format!("Package '{}' deployed", self.name),
-            &LogLevel::INFO,
-        );
-        self.execute_post_actions(ctx)?;
-        Ok(())
+        Ok(pruned)
     }
 
     pub fn is_dir(&self) -> bool {
@@ -539,8 +3547,11 @@ impl Package {
 
     pub fn package_is_templated(&self, cwd: &Path) -> bool {
         // Check if src exists as a directory or file, if not return true:
-        let src_path = cwd.join(&self.src);
-        if !src_path.exists() {
+        let Some(src) = &self.src else {
+            return false;
+        };
+        let src_path = cwd.join(src);
+        if !src_path.exists() || self.template == TemplateMode::Never {
             return false;
         }
         // Check for following templating indicators using walkdir (when necessary) and regex:
@@ -554,12 +3565,13 @@ impl Package {
         if src_path.is_dir() {
             for entry in walkdir::WalkDir::new(&src_path) {
                 let entry = entry.expect("Failed to read directory entry");
-                if entry.path().is_file() {
-                    return is_templated(&entry.path().to_path_buf());
+                // .tera files are skipped one by one, the rest can still be backed up
+                if entry.path().is_file() && !is_tera_file(entry.path()) {
+                    return self.is_template(entry.path());
                 }
             }
         } else if src_path.is_file() {
-            return is_templated(&src_path);
+            return self.is_template(&src_path);
         }
         false
     }
@@ -567,30 +3579,103 @@ impl Package {
 
 /// Get a package name from a given path string.
 /// The package name is derived from the last component of the path,
-/// with any leading '.' removed, and any trailing version numbers removed.
+/// with any leading '.' removed, and a trailing version (`-1.2`, `-v3`) removed.
 /// Additionally, any '-' or '.' characters are replaced with '_'.
 /// If the path is a directory, it should be prepended with d_
 /// Or, if it's a file, with f_
-pub fn get_package_name(args: &ImportArgs, cwd: &Path) -> String {
-    let path = resolve_path(&args.path, cwd);
+pub fn get_package_name(
+    path: &str,
+    name: &Option<String>,
+    cwd: &Path,
+) -> Result<String, anyhow::Error> {
+    let path = normalize_path(&resolve_path(path, cwd));
     let prefix = if path.is_dir() { "d_" } else { "f_" };
-    if let Some(custom_name) = &args.name {
-        return format!("{}{}", prefix, custom_name.replace(['-', '.'], "_"));
+    if let Some(custom_name) = name {
+        return Ok(format!(
+            "{}{}",
+            prefix,
+            custom_name.replace(['-', '.'], "_")
+        ));
     }
-    let last_component = path
+    let name = path
         .file_name()
-        .expect("Failed to get file name")
-        .to_str()
-        .unwrap();
-    let mut package_name = last_component.trim_start_matches('.').to_string();
+        .and_then(|file_name| file_name.to_str())
+        .map(name_from_file_name)
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Can't name a package after '{}', use --name",
+                path.display()
+            )
+        })?;
+    Ok(format!("{}{}", prefix, name))
+}
+
+// The unprefixed package name for a file or directory name
+fn name_from_file_name(file_name: &str) -> String {
+    let mut name = file_name.trim_start_matches('.');
+    if let Some((stem, suffix)) = name.rsplit_once('-')
+        && !stem.is_empty()
+        && is_version(suffix)
+    {
+        name = stem;
+    }
+    name.replace(['-', '.'], "_")
+}
+
+// Matches `^v?\d+(\.\d+)*$`
+fn is_version(s: &str) -> bool {
+    let digits = s.strip_prefix('v').unwrap_or(s);
+    !digits.is_empty()
+        && digits
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Check that a custom package name can be used as a TOML key.
+/// '-' and '.' are allowed since they are replaced with '_' by `get_package_name`.
+pub fn validate_package_name(name: &str) -> Result<(), anyhow::Error> {
+    if name.is_empty() {
+        anyhow::bail!("Package name must not be empty");
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')))
+    {
+        anyhow::bail!(
+            "Invalid package name '{}': character '{}' is not allowed, use letters, digits and underscores",
+            name,
+            c
+        );
+    }
+    Ok(())
+}
+
+/// The entries under `root`, sorted by name, with the directories first and
+/// shallowest first, so they all exist before any file is written. Files keep
+/// their lexicographic order, the same on every platform, and so do logs and
+/// where a failure stops.
+fn sorted_entries(root: &Path) -> Result<Vec<walkdir::DirEntry>, anyhow::Error> {
+    let mut entries = walkdir::WalkDir::new(root)
+        .follow_links(false)
+        .sort_by_file_name()
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| {
+        let is_dir = entry.path().is_dir();
+        (!is_dir, is_dir.then(|| entry.depth()))
+    });
+    Ok(entries)
+}
 
-    // Remove any trailing version numbers
-    if let Some(pos) = package_name.rfind('-') {
-        package_name.truncate(pos);
+/// `relative` under `root`, where the empty path of a single-file source is
+/// `root` itself.
+fn join_relative(root: &Path, relative: &Path) -> PathBuf {
+    if relative.as_os_str().is_empty() {
+        root.to_path_buf()
+    } else {
+        root.join(relative)
     }
-    // replace any remaining '-' with '_', and '.' with '_'
-    package_name = format!("{}{}", prefix, package_name);
-    package_name.replace(['-', '.'], "_")
 }
 
 /// Create a backup path by appending the backup extension to the original path
@@ -601,6 +3686,546 @@ fn create_backup_path(path: &Path) -> PathBuf {
     PathBuf::from(backup_path)
 }
 
+/// What `src` is and what `dest` is instead, when dest exists as a
+/// directory where src is a file or the other way around. Symlinked dests
+/// have their own checks.
+fn kind_mismatch(src: &Path, dest: &Path) -> Option<(&'static str, &'static str)> {
+    let src_is_dir = std::fs::metadata(src).ok()?.is_dir();
+    let dest_meta = dest.symlink_metadata().ok()?;
+    if dest_meta.is_symlink() || dest_meta.is_dir() == src_is_dir {
+        return None;
+    }
+    Some(if src_is_dir {
+        ("directory", "file")
+    } else {
+        ("file", "directory")
+    })
+}
+
+/// Whether `path` exists and holds exactly `content`.
+fn content_matches(path: &Path, content: &[u8]) -> anyhow::Result<bool> {
+    if !path.exists() {
+        return Ok(false);
+    }
+    let existing = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    Ok(existing == content)
+}
+
+fn is_empty_dir(path: &Path) -> bool {
+    std::fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none())
+}
+
+/// Put a KEEP_FILE in the source copy of a kept or empty directory, so git
+/// tracks it. One that holds files already is tracked through them. Reported
+/// like a backed up file.
+fn keep_src_dir(dir: &Path, dry_run: bool, report: &mut PackageReport) -> anyhow::Result<()> {
+    let placeholder = dir.join(KEEP_FILE);
+    if placeholder.exists() || (dir.exists() && !is_empty_dir(dir)) {
+        return Ok(());
+    }
+    report.backed_up.push(placeholder.clone());
+    if !dry_run {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(&placeholder, "")
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", placeholder.display(), e))?;
+    }
+    Ok(())
+}
+
+// Remove the directories between `path` and `root` that are left empty
+fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir.filter(|d| *d != root && d.starts_with(root)) {
+        if std::fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+/// Copy one deployed file back over its source, unless they already match.
+/// With `dry_run` the file is only added to the report's diffs.
+fn backup_file(
+    from: &Path,
+    to: &Path,
+    dry_run: bool,
+    reflink: bool,
+    report: &mut PackageReport,
+) -> anyhow::Result<()> {
+    let content = std::fs::read(from)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", from.display(), e))?;
+    if content_matches(to, &content)? {
+        return Ok(());
+    }
+    report.backed_up.push(to.to_path_buf());
+    if !dry_run {
+        create_parent_dirs(to)?;
+        copy_atomic(from, to, &content, file_mode_of(from), reflink).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to copy {} to {}: {}",
+                from.display(),
+                to.display(),
+                e
+            )
+        })?;
+        copy_permissions(from, to)?;
+        return Ok(());
+    }
+    let existing = std::fs::read(to).ok();
+    report.diffs.push(FileDiff {
+        src: from.to_path_buf(),
+        dest: to.to_path_buf(),
+        old: existing.and_then(|bytes| String::from_utf8(bytes).ok()),
+        new: String::from_utf8(content).ok(),
+    });
+    Ok(())
+}
+
+/// Print a line-by-line diff, with - for removed lines, + for added lines,
+/// and space for unchanged lines, colored where possible.
+pub fn print_line_diff(old: &str, new: &str) {
+    for diff in diff::lines(old, new) {
+        match diff {
+            diff::Result::Left(l) => {
+                println!("{}", paint(&format!("-{}", l), Color::Red));
+            }
+            diff::Result::Both(l, _) => {
+                println!(" {}", l);
+            }
+            diff::Result::Right(r) => {
+                println!("{}", paint(&format!("+{}", r), Color::Green));
+            }
+        };
+    }
+}
+
+/// Report a dest whose content already matches, fixing its mode if that drifted.
+/// Whether two deployed copies hold the same files with the same content,
+/// leaving out backups.
+fn same_content(a: &Path, b: &Path) -> bool {
+    if !a.is_dir() || !b.is_dir() {
+        return matches!((std::fs::read(a), std::fs::read(b)), (Ok(x), Ok(y)) if x == y);
+    }
+    let files = |root: &Path| -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = walkdir::WalkDir::new(root)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry.file_type().is_file()
+                    && entry.path().extension() != Some(OsStr::new(BACKUP_EXT))
+            })
+            .filter_map(|entry| entry.path().strip_prefix(root).ok().map(Path::to_path_buf))
+            .collect();
+        files.sort();
+        files
+    };
+    let a_files = files(a);
+    a_files == files(b)
+        && a_files
+            .iter()
+            .all(|file| same_content(&a.join(file), &b.join(file)))
+}
+
+/// Write `content` to a temporary file beside `path` and rename it over
+/// `path`, so a write that is cut short never leaves it half written. Where
+/// that can't be done, like a dest that is itself a mount point, `path` is
+/// written in place.
+///
+/// The temporary file is created with `mode`, or the mode `path` has now,
+/// before any content goes in: a private file is never readable by others
+/// in between.
+fn write_atomic(path: &Path, content: &[u8], mode: Option<u32>) -> std::io::Result<()> {
+    let temp_path = atomic_temp_path(path);
+    let mode = mode.or_else(|| file_mode_of(path));
+    let renamed =
+        write_new(&temp_path, content, mode).and_then(|_| std::fs::rename(&temp_path, path));
+    if renamed.is_ok() {
+        return Ok(());
+    }
+    std::fs::remove_file(&temp_path).ok();
+    std::fs::write(path, content)
+}
+
+// The temporary file beside `path` that write_atomic renames over it
+fn atomic_temp_path(path: &Path) -> PathBuf {
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(format!(".dotr-tmp-{}", std::process::id()));
+    path.with_file_name(temp_name)
+}
+
+/// Copy `from`, which holds `content`, to `path` like write_atomic: as a
+/// reflink sharing its blocks when `reflink` is set and the filesystem can,
+/// otherwise by writing `content`.
+fn copy_atomic(
+    from: &Path,
+    path: &Path,
+    content: &[u8],
+    mode: Option<u32>,
+    reflink: bool,
+) -> std::io::Result<()> {
+    if reflink {
+        let temp_path = atomic_temp_path(path);
+        let copied = reflink::reflink(from, &temp_path)
+            .and_then(|_| match mode {
+                Some(mode) => set_temp_mode(&temp_path, mode),
+                None => Ok(()),
+            })
+            .and_then(|_| std::fs::rename(&temp_path, path));
+        match copied {
+            Ok(()) => {
+                cprintln(
+                    &format!("Reflinked {} to {}", from.display(), path.display()),
+                    &LogLevel::TRACE,
+                );
+                return Ok(());
+            }
+            Err(e) => {
+                std::fs::remove_file(&temp_path).ok();
+                cprintln(
+                    &format!(
+                        "Copying {} to {}, no reflink: {}",
+                        from.display(),
+                        path.display(),
+                        e
+                    ),
+                    &LogLevel::TRACE,
+                );
+            }
+        }
+    }
+    write_atomic(path, content, mode)
+}
+
+// Create `path` anew with `mode`, narrowed by the umask, and write `content`
+#[cfg(unix)]
+fn write_new(path: &Path, content: &[u8], mode: Option<u32>) -> std::io::Result<()> {
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+
+    // A leftover from an earlier run would keep its own mode
+    std::fs::remove_file(path).ok();
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    if let Some(mode) = mode {
+        options.mode(mode);
+    }
+    options.open(path)?.write_all(content)
+}
+
+#[cfg(not(unix))]
+fn write_new(path: &Path, content: &[u8], _mode: Option<u32>) -> std::io::Result<()> {
+    std::fs::write(path, content)
+}
+
+#[cfg(unix)]
+fn set_temp_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_temp_mode(_path: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// The permission bits of the file at `path`, if there is one.
+#[cfg(unix)]
+fn file_mode_of(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path)
+        .ok()
+        .map(|metadata| metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn file_mode_of(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// Copy `dest` aside to `backup_path` before it gets overwritten.
+fn copy_to_backup(dest: &Path, backup_path: &Path) -> anyhow::Result<()> {
+    create_parent_dirs(backup_path)?;
+    std::fs::copy(dest, backup_path).map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to back up {} to {}: {}",
+            dest.display(),
+            backup_path.display(),
+            e
+        )
+    })?;
+    copy_permissions(dest, backup_path)?;
+    Ok(())
+}
+
+/// Create the missing directories above `path`.
+fn create_parent_dirs(path: &Path) -> anyhow::Result<()> {
+    let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) else {
+        return Ok(());
+    };
+    std::fs::create_dir_all(parent).map_err(|e| {
+        let hint = if e.kind() == std::io::ErrorKind::PermissionDenied {
+            " (check that you can write there, or change the package dest)"
+        } else {
+            ""
+        };
+        anyhow::anyhow!(
+            "Failed to create directory {} for {}: {}{}",
+            parent.display(),
+            path.display(),
+            e,
+            hint
+        )
+    })
+}
+
+/// Copy the permission bits of `from` onto `to`.
+/// Returns true if the permissions of `to` were changed.
+#[cfg(unix)]
+fn copy_permissions(from: &Path, to: &Path) -> anyhow::Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = std::fs::metadata(from)?.permissions().mode() & 0o7777;
+    let current = std::fs::metadata(to)?.permissions().mode() & 0o7777;
+    if mode == current {
+        return Ok(false);
+    }
+    std::fs::set_permissions(to, std::fs::Permissions::from_mode(mode))?;
+    Ok(true)
+}
+
+/// Copy the read-only flag of `from` onto `to`.
+/// Returns true if the permissions of `to` were changed.
+#[cfg(not(unix))]
+fn copy_permissions(from: &Path, to: &Path) -> anyhow::Result<bool> {
+    let readonly = std::fs::metadata(from)?.permissions().readonly();
+    let mut perms = std::fs::metadata(to)?.permissions();
+    if perms.readonly() == readonly {
+        return Ok(false);
+    }
+    perms.set_readonly(readonly);
+    std::fs::set_permissions(to, perms)?;
+    Ok(true)
+}
+
+/// `dir` and those of its ancestors that don't exist yet, deepest first.
+fn missing_dirs(dir: &Path) -> Vec<PathBuf> {
+    dir.ancestors()
+        .take_while(|a| !a.as_os_str().is_empty() && !a.exists())
+        .map(Path::to_path_buf)
+        .collect()
+}
+
+/// Set the permission bits of `path` to `mode`.
+/// Returns true if they were changed.
+#[cfg(unix)]
+pub fn set_mode(path: &Path, mode: u32) -> anyhow::Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let current = std::fs::metadata(path)?.permissions().mode() & 0o7777;
+    if current == mode {
+        return Ok(false);
+    }
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .map_err(|e| anyhow::anyhow!("Failed to set the mode of {}: {}", path.display(), e))?;
+    Ok(true)
+}
+
+/// Only the owner write bit means something here, as the read-only flag.
+#[cfg(not(unix))]
+pub fn set_mode(path: &Path, mode: u32) -> anyhow::Result<bool> {
+    let readonly = mode & 0o200 == 0;
+    let mut perms = std::fs::metadata(path)?.permissions();
+    if perms.readonly() == readonly {
+        return Ok(false);
+    }
+    perms.set_readonly(readonly);
+    std::fs::set_permissions(path, perms)?;
+    Ok(true)
+}
+
+/// The uid or gid of `name` from a passwd or group file, or `name` itself
+/// when it is already numeric.
+#[cfg(unix)]
+fn lookup_id(db: &str, kind: &str, name: &str) -> Result<u32, anyhow::Error> {
+    if let Ok(id) = name.parse::<u32>() {
+        return Ok(id);
+    }
+    let content =
+        std::fs::read_to_string(db).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", db, e))?;
+    content
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.first() == Some(&name))
+        .and_then(|fields| fields.get(2).and_then(|id| id.parse().ok()))
+        .ok_or_else(|| anyhow::anyhow!("Unknown {} '{}'", kind, name))
+}
+
+// Read an optional octal mode like "0644" from a package table.
+fn parse_mode(pkg_val: &Table, key: &str) -> Result<Option<u32>, anyhow::Error> {
+    let Some(value) = optional_string(pkg_val, key)? else {
+        return Ok(None);
+    };
+    parse_octal_mode(&value).map(Some).ok_or_else(|| {
+        anyhow::anyhow!(
+            "The '{}' field must be an octal mode like \"0644\", got \"{}\"",
+            key,
+            value
+        )
+    })
+}
+
+/// An octal mode like "644" or "0644", None when `value` isn't one.
+pub fn parse_octal_mode(value: &str) -> Option<u32> {
+    let valid = (3..=4).contains(&value.len()) && value.chars().all(|c| ('0'..='7').contains(&c));
+    valid.then(|| u32::from_str_radix(value, 8).ok()).flatten()
+}
+
+// Read an optional string from a package table.
+fn optional_string(pkg_val: &Table, key: &str) -> Result<Option<String>, anyhow::Error> {
+    pkg_val
+        .get(key)
+        .map(|v| {
+            v.as_str()
+                .map(|s| s.to_string())
+                .ok_or_else(|| anyhow::anyhow!("The '{}' field must be a string", key))
+        })
+        .transpose()
+}
+
+// Read an optional array of strings from a package table.
+fn string_array(pkg_val: &Table, key: &str) -> Result<Vec<String>, anyhow::Error> {
+    let Some(block) = pkg_val.get(key) else {
+        return Ok(Vec::new());
+    };
+    block
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("The '{}' field must be an array", key))?
+        .iter()
+        .map(|v| {
+            v.as_str()
+                .map(|s| s.to_string())
+                .ok_or_else(|| anyhow::anyhow!("Values of '{}' must be strings", key))
+        })
+        .collect()
+}
+
+/// Whether `entry` is a relative path that stays inside the directory it's
+/// joined to: no root, no `.` or `..`.
+pub fn is_inner_path(entry: &str) -> bool {
+    let path = Path::new(entry);
+    !entry.is_empty()
+        && !path.is_absolute()
+        && path
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+}
+
+// Read a `targets` entry given as a table: the dest of the package under
+// the `""` key, and the dests of its subpaths under theirs.
+fn parse_subpath_target(
+    key: &str,
+    table: &Table,
+) -> Result<(String, BTreeMap<String, String>), anyhow::Error> {
+    let mut dest = None;
+    let mut subpaths = BTreeMap::new();
+    for (subpath, value) in table {
+        let value = value.as_str().ok_or_else(|| {
+            anyhow::anyhow!(
+                "Target '{}': the dest of '{}' must be a string",
+                key,
+                subpath
+            )
+        })?;
+        if subpath.is_empty() {
+            dest = Some(value.to_string());
+        } else if is_inner_path(subpath) {
+            subpaths.insert(subpath.clone(), value.to_string());
+        } else {
+            anyhow::bail!(
+                "Target '{}': '{}' must be a relative path inside the package",
+                key,
+                subpath
+            );
+        }
+    }
+    let dest = dest.ok_or_else(|| {
+        anyhow::anyhow!(
+            "Target '{}' needs a \"\" entry, the dest of what no subpath covers",
+            key
+        )
+    })?;
+    Ok((dest, subpaths))
+}
+
+// Where the file at `target`, relative to the package, lands: under the
+// dest of the first of `subpaths` holding it, otherwise under `dest`
+fn place(dest: &Path, target: &Path, subpaths: &[(PathBuf, PathBuf)]) -> PathBuf {
+    match subpaths
+        .iter()
+        .find(|(subpath, _)| target.starts_with(subpath))
+    {
+        Some((subpath, root)) => {
+            join_relative(root, target.strip_prefix(subpath).unwrap_or(target))
+        }
+        None => join_relative(dest, target),
+    }
+}
+
+// Read the `rename` table of a package, refusing two srcs that map to the
+// same dest.
+fn parse_rename(pkg_val: &Table) -> Result<BTreeMap<String, String>, anyhow::Error> {
+    let Some(block) = pkg_val.get("rename") else {
+        return Ok(BTreeMap::new());
+    };
+    let table = block
+        .as_table()
+        .ok_or_else(|| anyhow::anyhow!("The 'rename' field must be a table"))?;
+    let mut rename = BTreeMap::new();
+    let mut claimed: HashMap<&str, &str> = HashMap::new();
+    for (src, dest) in table {
+        let dest = dest
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("rename of '{}' must be a string", src))?;
+        for path in [src.as_str(), dest] {
+            if !is_inner_path(path) {
+                anyhow::bail!("rename entry '{}' must be a path inside the package", path);
+            }
+        }
+        if let Some(other) = claimed.insert(dest, src) {
+            anyhow::bail!("rename maps both '{}' and '{}' to '{}'", other, src, dest);
+        }
+        rename.insert(src.clone(), dest.to_string());
+    }
+    Ok(rename)
+}
+
+/// The program and leading arguments used to run an action string.
+/// `cmd` gets `/C` and PowerShell gets `-Command`; any other shell is run with `-c`.
+/// Without a configured shell, Windows uses `cmd` and other platforms use `$SHELL`,
+/// falling back to `/bin/sh`.
+pub fn shell_command(shell: Option<&str>) -> Vec<String> {
+    let shell = match shell {
+        Some(shell) => shell.to_string(),
+        None if cfg!(windows) => "cmd".to_string(),
+        None => std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string()),
+    };
+    let name = Path::new(&shell)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let args: &[&str] = match name.as_str() {
+        "cmd" => &["/C"],
+        "powershell" | "pwsh" => &["-NoProfile", "-Command"],
+        _ => &["-c"],
+    };
+    let mut command = vec![shell];
+    command.extend(args.iter().map(|a| a.to_string()));
+    command
+}
+
 /// Compile a template file at the given path using Tera templating engine with the provided context. and return the rendered content as a String.
 pub fn compile_template(path: &Path, context: &Table) -> anyhow::Result<String> {
     let template_content = std::fs::read_to_string(path)?;
@@ -608,8 +4233,343 @@ pub fn compile_template(path: &Path, context: &Table) -> anyhow::Result<String>
 }
 
 pub fn compile_string(template_str: &str, context: &Table) -> anyhow::Result<String> {
+    render_with(&mut new_tera(), ONE_OFF_TEMPLATE, template_str, context)
+}
+
+/// Replace every occurrence of a value with its placeholder in a single pass,
+/// preferring the longest value at each position. Values only match on word
+/// boundaries, so a username of `al` leaves `also` alone. Returns the new
+/// content and the number of replacements.
+pub fn substitute_values(content: &str, substitutions: &[(String, String)]) -> (String, usize) {
+    let mut ordered: Vec<&(String, String)> = substitutions.iter().collect();
+    ordered.sort_by_key(|(value, _)| std::cmp::Reverse(value.len()));
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+
+    let mut result = String::with_capacity(content.len());
+    let mut count = 0;
+    let mut rest = content;
+    let mut previous: Option<char> = None;
+    'outer: while let Some(c) = rest.chars().next() {
+        for (value, placeholder) in ordered.iter() {
+            if !rest.starts_with(value.as_str()) {
+                continue;
+            }
+            let first = value.chars().next();
+            let last = value.chars().last();
+            let next = rest[value.len()..].chars().next();
+            if (is_word(first) && is_word(previous)) || (is_word(last) && is_word(next)) {
+                continue;
+            }
+            result.push_str(placeholder);
+            count += 1;
+            previous = last;
+            rest = &rest[value.len()..];
+            continue 'outer;
+        }
+        result.push(c);
+        previous = Some(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    (result, count)
+}
+
+/// A Tera environment with our filters registered and autoescaping off, like Tera::one_off.
+pub fn new_tera() -> tera::Tera {
+    let mut tera = tera::Tera::default();
+    filters::register_filters(&mut tera);
+    tera.autoescape_on(vec![]);
+    tera
+}
+
+/// Build a Tera environment with every file under `<working_dir>/templates/`
+/// loaded as a named template, so package templates can include them by relative path.
+pub fn load_tera(working_dir: &Path) -> anyhow::Result<tera::Tera> {
+    let mut tera = new_tera();
+    let templates_dir = working_dir.join(TEMPLATES_DIR);
+    if !templates_dir.is_dir() {
+        return Ok(tera);
+    }
+    let mut templates = Vec::new();
+    for entry in walkdir::WalkDir::new(&templates_dir) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(&templates_dir)?;
+        let name = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let content = std::fs::read_to_string(entry.path())?;
+        templates.push((name, content));
+    }
+    tera.add_raw_templates(templates).map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to load shared templates from {}: {}",
+            templates_dir.display(),
+            tera_error_chain(&e)
+        )
+    })?;
+    Ok(tera)
+}
+
+/// Add a template to the environment under `name` and render it.
+pub fn render_with(
+    tera: &mut tera::Tera,
+    name: &str,
+    template_str: &str,
+    context: &Table,
+) -> anyhow::Result<String> {
     let ctx = tera::Context::from_serialize(context)?;
-    Ok(tera::Tera::one_off(template_str, &ctx, false)?)
+    tera.add_raw_template(name, template_str)
+        .map_err(|e| anyhow::anyhow!("{}", tera_error_chain(&e)))?;
+    tera.render(name, &ctx)
+        .map_err(|e| anyhow::anyhow!("{}", tera_error_chain(&e)))
+}
+
+// Tera keeps the useful part of an error (e.g. which include is missing) in its sources.
+fn tera_error_chain(e: &tera::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = std::error::Error::source(e);
+    while let Some(inner) = source {
+        message.push_str(&format!(": {}", inner));
+        source = inner.source();
+    }
+    message
+}
+
+/// Check that every variable a template file references exists in the context.
+/// Variables guarded by `is defined` or a `default` filter are allowed to be missing.
+pub fn validate_template(path: &Path, context: &Table) -> anyhow::Result<()> {
+    let template_content = std::fs::read_to_string(path)?;
+    validate_template_str(&path.display().to_string(), &template_content, context)
+}
+
+pub fn validate_template_str(
+    label: &str,
+    template_str: &str,
+    context: &Table,
+) -> anyhow::Result<()> {
+    for var in template_references(label, template_str)? {
+        if lookup_path(context, &var).is_some() {
+            continue;
+        }
+        let candidates = suggest_variables(&var, context);
+        let hint = match candidates.as_slice() {
+            [] => String::new(),
+            [one] => format!("; did you mean '{}'?", one),
+            many => format!(
+                "; did you mean one of {}?",
+                many.iter()
+                    .map(|c| format!("'{}'", c))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        anyhow::bail!(
+            "file {} references undefined variable '{}'{}",
+            label,
+            var,
+            hint
+        );
+    }
+    Ok(())
+}
+
+/// The variable paths a template reads, e.g. `git.email`, leaving out the
+/// ones it guards with `is defined` or a `default` filter.
+pub fn template_references(label: &str, template_str: &str) -> anyhow::Result<Vec<String>> {
+    let template = tera::Template::new(label, None, template_str)?;
+    let mut refs = Vec::new();
+    collect_node_refs(&template.ast, &mut vec!["loop".to_string()], &mut refs);
+    Ok(refs)
+}
+
+// Walk template nodes, collecting referenced variable paths that are not bound locally.
+fn collect_node_refs(nodes: &[Node], locals: &mut Vec<String>, refs: &mut Vec<String>) {
+    for node in nodes {
+        match node {
+            Node::VariableBlock(_, expr) => collect_expr_refs(expr, locals, refs),
+            Node::Set(_, set) => {
+                collect_expr_refs(&set.value, locals, refs);
+                locals.push(set.key.clone());
+            }
+            Node::FilterSection(_, section, _) => {
+                collect_call_refs(&section.filter, locals, refs);
+                collect_node_refs(&section.body, locals, refs);
+            }
+            Node::Block(_, block, _) => collect_node_refs(&block.body, locals, refs),
+            Node::Forloop(_, forloop, _) => {
+                collect_expr_refs(&forloop.container, locals, refs);
+                let depth = locals.len();
+                locals.push(forloop.value.clone());
+                if let Some(key) = &forloop.key {
+                    locals.push(key.clone());
+                }
+                collect_node_refs(&forloop.body, locals, refs);
+                locals.truncate(depth);
+                if let Some(empty_body) = &forloop.empty_body {
+                    collect_node_refs(empty_body, locals, refs);
+                }
+            }
+            Node::If(cond, _) => {
+                for (_, expr, body) in cond.conditions.iter() {
+                    collect_expr_refs(expr, locals, refs);
+                    // A branch guarded by `x is defined` may use x freely
+                    let depth = locals.len();
+                    collect_defined_probes(expr, locals);
+                    collect_node_refs(body, locals, refs);
+                    locals.truncate(depth);
+                }
+                if let Some((_, body)) = &cond.otherwise {
+                    collect_node_refs(body, locals, refs);
+                }
+            }
+            Node::MacroDefinition(_, def, _) => {
+                let mut macro_locals: Vec<String> = def.args.keys().cloned().collect();
+                collect_node_refs(&def.body, &mut macro_locals, refs);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_expr_refs(expr: &Expr, locals: &[String], refs: &mut Vec<String>) {
+    // `{{ x | default(value="y") }}` explicitly handles a missing variable
+    if !expr.has_default_filter() {
+        collect_val_refs(&expr.val, locals, refs);
+    }
+    for filter in expr.filters.iter() {
+        collect_call_refs(filter, locals, refs);
+    }
+}
+
+fn collect_defined_probes(expr: &Expr, probes: &mut Vec<String>) {
+    match &expr.val {
+        ExprVal::Test(test) if test.name == "defined" && !expr.negated => {
+            probes.push(ident_to_path(&test.ident));
+        }
+        ExprVal::Logic(logic) => {
+            collect_defined_probes(&logic.lhs, probes);
+            collect_defined_probes(&logic.rhs, probes);
+        }
+        _ => {}
+    }
+}
+
+fn collect_call_refs(call: &FunctionCall, locals: &[String], refs: &mut Vec<String>) {
+    for arg in call.args.values() {
+        collect_expr_refs(arg, locals, refs);
+    }
+}
+
+fn collect_val_refs(val: &ExprVal, locals: &[String], refs: &mut Vec<String>) {
+    match val {
+        ExprVal::Ident(ident) => {
+            let path = ident_to_path(ident);
+            let root = path.split('.').next().unwrap_or_default();
+            if !root.is_empty()
+                && !root.starts_with("__tera")
+                && !locals
+                    .iter()
+                    .any(|l| path == *l || path.starts_with(&format!("{}.", l)))
+                && !refs.contains(&path)
+            {
+                refs.push(path);
+            }
+        }
+        ExprVal::Math(math) => {
+            collect_expr_refs(&math.lhs, locals, refs);
+            collect_expr_refs(&math.rhs, locals, refs);
+        }
+        ExprVal::Logic(logic) => {
+            collect_expr_refs(&logic.lhs, locals, refs);
+            collect_expr_refs(&logic.rhs, locals, refs);
+        }
+        ExprVal::Test(test) => {
+            // `is defined` / `is undefined` are how templates probe optional variables
+            if test.name != "defined" && test.name != "undefined" {
+                collect_val_refs(&ExprVal::Ident(test.ident.clone()), locals, refs);
+            }
+            for arg in test.args.iter() {
+                collect_expr_refs(arg, locals, refs);
+            }
+        }
+        ExprVal::MacroCall(call) => {
+            for arg in call.args.values() {
+                collect_expr_refs(arg, locals, refs);
+            }
+        }
+        ExprVal::FunctionCall(call) => collect_call_refs(call, locals, refs),
+        ExprVal::Array(items) => {
+            for item in items.iter() {
+                collect_expr_refs(item, locals, refs);
+            }
+        }
+        ExprVal::StringConcat(concat) => {
+            for value in concat.values.iter() {
+                collect_val_refs(value, locals, refs);
+            }
+        }
+        ExprVal::In(in_expr) => {
+            collect_expr_refs(&in_expr.lhs, locals, refs);
+            collect_expr_refs(&in_expr.rhs, locals, refs);
+        }
+        ExprVal::String(_) | ExprVal::Int(_) | ExprVal::Float(_) | ExprVal::Bool(_) => {}
+    }
+}
+
+// Turn a Tera identifier like `servers[0].host` into a dotted path `servers.0.host`.
+// Dynamic subscripts like `hosts[name]` can't be checked, so the path stops before them.
+fn ident_to_path(ident: &str) -> String {
+    let mut parts = Vec::new();
+    for segment in ident.split('.') {
+        let mut pieces = segment.split('[');
+        if let Some(head) = pieces.next()
+            && !head.is_empty()
+        {
+            parts.push(head.to_string());
+        }
+        for piece in pieces {
+            let key = piece.trim_end_matches(']').trim();
+            let unquoted = key.trim_matches(|c| c == '"' || c == '\'');
+            if key.parse::<usize>().is_ok() || unquoted.len() + 2 == key.len() {
+                parts.push(unquoted.to_string());
+            } else {
+                return parts.join(".");
+            }
+        }
+    }
+    parts.join(".")
+}
+
+// Closest available variable paths to `name`, best first.
+fn suggest_variables(name: &str, context: &Table) -> Vec<String> {
+    let mut keys = Vec::new();
+    collect_variable_paths(context, "", &mut keys);
+    let threshold = (name.chars().count() / 3).max(2);
+    let mut scored: Vec<(usize, String)> = keys
+        .into_iter()
+        .map(|key| (edit_distance(name, &key), key))
+        .filter(|(distance, _)| *distance <= threshold)
+        .collect();
+    scored.sort();
+    scored.into_iter().take(3).map(|(_, key)| key).collect()
+}
+
+fn collect_variable_paths(table: &Table, prefix: &str, keys: &mut Vec<String>) {
+    for (key, value) in table.iter() {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        if let toml::Value::Table(nested) = value {
+            collect_variable_paths(nested, &path, keys);
+        }
+        keys.push(path);
+    }
 }
 
 pub fn is_templated(p: &PathBuf) -> bool {
@@ -624,13 +4584,161 @@ pub fn is_templated(p: &PathBuf) -> bool {
     }
 }
 
+/// Whether `path` is named as a template, like `init.lua.tera`.
+pub fn is_tera_file(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new(TEMPLATE_EXT))
+}
+
+/// The path a source file deploys to within a directory package: its own,
+/// without a `.tera` extension.
+pub fn strip_tera_ext(path: &Path) -> PathBuf {
+    if is_tera_file(path) {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Separates a file's name from the condition of a host-specific variant,
+/// as in `config.toml##hostname.laptop`.
+pub const VARIANT_SEPARATOR: &str = "##";
+
+/// What a variant can be for, the most specific first.
+pub const VARIANT_KEYS: &[&str] = &["hostname", "os", "profile"];
+
+/// Split a variant like `kitty/kitty.conf##os.macos` into the path it
+/// deploys as and its condition, `os` and `macos`. `None` for other files.
+pub fn parse_variant(path: &Path) -> Result<Option<(PathBuf, String)>, anyhow::Error> {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(None);
+    };
+    let Some((base, condition)) = name.split_once(VARIANT_SEPARATOR) else {
+        return Ok(None);
+    };
+    let valid = condition
+        .split_once('.')
+        .is_some_and(|(key, value)| VARIANT_KEYS.contains(&key) && !value.is_empty());
+    if base.is_empty() || !valid {
+        anyhow::bail!(
+            "Invalid variant {}, expected a name like file{}hostname.myhost, with hostname, os or profile",
+            path.display(),
+            VARIANT_SEPARATOR
+        );
+    }
+    Ok(Some((path.with_file_name(base), condition.to_string())))
+}
+
+// The path `relative_path` deploys as here and how specific it is: 3 for
+// this host's variant down to 0 for a plain file. `None` for a variant of
+// another host, os or profile.
+fn select_variant(
+    relative_path: &Path,
+    ctx: &Context,
+) -> Result<Option<(PathBuf, usize)>, anyhow::Error> {
+    let Some((target, condition)) = parse_variant(relative_path)? else {
+        return Ok(Some((relative_path.to_path_buf(), 0)));
+    };
+    let (key, value) = condition.split_once('.').unwrap_or_default();
+    let system = |name: &str| {
+        ctx.get_system_variables()
+            .get(name)
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+    };
+    let applies = match key {
+        "hostname" => system("hostname").is_some_and(|host| host.eq_ignore_ascii_case(value)),
+        "os" => system("os").is_some_and(|os| os == value),
+        _ => ctx
+            .profile
+            .as_ref()
+            .is_some_and(|profile| profile.name == value),
+    };
+    let rank = VARIANT_KEYS.len() - VARIANT_KEYS.iter().position(|k| *k == key).unwrap_or(0);
+    Ok(applies.then_some((target, rank)))
+}
+
+// The template that `path` would be rendered from, `path` plus `.tera`
+fn with_tera_ext(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(TEMPLATE_EXT);
+    PathBuf::from(name)
+}
+
 pub fn is_templated_str(s: &str) -> bool {
     TEMPLATE_REGEX.is_match(s)
 }
 
-const RED: &str = "31";
-const GREEN: &str = "32";
+/// Whether `s` has template markers and they parse as a Tera template, so
+/// literal braces like `{{` in a comment aren't taken for one.
+pub fn parses_as_template(s: &str) -> bool {
+    is_templated_str(s) && tera::Template::new("", None, s).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_keeps_dashed_words() {
+        assert_eq!(name_from_file_name("wezterm-config"), "wezterm_config");
+        assert_eq!(name_from_file_name("oh-my-zsh"), "oh_my_zsh");
+    }
+
+    #[test]
+    fn test_name_strips_trailing_version() {
+        assert_eq!(name_from_file_name("foo-2.1"), "foo");
+        assert_eq!(name_from_file_name("foo-3.0"), "foo");
+        assert_eq!(name_from_file_name("nvim-v0.10.1"), "nvim");
+        assert_eq!(name_from_file_name("python-3"), "python");
+    }
+
+    #[test]
+    fn test_name_keeps_non_version_suffix() {
+        assert_eq!(name_from_file_name("foo-2.1-beta"), "foo_2_1_beta");
+        assert_eq!(name_from_file_name("foo-v"), "foo_v");
+        assert_eq!(name_from_file_name("foo-2."), "foo_2_");
+    }
 
-pub fn print_with_color(s: &str, color_code: &str) {
-    println!("\x1b[{}m{}\x1b[0m", color_code, s);
+    #[test]
+    fn test_name_of_dotfile() {
+        assert_eq!(name_from_file_name(".tmux.conf"), "tmux_conf");
+        assert_eq!(name_from_file_name(".bashrc"), "bashrc");
+    }
+
+    #[test]
+    fn test_name_of_dotted_directory() {
+        let dir = std::env::temp_dir().join(format!("dotr_pkg_name_{}", uuid::Uuid::new_v4()));
+        let dotted = dir.join(".oh-my-zsh");
+        std::fs::create_dir_all(&dotted).unwrap();
+        let name = get_package_name(dotted.to_str().unwrap(), &None, &dir).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(name, "d_oh_my_zsh");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_never_widens_the_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("dotr_write_atomic_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let dest = dir.join("netrc");
+
+        // A leftover temporary file doesn't lend its mode
+        std::fs::write(atomic_temp_path(&dest), "stale").unwrap();
+        set_temp_mode(&atomic_temp_path(&dest), 0o644).unwrap();
+        write_new(&atomic_temp_path(&dest), b"secret", Some(0o600)).unwrap();
+        assert_eq!(mode(&atomic_temp_path(&dest)), 0o600);
+        std::fs::remove_file(atomic_temp_path(&dest)).unwrap();
+
+        write_atomic(&dest, b"secret", Some(0o600)).unwrap();
+        assert_eq!(mode(&dest), 0o600);
+        // Without a mode, the dest keeps its own
+        write_atomic(&dest, b"new secret", None).unwrap();
+        assert_eq!(mode(&dest), 0o600);
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "new secret");
+        std::fs::remove_dir_all(&dir).ok();
+    }
 }
@@ -0,0 +1,102 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::manifest::sha256_file;
+
+// Bumped when the layout of a plan changes, older plans are refused
+pub const PLAN_VERSION: u32 = 1;
+
+/// What `deploy --plan` found it would do, for `deploy --apply` to carry out
+/// later exactly as planned.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Plan {
+    pub version: u32,
+    pub profile: Option<String>, // The profile the files were rendered with
+    pub files: Vec<PlannedFile>,
+}
+
+/// One file of a plan: `src` rendered to content with SHA-256 `hash`, to be
+/// written over `dest`, which held `replaces` when planned.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct PlannedFile {
+    pub package: String,
+    pub src: PathBuf,
+    pub dest: PathBuf,
+    pub action: PlanAction,
+    pub hash: String,
+    pub replaces: Option<String>, // None when dest didn't exist
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanAction {
+    Create, // dest doesn't exist
+    Update, // dest holds something else
+    Skip,   // dest is already up to date
+}
+
+impl Plan {
+    pub fn new(profile: Option<String>) -> Self {
+        Self {
+            version: PLAN_VERSION,
+            profile,
+            files: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let plan: Self = serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))?;
+        if plan.version != PLAN_VERSION {
+            anyhow::bail!(
+                "{} is a version {} plan, this dotr applies version {}, plan again",
+                path.display(),
+                plan.version,
+                PLAN_VERSION
+            );
+        }
+        Ok(plan)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn count(&self, action: PlanAction) -> usize {
+        self.files.iter().filter(|f| f.action == action).count()
+    }
+
+    /// The packages in the plan, in the order they were planned.
+    pub fn package_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for file in self.files.iter() {
+            if !names.contains(&file.package) {
+                names.push(file.package.clone());
+            }
+        }
+        names
+    }
+
+    /// The dests that no longer hold what they held when planned.
+    pub fn drifted(&self) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let mut drifted = Vec::new();
+        for file in self.files.iter() {
+            let current = if file.dest.is_file() {
+                Some(sha256_file(&file.dest)?)
+            } else {
+                None
+            };
+            if current != file.replaces {
+                drifted.push(file.dest.clone());
+            }
+        }
+        Ok(drifted)
+    }
+}
@@ -2,13 +2,20 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use toml::{Table, Value};
 
+use crate::{context::check_reserved_variables, prompts::Prompt};
+
+// The keys a profile table understands, see `Config::load`
+pub const PROFILE_KEYS: &[&str] = &["dependencies", "extends", "prompts", "variables"];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Profile {
     pub name: String,
     pub variables: Table,
     pub dependencies: Vec<String>,
     #[serde(default)]
-    pub prompts: HashMap<String, String>, // Profile-level prompts
+    pub prompts: HashMap<String, Prompt>, // Profile-level prompts
+    #[serde(default)]
+    pub extends: Option<String>, // Parent profile, see `Config::resolve_profile`
 }
 
 impl Profile {
@@ -18,6 +25,7 @@ impl Profile {
             variables: Table::new(),
             dependencies: Vec::new(),
             prompts: HashMap::new(),
+            extends: None,
         }
     }
 
@@ -29,6 +37,7 @@ impl Profile {
                 .ok_or_else(|| anyhow::anyhow!("Profile '{}' variables must be a table", name))?
                 .clone();
         }
+        check_reserved_variables(&variables, &format!("profile '{}'", name))?;
 
         let mut dependencies = Vec::new();
         if let Some(deps) = table.get("dependencies") {
@@ -49,18 +58,28 @@ impl Profile {
                 .as_table()
                 .ok_or_else(|| anyhow::anyhow!("Profile '{}' prompts must be a table", name))?;
             for (key, value) in prompts_table {
-                let prompt_str = value.as_str().ok_or_else(|| {
-                    anyhow::anyhow!("Profile '{}' prompt message must be a string", name)
-                })?;
-                prompts.insert(key.clone(), prompt_str.to_string());
+                let prompt = Prompt::from_value(key, value)
+                    .map_err(|e| anyhow::anyhow!("Profile '{}': {}", name, e))?;
+                prompts.insert(key.clone(), prompt);
             }
         }
 
+        let extends = match table.get("extends") {
+            Some(parent) => Some(
+                parent
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Profile '{}' extends must be a string", name))?
+                    .to_string(),
+            ),
+            None => None,
+        };
+
         Ok(Self {
             name: name.to_string(),
             variables,
             dependencies,
             prompts,
+            extends,
         })
     }
 
@@ -80,11 +99,37 @@ impl Profile {
         if !self.prompts.is_empty() {
             let mut prompts_table = Table::new();
             for (key, value) in &self.prompts {
-                prompts_table.insert(key.clone(), Value::String(value.clone()));
+                prompts_table.insert(key.clone(), value.to_value());
             }
             table.insert("prompts".to_string(), Value::Table(prompts_table));
         }
 
+        if let Some(parent) = &self.extends {
+            table.insert("extends".to_string(), Value::String(parent.clone()));
+        }
+
         table
     }
+
+    /// Layer this profile on top of its parent: dependencies are appended after
+    /// the parent's without duplicates, and variables and prompts override the parent's.
+    pub fn merged_onto(&self, parent: &Profile) -> Profile {
+        let mut dependencies = parent.dependencies.clone();
+        for dep in self.dependencies.iter() {
+            if !dependencies.contains(dep) {
+                dependencies.push(dep.clone());
+            }
+        }
+        let mut variables = parent.variables.clone();
+        variables.extend(self.variables.clone());
+        let mut prompts = parent.prompts.clone();
+        prompts.extend(self.prompts.clone());
+        Profile {
+            name: self.name.clone(),
+            variables,
+            dependencies,
+            prompts,
+            extends: self.extends.clone(),
+        }
+    }
 }
@@ -0,0 +1,288 @@
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::utils::{LogLevel, cprintln, prompt};
+
+// The keys of a prompt's long form. `secret` is only read by the config-level prompts.
+const PROMPT_KEYS: &[&str] = &["choices", "default", "message", "pattern", "secret", "type"];
+
+/// How many times an invalid answer is asked again before giving up.
+pub const MAX_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptKind {
+    #[default]
+    String,
+    Int,
+    Bool,
+    Choice,
+}
+
+/// A variable asked for on the first deploy. Either a bare message, or a
+/// table with a type, a default, choices and a pattern to check the answer.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Prompt {
+    pub message: String,
+    #[serde(default)]
+    pub kind: PromptKind,
+    #[serde(default)]
+    pub default: Option<Value>, // Used for an empty answer, and with --no-input
+    #[serde(default)]
+    pub choices: Vec<String>, // The allowed answers of a choice prompt
+    #[serde(default)]
+    pub pattern: Option<String>, // Regex a string answer must match in full
+}
+
+impl From<String> for Prompt {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&str> for Prompt {
+    fn from(message: &str) -> Self {
+        Self::from(message.to_string())
+    }
+}
+
+impl PromptKind {
+    fn parse(kind: &str) -> Result<Self, anyhow::Error> {
+        match kind {
+            "string" => Ok(Self::String),
+            "int" => Ok(Self::Int),
+            "bool" => Ok(Self::Bool),
+            "choice" => Ok(Self::Choice),
+            other => anyhow::bail!(
+                "unknown type '{}', expected string, int, bool or choice",
+                other
+            ),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Int => "int",
+            Self::Bool => "bool",
+            Self::Choice => "choice",
+        }
+    }
+}
+
+impl Prompt {
+    /// Parse the prompt of variable `key`, a message string or a table.
+    pub fn from_value(key: &str, value: &Value) -> Result<Self, anyhow::Error> {
+        let invalid = |e: String| anyhow::anyhow!("Prompt '{}': {}", key, e);
+        let table = match value {
+            Value::String(message) => return Ok(Self::from(message.clone())),
+            Value::Table(table) => table,
+            _ => return Err(invalid("must be a message or a table".to_string())),
+        };
+        if let Some(problem) = crate::utils::unknown_keys(table, PROMPT_KEYS).first() {
+            return Err(invalid(problem.clone()));
+        }
+        let message = table
+            .get("message")
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Prompt '{}' must have a message", key))?
+            .to_string();
+        let kind = match table.get("type") {
+            Some(kind) => PromptKind::parse(
+                kind.as_str()
+                    .ok_or_else(|| invalid("'type' must be a string".to_string()))?,
+            )
+            .map_err(|e| invalid(e.to_string()))?,
+            None => PromptKind::String,
+        };
+        let choices = match table.get("choices") {
+            Some(choices) => choices
+                .as_array()
+                .and_then(|a| {
+                    a.iter()
+                        .map(|c| c.as_str().map(|s| s.to_string()))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .ok_or_else(|| invalid("'choices' must be an array of strings".to_string()))?,
+            None => Vec::new(),
+        };
+        let pattern = match table.get("pattern") {
+            Some(pattern) => {
+                let pattern = pattern
+                    .as_str()
+                    .ok_or_else(|| invalid("'pattern' must be a string".to_string()))?;
+                full_match_regex(pattern)
+                    .map_err(|e| invalid(format!("invalid pattern: {}", e)))?;
+                Some(pattern.to_string())
+            }
+            None => None,
+        };
+        match kind {
+            PromptKind::Choice if choices.is_empty() => {
+                return Err(invalid("a choice prompt needs 'choices'".to_string()));
+            }
+            PromptKind::Choice => {}
+            _ if !choices.is_empty() => {
+                return Err(invalid("'choices' needs type = \"choice\"".to_string()));
+            }
+            _ => {}
+        }
+        if pattern.is_some() && kind != PromptKind::String {
+            return Err(invalid(
+                "'pattern' only applies to string prompts".to_string(),
+            ));
+        }
+        let mut prompt = Self {
+            message,
+            kind,
+            default: None,
+            choices,
+            pattern,
+        };
+        if let Some(default) = table.get("default") {
+            // The default goes through the same checks as a typed answer
+            let answer = match default {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            prompt.default = Some(
+                prompt
+                    .parse_answer(&answer)
+                    .map_err(|e| invalid(format!("invalid default: {}", e)))?,
+            );
+        }
+        Ok(prompt)
+    }
+
+    /// A bare message when nothing else is set, so simple prompts stay simple.
+    pub fn to_value(&self) -> Value {
+        if self.kind == PromptKind::String && self.default.is_none() && self.pattern.is_none() {
+            return Value::String(self.message.clone());
+        }
+        let mut table = Table::new();
+        table.insert("message".to_string(), Value::String(self.message.clone()));
+        table.insert(
+            "type".to_string(),
+            Value::String(self.kind.as_str().to_string()),
+        );
+        if let Some(default) = &self.default {
+            table.insert("default".to_string(), default.clone());
+        }
+        if !self.choices.is_empty() {
+            let choices = self
+                .choices
+                .iter()
+                .map(|c| Value::String(c.clone()))
+                .collect();
+            table.insert("choices".to_string(), Value::Array(choices));
+        }
+        if let Some(pattern) = &self.pattern {
+            table.insert("pattern".to_string(), Value::String(pattern.clone()));
+        }
+        Value::Table(table)
+    }
+
+    /// The message with a hint of what is expected, like "(int, default 8080)".
+    pub fn display_message(&self) -> String {
+        let mut hints: Vec<String> = Vec::new();
+        match self.kind {
+            PromptKind::String => {}
+            PromptKind::Int => hints.push("int".to_string()),
+            PromptKind::Bool => hints.push("y/n".to_string()),
+            PromptKind::Choice => hints.push(format!("one of {}", self.choices.join(", "))),
+        }
+        if let Some(default) = &self.default {
+            let default = match default {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            hints.push(format!("default {}", default));
+        }
+        if hints.is_empty() {
+            self.message.clone()
+        } else {
+            format!("{} ({})", self.message, hints.join(", "))
+        }
+    }
+
+    /// A value of the right type to show where one is missing.
+    pub fn placeholder(&self) -> Value {
+        if let Some(default) = &self.default {
+            return default.clone();
+        }
+        match self.kind {
+            PromptKind::String => Value::String(String::new()),
+            PromptKind::Int => Value::Integer(0),
+            PromptKind::Bool => Value::Boolean(false),
+            PromptKind::Choice => Value::String(self.choices[0].clone()),
+        }
+    }
+
+    /// Turn one line of input into a value of the prompt's type. An empty
+    /// answer takes the default; without one it is only valid for strings.
+    pub fn parse_answer(&self, input: &str) -> Result<Value, String> {
+        let input = input.trim();
+        if input.is_empty()
+            && let Some(default) = &self.default
+        {
+            return Ok(default.clone());
+        }
+        match self.kind {
+            PromptKind::String => {
+                if let Some(pattern) = &self.pattern {
+                    let regex = full_match_regex(pattern).map_err(|e| e.to_string())?;
+                    if !regex.is_match(input) {
+                        return Err(format!("'{}' doesn't match {}", input, pattern));
+                    }
+                }
+                Ok(Value::String(input.to_string()))
+            }
+            PromptKind::Int => input
+                .parse::<i64>()
+                .map(Value::Integer)
+                .map_err(|_| format!("'{}' is not a whole number", input)),
+            PromptKind::Bool => match input.to_lowercase().as_str() {
+                "y" | "yes" | "true" | "1" => Ok(Value::Boolean(true)),
+                "n" | "no" | "false" | "0" => Ok(Value::Boolean(false)),
+                _ => Err(format!("'{}' is not yes or no", input)),
+            },
+            PromptKind::Choice => {
+                if self.choices.iter().any(|c| c == input) {
+                    Ok(Value::String(input.to_string()))
+                } else {
+                    Err(format!(
+                        "'{}' is not one of {}",
+                        input,
+                        self.choices.join(", ")
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Ask for the value of variable `key`, asking again on an invalid answer
+    /// up to `MAX_ATTEMPTS` times.
+    pub fn ask(&self, key: &str) -> Result<Value, anyhow::Error> {
+        for _ in 0..MAX_ATTEMPTS {
+            match self.parse_answer(&prompt(&self.display_message())?) {
+                Ok(value) => return Ok(value),
+                Err(e) => cprintln(
+                    &format!("Invalid answer for '{}': {}", key, e),
+                    &LogLevel::WARNING,
+                ),
+            }
+        }
+        anyhow::bail!(
+            "No valid answer for '{}' after {} attempts",
+            key,
+            MAX_ATTEMPTS
+        )
+    }
+}
+
+fn full_match_regex(pattern: &str) -> Result<regex::Regex, regex::Error> {
+    regex::Regex::new(&format!("^(?:{})$", pattern))
+}
@@ -0,0 +1,342 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use toml::{Table, Value};
+
+use crate::{
+    cache::CACHE_DIR,
+    package::is_inner_path,
+    utils::{LogLevel, cprintln},
+};
+
+pub const REMOTE_DIR: &str = "remote";
+pub const REMOTE_KEYS: &[&str] = &["ref", "subdir", "url"];
+const CONTENT_DIR: &str = "content";
+#[cfg(feature = "remote")]
+const REVISION_FILE: &str = "revision"; // The ETag of a tarball, or the commit of a git ref
+
+/// A read-only package source downloaded into `.dotr/remote/<hash>/`: a
+/// tarball, or a git repository when the URL starts with `git+` or ends
+/// with `.git`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct RemoteSource {
+    pub url: String,
+    pub subdir: Option<String>, // Deploy only this directory of the download
+    #[serde(rename = "ref")]
+    pub reference: Option<String>, // Branch, tag or commit of a git source, `ref` in config.toml
+}
+
+/// Whether a fetch got something new or found the cache up to date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchOutcome {
+    Updated,
+    Unchanged,
+}
+
+impl RemoteSource {
+    pub fn from_table(table: &Table) -> Result<Self, anyhow::Error> {
+        if let Some(key) = table.keys().find(|k| !REMOTE_KEYS.contains(&k.as_str())) {
+            anyhow::bail!(
+                "Unknown key '{}' in remote, expected url, subdir or ref",
+                key
+            );
+        }
+        let field = |key: &str| -> Result<Option<String>, anyhow::Error> {
+            match table.get(key) {
+                Some(v) => Ok(Some(
+                    v.as_str()
+                        .ok_or_else(|| anyhow::anyhow!("remote.{} must be a string", key))?
+                        .to_string(),
+                )),
+                None => Ok(None),
+            }
+        };
+        let url = field("url")?.ok_or_else(|| anyhow::anyhow!("remote needs a url"))?;
+        let remote = Self {
+            url,
+            subdir: field("subdir")?,
+            reference: field("ref")?,
+        };
+        remote.validate()?;
+        Ok(remote)
+    }
+
+    /// Refuse a subdir reaching outside the download, and a url or ref that
+    /// git would read as an option.
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if let Some(subdir) = &self.subdir
+            && !is_inner_path(subdir)
+        {
+            anyhow::bail!(
+                "remote subdir '{}' must be a path inside the download",
+                subdir
+            );
+        }
+        if self.url.starts_with('-') {
+            anyhow::bail!("remote url '{}' must not start with '-'", self.url);
+        }
+        if let Some(reference) = &self.reference
+            && reference.starts_with('-')
+        {
+            anyhow::bail!("remote ref '{}' must not start with '-'", reference);
+        }
+        Ok(())
+    }
+
+    pub fn to_table(&self) -> Table {
+        let mut table = Table::new();
+        table.insert("url".to_string(), Value::String(self.url.clone()));
+        if let Some(subdir) = &self.subdir {
+            table.insert("subdir".to_string(), Value::String(subdir.clone()));
+        }
+        if let Some(reference) = &self.reference {
+            table.insert("ref".to_string(), Value::String(reference.clone()));
+        }
+        table
+    }
+
+    /// Read a src written as a URL, with `subdir` and `ref` in its fragment:
+    /// `https://host/archive/main.tar.gz#subdir=zsh`. `None` for local paths.
+    pub fn from_url(src: &str) -> Option<Self> {
+        let is_url = ["http://", "https://", "file://", "git+"]
+            .iter()
+            .any(|scheme| src.starts_with(scheme));
+        if !is_url {
+            return None;
+        }
+        let (url, fragment) = src.split_once('#').unwrap_or((src, ""));
+        let mut remote = Self {
+            url: url.to_string(),
+            subdir: None,
+            reference: None,
+        };
+        for pair in fragment.split('&').filter(|pair| !pair.is_empty()) {
+            match pair.split_once('=') {
+                Some(("subdir", subdir)) => remote.subdir = Some(subdir.to_string()),
+                Some(("ref", reference)) => remote.reference = Some(reference.to_string()),
+                _ => {}
+            }
+        }
+        Some(remote)
+    }
+
+    pub fn is_git(&self) -> bool {
+        self.url.starts_with("git+") || self.url.trim_end_matches('/').ends_with(".git")
+    }
+
+    /// The cache of this URL and ref, shared by the packages using its subdirs.
+    pub fn cache_dir(&self, working_dir: &Path) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(self.url.as_bytes());
+        hasher.update(b"#");
+        hasher.update(self.reference.as_deref().unwrap_or_default().as_bytes());
+        let hash = format!("{:x}", hasher.finalize());
+        working_dir
+            .join(CACHE_DIR)
+            .join(REMOTE_DIR)
+            .join(&hash[..16])
+    }
+
+    /// Where the package deploys from, whether it was fetched yet or not.
+    pub fn path(&self, working_dir: &Path) -> PathBuf {
+        let content = self.cache_dir(working_dir).join(CONTENT_DIR);
+        match &self.subdir {
+            Some(subdir) => content.join(subdir),
+            None => content,
+        }
+    }
+
+    pub fn is_cached(&self, working_dir: &Path) -> bool {
+        self.cache_dir(working_dir).join(CONTENT_DIR).is_dir()
+    }
+
+    /// Download the source unless it's cached already.
+    pub fn ensure(&self, working_dir: &Path, offline: bool) -> Result<(), anyhow::Error> {
+        if self.is_cached(working_dir) {
+            return Ok(());
+        }
+        if offline {
+            anyhow::bail!(
+                "{} isn't downloaded yet and --offline is set, run `dotr fetch` online first",
+                self.url
+            );
+        }
+        self.fetch(working_dir)?;
+        Ok(())
+    }
+
+    /// Download the source again, keeping the cache when its ETag or commit
+    /// didn't change.
+    #[cfg(feature = "remote")]
+    pub fn fetch(&self, working_dir: &Path) -> Result<FetchOutcome, anyhow::Error> {
+        self.validate()?;
+        let cache_dir = self.cache_dir(working_dir);
+        std::fs::create_dir_all(&cache_dir)?;
+        let revision_path = cache_dir.join(REVISION_FILE);
+        // Without content to fall back on, a 304 would leave nothing to deploy
+        let previous = self
+            .is_cached(working_dir)
+            .then(|| std::fs::read_to_string(&revision_path).ok())
+            .flatten();
+        let archive = cache_dir.join("archive.tmp");
+        let revision = if self.is_git() {
+            download::git_archive(self, &cache_dir, &archive)?
+        } else {
+            download::tarball(self, &cache_dir, previous.as_deref(), &archive)?
+        };
+        let Some(revision) = revision else {
+            return Ok(FetchOutcome::Unchanged);
+        };
+        if previous.as_deref() == Some(revision.as_str()) {
+            std::fs::remove_file(&archive).ok();
+            return Ok(FetchOutcome::Unchanged);
+        }
+        download::extract(&archive, &cache_dir.join(CONTENT_DIR))?;
+        std::fs::remove_file(&archive).ok();
+        std::fs::write(&revision_path, &revision)?;
+        Ok(FetchOutcome::Updated)
+    }
+
+    #[cfg(not(feature = "remote"))]
+    pub fn fetch(&self, _working_dir: &Path) -> Result<FetchOutcome, anyhow::Error> {
+        anyhow::bail!(
+            "Can't download {}, this dotr was built without the 'remote' feature",
+            self.url
+        )
+    }
+}
+
+/// Log the outcome of fetching a package's remote source.
+pub fn report_fetch(package: &str, remote: &RemoteSource, outcome: FetchOutcome) {
+    let message = match outcome {
+        FetchOutcome::Updated => format!("Fetched '{}' from {}", package, remote.url),
+        FetchOutcome::Unchanged => format!("'{}' is up to date with {}", package, remote.url),
+    };
+    cprintln(&message, &LogLevel::INFO);
+}
+
+// curl, git and tar do the network and archive work, so no HTTP client is linked in
+#[cfg(feature = "remote")]
+mod download {
+    use std::{
+        path::Path,
+        process::{Command, Output},
+    };
+
+    use super::RemoteSource;
+    use crate::manifest::sha256_file;
+
+    fn run(command: &mut Command) -> Result<Output, anyhow::Error> {
+        let program = command.get_program().to_string_lossy().to_string();
+        let output = command
+            .output()
+            .map_err(|e| anyhow::anyhow!("Failed to run {}: {}", program, e))?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "{} failed: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output)
+    }
+
+    /// Download a tarball to `archive`, sending the last ETag along. Returns
+    /// the new ETag, or the archive's SHA-256 when the server sends none.
+    /// `None` when the server says the cache is current.
+    pub fn tarball(
+        remote: &RemoteSource,
+        cache_dir: &Path,
+        etag: Option<&str>,
+        archive: &Path,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let etag_file = cache_dir.join("etag.tmp");
+        let mut curl = Command::new("curl");
+        curl.args(["-fsSL", "-w", "%{http_code}", "-o"])
+            .arg(archive)
+            .arg("--etag-save")
+            .arg(&etag_file);
+        if let Some(etag) = etag.filter(|etag| etag.starts_with('"') || etag.starts_with("W/")) {
+            curl.args(["-H", &format!("If-None-Match: {}", etag)]);
+        }
+        let output = run(curl.arg(&remote.url))
+            .map_err(|e| anyhow::anyhow!("Failed to download {}: {}", remote.url, e))?;
+        let saved = std::fs::read_to_string(&etag_file).unwrap_or_default();
+        std::fs::remove_file(&etag_file).ok();
+        if String::from_utf8_lossy(&output.stdout).trim() == "304" {
+            return Ok(None);
+        }
+        let saved = saved.trim();
+        if saved.is_empty() {
+            return sha256_file(archive).map(Some);
+        }
+        Ok(Some(saved.to_string()))
+    }
+
+    /// Fetch the ref of a git source into a bare repository in the cache and
+    /// write its tree to `archive`. Returns the commit.
+    pub fn git_archive(
+        remote: &RemoteSource,
+        cache_dir: &Path,
+        archive: &Path,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let url = remote.url.strip_prefix("git+").unwrap_or(&remote.url);
+        let repo = cache_dir.join("repo.git");
+        if !repo.exists() {
+            run(Command::new("git")
+                .args(["init", "-q", "--bare"])
+                .arg(&repo))?;
+        }
+        let reference = remote.reference.as_deref().unwrap_or("HEAD");
+        run(Command::new("git")
+            .arg("--git-dir")
+            .arg(&repo)
+            .args(["fetch", "-q", "--depth", "1", url, reference]))
+        .map_err(|e| anyhow::anyhow!("Failed to fetch {} from {}: {}", reference, url, e))?;
+        let commit = run(Command::new("git")
+            .arg("--git-dir")
+            .arg(&repo)
+            .args(["rev-parse", "FETCH_HEAD"]))?;
+        run(Command::new("git")
+            .arg("--git-dir")
+            .arg(&repo)
+            .args(["archive", "--format=tar", "-o"])
+            .arg(archive)
+            .arg("FETCH_HEAD"))?;
+        Ok(Some(
+            String::from_utf8_lossy(&commit.stdout).trim().to_string(),
+        ))
+    }
+
+    /// Unpack `archive` in place of `content`. A single top-level directory,
+    /// as in GitHub's archives, becomes the root.
+    pub fn extract(archive: &Path, content: &Path) -> Result<(), anyhow::Error> {
+        let unpacked = content.with_extension("tmp");
+        if unpacked.exists() {
+            std::fs::remove_dir_all(&unpacked)?;
+        }
+        std::fs::create_dir_all(&unpacked)?;
+        // -m: the archive's second-granular mtimes could match the last
+        // download's and keep the deploy cache from noticing the change
+        run(Command::new("tar")
+            .arg("-xmf")
+            .arg(archive)
+            .arg("-C")
+            .arg(&unpacked))
+        .map_err(|e| anyhow::anyhow!("Failed to unpack {}: {}", archive.display(), e))?;
+        let entries = std::fs::read_dir(&unpacked)?.collect::<Result<Vec<_>, _>>()?;
+        let root = match entries.as_slice() {
+            [only] if only.path().is_dir() => only.path(),
+            _ => unpacked.clone(),
+        };
+        if content.exists() {
+            std::fs::remove_dir_all(content)?;
+        }
+        std::fs::rename(&root, content)?;
+        if unpacked.exists() {
+            std::fs::remove_dir_all(&unpacked)?;
+        }
+        Ok(())
+    }
+}
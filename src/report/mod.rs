@@ -0,0 +1,123 @@
+use std::{path::PathBuf, time::Duration};
+
+pub mod output;
+
+/// What a deploy, update or diff did, package by package, in the order the
+/// packages were handled. A run stops at the first package that fails, so
+/// only the last entry can have an error.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub packages: Vec<PackageReport>,
+    pub elapsed: Duration, // How long the whole run took
+}
+
+#[derive(Debug, Default)]
+pub struct PackageReport {
+    pub name: String,
+    pub deployed: Vec<PathBuf>,         // Dests written by deploy
+    pub skipped: Vec<PathBuf>,          // Dests already up to date, or kept on a conflict
+    pub backed_up: Vec<PathBuf>, // Sources copied back by update, or that would be on a dry run
+    pub backups: Vec<PathBuf>, // Copies deploy made of dests before overwriting them, see backup_dir
+    pub diffs: Vec<FileDiff>,  // From diff, and from an update dry run
+    pub actions: Vec<String>,  // Pre and post actions that ran, as configured
+    pub pruned: Vec<PathBuf>,  // Sources removed by update --prune-missing, or that would be
+    pub removed: Vec<PathBuf>, // Stale dests of files deleted from the repository, removed by deploy or found by diff
+    pub failed: Vec<(PathBuf, String)>, // Files that couldn't be written or copied back, and why
+    pub error: Option<anyhow::Error>,
+}
+
+/// One file compared by diff or an update dry run: `src` would be written
+/// over `dest`. Contents that aren't UTF-8 are None.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileDiff {
+    pub src: PathBuf,
+    pub dest: PathBuf,
+    pub old: Option<String>, // What dest holds now
+    pub new: Option<String>, // What would be written
+}
+
+impl Report {
+    pub fn has_error(&self) -> bool {
+        self.packages.iter().any(|p| p.error.is_some())
+    }
+
+    /// The report, or the error of the package that failed.
+    pub fn into_result(mut self) -> Result<Self, anyhow::Error> {
+        match self.packages.iter_mut().find_map(|p| p.error.take()) {
+            Some(e) => Err(e),
+            None => Ok(self),
+        }
+    }
+
+    pub fn backed_up_count(&self) -> usize {
+        self.packages.iter().map(|p| p.backed_up.len()).sum()
+    }
+
+    pub fn pruned_count(&self) -> usize {
+        self.packages.iter().map(|p| p.pruned.len()).sum()
+    }
+
+    /// A table of the files each package wrote (dests on deploy, sources on
+    /// update), left unchanged and backed up, and whether it failed.
+    pub fn summary(&self, title: &str) -> String {
+        let header = ["package", "written", "unchanged", "backed up", "errors"];
+        let rows: Vec<[String; 5]> = self
+            .packages
+            .iter()
+            .map(|p| {
+                [
+                    p.name.clone(),
+                    (p.deployed.len() + p.backed_up.len()).to_string(),
+                    p.skipped.len().to_string(),
+                    p.backups.len().to_string(),
+                    p.failed
+                        .len()
+                        .max(usize::from(p.error.is_some()))
+                        .to_string(),
+                ]
+            })
+            .collect();
+        let widths: Vec<usize> = (0..header.len())
+            .map(|i| {
+                rows.iter()
+                    .map(|row| row[i].len())
+                    .chain([header[i].len()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        let line = |cells: [&str; 5]| {
+            let mut line = format!("\n  {:<width$}", cells[0], width = widths[0]);
+            for (cell, width) in cells.iter().zip(widths.iter()).skip(1) {
+                line.push_str(&format!("  {:>width$}", cell, width = width));
+            }
+            line
+        };
+        let mut summary = format!(
+            "{} {} package(s) in {:.2}s:",
+            title,
+            self.packages.len(),
+            self.elapsed.as_secs_f64()
+        );
+        summary.push_str(&line(header));
+        for row in rows.iter() {
+            summary.push_str(&line([&row[0], &row[1], &row[2], &row[3], &row[4]]));
+        }
+        summary
+    }
+}
+
+impl PackageReport {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+impl FileDiff {
+    pub fn is_changed(&self) -> bool {
+        self.old != self.new
+    }
+}
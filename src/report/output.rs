@@ -0,0 +1,227 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{manifest::FileStatus, report::PackageReport, state::LastDeploy};
+
+// Lines of unchanged context around the changes of a hunk, as `diff -u` shows
+const HUNK_CONTEXT: usize = 3;
+
+/// How diff, status, list and verify print what they found.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json, // A single `Document` on stdout, for editors and scripts
+}
+
+/// What `--output json` prints: one object per run, whichever command made
+/// it. Fields that don't apply to a command are left out, the others keep
+/// their name and meaning across commands.
+#[derive(Debug, Serialize)]
+pub struct Document {
+    pub version: String,
+    pub working_dir: PathBuf,
+    pub profile: Option<String>, // The active profile, null without one
+    pub packages: Vec<PackageEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<GroupEntry>, // Only from `list --groups`, which has no packages
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PackageEntry {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<PackageStatus>, // From status
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dest: Option<String>, // From list, as configured
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dests: Vec<PathBuf>, // From status, resolved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_deploy: Option<LastDeploy>, // From status, when it was ever deployed
+    pub files: Vec<FileEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>, // Why diff stopped at this package
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageStatus {
+    NeverDeployed,
+    Deployed,
+    ChangedSince, // Its source changed after the last deploy
+    OutOfSync,    // Files removed from its source are still at dest
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileEntry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub src: Option<PathBuf>,
+    pub dest: PathBuf,
+    pub state: FileState,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hunks: Vec<Hunk>, // From diff, empty when the file is unchanged or not text
+}
+
+/// The state of one file. Diff reports `unchanged`, `changed` and
+/// `removed`; status and verify compare dests with what deploy wrote and
+/// report `ok`, `modified` and `missing`, status also `removed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileState {
+    Unchanged,
+    Changed,
+    Removed, // Deleted from the source but still at dest, deploy removes it
+    Ok,
+    Modified,
+    Missing,
+}
+
+/// A run of changed lines with the context around them, numbered like the
+/// `@@ -old_start,old_lines +new_start,new_lines @@` header of a unified diff.
+/// Each line starts with ' ', '-' or '+'.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupEntry {
+    pub name: String,
+    pub members: Vec<String>,
+    pub missing: Vec<String>, // Members that aren't packages
+}
+
+impl Document {
+    pub fn new(working_dir: &Path, profile: Option<String>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            working_dir: working_dir.to_path_buf(),
+            profile,
+            packages: Vec::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, anyhow::Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl PackageEntry {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&PackageReport> for PackageEntry {
+    fn from(report: &PackageReport) -> Self {
+        let mut files: Vec<FileEntry> = report
+            .diffs
+            .iter()
+            .map(|diff| FileEntry {
+                src: Some(diff.src.clone()),
+                dest: diff.dest.clone(),
+                state: if diff.is_changed() {
+                    FileState::Changed
+                } else {
+                    FileState::Unchanged
+                },
+                hunks: match (&diff.old, &diff.new) {
+                    (Some(old), Some(new)) => hunks(old, new),
+                    _ => Vec::new(),
+                },
+            })
+            .collect();
+        files.extend(report.removed.iter().map(|dest| FileEntry {
+            src: None,
+            dest: dest.clone(),
+            state: FileState::Removed,
+            hunks: Vec::new(),
+        }));
+        Self {
+            name: report.name.clone(),
+            files,
+            error: report.error.as_ref().map(|e| format!("{:#}", e)),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<FileStatus> for FileState {
+    fn from(status: FileStatus) -> Self {
+        match status {
+            FileStatus::Ok => FileState::Ok,
+            FileStatus::Modified => FileState::Modified,
+            FileStatus::Missing => FileState::Missing,
+        }
+    }
+}
+
+impl std::fmt::Display for PackageStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            PackageStatus::NeverDeployed => "never deployed",
+            PackageStatus::Deployed => "deployed",
+            PackageStatus::ChangedSince => "changed since",
+            PackageStatus::OutOfSync => "out of sync",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+/// The line changes from `old` to `new`, grouped into hunks with up to
+/// three lines of context. Changes closer than twice that share a hunk.
+pub fn hunks(old: &str, new: &str) -> Vec<Hunk> {
+    // By `str::lines`, so a trailing newline doesn't count as an empty last line
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let lines: Vec<(char, &str)> = diff::slice(&old, &new)
+        .into_iter()
+        .map(|line| match line {
+            diff::Result::Left(l) => ('-', *l),
+            diff::Result::Both(l, _) => (' ', *l),
+            diff::Result::Right(r) => ('+', *r),
+        })
+        .collect();
+    let changes: Vec<usize> = (0..lines.len()).filter(|&i| lines[i].0 != ' ').collect();
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &i in changes.iter() {
+        let start = i.saturating_sub(HUNK_CONTEXT);
+        let end = (i + HUNK_CONTEXT + 1).min(lines.len());
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let before = &lines[..start];
+            let old_before = before.iter().filter(|(tag, _)| *tag != '+').count();
+            let new_before = before.iter().filter(|(tag, _)| *tag != '-').count();
+            let hunk = &lines[start..end];
+            let old_lines = hunk.iter().filter(|(tag, _)| *tag != '+').count();
+            let new_lines = hunk.iter().filter(|(tag, _)| *tag != '-').count();
+            // An empty side is numbered after the line it follows, as in `diff -u`
+            Hunk {
+                old_start: old_before + usize::from(old_lines > 0),
+                old_lines,
+                new_start: new_before + usize::from(new_lines > 0),
+                new_lines,
+                lines: hunk
+                    .iter()
+                    .map(|(tag, line)| format!("{}{}", tag, line))
+                    .collect(),
+            }
+        })
+        .collect()
+}
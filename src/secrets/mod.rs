@@ -0,0 +1,167 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use age::x25519::Identity;
+use toml::Table;
+
+use crate::{
+    config::Config,
+    context::{Context, check_reserved_variables},
+    utils::{resolve_path, run_editor},
+};
+
+/// Variable (environment or `.uservariables.toml`) holding the path to the age key file.
+pub const AGE_KEY_VARIABLE: &str = "DOTR_AGE_KEY";
+
+/// The configured `secrets_file`, resolved against the working directory.
+pub fn secrets_path(conf: &Config, working_dir: &Path) -> Option<PathBuf> {
+    conf.secrets_file
+        .as_ref()
+        .map(|file| resolve_path(file, working_dir))
+}
+
+/// Path of the age key file, from `.uservariables.toml` first, then the environment.
+pub fn key_path(ctx: &Context) -> Option<PathBuf> {
+    ctx.get_user_variable(AGE_KEY_VARIABLE)
+        .or_else(|| ctx.get_variable(AGE_KEY_VARIABLE))
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .map(|s| resolve_path(s, &ctx.working_dir))
+}
+
+/// Load the first X25519 identity from an age key file, as written by `age-keygen`.
+pub fn load_identity(path: &Path) -> Result<Identity, anyhow::Error> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read age key '{}': {}", path.display(), e))?;
+    let line = content
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#'))
+        .ok_or_else(|| anyhow::anyhow!("No age identity found in '{}'", path.display()))?;
+    Identity::from_str(line)
+        .map_err(|e| anyhow::anyhow!("Invalid age identity in '{}': {}", path.display(), e))
+}
+
+/// The identity named by `DOTR_AGE_KEY`.
+pub fn context_identity(ctx: &Context) -> Result<Identity, anyhow::Error> {
+    let path = key_path(ctx)
+        .ok_or_else(|| anyhow::anyhow!("{} is not set, can't decrypt secrets", AGE_KEY_VARIABLE))?;
+    load_identity(&path)
+}
+
+/// Decrypt a secrets file into its TOML plaintext. A missing file is empty.
+pub fn decrypt_file(path: &Path, identity: &Identity) -> Result<String, anyhow::Error> {
+    if !path.exists() {
+        return Ok(String::new());
+    }
+    let ciphertext = std::fs::read(path)?;
+    let plaintext = age::decrypt(identity, &ciphertext)
+        .map_err(|e| anyhow::anyhow!("Failed to decrypt '{}': {}", path.display(), e))?;
+    Ok(String::from_utf8(plaintext)?)
+}
+
+/// Encrypt `plaintext` to the identity's own recipient and write it to `path`.
+pub fn encrypt_file(
+    path: &Path,
+    identity: &Identity,
+    plaintext: &str,
+) -> Result<(), anyhow::Error> {
+    let ciphertext = age::encrypt(&identity.to_public(), plaintext.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Failed to encrypt '{}': {}", path.display(), e))?;
+    std::fs::write(path, ciphertext)?;
+    Ok(())
+}
+
+pub fn parse_secrets(plaintext: &str, path: &Path) -> Result<Table, anyhow::Error> {
+    let table: Table = plaintext
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Failed to parse decrypted '{}': {}", path.display(), e))?;
+    check_reserved_variables(&table, &path.display().to_string())?;
+    Ok(table)
+}
+
+/// Decrypt and parse the configured secrets file. None when no file is configured.
+pub fn read_secrets(conf: &Config, ctx: &Context) -> Result<Option<Table>, anyhow::Error> {
+    let Some(path) = secrets_path(conf, &ctx.working_dir) else {
+        return Ok(None);
+    };
+    let identity = context_identity(ctx)?;
+    let plaintext = decrypt_file(&path, &identity)?;
+    parse_secrets(&plaintext, &path).map(Some)
+}
+
+/// Set one top-level string variable in the secrets file.
+pub fn set_secret(
+    conf: &Config,
+    ctx: &Context,
+    key: &str,
+    value: &str,
+) -> Result<(), anyhow::Error> {
+    let path = require_secrets_path(conf, ctx)?;
+    let identity = context_identity(ctx)?;
+    let mut table = parse_secrets(&decrypt_file(&path, &identity)?, &path)?;
+    table.insert(key.to_string(), toml::Value::String(value.to_string()));
+    check_reserved_variables(&table, &path.display().to_string())?;
+    encrypt_file(&path, &identity, &toml::to_string(&table)?)
+}
+
+/// Decrypt the secrets file to a private temp file, open it in `$EDITOR`,
+/// and encrypt the result back. The plaintext is removed however this ends.
+pub fn edit_secrets(conf: &Config, ctx: &Context) -> Result<(), anyhow::Error> {
+    let path = require_secrets_path(conf, ctx)?;
+    let identity = context_identity(ctx)?;
+    let plaintext = decrypt_file(&path, &identity)?;
+
+    let temp = PlaintextFile::create(&plaintext)?;
+    let editor = std::env::var("EDITOR")
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    let status = run_editor(&editor, &temp.path)?;
+    if !status.success() {
+        anyhow::bail!(
+            "Editor exited with {:?}, secrets left unchanged",
+            status.code()
+        );
+    }
+    let edited = std::fs::read_to_string(&temp.path)?;
+    if edited == plaintext {
+        return Ok(());
+    }
+    parse_secrets(&edited, &path).map_err(|e| anyhow::anyhow!("{}, secrets left unchanged", e))?;
+    encrypt_file(&path, &identity, &edited)
+}
+
+fn require_secrets_path(conf: &Config, ctx: &Context) -> Result<PathBuf, anyhow::Error> {
+    secrets_path(conf, &ctx.working_dir)
+        .ok_or_else(|| anyhow::anyhow!("secrets_file is not set in config.toml"))
+}
+
+// Decrypted secrets on disk for as long as the editor needs them
+struct PlaintextFile {
+    path: PathBuf,
+}
+
+impl PlaintextFile {
+    fn create(content: &str) -> Result<Self, anyhow::Error> {
+        let path = std::env::temp_dir().join(format!("dotr-secrets-{}.toml", uuid::Uuid::new_v4()));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = Self { path };
+        std::io::Write::write_all(&mut options.open(&file.path)?, content.as_bytes())?;
+        Ok(file)
+    }
+}
+
+impl Drop for PlaintextFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
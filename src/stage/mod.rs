@@ -0,0 +1,169 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::manifest::sha256_file;
+
+// Where `update --stage-to` copies files when no directory is given
+pub const DEFAULT_STAGE_DIR: &str = ".dotr/staged";
+// Kept at the top of the stage, beside the package directories
+pub const STAGE_INDEX: &str = ".staged.toml";
+
+/// A file `update --stage-to` copied into the stage instead of over its
+/// source, and what that source held then.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct StagedFile {
+    pub src: String,          // The source it goes to, relative to the working directory
+    pub hash: Option<String>, // SHA-256 of the source when staged, None when it didn't exist
+}
+
+/// The staging area of `update --stage-to`: `<dir>/<package>/<path in src>`
+/// holds what update would have written, so `diff -r` against the source
+/// shows it, until `update --apply-staged` copies it over.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct Stage {
+    #[serde(skip)]
+    dir: PathBuf,
+    #[serde(default)]
+    pub files: BTreeMap<String, StagedFile>, // `<package>/<path in src>` to its source
+}
+
+impl Stage {
+    /// Load the stage at `dir`, empty when nothing was staged there.
+    pub fn load(dir: &Path) -> Result<Self, anyhow::Error> {
+        let path = dir.join(STAGE_INDEX);
+        let mut stage: Self = if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+            toml::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path.display(), e))?
+        } else {
+            Self::default()
+        };
+        stage.dir = dir.to_path_buf();
+        Ok(stage)
+    }
+
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(STAGE_INDEX);
+        std::fs::write(&path, toml::to_string(self)?)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Where the file at `relative_path` in the src of `package` is staged.
+    pub fn staged_path(&self, package: &str, relative_path: &Path) -> PathBuf {
+        // A single-file src is staged as the package directory itself
+        if relative_path.as_os_str().is_empty() {
+            return self.dir.join(package);
+        }
+        self.dir.join(package).join(relative_path)
+    }
+
+    /// Note that the file for `src` was staged, with what `src` holds now.
+    /// A file staged again keeps the hash from the first time.
+    pub fn record(
+        &mut self,
+        package: &str,
+        relative_path: &Path,
+        src: &Path,
+        working_dir: &Path,
+    ) -> Result<(), anyhow::Error> {
+        let key = stage_key(package, relative_path);
+        if self.files.contains_key(&key) {
+            return Ok(());
+        }
+        let hash = if src.is_file() {
+            Some(sha256_file(src)?)
+        } else {
+            None
+        };
+        let src = src.strip_prefix(working_dir).unwrap_or(src);
+        self.files.insert(
+            key,
+            StagedFile {
+                src: src.to_string_lossy().to_string(),
+                hash,
+            },
+        );
+        Ok(())
+    }
+
+    /// The sources that changed since their file was staged.
+    pub fn conflicts(&self, working_dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let mut conflicts = Vec::new();
+        for file in self.files.values() {
+            let src = working_dir.join(&file.src);
+            let hash = if src.is_file() {
+                Some(sha256_file(&src)?)
+            } else {
+                None
+            };
+            if hash != file.hash {
+                conflicts.push(src);
+            }
+        }
+        Ok(conflicts)
+    }
+
+    /// Copy every staged file over its source, and return the sources
+    /// written. A staged file removed during review is left out.
+    pub fn apply(&self, working_dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+        let mut applied = Vec::new();
+        for (key, file) in self.files.iter() {
+            let staged = self.dir.join(key);
+            if !staged.is_file() {
+                continue;
+            }
+            let src = working_dir.join(&file.src);
+            if let Some(parent) = src.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(&staged, &src).map_err(|e| {
+                anyhow::anyhow!(
+                    "Failed to copy {} to {}: {}",
+                    staged.display(),
+                    src.display(),
+                    e
+                )
+            })?;
+            applied.push(src);
+        }
+        Ok(applied)
+    }
+
+    /// Remove the stage and everything in it.
+    pub fn clear(&self) -> Result<(), anyhow::Error> {
+        match std::fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(anyhow::anyhow!(
+                "Failed to remove {}: {}",
+                self.dir.display(),
+                e
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Always with `/`, the index reads the same on every OS
+fn stage_key(package: &str, relative_path: &Path) -> String {
+    std::iter::once(package.to_string())
+        .chain(
+            relative_path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string()),
+        )
+        .collect::<Vec<_>>()
+        .join("/")
+}
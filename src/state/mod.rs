@@ -0,0 +1,302 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache::CACHE_DIR,
+    manifest::{FileStatus, sha256_bytes},
+    utils::{LogLevel, can_prompt, cprintln, prompt},
+};
+
+pub const STATE_FILE: &str = "state.toml";
+
+// Records every file deploy wrote, per package, so `clean` can remove exactly
+// those and nothing the user edited since.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct DeployState {
+    #[serde(default)]
+    packages: BTreeMap<String, PackageState>,
+    #[serde(skip)]
+    seen: HashSet<String>, // Dests recorded during this deploy, the rest are pruned
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PackageState {
+    #[serde(default)]
+    pub files: BTreeMap<String, String>, // Dest path to the hash of the content written
+    #[serde(default)]
+    pub dirs: BTreeSet<String>, // Directories deploy created for the files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_deploy: Option<LastDeploy>,
+}
+
+impl PackageState {
+    /// Compare each recorded file with the hash deploy wrote, like
+    /// `Manifest::verify` does with its own hashes.
+    pub fn verify(&self) -> Result<Vec<(PathBuf, FileStatus)>, anyhow::Error> {
+        let mut results = Vec::new();
+        for (dest, hash) in self.files.iter() {
+            let path = PathBuf::from(dest);
+            let status = match std::fs::read(&path) {
+                Ok(content) if sha256_bytes(&content) == *hash => FileStatus::Ok,
+                Ok(_) => FileStatus::Modified,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => FileStatus::Missing,
+                Err(e) => anyhow::bail!("Failed to read {}: {}", path.display(), e),
+            };
+            results.push((path, status));
+        }
+        Ok(results)
+    }
+}
+
+/// When a package was last deployed without an error, and how.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct LastDeploy {
+    pub at: u64, // Seconds since the Unix epoch
+    pub profile: Option<String>,
+    pub files_written: usize, // Files rewritten, not those already up to date
+}
+
+impl LastDeploy {
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.at)
+    }
+
+    /// Whether `time` is after this deploy. Both are compared in whole
+    /// seconds, so a change in the second of the deploy counts as before it.
+    pub fn precedes(&self, time: SystemTime) -> bool {
+        time.duration_since(UNIX_EPOCH)
+            .is_ok_and(|since| since.as_secs() > self.at)
+    }
+
+    /// `last deployed 3 days ago with profile work`
+    pub fn describe(&self) -> String {
+        let mut description = format!("last deployed {}", time_ago(self.time()));
+        if let Some(profile) = &self.profile {
+            description.push_str(&format!(" with profile {}", profile));
+        }
+        description
+    }
+}
+
+impl DeployState {
+    /// Load the state of a working directory, empty when nothing was deployed
+    /// yet. A state file that doesn't parse counts as empty too, with a warning.
+    pub fn load(working_dir: &Path) -> Result<Self, anyhow::Error> {
+        let path = state_path(working_dir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        match toml::from_str(&content) {
+            Ok(state) => Ok(state),
+            Err(e) => {
+                cprintln(
+                    &format!(
+                        "Ignoring {}, it doesn't parse: {}",
+                        path.display(),
+                        e.message()
+                    ),
+                    &LogLevel::WARNING,
+                );
+                Ok(Self::default())
+            }
+        }
+    }
+
+    pub fn save(&self, working_dir: &Path) -> Result<(), anyhow::Error> {
+        let path = state_path(working_dir);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn package(&self, name: &str) -> Option<&PackageState> {
+        self.packages.get(name)
+    }
+
+    pub fn package_names(&self) -> Vec<String> {
+        self.packages.keys().cloned().collect()
+    }
+
+    /// Record that `package` owns `dest` with `content_hash`. A dest moves
+    /// over from any package that owned it before.
+    pub fn record_file(&mut self, package: &str, dest: &Path, content_hash: String) {
+        let key = dest.to_string_lossy().to_string();
+        for (name, state) in self.packages.iter_mut() {
+            if name != package {
+                state.files.remove(&key);
+            }
+        }
+        self.seen.insert(key.clone());
+        self.packages
+            .entry(package.to_string())
+            .or_default()
+            .files
+            .insert(key, content_hash);
+    }
+
+    pub fn record_dir(&mut self, package: &str, dir: &Path) {
+        self.packages
+            .entry(package.to_string())
+            .or_default()
+            .dirs
+            .insert(dir.to_string_lossy().to_string());
+    }
+
+    /// Note that `package` just deployed without an error.
+    pub fn record_deploy(&mut self, package: &str, profile: Option<&str>, files_written: usize) {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.packages
+            .entry(package.to_string())
+            .or_default()
+            .last_deploy = Some(LastDeploy {
+            at,
+            profile: profile.map(str::to_string),
+            files_written,
+        });
+    }
+
+    /// Move what was recorded for `old` over to `new`, after a rename.
+    pub fn rename_package(&mut self, old: &str, new: &str) {
+        if let Some(state) = self.packages.remove(old) {
+            self.packages.insert(new.to_string(), state);
+        }
+    }
+
+    pub fn last_deploy(&self, package: &str) -> Option<&LastDeploy> {
+        self.packages.get(package)?.last_deploy.as_ref()
+    }
+
+    /// Forget the files of `package` that weren't recorded during this
+    /// deploy, it no longer owns them.
+    pub fn prune_package(&mut self, package: &str) {
+        let Some(state) = self.packages.get_mut(package) else {
+            return;
+        };
+        state.files.retain(|dest, _| self.seen.contains(dest));
+        if state.files.is_empty() && state.dirs.is_empty() && state.last_deploy.is_none() {
+            self.packages.remove(package);
+        }
+    }
+
+    /// Remove the recorded files of `package` and then the directories deploy
+    /// created for them, once empty. A file edited since it was deployed is
+    /// only removed with `force` or after confirming. Returns the number of
+    /// edited files that were kept.
+    pub fn clean_package(
+        &mut self,
+        package: &str,
+        force: bool,
+        dry_run: bool,
+    ) -> Result<usize, anyhow::Error> {
+        let Some(state) = self.packages.get(package).cloned() else {
+            return Ok(0);
+        };
+        let mut kept = PackageState {
+            files: BTreeMap::new(),
+            dirs: state.dirs.clone(),
+            last_deploy: state.last_deploy.clone(),
+        };
+        for (dest, hash) in state.files.iter() {
+            let path = PathBuf::from(dest);
+            let content = match std::fs::read(&path) {
+                Ok(content) => content,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    cprintln(
+                        &format!("{} is already gone", path.display()),
+                        &LogLevel::DEBUG,
+                    );
+                    continue;
+                }
+                Err(e) => anyhow::bail!("Failed to read {}: {}", path.display(), e),
+            };
+            if sha256_bytes(&content) != *hash && !force && !confirm_edited(&path, dry_run)? {
+                cprintln(
+                    &format!(
+                        "Kept {}, it was edited since the last deploy",
+                        path.display()
+                    ),
+                    &LogLevel::WARNING,
+                );
+                kept.files.insert(dest.clone(), hash.clone());
+                continue;
+            }
+            if dry_run {
+                cprintln(&format!("Would remove {}", path.display()), &LogLevel::INFO);
+                continue;
+            }
+            std::fs::remove_file(&path)
+                .map_err(|e| anyhow::anyhow!("Failed to remove {}: {}", path.display(), e))?;
+            cprintln(&format!("Removed {}", path.display()), &LogLevel::INFO);
+        }
+        if dry_run {
+            return Ok(kept.files.len());
+        }
+        // Deepest first, so nested directories are emptied before their parents
+        for dir in state.dirs.iter().rev() {
+            let path = Path::new(dir);
+            let is_empty = std::fs::read_dir(path)
+                .map(|mut entries| entries.next().is_none())
+                .unwrap_or(false);
+            if is_empty {
+                std::fs::remove_dir(path)
+                    .map_err(|e| anyhow::anyhow!("Failed to remove {}: {}", path.display(), e))?;
+                cprintln(&format!("Removed {}", path.display()), &LogLevel::INFO);
+            }
+            if is_empty || !path.exists() {
+                kept.dirs.remove(dir);
+            }
+        }
+        let edited = kept.files.len();
+        if kept.files.is_empty() {
+            self.packages.remove(package);
+        } else {
+            self.packages.insert(package.to_string(), kept);
+        }
+        Ok(edited)
+    }
+}
+
+// Ask before removing an edited file; without a terminal to ask on it is kept
+fn confirm_edited(path: &Path, dry_run: bool) -> Result<bool, anyhow::Error> {
+    if dry_run || !can_prompt() {
+        return Ok(false);
+    }
+    let answer = prompt(&format!(
+        "{} was edited since the last deploy, remove it anyway? [y/N]",
+        path.display()
+    ))?;
+    Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// How long ago `time` was, in the largest whole unit: `just now`,
+/// `5 minutes ago`, `3 days ago`.
+pub fn time_ago(time: SystemTime) -> String {
+    let secs = SystemTime::now()
+        .duration_since(time)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (count, unit) = match secs {
+        0..60 => return "just now".to_string(),
+        60..3_600 => (secs / 60, "minute"),
+        3_600..86_400 => (secs / 3_600, "hour"),
+        _ => (secs / 86_400, "day"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{} {}{} ago", count, unit, plural)
+}
+
+pub fn state_path(working_dir: &Path) -> PathBuf {
+    working_dir.join(CACHE_DIR).join(STATE_FILE)
+}
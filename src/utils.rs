@@ -1,28 +1,65 @@
-use std::path::{Path, PathBuf};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    io::{IsTerminal, Write},
+    path::{Component, Path, PathBuf},
+    sync::{
+        RwLock,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+};
 
 pub const BACKUP_EXT: &str = "dotrbak";
 
 /// Resolve a path string to an absolute PathBuf
-/// - If the path starts with '/', it's treated as an absolute path
-/// - If the path starts with '~', it's treated as relative to the home directory
+/// - If the path starts with '~' (or `%USERPROFILE%` on Windows), it's treated as relative to the home directory
+/// - If the path is absolute (starts with '/', or a drive letter on Windows), it's used as is
 /// - Otherwise, it's treated as relative to the current working directory (cwd)
 pub fn resolve_path(path: &str, cwd: &Path) -> PathBuf {
-    if path.starts_with('/') {
-        PathBuf::from(path)
-    } else if path.starts_with("~") {
+    if let Some(rest) = home_relative(path) {
         let home_dir = std::env::home_dir().expect("Failed to get home directory");
-        // remove first segment of the path
-        let p = path.splitn(2, '/').collect::<Vec<&str>>();
-        // print for debug
-        home_dir.join(p[1..].join("/"))
+        home_dir.join(rest)
+    } else if path.starts_with('/') || Path::new(path).is_absolute() {
+        PathBuf::from(path)
     } else {
         let p = cwd.join(path);
         std::path::absolute(&p).expect("Failed to get absolute path")
     }
 }
 
+/// Resolve `.` and `..` without touching the filesystem, since a dest may not exist yet.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+// The part of a home-based path after its first segment, e.g. `.config/nvim`
+// for `~/.config/nvim` or `%USERPROFILE%\.config\nvim`.
+fn home_relative(path: &str) -> Option<&str> {
+    let is_home = path.starts_with('~')
+        || (cfg!(windows)
+            && path
+                .get(..USERPROFILE.len())
+                .is_some_and(|p| p.eq_ignore_ascii_case(USERPROFILE)));
+    if !is_home {
+        return None;
+    }
+    Some(path.split_once(['/', '\\']).map_or("", |(_, rest)| rest))
+}
+
+const USERPROFILE: &str = "%USERPROFILE%";
+
 /// Convert an absolute path to use ~ notation if it's in the home directory
-/// - If the path is within the home directory, converts it to ~/...
+/// - If the path is within the home directory, converts it to ~/... (or ~\... with backslashes)
 /// - Otherwise, returns the original path as a string
 pub fn normalize_home_path(path: &str) -> String {
     if path.starts_with('~') {
@@ -38,11 +75,10 @@ pub fn normalize_home_path(path: &str) -> String {
             return "~".to_string();
         }
 
-        // Ensure we match on directory boundary by checking for trailing /
-        let home_with_slash = format!("{}/", home_str);
-        if path.starts_with(&home_with_slash) {
-            // Replace home directory with ~
-            let relative = &path[home_str.len()..];
+        // Ensure we match on directory boundary by checking for a trailing separator
+        if let Some(relative) = path.strip_prefix(home_str.as_ref())
+            && (relative.starts_with('/') || (cfg!(windows) && relative.starts_with('\\')))
+        {
             return format!("~{}", relative);
         }
     }
@@ -50,17 +86,101 @@ pub fn normalize_home_path(path: &str) -> String {
     path.to_string()
 }
 
-// Define terminal colors for WARNING, ERROR, INFO, FATAL
+// Bases that work without a [paths] entry: (name, environment variable, default)
+const XDG_BASES: [(&str, &str, &str); 4] = [
+    ("config", "XDG_CONFIG_HOME", "~/.config"),
+    ("data", "XDG_DATA_HOME", "~/.local/share"),
+    ("cache", "XDG_CACHE_HOME", "~/.cache"),
+    ("state", "XDG_STATE_HOME", "~/.local/state"),
+];
+
+/// Split `@name/rest` into its base name and the rest, None for other paths.
+pub fn split_path_base(path: &str) -> Option<(&str, &str)> {
+    let rest = path.strip_prefix('@')?;
+    Some(rest.split_once(['/', '\\']).unwrap_or((rest, "")))
+}
+
+/// The directory a base name stands for: the [paths] entry, or for the XDG
+/// names its environment variable, then the XDG default.
+pub fn path_base(name: &str, bases: &HashMap<String, String>) -> Option<String> {
+    if let Some(base) = bases.get(name) {
+        return Some(base.clone());
+    }
+    let (_, variable, default) = XDG_BASES.iter().find(|(n, _, _)| *n == name)?;
+    Some(
+        std::env::var(variable)
+            .ok()
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| default.to_string()),
+    )
+}
+
+/// Expand a leading `@name/` against its base, leaving other paths as they are.
+pub fn expand_path_base(
+    path: &str,
+    bases: &HashMap<String, String>,
+) -> Result<String, anyhow::Error> {
+    let Some((name, rest)) = split_path_base(path) else {
+        return Ok(path.to_string());
+    };
+    let base = path_base(name, bases).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Unknown path base '@{}' in '{}', define it under [paths] in config.toml",
+            name,
+            path
+        )
+    })?;
+    if rest.is_empty() {
+        return Ok(base);
+    }
+    Ok(format!("{}/{}", base.trim_end_matches(['/', '\\']), rest))
+}
+
+/// Rewrite an absolute path as `@name/...` when it lives under one of `bases`,
+/// picking the deepest base when several match.
+pub fn to_path_base_form(
+    path: &Path,
+    bases: &HashMap<String, String>,
+    cwd: &Path,
+) -> Option<String> {
+    let mut names: Vec<&String> = bases.keys().collect();
+    names.sort();
+    let (name, rest) = names
+        .into_iter()
+        .filter_map(|name| {
+            let base = resolve_path(&bases[name], cwd);
+            let rest = path.strip_prefix(&base).ok()?;
+            Some((base.components().count(), name, rest))
+        })
+        .max_by_key(|(depth, _, _)| *depth)
+        .map(|(_, name, rest)| (name, rest))?;
+    let rest: Vec<String> = rest
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if rest.is_empty() {
+        Some(format!("@{}", name))
+    } else {
+        Some(format!("@{}/{}", name, rest.join("/")))
+    }
+}
+
+// Define terminal colors for WARNING, ERROR, INFO, FATAL, DEBUG, TRACE
 pub const COLOR_WARNING: &str = "\x1b[33m"; // Yellow
 pub const COLOR_ERROR: &str = "\x1b[31m"; // Red
 pub const COLOR_INFO: &str = "\x1b[34m"; // Blue
 pub const COLOR_FATAL: &str = "\x1b[35m"; // Magenta
+pub const COLOR_DEBUG: &str = "\x1b[90m"; // Grey
+pub const COLOR_TRACE: &str = "\x1b[2m"; // Dim
 pub const RESET_COLOR: &str = "\x1b[0m"; // Reset
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogLevel {
+    TRACE, // Per-file skips and the commands of actions, only with -vv
+    DEBUG,
+    INFO,
     WARNING,
     ERROR,
-    INFO,
     FATAL,
 }
 
@@ -71,6 +191,8 @@ impl LogLevel {
             LogLevel::ERROR => "ERROR",
             LogLevel::INFO => "INFO",
             LogLevel::FATAL => "FATAL",
+            LogLevel::DEBUG => "DEBUG",
+            LogLevel::TRACE => "TRACE",
         }
     }
 
@@ -80,18 +202,544 @@ impl LogLevel {
             LogLevel::ERROR => format!("{}[{}]{}", COLOR_ERROR, self.as_str(), RESET_COLOR),
             LogLevel::INFO => format!("{}[{}]{}", COLOR_INFO, self.as_str(), RESET_COLOR),
             LogLevel::FATAL => format!("{}[{}]{}", COLOR_FATAL, self.as_str(), RESET_COLOR),
+            LogLevel::DEBUG => format!("{}[{}]{}", COLOR_DEBUG, self.as_str(), RESET_COLOR),
+            LogLevel::TRACE => format!("{}[{}]{}", COLOR_TRACE, self.as_str(), RESET_COLOR),
+        }
+    }
+
+    /// The `[LEVEL]` tag of a text log line, colored when color is on.
+    pub fn tag(&self) -> String {
+        if color_enabled() {
+            self.to_colorful_str()
+        } else {
+            format!("[{}]", self.as_str())
+        }
+    }
+
+    /// The lowest level shown for the given `-v` count and `-q` flag.
+    pub fn threshold(verbose: u8, quiet: bool) -> LogLevel {
+        if quiet {
+            LogLevel::WARNING
+        } else if verbose > 1 {
+            LogLevel::TRACE
+        } else if verbose > 0 {
+            LogLevel::DEBUG
+        } else {
+            LogLevel::INFO
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+struct Logger {
+    enabled: bool, // Off until `init_logger`, so the library stays quiet when embedded
+    color: bool,   // Off until `enable_color`, for the same reason
+    threshold: LogLevel,
+    format: LogFormat,
+    progress: Option<indicatif::MultiProgress>, // Bars on stderr, log lines are printed above them
+    stderr: bool, // Every line on stderr, while stdout carries a document
+}
+
+static LOGGER: RwLock<Logger> = RwLock::new(Logger {
+    enabled: false,
+    color: false,
+    threshold: LogLevel::INFO,
+    format: LogFormat::Text,
+    progress: None,
+    stderr: false,
+});
+
+/// Turn logging on with the given threshold and format, done once by `run_cli`.
+/// Nothing is logged before that.
+pub fn init_logger(threshold: LogLevel, format: LogFormat) {
+    let mut logger = LOGGER.write().unwrap_or_else(|e| e.into_inner());
+    logger.enabled = true;
+    logger.threshold = threshold;
+    logger.format = format;
+}
+
+/// Draw progress bars on stderr from now on. Only takes effect once logging
+/// is on, and with text logs; the caller checks for a terminal with
+/// `can_show_progress`.
+pub fn enable_progress(enabled: bool) {
+    let mut logger = LOGGER.write().unwrap_or_else(|e| e.into_inner());
+    logger.progress = (enabled && logger.enabled && logger.format == LogFormat::Text)
+        .then(indicatif::MultiProgress::new);
+}
+
+/// Log every level to stderr from now on, leaving stdout to a command that
+/// prints a document there, like `--output json`.
+pub fn log_to_stderr(enabled: bool) {
+    LOGGER.write().unwrap_or_else(|e| e.into_inner()).stderr = enabled;
+}
+
+/// Color log tags, tables and diffs from now on, see `wants_color`.
+pub fn enable_color(enabled: bool) {
+    LOGGER.write().unwrap_or_else(|e| e.into_inner()).color = enabled;
+}
+
+pub fn color_enabled() -> bool {
+    LOGGER.read().unwrap_or_else(|e| e.into_inner()).color
+}
+
+/// Whether output should be colored: stdout is a terminal, `NO_COLOR` isn't
+/// set (see no-color.org) and `--no-color` wasn't given.
+pub fn wants_color(no_color: bool) -> bool {
+    !no_color
+        && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        && std::io::stdout().is_terminal()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Grey,
+}
+
+impl Color {
+    fn code(&self) -> &'static str {
+        match self {
+            Color::Red => COLOR_ERROR,
+            Color::Green => "\x1b[32m",
+            Color::Yellow => COLOR_WARNING,
+            Color::Blue => COLOR_INFO,
+            Color::Grey => COLOR_DEBUG,
+        }
+    }
+}
+
+/// `text` in `color` when color is on, unchanged otherwise.
+pub fn paint(text: &str, color: Color) -> String {
+    if color_enabled() {
+        format!("{}{}{}", color.code(), text, RESET_COLOR)
+    } else {
+        text.to_string()
+    }
+}
+
+/// One cell of a table for `render_table`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    pub text: String,
+    pub color: Option<Color>,
+}
+
+impl Cell {
+    pub fn plain(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            color: None,
+        }
+    }
+
+    pub fn colored(text: impl Into<String>, color: Color) -> Self {
+        Self {
+            text: text.into(),
+            color: Some(color),
+        }
+    }
+}
+
+/// Lay `rows` out in columns, each as wide as its widest cell plus two
+/// spaces, one line per row. The last cell of a row isn't padded. Color goes
+/// around the padded text, so it never shifts the columns.
+pub fn render_table(rows: &[Vec<Cell>], color: bool) -> String {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            let width = cell.text.chars().count();
+            match widths.get_mut(i) {
+                Some(max) => *max = (*max).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+    let mut out = String::new();
+    for row in rows {
+        let mut line = String::new();
+        for (i, cell) in row.iter().enumerate() {
+            let text = if i + 1 < row.len() {
+                format!("{:<width$}  ", cell.text, width = widths[i])
+            } else {
+                cell.text.clone()
+            };
+            match cell.color.filter(|_| color) {
+                Some(c) => {
+                    let padding = text.len() - text.trim_end().len();
+                    line.push_str(&format!(
+                        "{}{}{}{}",
+                        c.code(),
+                        text.trim_end(),
+                        RESET_COLOR,
+                        " ".repeat(padding)
+                    ));
+                }
+                None => line.push_str(&text),
+            }
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Whether stderr is a terminal that progress bars can be drawn on.
+pub fn can_show_progress() -> bool {
+    std::io::stderr().is_terminal()
+}
+
+// Run `f`, which prints, with the progress bars hidden so its output isn't drawn over
+fn suspend_progress<R>(f: impl FnOnce() -> R) -> R {
+    let progress = LOGGER
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .progress
+        .clone();
+    match progress {
+        Some(progress) => progress.suspend(f),
+        None => f(),
+    }
+}
+
+/// A progress bar of `len` steps, shown below the ones already drawn while
+/// progress is enabled, and doing nothing otherwise. Cleared when dropped.
+pub struct Progress {
+    bar: Option<indicatif::ProgressBar>,
+}
+
+impl Progress {
+    pub fn new(len: usize, label: &str) -> Self {
+        let progress = LOGGER
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .progress
+            .clone();
+        let bar = progress.map(|progress| {
+            let bar = progress.add(indicatif::ProgressBar::new(len as u64));
+            let style = indicatif::ProgressStyle::with_template(
+                "{prefix:>12} [{bar:30}] {pos}/{len} {wide_msg}",
+            )
+            .expect("Progress template is valid")
+            .progress_chars("=> ");
+            bar.set_style(style);
+            bar.set_prefix(label.to_string());
+            bar
+        });
+        Self { bar }
+    }
+
+    pub fn set_message(&self, message: &str) {
+        if let Some(bar) = &self.bar {
+            bar.set_message(message.to_string());
+        }
+    }
+
+    pub fn inc(&self) {
+        if let Some(bar) = &self.bar {
+            bar.inc(1);
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(bar) = self.bar.take() {
+            bar.finish_and_clear();
+            if let Some(progress) = &LOGGER.read().unwrap_or_else(|e| e.into_inner()).progress {
+                progress.remove(&bar);
+            }
+        }
+    }
+}
+
+/// Render one log line in the given format.
+pub fn format_log_line(message: &str, level: &LogLevel, format: LogFormat) -> String {
+    match format {
+        LogFormat::Text => format!("{} {}", level.tag(), message),
+        LogFormat::Json => {
+            serde_json::json!({ "level": level.as_str(), "message": message }).to_string()
         }
     }
 }
 
 pub fn cprintln(message: &str, level: &LogLevel) {
-    match level {
+    log_line(message, level, false);
+}
+
+/// Like `cprintln`, but on stderr at every level, for messages about output
+/// printed there, like the banner.
+pub fn ecprintln(message: &str, level: &LogLevel) {
+    log_line(message, level, true);
+}
+
+fn log_line(message: &str, level: &LogLevel, to_stderr: bool) {
+    let (format, stderr) = {
+        let logger = LOGGER.read().unwrap_or_else(|e| e.into_inner());
+        if !logger.enabled || *level < logger.threshold {
+            return;
+        }
+        (logger.format, logger.stderr || to_stderr)
+    };
+    let line = format_log_line(message, level, format);
+    suspend_progress(|| match level {
+        _ if stderr => eprintln!("{}", line),
         LogLevel::ERROR | LogLevel::FATAL => {
-            eprintln!("{} {}", level.to_colorful_str(), message);
+            eprintln!("{}", line);
         }
-        LogLevel::WARNING | LogLevel::INFO => {
-            println!("{} {}", level.to_colorful_str(), message);
+        LogLevel::WARNING | LogLevel::INFO | LogLevel::DEBUG | LogLevel::TRACE => {
+            println!("{}", line);
+        }
+    })
+}
+
+thread_local! {
+    // Answers handed to `prompt` instead of reading stdin, see `set_prompt_answers`
+    static PROMPT_ANSWERS: RefCell<Option<VecDeque<String>>> = const { RefCell::new(None) };
+}
+
+/// Answer the following prompts on this thread with `answers`, in order,
+/// instead of reading stdin. Meant for tests and scripted runs.
+pub fn set_prompt_answers(answers: Vec<String>) {
+    PROMPT_ANSWERS.with(|a| *a.borrow_mut() = Some(answers.into()));
+}
+
+/// An error that should end dotr with `code` instead of 1, like the exit
+/// status of a program dotr ran.
+#[derive(Debug)]
+pub struct ExitCodeError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl std::fmt::Display for ExitCodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ExitCodeError {}
+
+// The exit status of a program ended by SIGINT, 128 + 2
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+// Walks and actions under way, which stop cleanly once `interrupted` says so
+static CATCHING: AtomicUsize = AtomicUsize::new(0);
+// Set while a prompt waits for input, which no flag can stop
+static PROMPTING: AtomicBool = AtomicBool::new(false);
+
+/// Catch Ctrl-C instead of dying halfway through a file: inside
+/// `catch_interrupts`, deploy and update stop at the next file once
+/// `interrupted` says so, and running actions are killed. Anywhere else,
+/// or while a prompt waits, dotr exits right away. Only the binary installs it.
+pub fn install_interrupt_handler() -> anyhow::Result<()> {
+    ctrlc::set_handler(|| {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        if CATCHING.load(Ordering::SeqCst) == 0 || PROMPTING.load(Ordering::SeqCst) {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
         }
+    })?;
+    Ok(())
+}
+
+/// Whether Ctrl-C was pressed since `install_interrupt_handler`.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Leave Ctrl-C to `interrupted` until the guard is dropped, for code that
+/// checks it often enough to stop in time.
+pub fn catch_interrupts() -> InterruptGuard {
+    CATCHING.fetch_add(1, Ordering::SeqCst);
+    InterruptGuard { _private: () }
+}
+
+pub struct InterruptGuard {
+    _private: (),
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        CATCHING.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The editor to open files in: `$VISUAL`, then `$EDITOR`.
+pub fn find_editor() -> Option<String> {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+}
+
+/// Open `path` in `editor`, a command with optional arguments like `code -w`,
+/// and wait for it to exit.
+pub fn run_editor(editor: &str, path: &Path) -> Result<std::process::ExitStatus, anyhow::Error> {
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or(editor);
+    suspend_progress(|| {
+        std::process::Command::new(program)
+            .args(parts)
+            .arg(path)
+            .status()
+    })
+    .map_err(|e| anyhow::anyhow!("Failed to start editor '{}': {}", editor, e))
+}
+
+/// Whether `prompt` can get answers: from `set_prompt_answers`, or a terminal.
+pub fn can_prompt() -> bool {
+    PROMPT_ANSWERS.with(|a| a.borrow().is_some()) || std::io::stdin().is_terminal()
+}
+
+/// Show `message` and read one trimmed line of input.
+pub fn prompt(message: &str) -> Result<String, anyhow::Error> {
+    suspend_progress(|| read_answer(message))
+}
+
+fn read_answer(message: &str) -> Result<String, anyhow::Error> {
+    if interrupted() {
+        return Err(ExitCodeError {
+            code: INTERRUPTED_EXIT_CODE,
+            message: format!("Interrupted before prompt '{}'", message),
+        }
+        .into());
+    }
+    print!("{}\n>>> ", message);
+    std::io::stdout().flush()?;
+    let injected =
+        PROMPT_ANSWERS.with(|a| a.borrow_mut().as_mut().map(|answers| answers.pop_front()));
+    let input = match injected {
+        Some(Some(answer)) => {
+            println!("{}", answer);
+            answer
+        }
+        Some(None) => anyhow::bail!("No answer left for prompt '{}'", message),
+        None => {
+            let mut input = String::new();
+            // Ctrl-C exits while waiting here
+            PROMPTING.store(true, Ordering::SeqCst);
+            if interrupted() {
+                std::process::exit(INTERRUPTED_EXIT_CODE);
+            }
+            let read = std::io::stdin().read_line(&mut input);
+            PROMPTING.store(false, Ordering::SeqCst);
+            read?;
+            input
+        }
+    };
+    Ok(input.trim().to_string())
+}
+
+// Levenshtein distance between two strings.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b_chars.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b_chars.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current.push((prev[j] + cost).min(prev[j + 1] + 1).min(current[j] + 1));
+        }
+        prev = current;
+    }
+    prev[b_chars.len()]
+}
+
+/// Describe each key of `table` that isn't one of `known`, suggesting the
+/// closest known key when there is one. Keys starting with `x-` are left to
+/// the user for their own extensions.
+pub fn unknown_keys(table: &toml::Table, known: &[&str]) -> Vec<String> {
+    table
+        .keys()
+        .filter(|key| !key.starts_with("x-") && !known.contains(&key.as_str()))
+        .map(|key| {
+            let threshold = (key.chars().count() / 3).max(2);
+            let closest = known
+                .iter()
+                .map(|k| (edit_distance(key, k), *k))
+                .filter(|(distance, _)| *distance <= threshold)
+                .min();
+            match closest {
+                Some((_, k)) => format!("unknown key '{}', did you mean '{}'?", key, k),
+                None => format!("unknown key '{}'", key),
+            }
+        })
+        .collect()
+}
+
+// Multipliers of the size units, largest first so sizes are written with the
+// largest unit that fits exactly
+const SIZE_UNITS: &[(&str, u64)] = &[("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10), ("B", 1)];
+
+/// Parse a size like `50MB`, `1.5 GB` or `2048`, in bytes. Units are KB, MB
+/// and GB, case-insensitive and counted in 1024s; a bare number is bytes.
+pub fn parse_size(size: &str) -> Result<u64, anyhow::Error> {
+    let trimmed = size.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let unit = unit.trim().to_ascii_uppercase();
+    let multiplier = match unit.as_str() {
+        "" => 1,
+        "K" | "KIB" => 1 << 10,
+        "M" | "MIB" => 1 << 20,
+        "G" | "GIB" => 1 << 30,
+        _ => SIZE_UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .map(|(_, multiplier)| *multiplier)
+            .ok_or_else(|| {
+                anyhow::anyhow!("Invalid size '{}', use a unit of B, KB, MB or GB", size)
+            })?,
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid size '{}', expected a number like 50MB", size))?;
+    Ok((number * multiplier as f64).round() as u64)
+}
+
+/// Write `bytes` the way `parse_size` reads it, `50MB` rather than 52428800.
+pub fn format_size(bytes: u64) -> String {
+    SIZE_UNITS
+        .iter()
+        .find(|(_, multiplier)| bytes > 0 && bytes.is_multiple_of(*multiplier))
+        .map(|(name, multiplier)| format!("{}{}", bytes / multiplier, name))
+        .unwrap_or_else(|| "0B".to_string())
+}
+
+/// Read a size limit from `key` of a config table, as a number of bytes or
+/// a string with a unit.
+pub fn size_field(table: &toml::Table, key: &str) -> Result<Option<u64>, anyhow::Error> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(toml::Value::Integer(bytes)) if *bytes >= 0 => Ok(Some(*bytes as u64)),
+        Some(toml::Value::String(size)) => parse_size(size)
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("The '{}' field: {}", key, e)),
+        Some(_) => anyhow::bail!(
+            "The '{}' field must be a size like \"50MB\" or a number of bytes",
+            key
+        ),
+    }
+}
+
+/// Read a count limit from `key` of a config table, a positive integer.
+pub fn count_field(table: &toml::Table, key: &str) -> Result<Option<usize>, anyhow::Error> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(toml::Value::Integer(count)) if *count > 0 => Ok(Some(*count as usize)),
+        Some(_) => anyhow::bail!("The '{}' field must be a positive number", key),
     }
 }
 
@@ -100,6 +748,36 @@ mod tests {
     use super::*;
     use std::path::PathBuf;
 
+    #[test]
+    fn test_parse_size_units() {
+        assert_eq!(parse_size("2048").unwrap(), 2048);
+        assert_eq!(parse_size("10B").unwrap(), 10);
+        assert_eq!(parse_size("1KB").unwrap(), 1024);
+        assert_eq!(parse_size("50MB").unwrap(), 50 * 1024 * 1024);
+        assert_eq!(parse_size("50 mb").unwrap(), 50 * 1024 * 1024);
+        assert_eq!(parse_size("2GB").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("1.5GB").unwrap(), 3 * 512 * 1024 * 1024);
+        assert_eq!(parse_size("4MiB").unwrap(), 4 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_parse_size_rejects_garbage() {
+        for size in ["", "MB", "50TB", "fifty", "5..0KB", "-1MB"] {
+            assert!(parse_size(size).is_err(), "{}", size);
+        }
+    }
+
+    #[test]
+    fn test_format_size_round_trips() {
+        assert_eq!(format_size(50 * 1024 * 1024), "50MB");
+        assert_eq!(format_size(1536), "1536B");
+        assert_eq!(format_size(3 * 1024), "3KB");
+        assert_eq!(format_size(0), "0B");
+        for size in ["50MB", "1536B", "2GB"] {
+            assert_eq!(format_size(parse_size(size).unwrap()), size);
+        }
+    }
+
     #[test]
     fn test_resolve_path_absolute() {
         let cwd = PathBuf::from("/some/cwd");
@@ -154,6 +832,41 @@ mod tests {
         assert!(resolved.is_absolute());
     }
 
+    #[cfg(windows)]
+    #[test]
+    fn test_resolve_path_windows_home_and_drive() {
+        let cwd = PathBuf::from(r"C:\some\cwd");
+        let home = std::env::home_dir().expect("Failed to get home directory");
+
+        assert_eq!(
+            resolve_path(r"%USERPROFILE%\.config\nvim", &cwd),
+            home.join(r".config\nvim")
+        );
+        assert_eq!(resolve_path(r"~\AppData", &cwd), home.join("AppData"));
+        assert_eq!(
+            resolve_path(r"D:\dotfiles", &cwd),
+            PathBuf::from(r"D:\dotfiles")
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_normalize_home_path_windows_separator() {
+        let home = std::env::home_dir().expect("Failed to get home directory");
+        let path = format!(r"{}\AppData\Roaming", home.to_string_lossy());
+        assert_eq!(normalize_home_path(&path), r"~\AppData\Roaming");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_userprofile_is_not_special_on_unix() {
+        let cwd = PathBuf::from("/some/cwd");
+        assert_eq!(
+            resolve_path("%USERPROFILE%/x", &cwd),
+            PathBuf::from("/some/cwd/%USERPROFILE%/x")
+        );
+    }
+
     #[test]
     fn test_normalize_home_path_already_normalized() {
         let path = "~/.config/nvim";
@@ -276,4 +989,83 @@ mod tests {
         let normalized = normalize_home_path(&path);
         assert_eq!(normalized, "~/.config/.hidden/..dotfile");
     }
+
+    #[test]
+    fn test_log_threshold() {
+        assert_eq!(LogLevel::threshold(0, false), LogLevel::INFO);
+        assert_eq!(LogLevel::threshold(1, false), LogLevel::DEBUG);
+        assert_eq!(LogLevel::threshold(2, false), LogLevel::TRACE);
+        assert_eq!(LogLevel::threshold(0, true), LogLevel::WARNING);
+        assert!(LogLevel::DEBUG < LogLevel::INFO);
+        assert!(LogLevel::TRACE < LogLevel::DEBUG);
+        assert!(LogLevel::WARNING < LogLevel::ERROR);
+    }
+
+    #[test]
+    fn test_format_log_line_json() {
+        let line = format_log_line("Deployed to \"x\"", &LogLevel::INFO, LogFormat::Json);
+        let parsed: serde_json::Value = serde_json::from_str(&line).expect("Invalid JSON");
+        assert_eq!(parsed["level"], "INFO");
+        assert_eq!(parsed["message"], "Deployed to \"x\"");
+        assert!(!line.contains('\n'));
+    }
+
+    fn bases(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_path_base_configured() {
+        let bases = bases(&[("config", "~/.config"), ("work", "/srv/work/")]);
+        assert_eq!(
+            expand_path_base("@config/nvim", &bases).unwrap(),
+            "~/.config/nvim"
+        );
+        assert_eq!(
+            expand_path_base("@work/app/rc", &bases).unwrap(),
+            "/srv/work/app/rc"
+        );
+        assert_eq!(expand_path_base("@work", &bases).unwrap(), "/srv/work/");
+        assert_eq!(expand_path_base("~/.bashrc", &bases).unwrap(), "~/.bashrc");
+    }
+
+    #[test]
+    fn test_expand_path_base_xdg_fallback() {
+        let expanded = expand_path_base("@data/fonts", &HashMap::new()).unwrap();
+        match std::env::var("XDG_DATA_HOME") {
+            Ok(dir) if !dir.is_empty() => assert_eq!(expanded, format!("{}/fonts", dir)),
+            _ => assert_eq!(expanded, "~/.local/share/fonts"),
+        }
+    }
+
+    #[test]
+    fn test_expand_path_base_unknown() {
+        let err = expand_path_base("@nowhere/x", &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("Unknown path base '@nowhere'"));
+    }
+
+    #[test]
+    fn test_to_path_base_form_prefers_deepest_base() {
+        let cwd = PathBuf::from("/repo");
+        let bases = bases(&[("home", "/home/me"), ("config", "/home/me/.config")]);
+        assert_eq!(
+            to_path_base_form(Path::new("/home/me/.config/nvim/init.lua"), &bases, &cwd),
+            Some("@config/nvim/init.lua".to_string())
+        );
+        assert_eq!(
+            to_path_base_form(Path::new("/home/me/.bashrc"), &bases, &cwd),
+            Some("@home/.bashrc".to_string())
+        );
+        assert_eq!(
+            to_path_base_form(Path::new("/home/me/.config"), &bases, &cwd),
+            Some("@config".to_string())
+        );
+        assert_eq!(
+            to_path_base_form(Path::new("/home/meh/.bashrc"), &bases, &cwd),
+            None
+        );
+    }
 }
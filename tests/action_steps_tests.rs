@@ -0,0 +1,117 @@
+use std::{fs, path::PathBuf};
+
+use clap::Parser;
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    config::Config,
+    package::Package,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_steps_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    // One file package whose actions log to actions.log, rendering a variable
+    fn setup(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+        fs::write(self.cwd.join("dotfiles/f_app"), "app").unwrap();
+        let mut config = Config::from_path(&self.cwd).expect("Failed to load config");
+        config.shell = Some("/bin/sh".to_string());
+        config.variables.insert(
+            "GREETING".to_string(),
+            toml::Value::String("hello".to_string()),
+        );
+        let table: toml::Table = format!(
+            r#"
+src = "dotfiles/f_app"
+dest = "{}"
+pre_actions = ["echo pre {{{{ GREETING }}}} >> actions.log"]
+post_actions = ["echo post >> actions.log"]
+"#,
+            self.cwd.join("home/app.conf").display()
+        )
+        .parse()
+        .unwrap();
+        let package = Package::from_table("f_app", &table).expect("Failed to build package");
+        config.packages.insert("f_app".to_string(), package);
+        config.save(&self.cwd).expect("Failed to save config");
+    }
+
+    fn deploy(&self, skip_actions: bool, actions_only: bool) {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs {
+            skip_actions,
+            actions_only,
+            ..Default::default()
+        }))))
+        .expect("Deploy failed");
+    }
+
+    fn actions_log(&self) -> String {
+        fs::read_to_string(self.cwd.join("actions.log")).unwrap_or_default()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.cwd);
+    }
+}
+
+#[test]
+fn test_skip_actions_deploys_files_only() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+
+    fixture.deploy(true, false);
+    assert!(fixture.cwd.join("home/app.conf").exists());
+    assert_eq!(fixture.actions_log(), "");
+}
+
+#[test]
+fn test_actions_only_runs_actions_in_order() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+
+    fixture.deploy(false, true);
+    assert_eq!(fixture.actions_log(), "pre hello\npost\n");
+    assert!(
+        !fixture.cwd.join("home/app.conf").exists(),
+        "No files should be deployed"
+    );
+    assert!(
+        !fixture.cwd.join(".dotr").exists(),
+        "The deploy cache should be left alone"
+    );
+}
+
+#[test]
+fn test_default_deploy_runs_both() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+
+    fixture.deploy(false, false);
+    assert!(fixture.cwd.join("home/app.conf").exists());
+    assert_eq!(fixture.actions_log(), "pre hello\npost\n");
+}
+
+#[test]
+fn test_flags_conflict() {
+    assert!(Cli::try_parse_from(["dotr", "deploy", "--skip-actions", "--actions-only"]).is_err());
+    assert!(Cli::try_parse_from(["dotr", "deploy", "--actions-only"]).is_ok());
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fs, path::PathBuf};
+use std::{fs, path::PathBuf};
 
 use dotr::{
     cli::{DeployUpdateArgs, InitArgs, run_cli},
@@ -7,28 +7,29 @@ use dotr::{
 
 mod common;
 
-const PLAYGROUND_DIR: &str = "tests/playground";
-
 struct TestFixture {
     cwd: PathBuf,
 }
 
 impl TestFixture {
     fn new() -> Self {
-        Self {
-            cwd: PathBuf::from(PLAYGROUND_DIR),
-        }
+        let cwd = std::env::temp_dir().join(format!("dotr_actions_test_{}", uuid::Uuid::new_v4()));
+        // Actions write their markers under src
+        fs::create_dir_all(cwd.join("src")).expect("Failed to create temp dir");
+        Self { cwd }
     }
 
     fn get_cli(&self, command: Option<dotr::cli::Command>) -> dotr::cli::Cli {
         dotr::cli::Cli {
             command,
-            working_dir: Some(PLAYGROUND_DIR.to_string()),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
         }
     }
 
     fn init(&self) {
-        run_cli(self.get_cli(Some(dotr::cli::Command::Init(InitArgs {})))).expect("Init failed");
+        run_cli(self.get_cli(Some(dotr::cli::Command::Init(InitArgs::default()))))
+            .expect("Init failed");
 
         // Set SHELL to /bin/sh in config for consistent test execution
         let mut config = self.get_config();
@@ -44,6 +45,7 @@ impl TestFixture {
             self.get_cli(Some(dotr::cli::Command::Deploy(DeployUpdateArgs {
                 packages,
                 profile: None,
+                ..Default::default()
             }))),
         )
         .expect("Deploy failed");
@@ -56,7 +58,7 @@ impl TestFixture {
 
 impl Drop for TestFixture {
     fn drop(&mut self) {
-        common::teardown(&self.cwd);
+        fs::remove_dir_all(&self.cwd).ok();
     }
 }
 
@@ -80,16 +82,10 @@ fn test_pre_action_basic() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_pre_action_test".to_string(),
-        src: "dotfiles/f_pre_action_test".to_string(),
-        dest: "src/.pre_action_test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec!["touch src/pre_action_marker.txt".to_string()],
-        post_actions: Vec::new(),
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_pre_action_test".to_string()),
+        dest: Some("src/.pre_action_test".to_string()),
+        pre_actions: vec!["touch src/pre_action_marker.txt".into()],
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -129,16 +125,10 @@ fn test_post_action_basic() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_post_action_test".to_string(),
-        src: "dotfiles/f_post_action_test".to_string(),
-        dest: "src/.post_action_test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: vec!["touch src/post_action_marker.txt".to_string()],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_post_action_test".to_string()),
+        dest: Some("src/.post_action_test".to_string()),
+        post_actions: vec!["touch src/post_action_marker.txt".into()],
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -178,16 +168,11 @@ fn test_pre_and_post_actions_together() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_both_actions_test".to_string(),
-        src: "dotfiles/f_both_actions_test".to_string(),
-        dest: "src/.both_actions_test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec!["echo 'pre' > src/both_pre_marker.txt".to_string()],
-        post_actions: vec!["echo 'post' > src/both_post_marker.txt".to_string()],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_both_actions_test".to_string()),
+        dest: Some("src/.both_actions_test".to_string()),
+        pre_actions: vec!["echo 'pre' > src/both_pre_marker.txt".into()],
+        post_actions: vec!["echo 'post' > src/both_post_marker.txt".into()],
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -240,20 +225,14 @@ fn test_multiple_pre_actions() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_multi_pre_test".to_string(),
-        src: "dotfiles/f_multi_pre_test".to_string(),
-        dest: "src/.multi_pre_test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
+        src: Some("dotfiles/f_multi_pre_test".to_string()),
+        dest: Some("src/.multi_pre_test".to_string()),
         pre_actions: vec![
-            "echo 'action1' > src/pre_action1.txt".to_string(),
-            "echo 'action2' > src/pre_action2.txt".to_string(),
-            "echo 'action3' > src/pre_action3.txt".to_string(),
+            "echo 'action1' > src/pre_action1.txt".into(),
+            "echo 'action2' > src/pre_action2.txt".into(),
+            "echo 'action3' > src/pre_action3.txt".into(),
         ],
-        post_actions: Vec::new(),
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -307,20 +286,14 @@ fn test_multiple_post_actions() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_multi_post_test".to_string(),
-        src: "dotfiles/f_multi_post_test".to_string(),
-        dest: "src/.multi_post_test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
+        src: Some("dotfiles/f_multi_post_test".to_string()),
+        dest: Some("src/.multi_post_test".to_string()),
         post_actions: vec![
-            "echo 'action1' > src/post_action1.txt".to_string(),
-            "echo 'action2' > src/post_action2.txt".to_string(),
-            "echo 'action3' > src/post_action3.txt".to_string(),
+            "echo 'action1' > src/post_action1.txt".into(),
+            "echo 'action2' > src/post_action2.txt".into(),
+            "echo 'action3' > src/post_action3.txt".into(),
         ],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -380,16 +353,11 @@ fn test_actions_with_variables() {
 
     let package = dotr::package::Package {
         name: "f_action_var_test".to_string(),
-        src: "dotfiles/f_action_var_test".to_string(),
-        dest: "src/.action_var_test".to_string(),
-        dependencies: None,
+        src: Some("dotfiles/f_action_var_test".to_string()),
+        dest: Some("src/.action_var_test".to_string()),
         variables: pkg_vars,
-        pre_actions: vec!["echo '{{ ACTION_VAR }}' > src/action_var_marker.txt".to_string()],
-        post_actions: Vec::new(),
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        pre_actions: vec!["echo '{{ ACTION_VAR }}' > src/action_var_marker.txt".into()],
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -417,16 +385,11 @@ fn test_actions_persist_after_save() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "test_persist".to_string(),
-        src: "dotfiles/test".to_string(),
-        dest: "src/.test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec!["echo 'pre1'".to_string(), "echo 'pre2'".to_string()],
-        post_actions: vec!["echo 'post1'".to_string(), "echo 'post2'".to_string()],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/test".to_string()),
+        dest: Some("src/.test".to_string()),
+        pre_actions: vec!["echo 'pre1'".into(), "echo 'pre2'".into()],
+        post_actions: vec!["echo 'post1'".into(), "echo 'post2'".into()],
+        ..dotr::package::Package::default()
     };
     config.packages.insert("test_persist".to_string(), package);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -440,10 +403,10 @@ fn test_actions_persist_after_save() {
 
     assert_eq!(pkg.pre_actions.len(), 2, "Should have 2 pre-actions");
     assert_eq!(pkg.post_actions.len(), 2, "Should have 2 post-actions");
-    assert_eq!(pkg.pre_actions[0], "echo 'pre1'");
-    assert_eq!(pkg.pre_actions[1], "echo 'pre2'");
-    assert_eq!(pkg.post_actions[0], "echo 'post1'");
-    assert_eq!(pkg.post_actions[1], "echo 'post2'");
+    assert_eq!(pkg.pre_actions[0].to_string(), "echo 'pre1'");
+    assert_eq!(pkg.pre_actions[1].to_string(), "echo 'pre2'");
+    assert_eq!(pkg.post_actions[0].to_string(), "echo 'post1'");
+    assert_eq!(pkg.post_actions[1].to_string(), "echo 'post2'");
 }
 
 #[test]
@@ -463,22 +426,17 @@ fn test_actions_execution_order() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_order_test".to_string(),
-        src: "dotfiles/f_order_test".to_string(),
-        dest: "src/.order_test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
+        src: Some("dotfiles/f_order_test".to_string()),
+        dest: Some("src/.order_test".to_string()),
         pre_actions: vec![
-            "echo 'pre1' > src/order_log.txt".to_string(),
-            "echo 'pre2' >> src/order_log.txt".to_string(),
+            "echo 'pre1' > src/order_log.txt".into(),
+            "echo 'pre2' >> src/order_log.txt".into(),
         ],
         post_actions: vec![
-            "echo 'post1' >> src/order_log.txt".to_string(),
-            "echo 'post2' >> src/order_log.txt".to_string(),
+            "echo 'post1' >> src/order_log.txt".into(),
+            "echo 'post2' >> src/order_log.txt".into(),
         ],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
     config.packages.insert("f_order_test".to_string(), package);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -515,16 +473,9 @@ fn test_empty_actions_dont_fail() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_no_actions_test".to_string(),
-        src: "dotfiles/f_no_actions_test".to_string(),
-        dest: "src/.no_actions_test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_no_actions_test".to_string()),
+        dest: Some("src/.no_actions_test".to_string()),
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -558,18 +509,13 @@ fn test_actions_with_complex_commands() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_complex_test".to_string(),
-        src: "dotfiles/f_complex_test".to_string(),
-        dest: "src/.complex_test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec!["mkdir -p src/nested/dir && touch src/nested/dir/file.txt".to_string()],
+        src: Some("dotfiles/f_complex_test".to_string()),
+        dest: Some("src/.complex_test".to_string()),
+        pre_actions: vec!["mkdir -p src/nested/dir && touch src/nested/dir/file.txt".into()],
         post_actions: vec![
-            "test -f src/.complex_test && echo 'deployed' > src/deploy_check.txt".to_string(),
+            "test -f src/.complex_test && echo 'deployed' > src/deploy_check.txt".into(),
         ],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -606,16 +552,10 @@ fn test_pre_action_failure() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_pre_fail".to_string(),
-        src: "dotfiles/f_pre_fail".to_string(),
-        dest: "src/.pre_fail".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec!["false".to_string()], // This command always fails
-        post_actions: vec![],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_pre_fail".to_string()),
+        dest: Some("src/.pre_fail".to_string()),
+        pre_actions: vec!["false".into()], // This command always fails
+        ..dotr::package::Package::default()
     };
     config.packages.insert("f_pre_fail".to_string(), package);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -625,6 +565,7 @@ fn test_pre_action_failure() {
         fixture.get_cli(Some(dotr::cli::Command::Deploy(DeployUpdateArgs {
             packages: Some(vec!["f_pre_fail".to_string()]),
             profile: None,
+            ..Default::default()
         }))),
     );
 
@@ -645,16 +586,10 @@ fn test_post_action_failure() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_post_fail".to_string(),
-        src: "dotfiles/f_post_fail".to_string(),
-        dest: "src/.post_fail".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec![],
-        post_actions: vec!["exit 1".to_string()], // This command exits with error
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_post_fail".to_string()),
+        dest: Some("src/.post_fail".to_string()),
+        post_actions: vec!["exit 1".into()], // This command exits with error
+        ..dotr::package::Package::default()
     };
     config.packages.insert("f_post_fail".to_string(), package);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -668,6 +603,7 @@ fn test_post_action_failure() {
         fixture.get_cli(Some(dotr::cli::Command::Deploy(DeployUpdateArgs {
             packages: Some(vec!["f_post_fail".to_string()]),
             profile: None,
+            ..Default::default()
         }))),
     );
 
@@ -688,16 +624,10 @@ fn test_action_with_nonexistent_command() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_bad_cmd".to_string(),
-        src: "dotfiles/f_bad_cmd".to_string(),
-        dest: "src/.bad_cmd".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec!["this_command_does_not_exist_12345".to_string()],
-        post_actions: vec![],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_bad_cmd".to_string()),
+        dest: Some("src/.bad_cmd".to_string()),
+        pre_actions: vec!["this_command_does_not_exist_12345".into()],
+        ..dotr::package::Package::default()
     };
     config.packages.insert("f_bad_cmd".to_string(), package);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -707,6 +637,7 @@ fn test_action_with_nonexistent_command() {
         fixture.get_cli(Some(dotr::cli::Command::Deploy(DeployUpdateArgs {
             packages: Some(vec!["f_bad_cmd".to_string()]),
             profile: None,
+            ..Default::default()
         }))),
     );
 
@@ -730,16 +661,10 @@ fn test_action_failure_with_error_message() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_err_msg".to_string(),
-        src: "dotfiles/f_err_msg".to_string(),
-        dest: "src/.err_msg".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec!["echo 'Error occurred' >&2 && exit 42".to_string()],
-        post_actions: vec![],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_err_msg".to_string()),
+        dest: Some("src/.err_msg".to_string()),
+        pre_actions: vec!["echo 'Error occurred' >&2 && exit 42".into()],
+        ..dotr::package::Package::default()
     };
     config.packages.insert("f_err_msg".to_string(), package);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -749,6 +674,7 @@ fn test_action_failure_with_error_message() {
         fixture.get_cli(Some(dotr::cli::Command::Deploy(DeployUpdateArgs {
             packages: Some(vec!["f_err_msg".to_string()]),
             profile: None,
+            ..Default::default()
         }))),
     );
 
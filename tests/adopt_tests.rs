@@ -0,0 +1,130 @@
+use std::{fs, path::PathBuf};
+
+use dotr::cli::{Cli, Command, ImportArgs, InitArgs, run_cli};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_adopt_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+        fs::write(self.cwd.join("config.toml"), "banner = false\n").expect("Failed to write");
+    }
+
+    fn adopt(&self, path: &str, name: &str) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Import(ImportArgs {
+            paths: vec![self.cwd.join(path).to_str().unwrap().to_string()],
+            name: Some(name.to_string()),
+            adopt: true,
+            ..Default::default()
+        }))))
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_adopt_file_package() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("home/.gitconfig", "[user]\n  name = me\n");
+
+    fixture
+        .adopt("home/.gitconfig", "git")
+        .expect("Adopt failed");
+    assert_eq!(
+        fixture.read_file("home/.gitconfig"),
+        "[user]\n  name = me\n"
+    );
+    assert_eq!(fixture.read_file("dotfiles/f_git"), "[user]\n  name = me\n");
+    assert!(
+        fixture
+            .read_file("config.toml")
+            .contains("[packages.f_git]")
+    );
+    assert!(!fixture.cwd.join("home/.gitconfig.dotrbak").exists());
+
+    // Deployed, so clean knows the file belongs to the package
+    let state = fixture.read_file(".dotr/state.toml");
+    assert!(
+        state.contains(
+            &fixture
+                .cwd
+                .join("home/.gitconfig")
+                .to_string_lossy()
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn test_adopt_directory_package() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("home/nvim/init.lua", "vim.g.a = 1\n");
+    fixture.write_file("home/nvim/lua/plugins.lua", "return {}\n");
+
+    fixture.adopt("home/nvim", "nvim").expect("Adopt failed");
+    assert_eq!(fixture.read_file("home/nvim/init.lua"), "vim.g.a = 1\n");
+    assert_eq!(
+        fixture.read_file("home/nvim/lua/plugins.lua"),
+        "return {}\n"
+    );
+    assert!(
+        fixture
+            .read_file(".dotr/state.toml")
+            .contains("plugins.lua")
+    );
+}
+
+#[test]
+fn test_failed_adopt_keeps_original() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    // Looks like a template, but NOPE isn't defined anywhere
+    fixture.write_file("home/.rc", "value={{ NOPE }}\n");
+
+    let err = fixture
+        .adopt("home/.rc", "rc")
+        .expect_err("Deploying an undefined variable should fail");
+    assert!(
+        err.to_string().contains("Failed to adopt 'f_rc'"),
+        "{}",
+        err
+    );
+    assert_eq!(fixture.read_file("home/.rc"), "value={{ NOPE }}\n");
+    // The import itself stands, so it can be fixed up and deployed later
+    assert!(fixture.read_file("config.toml").contains("[packages.f_rc]"));
+}
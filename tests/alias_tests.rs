@@ -0,0 +1,215 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    config::Config,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(config: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_alias_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fixture.write_file("config.toml", config);
+        fixture.write_file("dotfiles/d_nvim/init.lua", "init\n");
+        fixture.write_file("dotfiles/f_zshrc", "zshrc\n");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self, packages: Option<Vec<&str>>, profile: Option<&str>) -> anyhow::Result<()> {
+        self.run(Command::Deploy(DeployUpdateArgs {
+            packages: packages.map(|names| names.iter().map(|n| n.to_string()).collect()),
+            profile: profile.map(|p| p.to_string()),
+            ..Default::default()
+        }))
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn config(&self) -> Result<Config, anyhow::Error> {
+        Config::from_path(&self.cwd)
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[packages.d_nvim]
+src = "dotfiles/d_nvim"
+dest = "nvim"
+aliases = ["nvim", "vim"]
+
+[packages.f_zshrc]
+src = "dotfiles/f_zshrc"
+dest = "zshrc"
+aliases = ["zsh"]
+
+[packages.meta]
+dependencies = ["zsh"]
+post_actions = ["true"]
+skip = true
+
+[profiles.work]
+dependencies = ["nvim"]
+"#;
+
+#[test]
+fn test_deploy_by_alias() {
+    let fixture = TestFixture::new(CONFIG);
+    fixture
+        .deploy(Some(vec!["nvim"]), None)
+        .expect("Deploy by alias failed");
+
+    assert!(fixture.cwd.join("nvim/init.lua").exists());
+    assert!(!fixture.cwd.join("zshrc").exists());
+    let config = fixture.config().unwrap();
+    assert_eq!(config.resolve_package_name("vim").unwrap(), "d_nvim");
+    assert_eq!(config.resolve_package_name("d_nvim").unwrap(), "d_nvim");
+}
+
+#[test]
+fn test_dependencies_by_alias() {
+    let fixture = TestFixture::new(CONFIG);
+    fixture
+        .deploy(None, Some("work"))
+        .expect("Deploy with profile failed");
+    assert!(fixture.cwd.join("nvim/init.lua").exists());
+
+    fixture
+        .deploy(Some(vec!["meta"]), None)
+        .expect("Deploy of meta package failed");
+    assert!(fixture.cwd.join("zshrc").exists());
+}
+
+#[test]
+fn test_unknown_name_suggests_closest() {
+    let fixture = TestFixture::new(CONFIG);
+    let err = fixture
+        .deploy(Some(vec!["nvm"]), None)
+        .expect_err("An unknown package should fail");
+    assert!(
+        err.to_string().contains("did you mean 'd_nvim'?"),
+        "{}",
+        err
+    );
+
+    let err = fixture
+        .deploy(Some(vec!["tmux"]), None)
+        .expect_err("An unknown package should fail");
+    assert!(!err.to_string().contains("did you mean"), "{}", err);
+}
+
+#[test]
+fn test_alias_collisions_are_errors() {
+    let fixture =
+        TestFixture::new(&CONFIG.replace(r#"aliases = ["zsh"]"#, r#"aliases = ["d_nvim"]"#));
+    let err = fixture
+        .config()
+        .expect_err("An alias naming a package should fail");
+    assert!(err.to_string().contains("d_nvim"), "{}", err);
+
+    fixture.write_file(
+        "config.toml",
+        &CONFIG.replace(r#"aliases = ["zsh"]"#, r#"aliases = ["vim"]"#),
+    );
+    let err = fixture
+        .config()
+        .expect_err("An alias of two packages should fail");
+    assert!(err.to_string().contains("f_zshrc"), "{}", err);
+}
+
+#[test]
+fn test_aliases_round_trip() {
+    let fixture = TestFixture::new(CONFIG);
+    fixture
+        .config()
+        .unwrap()
+        .save(&fixture.cwd)
+        .expect("Failed to save config");
+
+    let config = fixture.config().expect("Failed to reload config");
+    assert_eq!(config.packages["d_nvim"].aliases, vec!["nvim", "vim"]);
+    assert_eq!(config.resolve_package_name("zsh").unwrap(), "f_zshrc");
+}
+
+#[test]
+fn test_array_of_tables_packages() {
+    let fixture = TestFixture::new(
+        r#"
+banner = false
+
+[[packages]]
+name = "f_zshrc"
+src = "dotfiles/f_zshrc"
+dest = "zshrc"
+
+[[packages]]
+name = "d_nvim"
+src = "dotfiles/d_nvim"
+dest = "nvim"
+aliases = ["nvim"]
+"#,
+    );
+    let config = fixture.config().expect("Failed to load config");
+    assert_eq!(config.packages.len(), 2);
+    config.save(&fixture.cwd).expect("Failed to save config");
+
+    let saved: toml::Table = fs::read_to_string(fixture.cwd.join("config.toml"))
+        .unwrap()
+        .parse()
+        .unwrap();
+    let entries = saved["packages"]
+        .as_array()
+        .expect("Packages stay an array");
+    let names: Vec<&str> = entries
+        .iter()
+        .map(|e| e["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["d_nvim", "f_zshrc"]);
+    fixture
+        .deploy(Some(vec!["nvim"]), None)
+        .expect("Deploy failed");
+    assert!(fixture.cwd.join("nvim/init.lua").exists());
+}
+
+#[test]
+fn test_array_entries_need_a_unique_name() {
+    let fixture = TestFixture::new("[[packages]]\nsrc = \"dotfiles/f_zshrc\"\ndest = \"zshrc\"\n");
+    let err = fixture.config().expect_err("A nameless entry should fail");
+    assert!(err.to_string().contains("name"), "{}", err);
+
+    fixture.write_file(
+        "config.toml",
+        "[[packages]]\nname = \"a\"\nsrc = \"x\"\ndest = \"y\"\n\n[[packages]]\nname = \"a\"\nsrc = \"x\"\ndest = \"z\"\n",
+    );
+    let err = fixture.config().expect_err("A duplicate name should fail");
+    assert!(err.to_string().contains("twice"), "{}", err);
+}
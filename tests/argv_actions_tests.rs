@@ -0,0 +1,150 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    Config, Context, DeployUpdateArgs, Report,
+    cli::{Cli, Command, InitArgs, run_cli},
+    package::{Action, Package},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(actions: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_argv_actions_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        run_cli(Cli {
+            command: Some(Command::Init(InitArgs::default())),
+            working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+        .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            format!("{}{}", CONFIG, actions),
+        )
+        .expect("Failed to write config");
+        fixture
+    }
+
+    fn deploy(&self) -> Result<Report, anyhow::Error> {
+        let conf = Config::from_path(&self.cwd)?;
+        let ctx = Context::from_config(&self.cwd, &conf)?;
+        conf.deploy_packages(&ctx, &DeployUpdateArgs::default())?
+            .into_result()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[variables]
+title = "it's a \"quoted\" name; with spaces"
+
+[packages.setup]
+"#;
+
+#[test]
+fn test_argv_action_needs_no_quoting() {
+    let fixture = TestFixture::new("post_actions = [[\"touch\", \"{{ title }}\"]]\n");
+    fixture.deploy().expect("Deploy failed");
+    assert!(
+        fixture
+            .cwd
+            .join("it's a \"quoted\" name; with spaces")
+            .is_file()
+    );
+}
+
+#[test]
+fn test_argv_action_runs_without_a_shell() {
+    let fixture = TestFixture::new("pre_actions = [[\"touch\", \"$HOME\", \"a*\"]]\n");
+    fixture.deploy().expect("Deploy failed");
+    // Nothing expands them
+    assert!(fixture.cwd.join("$HOME").is_file());
+    assert!(fixture.cwd.join("a*").is_file());
+}
+
+#[test]
+fn test_shell_and_argv_actions_mix() {
+    let fixture = TestFixture::new(
+        "pre_actions = [\"echo shell > shell.txt\", [\"cp\", \"shell.txt\", \"argv copy.txt\"]]\n",
+    );
+    let report = fixture.deploy().expect("Deploy failed");
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("argv copy.txt")).unwrap(),
+        "shell\n"
+    );
+    assert_eq!(
+        report.packages[0].actions,
+        vec![
+            "echo shell > shell.txt".to_string(),
+            "[\"cp\", \"shell.txt\", \"argv copy.txt\"]".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn test_failed_argv_action_shows_its_argv() {
+    let fixture = TestFixture::new("post_actions = [[\"false\", \"{{ title }}\"]]\n");
+    let err = fixture.deploy().expect_err("Deploy should fail");
+    assert!(
+        err.to_string()
+            .contains("Action '[\"false\", \"{{ title }}\"]' failed"),
+        "{}",
+        err
+    );
+
+    let fixture = TestFixture::new("post_actions = [[\"dotr-no-such-program\"]]\n");
+    let err = fixture.deploy().expect_err("Deploy should fail");
+    assert!(
+        err.to_string()
+            .contains("Failed to start action '[\"dotr-no-such-program\"]'"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_argv_actions_round_trip() {
+    let table: toml::Table = r#"
+pre_actions = ["echo one", ["ln", "-sf", "{{ HOME }}/.vimrc", "{{ HOME }}/.config/vim/vimrc"]]
+post_actions = [["true"]]
+"#
+    .parse()
+    .expect("Invalid TOML");
+    let pkg = Package::from_table("meta", &table).expect("Failed to parse package");
+    assert_eq!(pkg.pre_actions[0], Action::Shell("echo one".to_string()));
+    assert_eq!(
+        pkg.pre_actions[1],
+        Action::Argv(vec![
+            "ln".to_string(),
+            "-sf".to_string(),
+            "{{ HOME }}/.vimrc".to_string(),
+            "{{ HOME }}/.config/vim/vimrc".to_string(),
+        ])
+    );
+    let reparsed = Package::from_table("meta", &pkg.to_table()).expect("Round trip should parse");
+    assert_eq!(reparsed.pre_actions, pkg.pre_actions);
+    assert_eq!(reparsed.post_actions, pkg.post_actions);
+}
+
+#[test]
+fn test_malformed_argv_actions_are_rejected() {
+    for actions in ["[[\"ls\", 1]]", "[[]]", "[1]"] {
+        let table: toml::Table = format!("pre_actions = {}", actions)
+            .parse()
+            .expect("Invalid TOML");
+        let err = Package::from_table("meta", &table).expect_err("Parsing should fail");
+        assert!(err.to_string().contains("pre_actions"), "{}", err);
+    }
+}
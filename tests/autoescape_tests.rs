@@ -0,0 +1,131 @@
+use std::{fs, path::PathBuf};
+
+use dotr::cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(config: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_autoescape_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(fixture.cwd.join("config.toml"), config).expect("Failed to write config");
+        fs::create_dir_all(fixture.cwd.join("dotfiles/d_bar")).unwrap();
+        fs::write(
+            fixture.cwd.join("dotfiles/d_bar/config.ini"),
+            "label = {{ label }}\nformat = \"{{ format }}\"\n",
+        )
+        .unwrap();
+        fs::write(
+            fixture.cwd.join("dotfiles/d_bar/index.html"),
+            "<p>{{ label }}</p>\n",
+        )
+        .unwrap();
+        fs::write(
+            fixture.cwd.join("dotfiles/d_bar/shared.conf"),
+            "{% include \"header.tera\" %}",
+        )
+        .unwrap();
+        fs::write(fixture.cwd.join("templates/header.tera"), "# {{ label }}\n").unwrap();
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self) {
+        self.run(Command::Deploy(DeployUpdateArgs::default()))
+            .expect("Deploy failed");
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join("home/.config/bar").join(path)).unwrap()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[variables]
+label = "Tom & Jerry <3"
+format = "%{F#fff}'x' \"y\"%{F-}"
+
+[packages.d_bar]
+src = "dotfiles/d_bar"
+dest = "home/.config/bar"
+"#;
+
+#[test]
+fn test_deployed_files_are_not_escaped() {
+    let fixture = TestFixture::new(CONFIG);
+    fixture.deploy();
+    assert_eq!(
+        fixture.read("config.ini"),
+        "label = Tom & Jerry <3\nformat = \"%{F#fff}'x' \"y\"%{F-}\"\n"
+    );
+    // Not even files Tera would escape by their extension
+    assert_eq!(fixture.read("index.html"), "<p>Tom & Jerry <3</p>\n");
+    assert_eq!(fixture.read("shared.conf"), "# Tom & Jerry <3\n");
+}
+
+#[test]
+fn test_actions_are_not_escaped() {
+    let config = format!(
+        "{}post_actions = [\"echo '{{{{ label }}}}' > action.txt\"]\n",
+        CONFIG
+    );
+    let fixture = TestFixture::new(&config);
+    fixture.deploy();
+    let written = fs::read_to_string(fixture.cwd.join("action.txt")).unwrap();
+    assert_eq!(written.trim_end(), "Tom & Jerry <3");
+}
+
+#[test]
+fn test_autoescape_opts_in() {
+    let fixture = TestFixture::new(&format!("{}autoescape = true\n", CONFIG));
+    fixture.deploy();
+    assert_eq!(fixture.read("index.html"), "<p>Tom &amp; Jerry &lt;3</p>\n");
+    assert!(
+        fixture.read("config.ini").contains("&quot;y&quot;"),
+        "{}",
+        fixture.read("config.ini")
+    );
+    // `safe` still writes a value as it is
+    fs::write(
+        fixture.cwd.join("dotfiles/d_bar/index.html"),
+        "<p>{{ label | safe }}</p>\n",
+    )
+    .unwrap();
+    fixture.deploy();
+    assert_eq!(fixture.read("index.html"), "<p>Tom & Jerry <3</p>\n");
+}
+
+#[test]
+fn test_toggling_autoescape_redeploys_cached_files() {
+    let fixture = TestFixture::new(CONFIG);
+    fixture.deploy();
+    fs::write(
+        fixture.cwd.join("config.toml"),
+        format!("{}autoescape = true\n", CONFIG),
+    )
+    .unwrap();
+    fixture.deploy();
+    assert_eq!(fixture.read("index.html"), "<p>Tom &amp; Jerry &lt;3</p>\n");
+}
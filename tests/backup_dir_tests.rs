@@ -0,0 +1,167 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    config::Config,
+    context::Context,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(backup_dir: Option<&str>) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_backup_dir_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        let backup_dir = backup_dir
+            .map(|dir| format!("backup_dir = \"{}\"\n", dir))
+            .unwrap_or_default();
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            format!(
+                "banner = false\n{}\n[packages.d_nvim]\nsrc = \"dotfiles/nvim\"\ndest = \"home/nvim\"\nprune_dest = true\n",
+                backup_dir
+            ),
+        )
+        .expect("Failed to write config");
+        fixture.write("dotfiles/nvim/init.lua", "-- new\n");
+        fixture.write("home/nvim/init.lua", "-- mine\n");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self) {
+        self.run(Command::Deploy(DeployUpdateArgs::default()))
+            .expect("Deploy failed");
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    // Every file under `dir`, with its content
+    fn files_under(&self, dir: &str) -> Vec<(PathBuf, String)> {
+        walkdir::WalkDir::new(self.cwd.join(dir))
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| {
+                (
+                    entry.path().to_path_buf(),
+                    fs::read_to_string(entry.path()).unwrap(),
+                )
+            })
+            .collect()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_backups_go_under_backup_dir() {
+    let fixture = TestFixture::new(Some(".dotr/backups"));
+    fixture.write("home/nvim/old.lua", "-- pruned\n");
+    fixture.deploy();
+
+    assert!(!fixture.cwd.join("home/nvim/init.lua.dotrbak").exists());
+    assert!(!fixture.cwd.join("home/nvim/old.lua.dotrbak").exists());
+    let backups = fixture.files_under(".dotr/backups/d_nvim");
+    assert_eq!(backups.len(), 2, "{:?}", backups);
+    let (init_backup, content) = backups
+        .iter()
+        .find(|(path, _)| path.parent().unwrap().ends_with("home/nvim/init.lua"))
+        .expect("init.lua should be backed up");
+    assert_eq!(content, "-- mine\n");
+    // Named by when it was taken
+    let name = init_backup.file_name().unwrap().to_string_lossy();
+    assert!(name.parse::<u64>().is_ok(), "{}", name);
+    assert!(backups.iter().any(|(path, content)| {
+        path.parent().unwrap().ends_with("home/nvim/old.lua") && content == "-- pruned\n"
+    }));
+}
+
+#[test]
+fn test_backups_stay_beside_dests_by_default() {
+    let fixture = TestFixture::new(None);
+    fixture.deploy();
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("home/nvim/init.lua.dotrbak")).unwrap(),
+        "-- mine\n"
+    );
+    assert!(!fixture.cwd.join(".dotr/backups").exists());
+}
+
+#[test]
+fn test_every_backup_is_kept() {
+    let fixture = TestFixture::new(Some("backups"));
+    fixture.deploy();
+    fixture.write("home/nvim/init.lua", "-- edited again\n");
+    fixture.deploy();
+
+    let mut contents: Vec<String> = fixture
+        .files_under("backups")
+        .into_iter()
+        .map(|(_, content)| content)
+        .collect();
+    contents.sort();
+    assert_eq!(contents, vec!["-- edited again\n", "-- mine\n"]);
+
+    let conf = Config::from_path(&fixture.cwd).unwrap();
+    let ctx = Context::from_config(&fixture.cwd, &conf).unwrap();
+    let latest = conf.packages["d_nvim"]
+        .latest_backup(&fixture.cwd.join("home/nvim/init.lua"), &ctx)
+        .unwrap()
+        .expect("There is a backup");
+    assert_eq!(fs::read_to_string(latest).unwrap(), "-- edited again\n");
+}
+
+#[test]
+fn test_latest_backup_falls_back_to_the_sibling_file() {
+    let fixture = TestFixture::new(None);
+    fixture.deploy();
+    // backup_dir set after the sibling backup was made
+    let mut conf = Config::from_path(&fixture.cwd).unwrap();
+    conf.backup_dir = Some(".dotr/backups".to_string());
+    let ctx = Context::from_config(&fixture.cwd, &conf).unwrap();
+    let dest = fixture.cwd.join("home/nvim/init.lua");
+    assert_eq!(
+        conf.packages["d_nvim"].latest_backup(&dest, &ctx).unwrap(),
+        Some(fixture.cwd.join("home/nvim/init.lua.dotrbak"))
+    );
+}
+
+#[test]
+fn test_init_ignores_the_backup_dir() {
+    let fixture = TestFixture::new(None);
+    let gitignore = fs::read_to_string(fixture.cwd.join(".gitignore")).unwrap();
+    // .dotr/ already covers the suggested .dotr/backups
+    assert!(
+        gitignore.lines().any(|line| line == ".dotr/"),
+        "{}",
+        gitignore
+    );
+    assert!(
+        !gitignore.lines().any(|line| line == ".dotr/backups/"),
+        "{}",
+        gitignore
+    );
+}
@@ -0,0 +1,119 @@
+use std::{fs, path::PathBuf, process::Command as Process};
+
+use dotr::{
+    Config, Context,
+    cli::{BANNER_MAX_LINES, render_banner},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(banner: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_banner_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture.dotr(&["init"]);
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            format!("banner = true\n{}{}", banner, CONFIG),
+        )
+        .expect("Failed to write config");
+        fixture
+    }
+
+    fn dotr(&self, args: &[&str]) -> (String, String) {
+        let output = Process::new(env!("CARGO_BIN_EXE_dotr"))
+            .args(["-w", self.cwd.to_str().unwrap(), "--no-progress"])
+            .args(args)
+            .env_remove("DOTR_PROFILE")
+            .output()
+            .expect("Failed to run dotr");
+        (
+            String::from_utf8_lossy(&output.stdout).to_string(),
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        )
+    }
+
+    fn banner(&self) -> String {
+        let conf = Config::from_path(&self.cwd).expect("Failed to load config");
+        let ctx = Context::from_config(&self.cwd, &conf).expect("Failed to build context");
+        render_banner(&conf, &ctx)
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+default_profile = "work"
+
+[variables]
+name = "Ada"
+
+[profiles.work]
+"#;
+
+#[test]
+fn test_banner_text_is_rendered_with_variables() {
+    let fixture = TestFixture::new(
+        "banner_text = \"Hi {{ name }} on {{ dotr.hostname }} ({{ dotr.profile }})\"\n",
+    );
+    let banner = fixture.banner();
+    assert!(banner.starts_with("Hi Ada on "), "{}", banner);
+    assert!(banner.ends_with("(work)"), "{}", banner);
+
+    // On stderr, leaving stdout to the command
+    let (stdout, stderr) = fixture.dotr(&["list"]);
+    assert!(!stdout.contains("Hi Ada"), "{}", stdout);
+    assert!(stderr.contains("Hi Ada on "), "{}", stderr);
+}
+
+#[test]
+fn test_banner_file_is_read_from_the_repository() {
+    let fixture = TestFixture::new("banner_file = \"banner.txt\"\n");
+    fs::write(fixture.cwd.join("banner.txt"), "Welcome back, {{ name }}\n").unwrap();
+    assert_eq!(fixture.banner(), "Welcome back, Ada");
+}
+
+#[test]
+fn test_banner_falls_back_when_it_fails_to_render() {
+    let fixture = TestFixture::new("banner_text = \"{{ undefined_variable }}\"\n");
+    assert!(fixture.banner().contains("██"));
+
+    // The command still runs, with a warning
+    let (stdout, stderr) = fixture.dotr(&["list"]);
+    assert!(stdout.contains("No packages defined"), "{}", stdout);
+    assert!(
+        !stdout.contains("Failed to render the banner"),
+        "{}",
+        stdout
+    );
+    assert!(stderr.contains("Failed to render the banner"), "{}", stderr);
+    assert!(stderr.contains("██"), "{}", stderr);
+
+    let fixture = TestFixture::new("banner_file = \"missing.txt\"\n");
+    assert!(fixture.banner().contains("██"));
+}
+
+#[test]
+fn test_long_banner_is_trimmed() {
+    let fixture = TestFixture::new("banner_file = \"banner.txt\"\n");
+    let long: String = (1..=1000).map(|i| format!("line {}\n", i)).collect();
+    fs::write(fixture.cwd.join("banner.txt"), long).unwrap();
+    let banner = fixture.banner();
+    assert_eq!(banner.lines().count(), BANNER_MAX_LINES);
+    assert!(banner.ends_with(&format!("line {}", BANNER_MAX_LINES)));
+}
+
+#[test]
+fn test_banner_text_and_file_conflict() {
+    let fixture = TestFixture::new("banner_text = \"one\"\nbanner_file = \"banner.txt\"\n");
+    let err = Config::from_path(&fixture.cwd).expect_err("Config should fail");
+    assert!(format!("{:#}", err).contains("not both"), "{:#}", err);
+}
@@ -0,0 +1,229 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{CleanArgs, Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(package: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_block_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            format!(
+                "banner = false\n\n[variables]\neditor = \"vim\"\n\n{}",
+                package
+            ),
+        )
+        .expect("Failed to write config");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self) -> Result<(), anyhow::Error> {
+        self.run(Command::Deploy(DeployUpdateArgs::default()))
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).unwrap()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const ZSHRC: &str =
+    "[packages.f_zshrc]\nsrc = \"dotfiles/zshrc\"\ndest = \"home/.zshrc\"\nblock = true\n";
+
+#[test]
+fn test_block_is_appended_to_an_existing_dest() {
+    let fixture = TestFixture::new(ZSHRC);
+    fixture.write("dotfiles/zshrc", "export EDITOR={{ editor }}\n");
+    fixture.write("home/.zshrc", "# added by a tool\neval \"$(tool init)\"");
+
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(
+        fixture.read("home/.zshrc"),
+        "# added by a tool\neval \"$(tool init)\"\n\
+         # >>> dotr:f_zshrc >>>\nexport EDITOR=vim\n# <<< dotr:f_zshrc <<<\n"
+    );
+    assert_eq!(
+        fixture.read("home/.zshrc.dotrbak"),
+        "# added by a tool\neval \"$(tool init)\""
+    );
+}
+
+#[test]
+fn test_block_creates_a_missing_dest() {
+    let fixture = TestFixture::new(ZSHRC);
+    fixture.write("dotfiles/zshrc", "alias ll='ls -la'");
+
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(
+        fixture.read("home/.zshrc"),
+        "# >>> dotr:f_zshrc >>>\nalias ll='ls -la'\n# <<< dotr:f_zshrc <<<\n"
+    );
+}
+
+#[test]
+fn test_block_replaces_the_previous_block_only() {
+    let fixture = TestFixture::new(ZSHRC);
+    fixture.write("dotfiles/zshrc", "one\n");
+    fixture.write("home/.zshrc", "before\n");
+    fixture.deploy().expect("Deploy failed");
+    // Another tool appends after our block
+    let deployed = fixture.read("home/.zshrc");
+    fixture.write("home/.zshrc", &format!("{}after\n", deployed));
+
+    fixture.write("dotfiles/zshrc", "two\nthree\n");
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(
+        fixture.read("home/.zshrc"),
+        "before\n# >>> dotr:f_zshrc >>>\ntwo\nthree\n# <<< dotr:f_zshrc <<<\nafter\n"
+    );
+}
+
+#[test]
+fn test_unchanged_block_leaves_dest_alone() {
+    let fixture = TestFixture::new(ZSHRC);
+    fixture.write("dotfiles/zshrc", "one\n");
+    fixture.write("home/.zshrc", "before\n");
+    fixture.deploy().expect("Deploy failed");
+    fs::remove_file(fixture.cwd.join("home/.zshrc.dotrbak")).unwrap();
+
+    fixture
+        .run(Command::Deploy(DeployUpdateArgs {
+            no_cache: true,
+            ..Default::default()
+        }))
+        .expect("Deploy failed");
+    assert!(!fixture.cwd.join("home/.zshrc.dotrbak").exists());
+}
+
+#[test]
+fn test_block_uses_the_configured_comment() {
+    let fixture = TestFixture::new(
+        "[packages.f_vimrc]\nsrc = \"dotfiles/vimrc\"\ndest = \"home/.vimrc\"\nblock = true\nblock_comment = \"\\\"\"\n",
+    );
+    fixture.write("dotfiles/vimrc", "set number\n");
+
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(
+        fixture.read("home/.vimrc"),
+        "\" >>> dotr:f_vimrc >>>\nset number\n\" <<< dotr:f_vimrc <<<\n"
+    );
+}
+
+#[test]
+fn test_unclosed_block_is_an_error() {
+    let fixture = TestFixture::new(ZSHRC);
+    fixture.write("dotfiles/zshrc", "one\n");
+    fixture.write("home/.zshrc", "# >>> dotr:f_zshrc >>>\nstale\n");
+
+    let err = fixture.deploy().unwrap_err().to_string();
+    assert!(err.contains("never closed"), "{}", err);
+    assert_eq!(
+        fixture.read("home/.zshrc"),
+        "# >>> dotr:f_zshrc >>>\nstale\n"
+    );
+}
+
+#[test]
+fn test_update_copies_back_only_the_block() {
+    let fixture = TestFixture::new(ZSHRC);
+    fixture.write("dotfiles/zshrc", "one\n");
+    fixture.write("home/.zshrc", "before\n");
+    fixture.deploy().expect("Deploy failed");
+    fixture.write(
+        "home/.zshrc",
+        "before\n# >>> dotr:f_zshrc >>>\none\nedited\n# <<< dotr:f_zshrc <<<\nafter\n",
+    );
+
+    fixture
+        .run(Command::Update(UpdateArgs::default()))
+        .expect("Update failed");
+    assert_eq!(fixture.read("dotfiles/zshrc"), "one\nedited\n");
+}
+
+#[test]
+fn test_update_skips_a_dest_without_the_block() {
+    let fixture = TestFixture::new(ZSHRC);
+    fixture.write("dotfiles/zshrc", "one\n");
+    fixture.write("home/.zshrc", "nothing of ours\n");
+
+    fixture
+        .run(Command::Update(UpdateArgs::default()))
+        .expect("Update failed");
+    assert_eq!(fixture.read("dotfiles/zshrc"), "one\n");
+}
+
+#[test]
+fn test_clean_keeps_block_dests() {
+    let fixture = TestFixture::new(ZSHRC);
+    fixture.write("dotfiles/zshrc", "one\n");
+    fixture.deploy().expect("Deploy failed");
+
+    fixture
+        .run(Command::Clean(CleanArgs::default()))
+        .expect("Clean failed");
+    assert!(fixture.cwd.join("home/.zshrc").exists());
+}
+
+#[test]
+fn test_block_rejects_a_directory_src() {
+    let fixture = TestFixture::new(
+        "[packages.d_app]\nsrc = \"dotfiles/app\"\ndest = \"home/app\"\nblock = true\n",
+    );
+    fixture.write("dotfiles/app/config", "one\n");
+
+    let err = fixture.deploy().unwrap_err().to_string();
+    assert!(err.contains("only works with a file src"), "{}", err);
+}
+
+#[test]
+fn test_block_and_merge_conflict() {
+    let fixture = TestFixture::new(
+        "[packages.f_app]\nsrc = \"dotfiles/app.json\"\ndest = \"home/app.json\"\nblock = true\nmerge = \"json\"\n",
+    );
+    let err = Config::from_path(&fixture.cwd).unwrap_err().to_string();
+    assert!(err.contains("both block and merge"), "{}", err);
+}
+
+#[test]
+fn test_block_round_trips() {
+    let fixture = TestFixture::new(
+        "[packages.f_vimrc]\nsrc = \"dotfiles/vimrc\"\ndest = \"home/.vimrc\"\nblock = true\nblock_comment = \"\\\"\"\n",
+    );
+    let conf = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    conf.save(&fixture.cwd).expect("Failed to save config");
+    let conf = Config::from_path(&fixture.cwd).expect("Failed to reload config");
+    let package = &conf.packages["f_vimrc"];
+    assert!(package.block);
+    assert_eq!(package.block_comment(), "\"");
+}
@@ -0,0 +1,238 @@
+use std::{fs, path::PathBuf, time::SystemTime};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    manifest::sha256_bytes,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_cache_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+
+    fn deploy(&self, no_cache: bool) {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs {
+            no_cache,
+            ..Default::default()
+        }))))
+        .expect("Deploy failed");
+    }
+
+    fn deploy_forced(&self) {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs {
+            force: true,
+            ..Default::default()
+        }))))
+        .expect("Deploy failed");
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    // Rewrite a file but keep its mtime, so only a full comparison notices.
+    fn rewrite_keeping_mtime(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        let mtime = self.mtime(path);
+        fs::write(&file_path, content).expect("Failed to write file");
+        fs::File::options()
+            .write(true)
+            .open(&file_path)
+            .and_then(|f| f.set_modified(mtime))
+            .expect("Failed to restore mtime");
+    }
+
+    fn set_mtime(&self, path: &str, mtime: SystemTime) {
+        fs::File::options()
+            .write(true)
+            .open(self.cwd.join(path))
+            .and_then(|f| f.set_modified(mtime))
+            .expect("Failed to set mtime");
+    }
+
+    fn mtime(&self, path: &str) -> SystemTime {
+        fs::metadata(self.cwd.join(path))
+            .and_then(|m| m.modified())
+            .expect("Failed to read mtime")
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[variables]
+NAME = "first"
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "rc"
+
+[packages.f_tpl]
+src = "dotfiles/f_tpl"
+dest = "tpl"
+"#;
+
+fn setup() -> TestFixture {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("config.toml", CONFIG);
+    fixture.write_file("dotfiles/f_rc", "plain v1\n");
+    fixture.write_file("dotfiles/f_tpl", "name={{ NAME }}\n");
+    fixture.deploy(false);
+    fixture
+}
+
+#[test]
+fn test_deploy_writes_cache() {
+    let fixture = setup();
+    let cache = fixture.read_file(".dotr/cache.toml");
+    assert!(cache.contains("config_hash"));
+    assert!(cache.contains(&fixture.cwd.join("rc").to_string_lossy().to_string()));
+    assert!(cache.contains(&fixture.cwd.join("tpl").to_string_lossy().to_string()));
+}
+
+#[test]
+fn test_cache_records_sha256_of_dest() {
+    let fixture = setup();
+    let cache = fixture.read_file(".dotr/cache.toml");
+    // The cache is read back by later runs, so its hashes must be stable
+    assert!(cache.contains(&sha256_bytes(b"plain v1\n")));
+    assert!(cache.contains(&sha256_bytes(b"name=first\n")));
+}
+
+#[test]
+fn test_unchanged_src_is_skipped_and_no_cache_bypasses() {
+    let fixture = setup();
+    // Same mtime, so the cache trusts the src without reading it
+    fixture.rewrite_keeping_mtime("dotfiles/f_rc", "plain v2\n");
+
+    fixture.deploy(false);
+    assert_eq!(fixture.read_file("rc"), "plain v1\n");
+
+    fixture.deploy(true);
+    assert_eq!(fixture.read_file("rc"), "plain v2\n");
+}
+
+#[test]
+fn test_changed_src_mtime_is_redeployed() {
+    let fixture = setup();
+    fixture.write_file("dotfiles/f_rc", "plain v2\n");
+    let later = fixture.mtime("dotfiles/f_rc") + std::time::Duration::from_secs(5);
+    fs::File::options()
+        .write(true)
+        .open(fixture.cwd.join("dotfiles/f_rc"))
+        .and_then(|f| f.set_modified(later))
+        .expect("Failed to bump mtime");
+
+    fixture.deploy(false);
+    assert_eq!(fixture.read_file("rc"), "plain v2\n");
+}
+
+#[test]
+fn test_modified_dest_is_redeployed() {
+    let fixture = setup();
+    fixture.write_file("rc", "edited by hand\n");
+
+    fixture.deploy(false);
+    assert_eq!(fixture.read_file("rc"), "plain v1\n");
+    assert_eq!(fixture.read_file("rc.dotrbak"), "edited by hand\n");
+}
+
+#[test]
+fn test_variable_change_rerenders_template() {
+    let fixture = setup();
+    assert_eq!(fixture.read_file("tpl"), "name=first\n");
+    fixture.write_file(".uservariables.toml", "NAME = \"second\"\n");
+
+    fixture.deploy(false);
+    assert_eq!(fixture.read_file("tpl"), "name=second\n");
+}
+
+#[test]
+fn test_config_change_invalidates_cache() {
+    let fixture = setup();
+    fixture.rewrite_keeping_mtime("dotfiles/f_rc", "plain v2\n");
+    fixture.write_file("config.toml", &format!("{}\n# touched\n", CONFIG));
+
+    fixture.deploy(false);
+    assert_eq!(fixture.read_file("rc"), "plain v2\n");
+}
+
+#[test]
+fn test_shared_template_change_rerenders_template() {
+    let fixture = setup();
+    fixture.write_file("templates/greeting.tera", "hello\n");
+    fixture.write_file(
+        "dotfiles/f_tpl",
+        "{% include \"greeting.tera\" %}name={{ NAME }}\n",
+    );
+    fixture.deploy(false);
+    assert_eq!(fixture.read_file("tpl"), "hello\nname=first\n");
+
+    // Only the partial changes, the src keeps its mtime
+    fixture.write_file("templates/greeting.tera", "goodbye\n");
+    fixture.deploy(false);
+    assert_eq!(fixture.read_file("tpl"), "goodbye\nname=first\n");
+}
+
+#[test]
+fn test_noop_deploy_keeps_dest_mtime() {
+    let fixture = setup();
+    let earlier = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+    fixture.set_mtime("rc", earlier);
+    fixture.set_mtime("tpl", earlier);
+
+    // Without the cache every file is read, rendered and compared
+    fixture.deploy(true);
+    assert_eq!(fixture.mtime("rc"), earlier);
+    assert_eq!(fixture.mtime("tpl"), earlier);
+    assert!(!fixture.cwd.join("rc.dotrbak").exists());
+    assert!(!fixture.cwd.join("tpl.dotrbak").exists());
+}
+
+#[test]
+fn test_force_rewrites_and_backs_up() {
+    let fixture = setup();
+    let earlier = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+    fixture.set_mtime("rc", earlier);
+    fixture.set_mtime("tpl", earlier);
+
+    fixture.deploy_forced();
+    assert_ne!(fixture.mtime("rc"), earlier);
+    assert_ne!(fixture.mtime("tpl"), earlier);
+    assert_eq!(fixture.read_file("rc.dotrbak"), "plain v1\n");
+    assert_eq!(fixture.read_file("tpl.dotrbak"), "name=first\n");
+}
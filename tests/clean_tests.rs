@@ -0,0 +1,220 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{CleanArgs, Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    config::Config,
+    manifest::sha256_bytes,
+    package::Package,
+    state::{DeployState, state_path},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_clean_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    // A directory package d_nvim deploying to home/.config/nvim, and a file
+    // package f_rc deploying into the existing home directory
+    fn setup(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+        fs::create_dir_all(self.cwd.join("dotfiles/d_nvim/lua")).unwrap();
+        fs::write(self.cwd.join("dotfiles/d_nvim/init.lua"), "init").unwrap();
+        fs::write(self.cwd.join("dotfiles/d_nvim/lua/plugins.lua"), "plugins").unwrap();
+        fs::write(self.cwd.join("dotfiles/f_rc"), "rc").unwrap();
+        fs::create_dir_all(self.cwd.join("home")).unwrap();
+        self.add_package("d_nvim", "dotfiles/d_nvim", "home/.config/nvim");
+        self.add_package("f_rc", "dotfiles/f_rc", "home/.rc");
+    }
+
+    fn add_package(&self, name: &str, src: &str, dest: &str) {
+        let mut config = Config::from_path(&self.cwd).expect("Failed to load config");
+        let mut table = toml::Table::new();
+        table.insert("src".to_string(), toml::Value::String(src.to_string()));
+        table.insert(
+            "dest".to_string(),
+            toml::Value::String(self.cwd.join(dest).to_str().unwrap().to_string()),
+        );
+        let package = Package::from_table(name, &table).expect("Failed to build package");
+        config.packages.insert(name.to_string(), package);
+        config.save(&self.cwd).expect("Failed to save config");
+    }
+
+    fn deploy(&self) {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))))
+            .expect("Deploy failed");
+    }
+
+    fn clean(&self, args: CleanArgs) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Clean(args))))
+    }
+
+    fn state(&self) -> DeployState {
+        DeployState::load(&self.cwd).expect("Failed to load state")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.cwd);
+    }
+}
+
+#[test]
+fn test_deploy_records_state() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+    fixture.deploy();
+
+    assert!(state_path(&fixture.cwd).exists());
+    let state = fixture.state();
+    assert_eq!(state.package_names(), vec!["d_nvim", "f_rc"]);
+    let nvim = state.package("d_nvim").unwrap();
+    let dest = fixture.cwd.join("home/.config/nvim");
+    // Hashed like the manifests, so the state reads the same in any build
+    assert_eq!(
+        nvim.files.get(dest.join("init.lua").to_str().unwrap()),
+        Some(&sha256_bytes(b"init"))
+    );
+    assert!(
+        nvim.files
+            .contains_key(dest.join("lua/plugins.lua").to_str().unwrap())
+    );
+    // home existed already, .config and below were created by deploy
+    assert!(
+        nvim.dirs
+            .contains(fixture.cwd.join("home/.config").to_str().unwrap())
+    );
+    assert!(
+        !nvim
+            .dirs
+            .contains(fixture.cwd.join("home").to_str().unwrap())
+    );
+    assert!(state.package("f_rc").unwrap().dirs.is_empty());
+
+    // A second deploy, served from the cache, keeps every entry
+    fixture.deploy();
+    let redeployed = fixture.state().package("d_nvim").cloned().unwrap();
+    assert_eq!(redeployed.files, nvim.files);
+    assert_eq!(redeployed.dirs, nvim.dirs);
+}
+
+#[test]
+fn test_clean_removes_deployed_files_and_created_dirs() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+    fixture.deploy();
+    fs::write(fixture.cwd.join("home/unrelated"), "mine").unwrap();
+
+    fixture.clean(CleanArgs::default()).expect("Clean failed");
+    assert!(!fixture.cwd.join("home/.rc").exists());
+    assert!(!fixture.cwd.join("home/.config").exists());
+    assert!(fixture.cwd.join("home/unrelated").exists());
+    assert!(fixture.state().package_names().is_empty());
+}
+
+#[test]
+fn test_clean_only_named_packages() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+    fixture.deploy();
+
+    fixture
+        .clean(CleanArgs {
+            packages: Some(vec!["f_rc".to_string()]),
+            ..Default::default()
+        })
+        .expect("Clean failed");
+    assert!(!fixture.cwd.join("home/.rc").exists());
+    assert!(fixture.cwd.join("home/.config/nvim/init.lua").exists());
+    assert_eq!(fixture.state().package_names(), vec!["d_nvim"]);
+
+    let err = fixture
+        .clean(CleanArgs {
+            packages: Some(vec!["f_rc".to_string()]),
+            ..Default::default()
+        })
+        .expect_err("Clean should fail");
+    assert!(
+        err.to_string()
+            .contains("Package 'f_rc' has no deployed files")
+    );
+}
+
+#[test]
+fn test_clean_keeps_edited_files_without_force() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+    fixture.deploy();
+    let edited = fixture.cwd.join("home/.config/nvim/init.lua");
+    fs::write(&edited, "my own init").unwrap();
+
+    let err = fixture
+        .clean(CleanArgs::default())
+        .expect_err("Clean should refuse edited files");
+    assert!(err.to_string().contains("Kept 1 file(s)"), "{}", err);
+    assert!(edited.exists());
+    assert!(!fixture.cwd.join("home/.config/nvim/lua").exists());
+    assert!(!fixture.cwd.join("home/.rc").exists());
+    assert_eq!(fixture.state().package_names(), vec!["d_nvim"]);
+
+    fixture
+        .clean(CleanArgs {
+            force: true,
+            ..Default::default()
+        })
+        .expect("Forced clean failed");
+    assert!(!fixture.cwd.join("home/.config").exists());
+    assert!(fixture.state().package_names().is_empty());
+}
+
+#[test]
+fn test_clean_dry_run_removes_nothing() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+    fixture.deploy();
+
+    fixture
+        .clean(CleanArgs {
+            dry_run: true,
+            ..Default::default()
+        })
+        .expect("Clean failed");
+    assert!(fixture.cwd.join("home/.rc").exists());
+    assert!(fixture.cwd.join("home/.config/nvim/init.lua").exists());
+    assert_eq!(fixture.state().package_names(), vec!["d_nvim", "f_rc"]);
+}
+
+#[test]
+fn test_deploy_prunes_files_no_longer_owned() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+    fixture.deploy();
+    let plugins = fixture.cwd.join("home/.config/nvim/lua/plugins.lua");
+
+    fs::remove_file(fixture.cwd.join("dotfiles/d_nvim/lua/plugins.lua")).unwrap();
+    fixture.deploy();
+    let state = fixture.state();
+    let nvim = state.package("d_nvim").unwrap();
+    assert!(!nvim.files.contains_key(plugins.to_str().unwrap()));
+    assert_eq!(nvim.files.len(), 1);
+
+    // No longer owned, so clean leaves it alone
+    fixture.clean(CleanArgs::default()).expect("Clean failed");
+    assert!(plugins.exists());
+    assert!(!fixture.cwd.join("home/.config/nvim/init.lua").exists());
+}
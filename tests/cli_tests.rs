@@ -1,8 +1,11 @@
 use std::{collections::HashMap, fs, path::PathBuf};
 
 use dotr::{
-    cli::{Cli, Command, DeployUpdateArgs, ImportArgs, InitArgs, PrintVarsArgs, run_cli},
-    config::Config,
+    cli::{
+        Cli, Command, DeployUpdateArgs, ImportArgs, InitArgs, PrintVarsArgs, UpdateArgs,
+        VarsFormat, run_cli,
+    },
+    config::{Config, ProfileSelection, ProfileSource},
 };
 
 struct TestFixture {
@@ -20,11 +23,12 @@ impl TestFixture {
         Cli {
             command,
             working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
         }
     }
 
     fn init(&self) {
-        run_cli(self.get_cli(Some(Command::Init(InitArgs {})))).expect("Init failed");
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
     }
 
     fn get_config(&self) -> Config {
@@ -73,6 +77,18 @@ fn test_init_creates_config() {
         gitignore.contains(".uservariables.toml"),
         ".gitignore should contain .uservariables.toml"
     );
+    assert!(
+        gitignore.contains(".uservariables.*.toml"),
+        ".gitignore should contain per-profile user variable files"
+    );
+    assert!(
+        gitignore.contains(".dotr/"),
+        ".gitignore should contain the deploy cache directory"
+    );
+    assert!(
+        gitignore.contains(".dotr.lock"),
+        ".gitignore should contain the lock file"
+    );
 }
 
 #[test]
@@ -102,8 +118,9 @@ fn test_import_creates_package() {
 
     let _ = run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
         name: None,
-        path: fixture.cwd.join("test.conf").to_str().unwrap().to_string(),
+        paths: vec![fixture.cwd.join("test.conf").to_str().unwrap().to_string()],
         profile: None,
+        ..Default::default()
     }))));
 
     let config = fixture.get_config();
@@ -124,8 +141,9 @@ fn test_import_with_profile() {
 
     let _ = run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
         name: None,
-        path: fixture.cwd.join("work.conf").to_str().unwrap().to_string(),
+        paths: vec![fixture.cwd.join("work.conf").to_str().unwrap().to_string()],
         profile: Some("work".to_string()),
+        ..Default::default()
     }))));
 
     let config = fixture.get_config();
@@ -159,21 +177,16 @@ fn test_deploy_creates_files() {
 
     let test_package = dotr::package::Package {
         name: "f_test".to_string(),
-        src: "dotfiles/f_test".to_string(),
-        dest: fixture
-            .cwd
-            .join("deploy_dest")
-            .to_str()
-            .unwrap()
-            .to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_test".to_string()),
+        dest: Some(
+            fixture
+                .cwd
+                .join("deploy_dest")
+                .to_str()
+                .unwrap()
+                .to_string(),
+        ),
+        ..dotr::package::Package::default()
     };
 
     config.packages.insert("f_test".to_string(), test_package);
@@ -182,11 +195,144 @@ fn test_deploy_creates_files() {
     let _ = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: None,
         profile: None,
+        ..Default::default()
     }))));
 
     fixture.assert_file_exists("deploy_dest/config.txt", "Deployed file should exist");
 }
 
+fn add_file_package(fixture: &TestFixture, name: &str, dest: &str) {
+    let mut config = fixture.get_config();
+    let mut table = toml::Table::new();
+    table.insert(
+        "src".to_string(),
+        toml::Value::String(format!("dotfiles/{}", name)),
+    );
+    table.insert("dest".to_string(), toml::Value::String(dest.to_string()));
+    let package =
+        dotr::package::Package::from_table(name, &table).expect("Failed to build package");
+    config.packages.insert(name.to_string(), package);
+    config.save(&fixture.cwd).expect("Failed to save config");
+}
+
+#[test]
+fn test_deploy_file_creates_missing_parent_dirs() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("dotfiles/f_plain", "plain");
+    fixture.write_file("dotfiles/f_templated", "home is {{ HOME }}");
+    fs::write(fixture.cwd.join("dotfiles/f_binary"), [0xff, 0xfe, 0x00]).unwrap();
+
+    for name in ["f_plain", "f_templated", "f_binary"] {
+        let dest = fixture.cwd.join("out/a/b/c").join(name);
+        add_file_package(&fixture, name, dest.to_str().unwrap());
+    }
+
+    run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))))
+        .expect("Deploy should create the missing directories");
+
+    assert_eq!(fixture.read_file("out/a/b/c/f_plain"), "plain");
+    assert!(
+        fixture
+            .read_file("out/a/b/c/f_templated")
+            .starts_with("home is /")
+    );
+    assert_eq!(
+        fs::read(fixture.cwd.join("out/a/b/c/f_binary")).unwrap(),
+        vec![0xff, 0xfe, 0x00]
+    );
+}
+
+#[test]
+fn test_deploy_file_error_names_dest() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("dotfiles/f_plain", "plain");
+    // A file where a parent directory should be
+    fixture.write_file("blocker", "not a directory");
+    let dest = fixture.cwd.join("blocker/nested/f_plain");
+    add_file_package(&fixture, "f_plain", dest.to_str().unwrap());
+
+    let err = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))))
+        .expect_err("Deploy should fail");
+    assert!(
+        err.to_string().contains(&dest.display().to_string()),
+        "Error should name the dest: {}",
+        err
+    );
+}
+
+#[test]
+fn test_deploy_binary_file_skips_identical_content() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    let blob = vec![0x00, 0xff, 0x10, 0x80, 0xfe];
+    fs::write(fixture.cwd.join("dotfiles/f_font"), &blob).unwrap();
+    let dest = fixture.cwd.join("out/font.bin");
+    fs::create_dir_all(dest.parent().unwrap()).unwrap();
+    fs::write(&dest, [0xff, 0x01]).unwrap();
+    add_file_package(&fixture, "f_font", dest.to_str().unwrap());
+
+    let deploy = || {
+        run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
+            no_cache: true,
+            ..Default::default()
+        }))))
+        .expect("Deploy failed")
+    };
+    deploy();
+    assert_eq!(fs::read(&dest).unwrap(), blob);
+    let backup = fixture.cwd.join("out/font.bin.dotrbak");
+    assert_eq!(fs::read(&backup).unwrap(), vec![0xff, 0x01]);
+    let mtime = fs::metadata(&dest).unwrap().modified().unwrap();
+
+    deploy();
+    assert_eq!(
+        fs::read(&backup).unwrap(),
+        vec![0xff, 0x01],
+        "An unchanged binary should not be backed up again"
+    );
+    assert_eq!(
+        fs::metadata(&dest).unwrap().modified().unwrap(),
+        mtime,
+        "An unchanged binary should not be rewritten"
+    );
+    let backups = fs::read_dir(fixture.cwd.join("out"))
+        .unwrap()
+        .filter(|e| {
+            e.as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .ends_with(".dotrbak")
+        })
+        .count();
+    assert_eq!(backups, 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_deploy_unreadable_src_is_an_error() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fs::create_dir_all(fixture.cwd.join("dotfiles/d_app")).unwrap();
+    std::os::unix::fs::symlink(
+        fixture.cwd.join("missing"),
+        fixture.cwd.join("dotfiles/d_app/broken"),
+    )
+    .unwrap();
+    add_file_package(&fixture, "d_app", fixture.cwd.join("out").to_str().unwrap());
+
+    let err = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))))
+        .expect_err("Deploy should fail on an unreadable src");
+    assert!(
+        err.to_string().contains("Failed to read"),
+        "Unexpected error: {}",
+        err
+    );
+    assert!(!fixture.cwd.join("out/broken").exists());
+}
+
 #[test]
 fn test_deploy_with_profile() {
     let fixture = TestFixture::new();
@@ -199,26 +345,21 @@ fn test_deploy_with_profile() {
 
     let mut package = dotr::package::Package {
         name: "f_app".to_string(),
-        src: "dotfiles/f_app".to_string(),
-        dest: fixture
-            .cwd
-            .join("default_dest")
-            .to_str()
-            .unwrap()
-            .to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_app".to_string()),
+        dest: Some(
+            fixture
+                .cwd
+                .join("default_dest")
+                .to_str()
+                .unwrap()
+                .to_string(),
+        ),
+        ..dotr::package::Package::default()
     };
 
     package.targets.insert(
         "work".to_string(),
-        fixture.cwd.join("work_dest").to_str().unwrap().to_string(),
+        vec![fixture.cwd.join("work_dest").to_str().unwrap().to_string()],
     );
 
     config.packages.insert("f_app".to_string(), package);
@@ -228,6 +369,7 @@ fn test_deploy_with_profile() {
         variables: toml::Table::new(),
         dependencies: vec!["f_app".to_string()],
         prompts: HashMap::new(),
+        extends: None,
     };
     config.profiles.insert("work".to_string(), profile);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -235,6 +377,7 @@ fn test_deploy_with_profile() {
     let _ = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: None,
         profile: Some("work".to_string()),
+        ..Default::default()
     }))));
 
     fixture.assert_file_exists(
@@ -256,30 +399,16 @@ fn test_deploy_specific_packages() {
 
     let pkg1 = dotr::package::Package {
         name: "f_pkg1".to_string(),
-        src: "dotfiles/f_pkg1".to_string(),
-        dest: fixture.cwd.join("dest1").to_str().unwrap().to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_pkg1".to_string()),
+        dest: Some(fixture.cwd.join("dest1").to_str().unwrap().to_string()),
+        ..dotr::package::Package::default()
     };
 
     let pkg2 = dotr::package::Package {
         name: "f_pkg2".to_string(),
-        src: "dotfiles/f_pkg2".to_string(),
-        dest: fixture.cwd.join("dest2").to_str().unwrap().to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_pkg2".to_string()),
+        dest: Some(fixture.cwd.join("dest2").to_str().unwrap().to_string()),
+        ..dotr::package::Package::default()
     };
 
     config.packages.insert("f_pkg1".to_string(), pkg1);
@@ -290,6 +419,7 @@ fn test_deploy_specific_packages() {
     let _ = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: Some(vec!["f_pkg1".to_string()]),
         profile: None,
+        ..Default::default()
     }))));
 
     fixture.assert_file_exists("dest1/file1.txt", "pkg1 should be deployed");
@@ -306,21 +436,16 @@ fn test_update_backs_up_files() {
     let mut config = fixture.get_config();
     let pkg = dotr::package::Package {
         name: "f_update".to_string(),
-        src: "dotfiles/f_update".to_string(),
-        dest: fixture
-            .cwd
-            .join("update_dest")
-            .to_str()
-            .unwrap()
-            .to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_update".to_string()),
+        dest: Some(
+            fixture
+                .cwd
+                .join("update_dest")
+                .to_str()
+                .unwrap()
+                .to_string(),
+        ),
+        ..dotr::package::Package::default()
     };
     config.packages.insert("f_update".to_string(), pkg);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -328,9 +453,10 @@ fn test_update_backs_up_files() {
     // Create file at dest
     fixture.write_file("update_dest", "updated content");
 
-    let _ = run_cli(fixture.get_cli(Some(Command::Update(DeployUpdateArgs {
+    let _ = run_cli(fixture.get_cli(Some(Command::Update(UpdateArgs {
         packages: None,
         profile: None,
+        ..Default::default()
     }))));
 
     fixture.assert_file_exists("dotfiles/f_update", "File should be backed up");
@@ -353,8 +479,11 @@ fn test_print_vars_shows_variables() {
     config.save(&fixture.cwd).expect("Failed to save config");
 
     // This will print to stdout - we're just testing it doesn't panic
-    run_cli(fixture.get_cli(Some(Command::PrintVars(PrintVarsArgs { profile: None }))))
-        .expect("Print vars should succeed");
+    run_cli(fixture.get_cli(Some(Command::PrintVars(PrintVarsArgs {
+        profile: None,
+        ..Default::default()
+    }))))
+    .expect("Print vars should succeed");
 }
 
 #[test]
@@ -376,6 +505,7 @@ fn test_print_vars_with_profile() {
     // This will print to stdout - we're just testing it doesn't panic
     let _ = run_cli(fixture.get_cli(Some(Command::PrintVars(PrintVarsArgs {
         profile: Some("dev".to_string()),
+        ..Default::default()
     }))));
 }
 
@@ -394,6 +524,7 @@ fn test_banner_display() {
     let _ = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: None,
         profile: None,
+        ..Default::default()
     }))));
 
     // Just testing it doesn't panic
@@ -414,6 +545,7 @@ fn test_banner_disabled() {
     let _ = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: None,
         profile: None,
+        ..Default::default()
     }))));
 
     // Just testing it doesn't panic
@@ -427,8 +559,9 @@ fn test_working_dir_relative_path() {
     fs::create_dir_all(fixture.cwd.join("subdir")).expect("Failed to create subdir");
 
     let cli = Cli {
-        command: Some(Command::Init(InitArgs {})),
+        command: Some(Command::Init(InitArgs::default())),
         working_dir: Some(fixture.cwd.join("subdir").to_str().unwrap().to_string()),
+        ..Default::default()
     };
 
     run_cli(cli).expect("Init in subdir should succeed");
@@ -447,16 +580,10 @@ fn test_skip_flag_prevents_deployment() {
     let mut config = fixture.get_config();
     let pkg = dotr::package::Package {
         name: "f_skip".to_string(),
-        src: "dotfiles/f_skip".to_string(),
-        dest: fixture.cwd.join("skip_dest").to_str().unwrap().to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
+        src: Some("dotfiles/f_skip".to_string()),
+        dest: Some(fixture.cwd.join("skip_dest").to_str().unwrap().to_string()),
         skip: true,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
     config.packages.insert("f_skip".to_string(), pkg);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -465,6 +592,7 @@ fn test_skip_flag_prevents_deployment() {
     let _ = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: None,
         profile: None,
+        ..Default::default()
     }))));
 
     fixture.assert_file_not_exists("skip_dest/skip.txt", "Skip package should not be deployed");
@@ -483,30 +611,18 @@ fn test_profile_dependencies_deployment() {
 
     let pkg1 = dotr::package::Package {
         name: "f_dep1".to_string(),
-        src: "dotfiles/f_dep1".to_string(),
-        dest: fixture.cwd.join("dep1_dest").to_str().unwrap().to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
+        src: Some("dotfiles/f_dep1".to_string()),
+        dest: Some(fixture.cwd.join("dep1_dest").to_str().unwrap().to_string()),
         skip: true,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
 
     let pkg2 = dotr::package::Package {
         name: "f_dep2".to_string(),
-        src: "dotfiles/f_dep2".to_string(),
-        dest: fixture.cwd.join("dep2_dest").to_str().unwrap().to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
+        src: Some("dotfiles/f_dep2".to_string()),
+        dest: Some(fixture.cwd.join("dep2_dest").to_str().unwrap().to_string()),
         skip: true,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
 
     config.packages.insert("f_dep1".to_string(), pkg1);
@@ -518,6 +634,7 @@ fn test_profile_dependencies_deployment() {
         variables: toml::Table::new(),
         dependencies: vec!["f_dep1".to_string()],
         prompts: HashMap::new(),
+        extends: None,
     };
     config.profiles.insert("minimal".to_string(), profile);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -526,6 +643,7 @@ fn test_profile_dependencies_deployment() {
     let _ = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: None,
         profile: Some("minimal".to_string()),
+        ..Default::default()
     }))));
 
     fixture.assert_file_exists(
@@ -545,6 +663,7 @@ fn test_no_command_shows_help_message() {
     let cli = Cli {
         command: None,
         working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+        ..Default::default()
     };
 
     let result = run_cli(cli);
@@ -561,8 +680,10 @@ fn test_nonexistent_working_directory_fails() {
         command: Some(Command::Deploy(DeployUpdateArgs {
             packages: None,
             profile: None,
+            ..Default::default()
         })),
         working_dir: Some(nonexistent.to_str().unwrap().to_string()),
+        ..Default::default()
     };
 
     let result = run_cli(cli);
@@ -580,6 +701,7 @@ fn test_deploy_without_config_fails() {
     let result = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: None,
         profile: None,
+        ..Default::default()
     }))));
 
     assert!(result.is_err(), "Deploy without config should fail");
@@ -597,18 +719,17 @@ fn test_import_nonexistent_file_fails() {
     let fixture = TestFixture::new();
     fixture.init();
 
-    let result = run_cli(
-        fixture.get_cli(Some(Command::Import(ImportArgs {
-            name: None,
-            path: fixture
+    let result = run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
+        name: None,
+        paths: vec![fixture
                 .cwd
                 .join("does_not_exist.conf")
                 .to_str()
                 .unwrap()
-                .to_string(),
-            profile: None,
-        }))),
-    );
+                .to_string()],
+        profile: None,
+        ..Default::default()
+    }))));
 
     assert!(result.is_err(), "Import nonexistent file should fail");
     assert!(
@@ -617,6 +738,309 @@ fn test_import_nonexistent_file_fails() {
     );
 }
 
+#[test]
+fn test_import_collision_fails_without_overwrite() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("test.conf", "first");
+
+    let path = fixture.cwd.join("test.conf").to_str().unwrap().to_string();
+    run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
+        paths: vec![path.clone()],
+        ..Default::default()
+    }))))
+    .expect("First import should succeed");
+
+    fixture.write_file("test.conf", "second");
+    let result = run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
+        paths: vec![path],
+        ..Default::default()
+    }))));
+
+    let err = result.expect_err("Second import should fail");
+    assert!(err.to_string().contains("already exists"));
+    assert!(err.to_string().contains("--overwrite"));
+    assert_eq!(
+        fixture.read_file("dotfiles/f_test_conf"),
+        "first",
+        "Existing package contents should be untouched"
+    );
+}
+
+#[test]
+fn test_import_dashed_directory_keeps_full_name() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("src/.config/wezterm/wezterm.lua", "a");
+    fixture.write_file("src/.config/wezterm-config/extra.lua", "b");
+
+    for dir in ["wezterm", "wezterm-config"] {
+        let path = fixture.cwd.join("src/.config").join(dir);
+        run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
+            paths: vec![path.to_str().unwrap().to_string()],
+            ..Default::default()
+        }))))
+        .expect("Import should succeed");
+    }
+
+    let config = Config::from_path(&fixture.cwd).unwrap();
+    assert!(config.packages.contains_key("d_wezterm"));
+    assert!(config.packages.contains_key("d_wezterm_config"));
+}
+
+#[test]
+fn test_import_versioned_paths_get_numeric_suffix() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("foo-2.1", "two");
+    fixture.write_file("foo-3.0", "three");
+
+    let paths: Vec<String> = ["foo-2.1", "foo-3.0"]
+        .iter()
+        .map(|p| fixture.cwd.join(p).to_str().unwrap().to_string())
+        .collect();
+    run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
+        paths: paths.clone(),
+        ..Default::default()
+    }))))
+    .expect("Import should succeed");
+
+    let config = Config::from_path(&fixture.cwd).unwrap();
+    assert_eq!(
+        config.packages["f_foo"].dest.as_deref(),
+        Some(paths[0].as_str())
+    );
+    assert_eq!(
+        config.packages["f_foo_2"].dest.as_deref(),
+        Some(paths[1].as_str())
+    );
+    assert_eq!(fixture.read_file("dotfiles/f_foo_2"), "three");
+
+    // Importing the same path again still needs --overwrite
+    let err = run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
+        paths: vec![paths[1].clone()],
+        ..Default::default()
+    }))))
+    .expect_err("Re-import should fail");
+    assert!(err.to_string().contains("'f_foo_2' already exists"));
+}
+
+#[test]
+fn test_import_explicit_name_collision_fails() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("a.conf", "a");
+    fixture.write_file("b.conf", "b");
+
+    for (file, expect_ok) in [("a.conf", true), ("b.conf", false)] {
+        let result = run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
+            paths: vec![fixture.cwd.join(file).to_str().unwrap().to_string()],
+            name: Some("shared".to_string()),
+            ..Default::default()
+        }))));
+        assert_eq!(result.is_ok(), expect_ok);
+    }
+}
+
+#[test]
+fn test_import_collision_with_overwrite_sets_aside_old_contents() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("test.conf", "first");
+
+    let path = fixture.cwd.join("test.conf").to_str().unwrap().to_string();
+    run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
+        paths: vec![path.clone()],
+        ..Default::default()
+    }))))
+    .expect("First import should succeed");
+
+    fixture.write_file("test.conf", "second");
+    run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
+        paths: vec![path],
+        overwrite: true,
+        ..Default::default()
+    }))))
+    .expect("Import with overwrite should succeed");
+
+    assert_eq!(fixture.read_file("dotfiles/f_test_conf"), "second");
+    let aside: Vec<String> = fs::read_dir(fixture.cwd.join("dotfiles"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|n| n.starts_with("f_test_conf.imported-"))
+        .collect();
+    assert_eq!(aside.len(), 1, "Old contents should be set aside");
+    assert_eq!(
+        fixture.read_file(&format!("dotfiles/{}", aside[0])),
+        "first"
+    );
+}
+
+#[test]
+fn test_import_with_invalid_name_fails() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("test.conf", "content");
+
+    let result = run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
+        paths: vec![fixture.cwd.join("test.conf").to_str().unwrap().to_string()],
+        name: Some("my config/2".to_string()),
+        ..Default::default()
+    }))));
+
+    let err = result.expect_err("Import with invalid name should fail");
+    assert!(err.to_string().contains("Invalid package name"));
+    assert!(fixture.get_config().packages.is_empty());
+}
+
+#[test]
+fn test_import_multiple_paths_continues_after_failure() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("first.conf", "first");
+    fixture.write_file("tool/config.toml", "tool");
+    fixture.write_file("last.conf", "last");
+
+    let path = |p: &str| fixture.cwd.join(p).to_str().unwrap().to_string();
+    let result = run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
+        paths: vec![
+            path("first.conf"),
+            path("missing.conf"),
+            path("tool"),
+            path("last.conf"),
+        ],
+        ..Default::default()
+    }))));
+
+    let err = result.expect_err("A failed path should make the import fail");
+    assert!(err.to_string().contains("missing.conf"));
+    assert!(err.to_string().contains("does not exist"));
+
+    let config = fixture.get_config();
+    assert!(config.packages.contains_key("f_first_conf"));
+    assert!(config.packages.contains_key("d_tool"));
+    assert!(config.packages.contains_key("f_last_conf"));
+    fixture.assert_file_exists("dotfiles/d_tool/config.toml", "Directory imported");
+    fixture.assert_file_exists("dotfiles/f_last_conf", "Later paths still imported");
+}
+
+#[test]
+fn test_import_multiple_paths_fail_fast() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("first.conf", "first");
+    fixture.write_file("last.conf", "last");
+
+    let path = |p: &str| fixture.cwd.join(p).to_str().unwrap().to_string();
+    let result = run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
+        paths: vec![path("first.conf"), path("missing.conf"), path("last.conf")],
+        fail_fast: true,
+        ..Default::default()
+    }))));
+
+    assert!(result.is_err(), "Import should fail");
+    let config = fixture.get_config();
+    assert!(
+        config.packages.contains_key("f_first_conf"),
+        "Paths before the failure are kept"
+    );
+    assert!(
+        !config.packages.contains_key("f_last_conf"),
+        "Paths after the failure are not imported"
+    );
+}
+
+#[test]
+fn test_import_name_with_multiple_paths_fails() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("a.conf", "a");
+    fixture.write_file("b.conf", "b");
+
+    let path = |p: &str| fixture.cwd.join(p).to_str().unwrap().to_string();
+    let result = run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
+        paths: vec![path("a.conf"), path("b.conf")],
+        name: Some("both".to_string()),
+        ..Default::default()
+    }))));
+
+    assert!(result.is_err(), "--name with several paths is ambiguous");
+    assert!(fixture.get_config().packages.is_empty());
+}
+
+#[test]
+fn test_print_vars_formats() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file(".uservariables.toml", "[git]\nemail = \"me@example.com\"\n");
+
+    for format in [VarsFormat::Pretty, VarsFormat::Toml, VarsFormat::Json] {
+        run_cli(fixture.get_cli(Some(Command::PrintVars(PrintVarsArgs {
+            format,
+            ..Default::default()
+        }))))
+        .expect("Print vars should succeed in every format");
+    }
+
+    run_cli(fixture.get_cli(Some(Command::PrintVars(PrintVarsArgs {
+        only: Some("git.email".to_string()),
+        ..Default::default()
+    }))))
+    .expect("Existing key should be printed");
+}
+
+#[test]
+fn test_print_vars_only_missing_key_fails() {
+    let fixture = TestFixture::new();
+    fixture.init();
+
+    let result = run_cli(fixture.get_cli(Some(Command::PrintVars(PrintVarsArgs {
+        only: Some("git.missing".to_string()),
+        ..Default::default()
+    }))));
+
+    let err = result.expect_err("Missing key should fail");
+    assert!(err.to_string().contains("git.missing"));
+}
+
+#[test]
+fn test_config_secrets_round_trip() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file(
+        "config.toml",
+        r#"
+banner = false
+secrets = ["github.token"]
+
+[prompts]
+EMAIL = "Your email?"
+API_TOKEN = { message = "Your API token?", secret = true }
+"#,
+    );
+
+    let config = fixture.get_config();
+    assert_eq!(
+        config.secrets,
+        vec!["github.token".to_string(), "API_TOKEN".to_string()]
+    );
+    assert_eq!(config.prompts["API_TOKEN"].message, "Your API token?");
+
+    config.save(&fixture.cwd).expect("Failed to save config");
+    let reloaded = fixture.get_config();
+    assert_eq!(reloaded.secrets, config.secrets);
+
+    fixture.write_file(".uservariables.toml", "[github]\ntoken = \"t0k3n\"\n");
+    for show_secrets in [false, true] {
+        run_cli(fixture.get_cli(Some(Command::PrintVars(PrintVarsArgs {
+            only: Some("github.token".to_string()),
+            show_secrets,
+            ..Default::default()
+        }))))
+        .expect("Print vars should succeed");
+    }
+}
+
 #[test]
 fn test_deploy_with_invalid_profile_fails() {
     let fixture = TestFixture::new();
@@ -625,6 +1049,7 @@ fn test_deploy_with_invalid_profile_fails() {
     let result = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: None,
         profile: Some("nonexistent_profile".to_string()),
+        ..Default::default()
     }))));
 
     assert!(result.is_err(), "Deploy with invalid profile should fail");
@@ -639,9 +1064,10 @@ fn test_update_with_invalid_profile_fails() {
     let fixture = TestFixture::new();
     fixture.init();
 
-    let result = run_cli(fixture.get_cli(Some(Command::Update(DeployUpdateArgs {
+    let result = run_cli(fixture.get_cli(Some(Command::Update(UpdateArgs {
         packages: None,
         profile: Some("invalid_profile".to_string()),
+        ..Default::default()
     }))));
 
     assert!(result.is_err(), "Update with invalid profile should fail");
@@ -658,6 +1084,7 @@ fn test_print_vars_with_invalid_profile_fails() {
 
     let result = run_cli(fixture.get_cli(Some(Command::PrintVars(PrintVarsArgs {
         profile: Some("missing_profile".to_string()),
+        ..Default::default()
     }))));
 
     assert!(
@@ -678,6 +1105,7 @@ fn test_deploy_nonexistent_package_fails() {
     let result = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: Some(vec!["nonexistent_package".to_string()]),
         profile: None,
+        ..Default::default()
     }))));
 
     // Deploy should fail with error for nonexistent package
@@ -696,9 +1124,10 @@ fn test_update_nonexistent_package_fails() {
     let fixture = TestFixture::new();
     fixture.init();
 
-    let result = run_cli(fixture.get_cli(Some(Command::Update(DeployUpdateArgs {
+    let result = run_cli(fixture.get_cli(Some(Command::Update(UpdateArgs {
         packages: Some(vec!["nonexistent_package".to_string()]),
         profile: None,
+        ..Default::default()
     }))));
 
     // Update should fail with error for nonexistent package
@@ -723,6 +1152,7 @@ fn test_invalid_toml_config_fails() {
     let result = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: None,
         profile: None,
+        ..Default::default()
     }))));
 
     assert!(result.is_err(), "Invalid TOML config should fail");
@@ -737,8 +1167,10 @@ fn test_invalid_uservariables_toml_fails() {
     fixture.write_file(".uservariables.toml", "bad toml [[[");
 
     // Use PrintVars which will definitely try to load context
-    let result =
-        run_cli(fixture.get_cli(Some(Command::PrintVars(PrintVarsArgs { profile: None }))));
+    let result = run_cli(fixture.get_cli(Some(Command::PrintVars(PrintVarsArgs {
+        profile: None,
+        ..Default::default()
+    }))));
 
     assert!(result.is_err(), "Invalid uservariables TOML should fail");
     let error_msg = result.unwrap_err().to_string();
@@ -758,16 +1190,10 @@ fn test_package_with_missing_dependency_fails() {
     let mut config = fixture.get_config();
     let pkg = dotr::package::Package {
         name: "test_pkg".to_string(),
-        src: "dotfiles/test_pkg".to_string(),
-        dest: fixture.cwd.join("dest").to_str().unwrap().to_string(),
+        src: Some("dotfiles/test_pkg".to_string()),
+        dest: Some(fixture.cwd.join("dest").to_str().unwrap().to_string()),
         dependencies: Some(vec!["nonexistent_dep".to_string()]),
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
     config.packages.insert("test_pkg".to_string(), pkg);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -775,6 +1201,7 @@ fn test_package_with_missing_dependency_fails() {
     let result = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: Some(vec!["test_pkg".to_string()]),
         profile: None,
+        ..Default::default()
     }))));
 
     assert!(
@@ -796,16 +1223,9 @@ fn test_deploy_missing_source_fails() {
     let mut config = fixture.get_config();
     let pkg = dotr::package::Package {
         name: "missing_src".to_string(),
-        src: "dotfiles/missing_src".to_string(),
-        dest: fixture.cwd.join("dest").to_str().unwrap().to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/missing_src".to_string()),
+        dest: Some(fixture.cwd.join("dest").to_str().unwrap().to_string()),
+        ..dotr::package::Package::default()
     };
     config.packages.insert("missing_src".to_string(), pkg);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -813,6 +1233,7 @@ fn test_deploy_missing_source_fails() {
     let result = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: None,
         profile: None,
+        ..Default::default()
     }))));
 
     // This might succeed as walkdir might not find any files, depending on implementation
@@ -840,8 +1261,9 @@ fn test_import_normalizes_home_path() {
     // Import the file
     let _ = run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
         name: None,
-        path: test_dir.to_str().unwrap().to_string(),
+        paths: vec![test_dir.to_str().unwrap().to_string()],
         profile: None,
+        ..Default::default()
     }))));
 
     let config = fixture.get_config();
@@ -853,7 +1275,7 @@ fn test_import_normalizes_home_path() {
 
     // Path should not have ~ since it's not in home directory
     assert!(
-        !package.dest.starts_with('~'),
+        !package.dest.as_ref().unwrap().starts_with('~'),
         "Non-home path should not use ~"
     );
 }
@@ -896,8 +1318,9 @@ fn test_import_converts_absolute_home_path_to_tilde() {
     let abs_path = test_file.to_str().unwrap().to_string();
     let _ = run_cli(fixture.get_cli(Some(Command::Import(ImportArgs {
         name: None,
-        path: abs_path.clone(),
+        paths: vec![abs_path.clone()],
         profile: None,
+        ..Default::default()
     }))));
 
     let config = fixture.get_config();
@@ -909,9 +1332,9 @@ fn test_import_converts_absolute_home_path_to_tilde() {
 
     // Since the path is NOT in home directory, it should remain absolute
     assert!(
-        !package.dest.starts_with('~'),
+        !package.dest.as_ref().unwrap().starts_with('~'),
         "Path outside home should not use ~ notation, got: {}",
-        package.dest
+        package.dest.as_ref().unwrap()
     );
 
     // Test 2: Verify utility function correctly normalizes home paths
@@ -941,16 +1364,10 @@ fn test_dotr_profile_env_var_deploy() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_profile_test".to_string(),
-        src: "dotfiles/f_profile_test".to_string(),
-        dest: "src/.profile_test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec![],
-        post_actions: vec![],
-        targets: std::collections::HashMap::new(),
+        src: Some("dotfiles/f_profile_test".to_string()),
+        dest: Some("src/.profile_test".to_string()),
         skip: true,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
 
     let profile = dotr::profile::Profile {
@@ -958,6 +1375,7 @@ fn test_dotr_profile_env_var_deploy() {
         variables: toml::Table::new(),
         dependencies: vec!["f_profile_test".to_string()],
         prompts: HashMap::new(),
+        extends: None,
     };
 
     config
@@ -973,6 +1391,7 @@ fn test_dotr_profile_env_var_deploy() {
     let result = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: None,
         profile: None,
+        ..Default::default()
     }))));
 
     assert!(
@@ -1000,16 +1419,9 @@ fn test_dotr_profile_env_var_update() {
     let dest_path = fixture.cwd.join("dest/.env_update");
     let package = dotr::package::Package {
         name: "f_env_update".to_string(),
-        src: "dotfiles/f_env_update".to_string(),
-        dest: dest_path.to_str().unwrap().to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec![],
-        post_actions: vec![],
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_env_update".to_string()),
+        dest: Some(dest_path.to_str().unwrap().to_string()),
+        ..dotr::package::Package::default()
     };
 
     let profile = dotr::profile::Profile {
@@ -1017,6 +1429,7 @@ fn test_dotr_profile_env_var_update() {
         variables: toml::Table::new(),
         dependencies: vec!["f_env_update".to_string()],
         prompts: HashMap::new(),
+        extends: None,
     };
 
     config.packages.insert("f_env_update".to_string(), package);
@@ -1027,6 +1440,7 @@ fn test_dotr_profile_env_var_update() {
     run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: Some(vec!["f_env_update".to_string()]),
         profile: None,
+        ..Default::default()
     }))))
     .expect("Deploy failed");
 
@@ -1037,9 +1451,10 @@ fn test_dotr_profile_env_var_update() {
     fixture.write_file(".uservariables.toml", "DOTR_PROFILE = \"updateenv\"\n");
 
     // Update without specifying profile - should succeed with profile from env var
-    let result = run_cli(fixture.get_cli(Some(Command::Update(DeployUpdateArgs {
+    let result = run_cli(fixture.get_cli(Some(Command::Update(UpdateArgs {
         packages: Some(vec!["f_env_update".to_string()]),
         profile: None,
+        ..Default::default()
     }))));
 
     assert!(
@@ -1066,6 +1481,7 @@ fn test_dotr_profile_env_var_print_vars() {
         variables: profile_vars,
         dependencies: vec![],
         prompts: HashMap::new(),
+        extends: None,
     };
 
     config.profiles.insert("printenv".to_string(), profile);
@@ -1075,8 +1491,10 @@ fn test_dotr_profile_env_var_print_vars() {
     fixture.write_file(".uservariables.toml", "DOTR_PROFILE = \"printenv\"\n");
 
     // Should work without specifying profile
-    let result =
-        run_cli(fixture.get_cli(Some(Command::PrintVars(PrintVarsArgs { profile: None }))));
+    let result = run_cli(fixture.get_cli(Some(Command::PrintVars(PrintVarsArgs {
+        profile: None,
+        ..Default::default()
+    }))));
 
     assert!(
         result.is_ok(),
@@ -1094,16 +1512,10 @@ fn test_cli_profile_overrides_env_var() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_override".to_string(),
-        src: "dotfiles/f_override".to_string(),
-        dest: "src/.override".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec![],
-        post_actions: vec![],
-        targets: std::collections::HashMap::new(),
+        src: Some("dotfiles/f_override".to_string()),
+        dest: Some("src/.override".to_string()),
         skip: true,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
 
     let profile1 = dotr::profile::Profile {
@@ -1111,6 +1523,7 @@ fn test_cli_profile_overrides_env_var() {
         variables: toml::Table::new(),
         dependencies: vec!["f_override".to_string()],
         prompts: HashMap::new(),
+        extends: None,
     };
 
     let profile2 = dotr::profile::Profile {
@@ -1118,6 +1531,7 @@ fn test_cli_profile_overrides_env_var() {
         variables: toml::Table::new(),
         dependencies: vec!["f_override".to_string()],
         prompts: HashMap::new(),
+        extends: None,
     };
 
     config.packages.insert("f_override".to_string(), package);
@@ -1132,6 +1546,7 @@ fn test_cli_profile_overrides_env_var() {
     let result = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: None,
         profile: Some("cliprofile".to_string()),
+        ..Default::default()
     }))));
 
     assert!(result.is_ok(), "Deploy should use CLI profile over env var");
@@ -1151,16 +1566,9 @@ fn test_invalid_dotr_profile_env_var_ignored() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_invalid_env".to_string(),
-        src: "dotfiles/f_invalid_env".to_string(),
-        dest: "src/.invalid_env".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec![],
-        post_actions: vec![],
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_invalid_env".to_string()),
+        dest: Some("src/.invalid_env".to_string()),
+        ..dotr::package::Package::default()
     };
 
     config.packages.insert("f_invalid_env".to_string(), package);
@@ -1173,6 +1581,7 @@ fn test_invalid_dotr_profile_env_var_ignored() {
     let result = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
         packages: None,
         profile: None,
+        ..Default::default()
     }))));
 
     assert!(
@@ -1180,3 +1589,119 @@ fn test_invalid_dotr_profile_env_var_ignored() {
         "Deploy should fail with invalid DOTR_PROFILE env var"
     );
 }
+
+fn selected_profile(fixture: &TestFixture, cli_profile: Option<&str>) -> ProfileSelection {
+    let config = fixture.get_config();
+    let ctx = dotr::context::Context::new(&fixture.cwd).expect("Failed to create context");
+    config
+        .get_profile_details(&cli_profile.map(|p| p.to_string()), &ctx)
+        .expect("Failed to select profile")
+}
+
+#[test]
+fn test_default_profile_precedence() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    let mut config = fixture.get_config();
+    config.default_profile = Some("home".to_string());
+    config
+        .profiles
+        .insert("home".to_string(), dotr::profile::Profile::new("home"));
+    config
+        .profiles
+        .insert("work".to_string(), dotr::profile::Profile::new("work"));
+    config.save(&fixture.cwd).expect("Failed to save config");
+    assert_eq!(
+        fixture.get_config().default_profile.as_deref(),
+        Some("home"),
+        "default_profile should survive a save"
+    );
+
+    let selection = selected_profile(&fixture, None);
+    assert_eq!(selection.name.as_deref(), Some("home"));
+    assert_eq!(selection.source, Some(ProfileSource::Default));
+    assert!(selection.profile.is_some());
+
+    fixture.write_file(".uservariables.toml", "DOTR_PROFILE = \"work\"\n");
+    let selection = selected_profile(&fixture, None);
+    assert_eq!(selection.name.as_deref(), Some("work"));
+    assert_eq!(selection.source, Some(ProfileSource::UserVariables));
+
+    let selection = selected_profile(&fixture, Some("home"));
+    assert_eq!(selection.name.as_deref(), Some("home"));
+    assert_eq!(selection.source, Some(ProfileSource::Cli));
+
+    let selection = selected_profile(&fixture, Some(""));
+    assert!(
+        selection.name.is_none(),
+        "Empty --profile disables profiles"
+    );
+    assert!(selection.source.is_none());
+}
+
+#[test]
+fn test_invalid_default_profile_only_fails_commands_that_need_it() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    let mut config = fixture.get_config();
+    config.default_profile = Some("missing".to_string());
+    config.save(&fixture.cwd).expect("Failed to save config");
+
+    run_cli(fixture.get_cli(Some(Command::PrintVars(PrintVarsArgs::default()))))
+        .expect("print-vars should only warn about a missing default_profile");
+
+    let err = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))))
+        .expect_err("Deploy should fail with a missing default_profile");
+    assert!(err.to_string().contains("default_profile"));
+
+    run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
+        profile: Some(String::new()),
+        ..Default::default()
+    }))))
+    .expect("--profile \"\" should skip the default profile");
+}
+
+#[test]
+fn test_log_flags_parse() {
+    use clap::Parser;
+    use dotr::utils::LogFormat;
+
+    let cli = Cli::try_parse_from(["dotr", "-vv", "deploy"]).expect("-vv should parse");
+    assert_eq!(cli.verbose, 2);
+    assert!(!cli.quiet);
+
+    let cli = Cli::try_parse_from(["dotr", "deploy", "-q", "--log-format", "json"])
+        .expect("Global flags should parse after the subcommand");
+    assert!(cli.quiet);
+    assert_eq!(cli.log_format, LogFormat::Json);
+
+    assert!(
+        Cli::try_parse_from(["dotr", "-v", "-q", "deploy"]).is_err(),
+        "-v and -q are mutually exclusive"
+    );
+}
+
+#[test]
+fn test_quiet_deploy_succeeds() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fs::write(fixture.cwd.join("dotfiles/f_quiet"), "quiet\n").expect("Failed to write");
+    fs::write(
+        fixture.cwd.join("config.toml"),
+        r#"
+[packages.f_quiet]
+src = "dotfiles/f_quiet"
+dest = "quiet.conf"
+"#,
+    )
+    .expect("Failed to write config");
+
+    run_cli(Cli {
+        command: Some(Command::Deploy(DeployUpdateArgs::default())),
+        working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+        quiet: true,
+        ..Default::default()
+    })
+    .expect("Quiet deploy failed");
+    fixture.assert_file_exists("quiet.conf", "Quiet deploy should still deploy");
+}
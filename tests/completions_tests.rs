@@ -0,0 +1,123 @@
+use std::{fs, path::PathBuf};
+
+use clap_complete::Shell;
+use dotr::cli::{
+    Cli, Command, CompleteArgs, CompleteKind, CompletionsArgs, complete_names, generate_script,
+    run_cli,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_completions_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn write_config(&self, content: &str) {
+        fs::write(self.cwd.join("config.toml"), content).expect("Failed to write config");
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_complete_names_lists_packages_and_profiles() {
+    let fixture = TestFixture::new();
+    fixture.write_config(
+        r#"
+[packages.f_zshrc]
+src = "dotfiles/f_zshrc"
+dest = "~/.zshrc"
+
+[packages.d_nvim]
+src = "dotfiles/d_nvim"
+dest = "~/.config/nvim"
+
+[profiles.work]
+dependencies = ["d_nvim"]
+
+[profiles.home]
+dependencies = []
+"#,
+    );
+
+    assert_eq!(
+        complete_names(&fixture.cwd, CompleteKind::Packages),
+        vec!["d_nvim", "f_zshrc"]
+    );
+    assert_eq!(
+        complete_names(&fixture.cwd, CompleteKind::Profiles),
+        vec!["home", "work"]
+    );
+}
+
+#[test]
+fn test_complete_names_is_empty_without_config() {
+    let fixture = TestFixture::new();
+    assert!(complete_names(&fixture.cwd, CompleteKind::Packages).is_empty());
+
+    fixture.write_config("this is [not toml");
+    assert!(complete_names(&fixture.cwd, CompleteKind::Profiles).is_empty());
+
+    // The hidden subcommand succeeds even when the working dir is missing
+    let cli = Cli {
+        command: Some(Command::Complete(CompleteArgs {
+            kind: CompleteKind::Packages,
+        })),
+        working_dir: Some(fixture.cwd.join("missing").to_str().unwrap().to_string()),
+        ..Default::default()
+    };
+    run_cli(cli).expect("Completer must never fail");
+}
+
+#[test]
+fn test_scripts_complete_names_dynamically() {
+    let bash = generate_script(Shell::Bash).expect("bash script");
+    assert!(bash.contains(r#"compgen -W "$(dotr __complete packages 2>/dev/null)""#));
+    assert!(bash.contains(r#"compgen -W "$(dotr __complete profiles 2>/dev/null)""#));
+
+    let zsh = generate_script(Shell::Zsh).expect("zsh script");
+    assert!(zsh.starts_with("#compdef dotr\n"));
+    assert!(zsh.contains(":PACKAGES:_dotr_names packages"));
+    assert!(zsh.contains(":PROFILE:_dotr_names profiles"));
+
+    let fish = generate_script(Shell::Fish).expect("fish script");
+    assert!(fish.contains(r#"-s p -l packages -r -f -a "(dotr __complete packages 2>/dev/null)""#));
+
+    let powershell = generate_script(Shell::PowerShell).expect("powershell script");
+    assert!(powershell.contains("'dotr;deploy;-P' { 'profiles' }"));
+    assert!(powershell.contains("'dotr;print-vars;-p' { 'profiles' }"));
+}
+
+#[test]
+fn test_scripts_hide_the_completer() {
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+        let script = generate_script(shell).expect("script");
+        assert!(
+            !script.contains("'__complete'") && !script.contains("\"__complete\""),
+            "{} script should not offer the hidden subcommand",
+            shell
+        );
+        assert!(script.contains("completions"));
+    }
+}
+
+#[test]
+fn test_completions_subcommand_runs_without_config() {
+    let fixture = TestFixture::new();
+    let cli = Cli {
+        command: Some(Command::Completions(CompletionsArgs { shell: Shell::Bash })),
+        working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+        ..Default::default()
+    };
+    run_cli(cli).expect("Completions don't need a repository");
+}
@@ -0,0 +1,135 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, run_cli},
+    config::Config,
+    utils::unknown_keys,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(config: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_config_keys_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(temp_dir.join("dotfiles")).expect("Failed to create temp dir");
+        fs::write(temp_dir.join("config.toml"), config).expect("Failed to write config");
+        Self { cwd: temp_dir }
+    }
+
+    fn deploy(&self, strict: bool) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(Command::Deploy(DeployUpdateArgs::default())),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            strict,
+            ..Default::default()
+        })
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.cwd);
+    }
+}
+
+const TYPO_CONFIG: &str = r#"
+banner = false
+bannr = true
+
+[packages.f_app]
+pre_actions = ["true"]
+post_action = ["echo hi"]
+x-note = "kept for me"
+
+[profiles.work]
+dependency = ["f_app"]
+
+[git]
+auto_comit = true
+"#;
+
+#[test]
+fn test_unknown_keys_suggests_closest() {
+    let table: toml::Table = r#"
+post_action = []
+src = "x"
+colour = "red"
+x-mine = 1
+"#
+    .parse()
+    .unwrap();
+    let problems = unknown_keys(&table, &["post_actions", "pre_actions", "src"]);
+    assert_eq!(
+        problems,
+        vec![
+            "unknown key 'colour'".to_string(),
+            "unknown key 'post_action', did you mean 'post_actions'?".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn test_unknown_keys_warn_by_default() {
+    let fixture = TestFixture::new(TYPO_CONFIG);
+    let config = Config::from_path(&fixture.cwd).expect("Unknown keys should only warn");
+    assert!(config.packages["f_app"].post_actions.is_empty());
+    fixture.deploy(false).expect("Deploy should succeed");
+}
+
+#[test]
+fn test_strict_flag_rejects_unknown_keys() {
+    let fixture = TestFixture::new(TYPO_CONFIG);
+    let err = Config::load(&fixture.cwd, true).expect_err("Strict load should fail");
+    let message = err.to_string();
+    for expected in [
+        "unknown key 'bannr', did you mean 'banner'?",
+        "package 'f_app': unknown key 'post_action', did you mean 'post_actions'?",
+        "profile 'work': unknown key 'dependency', did you mean 'dependencies'?",
+        "[git]: unknown key 'auto_comit', did you mean 'auto_commit'?",
+    ] {
+        assert!(
+            message.contains(expected),
+            "Missing '{}' in: {}",
+            expected,
+            message
+        );
+    }
+    assert!(
+        !message.contains("x-note"),
+        "x- keys are allowed: {}",
+        message
+    );
+
+    let err = fixture.deploy(true).expect_err("Strict deploy should fail");
+    assert!(err.to_string().contains("config.toml has unknown keys"));
+}
+
+#[test]
+fn test_strict_config_setting() {
+    let fixture = TestFixture::new(&format!("strict = true\n{}", TYPO_CONFIG));
+    assert!(Config::from_path(&fixture.cwd).is_err());
+    fixture
+        .deploy(false)
+        .expect_err("strict = true should fail");
+
+    // Once fixed, strict configs load and keep the setting
+    let fixture = TestFixture::new(
+        r#"
+strict = true
+banner = false
+
+[packages.f_app]
+post_actions = ["echo hi"]
+x-note = "kept for me"
+"#,
+    );
+    let config = Config::from_path(&fixture.cwd).expect("Valid strict config should load");
+    assert!(config.strict);
+    assert_eq!(
+        config.to_table().get("strict"),
+        Some(&toml::Value::Boolean(true))
+    );
+}
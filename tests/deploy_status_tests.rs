@@ -0,0 +1,148 @@
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, StatusArgs, run_cli},
+    config::Config,
+    context::Context,
+    state::{DeployState, state_path, time_ago},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_status_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(fixture.cwd.join("dotfiles/f_a"), "a\n").unwrap();
+        fs::create_dir_all(fixture.cwd.join("dotfiles/d_b")).unwrap();
+        fs::write(fixture.cwd.join("dotfiles/d_b/one"), "1\n").unwrap();
+        fs::write(fixture.cwd.join("dotfiles/d_b/two"), "2\n").unwrap();
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            r#"
+banner = false
+
+[packages.f_a]
+src = "dotfiles/f_a"
+dest = "home/a"
+
+[packages.d_b]
+src = "dotfiles/d_b"
+dest = "home/b"
+
+[profiles.work]
+dependencies = ["f_a", "d_b"]
+"#,
+        )
+        .unwrap();
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self, packages: Option<&[&str]>) {
+        self.run(Command::Deploy(DeployUpdateArgs {
+            packages: packages.map(|names| names.iter().map(|n| n.to_string()).collect()),
+            profile: Some("work".to_string()),
+            ..Default::default()
+        }))
+        .expect("Deploy failed");
+    }
+
+    fn state(&self) -> DeployState {
+        DeployState::load(&self.cwd).expect("Failed to load state")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_deploy_records_when_and_how() {
+    let fixture = TestFixture::new();
+    assert!(!state_path(&fixture.cwd).exists());
+    fixture.deploy(None);
+
+    let state = fixture.state();
+    let last_deploy = state.last_deploy("d_b").expect("d_b should be recorded");
+    assert_eq!(last_deploy.profile.as_deref(), Some("work"));
+    assert_eq!(last_deploy.files_written, 2);
+    assert!(
+        last_deploy
+            .describe()
+            .starts_with("last deployed just now with profile work")
+    );
+
+    // Nothing to rewrite the second time, but the deploy still counts
+    fixture.deploy(None);
+    assert_eq!(fixture.state().last_deploy("d_b").unwrap().files_written, 0);
+    fixture
+        .run(Command::Status(StatusArgs::default()))
+        .expect("Status failed");
+}
+
+#[test]
+fn test_src_changes_after_the_deploy_show() {
+    let fixture = TestFixture::new();
+    fixture.deploy(None);
+    let conf = Config::from_path(&fixture.cwd).unwrap();
+    let ctx = Context::from_config(&fixture.cwd, &conf).unwrap();
+    let package = &conf.packages["d_b"];
+    let last_deploy = fixture.state().last_deploy("d_b").unwrap().clone();
+    assert!(!last_deploy.precedes(package.latest_src_change(&ctx).unwrap()));
+
+    let file = fs::File::options()
+        .write(true)
+        .open(fixture.cwd.join("dotfiles/d_b/two"))
+        .unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(5))
+        .unwrap();
+    assert!(last_deploy.precedes(package.latest_src_change(&ctx).unwrap()));
+
+    // A deploy of the other package leaves d_b's record alone
+    fixture.deploy(Some(&["f_a"]));
+    assert_eq!(fixture.state().last_deploy("d_b"), Some(&last_deploy));
+}
+
+#[test]
+fn test_corrupt_state_counts_as_empty() {
+    let fixture = TestFixture::new();
+    fs::create_dir_all(state_path(&fixture.cwd).parent().unwrap()).unwrap();
+    fs::write(state_path(&fixture.cwd), "packages = [not toml").unwrap();
+
+    assert!(fixture.state().package_names().is_empty());
+    fixture.deploy(None);
+    assert!(fixture.state().last_deploy("f_a").is_some());
+}
+
+#[test]
+fn test_time_ago() {
+    let now = SystemTime::now();
+    assert_eq!(time_ago(now), "just now");
+    assert_eq!(time_ago(now - Duration::from_secs(60)), "1 minute ago");
+    assert_eq!(time_ago(now - Duration::from_secs(7_200)), "2 hours ago");
+    assert_eq!(
+        time_ago(now - Duration::from_secs(3 * 86_400)),
+        "3 days ago"
+    );
+}
@@ -0,0 +1,127 @@
+use std::{fs, path::PathBuf};
+
+use dotr::cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli};
+
+struct TestFixture {
+    cwd: PathBuf,
+    outside: PathBuf, // Outside both the working and the home directory
+    in_home: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let id = uuid::Uuid::new_v4();
+        let temp_dir = std::env::temp_dir().join(format!("dotr_dest_guard_test_{}", id));
+        let cwd = temp_dir.join("repo");
+        fs::create_dir_all(&cwd).expect("Failed to create temp dir");
+        let home = std::env::home_dir().expect("Failed to get home directory");
+        let fixture = Self {
+            cwd,
+            outside: temp_dir.join("outside"),
+            in_home: home.join(format!(".dotr_dest_guard_test_{}", id)),
+        };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(fixture.cwd.join("dotfiles/f_rc"), "rc\n").expect("Failed to write file");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn write_config(&self, top_level: &str, dest: &str, package: &str) {
+        let config = format!(
+            "banner = false\n{}\n\n[packages.f_rc]\nsrc = \"dotfiles/f_rc\"\ndest = \"{}\"\n{}\n",
+            top_level, dest, package
+        );
+        fs::write(self.cwd.join("config.toml"), config).expect("Failed to write config");
+    }
+
+    fn deploy(&self, allow_system_paths: bool) -> Result<(), anyhow::Error> {
+        self.run(Command::Deploy(DeployUpdateArgs {
+            allow_system_paths,
+            ..Default::default()
+        }))
+    }
+
+    fn outside_dest(&self) -> String {
+        self.outside.join("rc").to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(self.cwd.parent().unwrap()).ok();
+        fs::remove_file(&self.in_home).ok();
+    }
+}
+
+#[test]
+fn test_dest_outside_home_is_refused() {
+    let fixture = TestFixture::new();
+    fixture.write_config("", &fixture.outside_dest(), "");
+
+    let err = fixture
+        .deploy(false)
+        .expect_err("A dest outside home should need confirmation");
+    let message = err.to_string();
+    assert!(message.contains("f_rc"), "{}", message);
+    assert!(message.contains(&fixture.outside_dest()), "{}", message);
+    assert!(!fixture.outside.join("rc").exists());
+}
+
+#[test]
+fn test_flag_allows_dest_outside_home() {
+    let fixture = TestFixture::new();
+    fixture.write_config("", &fixture.outside_dest(), "");
+
+    fixture.deploy(true).expect("Deploy with the flag failed");
+    assert!(fixture.outside.join("rc").exists());
+}
+
+#[test]
+fn test_package_allows_dest_outside_home() {
+    let fixture = TestFixture::new();
+    fixture.write_config("", &fixture.outside_dest(), "allow_outside_home = true");
+
+    fixture.deploy(false).expect("Deploy failed");
+    assert!(fixture.outside.join("rc").exists());
+}
+
+#[test]
+fn test_dest_in_home_is_always_allowed() {
+    let fixture = TestFixture::new();
+    let name = fixture.in_home.file_name().unwrap().to_str().unwrap();
+    fixture.write_config("", &format!("~/{}", name), "");
+
+    fixture.deploy(false).expect("Deploy failed");
+    assert!(fixture.in_home.exists());
+}
+
+#[test]
+fn test_protected_paths_need_the_flag() {
+    let fixture = TestFixture::new();
+    fixture.write_config(
+        &format!(
+            "protected_paths = [\"{}\"]",
+            fixture.outside.to_str().unwrap()
+        ),
+        &fixture.outside_dest(),
+        "allow_outside_home = true",
+    );
+
+    let err = fixture
+        .deploy(false)
+        .expect_err("A protected dest should need the flag");
+    assert!(err.to_string().contains("protected"), "{}", err);
+    assert!(!fixture.outside.join("rc").exists());
+
+    fixture.deploy(true).expect("Deploy with the flag failed");
+    assert!(fixture.outside.join("rc").exists());
+}
@@ -0,0 +1,143 @@
+use std::{fs, path::PathBuf};
+
+use dotr::cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_dest_kind_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            "banner = false\n\n[packages.f_gitconfig]\nsrc = \"dotfiles/gitconfig\"\ndest = \"home/.gitconfig\"\npost_actions = [\"touch ran\"]\n\n[packages.d_nvim]\nsrc = \"dotfiles/nvim\"\ndest = \"home/nvim\"\n",
+        )
+        .expect("Failed to write config");
+        fixture.write("dotfiles/gitconfig", "[user]\n");
+        fixture.write("dotfiles/nvim/init.lua", "-- init\n");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self, package: &str, replace_mismatched: bool) -> Result<(), anyhow::Error> {
+        self.run(Command::Deploy(DeployUpdateArgs {
+            packages: Some(vec![package.to_string()]),
+            replace_mismatched,
+            ..Default::default()
+        }))
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).unwrap()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_file_package_refuses_a_directory_dest() {
+    let fixture = TestFixture::new();
+    fixture.write("home/.gitconfig/leftover", "keep me\n");
+
+    let err = fixture
+        .deploy("f_gitconfig", false)
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("'f_gitconfig' expects a file at") && err.contains("but found a directory"),
+        "{}",
+        err
+    );
+    assert!(err.contains("--replace-mismatched"), "{}", err);
+    assert_eq!(fixture.read("home/.gitconfig/leftover"), "keep me\n");
+    // Nothing ran either
+    assert!(!fixture.cwd.join("ran").exists());
+}
+
+#[test]
+fn test_directory_package_refuses_a_file_dest() {
+    let fixture = TestFixture::new();
+    fixture.write("home/nvim", "not a directory\n");
+
+    let err = fixture.deploy("d_nvim", false).unwrap_err().to_string();
+    assert!(
+        err.contains("'d_nvim' expects a directory at") && err.contains("but found a file"),
+        "{}",
+        err
+    );
+    assert_eq!(fixture.read("home/nvim"), "not a directory\n");
+}
+
+#[test]
+fn test_replace_mismatched_moves_a_directory_aside() {
+    let fixture = TestFixture::new();
+    fixture.write("home/.gitconfig/leftover", "keep me\n");
+
+    fixture.deploy("f_gitconfig", true).expect("Deploy failed");
+    assert_eq!(fixture.read("home/.gitconfig"), "[user]\n");
+    assert_eq!(
+        fixture.read("home/.gitconfig.dotrbak.d/leftover"),
+        "keep me\n"
+    );
+}
+
+#[test]
+fn test_replace_mismatched_backs_up_a_file() {
+    let fixture = TestFixture::new();
+    fixture.write("home/nvim", "not a directory\n");
+
+    fixture.deploy("d_nvim", true).expect("Deploy failed");
+    assert_eq!(fixture.read("home/nvim/init.lua"), "-- init\n");
+    assert_eq!(fixture.read("home/nvim.dotrbak"), "not a directory\n");
+}
+
+#[test]
+fn test_replace_mismatched_keeps_an_older_directory_backup() {
+    let fixture = TestFixture::new();
+    fixture.write("home/.gitconfig/leftover", "new\n");
+    fixture.write("home/.gitconfig.dotrbak.d/leftover", "old\n");
+
+    let err = fixture.deploy("f_gitconfig", true).unwrap_err().to_string();
+    assert!(err.contains("already exists"), "{}", err);
+    assert_eq!(fixture.read("home/.gitconfig.dotrbak.d/leftover"), "old\n");
+}
+
+#[test]
+fn test_update_refuses_a_mismatched_dest() {
+    let fixture = TestFixture::new();
+    fixture.write("home/.gitconfig/leftover", "keep me\n");
+
+    let err = fixture
+        .run(Command::Update(UpdateArgs {
+            packages: Some(vec!["f_gitconfig".to_string()]),
+            ..Default::default()
+        }))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("expects a file"), "{}", err);
+    assert_eq!(fixture.read("dotfiles/gitconfig"), "[user]\n");
+}
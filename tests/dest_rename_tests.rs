@@ -0,0 +1,144 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+    context::Context,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(rename: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_dest_rename_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            format!(
+                "banner = false\n\n[packages.d_ssh]\nsrc = \"dotfiles/ssh\"\ndest = \"home/.ssh\"\n\n[packages.d_ssh.rename]\n{}",
+                rename
+            ),
+        )
+        .expect("Failed to write config");
+        fixture.write("dotfiles/ssh/config.work", "Host work\n");
+        fixture.write("dotfiles/ssh/known_hosts", "github.com ssh-ed25519\n");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self) {
+        self.run(Command::Deploy(DeployUpdateArgs::default()))
+            .expect("Deploy failed");
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).unwrap()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const RENAME: &str = "\"config.work\" = \"config\"\n";
+
+#[test]
+fn test_deploy_applies_the_rename() {
+    let fixture = TestFixture::new(RENAME);
+    fixture.deploy();
+    assert_eq!(fixture.read("home/.ssh/config"), "Host work\n");
+    assert!(!fixture.cwd.join("home/.ssh/config.work").exists());
+    assert_eq!(
+        fixture.read("home/.ssh/known_hosts"),
+        "github.com ssh-ed25519\n"
+    );
+}
+
+#[test]
+fn test_rename_applies_to_templates() {
+    let fixture = TestFixture::new(RENAME);
+    fs::remove_file(fixture.cwd.join("dotfiles/ssh/config.work")).unwrap();
+    fixture.write("dotfiles/ssh/config.work.tera", "Host {{ \"work\" }}\n");
+    fixture.deploy();
+    assert_eq!(fixture.read("home/.ssh/config"), "Host work\n");
+}
+
+#[test]
+fn test_update_maps_back_to_the_renamed_src() {
+    let fixture = TestFixture::new(RENAME);
+    fixture.deploy();
+    fixture.write("home/.ssh/config", "Host work\n  User me\n");
+    // Not ours: the src of that name deploys as config
+    fixture.write("home/.ssh/config.work", "stray\n");
+
+    fixture
+        .run(Command::Update(UpdateArgs::default()))
+        .expect("Update failed");
+    assert_eq!(
+        fixture.read("dotfiles/ssh/config.work"),
+        "Host work\n  User me\n"
+    );
+    assert!(!fixture.cwd.join("dotfiles/ssh/config").exists());
+}
+
+#[test]
+fn test_file_pairs_use_the_renamed_dest() {
+    let fixture = TestFixture::new(RENAME);
+    let conf = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    let ctx = Context::from_config(&fixture.cwd, &conf).expect("Failed to create context");
+    let pairs = conf.packages["d_ssh"]
+        .file_pairs(&ctx)
+        .expect("Failed to list files");
+    assert!(
+        pairs
+            .iter()
+            .any(|(src, dest)| src.ends_with("config.work") && dest.ends_with(".ssh/config")),
+        "{:?}",
+        pairs
+    );
+}
+
+#[test]
+fn test_conflicting_renames_are_an_error() {
+    let fixture = TestFixture::new("\"config.work\" = \"config\"\n\"config.home\" = \"config\"\n");
+    let err = Config::from_path(&fixture.cwd).unwrap_err().to_string();
+    assert!(
+        err.contains("rename maps both 'config.home' and 'config.work' to 'config'"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_rename_round_trips() {
+    let fixture = TestFixture::new(RENAME);
+    let conf = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    conf.save(&fixture.cwd).expect("Failed to save config");
+    let conf = Config::from_path(&fixture.cwd).expect("Failed to reload config");
+    assert_eq!(
+        conf.packages["d_ssh"].rename.get("config.work"),
+        Some(&"config".to_string())
+    );
+}
@@ -7,7 +7,6 @@ use dotr::{
 
 mod common;
 
-const PLAYGROUND_DIR: &str = "tests/playground";
 const NVIM_PATH: &str = "src/nvim/";
 const BASHRC_PATH: &str = "src/.bashrc";
 const TMUX_PATH: &str = "src/tmux/";
@@ -18,7 +17,7 @@ struct TestFixture {
 
 impl TestFixture {
     fn new() -> Self {
-        let cwd = PathBuf::from(PLAYGROUND_DIR);
+        let cwd = std::env::temp_dir().join(format!("dotr_diff_test_{}", uuid::Uuid::new_v4()));
         common::setup(&cwd);
         Self { cwd }
     }
@@ -26,19 +25,22 @@ impl TestFixture {
     fn get_cli(&self, command: Option<dotr::cli::Command>) -> dotr::cli::Cli {
         dotr::cli::Cli {
             command,
-            working_dir: Some(PLAYGROUND_DIR.to_string()),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
         }
     }
 
     fn init(&self) {
-        run_cli(self.get_cli(Some(dotr::cli::Command::Init(InitArgs {})))).expect("Init failed");
+        run_cli(self.get_cli(Some(dotr::cli::Command::Init(InitArgs::default()))))
+            .expect("Init failed");
     }
 
     fn import(&self, path: &str) {
         run_cli(self.get_cli(Some(dotr::cli::Command::Import(ImportArgs {
-            path: path.to_string(),
+            paths: vec![path.to_string()],
             name: None,
             profile: None,
+            ..Default::default()
         }))))
         .expect("Import failed");
     }
@@ -48,6 +50,7 @@ impl TestFixture {
             self.get_cli(Some(dotr::cli::Command::Deploy(DeployUpdateArgs {
                 packages,
                 profile: None,
+                ..Default::default()
             }))),
         )
         .expect("Deploy failed");
@@ -58,6 +61,7 @@ impl TestFixture {
             self.get_cli(Some(dotr::cli::Command::Diff(DeployUpdateArgs {
                 packages,
                 profile: None,
+                ..Default::default()
             }))),
         )
     }
@@ -73,18 +77,13 @@ impl TestFixture {
     }
 
     fn get_package_name(&self, path: &str) -> String {
-        let args = ImportArgs {
-            path: path.to_string(),
-            name: None,
-            profile: None,
-        };
-        dotr::package::get_package_name(&args, &self.cwd)
+        dotr::package::get_package_name(path, &None, &self.cwd).unwrap()
     }
 }
 
 impl Drop for TestFixture {
     fn drop(&mut self) {
-        common::teardown(&self.cwd);
+        fs::remove_dir_all(&self.cwd).ok();
     }
 }
 
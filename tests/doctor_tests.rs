@@ -0,0 +1,163 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DoctorArgs, InitArgs, run_cli},
+    config::Config,
+    context::Context,
+    doctor::{CheckStatus, Finding, checks},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(config: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_doctor_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(fixture.cwd.join("config.toml"), config).expect("Failed to write config");
+        fs::write(fixture.cwd.join("dotfiles/f_rc"), "{{ editor }}\n").unwrap();
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn doctor(&self) -> Result<(), anyhow::Error> {
+        self.run(Command::Doctor(DoctorArgs::default()))
+    }
+
+    // What the check called `name` finds
+    fn findings(&self, name: &str) -> Vec<Finding> {
+        let conf = Config::from_path(&self.cwd).expect("Failed to load config");
+        let ctx = Context::from_config(&self.cwd, &conf).expect("Failed to create context");
+        let check = checks()
+            .into_iter()
+            .find(|check| check.name == name)
+            .expect("No such check");
+        (check.run)(&conf, &ctx)
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const HEALTHY_CONFIG: &str = r#"
+banner = false
+
+[variables]
+editor = "nvim"
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "home/rc"
+
+[profiles.work]
+dependencies = ["f_rc"]
+"#;
+
+#[test]
+fn test_healthy_config_passes() {
+    let fixture = TestFixture::new(HEALTHY_CONFIG);
+    fixture.doctor().expect("Doctor should pass");
+    for check in checks() {
+        assert!(fixture.findings(check.name).is_empty(), "{}", check.name);
+    }
+    // Nothing was deployed or created along the way
+    assert!(!fixture.cwd.join("home").exists());
+}
+
+#[test]
+fn test_unknown_keys_only_warn() {
+    let fixture = TestFixture::new(&format!("{}\nfavourite_colour = \"red\"\n", HEALTHY_CONFIG));
+    let findings = fixture.findings("config");
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].status, CheckStatus::Warn);
+    assert!(findings[0].message.contains("favourite_colour"));
+    fixture
+        .doctor()
+        .expect("Warnings should not fail the doctor");
+}
+
+#[test]
+fn test_broken_references_fail() {
+    let fixture = TestFixture::new(
+        r#"
+banner = false
+
+[packages.f_rc]
+src = "dotfiles/f_missing"
+dest = "home/rc"
+dependencies = ["f_nowhere"]
+
+[packages.f_rc.targets]
+laptop = "home/laptop_rc"
+
+[profiles.work]
+dependencies = ["f_gone"]
+"#,
+    );
+    let sources = fixture.findings("sources");
+    assert!(sources[0].message.contains("f_missing"), "{:?}", sources);
+    let dependencies = fixture.findings("dependencies");
+    assert_eq!(dependencies.len(), 2, "{:?}", dependencies);
+    assert!(dependencies.iter().all(|f| f.status == CheckStatus::Fail));
+    let targets = fixture.findings("targets");
+    assert!(targets[0].message.contains("'laptop'"), "{:?}", targets);
+
+    let err = fixture.doctor().expect_err("Doctor should fail");
+    assert!(err.to_string().contains("3 check(s) failed"), "{}", err);
+}
+
+#[test]
+fn test_undefined_template_variable_fails() {
+    let fixture = TestFixture::new(&HEALTHY_CONFIG.replace("editor = \"nvim\"", ""));
+    let findings = fixture.findings("templates");
+    assert_eq!(findings.len(), 1);
+    assert!(findings[0].message.contains("'editor'"), "{:?}", findings);
+    assert!(fixture.doctor().is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_read_only_dest_parent_fails() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = TestFixture::new(&HEALTHY_CONFIG.replace("home/rc", "locked/nested/rc"));
+    let locked = fixture.cwd.join("locked");
+    fs::create_dir_all(&locked).unwrap();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o555)).unwrap();
+    let findings = fixture.findings("dest permissions");
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+    assert_eq!(findings.len(), 1);
+    assert!(findings[0].message.contains("read-only"), "{:?}", findings);
+    assert!(!locked.join("nested").exists());
+}
+
+#[test]
+fn test_auto_commit_needs_a_repository() {
+    let fixture = TestFixture::new(&format!("{}\n[git]\nauto_commit = true\n", HEALTHY_CONFIG));
+    let findings = fixture.findings("git");
+    assert_eq!(findings.len(), 1);
+    assert!(findings[0].message.contains("not a git repository"));
+}
+
+#[test]
+fn test_unloadable_config_fails() {
+    let fixture = TestFixture::new("banner = [not toml");
+    assert!(fixture.doctor().is_err());
+}
@@ -0,0 +1,198 @@
+#![cfg(unix)]
+
+use std::{
+    fs, os::unix::fs::PermissionsExt, path::PathBuf, process::Command as Process, sync::Once,
+};
+
+use dotr::{
+    cli::{Cli, Command, EditArgs, InitArgs, run_cli},
+    utils::set_prompt_answers,
+};
+
+// Appends a line to the file, or to init.lua of a directory. Sources holding
+// "keep" are left alone and those holding "fail" make it exit with 3.
+const EDITOR_SCRIPT: &str = r#"#!/bin/sh
+target="$1"
+[ -d "$target" ] && target="$target/init.lua"
+grep -q keep "$target" && exit 0
+grep -q fail "$target" && exit 3
+echo "edited" >> "$target"
+"#;
+
+static EDITOR: Once = Once::new();
+
+fn editor_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("dotr_fake_editor.sh")
+}
+
+// Every test in this file shares the one fake editor in $VISUAL
+fn install_editor() {
+    EDITOR.call_once(|| {
+        let path = editor_path();
+        fs::write(&path, EDITOR_SCRIPT).expect("Failed to write editor script");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+            .expect("Failed to make editor script executable");
+        // SAFETY: set once, before any test of this file runs an editor
+        unsafe { std::env::set_var("VISUAL", &path) };
+    });
+}
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        install_editor();
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_edit_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fixture.write_file("config.toml", CONFIG);
+        fixture.write_file("dotfiles/f_rc", "rc\n");
+        fixture.write_file("dotfiles/d_nvim/init.lua", "init\n");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn edit(&self, package: &str, and_deploy: bool, no_deploy: bool) -> Result<(), anyhow::Error> {
+        self.run(Command::Edit(EditArgs {
+            package: package.to_string(),
+            and_deploy,
+            no_deploy,
+            ..Default::default()
+        }))
+    }
+
+    fn dotr(&self, args: &[&str]) -> Process {
+        let mut process = Process::new(env!("CARGO_BIN_EXE_dotr"));
+        process
+            .args(["-w", self.cwd.to_str().unwrap(), "--no-progress"])
+            .args(args)
+            .env_remove("VISUAL")
+            .env_remove("EDITOR");
+        process
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "rc"
+
+[packages.d_nvim]
+src = "dotfiles/d_nvim"
+dest = "nvim"
+"#;
+
+#[test]
+fn test_edit_and_deploy_file_package() {
+    let fixture = TestFixture::new();
+    fixture.edit("f_rc", true, false).expect("Edit failed");
+
+    assert_eq!(fixture.read_file("dotfiles/f_rc"), "rc\nedited\n");
+    assert_eq!(fixture.read_file("rc"), "rc\nedited\n");
+}
+
+#[test]
+fn test_edit_dir_package_after_prompt() {
+    let fixture = TestFixture::new();
+    set_prompt_answers(vec!["y".to_string()]);
+    fixture.edit("d_nvim", false, false).expect("Edit failed");
+
+    assert_eq!(
+        fixture.read_file("dotfiles/d_nvim/init.lua"),
+        "init\nedited\n"
+    );
+    assert_eq!(fixture.read_file("nvim/init.lua"), "init\nedited\n");
+}
+
+#[test]
+fn test_no_deploy_leaves_dest_alone() {
+    let fixture = TestFixture::new();
+    fixture.edit("f_rc", false, true).expect("Edit failed");
+
+    assert_eq!(fixture.read_file("dotfiles/f_rc"), "rc\nedited\n");
+    assert!(!fixture.cwd.join("rc").exists());
+}
+
+#[test]
+fn test_unchanged_source_is_not_deployed() {
+    let fixture = TestFixture::new();
+    fixture.write_file("dotfiles/f_rc", "keep\n");
+    fixture.edit("f_rc", true, false).expect("Edit failed");
+
+    assert!(!fixture.cwd.join("rc").exists());
+}
+
+#[test]
+fn test_unknown_package_fails() {
+    let fixture = TestFixture::new();
+    let err = fixture
+        .edit("f_missing", true, false)
+        .expect_err("An unknown package should fail");
+    assert!(err.to_string().contains("f_missing"), "{}", err);
+}
+
+#[test]
+fn test_missing_editor_fails() {
+    let fixture = TestFixture::new();
+    let output = fixture
+        .dotr(&["edit", "f_rc"])
+        .output()
+        .expect("Failed to run dotr");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        format!("{}{}", stdout, stderr).contains("$VISUAL"),
+        "{}{}",
+        stdout,
+        stderr
+    );
+}
+
+#[test]
+fn test_editor_exit_status_is_passed_through() {
+    let fixture = TestFixture::new();
+    fixture.write_file("dotfiles/f_rc", "fail\n");
+    let status = fixture
+        .dotr(&["edit", "f_rc", "--and-deploy"])
+        .env("VISUAL", editor_path())
+        .status()
+        .expect("Failed to run dotr");
+
+    assert_eq!(status.code(), Some(3));
+    assert!(!fixture.cwd.join("rc").exists());
+}
@@ -0,0 +1,116 @@
+use std::{fs, path::PathBuf, sync::Once};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    config::Config,
+    context::Context,
+};
+
+static ENVIRONMENT: Once = Once::new();
+
+// Every test in this file sees the same three variables
+fn set_environment() {
+    ENVIRONMENT.call_once(|| {
+        // SAFETY: set once, before any test of this file reads the environment
+        unsafe {
+            std::env::set_var("DOTR_ALLOW_THEME", "dark");
+            std::env::set_var("DOTR_ALLOW_FONT", "mono");
+            std::env::set_var("DOTR_JUNK", "noise");
+        }
+    });
+}
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(config: &str) -> Self {
+        set_environment();
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_env_allowlist_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(fixture.cwd.join("config.toml"), config).expect("Failed to write config");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn context(&self) -> Context {
+        let conf = Config::from_path(&self.cwd).expect("Failed to load config");
+        Context::from_config(&self.cwd, &conf).expect("Failed to create context")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const ALLOWLIST_CONFIG: &str = r#"
+banner = false
+env_allowlist = ["DOTR_ALLOW_*"]
+
+[variables]
+DOTR_ALLOW_FONT = "serif"
+"#;
+
+#[test]
+fn test_allowlist_filters_top_level_env() {
+    let fixture = TestFixture::new(ALLOWLIST_CONFIG);
+    let variables = fixture.context().get_context_variables();
+
+    assert_eq!(variables["DOTR_ALLOW_THEME"].as_str(), Some("dark"));
+    assert!(!variables.contains_key("DOTR_JUNK"));
+    assert_eq!(variables["env"]["DOTR_JUNK"].as_str(), Some("noise"));
+}
+
+#[test]
+fn test_without_allowlist_everything_stays() {
+    let fixture = TestFixture::new("banner = false\n");
+    let variables = fixture.context().get_context_variables();
+
+    assert_eq!(variables["DOTR_JUNK"].as_str(), Some("noise"));
+    assert_eq!(variables["env"]["DOTR_ALLOW_THEME"].as_str(), Some("dark"));
+}
+
+#[test]
+fn test_config_overrides_allowed_env() {
+    let fixture = TestFixture::new(ALLOWLIST_CONFIG);
+    let variables = fixture.context().get_context_variables();
+
+    assert_eq!(variables["DOTR_ALLOW_FONT"].as_str(), Some("serif"));
+    assert_eq!(variables["env"]["DOTR_ALLOW_FONT"].as_str(), Some("mono"));
+}
+
+#[test]
+fn test_nested_env_renders() {
+    let fixture = TestFixture::new(&format!(
+        "{}\n[packages.f_rc]\nsrc = \"dotfiles/f_rc\"\ndest = \"rc\"\n",
+        ALLOWLIST_CONFIG
+    ));
+    fs::write(
+        fixture.cwd.join("dotfiles/f_rc"),
+        "{{ env.DOTR_JUNK }} {{ DOTR_ALLOW_THEME }} {{ DOTR_ALLOW_FONT }}\n",
+    )
+    .unwrap();
+    fixture
+        .run(Command::Deploy(DeployUpdateArgs::default()))
+        .expect("Deploy failed");
+
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("rc")).unwrap(),
+        "noise dark serif\n"
+    );
+}
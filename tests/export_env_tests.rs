@@ -0,0 +1,127 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    context::export_variables,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(top_level: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_export_env_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            format!("banner = false\n{}\n{}", top_level, PACKAGE_CONFIG),
+        )
+        .expect("Failed to write config");
+        fs::write(fixture.cwd.join("dotfiles/f_rc"), "rc\n").unwrap();
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    // The DOTR_VAR_ lines the post action saw
+    fn deploy_env(&self) -> Vec<String> {
+        self.run(Command::Deploy(DeployUpdateArgs::default()))
+            .expect("Deploy failed");
+        let mut lines: Vec<String> = fs::read_to_string(self.cwd.join("env.txt"))
+            .expect("The post action didn't run")
+            .lines()
+            .map(str::to_string)
+            .collect();
+        lines.sort();
+        lines
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const PACKAGE_CONFIG: &str = r#"
+[variables]
+editor = "nvim"
+ports = [8080, 8443]
+since = 1979-05-27T07:32:00Z
+ratio = 0.5
+enabled = true
+
+[variables.database]
+host = "db.local"
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "rc"
+shell = "/bin/sh"
+post_actions = ["env | grep DOTR_VAR_ > env.txt || true"]
+"#;
+
+#[cfg(unix)]
+#[test]
+fn test_post_action_sees_exported_variables() {
+    let fixture = TestFixture::new("export_env = true");
+    let env = fixture.deploy_env();
+    for expected in [
+        "DOTR_VAR_EDITOR=nvim",
+        "DOTR_VAR_PORTS=8080:8443",
+        "DOTR_VAR_SINCE=1979-05-27T07:32:00Z",
+        "DOTR_VAR_RATIO=0.5",
+        "DOTR_VAR_ENABLED=true",
+        "DOTR_VAR_DATABASE_HOST=db.local",
+        "DOTR_VAR_DOTR_PACKAGE_NAME=f_rc",
+    ] {
+        assert!(env.iter().any(|line| line == expected), "{:?}", env);
+    }
+    // The environment itself isn't exported a second time
+    assert!(!env.iter().any(|line| line.starts_with("DOTR_VAR_ENV_")));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_export_is_off_by_default() {
+    let fixture = TestFixture::new("");
+    assert!(fixture.deploy_env().is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_array_separator_is_configurable() {
+    let fixture = TestFixture::new("export_env = true\nexport_env_separator = \",\"");
+    let env = fixture.deploy_env();
+    assert!(
+        env.iter().any(|line| line == "DOTR_VAR_PORTS=8080,8443"),
+        "{:?}",
+        env
+    );
+}
+
+#[test]
+fn test_names_are_made_shell_safe() {
+    let variables: toml::Table = "\"git-user\" = { \"e.mail\" = \"me@example.com\" }"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        export_variables(&variables, ":"),
+        vec![(
+            "DOTR_VAR_GIT_USER_E_MAIL".to_string(),
+            "me@example.com".to_string()
+        )]
+    );
+}
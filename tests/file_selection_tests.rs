@@ -0,0 +1,176 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+    package::Package,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(package: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_file_selection_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            format!("{}{}", CONFIG, package),
+        )
+        .expect("Failed to write config");
+        fixture.write("dotfiles/d_nvim/init.lua", "-- {{ name }}\n");
+        fixture.write("dotfiles/d_nvim/options.lua", "-- options\n");
+        fixture.write("dotfiles/d_nvim/lua/keymaps.lua", "-- keymaps\n");
+        fixture.write("dotfiles/d_nvim/lua/plugins.lua", "-- plugins\n");
+        fixture.write("dotfiles/d_nvim/generated/x7f3/cache.lua", "-- junk\n");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self) -> Result<(), anyhow::Error> {
+        self.run(Command::Deploy(DeployUpdateArgs::default()))
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).unwrap()
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.cwd.join(path).exists()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[variables]
+name = "nvim"
+
+[packages.d_nvim]
+src = "dotfiles/d_nvim"
+dest = "home/.config/nvim"
+"#;
+
+#[test]
+fn test_max_depth_skips_nested_dirs() {
+    let fixture = TestFixture::new("max_depth = 1\n");
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(fixture.read("home/.config/nvim/init.lua"), "-- nvim\n");
+    assert!(fixture.exists("home/.config/nvim/options.lua"));
+    assert!(!fixture.exists("home/.config/nvim/lua"));
+    assert!(!fixture.exists("home/.config/nvim/generated"));
+}
+
+#[test]
+fn test_max_depth_two_reaches_one_level_down() {
+    let fixture = TestFixture::new("max_depth = 2\n");
+    fixture.deploy().expect("Deploy failed");
+    assert!(fixture.exists("home/.config/nvim/lua/keymaps.lua"));
+    assert!(!fixture.exists("home/.config/nvim/generated/x7f3"));
+}
+
+#[test]
+fn test_files_deploys_only_the_listed_files() {
+    let fixture = TestFixture::new("files = [\"init.lua\", \"lua/keymaps.lua\"]\n");
+    fixture.deploy().expect("Deploy failed");
+    // Still rendered as usual
+    assert_eq!(fixture.read("home/.config/nvim/init.lua"), "-- nvim\n");
+    assert_eq!(
+        fixture.read("home/.config/nvim/lua/keymaps.lua"),
+        "-- keymaps\n"
+    );
+    assert!(!fixture.exists("home/.config/nvim/options.lua"));
+    assert!(!fixture.exists("home/.config/nvim/lua/plugins.lua"));
+    assert!(!fixture.exists("home/.config/nvim/generated"));
+}
+
+#[test]
+fn test_missing_listed_file_is_an_error() {
+    let fixture = TestFixture::new("files = [\"init.lua\", \"lua/missing.lua\"]\n");
+    let err = fixture.deploy().expect_err("Deploy should fail");
+    assert!(
+        format!("{:#}", err).contains("'lua/missing.lua' in files"),
+        "{:#}",
+        err
+    );
+    assert!(!fixture.exists("home/.config/nvim/init.lua"));
+}
+
+#[test]
+fn test_ignore_filters_the_listed_files() {
+    let fixture =
+        TestFixture::new("files = [\"init.lua\", \"lua/keymaps.lua\"]\nignore = [\"lua/**\"]\n");
+    fixture.deploy().expect("Deploy failed");
+    assert!(fixture.exists("home/.config/nvim/init.lua"));
+    assert!(!fixture.exists("home/.config/nvim/lua/keymaps.lua"));
+
+    // Everything filtered only warns, and deploys nothing
+    let fixture = TestFixture::new("files = [\"lua/keymaps.lua\"]\nignore = [\"lua/**\"]\n");
+    fixture.deploy().expect("Deploy failed");
+    assert!(!fixture.exists("home/.config/nvim/lua/keymaps.lua"));
+    assert!(!fixture.exists("home/.config/nvim/init.lua"));
+}
+
+#[test]
+fn test_update_copies_back_only_the_listed_files() {
+    let fixture = TestFixture::new("files = [\"options.lua\"]\n");
+    fixture.deploy().expect("Deploy failed");
+    fixture.write("home/.config/nvim/options.lua", "-- edited\n");
+    fixture.write("home/.config/nvim/stray.lua", "-- not ours\n");
+    fixture
+        .run(Command::Update(UpdateArgs::default()))
+        .expect("Update failed");
+    assert_eq!(fixture.read("dotfiles/d_nvim/options.lua"), "-- edited\n");
+    assert!(!fixture.exists("dotfiles/d_nvim/stray.lua"));
+}
+
+#[test]
+fn test_file_selection_round_trips() {
+    let fixture = TestFixture::new("max_depth = 3\nfiles = [\"init.lua\", \"lua/keymaps.lua\"]\n");
+    let conf = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    let package = &conf.packages["d_nvim"];
+    assert_eq!(package.max_depth, Some(3));
+    let reparsed = Package::from_table("d_nvim", &package.to_table()).expect("Failed to parse");
+    assert_eq!(reparsed.max_depth, Some(3));
+    assert_eq!(reparsed.files, vec!["init.lua", "lua/keymaps.lua"]);
+
+    fs::write(
+        fixture.cwd.join("config.toml"),
+        format!("{}files = [\"../outside\"]\n", CONFIG),
+    )
+    .unwrap();
+    let err = Config::from_path(&fixture.cwd).expect_err("Config should fail");
+    assert!(format!("{:#}", err).contains("inside src"), "{:#}", err);
+
+    fs::write(
+        fixture.cwd.join("config.toml"),
+        format!("{}max_depth = 0\n", CONFIG),
+    )
+    .unwrap();
+    assert!(Config::from_path(&fixture.cwd).is_err());
+}
@@ -0,0 +1,105 @@
+use dotr::package::compile_string;
+use toml::Table;
+
+fn render(template: &str, value: toml::Value) -> Result<String, anyhow::Error> {
+    let mut context = Table::new();
+    context.insert("v".to_string(), value);
+    compile_string(template, &context)
+}
+
+fn render_str(template: &str, value: &str) -> String {
+    render(template, toml::Value::String(value.to_string())).expect("Failed to compile")
+}
+
+#[test]
+fn test_homedir_expands_tilde() {
+    let home = std::env::home_dir().expect("No home dir");
+    let home = home.to_string_lossy();
+    assert_eq!(render_str("{{ v | homedir }}", "~"), home);
+    assert_eq!(
+        render_str("{{ v | homedir }}", "~/My Documents/ünï"),
+        format!("{}/My Documents/ünï", home)
+    );
+}
+
+#[test]
+fn test_homedir_leaves_other_paths_alone() {
+    assert_eq!(render_str("{{ v | homedir }}", "/etc/hosts"), "/etc/hosts");
+    assert_eq!(render_str("{{ v | homedir }}", "~other/x"), "~other/x");
+    assert_eq!(render_str("{{ v | homedir }}", "a ~/b"), "a ~/b");
+}
+
+#[test]
+fn test_shell_quote() {
+    assert_eq!(render_str("{{ v | shell_quote }}", "plain"), "'plain'");
+    assert_eq!(
+        render_str("{{ v | shell_quote }}", "with spaces"),
+        "'with spaces'"
+    );
+    assert_eq!(render_str("{{ v | shell_quote }}", "it's"), r"'it'\''s'");
+    assert_eq!(
+        render_str("{{ v | shell_quote }}", r#"say "hi" $HOME"#),
+        r#"'say "hi" $HOME'"#
+    );
+    assert_eq!(render_str("{{ v | shell_quote }}", "日本語"), "'日本語'");
+    assert_eq!(render_str("{{ v | shell_quote }}", ""), "''");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_shell_quote_round_trips_through_shell() {
+    let value = "it's a \"test\" with $VARS and `ticks` — ✓";
+    let quoted = render_str("{{ v | shell_quote }}", value);
+    let output = std::process::Command::new("/bin/sh")
+        .arg("-c")
+        .arg(format!("printf %s {}", quoted))
+        .output()
+        .expect("Failed to run sh");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), value);
+}
+
+#[test]
+fn test_dirname() {
+    assert_eq!(render_str("{{ v | dirname }}", "/a/b/c.txt"), "/a/b");
+    assert_eq!(
+        render_str("{{ v | dirname }}", "~/My Files/ré sumé.pdf"),
+        "~/My Files"
+    );
+    assert_eq!(render_str("{{ v | dirname }}", "file"), "");
+    assert_eq!(render_str("{{ v | dirname }}", "/"), "/");
+}
+
+#[test]
+fn test_basename() {
+    assert_eq!(render_str("{{ v | basename }}", "/a/b/c.txt"), "c.txt");
+    assert_eq!(
+        render_str("{{ v | basename }}", "~/My Files/ré sumé.pdf"),
+        "ré sumé.pdf"
+    );
+    assert_eq!(render_str("{{ v | basename }}", "/a/dir/"), "dir");
+    assert_eq!(render_str("{{ v | basename }}", "/"), "");
+}
+
+#[test]
+fn test_filters_reject_non_strings() {
+    for filter in ["homedir", "shell_quote", "dirname", "basename"] {
+        let err = render(
+            &format!("{{{{ v | {} }}}}", filter),
+            toml::Value::Integer(42),
+        )
+        .expect_err("Non-string input should fail");
+        let message = format!("{:#}", err);
+        assert!(
+            message.contains(&format!(
+                "Filter '{}' expected a string but got a number",
+                filter
+            )),
+            "Unexpected error: {}",
+            message
+        );
+    }
+
+    let err = render("{{ v | basename }}", toml::Value::Array(vec![]))
+        .expect_err("Array input should fail");
+    assert!(format!("{:#}", err).contains("got an array"));
+}
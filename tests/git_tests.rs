@@ -0,0 +1,240 @@
+use std::{fs, path::PathBuf, process::Command as Process};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+    git::{DEFAULT_COMMIT_MESSAGE, GitConfig, is_repository, today},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir = std::env::temp_dir().join(format!("dotr_git_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+
+    fn deploy(&self) {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))))
+            .expect("Deploy failed");
+    }
+
+    fn update(&self) {
+        run_cli(self.get_cli(Some(Command::Update(UpdateArgs::default())))).expect("Update failed");
+    }
+
+    fn git(&self, args: &[&str]) -> String {
+        let output = Process::new("git")
+            .args(args)
+            .current_dir(&self.cwd)
+            .output()
+            .expect("Failed to run git");
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    fn git_init(&self) {
+        self.git(&["init", "-q"]);
+        self.git(&["config", "user.email", "test@example.com"]);
+        self.git(&["config", "user.name", "Test"]);
+        self.git(&["add", "-A"]);
+        self.git(&["commit", "-q", "-m", "initial"]);
+    }
+
+    fn commit_count(&self) -> usize {
+        self.git(&["rev-list", "--count", "HEAD"]).parse().unwrap()
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[variables]
+HOST = "laptop"
+
+[git]
+auto_commit = true
+commit_message = "dotr update from {{ HOST }} on {{ date }}"
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "rc"
+"#;
+
+fn setup(config: &str) -> TestFixture {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("config.toml", config);
+    fixture.write_file("dotfiles/f_rc", "original\n");
+    fixture
+}
+
+#[test]
+fn test_git_config_round_trip() {
+    let config = Config::from_table(&CONFIG.parse().unwrap()).expect("Failed to parse");
+    assert!(config.git.auto_commit);
+    assert_eq!(
+        config.git.commit_message,
+        "dotr update from {{ HOST }} on {{ date }}"
+    );
+    let reparsed = Config::from_table(&config.to_table()).expect("Round trip failed");
+    assert_eq!(reparsed.git, config.git);
+
+    let default = Config::new();
+    assert_eq!(default.git, GitConfig::default());
+    assert_eq!(default.git.commit_message, DEFAULT_COMMIT_MESSAGE);
+    assert!(!default.to_table().contains_key("git"));
+}
+
+#[test]
+fn test_update_commits_changes() {
+    let fixture = setup(CONFIG);
+    fixture.git_init();
+    fixture.deploy();
+    fixture.write_file("rc", "edited on this machine\n");
+
+    fixture.update();
+
+    assert_eq!(fixture.commit_count(), 2);
+    assert_eq!(
+        fixture.git(&["log", "-1", "--format=%s"]),
+        format!("dotr update from laptop on {}", today())
+    );
+    assert_eq!(
+        fixture.git(&["status", "--porcelain", "--", "dotfiles"]),
+        "",
+        "dotfiles/ should be clean after the commit"
+    );
+}
+
+#[test]
+fn test_update_without_changes_does_not_commit() {
+    let fixture = setup(CONFIG);
+    fixture.git_init();
+    fixture.deploy();
+
+    fixture.update();
+
+    assert_eq!(
+        fixture.commit_count(),
+        1,
+        "Nothing changed, nothing to commit"
+    );
+}
+
+#[test]
+fn test_auto_commit_leaves_unrelated_staged_files() {
+    let fixture = setup(CONFIG);
+    fixture.git_init();
+    fixture.deploy();
+    fixture.write_file("notes.txt", "work in progress\n");
+    fixture.git(&["add", "notes.txt"]);
+    fixture.write_file("rc", "edited\n");
+
+    fixture.update();
+
+    assert_eq!(fixture.commit_count(), 2);
+    assert_eq!(
+        fixture.git(&["diff", "--cached", "--name-only"]),
+        "notes.txt"
+    );
+}
+
+#[test]
+fn test_update_outside_git_repository() {
+    let fixture = setup(CONFIG);
+    assert!(!is_repository(&fixture.cwd));
+    fixture.deploy();
+    fixture.write_file("rc", "edited\n");
+
+    // Warns and carries on
+    fixture.update();
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("dotfiles/f_rc")).unwrap(),
+        "edited\n"
+    );
+}
+
+#[test]
+fn test_auto_commit_disabled_by_default() {
+    let fixture = setup(
+        r#"
+banner = false
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "rc"
+"#,
+    );
+    fixture.git_init();
+    fixture.deploy();
+    fixture.write_file("rc", "edited\n");
+
+    fixture.update();
+
+    assert_eq!(fixture.commit_count(), 1);
+}
+
+#[test]
+fn test_deploy_with_uncommitted_changes_still_deploys() {
+    let fixture = setup(CONFIG);
+    fixture.git_init();
+    fixture.write_file("dotfiles/f_rc", "uncommitted\n");
+
+    // Only a warning, with or without --no-git-check
+    fixture.deploy();
+    run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
+        no_git_check: true,
+        ..Default::default()
+    }))))
+    .expect("Deploy failed");
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("rc")).unwrap(),
+        "uncommitted\n"
+    );
+}
+
+#[test]
+fn test_today_format() {
+    let date = today();
+    assert_eq!(date.len(), 10);
+    let parts: Vec<&str> = date.split('-').collect();
+    assert_eq!(parts.len(), 3);
+    assert!(parts[0].parse::<u32>().unwrap() >= 2024);
+    assert!((1..=12).contains(&parts[1].parse::<u32>().unwrap()));
+    assert!((1..=31).contains(&parts[2].parse::<u32>().unwrap()));
+}
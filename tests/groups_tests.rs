@@ -0,0 +1,155 @@
+use std::{fs, path::PathBuf};
+
+use clap::Parser;
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, ListArgs, render_list, run_cli},
+    config::Config,
+    context::Context,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(groups: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_groups_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        run_cli(Cli {
+            command: Some(Command::Init(InitArgs::default())),
+            working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+        .expect("Init failed");
+        let mut config = "banner = false\n".to_string();
+        for name in ["bashrc", "zshrc", "alacritty", "kitty", "extra"] {
+            config.push_str(&format!(
+                "\n[packages.f_{0}]\nsrc = \"dotfiles/f_{0}\"\ndest = \"home/{0}\"\naliases = [\"{0}\"]\n",
+                name
+            ));
+            fs::create_dir_all(fixture.cwd.join("dotfiles")).unwrap();
+            fs::write(
+                fixture.cwd.join("dotfiles").join(format!("f_{}", name)),
+                name,
+            )
+            .unwrap();
+        }
+        config.push_str(&format!("\n[groups]\n{}", groups));
+        fs::write(fixture.cwd.join("config.toml"), config).expect("Failed to write config");
+        fixture
+    }
+
+    fn config(&self) -> Config {
+        Config::from_path(&self.cwd).expect("Failed to load config")
+    }
+
+    fn selected(&self, names: &[&str]) -> Result<Vec<String>, anyhow::Error> {
+        let conf = self.config();
+        let ctx = Context::from_config(&self.cwd, &conf).expect("Failed to create context");
+        let names = Some(names.iter().map(|n| n.to_string()).collect());
+        let mut selected: Vec<String> = conf.filter_packages(&ctx, &names)?.into_keys().collect();
+        selected.sort();
+        Ok(selected)
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const GROUPS: &str = "shell = [\"bashrc\", \"f_zshrc\"]\ngui = [\"alacritty\", \"kitty\"]\nall = [\"@shell\", \"@gui\"]\n";
+
+#[test]
+fn test_groups_expand_to_their_packages() {
+    let fixture = TestFixture::new(GROUPS);
+    assert_eq!(
+        fixture.selected(&["@shell", "extra"]).unwrap(),
+        vec!["f_bashrc", "f_extra", "f_zshrc"]
+    );
+    // Nested groups, and a package named twice
+    assert_eq!(
+        fixture.selected(&["@all", "kitty"]).unwrap(),
+        vec!["f_alacritty", "f_bashrc", "f_kitty", "f_zshrc"]
+    );
+
+    run_cli(Cli {
+        command: Some(Command::Deploy(DeployUpdateArgs {
+            packages: Some(vec!["@gui".to_string()]),
+            ..Default::default()
+        })),
+        working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+        ..Default::default()
+    })
+    .expect("Deploy failed");
+    assert!(fixture.cwd.join("home/kitty").exists());
+    assert!(!fixture.cwd.join("home/bashrc").exists());
+}
+
+#[test]
+fn test_unknown_groups_and_members_are_errors() {
+    let fixture = TestFixture::new("shell = [\"bashrc\", \"fish\"]\ngui = [\"@desktop\"]\n");
+    let err = fixture.selected(&["@nope"]).expect_err("Unknown group");
+    assert!(err.to_string().contains("Group 'nope'"), "{}", err);
+    let err = fixture.selected(&["@shell"]).expect_err("Unknown member");
+    assert!(err.to_string().contains("group 'shell'"), "{}", err);
+    let err = fixture
+        .selected(&["@gui"])
+        .expect_err("Unknown nested group");
+    assert!(err.to_string().contains("Group 'desktop'"), "{}", err);
+    assert_eq!(fixture.config().dependency_problems().len(), 2);
+}
+
+#[test]
+fn test_group_cycles_are_errors() {
+    let fixture = TestFixture::new("a = [\"bashrc\", \"@b\"]\nb = [\"@a\"]\n");
+    let err = fixture.selected(&["@a"]).expect_err("Cycle");
+    assert!(
+        err.to_string().contains("@a -> @b -> @a"),
+        "{}",
+        err.to_string()
+    );
+}
+
+#[test]
+fn test_groups_are_saved_and_listed() {
+    let fixture =
+        TestFixture::new("shell = [\"bashrc\", \"fish\"]\ngui = [\"@gui2\", \"kitty\"]\n");
+    let conf = fixture.config();
+    conf.save(&fixture.cwd).expect("Save failed");
+    let conf = fixture.config();
+    assert_eq!(conf.groups["shell"], vec!["bashrc", "fish"]);
+
+    let listed = render_list(
+        &conf,
+        &ListArgs {
+            groups: true,
+            ..Default::default()
+        },
+        false,
+    );
+    assert!(listed.contains("@shell"), "{}", listed);
+    assert!(listed.contains("fish (missing)"), "{}", listed);
+    assert!(listed.contains("@gui2 (missing)"), "{}", listed);
+    assert!(!listed.contains("kitty (missing)"), "{}", listed);
+
+    let listed = render_list(&conf, &ListArgs::default(), false);
+    assert!(listed.contains("f_kitty"), "{}", listed);
+
+    let cli = Cli::try_parse_from(["dotr", "deploy", "-p", "@shell", "@gui", "extra"])
+        .expect("Should parse");
+    let Some(Command::Deploy(args)) = cli.command else {
+        panic!("Expected the deploy command");
+    };
+    assert_eq!(args.packages.unwrap().len(), 3);
+}
+
+#[test]
+fn test_groups_must_be_arrays_of_names() {
+    let fixture = TestFixture::new("shell = \"bashrc\"\n");
+    let err = Config::from_path(&fixture.cwd).expect_err("Config should be refused");
+    assert!(err.to_string().contains("Group 'shell'"), "{}", err);
+}
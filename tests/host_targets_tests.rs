@@ -0,0 +1,106 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, InitArgs, run_cli},
+    config::Config,
+    context::Context,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(targets: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_host_targets_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        run_cli(Cli {
+            command: Some(Command::Init(InitArgs::default())),
+            working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+        .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            format!(
+                "banner = false\n\n[packages.f_outputs]\nsrc = \"dotfiles/outputs\"\ndest = \"home/outputs\"\n\n[packages.f_outputs.targets]\n{}\n[profiles.work]\n",
+                targets
+            ),
+        )
+        .expect("Failed to write config");
+        fixture
+    }
+
+    // The dests of f_outputs on `hostname`, with `profile` active
+    fn dests(&self, hostname: &str, profile: Option<&str>) -> Vec<String> {
+        let conf = Config::from_path(&self.cwd).expect("Failed to load config");
+        let mut ctx = Context::from_config(&self.cwd, &conf).expect("Failed to create context");
+        ctx.set_hostname(hostname);
+        if let Some(name) = profile {
+            ctx.set_profile(Some(conf.profiles[name].clone()))
+                .expect("Failed to set profile");
+        }
+        conf.packages["f_outputs"]
+            .resolve_dests(&ctx)
+            .expect("Failed to resolve dests")
+            .iter()
+            .map(|dest| {
+                dest.strip_prefix(&self.cwd)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_host_target_beats_profile_target() {
+    let fixture = TestFixture::new(
+        "\"host:framework\" = \"home/outputs-laptop\"\nwork = \"home/outputs-work\"\n",
+    );
+    assert_eq!(
+        fixture.dests("framework", Some("work")),
+        vec!["home/outputs-laptop"]
+    );
+    assert_eq!(
+        fixture.dests("desktop", Some("work")),
+        vec!["home/outputs-work"]
+    );
+    assert_eq!(fixture.dests("desktop", None), vec!["home/outputs"]);
+}
+
+#[test]
+fn test_host_glob_matches() {
+    let fixture = TestFixture::new("\"host:fw-*\" = [\"home/a\", \"home/b\"]\n");
+    assert_eq!(fixture.dests("fw-13", None), vec!["home/a", "home/b"]);
+    assert_eq!(fixture.dests("desktop", None), vec!["home/outputs"]);
+}
+
+#[test]
+fn test_exact_host_beats_glob() {
+    let fixture = TestFixture::new(
+        "\"host:fw-*\" = \"home/outputs-fw\"\n\"host:fw-16\" = \"home/outputs-16\"\n",
+    );
+    assert_eq!(fixture.dests("fw-16", None), vec!["home/outputs-16"]);
+    assert_eq!(fixture.dests("fw-13", None), vec!["home/outputs-fw"]);
+}
+
+#[test]
+fn test_host_targets_round_trip() {
+    let fixture = TestFixture::new("\"host:fw-*\" = \"home/outputs-fw\"\n");
+    let conf = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    let table = conf.packages["f_outputs"].to_table();
+    assert_eq!(
+        table["targets"]["host:fw-*"].as_str(),
+        Some("home/outputs-fw")
+    );
+}
@@ -1,21 +1,19 @@
 use std::{fs, path::PathBuf};
 
 use dotr::{
-    cli::{DeployUpdateArgs, ImportArgs, InitArgs, run_cli},
+    cli::{DeployUpdateArgs, ImportArgs, InitArgs, UpdateArgs, run_cli},
     config::Config,
 };
 
 mod common;
 
-const PLAYGROUND_DIR: &str = "tests/playground";
-
 struct TestFixture {
     cwd: PathBuf,
 }
 
 impl TestFixture {
     fn new() -> Self {
-        let cwd = PathBuf::from(PLAYGROUND_DIR);
+        let cwd = std::env::temp_dir().join(format!("dotr_ignore_test_{}", uuid::Uuid::new_v4()));
         common::setup(&cwd);
         Self { cwd }
     }
@@ -23,19 +21,22 @@ impl TestFixture {
     fn get_cli(&self, command: Option<dotr::cli::Command>) -> dotr::cli::Cli {
         dotr::cli::Cli {
             command,
-            working_dir: Some(PLAYGROUND_DIR.to_string()),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
         }
     }
 
     fn init(&self) {
-        run_cli(self.get_cli(Some(dotr::cli::Command::Init(InitArgs {})))).expect("Init failed");
+        run_cli(self.get_cli(Some(dotr::cli::Command::Init(InitArgs::default()))))
+            .expect("Init failed");
     }
 
     fn import(&self, path: &str) {
         run_cli(self.get_cli(Some(dotr::cli::Command::Import(ImportArgs {
-            path: path.to_string(),
+            paths: vec![path.to_string()],
             name: None,
             profile: None,
+            ..Default::default()
         }))))
         .expect("Import failed");
     }
@@ -45,18 +46,18 @@ impl TestFixture {
             self.get_cli(Some(dotr::cli::Command::Deploy(DeployUpdateArgs {
                 packages,
                 profile: None,
+                ..Default::default()
             }))),
         )
         .expect("Deploy failed");
     }
 
     fn update(&self, packages: Option<Vec<String>>) {
-        run_cli(
-            self.get_cli(Some(dotr::cli::Command::Update(DeployUpdateArgs {
-                packages,
-                profile: None,
-            }))),
-        )
+        run_cli(self.get_cli(Some(dotr::cli::Command::Update(UpdateArgs {
+            packages,
+            profile: None,
+            ..Default::default()
+        }))))
         .expect("Update failed");
     }
 
@@ -76,7 +77,6 @@ impl TestFixture {
         assert!(self.cwd.join(path).exists(), "{}", message);
     }
 
-    #[allow(dead_code)]
     fn assert_file_not_exists(&self, path: &str, message: &str) {
         assert!(!self.cwd.join(path).exists(), "{}", message);
     }
@@ -84,7 +84,7 @@ impl TestFixture {
 
 impl Drop for TestFixture {
     fn drop(&mut self) {
-        common::teardown(&self.cwd);
+        fs::remove_dir_all(&self.cwd).ok();
     }
 }
 
@@ -420,3 +420,67 @@ fn test_ignore_with_complex_glob() {
     // Verify only prod file is deployed
     fixture.assert_file_exists("src/testdir/prod_file.txt", "prod file should be deployed");
 }
+
+#[test]
+fn test_global_ignore_applies_to_every_package() {
+    let fixture = TestFixture::new();
+    fixture.init();
+
+    fixture.write_file("src/first/app.conf", "first");
+    fixture.write_file("src/first/debug.log", "log");
+    fixture.write_file("src/second/app.conf", "second");
+    fixture.write_file("src/second/nested/trace.log", "log");
+    fixture.import("src/first/");
+    fixture.import("src/second/");
+
+    let mut config = fixture.get_config();
+    config.ignore = vec!["**/*.log".to_string()];
+    config.save(&fixture.cwd).expect("Failed to save config");
+    assert_eq!(fixture.get_config().ignore, vec!["**/*.log".to_string()]);
+
+    fs::remove_dir_all(fixture.cwd.join("src/first")).unwrap();
+    fs::remove_dir_all(fixture.cwd.join("src/second")).unwrap();
+    fixture.deploy(None);
+
+    fixture.assert_file_exists("src/first/app.conf", "first app.conf should be deployed");
+    fixture.assert_file_exists("src/second/app.conf", "second app.conf should be deployed");
+    fixture.assert_file_not_exists("src/first/debug.log", "debug.log should be ignored");
+    fixture.assert_file_not_exists(
+        "src/second/nested/trace.log",
+        "nested trace.log should be ignored",
+    );
+
+    // Update skips them too
+    fixture.write_file("src/first/debug.log", "new log");
+    fixture.update(None);
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("dotfiles/d_first/debug.log")).unwrap(),
+        "log"
+    );
+}
+
+#[test]
+fn test_package_negation_overrides_global_ignore() {
+    let fixture = TestFixture::new();
+    fixture.init();
+
+    fixture.write_file("src/testdir/debug.log", "log");
+    fixture.write_file("src/testdir/keep.log", "keep");
+    fixture.import("src/testdir/");
+
+    let mut config = fixture.get_config();
+    config.ignore = vec!["*.log".to_string()];
+    config
+        .packages
+        .get_mut("d_testdir")
+        .unwrap()
+        .ignore
+        .push("!keep.log".to_string());
+    config.save(&fixture.cwd).expect("Failed to save config");
+
+    fs::remove_dir_all(fixture.cwd.join("src/testdir")).unwrap();
+    fixture.deploy(None);
+
+    fixture.assert_file_exists("src/testdir/keep.log", "keep.log should be re-included");
+    fixture.assert_file_not_exists("src/testdir/debug.log", "debug.log should be ignored");
+}
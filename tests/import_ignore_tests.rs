@@ -0,0 +1,120 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, ImportArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_import_ignore_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fixture.write_file("home/tool/config.toml", "theme = \"dark\"\n");
+        fixture.write_file("home/tool/cache/blob.bin", "0123456789");
+        fixture.write_file("home/tool/cache/nested/more.bin", "0123456789");
+        fixture.write_file("home/tool/session.lock", "1234");
+        fixture.write_file("home/tool/debug.log", "log");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn import(&self, no_ignore: bool) {
+        self.run(Command::Import(ImportArgs {
+            paths: vec![self.cwd.join("home/tool").to_str().unwrap().to_string()],
+            no_ignore,
+            ..Default::default()
+        }))
+        .expect("Import failed");
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.cwd.join(path).exists()
+    }
+
+    fn ignore_list(&self) -> Vec<String> {
+        let config = Config::from_path(&self.cwd).expect("Failed to load config");
+        config.packages["d_tool"].ignore.clone()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_dotrignore_excludes_files_on_import() {
+    let fixture = TestFixture::new();
+    fixture.write_file(".dotrignore", "cache/\n*.lock\n");
+    fixture.import(false);
+
+    assert!(fixture.exists("dotfiles/d_tool/config.toml"));
+    assert!(fixture.exists("dotfiles/d_tool/debug.log"));
+    assert!(!fixture.exists("dotfiles/d_tool/cache/blob.bin"));
+    assert!(!fixture.exists("dotfiles/d_tool/session.lock"));
+    assert_eq!(fixture.ignore_list(), vec!["cache/**", "session.lock"]);
+}
+
+#[test]
+fn test_gitignore_of_imported_dir_is_honored() {
+    let fixture = TestFixture::new();
+    fixture.write_file("home/tool/.gitignore", "*.log\n");
+    fixture.import(false);
+
+    assert!(!fixture.exists("dotfiles/d_tool/debug.log"));
+    assert!(fixture.exists("dotfiles/d_tool/.gitignore"));
+    assert!(fixture.exists("dotfiles/d_tool/cache/blob.bin"));
+    assert_eq!(fixture.ignore_list(), vec!["debug.log"]);
+}
+
+#[test]
+fn test_no_ignore_copies_everything() {
+    let fixture = TestFixture::new();
+    fixture.write_file(".dotrignore", "cache/\n");
+    fixture.import(true);
+
+    assert!(fixture.exists("dotfiles/d_tool/cache/nested/more.bin"));
+    assert!(fixture.ignore_list().is_empty());
+}
+
+#[test]
+fn test_update_keeps_excluded_files_out() {
+    let fixture = TestFixture::new();
+    fixture.write_file(".dotrignore", "cache/\n");
+    fixture.import(false);
+    // Gone from .dotrignore, the package's own ignore list still applies
+    fs::remove_file(fixture.cwd.join(".dotrignore")).unwrap();
+    fixture.write_file("home/tool/cache/new.bin", "new");
+
+    fixture
+        .run(Command::Update(UpdateArgs::default()))
+        .expect("Update failed");
+
+    assert!(!fixture.exists("dotfiles/d_tool/cache/new.bin"));
+    assert!(!fixture.exists("dotfiles/d_tool/cache/blob.bin"));
+}
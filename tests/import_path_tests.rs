@@ -0,0 +1,104 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, ImportArgs, InitArgs, run_cli},
+    config::Config,
+    package::{Package, get_package_name},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_import_path_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        run_cli(Cli {
+            command: Some(Command::Init(InitArgs::default())),
+            working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+        .expect("Init failed");
+        fs::create_dir_all(fixture.cwd.join("src/nvim/lua")).unwrap();
+        fs::write(fixture.cwd.join("src/nvim/init.lua"), "-- nvim\n").unwrap();
+        fixture
+    }
+
+    fn import(&self, path: &str) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(Command::Import(ImportArgs {
+                paths: vec![path.to_string()],
+                fail_fast: true,
+                ..Default::default()
+            })),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn package(&self, path: &str) -> Result<Package, anyhow::Error> {
+        Package::from_path(path, &None, &self.cwd)
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_trailing_slash_imports_the_same_package() {
+    let fixture = TestFixture::new();
+    let plain = fixture.package("src/nvim").expect("Import failed");
+    for typed in ["src/nvim/", "src/nvim//", "./src/nvim", "src/nvim/lua/.."] {
+        let package = fixture.package(typed).expect("Import failed");
+        assert_eq!(package.name, plain.name, "{}", typed);
+        assert_eq!(package.dest, plain.dest, "{}", typed);
+    }
+    assert_eq!(plain.name, "d_nvim");
+    assert!(!plain.dest.unwrap().ends_with('/'));
+
+    fixture.import("src/nvim/").expect("Import failed");
+    let conf = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    assert_eq!(
+        conf.packages["d_nvim"].src.as_deref(),
+        Some("dotfiles/d_nvim")
+    );
+    assert!(fixture.cwd.join("dotfiles/d_nvim/init.lua").is_file());
+}
+
+#[test]
+fn test_working_dir_and_its_parents_are_refused() {
+    let fixture = TestFixture::new();
+    for path in [".", "./", "..", "src/.."] {
+        let err = fixture.package(path).expect_err("Import should fail");
+        assert!(
+            err.to_string().contains("working directory"),
+            "{}: {}",
+            path,
+            err
+        );
+    }
+    assert!(fixture.import(".").is_err());
+}
+
+#[test]
+fn test_paths_inside_dotfiles_are_refused() {
+    let fixture = TestFixture::new();
+    fs::create_dir_all(fixture.cwd.join("dotfiles/d_tool")).unwrap();
+    for path in ["dotfiles/d_tool", "src/../dotfiles/d_tool/", "dotfiles"] {
+        let err = fixture.package(path).expect_err("Import should fail");
+        assert!(err.to_string().contains("inside dotfiles/"), "{}", err);
+    }
+}
+
+#[test]
+fn test_unnameable_path_is_an_error() {
+    let err =
+        get_package_name("/", &None, &std::env::temp_dir()).expect_err("The root has no name");
+    assert!(err.to_string().contains("--name"), "{}", err);
+}
@@ -0,0 +1,180 @@
+use std::{fs, path::PathBuf};
+
+use clap::Parser;
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, ImportArgs, InitArgs, run_cli},
+    config::Config,
+    context::Context,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_import_stdin_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    // Import `content` as if it were piped in
+    fn import(&self, content: &[u8], args: ImportArgs) -> Result<Config, anyhow::Error> {
+        let mut conf = Config::from_path(&self.cwd)?;
+        let ctx = Context::from_config(&self.cwd, &conf)?;
+        let args = ImportArgs {
+            stdin: true,
+            ..args
+        };
+        conf.import_from_reader(&mut &content[..], &args, &ctx, &None)?;
+        Config::from_path(&self.cwd)
+    }
+
+    fn dest(&self, path: &str) -> Option<String> {
+        Some(self.cwd.join(path).to_str().unwrap().to_string())
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_import_stores_the_content_as_piped() {
+    let fixture = TestFixture::new();
+    // No trailing newline, and one that isn't UTF-8
+    let content = b"apiVersion: v1\nclusters: []\n\xff";
+    let conf = fixture
+        .import(
+            content,
+            ImportArgs {
+                dest: fixture.dest("home/.kube/config"),
+                ..Default::default()
+            },
+        )
+        .expect("Import failed");
+
+    let package = &conf.packages["f_config"];
+    assert_eq!(package.src.as_deref(), Some("dotfiles/f_config"));
+    assert_eq!(package.dest, fixture.dest("home/.kube/config"));
+    assert_eq!(
+        fs::read(fixture.cwd.join("dotfiles/f_config")).unwrap(),
+        content
+    );
+    // Nothing is written to dest until deployed
+    assert!(!fixture.cwd.join("home/.kube/config").exists());
+
+    fixture
+        .run(Command::Deploy(DeployUpdateArgs::default()))
+        .expect("Deploy failed");
+    assert_eq!(
+        fs::read(fixture.cwd.join("home/.kube/config")).unwrap(),
+        content
+    );
+}
+
+#[test]
+fn test_import_uses_the_given_name() {
+    let fixture = TestFixture::new();
+    let conf = fixture
+        .import(
+            b"git_protocol: ssh\n",
+            ImportArgs {
+                dest: fixture.dest("home/.config/gh/config.yml"),
+                name: Some("gh".to_string()),
+                ..Default::default()
+            },
+        )
+        .expect("Import failed");
+    assert!(conf.packages.contains_key("f_gh"));
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("dotfiles/f_gh")).unwrap(),
+        "git_protocol: ssh\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_import_sets_the_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = TestFixture::new();
+    fixture
+        .import(
+            b"token = secret\n",
+            ImportArgs {
+                dest: fixture.dest("home/.netrc"),
+                mode: Some("600".to_string()),
+                ..Default::default()
+            },
+        )
+        .expect("Import failed");
+    let mode = fs::metadata(fixture.cwd.join("dotfiles/f_netrc"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[test]
+fn test_import_refuses_a_bad_mode() {
+    let fixture = TestFixture::new();
+    let err = fixture
+        .import(
+            b"x\n",
+            ImportArgs {
+                dest: fixture.dest("home/.netrc"),
+                mode: Some("rw".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("octal mode"), "{}", err);
+    assert!(!fixture.cwd.join("dotfiles/f_netrc").exists());
+}
+
+#[test]
+fn test_import_refuses_an_existing_package() {
+    let fixture = TestFixture::new();
+    let args = || ImportArgs {
+        dest: fixture.dest("home/.netrc"),
+        name: Some("netrc".to_string()),
+        ..Default::default()
+    };
+    fixture.import(b"one\n", args()).expect("Import failed");
+
+    let err = fixture.import(b"two\n", args()).unwrap_err().to_string();
+    assert!(err.contains("already exists"), "{}", err);
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("dotfiles/f_netrc")).unwrap(),
+        "one\n"
+    );
+}
+
+#[test]
+fn test_stdin_flags_parse() {
+    assert!(Cli::try_parse_from(["dotr", "import", "--stdin", "--dest", "~/.netrc"]).is_ok());
+    // A path and --stdin, or --stdin without a dest
+    assert!(
+        Cli::try_parse_from(["dotr", "import", "~/.bashrc", "--stdin", "--dest", "~/.x"]).is_err()
+    );
+    assert!(Cli::try_parse_from(["dotr", "import", "--stdin"]).is_err());
+    assert!(Cli::try_parse_from(["dotr", "import", "~/.bashrc", "--dest", "~/.x"]).is_err());
+    assert!(Cli::try_parse_from(["dotr", "import"]).is_err());
+}
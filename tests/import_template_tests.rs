@@ -0,0 +1,179 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, ImportArgs, InitArgs, run_cli},
+    context::Context,
+    package::substitute_values,
+};
+
+// Seeded in config.toml, so the tests never depend on the environment's USER
+const TEST_USER: &str = "dotr-test";
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_import_tpl_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+        let config = format!("banner = false\n\n[variables]\nUSER = \"{}\"\n", TEST_USER);
+        fs::write(self.cwd.join("config.toml"), config).expect("Failed to write");
+    }
+
+    fn import(&self, path: &str, name: &str, substitute: Vec<String>) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Import(ImportArgs {
+            paths: vec![self.cwd.join(path).to_str().unwrap().to_string()],
+            name: Some(name.to_string()),
+            as_template: true,
+            substitute,
+            ..Default::default()
+        }))))
+    }
+
+    fn write_file(&self, path: &str, content: &[u8]) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+
+    fn variable(&self, name: &str) -> String {
+        let ctx = Context::new(&self.cwd).expect("Failed to create context");
+        ctx.lookup_variable(name)
+            .and_then(|v| v.as_str().map(String::from))
+            .unwrap_or_else(|| panic!("{} must be set for this test", name))
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+fn pairs(items: &[(&str, &str)]) -> Vec<(String, String)> {
+    items
+        .iter()
+        .map(|(v, p)| (v.to_string(), p.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_substitute_prefers_longest_value() {
+    let substitutions = pairs(&[("alice", "{{ USER }}"), ("/home/alice", "{{ HOME }}")]);
+    let (result, count) = substitute_values("dir=/home/alice/bin owner=alice", &substitutions);
+    assert_eq!(result, "dir={{ HOME }}/bin owner={{ USER }}");
+    assert_eq!(count, 2);
+}
+
+#[test]
+fn test_substitute_respects_word_boundaries() {
+    let substitutions = pairs(&[("al", "{{ USER }}")]);
+    let (result, count) = substitute_values("al also val al_x al.", &substitutions);
+    assert_eq!(result, "{{ USER }} also val al_x {{ USER }}.");
+    assert_eq!(count, 2);
+}
+
+#[test]
+fn test_import_as_template_rewrites_only_the_copy() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    let home = fixture.variable("HOME");
+    let original = format!("cache = {}/.cache\nowner = {}\n", home, TEST_USER);
+    fixture.write_file("app/app.conf", original.as_bytes());
+    fixture.write_file("app/notes.txt", b"nothing personal here\n");
+
+    fixture
+        .import("app", "app", Vec::new())
+        .expect("Import failed");
+
+    assert_eq!(
+        fixture.read_file("dotfiles/d_app/app.conf"),
+        "cache = {{ HOME }}/.cache\nowner = {{ USER }}\n"
+    );
+    assert_eq!(
+        fixture.read_file("dotfiles/d_app/notes.txt"),
+        "nothing personal here\n"
+    );
+    assert_eq!(
+        fixture.read_file("app/app.conf"),
+        original,
+        "The original must never be modified"
+    );
+
+    // Deploying renders it back to what it was
+    run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))))
+        .expect("Deploy failed");
+    assert_eq!(fixture.read_file("app/app.conf"), original);
+}
+
+#[test]
+fn test_import_as_template_with_extra_substitution() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file(
+        "config.toml",
+        b"banner = false\n\n[variables]\nEMAIL = \"me@example.com\"\n",
+    );
+    fixture.write_file("gitconfig", b"[user]\n  email = me@example.com\n");
+
+    fixture
+        .import("gitconfig", "gitconfig", vec!["EMAIL".to_string()])
+        .expect("Import failed");
+
+    assert_eq!(
+        fixture.read_file("dotfiles/f_gitconfig"),
+        "[user]\n  email = {{ EMAIL }}\n"
+    );
+}
+
+#[test]
+fn test_import_as_template_unknown_variable() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("rc", b"hello\n");
+
+    let err = fixture
+        .import("rc", "rc", vec!["NOT_A_VARIABLE_ANYWHERE".to_string()])
+        .expect_err("Unknown variables can't be substituted");
+    assert!(err.to_string().contains("NOT_A_VARIABLE_ANYWHERE"));
+}
+
+#[test]
+fn test_import_as_template_skips_binary_files() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    let home = fixture.variable("HOME");
+    let mut binary = vec![0xff, 0xfe, 0x00];
+    binary.extend_from_slice(home.as_bytes());
+    fixture.write_file("blob/data.bin", &binary);
+
+    fixture
+        .import("blob", "blob", Vec::new())
+        .expect("Import failed");
+
+    assert_eq!(
+        fs::read(fixture.cwd.join("dotfiles/d_blob/data.bin")).unwrap(),
+        binary
+    );
+}
@@ -0,0 +1,226 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, ImportArgs, InitArgs, run_cli},
+    config::Config,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_include_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fixture.write_file("config.toml", CONFIG);
+        fixture.write_file("packages/shell.toml", SHELL);
+        fixture.write_file("packages/editors.toml", EDITORS);
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn import(&self, path: &str, into: Option<&str>) -> Result<(), anyhow::Error> {
+        self.write_file(path, "content\n");
+        self.run(Command::Import(ImportArgs {
+            paths: vec![self.cwd.join(path).to_str().unwrap().to_string()],
+            into: into.map(|s| s.to_string()),
+            ..Default::default()
+        }))
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+
+    fn read_table(&self, path: &str) -> toml::Table {
+        self.read_file(path).parse().expect("Failed to parse TOML")
+    }
+
+    fn config(&self) -> Result<Config, anyhow::Error> {
+        Config::from_path(&self.cwd)
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+include = ["packages/*.toml"]
+
+[variables]
+EDITOR = "vim"
+
+[packages.f_gitconfig]
+src = "dotfiles/f_gitconfig"
+dest = "gitconfig"
+"#;
+
+// Written by hand, with keys dotr would write differently
+const SHELL: &str = r#"# Shell packages
+[packages.f_zshrc]
+src = "dotfiles/f_zshrc"
+dest = "zshrc"
+
+[profiles.work]
+dependencies = ["f_zshrc"]
+"#;
+
+const EDITORS: &str = r#"
+[packages.d_nvim]
+src = "dotfiles/d_nvim"
+dest = "nvim"
+
+[variables]
+THEME = "dark"
+"#;
+
+#[test]
+fn test_fragments_are_merged() {
+    let fixture = TestFixture::new();
+    let config = fixture.config().expect("Failed to load config");
+
+    for name in ["f_gitconfig", "f_zshrc", "d_nvim"] {
+        assert!(config.packages.contains_key(name), "{} missing", name);
+    }
+    assert!(config.profiles.contains_key("work"));
+    assert_eq!(
+        config.variables.get("THEME").and_then(|v| v.as_str()),
+        Some("dark")
+    );
+    assert_eq!(
+        config.package_origin("f_zshrc"),
+        Some(PathBuf::from("packages/shell.toml").as_path())
+    );
+    assert_eq!(config.package_origin("f_gitconfig"), None);
+}
+
+#[test]
+fn test_save_leaves_fragments_untouched() {
+    let fixture = TestFixture::new();
+    let config = fixture.config().expect("Failed to load config");
+    config.save(&fixture.cwd).expect("Failed to save config");
+
+    assert_eq!(fixture.read_file("packages/shell.toml"), SHELL);
+    assert_eq!(fixture.read_file("packages/editors.toml"), EDITORS);
+    let main = fixture.read_table("config.toml");
+    let packages = main["packages"].as_table().unwrap();
+    assert_eq!(packages.keys().collect::<Vec<_>>(), vec!["f_gitconfig"]);
+    assert!(!main.contains_key("profiles"));
+    assert!(!main["variables"].as_table().unwrap().contains_key("THEME"));
+    assert_eq!(
+        main["include"].as_array().unwrap()[0].as_str(),
+        Some("packages/*.toml")
+    );
+
+    let reloaded = fixture.config().expect("Failed to reload config");
+    assert_eq!(reloaded.packages.len(), 3);
+}
+
+#[test]
+fn test_import_writes_to_main_file_or_into() {
+    let fixture = TestFixture::new();
+    fixture.import("home/.bashrc", None).expect("Import failed");
+    fixture
+        .import("home/.tmux.conf", Some("packages/shell.toml"))
+        .expect("Import --into failed");
+
+    let main = fixture.read_table("config.toml");
+    let packages = main["packages"].as_table().unwrap();
+    assert!(packages.contains_key("f_bashrc"));
+    assert!(!packages.contains_key("f_tmux_conf"));
+    let shell = fixture.read_table("packages/shell.toml");
+    let shell_packages = shell["packages"].as_table().unwrap();
+    assert!(shell_packages.contains_key("f_tmux_conf"));
+    assert!(shell_packages.contains_key("f_zshrc"));
+    assert!(shell.contains_key("profiles"));
+    assert_eq!(fixture.read_file("packages/editors.toml"), EDITORS);
+
+    let config = fixture.config().expect("Failed to reload config");
+    assert_eq!(config.packages.len(), 5);
+    assert_eq!(
+        config.package_origin("f_tmux_conf"),
+        Some(PathBuf::from("packages/shell.toml").as_path())
+    );
+}
+
+#[test]
+fn test_into_must_be_included() {
+    let fixture = TestFixture::new();
+    let err = fixture
+        .import("home/.bashrc", Some("other/extra.toml"))
+        .expect_err("A file outside the include patterns should be refused");
+    assert!(err.to_string().contains("include pattern"), "{}", err);
+}
+
+#[test]
+fn test_duplicate_package_names_both_files() {
+    let fixture = TestFixture::new();
+    fixture.write_file(
+        "packages/zz_more.toml",
+        "[packages.f_zshrc]\nsrc = \"dotfiles/f_zshrc\"\ndest = \"zshrc2\"\n",
+    );
+    let err = fixture
+        .config()
+        .expect_err("A package defined twice should fail");
+    let message = err.to_string();
+    assert!(message.contains("packages/shell.toml"), "{}", message);
+    assert!(message.contains("packages/zz_more.toml"), "{}", message);
+
+    fs::remove_file(fixture.cwd.join("packages/zz_more.toml")).unwrap();
+    fixture.write_file(
+        "packages/zz_more.toml",
+        "[packages.f_gitconfig]\nsrc = \"dotfiles/x\"\ndest = \"x\"\n",
+    );
+    let err = fixture
+        .config()
+        .expect_err("A package also in config.toml should fail");
+    assert!(err.to_string().contains("config.toml"), "{}", err);
+}
+
+#[test]
+fn test_fragments_only_hold_known_sections() {
+    let fixture = TestFixture::new();
+    fixture.write_file("packages/bad.toml", "shell = \"zsh\"\n");
+    let err = fixture
+        .config()
+        .expect_err("Top-level settings in a fragment should fail");
+    assert!(err.to_string().contains("packages/bad.toml"), "{}", err);
+}
+
+#[test]
+fn test_missing_literal_include_fails() {
+    let fixture = TestFixture::new();
+    fixture.write_file(
+        "config.toml",
+        &CONFIG.replace("packages/*.toml", "packages/missing.toml"),
+    );
+    let err = fixture
+        .config()
+        .expect_err("A missing included file should fail");
+    assert!(err.to_string().contains("packages/missing.toml"), "{}", err);
+}
@@ -0,0 +1,126 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, InitArgs, run_cli},
+    config::{Config, presets::InitPreset},
+    context::Context,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_init_preset_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn init(&self, preset: InitPreset) {
+        run_cli(Cli {
+            command: Some(Command::Init(InitArgs { preset })),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+        .expect("Init failed");
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).unwrap()
+    }
+
+    fn config(&self) -> Config {
+        Config::from_path(&self.cwd).expect("Failed to load config")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_minimal_is_an_empty_config() {
+    let fixture = TestFixture::new();
+    fixture.init(InitPreset::Minimal);
+    let conf = fixture.config();
+    assert!(conf.profiles.is_empty());
+    assert!(conf.packages.is_empty());
+    assert!(fixture.cwd.join("templates").is_dir());
+    assert!(!fixture.cwd.join("README.md").exists());
+    assert!(!fixture.read(".gitignore").contains('#'));
+}
+
+#[test]
+fn test_standard_adds_profiles_and_a_readme() {
+    let fixture = TestFixture::new();
+    fixture.init(InitPreset::Standard);
+    let conf = fixture.config();
+    let mut profiles: Vec<&String> = conf.profiles.keys().collect();
+    profiles.sort();
+    assert_eq!(profiles, vec!["home", "work"]);
+    assert!(conf.packages.is_empty());
+
+    let config = fixture.read("config.toml");
+    assert!(config.contains("# [packages.d_nvim]\n"), "{}", config);
+    assert!(
+        config.contains("# src = \"dotfiles/d_nvim\"\n"),
+        "{}",
+        config
+    );
+    let readme = fixture.read("README.md");
+    assert!(
+        readme.contains("`dotr deploy --profile work`"),
+        "{}",
+        readme
+    );
+    assert!(!fixture.cwd.join("dotfiles/example").exists());
+}
+
+#[test]
+fn test_full_adds_a_templated_sample() {
+    let fixture = TestFixture::new();
+    fixture.init(InitPreset::Full);
+    let conf = fixture.config();
+    let package = &conf.packages["d_example"];
+    assert_eq!(package.src.as_deref(), Some("dotfiles/example"));
+    assert_eq!(package.variables["greeting"].as_str(), Some("Hello"));
+    assert!(package.prompts.contains_key("name"));
+
+    let mut ctx = Context::from_config(&fixture.cwd, &conf).expect("Failed to create context");
+    let mut answers = toml::Table::new();
+    answers.insert("name".to_string(), toml::Value::String("Ada".to_string()));
+    ctx.set_user_variables(answers);
+    assert_eq!(package.check_templates(&ctx).expect("Render failed"), 1);
+
+    let gitignore = fixture.read(".gitignore");
+    for pattern in [".uservariables.toml\n", ".dotr/\n"] {
+        assert!(gitignore.contains(pattern), "{}", gitignore);
+    }
+    assert!(gitignore.starts_with("# "), "{}", gitignore);
+}
+
+#[test]
+fn test_preset_never_overwrites_an_existing_repo() {
+    let fixture = TestFixture::new();
+    fixture.init(InitPreset::Minimal);
+    fs::write(fixture.cwd.join("README.md"), "mine\n").unwrap();
+    let config = fixture.read("config.toml");
+
+    fixture.init(InitPreset::Full);
+    assert_eq!(fixture.read("config.toml"), config);
+    assert_eq!(fixture.read("README.md"), "mine\n");
+    assert!(!fixture.cwd.join("dotfiles/example").exists());
+}
+
+#[test]
+fn test_preset_keeps_an_existing_readme() {
+    let fixture = TestFixture::new();
+    fs::write(fixture.cwd.join("README.md"), "mine\n").unwrap();
+    fixture.init(InitPreset::Standard);
+    assert_eq!(fixture.read("README.md"), "mine\n");
+    assert_eq!(fixture.config().profiles.len(), 2);
+}
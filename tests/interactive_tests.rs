@@ -0,0 +1,174 @@
+use std::{fs, io::IsTerminal, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    config::Config,
+    package::Package,
+    utils::set_prompt_answers,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_interactive_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+
+    fn deploy(&self, interactive: bool, no_cache: bool) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs {
+            interactive,
+            no_cache,
+            ..Default::default()
+        }))))
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+
+    fn add_package(&self, name: &str, dest: &str) {
+        let mut config = Config::from_path(&self.cwd).expect("Failed to load config");
+        let mut table = toml::Table::new();
+        table.insert(
+            "src".to_string(),
+            toml::Value::String(format!("dotfiles/{}", name)),
+        );
+        table.insert(
+            "dest".to_string(),
+            toml::Value::String(self.cwd.join(dest).to_str().unwrap().to_string()),
+        );
+        let package = Package::from_table(name, &table).expect("Failed to build package");
+        config.packages.insert(name.to_string(), package);
+        config.save(&self.cwd).expect("Failed to save config");
+    }
+
+    // Deployed once, then edited on both sides
+    fn setup_conflict(&self, src: &str) {
+        self.init();
+        self.write_file("dotfiles/f_app", "original\n");
+        self.add_package("f_app", "home/app.conf");
+        self.deploy(false, false).expect("Deploy failed");
+        self.write_file("home/app.conf", "local edit\n");
+        self.write_file("dotfiles/f_app", src);
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.cwd);
+    }
+}
+
+fn answers(list: &[&str]) {
+    set_prompt_answers(list.iter().map(|a| a.to_string()).collect());
+}
+
+#[test]
+fn test_keep_local() {
+    let fixture = TestFixture::new();
+    fixture.setup_conflict("from repo\n");
+
+    answers(&["k"]);
+    fixture.deploy(true, false).expect("Deploy failed");
+    assert_eq!(fixture.read_file("home/app.conf"), "local edit\n");
+    assert_eq!(fixture.read_file("dotfiles/f_app"), "from repo\n");
+}
+
+#[test]
+fn test_diff_then_overwrite() {
+    let fixture = TestFixture::new();
+    fixture.setup_conflict("from repo\n");
+
+    answers(&["d", "o"]);
+    fixture.deploy(true, false).expect("Deploy failed");
+    assert_eq!(fixture.read_file("home/app.conf"), "from repo\n");
+}
+
+#[test]
+fn test_merge_into_source() {
+    let fixture = TestFixture::new();
+    fixture.setup_conflict("from repo\n");
+
+    answers(&["m"]);
+    fixture.deploy(true, false).expect("Deploy failed");
+    assert_eq!(fixture.read_file("dotfiles/f_app"), "local edit\n");
+    assert_eq!(fixture.read_file("home/app.conf"), "local edit\n");
+}
+
+#[test]
+fn test_templated_source_cannot_be_merged() {
+    let fixture = TestFixture::new();
+    fixture.setup_conflict("home = {{ HOME }}\n");
+
+    answers(&["m", "o"]);
+    fixture.deploy(true, false).expect("Deploy failed");
+    assert_eq!(fixture.read_file("dotfiles/f_app"), "home = {{ HOME }}\n");
+    assert!(fixture.read_file("home/app.conf").starts_with("home = /"));
+}
+
+#[test]
+fn test_untouched_dest_is_not_a_conflict() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("dotfiles/f_app", "original\n");
+    fixture.add_package("f_app", "home/app.conf");
+    fixture.deploy(false, false).expect("Deploy failed");
+    fixture.write_file("dotfiles/f_app", "from repo\n");
+
+    // Any prompt would fail for lack of answers
+    answers(&[]);
+    fixture.deploy(true, false).expect("Deploy should not ask");
+    assert_eq!(fixture.read_file("home/app.conf"), "from repo\n");
+}
+
+#[test]
+fn test_without_cache_any_difference_asks() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("dotfiles/f_app", "from repo\n");
+    fixture.write_file("home/app.conf", "local\n");
+    fixture.add_package("f_app", "home/app.conf");
+
+    answers(&["x", "k"]);
+    fixture.deploy(true, true).expect("Deploy failed");
+    assert_eq!(fixture.read_file("home/app.conf"), "local\n");
+}
+
+#[test]
+fn test_interactive_without_terminal_fails() {
+    if std::io::stdin().is_terminal() {
+        return;
+    }
+    let fixture = TestFixture::new();
+    fixture.init();
+
+    let err = fixture
+        .deploy(true, false)
+        .expect_err("Deploy should refuse to ask without a terminal");
+    assert!(err.to_string().contains("--interactive needs a terminal"));
+}
@@ -0,0 +1,170 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+    context::Context,
+    utils::ExitCodeError,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_interrupt_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            "banner = false\n\n[packages.d_app]\nsrc = \"dotfiles/app\"\ndest = \"home/app\"\nfile_mode = \"0600\"\npre_actions = [\"touch pre_ran\"]\npost_actions = [\"touch post_ran\"]\n",
+        )
+        .expect("Failed to write config");
+        fs::create_dir_all(fixture.cwd.join("dotfiles/app")).unwrap();
+        for name in ["a.conf", "b.conf"] {
+            fs::write(fixture.cwd.join("dotfiles/app").join(name), name).unwrap();
+        }
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn interrupted_context(&self, conf: &Config) -> Context {
+        let ctx = Context::from_config(&self.cwd, conf).expect("Failed to create context");
+        ctx.interrupt();
+        ctx
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+fn assert_interrupted(err: anyhow::Error, message: &str) {
+    let exit = err
+        .downcast_ref::<ExitCodeError>()
+        .expect("An interrupt carries its exit code");
+    assert_eq!(exit.code, 130);
+    assert_eq!(exit.message, message);
+}
+
+#[test]
+fn test_interrupted_deploy_stops_before_the_next_file() {
+    let fixture = TestFixture::new();
+    let conf = Config::from_path(&fixture.cwd).unwrap();
+    let ctx = fixture.interrupted_context(&conf);
+    let report = conf
+        .deploy_packages(&ctx, &DeployUpdateArgs::default())
+        .expect("Deploy runs");
+    assert_interrupted(
+        report.into_result().expect_err("Deploy was interrupted"),
+        "Interrupted after 0 file(s) of package 'd_app'",
+    );
+    assert!(!fixture.cwd.join("home/app/a.conf").exists());
+    assert!(!fixture.cwd.join("pre_ran").exists());
+    assert!(!fixture.cwd.join("post_ran").exists());
+}
+
+#[test]
+fn test_interrupted_update_stops_before_the_next_file() {
+    let fixture = TestFixture::new();
+    fixture
+        .run(Command::Deploy(DeployUpdateArgs::default()))
+        .expect("Deploy failed");
+    fs::write(fixture.cwd.join("home/app/a.conf"), "edited").unwrap();
+    let conf = Config::from_path(&fixture.cwd).unwrap();
+    let ctx = fixture.interrupted_context(&conf);
+    let report = conf
+        .backup_packages(&ctx, &UpdateArgs::default())
+        .expect("Update runs");
+    assert_interrupted(
+        report.into_result().expect_err("Update was interrupted"),
+        "Interrupted after 0 file(s) of package 'd_app'",
+    );
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("dotfiles/app/a.conf")).unwrap(),
+        "a.conf"
+    );
+}
+
+#[test]
+fn test_files_are_replaced_whole() {
+    let fixture = TestFixture::new();
+    fs::create_dir_all(fixture.cwd.join("home/app")).unwrap();
+    fs::write(fixture.cwd.join("home/app/a.conf"), "old").unwrap();
+    fixture
+        .run(Command::Deploy(DeployUpdateArgs::default()))
+        .expect("Deploy failed");
+
+    let names: Vec<String> = fs::read_dir(fixture.cwd.join("home/app"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    assert!(
+        names.iter().all(|name| !name.contains("dotr-tmp")),
+        "{:?}",
+        names
+    );
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("home/app/a.conf")).unwrap(),
+        "a.conf"
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(fixture.cwd.join("home/app/a.conf"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ctrl_c_outside_a_walk_exits_right_away() {
+    use std::{
+        process::Command as Process,
+        time::{Duration, Instant},
+    };
+
+    use dotr::lock::WorkdirLock;
+
+    let fixture = TestFixture::new();
+    // Waiting for the lock never checks the flag
+    let _lock = WorkdirLock::acquire(&fixture.cwd, Duration::ZERO).expect("Lock failed");
+    let mut child = Process::new(env!("CARGO_BIN_EXE_dotr"))
+        .args(["-w", fixture.cwd.to_str().unwrap(), "--no-progress"])
+        .args(["deploy", "--lock-timeout", "30"])
+        .spawn()
+        .expect("Failed to run dotr");
+    std::thread::sleep(Duration::from_millis(500));
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGINT);
+    }
+    let sent = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if sent.elapsed() > Duration::from_secs(5) {
+            child.kill().ok();
+            panic!("dotr kept running after Ctrl-C");
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    assert_eq!(status.code(), Some(130));
+}
@@ -0,0 +1,87 @@
+use dotr::config::Config;
+
+fn load(config: &str) -> Result<Config, anyhow::Error> {
+    Config::from_table(&toml::from_str(config).expect("Config is not TOML"))
+}
+
+#[test]
+fn test_every_bad_package_is_reported() {
+    let err = load(
+        r#"
+[packages.d_nvim]
+src = "dotfiles/d_nvim"
+dest = "~/.config/nvim"
+dependencies = 42
+
+[packages.f_good]
+src = "dotfiles/f_good"
+dest = "~/.good"
+
+[packages.f_tmux]
+src = "dotfiles/f_tmux"
+"#,
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(err.starts_with("2 invalid entries"), "{}", err);
+    assert!(
+        err.contains("package 'd_nvim': The 'dependencies' field must be an array"),
+        "{}",
+        err
+    );
+    assert!(
+        err.contains("package 'f_tmux' has src but no dest"),
+        "{}",
+        err
+    );
+    assert!(!err.contains("f_good"), "{}", err);
+}
+
+#[test]
+fn test_bad_profiles_are_reported_with_packages() {
+    let err = load(
+        r#"
+[packages.f_bad]
+src = "dotfiles/f_bad"
+dest = "~/.bad"
+merge = "ini"
+
+[profiles.work]
+dependencies = 42
+"#,
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(
+        err.contains("package 'f_bad': Unknown merge format 'ini'"),
+        "{}",
+        err
+    );
+    assert!(
+        err.contains("profile 'work' dependencies must be an array"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_single_bad_package_names_it() {
+    let err = load("[packages.f_bad]\nsrc = \"dotfiles/f_bad\"\ndest = 42\n")
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        err,
+        "Invalid configuration: package 'f_bad': Package dest must be a string"
+    );
+}
+
+#[test]
+fn test_package_that_is_not_a_table() {
+    let err = load("[packages]\nf_bad = \"dotfiles/f_bad\"\n")
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        err,
+        "Invalid configuration: package 'f_bad' must be a table"
+    );
+}
@@ -0,0 +1,215 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command as Process,
+    time::{Duration, SystemTime},
+};
+
+use dotr::report::output::hunks;
+use serde_json::Value;
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_json_output_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture.dotr(&["init"]);
+        fs::write(fixture.cwd.join("config.toml"), CONFIG).expect("Failed to write config");
+        fixture.write("dotfiles/f_rc", &numbered(12, &[]));
+        fixture.write("dotfiles/d_nvim/init.lua", "-- nvim\n");
+        fixture.write("dotfiles/d_nvim/old.lua", "-- old\n");
+        fixture.dotr(&["deploy"]);
+        // Drift: two separate edits, a deleted file and an edited dest
+        fixture.write("dotfiles/f_rc", &numbered(12, &[2, 11]));
+        fs::File::options()
+            .write(true)
+            .open(fixture.cwd.join("dotfiles/f_rc"))
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(3600))
+            .unwrap();
+        fs::remove_file(fixture.cwd.join("dotfiles/d_nvim/old.lua")).unwrap();
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            CONFIG.replace("[groups]", "removed = [\"old.lua\"]\n\n[groups]"),
+        )
+        .unwrap();
+        fixture.write("home/.config/nvim/init.lua", "-- edited\n");
+        fixture
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn dotr(&self, args: &[&str]) -> (String, String) {
+        let output = Process::new(env!("CARGO_BIN_EXE_dotr"))
+            .args(["-w", self.cwd.to_str().unwrap(), "--no-progress"])
+            .args(args)
+            .env_remove("DOTR_PROFILE")
+            .output()
+            .expect("Failed to run dotr");
+        (
+            String::from_utf8_lossy(&output.stdout).to_string(),
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        )
+    }
+
+    /// The JSON a command printed, with what changes from run to run replaced
+    /// by placeholders.
+    fn json(&self, args: &[&str]) -> Value {
+        let (stdout, stderr) = self.dotr(args);
+        let mut value: Value = serde_json::from_str(&stdout)
+            .unwrap_or_else(|e| panic!("Not JSON ({}):\n{}\n{}", e, stdout, stderr));
+        normalize(&mut value, &self.cwd);
+        value
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+// The banner is on, to check it stays out of the document
+const CONFIG: &str = r#"
+banner = true
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "home/.rc"
+
+[packages.d_nvim]
+src = "dotfiles/d_nvim"
+dest = "home/.config/nvim"
+
+[groups]
+editors = ["d_nvim", "f_vim"]
+"#;
+
+// Lines "line 1" to "line n", those in `changed` upper case
+fn numbered(n: usize, changed: &[usize]) -> String {
+    (1..=n)
+        .map(|i| {
+            if changed.contains(&i) {
+                format!("LINE {}\n", i)
+            } else {
+                format!("line {}\n", i)
+            }
+        })
+        .collect()
+}
+
+fn normalize(value: &mut Value, cwd: &Path) {
+    match value {
+        Value::String(s) => *s = s.replace(cwd.to_str().unwrap(), "<WORKDIR>"),
+        Value::Array(items) => items.iter_mut().for_each(|item| normalize(item, cwd)),
+        Value::Object(map) => {
+            for (key, item) in map.iter_mut() {
+                match key.as_str() {
+                    "version" => *item = Value::from("<VERSION>"),
+                    "at" => *item = Value::from(0),
+                    _ => normalize(item, cwd),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Compare with tests/golden/<name>, writing it instead when
+/// DOTR_UPDATE_GOLDEN is set.
+fn assert_golden(name: &str, value: &Value) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name);
+    let actual = serde_json::to_string_pretty(value).unwrap() + "\n";
+    if std::env::var_os("DOTR_UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).expect("Failed to read golden file");
+    assert_eq!(actual, expected, "{} changed shape", name);
+}
+
+#[test]
+fn test_diff_json_matches_golden() {
+    let fixture = TestFixture::new();
+    assert_golden("diff.json", &fixture.json(&["diff", "--output", "json"]));
+}
+
+#[test]
+fn test_status_json_matches_golden() {
+    let fixture = TestFixture::new();
+    assert_golden(
+        "status.json",
+        &fixture.json(&["status", "--output", "json"]),
+    );
+}
+
+#[test]
+fn test_list_json_matches_golden() {
+    let fixture = TestFixture::new();
+    assert_golden("list.json", &fixture.json(&["list", "--output", "json"]));
+    assert_golden(
+        "list_groups.json",
+        &fixture.json(&["list", "--groups", "--output", "json"]),
+    );
+}
+
+#[test]
+fn test_verify_json_matches_golden() {
+    let fixture = TestFixture::new();
+    assert_golden(
+        "verify.json",
+        &fixture.json(&["verify", "--output", "json"]),
+    );
+}
+
+#[test]
+fn test_json_stdout_is_only_the_document() {
+    let fixture = TestFixture::new();
+    let (stdout, stderr) = fixture.dotr(&["-v", "diff", "--output", "json"]);
+    assert!(stdout.trim_start().starts_with('{'), "{}", stdout);
+    assert!(stdout.trim_end().ends_with('}'), "{}", stdout);
+    assert!(!stdout.contains('\x1b'), "{}", stdout);
+    assert!(!stdout.contains("██"), "{}", stdout);
+    // The log still reaches stderr
+    assert!(stderr.contains("Checking differences"), "{}", stderr);
+
+    // The banner goes to stderr too, even without --output json
+    let (stdout, stderr) = fixture.dotr(&["diff"]);
+    assert!(!stdout.contains("██"), "{}", stdout);
+    assert!(stderr.contains("██"), "{}", stderr);
+}
+
+#[test]
+fn test_hunks_follow_unified_diff_numbering() {
+    let old = numbered(20, &[]);
+    let new = numbered(20, &[5, 8, 18]);
+    let found = hunks(&old, &new);
+    // 5 and 8 are close enough to share their context, 18 is not
+    assert_eq!(found.len(), 2);
+    assert_eq!(
+        (found[0].old_start, found[0].old_lines),
+        (2, 10),
+        "{:?}",
+        found[0]
+    );
+    assert_eq!(found[0].lines.first().unwrap(), " line 2");
+    assert_eq!(found[0].lines.last().unwrap(), " line 11");
+    assert_eq!((found[1].new_start, found[1].new_lines), (15, 6));
+
+    let added = hunks("", "one\n");
+    assert_eq!(added.len(), 1);
+    assert_eq!((added[0].old_start, added[0].old_lines), (0, 0));
+    assert_eq!((added[0].new_start, added[0].new_lines), (1, 1));
+    assert!(hunks("same\n", "same\n").is_empty());
+}
@@ -0,0 +1,124 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{CleanArgs, Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+    package::KEEP_FILE,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(package: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_keep_dirs_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            format!(
+                "banner = false\n\n[packages.d_app]\nsrc = \"dotfiles/app\"\ndest = \"home/app\"\n{}\n",
+                package
+            ),
+        )
+        .expect("Failed to write config");
+        fs::create_dir_all(fixture.cwd.join("dotfiles/app")).unwrap();
+        fs::write(fixture.cwd.join("dotfiles/app/config"), "setting = 1\n").unwrap();
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self) {
+        self.run(Command::Deploy(DeployUpdateArgs::default()))
+            .expect("Deploy failed");
+    }
+
+    fn update(&self) {
+        self.run(Command::Update(UpdateArgs::default()))
+            .expect("Update failed");
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        self.cwd.join(path)
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_empty_dir_round_trips() {
+    let fixture = TestFixture::new("");
+    fixture.deploy();
+    fs::create_dir_all(fixture.path("home/app/cache")).unwrap();
+    fixture.update();
+    assert!(fixture.path("dotfiles/app/cache").join(KEEP_FILE).is_file());
+
+    // A fresh machine only has what git tracked
+    fs::remove_dir_all(fixture.path("home")).unwrap();
+    fixture.deploy();
+    assert!(fixture.path("home/app/cache").is_dir());
+    assert!(!fixture.path("home/app/cache").join(KEEP_FILE).exists());
+    assert_eq!(
+        fs::read_dir(fixture.path("home/app/cache"))
+            .unwrap()
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn test_keep_dirs_are_created_and_left_alone() {
+    let fixture = TestFixture::new("prune_dest = true\nkeep_dirs = [\"logs\", \"state/db\"]");
+    fixture.deploy();
+    assert!(fixture.path("home/app/logs").is_dir());
+    assert!(fixture.path("home/app/state/db").is_dir());
+
+    // Their contents belong to the app: not pruned, not copied back
+    fs::write(fixture.path("home/app/logs/app.log"), "started\n").unwrap();
+    fixture.deploy();
+    assert!(fixture.path("home/app/logs/app.log").exists());
+    fixture.update();
+    assert!(fixture.path("dotfiles/app/logs").join(KEEP_FILE).is_file());
+    assert!(!fixture.path("dotfiles/app/logs/app.log").exists());
+}
+
+#[test]
+fn test_clean_removes_keep_dirs_once_empty() {
+    let fixture = TestFixture::new("keep_dirs = [\"logs\"]");
+    fixture.deploy();
+    fixture
+        .run(Command::Clean(CleanArgs::default()))
+        .expect("Clean failed");
+    assert!(!fixture.path("home/app").exists());
+
+    fixture.deploy();
+    fs::write(fixture.path("home/app/logs/app.log"), "started\n").unwrap();
+    fixture
+        .run(Command::Clean(CleanArgs::default()))
+        .expect("Clean failed");
+    assert!(fixture.path("home/app/logs/app.log").exists());
+    assert!(!fixture.path("home/app/config").exists());
+}
+
+#[test]
+fn test_keep_dirs_must_stay_inside_dest() {
+    let fixture = TestFixture::new("keep_dirs = [\"../outside\"]");
+    let err = Config::from_path(&fixture.cwd).expect_err("A path leaving dest should fail");
+    assert!(err.to_string().contains("keep_dirs"), "{}", err);
+}
@@ -0,0 +1,154 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(host: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_layered_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fixture.write_file("config.toml", &CONFIG.replace("laptop", host));
+        fixture.write_file("dotfiles/alacritty/common/font.toml", "size = 11\n");
+        fixture.write_file(
+            "dotfiles/alacritty/common/colors.toml",
+            "theme = \"light\"\n",
+        );
+        fixture.write_file(
+            "dotfiles/alacritty/laptop/colors.toml",
+            "theme = \"dark\"\n",
+        );
+        fixture.write_file("dotfiles/alacritty/laptop/keys.toml", "copy = \"C-c\"\n");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[variables]
+host = "laptop"
+
+[packages.d_alacritty]
+src = ["dotfiles/alacritty/common", "dotfiles/alacritty/{{ host }}"]
+dest = "alacritty"
+"#;
+
+#[test]
+fn test_later_sources_overlay_earlier_ones() {
+    let fixture = TestFixture::new("laptop");
+    fixture
+        .run(Command::Deploy(DeployUpdateArgs::default()))
+        .expect("Deploy failed");
+
+    assert_eq!(fixture.read_file("alacritty/font.toml"), "size = 11\n");
+    assert_eq!(
+        fixture.read_file("alacritty/colors.toml"),
+        "theme = \"dark\"\n"
+    );
+    assert_eq!(fixture.read_file("alacritty/keys.toml"), "copy = \"C-c\"\n");
+}
+
+#[test]
+fn test_missing_overlay_is_skipped() {
+    let fixture = TestFixture::new("desktop");
+    fixture
+        .run(Command::Deploy(DeployUpdateArgs::default()))
+        .expect("Deploy failed");
+
+    assert_eq!(
+        fixture.read_file("alacritty/colors.toml"),
+        "theme = \"light\"\n"
+    );
+    assert!(!fixture.cwd.join("alacritty/keys.toml").exists());
+}
+
+#[test]
+fn test_no_files_in_any_source_fails() {
+    let fixture = TestFixture::new("desktop");
+    fs::remove_dir_all(fixture.cwd.join("dotfiles/alacritty/common")).unwrap();
+    let err = fixture
+        .run(Command::Deploy(DeployUpdateArgs::default()))
+        .expect_err("An empty merged set should fail");
+    assert!(err.to_string().contains("no files"), "{}", err);
+}
+
+#[test]
+fn test_update_writes_back_to_the_owning_source() {
+    let fixture = TestFixture::new("laptop");
+    fixture
+        .run(Command::Deploy(DeployUpdateArgs::default()))
+        .expect("Deploy failed");
+    fixture.write_file("alacritty/font.toml", "size = 13\n");
+    fixture.write_file("alacritty/colors.toml", "theme = \"nord\"\n");
+    fixture.write_file("alacritty/new.toml", "new = true\n");
+
+    fixture
+        .run(Command::Update(UpdateArgs::default()))
+        .expect("Update failed");
+
+    assert_eq!(
+        fixture.read_file("dotfiles/alacritty/common/font.toml"),
+        "size = 13\n"
+    );
+    assert_eq!(
+        fixture.read_file("dotfiles/alacritty/laptop/colors.toml"),
+        "theme = \"nord\"\n"
+    );
+    assert_eq!(
+        fixture.read_file("dotfiles/alacritty/common/colors.toml"),
+        "theme = \"light\"\n"
+    );
+    assert_eq!(
+        fixture.read_file("dotfiles/alacritty/laptop/new.toml"),
+        "new = true\n"
+    );
+}
+
+#[test]
+fn test_src_array_round_trips() {
+    let fixture = TestFixture::new("laptop");
+    let config = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    config.save(&fixture.cwd).expect("Failed to save config");
+
+    let config = Config::from_path(&fixture.cwd).expect("Failed to reload config");
+    let package = &config.packages["d_alacritty"];
+    assert_eq!(package.src.as_deref(), Some("dotfiles/alacritty/common"));
+    assert_eq!(package.overlays, vec!["dotfiles/alacritty/{{ host }}"]);
+}
@@ -0,0 +1,168 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, ImportArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(limits: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_limits_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fixture.write_config(limits, "");
+        fixture.write("dotfiles/app/config", "setting = 1\n");
+        fixture.write("dotfiles/app/themes/dark", "dark\n");
+        fixture
+    }
+
+    fn write_config(&self, limits: &str, package_limits: &str) {
+        fs::write(
+            self.cwd.join("config.toml"),
+            format!(
+                "banner = false\n{}\n[packages.d_app]\nsrc = \"dotfiles/app\"\ndest = \"home/app\"\n{}",
+                limits, package_limits
+            ),
+        )
+        .expect("Failed to write config");
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self, ignore_limits: bool) -> Result<(), anyhow::Error> {
+        self.run(Command::Deploy(DeployUpdateArgs {
+            ignore_limits,
+            ..Default::default()
+        }))
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_big_file_stops_the_package_before_writing() {
+    let fixture = TestFixture::new("max_file_size = \"1KB\"\n");
+    fixture.write("dotfiles/app/vm.img", &"x".repeat(2048));
+    let err = fixture.deploy(false).expect_err("Deploy should be refused");
+    let message = format!("{:#}", err);
+    assert!(message.contains("vm.img"), "{}", message);
+    assert!(message.contains("max_file_size = \"1KB\""), "{}", message);
+    assert!(!fixture.cwd.join("home/app").exists());
+
+    fixture.deploy(true).expect("Deploy failed");
+    assert!(fixture.cwd.join("home/app/vm.img").exists());
+}
+
+#[test]
+fn test_ignored_files_do_not_count() {
+    let fixture = TestFixture::new("max_file_size = \"1KB\"\n");
+    fixture.write("dotfiles/app/vm.img", &"x".repeat(2048));
+    fixture.write_config("max_file_size = \"1KB\"\n", "ignore = [\"*.img\"]\n");
+    fixture.deploy(false).expect("Deploy failed");
+    assert!(fixture.cwd.join("home/app/config").exists());
+}
+
+#[test]
+fn test_too_many_files_and_package_overrides() {
+    let fixture = TestFixture::new("max_files = 1\n");
+    let err = fixture.deploy(false).expect_err("Deploy should be refused");
+    assert!(format!("{:#}", err).contains("2 files"), "{:#}", err);
+
+    // The package's own limit wins
+    fixture.write_config("max_files = 1\n", "max_files = 10\n");
+    fixture.deploy(false).expect("Deploy failed");
+
+    // Update counts what it would copy back
+    fixture.write_config("max_files = 1\n", "max_files = 2\n");
+    fixture.write("home/app/extra", "extra\n");
+    let err = fixture
+        .run(Command::Update(UpdateArgs::default()))
+        .expect_err("Update should be refused");
+    assert!(format!("{:#}", err).contains("3 files"), "{:#}", err);
+    assert!(!fixture.cwd.join("dotfiles/app/extra").exists());
+    fixture
+        .run(Command::Update(UpdateArgs {
+            ignore_limits: true,
+            ..Default::default()
+        }))
+        .expect("Update failed");
+    assert!(fixture.cwd.join("dotfiles/app/extra").exists());
+}
+
+#[test]
+fn test_import_checks_the_limits() {
+    let fixture = TestFixture::new("max_file_size = \"1KB\"\n");
+    fixture.write("outside/tool/big.bin", &"x".repeat(4096));
+    fixture.write("outside/tool/rc", "rc\n");
+    let import = |ignore_limits| {
+        fixture.run(Command::Import(ImportArgs {
+            paths: vec![
+                fixture
+                    .cwd
+                    .join("outside/tool")
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            ],
+            ignore_limits,
+            ..Default::default()
+        }))
+    };
+    let err = import(false).expect_err("Import should be refused");
+    let message = format!("{:#}", err);
+    assert!(message.contains("big.bin"), "{}", message);
+    assert!(message.contains("ignore"), "{}", message);
+    assert!(!fixture.cwd.join("dotfiles/d_tool").exists());
+
+    import(true).expect("Import failed");
+    assert!(fixture.cwd.join("dotfiles/d_tool/big.bin").exists());
+    // The config-level limit isn't copied into the package
+    let conf = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    assert_eq!(conf.packages["d_tool"].max_file_size, None);
+}
+
+#[test]
+fn test_limits_are_parsed_and_saved() {
+    let fixture = TestFixture::new("max_file_size = \"50MB\"\nmax_files = 5000\n");
+    fixture.write_config(
+        "max_file_size = \"50MB\"\nmax_files = 5000\n",
+        "max_file_size = 2048\n",
+    );
+    let conf = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    assert_eq!(conf.max_file_size, Some(50 * 1024 * 1024));
+    assert_eq!(conf.max_files, Some(5000));
+    assert_eq!(conf.packages["d_app"].max_file_size, Some(2048));
+    conf.save(&fixture.cwd).expect("Save failed");
+    let saved = fs::read_to_string(fixture.cwd.join("config.toml")).unwrap();
+    assert!(saved.contains("max_file_size = \"50MB\""), "{}", saved);
+    assert!(saved.contains("max_file_size = \"2KB\""), "{}", saved);
+
+    for limits in ["max_file_size = \"50TB\"\n", "max_files = 0\n"] {
+        fixture.write_config(limits, "");
+        assert!(Config::from_path(&fixture.cwd).is_err(), "{}", limits);
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, PrintVarsArgs, UpdateArgs, run_cli},
+    lock::{LOCK_FILE, WorkdirLock},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_lock_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.cwd);
+    }
+}
+
+#[test]
+fn test_lock_records_pid() {
+    let fixture = TestFixture::new();
+    let _lock = WorkdirLock::acquire(&fixture.cwd, Duration::ZERO).expect("Lock failed");
+
+    let content = fs::read_to_string(fixture.cwd.join(LOCK_FILE)).unwrap();
+    assert_eq!(content.trim(), std::process::id().to_string());
+}
+
+#[test]
+fn test_held_lock_fails_immediately_with_pid() {
+    let fixture = TestFixture::new();
+    let _lock = WorkdirLock::acquire(&fixture.cwd, Duration::ZERO).expect("Lock failed");
+
+    let err = WorkdirLock::acquire(&fixture.cwd, Duration::ZERO).unwrap_err();
+    let message = err.to_string();
+    assert!(
+        message.contains(&format!("PID {}", std::process::id())),
+        "Error should name the PID holding the lock: {}",
+        message
+    );
+}
+
+#[test]
+fn test_held_lock_waits_for_timeout() {
+    let fixture = TestFixture::new();
+    let _lock = WorkdirLock::acquire(&fixture.cwd, Duration::ZERO).expect("Lock failed");
+
+    let started = Instant::now();
+    let result = WorkdirLock::acquire(&fixture.cwd, Duration::from_millis(300));
+    assert!(
+        result.is_err(),
+        "Lock should still be held after the timeout"
+    );
+    assert!(started.elapsed() >= Duration::from_millis(300));
+}
+
+#[test]
+fn test_lock_acquired_once_released() {
+    let fixture = TestFixture::new();
+    let lock = WorkdirLock::acquire(&fixture.cwd, Duration::ZERO).expect("Lock failed");
+
+    let cwd = fixture.cwd.clone();
+    let waiter = std::thread::spawn(move || WorkdirLock::acquire(&cwd, Duration::from_secs(5)));
+    std::thread::sleep(Duration::from_millis(200));
+    drop(lock);
+
+    assert!(
+        waiter.join().unwrap().is_ok(),
+        "Waiting acquire should succeed after the holder releases"
+    );
+}
+
+#[test]
+fn test_leftover_lock_file_does_not_block() {
+    let fixture = TestFixture::new();
+    fs::write(fixture.cwd.join(LOCK_FILE), "999999\n").unwrap();
+
+    assert!(WorkdirLock::acquire(&fixture.cwd, Duration::ZERO).is_ok());
+}
+
+#[test]
+fn test_mutating_command_fails_while_locked() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    let _lock = WorkdirLock::acquire(&fixture.cwd, Duration::ZERO).expect("Lock failed");
+
+    let result = run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))));
+    assert!(result.is_err(), "Deploy should fail while the lock is held");
+    let result = run_cli(fixture.get_cli(Some(Command::Update(UpdateArgs::default()))));
+    assert!(result.is_err(), "Update should fail while the lock is held");
+}
+
+#[test]
+fn test_read_only_commands_ignore_lock() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    let _lock = WorkdirLock::acquire(&fixture.cwd, Duration::ZERO).expect("Lock failed");
+
+    run_cli(fixture.get_cli(Some(Command::Diff(DeployUpdateArgs::default()))))
+        .expect("Diff should not need the lock");
+    run_cli(fixture.get_cli(Some(Command::PrintVars(PrintVarsArgs::default()))))
+        .expect("print-vars should not need the lock");
+}
+
+#[test]
+fn test_command_releases_lock() {
+    let fixture = TestFixture::new();
+    fixture.init();
+
+    run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))))
+        .expect("Deploy failed");
+    assert!(
+        WorkdirLock::acquire(&fixture.cwd, Duration::ZERO).is_ok(),
+        "Lock should be released once the command returns"
+    );
+}
@@ -0,0 +1,301 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+    package::MergeFormat,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_merge_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+
+    fn deploy(&self) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))))
+    }
+
+    fn update(&self) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Update(UpdateArgs::default()))))
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+
+    fn read_json(&self, path: &str) -> serde_json::Value {
+        serde_json::from_str(&self.read_file(path)).expect("Invalid JSON")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+fn package_config(name: &str, dest: &str, format: &str) -> String {
+    format!(
+        r#"
+banner = false
+
+[variables]
+THEME = "dark"
+
+[packages.{name}]
+src = "dotfiles/{name}"
+dest = "{dest}"
+merge = "{format}"
+"#
+    )
+}
+
+#[test]
+fn test_merge_parses_and_round_trips() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("dotfiles/f_vscode", "{}\n");
+    fixture.write_file(
+        "config.toml",
+        &package_config("f_vscode", "settings.json", "json"),
+    );
+
+    let config = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    assert_eq!(config.packages["f_vscode"].merge, Some(MergeFormat::Json));
+
+    let table = config.packages["f_vscode"].to_table();
+    assert_eq!(table["merge"].as_str(), Some("json"));
+}
+
+#[test]
+fn test_unknown_merge_format_is_rejected() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("config.toml", &package_config("f_rc", "rc", "ini"));
+
+    let err = Config::from_path(&fixture.cwd).expect_err("ini is not a merge format");
+    assert!(
+        format!("{:#}", err).contains("Unknown merge format 'ini'"),
+        "Unexpected error: {:#}",
+        err
+    );
+}
+
+#[test]
+fn test_json_merge_keeps_unmanaged_keys() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file(
+        "config.toml",
+        &package_config("f_vscode", "settings.json", "json"),
+    );
+    fixture.write_file(
+        "dotfiles/f_vscode",
+        r#"{
+  "editor": { "fontSize": 14, "theme": "{{ THEME }}" },
+  "extensions": ["rust-analyzer"]
+}
+"#,
+    );
+    fixture.write_file(
+        "settings.json",
+        r#"{
+  "editor": { "fontSize": 12, "minimap": false },
+  "extensions": ["copilot", "vim"],
+  "telemetry": false
+}
+"#,
+    );
+
+    fixture.deploy().expect("Deploy failed");
+
+    let merged = fixture.read_json("settings.json");
+    assert_eq!(
+        merged,
+        serde_json::json!({
+            "editor": { "fontSize": 14, "minimap": false, "theme": "dark" },
+            "extensions": ["rust-analyzer"],
+            "telemetry": false
+        }),
+        "Source keys win, nested objects merge and arrays are replaced"
+    );
+    assert!(
+        fixture.cwd.join("settings.json.dotrbak").exists(),
+        "The original dest should be backed up"
+    );
+}
+
+#[test]
+fn test_merge_replaces_on_type_conflict() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("config.toml", &package_config("f_app", "app.json", "json"));
+    fixture.write_file(
+        "dotfiles/f_app",
+        r#"{ "proxy": { "host": "localhost" }, "port": 8080 }"#,
+    );
+    fixture.write_file(
+        "app.json",
+        r#"{ "proxy": "none", "port": { "http": 80 }, "keep": true }"#,
+    );
+
+    fixture.deploy().expect("Deploy failed");
+
+    assert_eq!(
+        fixture.read_json("app.json"),
+        serde_json::json!({
+            "proxy": { "host": "localhost" },
+            "port": 8080,
+            "keep": true
+        })
+    );
+}
+
+#[test]
+fn test_merge_creates_missing_dest() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("config.toml", &package_config("f_app", "app.json", "json"));
+    fixture.write_file("dotfiles/f_app", r#"{"a":{"b":1}}"#);
+
+    fixture.deploy().expect("Deploy failed");
+
+    assert_eq!(
+        fixture.read_file("app.json"),
+        "{\n  \"a\": {\n    \"b\": 1\n  }\n}\n"
+    );
+    assert!(!fixture.cwd.join("app.json.dotrbak").exists());
+
+    // A second deploy finds nothing to change
+    fixture.deploy().expect("Deploy failed");
+    assert!(!fixture.cwd.join("app.json.dotrbak").exists());
+}
+
+#[test]
+fn test_merge_parse_error_names_the_file() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("config.toml", &package_config("f_app", "app.json", "json"));
+    fixture.write_file("dotfiles/f_app", r#"{ "a": 1 }"#);
+    fixture.write_file("app.json", "{ not json");
+
+    let err = fixture
+        .deploy()
+        .expect_err("Broken dest should fail the merge");
+    let message = format!("{:#}", err);
+    assert!(
+        message.contains("app.json"),
+        "Unexpected error: {}",
+        message
+    );
+    assert!(message.contains("as json"), "Unexpected error: {}", message);
+    assert_eq!(
+        fixture.read_file("app.json"),
+        "{ not json",
+        "Dest must be left alone when it can't be parsed"
+    );
+}
+
+#[test]
+fn test_yaml_merge() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file(
+        "config.toml",
+        &package_config("f_compose", "compose.yaml", "yaml"),
+    );
+    fixture.write_file(
+        "dotfiles/f_compose",
+        "services:\n  web:\n    image: nginx\n    ports:\n      - \"8080:80\"\n",
+    );
+    fixture.write_file(
+        "compose.yaml",
+        "services:\n  web:\n    image: apache\n    ports:\n      - \"80:80\"\n    restart: always\n  db:\n    image: postgres\n",
+    );
+
+    fixture.deploy().expect("Deploy failed");
+
+    let merged: serde_json::Value =
+        serde_yaml::from_str(&fixture.read_file("compose.yaml")).expect("Invalid YAML");
+    assert_eq!(
+        merged,
+        serde_json::json!({
+            "services": {
+                "web": { "image": "nginx", "ports": ["8080:80"], "restart": "always" },
+                "db": { "image": "postgres" }
+            }
+        })
+    );
+}
+
+#[test]
+fn test_toml_merge() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file(
+        "config.toml",
+        &package_config("f_starship", "starship.toml", "toml"),
+    );
+    fixture.write_file(
+        "dotfiles/f_starship",
+        "[character]\nsuccess_symbol = \"> \"\n",
+    );
+    fixture.write_file(
+        "starship.toml",
+        "add_newline = false\n\n[character]\nsuccess_symbol = \"$ \"\nerror_symbol = \"! \"\n",
+    );
+
+    fixture.deploy().expect("Deploy failed");
+
+    let merged: toml::Table = fixture
+        .read_file("starship.toml")
+        .parse()
+        .expect("Invalid TOML");
+    assert_eq!(merged["add_newline"].as_bool(), Some(false));
+    assert_eq!(merged["character"]["success_symbol"].as_str(), Some("> "));
+    assert_eq!(merged["character"]["error_symbol"].as_str(), Some("! "));
+}
+
+#[test]
+fn test_update_skips_merge_packages() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("config.toml", &package_config("f_app", "app.json", "json"));
+    fixture.write_file("dotfiles/f_app", r#"{ "a": 1 }"#);
+    fixture.write_file("app.json", r#"{ "a": 2, "b": 3 }"#);
+
+    fixture.update().expect("Update failed");
+
+    assert_eq!(
+        fixture.read_file("dotfiles/f_app"),
+        r#"{ "a": 1 }"#,
+        "Merged dest must not be copied back into src"
+    );
+}
@@ -0,0 +1,245 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+    package::{Action, Package},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_meta_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+
+    fn deploy_args(&self, packages: Option<Vec<String>>) -> DeployUpdateArgs {
+        DeployUpdateArgs {
+            packages,
+            profile: None,
+            ..Default::default()
+        }
+    }
+
+    fn write_config(&self, content: &str) {
+        fs::write(self.cwd.join("config.toml"), content).expect("Failed to write config");
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+fn parse_package(name: &str, content: &str) -> Result<Package, anyhow::Error> {
+    let table = content.parse::<toml::Table>().expect("Invalid TOML");
+    Package::from_table(name, &table)
+}
+
+#[test]
+fn test_meta_package_from_table() {
+    let pkg = parse_package(
+        "brew",
+        r#"
+pre_actions = ["echo installing"]
+"#,
+    )
+    .expect("Meta package should be valid");
+
+    assert!(pkg.is_meta());
+    assert!(pkg.src.is_none());
+    assert!(pkg.dest.is_none());
+}
+
+#[test]
+fn test_meta_package_with_only_dependencies() {
+    let pkg = parse_package(
+        "desktop",
+        r#"
+dependencies = ["f_bashrc", "d_nvim"]
+"#,
+    )
+    .expect("Dependency-only package should be valid");
+
+    assert!(pkg.is_meta());
+}
+
+#[test]
+fn test_empty_package_is_rejected() {
+    let result = parse_package("empty", "");
+    let err = result.expect_err("Empty package should be rejected");
+    assert!(err.to_string().contains("is empty"));
+
+    let result = parse_package("empty_deps", "dependencies = []");
+    assert!(result.is_err(), "Empty dependencies should not count");
+}
+
+#[test]
+fn test_src_without_dest_is_rejected() {
+    let result = parse_package("half", r#"src = "dotfiles/half""#);
+    let err = result.expect_err("src without dest should be rejected");
+    assert!(err.to_string().contains("no dest"));
+
+    let result = parse_package("half", r#"dest = "~/.half""#);
+    let err = result.expect_err("dest without src should be rejected");
+    assert!(err.to_string().contains("no src"));
+}
+
+#[test]
+fn test_meta_package_round_trip() {
+    let pkg = parse_package(
+        "brew",
+        r#"
+dependencies = ["f_base"]
+pre_actions = ["echo pre"]
+post_actions = ["echo post"]
+"#,
+    )
+    .expect("Meta package should be valid");
+
+    let table = pkg.to_table();
+    assert!(!table.contains_key("src"), "src should be omitted");
+    assert!(!table.contains_key("dest"), "dest should be omitted");
+
+    let reparsed = Package::from_table("brew", &table).expect("Round trip should parse");
+    assert!(reparsed.is_meta());
+    assert_eq!(reparsed.dependencies, Some(vec!["f_base".to_string()]));
+    assert_eq!(reparsed.pre_actions, vec![Action::from("echo pre")]);
+    assert_eq!(reparsed.post_actions, vec![Action::from("echo post")]);
+}
+
+#[test]
+fn test_meta_package_deploys_before_dependents() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fs::write(fixture.cwd.join("dotfiles/f_tool"), "tool config\n").expect("Failed to write");
+    fixture.write_config(
+        r#"
+banner = false
+
+[packages.brew]
+pre_actions = ["echo brew-pre >> order.log"]
+post_actions = ["echo brew-post >> order.log"]
+
+[packages.f_tool]
+src = "dotfiles/f_tool"
+dest = "tool.conf"
+dependencies = ["brew"]
+pre_actions = ["echo tool-pre >> order.log"]
+"#,
+    );
+
+    let config = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    let mut ctx = dotr::context::Context::new(&fixture.cwd).expect("Failed to create context");
+    ctx.extend_variables(config.variables.clone());
+    config
+        .deploy_packages(&ctx, &fixture.deploy_args(Some(vec!["f_tool".to_string()])))
+        .expect("Deploy failed");
+
+    assert_eq!(
+        fixture.read_file("order.log"),
+        "brew-pre\nbrew-post\ntool-pre\n",
+        "Meta package should deploy before its dependents"
+    );
+    assert_eq!(fixture.read_file("tool.conf"), "tool config\n");
+}
+
+#[test]
+fn test_transitive_dependencies_are_ordered() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_config(
+        r#"
+banner = false
+
+[packages.a]
+dependencies = ["b"]
+pre_actions = ["echo a >> order.log"]
+
+[packages.b]
+dependencies = ["c"]
+pre_actions = ["echo b >> order.log"]
+
+[packages.c]
+pre_actions = ["echo c >> order.log"]
+"#,
+    );
+
+    let config = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    let ctx = dotr::context::Context::new(&fixture.cwd).expect("Failed to create context");
+    let ordered = config
+        .ordered_packages(&ctx, &Some(vec!["a".to_string()]))
+        .expect("Ordering failed");
+    let names: Vec<&str> = ordered.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, vec!["c", "b", "a"]);
+
+    config
+        .deploy_packages(&ctx, &fixture.deploy_args(Some(vec!["a".to_string()])))
+        .expect("Deploy failed");
+    assert_eq!(fixture.read_file("order.log"), "c\nb\na\n");
+}
+
+#[test]
+fn test_circular_dependencies_are_reported() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_config(
+        r#"
+banner = false
+
+[packages.a]
+dependencies = ["b"]
+
+[packages.b]
+dependencies = ["a"]
+"#,
+    );
+
+    let config = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    let ctx = dotr::context::Context::new(&fixture.cwd).expect("Failed to create context");
+    let err = config
+        .ordered_packages(&ctx, &None)
+        .expect_err("Cycle should be rejected");
+    assert!(err.to_string().contains("Circular package dependency"));
+}
+
+#[test]
+fn test_update_is_noop_for_meta_package() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_config(
+        r#"
+banner = false
+
+[packages.brew]
+pre_actions = ["echo hi"]
+"#,
+    );
+
+    run_cli(fixture.get_cli(Some(Command::Update(UpdateArgs::default()))))
+        .expect("Update of meta package should succeed");
+    run_cli(fixture.get_cli(Some(Command::Diff(fixture.deploy_args(None)))))
+        .expect("Diff of meta package should succeed");
+}
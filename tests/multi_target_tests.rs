@@ -0,0 +1,163 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    package::Package,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_multi_target_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    // f_rc deploys to two places under the work profile
+    fn setup(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+        fs::write(self.cwd.join("config.toml"), CONFIG).expect("Failed to write config");
+        self.write_file("dotfiles/f_rc", "name={{ NAME }}\n");
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(command)))
+    }
+
+    fn work_args(&self) -> DeployUpdateArgs {
+        DeployUpdateArgs {
+            profile: Some("work".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).unwrap_or_default()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[variables]
+NAME = "me"
+
+[profiles.work]
+dependencies = ["f_rc"]
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "rc"
+
+[packages.f_rc.targets]
+work = ["work/rc", "backup/rc"]
+"#;
+
+#[test]
+fn test_deploys_to_every_target() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+    fixture.write_file("work/rc", "old\n");
+
+    fixture
+        .run(Command::Deploy(fixture.work_args()))
+        .expect("Deploy failed");
+    assert_eq!(fixture.read("work/rc"), "name=me\n");
+    assert_eq!(fixture.read("backup/rc"), "name=me\n");
+    assert_eq!(fixture.read("work/rc.dotrbak"), "old\n");
+    assert!(!fixture.cwd.join("rc").exists());
+
+    // Without the profile the plain dest is used
+    fixture
+        .run(Command::Deploy(DeployUpdateArgs::default()))
+        .expect("Deploy failed");
+    assert_eq!(fixture.read("rc"), "name=me\n");
+}
+
+#[test]
+fn test_update_copies_back_from_first_target() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+    fixture.write_file("dotfiles/f_rc", "plain\n");
+    fixture
+        .run(Command::Deploy(fixture.work_args()))
+        .expect("Deploy failed");
+    fixture.write_file("work/rc", "from work\n");
+    fixture.write_file("backup/rc", "from backup\n");
+
+    fixture
+        .run(Command::Update(UpdateArgs {
+            profile: Some("work".to_string()),
+            ..Default::default()
+        }))
+        .expect("Update failed");
+    assert_eq!(fixture.read("dotfiles/f_rc"), "from work\n");
+}
+
+#[test]
+fn test_targets_round_trip() {
+    let table: toml::Table = toml::from_str(
+        r#"
+src = "dotfiles/f_rc"
+dest = "rc"
+
+[targets]
+work = ["work/rc", "backup/rc"]
+home = "home/rc"
+"#,
+    )
+    .unwrap();
+    let package = Package::from_table("f_rc", &table).expect("Parse failed");
+    assert_eq!(
+        package.targets["work"],
+        vec!["work/rc".to_string(), "backup/rc".to_string()]
+    );
+    assert_eq!(package.targets["home"], vec!["home/rc".to_string()]);
+
+    let saved = package.to_table();
+    let targets = saved["targets"].as_table().unwrap();
+    assert!(targets["work"].is_array());
+    assert_eq!(targets["home"].as_str(), Some("home/rc"));
+}
+
+#[test]
+fn test_empty_target_array_is_rejected() {
+    let table: toml::Table = toml::from_str(
+        r#"
+src = "dotfiles/f_rc"
+dest = "rc"
+
+[targets]
+work = []
+"#,
+    )
+    .unwrap();
+    let err = Package::from_table("f_rc", &table).expect_err("Empty targets should fail");
+    assert!(err.to_string().contains("non-empty"), "{}", err);
+}
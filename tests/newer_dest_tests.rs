@@ -0,0 +1,168 @@
+use std::{
+    fs,
+    path::PathBuf,
+    process::Command as Process,
+    time::{Duration, SystemTime},
+};
+
+use dotr::{Config, Context, DeployUpdateArgs, Report};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(config: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_newer_dest_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Config::init(&temp_dir, Default::default()).expect("Init failed");
+        fs::write(temp_dir.join("config.toml"), config).expect("Failed to write config");
+        let fixture = Self { cwd: temp_dir };
+        fixture.write("dotfiles/f_zshrc", "from the repo\n");
+        fixture.write("dotfiles/f_greeting", "hello {{ name }}\n");
+        // Sources last changed an hour ago, the dests were edited since
+        fixture.age("dotfiles/f_zshrc", 3600);
+        fixture.age("dotfiles/f_greeting", 3600);
+        fixture.write("home/.zshrc", "edited here\n");
+        fixture.write("home/greeting", "edited here\n");
+        fixture
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).unwrap()
+    }
+
+    fn age(&self, path: &str, seconds: u64) {
+        let file = fs::File::options()
+            .write(true)
+            .open(self.cwd.join(path))
+            .unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    fn deploy(&self, force: bool) -> Report {
+        let conf = Config::from_path(&self.cwd).expect("Failed to load config");
+        let mut ctx = Context::from_config(&self.cwd, &conf).expect("Failed to create context");
+        ctx.set_force(force);
+        conf.deploy_packages(
+            &ctx,
+            &DeployUpdateArgs {
+                force,
+                ..Default::default()
+            },
+        )
+        .expect("Deploy failed")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[variables]
+name = "world"
+
+[packages.f_zshrc]
+src = "dotfiles/f_zshrc"
+dest = "home/.zshrc"
+
+[packages.f_greeting]
+src = "dotfiles/f_greeting"
+dest = "home/greeting"
+"#;
+
+fn skipped(report: &Report, name: &str) -> usize {
+    report
+        .packages
+        .iter()
+        .find(|p| p.name == name)
+        .map_or(0, |p| p.skipped.len())
+}
+
+#[test]
+fn test_newer_dest_is_kept_with_a_warning() {
+    let fixture = TestFixture::new(CONFIG);
+    let output = Process::new(env!("CARGO_BIN_EXE_dotr"))
+        .args([
+            "-w",
+            fixture.cwd.to_str().unwrap(),
+            "--no-progress",
+            "deploy",
+        ])
+        .output()
+        .expect("Failed to run dotr");
+    assert!(output.status.success());
+    let printed = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        printed.contains("modified more recently than its source"),
+        "{}",
+        printed
+    );
+    assert!(printed.contains("--force"), "{}", printed);
+    assert_eq!(fixture.read("home/.zshrc"), "edited here\n");
+    assert_eq!(fixture.read("home/greeting"), "edited here\n");
+}
+
+#[test]
+fn test_force_overwrites_a_newer_dest() {
+    let fixture = TestFixture::new(CONFIG);
+    let report = fixture.deploy(false);
+    assert_eq!(skipped(&report, "f_zshrc"), 1);
+    assert_eq!(fixture.read("home/.zshrc"), "edited here\n");
+
+    fixture.deploy(true);
+    assert_eq!(fixture.read("home/.zshrc"), "from the repo\n");
+    assert_eq!(fixture.read("home/greeting"), "hello world\n");
+}
+
+#[test]
+fn test_overwrite_newer_package_setting() {
+    let config = CONFIG.replace(
+        "dest = \"home/.zshrc\"\n",
+        "dest = \"home/.zshrc\"\noverwrite_newer = true\n",
+    );
+    let fixture = TestFixture::new(&config);
+    fixture.deploy(false);
+    assert_eq!(fixture.read("home/.zshrc"), "from the repo\n");
+    assert_eq!(fixture.read("home/greeting"), "edited here\n");
+}
+
+#[test]
+fn test_rerendered_template_is_not_mistaken_for_an_edit() {
+    let fixture = TestFixture::new(CONFIG);
+    fixture.deploy(true);
+    // Deployed long after its source changed, then the variables change
+    fs::write(
+        fixture.cwd.join("config.toml"),
+        CONFIG.replace("\"world\"", "\"there\""),
+    )
+    .unwrap();
+    fixture.deploy(false);
+    assert_eq!(fixture.read("home/greeting"), "hello there\n");
+}
+
+#[test]
+fn test_small_clock_skew_is_tolerated() {
+    let fixture = TestFixture::new(CONFIG);
+    fixture.age("dotfiles/f_zshrc", 1);
+    fixture.age("home/.zshrc", 0);
+    fixture.deploy(false);
+    assert_eq!(fixture.read("home/.zshrc"), "from the repo\n");
+}
@@ -0,0 +1,124 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, StatusArgs, run_cli},
+    config::Config,
+    context::Context,
+    utils::{Cell, Color, render_table},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_output_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        run_cli(Cli {
+            command: Some(Command::Init(InitArgs::default())),
+            working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+        .expect("Init failed");
+        fs::write(fixture.cwd.join("config.toml"), PACKAGE_CONFIG).expect("Failed to write config");
+        fs::write(fixture.cwd.join("dotfiles/f_rc"), "rc\n").unwrap();
+        fs::create_dir_all(fixture.cwd.join("dotfiles/nvim")).unwrap();
+        fs::write(fixture.cwd.join("dotfiles/nvim/init.lua"), "-- nvim\n").unwrap();
+        fixture
+    }
+
+    fn status(&self, color: bool) -> String {
+        let conf = Config::from_path(&self.cwd).expect("Failed to load config");
+        let ctx = Context::from_config(&self.cwd, &conf).expect("Failed to create context");
+        conf.render_status(&ctx, &StatusArgs::default(), color)
+            .expect("Status failed")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const PACKAGE_CONFIG: &str = r#"
+banner = false
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "home/.rc"
+
+[packages.d_neovim_config]
+src = "dotfiles/nvim"
+dest = "home/.config/nvim"
+"#;
+
+#[test]
+fn test_table_columns_line_up() {
+    let rows = vec![
+        vec![
+            Cell::plain("f_rc"),
+            Cell::colored("deployed", Color::Green),
+            Cell::plain("~/.rc"),
+        ],
+        vec![
+            Cell::plain("d_neovim_config"),
+            Cell::colored("never deployed", Color::Grey),
+            Cell::plain("~/.config/nvim"),
+        ],
+        vec![Cell::plain("f_short"), Cell::plain(""), Cell::plain("")],
+    ];
+    assert_eq!(
+        render_table(&rows, false),
+        "\
+f_rc             deployed        ~/.rc
+d_neovim_config  never deployed  ~/.config/nvim
+f_short
+"
+    );
+}
+
+#[test]
+fn test_color_does_not_shift_columns() {
+    let rows = vec![
+        vec![Cell::colored("a", Color::Red), Cell::plain("x")],
+        vec![Cell::plain("long"), Cell::plain("y")],
+    ];
+    let colored = render_table(&rows, true);
+    assert!(colored.contains('\x1b'));
+    let stripped = colored.replace("\x1b[31m", "").replace("\x1b[0m", "");
+    assert_eq!(stripped, render_table(&rows, false));
+}
+
+#[test]
+fn test_status_snapshot() {
+    let fixture = TestFixture::new();
+    assert_eq!(
+        fixture.status(false),
+        "\
+d_neovim_config  never deployed  home/.config/nvim
+f_rc             never deployed  home/.rc
+"
+    );
+
+    run_cli(Cli {
+        command: Some(Command::Deploy(DeployUpdateArgs {
+            packages: Some(vec!["f_rc".to_string()]),
+            ..Default::default()
+        })),
+        working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+        ..Default::default()
+    })
+    .expect("Deploy failed");
+    assert_eq!(
+        fixture.status(false),
+        "\
+d_neovim_config  never deployed  home/.config/nvim
+f_rc             deployed        home/.rc           last deployed just now, 1 file(s) written
+"
+    );
+    assert!(!fixture.status(false).contains('\x1b'));
+}
@@ -0,0 +1,137 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    config::Config,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_metadata_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fixture.write_file("config.toml", CONFIG);
+        fixture.write_file(
+            "dotfiles/f_rc",
+            "# managed by dotr package {{ dotr_package.name }}, do not edit\n\
+             # src: {{ dotr_package.src }}\n\
+             # dest: {{ dotr_package.dest }}\n",
+        );
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self, profile: Option<&str>) {
+        self.run(Command::Deploy(DeployUpdateArgs {
+            profile: profile.map(|p| p.to_string()),
+            ..Default::default()
+        }))
+        .expect("Deploy failed");
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "home/rc"
+post_actions = ["echo deployed {{ dotr_package.name }} to {{ dotr_package.dest }} > action.txt"]
+
+[packages.f_rc.targets]
+work = ["work/rc"]
+
+[profiles.work]
+dependencies = ["f_rc"]
+"#;
+
+#[test]
+fn test_template_sees_package_metadata() {
+    let fixture = TestFixture::new();
+    fixture.deploy(None);
+
+    let src = fixture.cwd.join("dotfiles/f_rc");
+    let dest = fixture.cwd.join("home/rc");
+    assert_eq!(
+        fixture.read_file("home/rc"),
+        format!(
+            "# managed by dotr package f_rc, do not edit\n# src: {}\n# dest: {}\n",
+            src.display(),
+            dest.display()
+        )
+    );
+}
+
+#[test]
+fn test_dest_follows_profile_targets() {
+    let fixture = TestFixture::new();
+    fixture.deploy(Some("work"));
+
+    let dest = fixture.cwd.join("work/rc");
+    let rendered = fixture.read_file("work/rc");
+    assert!(rendered.contains("package f_rc,"), "{}", rendered);
+    assert!(
+        rendered.contains(&format!("# dest: {}\n", dest.display())),
+        "{}",
+        rendered
+    );
+    assert!(!fixture.cwd.join("home/rc").exists());
+}
+
+#[test]
+fn test_actions_see_package_metadata() {
+    let fixture = TestFixture::new();
+    fixture.deploy(Some("work"));
+
+    assert_eq!(
+        fixture.read_file("action.txt").trim(),
+        format!("deployed f_rc to {}", fixture.cwd.join("work/rc").display())
+    );
+}
+
+#[test]
+fn test_dotr_package_is_reserved() {
+    let fixture = TestFixture::new();
+    fixture.write_file(
+        "config.toml",
+        &CONFIG.replace(
+            "banner = false\n",
+            "banner = false\n\n[variables.dotr_package]\nname = \"mine\"\n",
+        ),
+    );
+    let err = Config::from_path(&fixture.cwd).expect_err("dotr_package should be reserved");
+    assert!(err.to_string().contains("dotr_package"), "{}", err);
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fs, path::PathBuf};
+use std::{fs, path::PathBuf};
 
 use dotr::{
     cli::{DeployUpdateArgs, InitArgs, run_cli},
@@ -7,28 +7,31 @@ use dotr::{
 
 mod common;
 
-const PLAYGROUND_DIR: &str = "tests/playground";
-
 struct TestFixture {
     cwd: PathBuf,
 }
 
 impl TestFixture {
     fn new() -> Self {
-        Self {
-            cwd: PathBuf::from(PLAYGROUND_DIR),
-        }
+        let cwd = std::env::temp_dir().join(format!(
+            "dotr_package_variables_test_{}",
+            uuid::Uuid::new_v4()
+        ));
+        fs::create_dir_all(&cwd).expect("Failed to create temp dir");
+        Self { cwd }
     }
 
     fn get_cli(&self, command: Option<dotr::cli::Command>) -> dotr::cli::Cli {
         dotr::cli::Cli {
             command,
-            working_dir: Some(PLAYGROUND_DIR.to_string()),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
         }
     }
 
     fn init(&self) {
-        run_cli(self.get_cli(Some(dotr::cli::Command::Init(InitArgs {})))).expect("Init failed");
+        run_cli(self.get_cli(Some(dotr::cli::Command::Init(InitArgs::default()))))
+            .expect("Init failed");
     }
 
     fn deploy(&self, packages: Option<Vec<String>>) {
@@ -36,6 +39,7 @@ impl TestFixture {
             self.get_cli(Some(dotr::cli::Command::Deploy(DeployUpdateArgs {
                 packages,
                 profile: None,
+                ..Default::default()
             }))),
         )
         .expect("Deploy failed");
@@ -48,7 +52,7 @@ impl TestFixture {
 
 impl Drop for TestFixture {
     fn drop(&mut self) {
-        common::teardown(&self.cwd);
+        fs::remove_dir_all(&self.cwd).ok();
     }
 }
 
@@ -75,16 +79,10 @@ fn test_package_variables_basic() {
 
     let package = dotr::package::Package {
         name: "f_pkg_var_test".to_string(),
-        src: "dotfiles/f_pkg_var_test".to_string(),
-        dest: "src/.pkg_var_test".to_string(),
-        dependencies: None,
+        src: Some("dotfiles/f_pkg_var_test".to_string()),
+        dest: Some("src/.pkg_var_test".to_string()),
         variables: pkg_vars,
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -140,16 +138,10 @@ fn test_package_variables_override_config_variables() {
 
     let package = dotr::package::Package {
         name: "f_override_test".to_string(),
-        src: "dotfiles/f_override_test".to_string(),
-        dest: "src/.override_test".to_string(),
-        dependencies: None,
+        src: Some("dotfiles/f_override_test".to_string()),
+        dest: Some("src/.override_test".to_string()),
         variables: pkg_vars,
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -200,16 +192,10 @@ MY_VAR = "user_value"
 
     let package = dotr::package::Package {
         name: "f_user_override_test".to_string(),
-        src: "dotfiles/f_user_override_test".to_string(),
-        dest: "src/.user_override_test".to_string(),
-        dependencies: None,
+        src: Some("dotfiles/f_user_override_test".to_string()),
+        dest: Some("src/.user_override_test".to_string()),
         variables: pkg_vars,
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -254,16 +240,10 @@ fn test_package_variables_with_nested_structures() {
 
     let package = dotr::package::Package {
         name: "f_nested_test".to_string(),
-        src: "dotfiles/f_nested_test".to_string(),
-        dest: "src/.nested_test".to_string(),
-        dependencies: None,
+        src: Some("dotfiles/f_nested_test".to_string()),
+        dest: Some("src/.nested_test".to_string()),
         variables: pkg_vars,
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
     config.packages.insert("f_nested_test".to_string(), package);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -303,16 +283,10 @@ fn test_package_variables_persist_after_save() {
 
     let package = dotr::package::Package {
         name: "test_package".to_string(),
-        src: "dotfiles/test".to_string(),
-        dest: "src/.test".to_string(),
-        dependencies: None,
+        src: Some("dotfiles/test".to_string()),
+        dest: Some("src/.test".to_string()),
         variables: pkg_vars,
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
     config.packages.insert("test_package".to_string(), package);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -374,16 +348,10 @@ TEST_VAR = "user_value"
 
     let package = dotr::package::Package {
         name: "f_priority_test".to_string(),
-        src: "dotfiles/f_priority_test".to_string(),
-        dest: "src/.priority_test".to_string(),
-        dependencies: None,
+        src: Some("dotfiles/f_priority_test".to_string()),
+        dest: Some("src/.priority_test".to_string()),
         variables: pkg_vars,
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -430,16 +398,10 @@ fn test_multiple_packages_with_different_variables() {
 
     let package1 = dotr::package::Package {
         name: "f_pkg1".to_string(),
-        src: "dotfiles/f_pkg1".to_string(),
-        dest: "src/.pkg1".to_string(),
-        dependencies: None,
+        src: Some("dotfiles/f_pkg1".to_string()),
+        dest: Some("src/.pkg1".to_string()),
         variables: pkg1_vars,
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
 
     // Create second package with its variables
@@ -451,16 +413,10 @@ fn test_multiple_packages_with_different_variables() {
 
     let package2 = dotr::package::Package {
         name: "f_pkg2".to_string(),
-        src: "dotfiles/f_pkg2".to_string(),
-        dest: "src/.pkg2".to_string(),
-        dependencies: None,
+        src: Some("dotfiles/f_pkg2".to_string()),
+        dest: Some("src/.pkg2".to_string()),
         variables: pkg2_vars,
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        ..dotr::package::Package::default()
     };
 
     config.packages.insert("f_pkg1".to_string(), package1);
@@ -0,0 +1,120 @@
+use std::{fs, path::PathBuf};
+
+use dotr::cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir = std::env::temp_dir().join(format!(
+            "dotr_partial_failure_test_{}",
+            uuid::Uuid::new_v4()
+        ));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()), false)
+            .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            "banner = false\n\n[packages.d_app]\nsrc = \"dotfiles/app\"\ndest = \"home/app\"\npost_actions = [\"touch post_ran\"]\n",
+        )
+        .expect("Failed to write config");
+        fs::create_dir_all(fixture.cwd.join("dotfiles/app")).unwrap();
+        for name in ["a.conf", "b.conf", "c.conf"] {
+            fs::write(fixture.cwd.join("dotfiles/app").join(name), name).unwrap();
+        }
+        fixture
+    }
+
+    fn run(&self, command: Command, fail_fast: bool) -> Result<(), anyhow::Error> {
+        let command = match command {
+            Command::Deploy(args) => Command::Deploy(DeployUpdateArgs { fail_fast, ..args }),
+            Command::Update(args) => Command::Update(UpdateArgs { fail_fast, ..args }),
+            command => command,
+        };
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self, fail_fast: bool) -> Result<(), anyhow::Error> {
+        self.run(Command::Deploy(DeployUpdateArgs::default()), fail_fast)
+    }
+
+    // Something deploy can't write over, even when running as root
+    fn block(&self, path: &str) {
+        fs::create_dir_all(self.cwd.join(path).join("in_the_way")).unwrap();
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        self.cwd.join(path)
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_unwritable_file_does_not_stop_its_siblings() {
+    let fixture = TestFixture::new();
+    fixture.block("home/app/b.conf");
+    let err = fixture.deploy(false).expect_err("Deploy should fail");
+    let message = err.to_string();
+    assert!(message.contains("deployed 2, failed 1"), "{}", message);
+    assert!(message.contains("b.conf"), "{}", message);
+
+    assert_eq!(
+        fs::read_to_string(fixture.path("home/app/a.conf")).unwrap(),
+        "a.conf"
+    );
+    assert_eq!(
+        fs::read_to_string(fixture.path("home/app/c.conf")).unwrap(),
+        "c.conf"
+    );
+    // The package didn't fully land, so its post actions wait
+    assert!(!fixture.path("post_ran").exists());
+}
+
+#[test]
+fn test_fail_fast_stops_at_the_first_failure() {
+    let fixture = TestFixture::new();
+    fixture.block("home/app/b.conf");
+    let err = fixture.deploy(true).expect_err("Deploy should fail");
+    assert!(!err.to_string().contains("failed 1"), "{}", err);
+    assert!(fixture.path("home/app/a.conf").exists());
+    assert!(!fixture.path("home/app/c.conf").exists());
+}
+
+#[test]
+fn test_update_copies_back_what_it_can() {
+    let fixture = TestFixture::new();
+    fixture.deploy(false).expect("Deploy failed");
+    for name in ["a.conf", "b.conf", "c.conf"] {
+        fs::write(fixture.path("home/app").join(name), "edited").unwrap();
+    }
+    fs::remove_file(fixture.path("dotfiles/app/b.conf")).unwrap();
+    fixture.block("dotfiles/app/b.conf");
+
+    let err = fixture
+        .run(Command::Update(UpdateArgs::default()), false)
+        .expect_err("Update should fail");
+    let message = err.to_string();
+    assert!(message.contains("copied back 2, failed 1"), "{}", message);
+    assert!(message.contains("b.conf"), "{}", message);
+    assert_eq!(
+        fs::read_to_string(fixture.path("dotfiles/app/a.conf")).unwrap(),
+        "edited"
+    );
+    assert_eq!(
+        fs::read_to_string(fixture.path("dotfiles/app/c.conf")).unwrap(),
+        "edited"
+    );
+}
@@ -0,0 +1,157 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    context::Context,
+    package::load_tera,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_partials_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+
+    fn deploy(&self) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))))
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn write_config(&self) {
+        self.write_file("config.toml", CONFIG);
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[variables]
+THEME = "dark"
+
+[packages.f_alacritty]
+src = "dotfiles/f_alacritty"
+dest = "alacritty.toml"
+
+[packages.f_kitty]
+src = "dotfiles/f_kitty"
+dest = "kitty.conf"
+"#;
+
+#[test]
+fn test_init_creates_templates_dir() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    assert!(fixture.cwd.join("templates").is_dir());
+}
+
+#[test]
+fn test_packages_share_included_partial() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_config();
+    fixture.write_file(
+        "templates/colors/gruvbox.tera",
+        "bg = \"#282828\" # {{ THEME }}\n",
+    );
+    fixture.write_file(
+        "dotfiles/f_alacritty",
+        "[colors]\n{% include \"colors/gruvbox.tera\" %}",
+    );
+    fixture.write_file(
+        "dotfiles/f_kitty",
+        "# kitty\n{% include \"colors/gruvbox.tera\" %}",
+    );
+
+    fixture.deploy().expect("Deploy failed");
+
+    assert_eq!(
+        fixture.read_file("alacritty.toml"),
+        "[colors]\nbg = \"#282828\" # dark\n"
+    );
+    assert_eq!(
+        fixture.read_file("kitty.conf"),
+        "# kitty\nbg = \"#282828\" # dark\n"
+    );
+}
+
+#[test]
+fn test_missing_include_reports_including_file() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_config();
+    fixture.write_file("dotfiles/f_alacritty", "{% include \"colors/nope.tera\" %}");
+    fixture.write_file("dotfiles/f_kitty", "plain\n");
+
+    let err = fixture.deploy().expect_err("Missing include should fail");
+    let message = err.to_string();
+    assert!(
+        message.contains("f_alacritty"),
+        "Unexpected error: {}",
+        message
+    );
+    assert!(
+        message.contains("colors/nope.tera"),
+        "Unexpected error: {}",
+        message
+    );
+    assert!(!fixture.cwd.join("alacritty.toml").exists());
+}
+
+#[test]
+fn test_load_tera_names_templates_by_relative_path() {
+    let fixture = TestFixture::new();
+    fixture.write_file("templates/a.tera", "a");
+    fixture.write_file("templates/nested/deep/b.tera", "b");
+
+    let tera = load_tera(&fixture.cwd).expect("Failed to load templates");
+    let mut names: Vec<&str> = tera.get_template_names().collect();
+    names.sort();
+    assert_eq!(names, vec!["a.tera", "nested/deep/b.tera"]);
+}
+
+#[test]
+fn test_broken_partial_is_reported() {
+    let fixture = TestFixture::new();
+    fixture.write_file("templates/broken.tera", "{% if %}");
+
+    let ctx = Context::new(&fixture.cwd).expect("Failed to create context");
+    let err = ctx
+        .with_templates(|_| Ok(()))
+        .expect_err("Broken partial should fail to load");
+    assert!(err.to_string().contains("broken.tera"));
+}
@@ -0,0 +1,183 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, ImportArgs, InitArgs, run_cli},
+    config::Config,
+    package::Package,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_bases_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+
+    fn deploy(&self) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))))
+    }
+
+    fn import(&self, path: &str) {
+        run_cli(self.get_cli(Some(Command::Import(ImportArgs {
+            paths: vec![self.path(path)],
+            ..Default::default()
+        }))))
+        .expect("Import failed");
+    }
+
+    fn path(&self, path: &str) -> String {
+        self.cwd.join(path).to_str().unwrap().to_string()
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn get_config(&self) -> Config {
+        Config::from_path(&self.cwd).expect("Failed to load config")
+    }
+
+    fn set_bases(&self, bases: &[(&str, &str)]) {
+        let mut config = self.get_config();
+        for (name, base) in bases {
+            config.paths.insert(name.to_string(), self.path(base));
+        }
+        config.save(&self.cwd).expect("Failed to save config");
+    }
+
+    fn add_package(&self, name: &str, dest: &str) {
+        let mut config = self.get_config();
+        let mut table = toml::Table::new();
+        table.insert(
+            "src".to_string(),
+            toml::Value::String(format!("dotfiles/{}", name)),
+        );
+        table.insert("dest".to_string(), toml::Value::String(dest.to_string()));
+        let package = Package::from_table(name, &table).expect("Failed to build package");
+        config.packages.insert(name.to_string(), package);
+        config.save(&self.cwd).expect("Failed to save config");
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.cwd);
+    }
+}
+
+#[test]
+fn test_deploy_expands_configured_base() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.set_bases(&[("config", "home/.config")]);
+    fixture.write_file("dotfiles/d_nvim/init.lua", "-- nvim");
+    fixture.add_package("d_nvim", "@config/nvim");
+
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("home/.config/nvim/init.lua")).unwrap(),
+        "-- nvim"
+    );
+}
+
+#[test]
+fn test_unknown_base_fails() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("dotfiles/f_app", "app");
+    fixture.add_package("f_app", "@nowhere/app");
+
+    let err = fixture.deploy().expect_err("Deploy should fail");
+    assert!(
+        err.to_string().contains("Unknown path base '@nowhere'"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_import_writes_base_form() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.set_bases(&[("home", "home"), ("config", "home/.config")]);
+    fixture.write_file("home/.config/wezterm/wezterm.lua", "return {}");
+    fixture.write_file("home/.bashrc", "# bash");
+    fixture.write_file("elsewhere/app.conf", "app");
+
+    fixture.import("home/.config/wezterm");
+    fixture.import("home/.bashrc");
+    fixture.import("elsewhere/app.conf");
+
+    let config = fixture.get_config();
+    assert_eq!(
+        config.packages["d_wezterm"].dest.as_deref(),
+        Some("@config/wezterm")
+    );
+    assert_eq!(
+        config.packages["f_bashrc"].dest.as_deref(),
+        Some("@home/.bashrc")
+    );
+    assert_eq!(
+        config.packages["f_app_conf"].dest.as_deref(),
+        Some(fixture.path("elsewhere/app.conf").as_str())
+    );
+
+    // And the based dest deploys back to where it came from
+    fs::remove_dir_all(fixture.cwd.join("home/.config/wezterm")).unwrap();
+    fixture.deploy().expect("Deploy failed");
+    assert!(
+        fixture
+            .cwd
+            .join("home/.config/wezterm/wezterm.lua")
+            .exists()
+    );
+}
+
+#[test]
+fn test_paths_round_trip() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.set_bases(&[("data", "share")]);
+
+    let content = fs::read_to_string(fixture.cwd.join("config.toml")).unwrap();
+    assert!(content.contains("[paths]"));
+    assert_eq!(fixture.get_config().paths["data"], fixture.path("share"));
+}
+
+#[test]
+fn test_invalid_paths_rejected() {
+    for paths in [
+        "paths = \"~/.config\"",
+        "[paths]\nconfig = 1",
+        "[paths]\nconfig = \"@data/x\"",
+        "[paths]\n\"bad name\" = \"~/x\"",
+    ] {
+        let table: toml::Table = paths.parse().unwrap();
+        assert!(
+            Config::from_table(&table).is_err(),
+            "Should reject: {}",
+            paths
+        );
+    }
+}
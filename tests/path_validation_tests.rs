@@ -0,0 +1,275 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+    package::Package,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_paths_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+
+    fn deploy(&self, skip_validation: bool) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs {
+            skip_validation,
+            ..Default::default()
+        }))))
+    }
+
+    fn deploy_strict(&self) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            strict: true,
+            ..self.get_cli(Some(Command::Deploy(DeployUpdateArgs::default())))
+        })
+    }
+
+    fn diff_strict(&self) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            strict: true,
+            ..self.get_cli(Some(Command::Diff(DeployUpdateArgs::default())))
+        })
+    }
+
+    // d_config deploys to home/config, with f_kitty writing one of its files
+    fn add_nested_packages(&self) {
+        self.write_file("dotfiles/d_config/kitty/kitty.conf", "from d_config");
+        self.write_file("dotfiles/d_config/git/config.tera", "{{ HOME }}");
+        self.write_file("dotfiles/f_kitty", "from f_kitty");
+        self.add_package("d_config", &self.path("home/config"), &[]);
+        self.add_package("f_kitty", &self.path("home/config/kitty/kitty.conf"), &[]);
+    }
+
+    fn update(&self) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Update(UpdateArgs::default()))))
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn path(&self, path: &str) -> String {
+        self.cwd.join(path).to_str().unwrap().to_string()
+    }
+
+    fn add_package(&self, name: &str, dest: &str, targets: &[(&str, &str)]) {
+        let mut config = Config::from_path(&self.cwd).expect("Failed to load config");
+        let mut table = toml::Table::new();
+        table.insert(
+            "src".to_string(),
+            toml::Value::String(format!("dotfiles/{}", name)),
+        );
+        table.insert("dest".to_string(), toml::Value::String(dest.to_string()));
+        if !targets.is_empty() {
+            let mut targets_table = toml::Table::new();
+            for (profile, target) in targets {
+                targets_table.insert(profile.to_string(), toml::Value::String(target.to_string()));
+            }
+            table.insert("targets".to_string(), toml::Value::Table(targets_table));
+        }
+        let package = Package::from_table(name, &table).expect("Failed to build package");
+        config.packages.insert(name.to_string(), package);
+        config.save(&self.cwd).expect("Failed to save config");
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.cwd);
+    }
+}
+
+#[test]
+fn test_dest_inside_dotfiles_fails() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("dotfiles/d_app/app.conf", "app");
+    fixture.write_file("dotfiles/f_other", "other");
+    fixture.add_package("d_app", &fixture.path("dotfiles/f_other_copy"), &[]);
+
+    let err = fixture.deploy(false).expect_err("Deploy should fail");
+    let message = err.to_string();
+    assert!(message.contains("d_app"), "Unexpected error: {}", message);
+    assert!(message.contains("inside the repository's dotfiles/"));
+    assert!(!fixture.cwd.join("dotfiles/f_other_copy").exists());
+
+    assert!(fixture.update().is_err(), "Update should fail too");
+}
+
+#[test]
+fn test_dest_overlapping_src_fails() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("dotfiles/d_app/app.conf", "app");
+    // A src kept outside dotfiles/, with dest one level up from it
+    let mut config = Config::from_path(&fixture.cwd).unwrap();
+    let mut table = toml::Table::new();
+    table.insert(
+        "src".to_string(),
+        toml::Value::String("extra/app".to_string()),
+    );
+    table.insert(
+        "dest".to_string(),
+        toml::Value::String(fixture.path("extra/app/../")),
+    );
+    config.packages.insert(
+        "d_extra".to_string(),
+        Package::from_table("d_extra", &table).unwrap(),
+    );
+    config.save(&fixture.cwd).unwrap();
+    fixture.write_file("extra/app/app.conf", "app");
+
+    let err = fixture.deploy(false).expect_err("Deploy should fail");
+    assert!(
+        err.to_string().contains("d_extra: dest"),
+        "Unexpected error: {}",
+        err
+    );
+    assert!(err.to_string().contains("overlaps its src"));
+}
+
+#[test]
+fn test_profile_target_inside_dotfiles_fails() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("dotfiles/f_app", "app");
+    fixture.add_package(
+        "f_app",
+        &fixture.path("home/app"),
+        &[("work", "dotfiles/f_app_work")],
+    );
+
+    let err = fixture.deploy(false).expect_err("Deploy should fail");
+    assert!(err.to_string().contains("dotfiles/f_app_work"));
+}
+
+#[test]
+fn test_shared_dest_fails_and_names_both_packages() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("dotfiles/f_one", "one");
+    fixture.write_file("dotfiles/f_two", "two");
+    fixture.add_package("f_one", &fixture.path("home/app.conf"), &[]);
+    fixture.add_package("f_two", &fixture.path("home/./app.conf"), &[]);
+
+    let err = fixture.deploy(false).expect_err("Deploy should fail");
+    assert!(
+        err.to_string().contains("f_one, f_two: all deploy to"),
+        "Unexpected error: {}",
+        err
+    );
+    assert!(!fixture.cwd.join("home/app.conf").exists());
+}
+
+#[test]
+fn test_skip_validation() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("dotfiles/f_one", "one");
+    fixture.write_file("dotfiles/f_two", "two");
+    fixture.add_package("f_one", &fixture.path("home/app.conf"), &[]);
+    fixture.add_package("f_two", &fixture.path("home/app.conf"), &[]);
+
+    fixture
+        .deploy(true)
+        .expect("Deploy should go ahead with --skip-validation");
+    assert!(fixture.cwd.join("home/app.conf").exists());
+}
+
+#[test]
+fn test_valid_packages_deploy() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("dotfiles/d_app/app.conf", "app");
+    fixture.add_package(
+        "d_app",
+        &fixture.path("home/app"),
+        &[("work", &fixture.path("work/app"))],
+    );
+
+    fixture.deploy(false).expect("Deploy should succeed");
+    fixture.update().expect("Update should succeed");
+}
+
+#[test]
+fn test_file_written_by_two_packages_warns() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.add_nested_packages();
+
+    fixture
+        .deploy(false)
+        .expect("Shared files are only a warning by default");
+    assert!(fixture.cwd.join("home/config/kitty/kitty.conf").exists());
+}
+
+#[test]
+fn test_file_written_by_two_packages_fails_with_strict() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.add_nested_packages();
+
+    let err = fixture.deploy_strict().expect_err("Deploy should fail");
+    let message = err.to_string();
+    assert!(
+        message.contains("d_config, f_kitty all write"),
+        "Unexpected error: {}",
+        message
+    );
+    assert!(
+        message.contains("kitty.conf"),
+        "Unexpected error: {}",
+        message
+    );
+    assert!(!message.contains("git"), "Unexpected error: {}", message);
+    assert!(!fixture.cwd.join("home/config").exists());
+
+    let err = fixture.diff_strict().expect_err("Diff should fail too");
+    assert!(err.to_string().contains("d_config, f_kitty all write"));
+}
+
+#[test]
+fn test_shared_file_check_respects_ignore_and_skip_validation() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.add_nested_packages();
+    let mut config = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    config.strict = true;
+    config.save(&fixture.cwd).expect("Failed to save config");
+    run_cli(fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs {
+        skip_validation: true,
+        ..Default::default()
+    }))))
+    .expect("--skip-validation skips the check");
+
+    let mut config = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    config.packages.get_mut("d_config").unwrap().ignore = vec!["kitty/**".to_string()];
+    config.save(&fixture.cwd).expect("Failed to save config");
+    fixture
+        .deploy(false)
+        .expect("An ignored file isn't written by d_config");
+}
@@ -0,0 +1,267 @@
+#![cfg(unix)]
+
+use std::{
+    fs,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::PathBuf,
+};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    config::Config,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_perm_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+
+    fn deploy(&self) {
+        self.try_deploy().expect("Deploy failed");
+    }
+
+    fn try_deploy(&self) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs {
+            packages: None,
+            profile: None,
+            ..Default::default()
+        }))))
+    }
+
+    fn add_package(&self, name: &str, dest: &str) {
+        self.add_package_with(name, dest, &[]);
+    }
+
+    // `extra` holds more string fields of the package, like file_mode
+    fn add_package_with(&self, name: &str, dest: &str, extra: &[(&str, &str)]) {
+        let mut config = Config::from_path(&self.cwd).expect("Failed to load config");
+        let mut table = toml::Table::new();
+        table.insert(
+            "src".to_string(),
+            toml::Value::String(format!("dotfiles/{}", name)),
+        );
+        table.insert("dest".to_string(), toml::Value::String(dest.to_string()));
+        for (key, value) in extra {
+            table.insert(key.to_string(), toml::Value::String(value.to_string()));
+        }
+        let package =
+            dotr::package::Package::from_table(name, &table).expect("Failed to build package");
+        config.packages.insert(name.to_string(), package);
+        config.save(&self.cwd).expect("Failed to save config");
+    }
+
+    fn write_with_mode(&self, path: &str, content: &str, mode: u32) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(&file_path, content).expect("Failed to write file");
+        set_mode(&file_path, mode);
+    }
+
+    fn mode(&self, path: &str) -> u32 {
+        fs::metadata(self.cwd.join(path))
+            .expect("Failed to stat file")
+            .permissions()
+            .mode()
+            & 0o7777
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+fn set_mode(path: &PathBuf, mode: u32) {
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).expect("Failed to set mode");
+}
+
+#[test]
+fn test_deploy_preserves_executable_bit() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_with_mode("dotfiles/d_bin/hello.sh", "#!/bin/sh\necho hi\n", 0o755);
+    fixture.add_package("d_bin", "bin");
+
+    fixture.deploy();
+    assert_eq!(
+        fixture.mode("bin/hello.sh"),
+        0o755,
+        "Deployed script should keep its mode"
+    );
+
+    // A second deploy is a no-op for content, mode must stay intact
+    fixture.deploy();
+    assert_eq!(fixture.mode("bin/hello.sh"), 0o755);
+}
+
+#[test]
+fn test_deploy_fixes_permissions_when_content_matches() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_with_mode("dotfiles/f_script", "#!/bin/sh\necho hi\n", 0o755);
+    fixture.add_package("f_script", "script.sh");
+
+    fixture.deploy();
+    set_mode(&fixture.cwd.join("script.sh"), 0o644);
+
+    fixture.deploy();
+    assert_eq!(
+        fixture.mode("script.sh"),
+        0o755,
+        "Permissions should be restored even when content is unchanged"
+    );
+    assert!(
+        !fixture.cwd.join("script.sh.dotrbak").exists(),
+        "Permission-only updates should not create a backup"
+    );
+}
+
+#[test]
+fn test_backup_keeps_original_mode() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_with_mode("dotfiles/f_tool", "new content\n", 0o644);
+    fixture.write_with_mode("tool.conf", "old content\n", 0o700);
+    fixture.add_package("f_tool", "tool.conf");
+
+    fixture.deploy();
+    assert_eq!(fixture.mode("tool.conf"), 0o644);
+    assert_eq!(
+        fixture.mode("tool.conf.dotrbak"),
+        0o700,
+        "Backup should keep the mode of the replaced file"
+    );
+}
+
+#[test]
+fn test_binary_file_keeps_mode() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    let bin_path = fixture.cwd.join("dotfiles/f_blob");
+    fs::write(&bin_path, [0xff, 0xfe, 0x00, 0x80]).expect("Failed to write binary");
+    set_mode(&bin_path, 0o750);
+    fixture.add_package("f_blob", "blob.bin");
+
+    fixture.deploy();
+    assert_eq!(fixture.mode("blob.bin"), 0o750);
+}
+
+#[test]
+fn test_file_and_dir_modes_override_src() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_with_mode("dotfiles/d_etc/app.conf", "a=1\n", 0o755);
+    fixture.write_with_mode("dotfiles/d_etc/conf.d/extra.conf", "b=2\n", 0o600);
+    fixture.add_package_with(
+        "d_etc",
+        "etc/app",
+        &[("file_mode", "0644"), ("dir_mode", "0750")],
+    );
+
+    fixture.deploy();
+    assert_eq!(fixture.mode("etc/app/app.conf"), 0o644);
+    assert_eq!(fixture.mode("etc/app/conf.d/extra.conf"), 0o644);
+    assert_eq!(fixture.mode("etc/app"), 0o750);
+    assert_eq!(fixture.mode("etc/app/conf.d"), 0o750);
+    assert_eq!(
+        fixture.mode("etc"),
+        0o750,
+        "Created parents get dir_mode too"
+    );
+
+    // Modes drifting on disk are put back, without a backup
+    set_mode(&fixture.cwd.join("etc/app/app.conf"), 0o600);
+    fixture.deploy();
+    assert_eq!(fixture.mode("etc/app/app.conf"), 0o644);
+    assert!(!fixture.cwd.join("etc/app/app.conf.dotrbak").exists());
+}
+
+#[test]
+fn test_invalid_modes_are_rejected() {
+    for mode in ["999", "0o644", "64", "07777a", "12345"] {
+        let mut table = toml::Table::new();
+        table.insert("src".to_string(), "dotfiles/f_rc".into());
+        table.insert("dest".to_string(), "rc".into());
+        table.insert("file_mode".to_string(), mode.into());
+        let err = dotr::package::Package::from_table("f_rc", &table)
+            .expect_err("Invalid mode should fail");
+        assert!(err.to_string().contains("octal mode"), "{}", err);
+    }
+}
+
+#[test]
+fn test_modes_and_owner_round_trip() {
+    let mut table = toml::Table::new();
+    table.insert("src".to_string(), "dotfiles/f_rc".into());
+    table.insert("dest".to_string(), "/etc/rc".into());
+    table.insert("file_mode".to_string(), "644".into());
+    table.insert("dir_mode".to_string(), "0755".into());
+    table.insert("owner".to_string(), "root".into());
+    table.insert("group".to_string(), "wheel".into());
+    let package = dotr::package::Package::from_table("f_rc", &table).unwrap();
+    assert_eq!(package.file_mode, Some(0o644));
+
+    let saved = package.to_table();
+    assert_eq!(saved["file_mode"].as_str(), Some("0644"));
+    assert_eq!(saved["dir_mode"].as_str(), Some("0755"));
+    assert_eq!(saved["owner"].as_str(), Some("root"));
+    assert_eq!(saved["group"].as_str(), Some("wheel"));
+}
+
+#[test]
+fn test_owner_needs_privileges() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_with_mode("dotfiles/f_rc", "a=1\n", 0o644);
+    fixture.add_package_with("f_rc", "rc", &[("owner", "0"), ("group", "0")]);
+
+    let is_root = fs::metadata(fixture.cwd.join("dotfiles/f_rc"))
+        .unwrap()
+        .uid()
+        == 0;
+    let result = fixture.try_deploy();
+    if is_root {
+        result.expect("Deploy as root should chown");
+        assert_eq!(fs::metadata(fixture.cwd.join("rc")).unwrap().uid(), 0);
+    } else {
+        let err = result.expect_err("Chown without privileges should fail");
+        assert!(err.to_string().contains("sudo"), "{}", err);
+    }
+}
+
+#[test]
+fn test_unknown_owner_is_an_error() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_with_mode("dotfiles/f_rc", "a=1\n", 0o644);
+    fixture.add_package_with("f_rc", "rc", &[("owner", "no_such_dotr_user")]);
+
+    let err = fixture.try_deploy().expect_err("Unknown user should fail");
+    assert!(
+        err.to_string().contains("Unknown user 'no_such_dotr_user'"),
+        "{}",
+        err
+    );
+}
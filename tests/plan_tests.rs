@@ -0,0 +1,146 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    plan::{Plan, PlanAction},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_plan_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            "banner = false\n\n[variables]\nname = \"world\"\n\n[packages.d_app]\nsrc = \"dotfiles/app\"\ndest = \"home/app\"\npost_actions = [\"touch ran\"]\n",
+        )
+        .expect("Failed to write config");
+        fixture.write("dotfiles/app/greeting", "hello {{ name }}\n");
+        fixture.write("dotfiles/app/same", "same\n");
+        fixture.write("dotfiles/app/changed", "new\n");
+        fixture.write("home/app/same", "same\n");
+        fixture.write("home/app/changed", "old\n");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn plan(&self) -> Plan {
+        self.run(Command::Deploy(DeployUpdateArgs {
+            plan: Some("plan.json".to_string()),
+            ..Default::default()
+        }))
+        .expect("Plan failed");
+        Plan::load(&self.cwd.join("plan.json")).expect("Failed to load plan")
+    }
+
+    fn apply(&self) -> Result<(), anyhow::Error> {
+        self.run(Command::Deploy(DeployUpdateArgs {
+            apply: Some("plan.json".to_string()),
+            ..Default::default()
+        }))
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).unwrap()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_plan_lists_actions_without_deploying() {
+    let fixture = TestFixture::new();
+    let plan = fixture.plan();
+
+    let action = |name: &str| {
+        plan.files
+            .iter()
+            .find(|f| f.dest.ends_with(name))
+            .map(|f| f.action)
+    };
+    assert_eq!(action("greeting"), Some(PlanAction::Create));
+    assert_eq!(action("same"), Some(PlanAction::Skip));
+    assert_eq!(action("changed"), Some(PlanAction::Update));
+    assert!(plan.files.iter().all(|f| f.package == "d_app"));
+
+    assert!(!fixture.cwd.join("home/app/greeting").exists());
+    assert_eq!(fixture.read("home/app/changed"), "old\n");
+    assert!(!fixture.cwd.join("ran").exists());
+    // Plain JSON for other tools to read
+    let json: serde_json::Value = serde_json::from_str(&fixture.read("plan.json")).unwrap();
+    assert_eq!(json["files"][0]["action"], "update");
+}
+
+#[test]
+fn test_apply_writes_the_planned_files() {
+    let fixture = TestFixture::new();
+    fixture.plan();
+
+    fixture.apply().expect("Apply failed");
+    assert_eq!(fixture.read("home/app/greeting"), "hello world\n");
+    assert_eq!(fixture.read("home/app/changed"), "new\n");
+    assert_eq!(fixture.read("home/app/changed.dotrbak"), "old\n");
+    // Only the files, never the actions
+    assert!(!fixture.cwd.join("ran").exists());
+}
+
+#[test]
+fn test_apply_refuses_a_dest_that_drifted() {
+    let fixture = TestFixture::new();
+    fixture.plan();
+    fixture.write("home/app/changed", "edited after planning\n");
+
+    let err = fixture.apply().unwrap_err().to_string();
+    assert!(err.contains("changed since the plan was made"), "{}", err);
+    assert!(err.contains("home/app/changed"), "{}", err);
+    // Nothing was written, not even the files that didn't drift
+    assert!(!fixture.cwd.join("home/app/greeting").exists());
+    assert_eq!(fixture.read("home/app/changed"), "edited after planning\n");
+}
+
+#[test]
+fn test_apply_refuses_a_dest_created_since_planning() {
+    let fixture = TestFixture::new();
+    fixture.plan();
+    fixture.write("home/app/greeting", "hi\n");
+
+    let err = fixture.apply().unwrap_err().to_string();
+    assert!(err.contains("greeting"), "{}", err);
+    assert_eq!(fixture.read("home/app/greeting"), "hi\n");
+}
+
+#[test]
+fn test_apply_refuses_a_src_that_renders_differently() {
+    let fixture = TestFixture::new();
+    fixture.plan();
+    fixture.write("dotfiles/app/greeting", "goodbye {{ name }}\n");
+
+    let err = fixture.apply().unwrap_err().to_string();
+    assert!(err.contains("no longer renders"), "{}", err);
+    assert!(!fixture.cwd.join("home/app/greeting").exists());
+}
@@ -0,0 +1,203 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    config::Config,
+    context::{Context, current_os},
+    package::Package,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_platform_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+
+    fn deploy(&self) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))))
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+fn package_for(only_on: &str, except_on: &str) -> Package {
+    let table: toml::Table = format!(
+        "src = \"dotfiles/f_rc\"\ndest = \"rc\"\nonly_on = {}\nexcept_on = {}\n",
+        only_on, except_on
+    )
+    .parse()
+    .expect("Invalid TOML");
+    Package::from_table("f_rc", &table).expect("Failed to parse package")
+}
+
+#[test]
+fn test_system_variables_are_populated() {
+    let fixture = TestFixture::new();
+    let ctx = Context::new(&fixture.cwd).expect("Failed to create context");
+
+    assert_eq!(
+        ctx.lookup_variable("dotr.os"),
+        Some(toml::Value::String(current_os()))
+    );
+    assert_eq!(
+        ctx.lookup_variable("dotr.arch"),
+        Some(toml::Value::String(std::env::consts::ARCH.to_string()))
+    );
+    assert!(ctx.lookup_variable("dotr.hostname").is_some());
+    assert!(ctx.lookup_variable("dotr.username").is_some());
+}
+
+#[test]
+fn test_system_variables_in_templates() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file(
+        "config.toml",
+        r#"
+banner = false
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "rc"
+"#,
+    );
+    fixture.write_file(
+        "dotfiles/f_rc",
+        "os={{ dotr.os }}\n{% if dotr.os == \"windows\" %}win{% else %}posix{% endif %}\n",
+    );
+
+    fixture.deploy().expect("Deploy failed");
+    let expected_branch = if current_os() == "windows" {
+        "win"
+    } else {
+        "posix"
+    };
+    assert_eq!(
+        fixture.read_file("rc"),
+        format!("os={}\n{}\n", current_os(), expected_branch)
+    );
+}
+
+#[test]
+fn test_applies_to_os() {
+    let pkg = package_for("[\"macos\"]", "[]");
+    assert!(pkg.applies_to_os("macos"));
+    assert!(!pkg.applies_to_os("linux"));
+
+    let pkg = package_for("[]", "[\"windows\"]");
+    assert!(pkg.applies_to_os("linux"));
+    assert!(!pkg.applies_to_os("windows"));
+
+    let pkg = package_for("[\"linux\", \"macos\"]", "[\"macos\"]");
+    assert!(pkg.applies_to_os("linux"));
+    assert!(!pkg.applies_to_os("macos"));
+
+    let table = pkg.to_table();
+    assert!(table.contains_key("only_on"));
+    assert!(table.contains_key("except_on"));
+}
+
+#[test]
+fn test_filter_packages_skips_other_os() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file(
+        "config.toml",
+        &format!(
+            r#"
+banner = false
+
+[packages.here]
+pre_actions = ["echo here"]
+only_on = ["{os}"]
+
+[packages.elsewhere]
+pre_actions = ["echo elsewhere"]
+except_on = ["{os}"]
+
+[packages.anywhere]
+pre_actions = ["echo anywhere"]
+dependencies = ["elsewhere"]
+"#,
+            os = current_os()
+        ),
+    );
+
+    let config = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    let ctx = Context::new(&fixture.cwd).expect("Failed to create context");
+    let packages = config
+        .filter_packages(&ctx, &None)
+        .expect("Filtering failed");
+    let mut names: Vec<&String> = packages.keys().collect();
+    names.sort();
+    assert_eq!(names, vec!["anywhere", "here"]);
+}
+
+#[test]
+fn test_reserved_name_is_rejected() {
+    let fixture = TestFixture::new();
+    fixture.init();
+
+    fixture.write_file(".uservariables.toml", "[dotr]\nos = \"plan9\"\n");
+    let err = Context::new(&fixture.cwd).expect_err("Reserved name should be rejected");
+    assert!(err.to_string().contains("reserved"));
+    fs::remove_file(fixture.cwd.join(".uservariables.toml")).unwrap();
+
+    fixture.write_file("config.toml", "banner = false\n[variables]\ndotr = 1\n");
+    let err = Config::from_path(&fixture.cwd).expect_err("Reserved name should be rejected");
+    assert!(err.to_string().contains("config variables"));
+
+    let table: toml::Table = "pre_actions = [\"true\"]\n[variables]\ndotr = 1\n"
+        .parse()
+        .unwrap();
+    let err = Package::from_table("p", &table).expect_err("Reserved name should be rejected");
+    assert!(err.to_string().contains("package 'p'"));
+}
+
+#[test]
+fn test_system_variables_win_over_environment() {
+    let fixture = TestFixture::new();
+    let mut ctx = Context::new(&fixture.cwd).expect("Failed to create context");
+    let mut fake = toml::Table::new();
+    fake.insert("dotr".to_string(), toml::Value::String("env".to_string()));
+    ctx.extend_variables(fake);
+
+    assert!(ctx.get_context_variables()["dotr"].is_table());
+    assert!(ctx.get_context_variable("dotr").unwrap().is_table());
+    ctx.print_variables(None);
+    ctx.print_variables_with_sources(None, false);
+}
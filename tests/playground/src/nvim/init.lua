@@ -1,3 +0,0 @@
--- Neovim configuration
-vim.opt.number = true
-vim.opt.expandtab = true
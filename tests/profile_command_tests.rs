@@ -0,0 +1,199 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, InitArgs, ProfileArgs, ProfileCommand, run_cli},
+    config::Config,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_profile_cmd_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        run_cli(fixture.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+        fs::write(fixture.cwd.join("config.toml"), CONFIG).expect("Failed to write config");
+        fixture
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn profile(&self, command: ProfileCommand) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Profile(ProfileArgs { command }))))
+    }
+
+    fn get_config(&self) -> Config {
+        Config::from_path(&self.cwd).expect("Failed to load config")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[packages.f_git]
+pre_actions = ["true"]
+
+[packages.f_zsh]
+pre_actions = ["true"]
+
+[packages.f_ssh]
+pre_actions = ["true"]
+[packages.f_ssh.targets]
+work = "~/.ssh-work"
+
+[profiles.work]
+dependencies = ["f_git"]
+[profiles.work.variables]
+EMAIL = "me@work.example"
+"#;
+
+fn strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn test_list_and_show() {
+    let fixture = TestFixture::new();
+    fixture.profile(ProfileCommand::List).expect("List failed");
+    fixture
+        .profile(ProfileCommand::Show {
+            name: "work".to_string(),
+        })
+        .expect("Show failed");
+
+    let err = fixture
+        .profile(ProfileCommand::Show {
+            name: "nope".to_string(),
+        })
+        .expect_err("Showing a missing profile should fail");
+    assert!(err.to_string().contains("not found"));
+}
+
+#[test]
+fn test_create_profile() {
+    let fixture = TestFixture::new();
+    fixture
+        .profile(ProfileCommand::Create {
+            name: "home".to_string(),
+        })
+        .expect("Create failed");
+
+    let config = fixture.get_config();
+    assert!(config.profiles["home"].dependencies.is_empty());
+
+    let err = fixture
+        .profile(ProfileCommand::Create {
+            name: "home".to_string(),
+        })
+        .expect_err("Creating a duplicate should fail");
+    assert!(err.to_string().contains("already exists"));
+}
+
+#[test]
+fn test_add_packages_skips_duplicates() {
+    let fixture = TestFixture::new();
+    fixture
+        .profile(ProfileCommand::Add {
+            name: "work".to_string(),
+            packages: strings(&["f_zsh", "f_git", "f_ssh"]),
+        })
+        .expect("Add failed");
+
+    assert_eq!(
+        fixture.get_config().profiles["work"].dependencies,
+        strings(&["f_git", "f_zsh", "f_ssh"])
+    );
+    assert_eq!(
+        fixture.get_config().profiles["work"].variables["EMAIL"].as_str(),
+        Some("me@work.example"),
+        "Other profile fields are kept"
+    );
+}
+
+#[test]
+fn test_add_unknown_package_changes_nothing() {
+    let fixture = TestFixture::new();
+    let err = fixture
+        .profile(ProfileCommand::Add {
+            name: "work".to_string(),
+            packages: strings(&["f_zsh", "f_missing"]),
+        })
+        .expect_err("Unknown package should fail");
+    assert!(err.to_string().contains("f_missing"));
+    assert_eq!(
+        fixture.get_config().profiles["work"].dependencies,
+        strings(&["f_git"])
+    );
+}
+
+#[test]
+fn test_remove_packages() {
+    let fixture = TestFixture::new();
+    fixture
+        .profile(ProfileCommand::Remove {
+            name: "work".to_string(),
+            packages: strings(&["f_git", "f_zsh"]),
+        })
+        .expect("Remove failed");
+    assert!(
+        fixture.get_config().profiles["work"]
+            .dependencies
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_delete_refuses_targeted_profile_without_force() {
+    let fixture = TestFixture::new();
+    let err = fixture
+        .profile(ProfileCommand::Delete {
+            name: "work".to_string(),
+            force: false,
+        })
+        .expect_err("Delete should be refused");
+    assert!(err.to_string().contains("f_ssh"));
+    assert!(fixture.get_config().profiles.contains_key("work"));
+
+    fixture
+        .profile(ProfileCommand::Delete {
+            name: "work".to_string(),
+            force: true,
+        })
+        .expect("Forced delete failed");
+    let config = fixture.get_config();
+    assert!(!config.profiles.contains_key("work"));
+    assert!(config.packages["f_ssh"].targets.is_empty());
+}
+
+#[test]
+fn test_delete_refuses_extended_profile() {
+    let fixture = TestFixture::new();
+    let mut config = fixture.get_config();
+    config.create_profile("laptop").unwrap();
+    config.profiles.get_mut("laptop").unwrap().extends = Some("work".to_string());
+    config.save(&fixture.cwd).unwrap();
+
+    let err = fixture
+        .profile(ProfileCommand::Delete {
+            name: "work".to_string(),
+            force: true,
+        })
+        .expect_err("Delete of an extended profile should be refused");
+    assert!(err.to_string().contains("extended by: laptop"));
+}
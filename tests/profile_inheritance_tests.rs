@@ -0,0 +1,115 @@
+use dotr::config::Config;
+
+fn parse_config(content: &str) -> Result<Config, anyhow::Error> {
+    let table = content.parse::<toml::Table>().expect("Invalid TOML");
+    Config::from_table(&table)
+}
+
+const CHAIN: &str = r#"
+banner = false
+
+[profiles.base]
+dependencies = ["f_git", "f_zsh"]
+[profiles.base.variables]
+EMAIL = "me@example.com"
+EDITOR = "vim"
+[profiles.base.prompts]
+TOKEN = "Base token?"
+
+[profiles.work]
+extends = "base"
+dependencies = ["f_vpn", "f_git"]
+[profiles.work.variables]
+EMAIL = "me@work.example"
+
+[profiles.work-laptop]
+extends = "work"
+dependencies = ["f_battery"]
+[profiles.work-laptop.variables]
+EDITOR = "nvim"
+[profiles.work-laptop.prompts]
+TOKEN = "Laptop token?"
+"#;
+
+#[test]
+fn test_two_level_chain_merges_dependencies() {
+    let config = parse_config(CHAIN).expect("Failed to parse config");
+    let profile = config
+        .resolve_profile("work-laptop")
+        .expect("Failed to resolve")
+        .expect("Profile should exist");
+
+    assert_eq!(profile.name, "work-laptop");
+    assert_eq!(
+        profile.dependencies,
+        vec!["f_git", "f_zsh", "f_vpn", "f_battery"],
+        "Parent dependencies come first, without duplicates"
+    );
+}
+
+#[test]
+fn test_child_variables_and_prompts_override_parent() {
+    let config = parse_config(CHAIN).expect("Failed to parse config");
+    let profile = config
+        .resolve_profile("work-laptop")
+        .expect("Failed to resolve")
+        .expect("Profile should exist");
+
+    assert_eq!(profile.variables["EMAIL"].as_str(), Some("me@work.example"));
+    assert_eq!(profile.variables["EDITOR"].as_str(), Some("nvim"));
+    assert_eq!(profile.prompts["TOKEN"].message, "Laptop token?");
+
+    let work = config.resolve_profile("work").unwrap().unwrap();
+    assert_eq!(work.variables["EDITOR"].as_str(), Some("vim"));
+    assert_eq!(work.prompts["TOKEN"].message, "Base token?");
+}
+
+#[test]
+fn test_extends_is_persisted_not_flattened() {
+    let config = parse_config(CHAIN).expect("Failed to parse config");
+    let table = config.to_table();
+    let laptop = table["profiles"]["work-laptop"]
+        .as_table()
+        .expect("Profile should be a table");
+
+    assert_eq!(laptop["extends"].as_str(), Some("work"));
+    assert_eq!(
+        laptop["dependencies"].as_array().map(|d| d.len()),
+        Some(1),
+        "Only the profile's own dependencies are saved"
+    );
+    parse_config(&table.to_string()).expect("Saved config should parse again");
+}
+
+#[test]
+fn test_inheritance_cycle_is_rejected() {
+    let err = parse_config(
+        r#"
+[profiles.a]
+extends = "b"
+[profiles.b]
+extends = "c"
+[profiles.c]
+extends = "b"
+"#,
+    )
+    .expect_err("Cycle should be rejected");
+    assert!(
+        err.to_string().contains("Circular profile inheritance"),
+        "Unexpected error: {}",
+        err
+    );
+
+    let err = parse_config("[profiles.a]\nextends = \"a\"\n").expect_err("Self-reference");
+    assert!(err.to_string().contains("a extends a"));
+}
+
+#[test]
+fn test_missing_parent_is_rejected() {
+    let err = parse_config("[profiles.a]\nextends = \"ghost\"\n")
+        .expect_err("Missing parent should be rejected");
+    assert_eq!(
+        err.to_string(),
+        "Profile 'a' extends 'ghost', which is not defined"
+    );
+}
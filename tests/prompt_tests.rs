@@ -8,15 +8,13 @@ use dotr::{
 
 mod common;
 
-const PLAYGROUND_DIR: &str = "tests/playground";
-
 struct TestFixture {
     cwd: PathBuf,
 }
 
 impl TestFixture {
     fn new() -> Self {
-        let cwd = PathBuf::from(PLAYGROUND_DIR);
+        let cwd = std::env::temp_dir().join(format!("dotr_prompt_test_{}", uuid::Uuid::new_v4()));
         common::setup(&cwd);
         Self { cwd }
     }
@@ -24,12 +22,14 @@ impl TestFixture {
     fn get_cli(&self, command: Option<dotr::cli::Command>) -> dotr::cli::Cli {
         dotr::cli::Cli {
             command,
-            working_dir: Some(PLAYGROUND_DIR.to_string()),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
         }
     }
 
     fn init(&self) {
-        run_cli(self.get_cli(Some(dotr::cli::Command::Init(InitArgs {})))).expect("Init failed");
+        run_cli(self.get_cli(Some(dotr::cli::Command::Init(InitArgs::default()))))
+            .expect("Init failed");
     }
 
     fn get_config(&self) -> Config {
@@ -43,7 +43,7 @@ impl TestFixture {
 
 impl Drop for TestFixture {
     fn drop(&mut self) {
-        common::teardown(&self.cwd);
+        fs::remove_dir_all(&self.cwd).ok();
     }
 }
 
@@ -56,23 +56,30 @@ fn test_prompts_in_config() {
     let mut config = fixture.get_config();
     config.prompts.insert(
         "USER_EMAIL".to_string(),
-        "Enter your email address".to_string(),
+        "Enter your email address".to_string().into(),
+    );
+    config.prompts.insert(
+        "USER_NAME".to_string(),
+        "Enter your full name".to_string().into(),
     );
-    config
-        .prompts
-        .insert("USER_NAME".to_string(), "Enter your full name".to_string());
     config.save(&fixture.cwd).expect("Failed to save config");
 
     // Reload and verify prompts are saved
     let reloaded_config = fixture.get_config();
     assert_eq!(reloaded_config.prompts.len(), 2);
     assert_eq!(
-        reloaded_config.prompts.get("USER_EMAIL"),
-        Some(&"Enter your email address".to_string())
+        reloaded_config
+            .prompts
+            .get("USER_EMAIL")
+            .map(|p| p.message.as_str()),
+        Some("Enter your email address")
     );
     assert_eq!(
-        reloaded_config.prompts.get("USER_NAME"),
-        Some(&"Enter your full name".to_string())
+        reloaded_config
+            .prompts
+            .get("USER_NAME")
+            .map(|p| p.message.as_str()),
+        Some("Enter your full name")
     );
 }
 
@@ -83,16 +90,20 @@ fn test_prompts_persist_after_save() {
 
     // Add prompts
     let mut config = fixture.get_config();
-    config
-        .prompts
-        .insert("API_KEY".to_string(), "Enter your API key".to_string());
+    config.prompts.insert(
+        "API_KEY".to_string(),
+        "Enter your API key".to_string().into(),
+    );
     config.save(&fixture.cwd).expect("Failed to save config");
 
     // Reload and verify
     let reloaded_config = fixture.get_config();
     assert_eq!(
-        reloaded_config.prompts.get("API_KEY"),
-        Some(&"Enter your API key".to_string())
+        reloaded_config
+            .prompts
+            .get("API_KEY")
+            .map(|p| p.message.as_str()),
+        Some("Enter your API key")
     );
 }
 
@@ -117,15 +128,15 @@ fn test_multiple_prompts() {
     let mut config = fixture.get_config();
     config.prompts.insert(
         "GITHUB_TOKEN".to_string(),
-        "Enter your GitHub personal access token".to_string(),
+        "Enter your GitHub personal access token".to_string().into(),
     );
     config.prompts.insert(
         "OPENAI_API_KEY".to_string(),
-        "Enter your OpenAI API key".to_string(),
+        "Enter your OpenAI API key".to_string().into(),
     );
     config.prompts.insert(
         "AWS_ACCESS_KEY".to_string(),
-        "Enter your AWS access key".to_string(),
+        "Enter your AWS access key".to_string().into(),
     );
     config.save(&fixture.cwd).expect("Failed to save config");
 
@@ -141,11 +152,13 @@ fn test_prompts_with_special_characters() {
     let mut config = fixture.get_config();
     config.prompts.insert(
         "SPECIAL_VAR".to_string(),
-        "Enter value (e.g., user@example.com)".to_string(),
+        "Enter value (e.g., user@example.com)".to_string().into(),
     );
     config.prompts.insert(
         "COMPLEX_PROMPT".to_string(),
-        "What's your API key? [Leave empty to skip]".to_string(),
+        "What's your API key? [Leave empty to skip]"
+            .to_string()
+            .into(),
     );
     config.save(&fixture.cwd).expect("Failed to save config");
 
@@ -226,9 +239,10 @@ fn test_prompts_do_not_interfere_with_variables() {
     );
 
     // Add prompts
-    config
-        .prompts
-        .insert("PROMPT_VAR".to_string(), "Enter prompt value".to_string());
+    config.prompts.insert(
+        "PROMPT_VAR".to_string(),
+        "Enter prompt value".to_string().into(),
+    );
 
     config.save(&fixture.cwd).expect("Failed to save config");
 
@@ -245,13 +259,16 @@ fn test_prompts_with_empty_message() {
     let mut config = fixture.get_config();
     config
         .prompts
-        .insert("VAR_WITH_EMPTY_MSG".to_string(), "".to_string());
+        .insert("VAR_WITH_EMPTY_MSG".to_string(), "".to_string().into());
     config.save(&fixture.cwd).expect("Failed to save config");
 
     let reloaded_config = fixture.get_config();
     assert_eq!(
-        reloaded_config.prompts.get("VAR_WITH_EMPTY_MSG"),
-        Some(&"".to_string())
+        reloaded_config
+            .prompts
+            .get("VAR_WITH_EMPTY_MSG")
+            .map(|p| p.message.as_str()),
+        Some("")
     );
 }
 
@@ -262,12 +279,13 @@ fn test_prompts_removal() {
 
     // Add prompts
     let mut config = fixture.get_config();
+    config.prompts.insert(
+        "TO_REMOVE".to_string(),
+        "This will be removed".to_string().into(),
+    );
     config
         .prompts
-        .insert("TO_REMOVE".to_string(), "This will be removed".to_string());
-    config
-        .prompts
-        .insert("TO_KEEP".to_string(), "This will stay".to_string());
+        .insert("TO_KEEP".to_string(), "This will stay".to_string().into());
     config.save(&fixture.cwd).expect("Failed to save config");
 
     // Remove one prompt
@@ -291,21 +309,24 @@ fn test_prompts_update_message() {
     let mut config = fixture.get_config();
     config
         .prompts
-        .insert("VAR".to_string(), "Old message".to_string());
+        .insert("VAR".to_string(), "Old message".to_string().into());
     config.save(&fixture.cwd).expect("Failed to save config");
 
     // Update prompt message
     let mut config = fixture.get_config();
     config
         .prompts
-        .insert("VAR".to_string(), "New message".to_string());
+        .insert("VAR".to_string(), "New message".to_string().into());
     config.save(&fixture.cwd).expect("Failed to save config");
 
     // Verify
     let reloaded_config = fixture.get_config();
     assert_eq!(
-        reloaded_config.prompts.get("VAR"),
-        Some(&"New message".to_string())
+        reloaded_config
+            .prompts
+            .get("VAR")
+            .map(|p| p.message.as_str()),
+        Some("New message")
     );
 }
 
@@ -317,14 +338,19 @@ fn test_prompts_with_multiline_message() {
     let mut config = fixture.get_config();
     config.prompts.insert(
         "MULTILINE_VAR".to_string(),
-        "Enter your API key\n(You can find it in your account settings)".to_string(),
+        "Enter your API key\n(You can find it in your account settings)"
+            .to_string()
+            .into(),
     );
     config.save(&fixture.cwd).expect("Failed to save config");
 
     let reloaded_config = fixture.get_config();
     assert_eq!(
-        reloaded_config.prompts.get("MULTILINE_VAR"),
-        Some(&"Enter your API key\n(You can find it in your account settings)".to_string())
+        reloaded_config
+            .prompts
+            .get("MULTILINE_VAR")
+            .map(|p| p.message.as_str()),
+        Some("Enter your API key\n(You can find it in your account settings)")
     );
 }
 
@@ -346,22 +372,29 @@ fn test_prompts_with_unicode() {
     let mut config = fixture.get_config();
     config.prompts.insert(
         "UNICODE_VAR".to_string(),
-        "请输入你的名字 (Enter your name)".to_string(),
+        "请输入你的名字 (Enter your name)".to_string().into(),
+    );
+    config.prompts.insert(
+        "EMOJI_VAR".to_string(),
+        "🔑 Enter your API key".to_string().into(),
     );
-    config
-        .prompts
-        .insert("EMOJI_VAR".to_string(), "🔑 Enter your API key".to_string());
     config.save(&fixture.cwd).expect("Failed to save config");
 
     let reloaded_config = fixture.get_config();
     assert_eq!(reloaded_config.prompts.len(), 2);
     assert_eq!(
-        reloaded_config.prompts.get("UNICODE_VAR"),
-        Some(&"请输入你的名字 (Enter your name)".to_string())
+        reloaded_config
+            .prompts
+            .get("UNICODE_VAR")
+            .map(|p| p.message.as_str()),
+        Some("请输入你的名字 (Enter your name)")
     );
     assert_eq!(
-        reloaded_config.prompts.get("EMOJI_VAR"),
-        Some(&"🔑 Enter your API key".to_string())
+        reloaded_config
+            .prompts
+            .get("EMOJI_VAR")
+            .map(|p| p.message.as_str()),
+        Some("🔑 Enter your API key")
     );
 }
 
@@ -374,7 +407,7 @@ fn test_many_prompts() {
     for i in 0..20 {
         config.prompts.insert(
             format!("VAR_{}", i),
-            format!("Enter value for variable {}", i),
+            format!("Enter value for variable {}", i).into(),
         );
     }
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -389,12 +422,14 @@ fn test_prompts_are_case_sensitive() {
     fixture.init();
 
     let mut config = fixture.get_config();
-    config
-        .prompts
-        .insert("api_key".to_string(), "Enter lowercase API key".to_string());
-    config
-        .prompts
-        .insert("API_KEY".to_string(), "Enter uppercase API KEY".to_string());
+    config.prompts.insert(
+        "api_key".to_string(),
+        "Enter lowercase API key".to_string().into(),
+    );
+    config.prompts.insert(
+        "API_KEY".to_string(),
+        "Enter uppercase API KEY".to_string().into(),
+    );
     config.save(&fixture.cwd).expect("Failed to save config");
 
     let reloaded_config = fixture.get_config();
@@ -419,20 +454,13 @@ fn test_package_level_prompts() {
     let mut config = fixture.get_config();
     let mut package = dotr::package::Package {
         name: "f_test".to_string(),
-        src: "dotfiles/f_test".to_string(),
-        dest: "src/.test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec![],
-        post_actions: vec![],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_test".to_string()),
+        dest: Some("src/.test".to_string()),
+        ..dotr::package::Package::default()
     };
     package.prompts.insert(
         "PACKAGE_VAR".to_string(),
-        "Enter package variable".to_string(),
+        "Enter package variable".to_string().into(),
     );
     config.packages.insert("f_test".to_string(), package);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -442,8 +470,11 @@ fn test_package_level_prompts() {
     let package = reloaded_config.packages.get("f_test").unwrap();
     assert_eq!(package.prompts.len(), 1);
     assert_eq!(
-        package.prompts.get("PACKAGE_VAR"),
-        Some(&"Enter package variable".to_string())
+        package
+            .prompts
+            .get("PACKAGE_VAR")
+            .map(|p| p.message.as_str()),
+        Some("Enter package variable")
     );
 }
 
@@ -461,28 +492,21 @@ fn test_package_multiple_prompts() {
     let mut config = fixture.get_config();
     let mut package = dotr::package::Package {
         name: "f_test".to_string(),
-        src: "dotfiles/f_test".to_string(),
-        dest: "src/.test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec![],
-        post_actions: vec![],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_test".to_string()),
+        dest: Some("src/.test".to_string()),
+        ..dotr::package::Package::default()
     };
     package.prompts.insert(
         "PKG_VAR1".to_string(),
-        "Enter first package variable".to_string(),
+        "Enter first package variable".to_string().into(),
     );
     package.prompts.insert(
         "PKG_VAR2".to_string(),
-        "Enter second package variable".to_string(),
+        "Enter second package variable".to_string().into(),
     );
     package.prompts.insert(
         "PKG_VAR3".to_string(),
-        "Enter third package variable".to_string(),
+        "Enter third package variable".to_string().into(),
     );
     config.packages.insert("f_test".to_string(), package);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -505,10 +529,11 @@ fn test_profile_level_prompts() {
         variables: toml::Table::new(),
         dependencies: vec![],
         prompts: HashMap::new(),
+        extends: None,
     };
     profile.prompts.insert(
         "WORK_EMAIL".to_string(),
-        "Enter your work email".to_string(),
+        "Enter your work email".to_string().into(),
     );
     config.profiles.insert("work".to_string(), profile);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -518,8 +543,11 @@ fn test_profile_level_prompts() {
     let profile = reloaded_config.profiles.get("work").unwrap();
     assert_eq!(profile.prompts.len(), 1);
     assert_eq!(
-        profile.prompts.get("WORK_EMAIL"),
-        Some(&"Enter your work email".to_string())
+        profile
+            .prompts
+            .get("WORK_EMAIL")
+            .map(|p| p.message.as_str()),
+        Some("Enter your work email")
     );
 }
 
@@ -535,17 +563,20 @@ fn test_profile_multiple_prompts() {
         variables: toml::Table::new(),
         dependencies: vec![],
         prompts: HashMap::new(),
+        extends: None,
     };
     profile.prompts.insert(
         "WORK_EMAIL".to_string(),
-        "Enter your work email".to_string(),
+        "Enter your work email".to_string().into(),
+    );
+    profile.prompts.insert(
+        "SLACK_TOKEN".to_string(),
+        "Enter Slack token".to_string().into(),
+    );
+    profile.prompts.insert(
+        "VPN_PASSWORD".to_string(),
+        "Enter VPN password".to_string().into(),
     );
-    profile
-        .prompts
-        .insert("SLACK_TOKEN".to_string(), "Enter Slack token".to_string());
-    profile
-        .prompts
-        .insert("VPN_PASSWORD".to_string(), "Enter VPN password".to_string());
     config.profiles.insert("work".to_string(), profile);
     config.save(&fixture.cwd).expect("Failed to save config");
 
@@ -569,20 +600,13 @@ fn test_package_and_profile_prompts_together() {
     let mut config = fixture.get_config();
     let mut package = dotr::package::Package {
         name: "f_test".to_string(),
-        src: "dotfiles/f_test".to_string(),
-        dest: "src/.test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec![],
-        post_actions: vec![],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_test".to_string()),
+        dest: Some("src/.test".to_string()),
+        ..dotr::package::Package::default()
     };
     package.prompts.insert(
         "PACKAGE_VAR".to_string(),
-        "Enter package variable".to_string(),
+        "Enter package variable".to_string().into(),
     );
     config.packages.insert("f_test".to_string(), package);
 
@@ -592,10 +616,11 @@ fn test_package_and_profile_prompts_together() {
         variables: toml::Table::new(),
         dependencies: vec![],
         prompts: HashMap::new(),
+        extends: None,
     };
     profile.prompts.insert(
         "PROFILE_VAR".to_string(),
-        "Enter profile variable".to_string(),
+        "Enter profile variable".to_string().into(),
     );
     config.profiles.insert("work".to_string(), profile);
 
@@ -624,16 +649,9 @@ fn test_package_prompts_do_not_interfere_with_variables() {
     let mut config = fixture.get_config();
     let mut package = dotr::package::Package {
         name: "f_test".to_string(),
-        src: "dotfiles/f_test".to_string(),
-        dest: "src/.test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec![],
-        post_actions: vec![],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_test".to_string()),
+        dest: Some("src/.test".to_string()),
+        ..dotr::package::Package::default()
     };
     package.variables.insert(
         "STATIC_VAR".to_string(),
@@ -641,7 +659,7 @@ fn test_package_prompts_do_not_interfere_with_variables() {
     );
     package.prompts.insert(
         "PROMPT_VAR".to_string(),
-        "Enter prompt variable".to_string(),
+        "Enter prompt variable".to_string().into(),
     );
     config.packages.insert("f_test".to_string(), package);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -665,6 +683,7 @@ fn test_profile_prompts_do_not_interfere_with_variables() {
         variables: toml::Table::new(),
         dependencies: vec![],
         prompts: HashMap::new(),
+        extends: None,
     };
     profile.variables.insert(
         "STATIC_VAR".to_string(),
@@ -672,7 +691,7 @@ fn test_profile_prompts_do_not_interfere_with_variables() {
     );
     profile.prompts.insert(
         "PROMPT_VAR".to_string(),
-        "Enter prompt variable".to_string(),
+        "Enter prompt variable".to_string().into(),
     );
     config.profiles.insert("work".to_string(), profile);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -698,16 +717,9 @@ fn test_empty_package_prompts() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_test".to_string(),
-        src: "dotfiles/f_test".to_string(),
-        dest: "src/.test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec![],
-        post_actions: vec![],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_test".to_string()),
+        dest: Some("src/.test".to_string()),
+        ..dotr::package::Package::default()
     };
     config.packages.insert("f_test".to_string(), package);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -730,6 +742,7 @@ fn test_empty_profile_prompts() {
         variables: toml::Table::new(),
         dependencies: vec![],
         prompts: HashMap::new(),
+        extends: None,
     };
     config.profiles.insert("work".to_string(), profile);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -754,26 +767,19 @@ fn test_three_level_prompts_hierarchy() {
     let mut config = fixture.get_config();
     config.prompts.insert(
         "CONFIG_VAR".to_string(),
-        "Enter config variable".to_string(),
+        "Enter config variable".to_string().into(),
     );
 
     // Add package with prompt
     let mut package = dotr::package::Package {
         name: "f_test".to_string(),
-        src: "dotfiles/f_test".to_string(),
-        dest: "src/.test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: vec![],
-        post_actions: vec![],
-        targets: HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_test".to_string()),
+        dest: Some("src/.test".to_string()),
+        ..dotr::package::Package::default()
     };
     package.prompts.insert(
         "PACKAGE_VAR".to_string(),
-        "Enter package variable".to_string(),
+        "Enter package variable".to_string().into(),
     );
     config.packages.insert("f_test".to_string(), package);
 
@@ -783,10 +789,11 @@ fn test_three_level_prompts_hierarchy() {
         variables: toml::Table::new(),
         dependencies: vec![],
         prompts: HashMap::new(),
+        extends: None,
     };
     profile.prompts.insert(
         "PROFILE_VAR".to_string(),
-        "Enter profile variable".to_string(),
+        "Enter profile variable".to_string().into(),
     );
     config.profiles.insert("work".to_string(), profile);
 
@@ -0,0 +1,226 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, InitArgs, PrintVarsArgs, parse_set_values, run_cli},
+    config::Config,
+    context::Context,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_provenance_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(fixture.cwd.join("config.toml"), CONFIG).expect("Failed to write config");
+        fs::write(
+            fixture.cwd.join(".uservariables.toml"),
+            "EDITOR = \"nvim\"\n",
+        )
+        .expect("Failed to write .uservariables.toml");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn context(&self, profile: Option<&str>) -> (Config, Context) {
+        let conf = Config::from_path(&self.cwd).expect("Failed to load config");
+        let mut ctx = Context::from_config(&self.cwd, &conf).expect("Failed to create context");
+        let profile = profile.map(|name| {
+            conf.resolve_profile(name)
+                .expect("Failed to resolve profile")
+                .expect("Profile not found")
+        });
+        ctx.set_profile(profile).expect("Failed to set profile");
+        (conf, ctx)
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[variables]
+EDITOR = "vim"
+PAGER = "less"
+TOKEN = "plain"
+
+[packages.f_code]
+src = "dotfiles/f_code"
+dest = "code"
+
+[packages.f_code.variables]
+EDITOR = "code"
+FONT = "mono"
+
+[profiles.work]
+dependencies = ["f_code"]
+
+[profiles.work.variables]
+PAGER = "bat"
+"#;
+
+fn sources(ctx: &Context, key: &str, package: Option<&dotr::package::Package>) -> Vec<String> {
+    ctx.variable_sources(key, package)
+        .into_iter()
+        .map(|(source, _)| source)
+        .collect()
+}
+
+#[test]
+fn test_sources_are_listed_lowest_priority_first() {
+    let fixture = TestFixture::new();
+    let (_, ctx) = fixture.context(Some("work"));
+
+    assert_eq!(
+        sources(&ctx, "EDITOR", None),
+        vec!["config.toml", ".uservariables.toml"]
+    );
+    assert_eq!(
+        sources(&ctx, "PAGER", None),
+        vec!["config.toml", "profile 'work'"]
+    );
+    assert_eq!(ctx.get_variable_source("EDITOR"), ".uservariables.toml");
+    assert_eq!(ctx.get_variable_source("dotr"), "dotr (automatic)");
+    assert_eq!(ctx.get_variable_source("MISSING"), "unset");
+}
+
+#[test]
+fn test_environment_is_the_lowest_layer() {
+    let fixture = TestFixture::new();
+    let (_, mut ctx) = fixture.context(None);
+    let mut config = toml::Table::new();
+    config.insert("HOME".to_string(), toml::Value::String("/elsewhere".into()));
+    ctx.extend_variables(config);
+
+    let sources = sources(&ctx, "HOME", None);
+    assert_eq!(sources, vec!["environment", "config.toml"]);
+}
+
+#[test]
+fn test_describe_variable_names_overridden_sources() {
+    let fixture = TestFixture::new();
+    let (_, ctx) = fixture.context(None);
+    let value = toml::Value::String("nvim".to_string());
+
+    assert_eq!(
+        ctx.describe_variable("EDITOR", &value, None, false),
+        "EDITOR = nvim   (from .uservariables.toml, overrides config.toml)"
+    );
+    assert_eq!(
+        ctx.describe_variable("EDITOR", &value, None, true),
+        "EDITOR = nvim   (from .uservariables.toml)\n      overrides config.toml = vim"
+    );
+    let less = toml::Value::String("less".to_string());
+    assert_eq!(
+        ctx.describe_variable("PAGER", &less, None, false),
+        "PAGER = less   (from config.toml)"
+    );
+}
+
+#[test]
+fn test_package_variables_have_their_own_layer() {
+    let fixture = TestFixture::new();
+    let (conf, ctx) = fixture.context(Some("work"));
+    let package = conf.packages.get("f_code").expect("Package not found");
+
+    assert_eq!(
+        sources(&ctx, "EDITOR", Some(package)),
+        vec!["config.toml", "package 'f_code'", ".uservariables.toml"]
+    );
+    assert_eq!(
+        sources(&ctx, "FONT", Some(package)),
+        vec!["package 'f_code'"]
+    );
+    // Only a package's templates see its variables
+    assert!(ctx.get_context_variables().get("FONT").is_none());
+    assert_eq!(
+        ctx.get_package_display_variables(Some(package)).get("FONT"),
+        Some(&toml::Value::String("mono".to_string()))
+    );
+    let mut package_variables = package.get_context_variables(&ctx);
+    assert!(package_variables.remove("dotr_package").is_some());
+    assert_eq!(package_variables, ctx.merge_variables(Some(package)));
+}
+
+#[test]
+fn test_set_values_keep_the_file_value_visible() {
+    let fixture = TestFixture::new();
+    let (_, mut ctx) = fixture.context(None);
+    ctx.set_user_variables(parse_set_values(&["EDITOR=helix".to_string()]).unwrap());
+
+    let chain = ctx.variable_sources("EDITOR", None);
+    assert_eq!(
+        chain,
+        vec![
+            ("config.toml".to_string(), toml::Value::String("vim".into())),
+            (
+                ".uservariables.toml".to_string(),
+                toml::Value::String("nvim".into())
+            ),
+            ("--set".to_string(), toml::Value::String("helix".into())),
+        ]
+    );
+    assert_eq!(
+        ctx.get_user_variable("EDITOR"),
+        Some(&toml::Value::String("helix".to_string()))
+    );
+}
+
+#[test]
+fn test_secret_values_are_masked_in_every_layer() {
+    let fixture = TestFixture::new();
+    let (_, mut ctx) = fixture.context(None);
+    ctx.set_user_variables(parse_set_values(&["TOKEN=hunter2".to_string()]).unwrap());
+    ctx.set_secrets(vec!["TOKEN".to_string()]);
+
+    let values: Vec<toml::Value> = ctx
+        .variable_sources("TOKEN", None)
+        .into_iter()
+        .map(|(_, value)| value)
+        .collect();
+    assert_eq!(values.len(), 2);
+    assert!(
+        values
+            .iter()
+            .all(|v| v.as_str() == Some(dotr::context::SECRET_MASK))
+    );
+}
+
+#[test]
+fn test_print_vars_with_package() {
+    let fixture = TestFixture::new();
+    fixture
+        .run(Command::PrintVars(PrintVarsArgs {
+            package: Some("f_code".to_string()),
+            sources: true,
+            ..Default::default()
+        }))
+        .expect("print-vars --package failed");
+
+    let err = fixture
+        .run(Command::PrintVars(PrintVarsArgs {
+            package: Some("f_missing".to_string()),
+            ..Default::default()
+        }))
+        .expect_err("An unknown package should fail");
+    assert!(err.to_string().contains("f_missing"));
+}
@@ -0,0 +1,186 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    config::Config,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_prune_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+
+    fn deploy(&self, prune: bool) {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs {
+            prune,
+            ..Default::default()
+        }))))
+        .expect("Deploy failed");
+    }
+
+    fn write_config(&self, content: &str) {
+        fs::write(self.cwd.join("config.toml"), content).expect("Failed to write config");
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.cwd.join(path).exists()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const NVIM_CONFIG: &str = r#"
+banner = false
+
+[packages.d_nvim]
+src = "dotfiles/d_nvim"
+dest = "nvim"
+"#;
+
+#[test]
+fn test_deploy_without_prune_keeps_stale_files() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_config(NVIM_CONFIG);
+    fixture.write_file("dotfiles/d_nvim/init.lua", "init\n");
+    fixture.write_file("dotfiles/d_nvim/plugins/old.lua", "old\n");
+
+    fixture.deploy(false);
+    fs::remove_file(fixture.cwd.join("dotfiles/d_nvim/plugins/old.lua")).unwrap();
+    fixture.deploy(false);
+
+    assert!(
+        fixture.exists("nvim/plugins/old.lua"),
+        "Stale file should remain without prune"
+    );
+}
+
+#[test]
+fn test_prune_flag_removes_stale_files_with_backup() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_config(NVIM_CONFIG);
+    fixture.write_file("dotfiles/d_nvim/init.lua", "init\n");
+    fixture.write_file("dotfiles/d_nvim/plugins/old.lua", "old\n");
+
+    fixture.deploy(false);
+    fs::remove_file(fixture.cwd.join("dotfiles/d_nvim/plugins/old.lua")).unwrap();
+    fixture.deploy(true);
+
+    assert!(!fixture.exists("nvim/plugins/old.lua"), "Stale file pruned");
+    assert!(
+        fixture.exists("nvim/plugins/old.lua.dotrbak"),
+        "Pruned file should be backed up"
+    );
+    assert!(
+        fixture.exists("nvim/init.lua"),
+        "Source files stay deployed"
+    );
+}
+
+#[test]
+fn test_prune_dest_setting_respects_ignore_and_backups() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_config(
+        r#"
+banner = false
+
+[packages.d_nvim]
+src = "dotfiles/d_nvim"
+dest = "nvim"
+prune_dest = true
+ignore = ["cache/**"]
+"#,
+    );
+    fixture.write_file("dotfiles/d_nvim/init.lua", "init\n");
+    fixture.write_file("nvim/cache/state.json", "{}\n");
+    fixture.write_file("nvim/old.lua.dotrbak", "backup\n");
+    fixture.write_file("nvim/stale.lua", "stale\n");
+
+    fixture.deploy(false);
+
+    assert!(!fixture.exists("nvim/stale.lua"), "Stale file pruned");
+    assert!(fixture.exists("nvim/cache/state.json"), "Ignored file kept");
+    assert!(fixture.exists("nvim/old.lua.dotrbak"), "Backup file kept");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_prune_does_not_follow_symlinks() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_config(NVIM_CONFIG);
+    fixture.write_file("dotfiles/d_nvim/init.lua", "init\n");
+    fixture.write_file("outside/keep.txt", "keep\n");
+    fs::create_dir_all(fixture.cwd.join("nvim")).unwrap();
+    std::os::unix::fs::symlink(fixture.cwd.join("outside"), fixture.cwd.join("nvim/linked"))
+        .unwrap();
+
+    fixture.deploy(true);
+
+    assert!(
+        fixture.exists("outside/keep.txt"),
+        "Files outside dest must never be pruned"
+    );
+    assert!(
+        fs::symlink_metadata(fixture.cwd.join("nvim/linked")).is_err(),
+        "The stale symlink itself is removed"
+    );
+}
+
+#[test]
+fn test_prune_ignores_file_packages() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_config(
+        r#"
+banner = false
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "rc"
+prune_dest = true
+"#,
+    );
+    fixture.write_file("dotfiles/f_rc", "rc\n");
+    fixture.write_file("neighbour", "untouched\n");
+
+    fixture.deploy(true);
+
+    assert!(fixture.exists("rc"));
+    assert!(fixture.exists("neighbour"));
+    let config = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    assert!(config.packages["f_rc"].prune_dest);
+}
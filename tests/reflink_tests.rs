@@ -0,0 +1,113 @@
+use std::{fs, path::PathBuf};
+
+use dotr::cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_reflink_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            "banner = false\n\n[packages.d_fonts]\nsrc = \"dotfiles/fonts\"\ndest = \"home/fonts\"\n",
+        )
+        .expect("Failed to write config");
+        fs::create_dir_all(fixture.cwd.join("dotfiles/fonts")).unwrap();
+        fs::write(fixture.cwd.join("dotfiles/fonts/font.ttf"), font(1)).unwrap();
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn read(&self, path: &str) -> Vec<u8> {
+        fs::read(self.cwd.join(path)).unwrap()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+// Binary content, not UTF-8, a bit different for each `seed`
+fn font(seed: u8) -> Vec<u8> {
+    (0..64 * 1024)
+        .map(|i| (i % 251) as u8 ^ seed)
+        .chain([0xff, 0xfe])
+        .collect()
+}
+
+// The temp dir is tmpfs or ext4 on most machines, where reflinks fail and
+// the regular copy is taken instead
+#[test]
+fn test_binary_deploy_falls_back_to_a_copy() {
+    let fixture = TestFixture::new();
+    fixture
+        .run(Command::Deploy(DeployUpdateArgs::default()))
+        .expect("Deploy failed");
+    assert_eq!(fixture.read("home/fonts/font.ttf"), font(1));
+
+    // Replacing an existing dest, with its backup
+    fs::write(fixture.cwd.join("dotfiles/fonts/font.ttf"), font(2)).unwrap();
+    fixture
+        .run(Command::Deploy(DeployUpdateArgs::default()))
+        .expect("Deploy failed");
+    assert_eq!(fixture.read("home/fonts/font.ttf"), font(2));
+    assert_eq!(fixture.read("home/fonts/font.ttf.dotrbak"), font(1));
+    // No temporary file is left behind
+    let names: Vec<_> = fs::read_dir(fixture.cwd.join("home/fonts"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(names.len(), 2, "{:?}", names);
+}
+
+#[test]
+fn test_no_reflink_deploys_the_same_bytes() {
+    let fixture = TestFixture::new();
+    fixture
+        .run(Command::Deploy(DeployUpdateArgs {
+            no_reflink: true,
+            ..Default::default()
+        }))
+        .expect("Deploy failed");
+    assert_eq!(fixture.read("home/fonts/font.ttf"), font(1));
+}
+
+#[test]
+fn test_update_copies_binary_files_back() {
+    let fixture = TestFixture::new();
+    fixture
+        .run(Command::Deploy(DeployUpdateArgs::default()))
+        .expect("Deploy failed");
+    fs::write(fixture.cwd.join("home/fonts/font.ttf"), font(3)).unwrap();
+
+    fixture
+        .run(Command::Update(UpdateArgs::default()))
+        .expect("Update failed");
+    assert_eq!(fixture.read("dotfiles/fonts/font.ttf"), font(3));
+
+    fs::write(fixture.cwd.join("home/fonts/font.ttf"), font(4)).unwrap();
+    fixture
+        .run(Command::Update(UpdateArgs {
+            no_reflink: true,
+            ..Default::default()
+        }))
+        .expect("Update failed");
+    assert_eq!(fixture.read("dotfiles/fonts/font.ttf"), font(4));
+}
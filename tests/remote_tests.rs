@@ -0,0 +1,243 @@
+#![cfg(feature = "remote")]
+
+use std::{fs, path::PathBuf, process::Command as Process};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, FetchArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+    upstream: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_remote_test_{}", uuid::Uuid::new_v4()));
+        let cwd = temp_dir.join("repo");
+        fs::create_dir_all(&cwd).expect("Failed to create temp dir");
+        let fixture = Self {
+            cwd,
+            upstream: temp_dir.join("upstream"),
+        };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fixture.write_upstream(
+            "base-main/zsh/aliases",
+            "alias ll='ls -l' # for {{ user_name }}\n",
+        );
+        fixture.write_upstream("base-main/README.md", "shared base\n");
+        fixture.pack();
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        self.run_with(command, false)
+    }
+
+    fn run_with(&self, command: Command, offline: bool) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            offline,
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self, offline: bool) -> Result<(), anyhow::Error> {
+        self.run_with(Command::Deploy(DeployUpdateArgs::default()), offline)
+    }
+
+    fn write_upstream(&self, path: &str, content: &str) {
+        let file_path = self.upstream.join(path);
+        fs::create_dir_all(file_path.parent().unwrap()).expect("Failed to create parent dir");
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    // Pack upstream/base-main into a tarball the way GitHub archives look
+    fn pack(&self) {
+        let status = Process::new("tar")
+            .arg("-czf")
+            .arg(self.tarball())
+            .arg("-C")
+            .arg(&self.upstream)
+            .arg("base-main")
+            .status()
+            .expect("Failed to run tar");
+        assert!(status.success());
+    }
+
+    fn tarball(&self) -> PathBuf {
+        self.upstream.with_file_name("base.tar.gz")
+    }
+
+    fn write_config(&self, package: &str) {
+        let config = format!(
+            "banner = false\n\n[variables]\nuser_name = \"me\"\n\n[packages.d_zsh]\n{}\ndest = \"home/zsh\"\n",
+            package
+        );
+        fs::write(self.cwd.join("config.toml"), config).expect("Failed to write config");
+    }
+
+    fn tarball_src(&self) -> String {
+        format!(
+            "src = \"file://{}#subdir=zsh\"",
+            self.tarball().to_str().unwrap()
+        )
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(self.cwd.parent().unwrap()).ok();
+    }
+}
+
+#[test]
+fn test_deploy_from_tarball_subdir() {
+    let fixture = TestFixture::new();
+    fixture.write_config(&fixture.tarball_src());
+    fixture.deploy(false).expect("Deploy failed");
+
+    assert_eq!(
+        fixture.read_file("home/zsh/aliases"),
+        "alias ll='ls -l' # for me\n"
+    );
+    assert!(!fixture.cwd.join("home/zsh/README.md").exists());
+    assert!(fixture.cwd.join(".dotr/remote").is_dir());
+}
+
+#[test]
+fn test_update_leaves_remote_src_alone() {
+    let fixture = TestFixture::new();
+    fixture.write_config(&fixture.tarball_src());
+    fixture.deploy(false).expect("Deploy failed");
+    fs::write(fixture.cwd.join("home/zsh/aliases"), "changed\n").unwrap();
+
+    fixture
+        .run(Command::Update(UpdateArgs::default()))
+        .expect("Update failed");
+
+    let config = Config::from_path(&fixture.cwd).unwrap();
+    let remote = config.packages["d_zsh"].remote_source().unwrap();
+    let cached = remote.path(&fixture.cwd).join("aliases");
+    assert!(
+        fs::read_to_string(cached)
+            .unwrap()
+            .contains("{{ user_name }}")
+    );
+}
+
+#[test]
+fn test_offline_needs_a_cache() {
+    let fixture = TestFixture::new();
+    fixture.write_config(&fixture.tarball_src());
+    let err = fixture
+        .deploy(true)
+        .expect_err("Offline deploy without a cache should fail");
+    assert!(err.to_string().contains("--offline"), "{}", err);
+    assert!(!fixture.cwd.join("home/zsh/aliases").exists());
+
+    fixture.deploy(false).expect("Deploy failed");
+    fs::remove_file(fixture.cwd.join("home/zsh/aliases")).unwrap();
+    fixture
+        .deploy(true)
+        .expect("Offline deploy from the cache failed");
+    assert!(fixture.cwd.join("home/zsh/aliases").exists());
+}
+
+#[test]
+fn test_fetch_refreshes_the_cache() {
+    let fixture = TestFixture::new();
+    fixture.write_config(&fixture.tarball_src());
+    fixture.deploy(false).expect("Deploy failed");
+    fixture.write_upstream("base-main/zsh/aliases", "alias la='ls -a'\n");
+    fixture.pack();
+
+    // Deploy keeps using what was downloaded
+    fixture.deploy(false).expect("Deploy failed");
+    assert!(fixture.read_file("home/zsh/aliases").contains("ll"));
+
+    fixture
+        .run(Command::Fetch(FetchArgs::default()))
+        .expect("Fetch failed");
+    fixture.deploy(false).expect("Deploy failed");
+    assert_eq!(fixture.read_file("home/zsh/aliases"), "alias la='ls -a'\n");
+}
+
+#[test]
+fn test_git_remote_table() {
+    let fixture = TestFixture::new();
+    let git = |args: &[&str]| {
+        let status = Process::new("git")
+            .args(["-c", "user.name=dotr", "-c", "user.email=dotr@example.com"])
+            .arg("-C")
+            .arg(fixture.upstream.join("base-main"))
+            .args(args)
+            .status()
+            .expect("Failed to run git");
+        assert!(status.success());
+    };
+    git(&["init", "-q", "-b", "main"]);
+    git(&["add", "-A"]);
+    git(&["commit", "-q", "-m", "base"]);
+    fixture.write_config(&format!(
+        "remote = {{ url = \"git+file://{}\", subdir = \"zsh\", ref = \"main\" }}",
+        fixture.upstream.join("base-main").to_str().unwrap()
+    ));
+    fixture.deploy(false).expect("Deploy failed");
+    assert_eq!(
+        fixture.read_file("home/zsh/aliases"),
+        "alias ll='ls -l' # for me\n"
+    );
+
+    let config = Config::from_path(&fixture.cwd).unwrap();
+    config.save(&fixture.cwd).unwrap();
+    let reloaded = Config::from_path(&fixture.cwd).unwrap();
+    assert_eq!(
+        reloaded.packages["d_zsh"].remote,
+        config.packages["d_zsh"].remote
+    );
+    assert!(
+        fixture
+            .run(Command::Edit(dotr::cli::EditArgs {
+                package: "d_zsh".to_string(),
+                ..Default::default()
+            }))
+            .is_err()
+    );
+}
+
+#[test]
+fn test_subdir_must_stay_inside_the_download() {
+    let fixture = TestFixture::new();
+    for package in [
+        "remote = { url = \"https://example.com/base.tar.gz\", subdir = \"../../..\" }".to_string(),
+        "remote = { url = \"https://example.com/base.tar.gz\", subdir = \"/etc\" }".to_string(),
+        format!(
+            "src = \"file://{}#subdir=zsh/../..\"",
+            fixture.tarball().to_str().unwrap()
+        ),
+    ] {
+        fixture.write_config(&package);
+        let err = Config::from_path(&fixture.cwd).expect_err(&package);
+        assert!(format!("{:#}", err).contains("must be a path inside the download"));
+    }
+}
+
+#[test]
+fn test_ref_must_not_look_like_an_option() {
+    let fixture = TestFixture::new();
+    fixture.write_config(
+        "remote = { url = \"git+https://example.com/base.git\", ref = \"--upload-pack=touch pwned\" }",
+    );
+    let err = Config::from_path(&fixture.cwd).expect_err("A ref starting with - must be refused");
+    assert!(format!("{:#}", err).contains("must not start with '-'"));
+}
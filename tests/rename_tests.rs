@@ -0,0 +1,194 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, RenameArgs, run_cli},
+    config::Config,
+    state::DeployState,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_rename_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(fixture.cwd.join("config.toml"), CONFIG).expect("Failed to write config");
+        fs::create_dir_all(fixture.cwd.join("dotfiles/d_nvim")).unwrap();
+        fs::write(fixture.cwd.join("dotfiles/d_nvim/init.lua"), "-- nvim\n").unwrap();
+        fs::write(fixture.cwd.join("dotfiles/d_nvim##os.macos"), "").unwrap();
+        fs::write(fixture.cwd.join("dotfiles/f_rc"), "rc\n").unwrap();
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn rename(&self, old: &str, new: &str, dry_run: bool) -> Result<(), anyhow::Error> {
+        self.run(Command::Rename(RenameArgs {
+            old: old.to_string(),
+            new: new.to_string(),
+            dry_run,
+        }))
+    }
+
+    fn config(&self) -> Config {
+        Config::from_path(&self.cwd).expect("Failed to load config")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[packages.d_nvim]
+src = "dotfiles/d_nvim"
+dest = "home/.config/nvim"
+aliases = ["vim"]
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "home/.rc"
+dependencies = ["d_nvim"]
+
+[packages.f_elsewhere]
+src = "dotfiles/f_rc"
+dest = "home/.rc2"
+dependencies = ["vim"]
+
+[profiles.work]
+dependencies = ["d_nvim", "f_rc"]
+"#;
+
+#[test]
+fn test_rename_updates_every_reference() {
+    let fixture = TestFixture::new();
+    fixture
+        .rename("d_nvim", "d_neovim", false)
+        .expect("Rename failed");
+
+    let conf = fixture.config();
+    assert!(!conf.packages.contains_key("d_nvim"));
+    let package = &conf.packages["d_neovim"];
+    assert_eq!(package.src.as_deref(), Some("dotfiles/d_neovim"));
+    assert_eq!(package.aliases, vec!["vim".to_string()]);
+    assert_eq!(
+        conf.packages["f_rc"].dependencies,
+        Some(vec!["d_neovim".to_string()])
+    );
+    // A dependency through an alias still points at the package
+    assert_eq!(
+        conf.packages["f_elsewhere"].dependencies,
+        Some(vec!["vim".to_string()])
+    );
+    assert_eq!(
+        conf.profiles["work"].dependencies,
+        vec!["d_neovim".to_string(), "f_rc".to_string()]
+    );
+
+    assert!(fixture.cwd.join("dotfiles/d_neovim/init.lua").is_file());
+    assert!(fixture.cwd.join("dotfiles/d_neovim##os.macos").is_file());
+    assert!(!fixture.cwd.join("dotfiles/d_nvim").exists());
+}
+
+#[test]
+fn test_custom_src_is_left_in_place() {
+    let fixture = TestFixture::new();
+    fixture
+        .rename("f_elsewhere", "f_rc2", false)
+        .expect("Rename failed");
+    let conf = fixture.config();
+    assert_eq!(conf.packages["f_rc2"].src.as_deref(), Some("dotfiles/f_rc"));
+    assert!(fixture.cwd.join("dotfiles/f_rc").is_file());
+}
+
+#[test]
+fn test_dry_run_changes_nothing() {
+    let fixture = TestFixture::new();
+    fixture
+        .rename("d_nvim", "d_neovim", true)
+        .expect("Dry run failed");
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("config.toml")).unwrap(),
+        CONFIG
+    );
+    assert!(fixture.cwd.join("dotfiles/d_nvim").is_dir());
+}
+
+#[test]
+fn test_new_name_must_be_free_and_legal() {
+    let fixture = TestFixture::new();
+    for (new, expected) in [
+        ("f_rc", "already exists"),
+        ("vim", "already an alias"),
+        ("bad name", "Invalid package name"),
+    ] {
+        let err = fixture
+            .rename("f_elsewhere", new, false)
+            .expect_err("Rename should fail");
+        assert!(err.to_string().contains(expected), "{}", err);
+    }
+    // Renaming a package to its own alias takes the alias back
+    fixture
+        .rename("d_nvim", "vim", false)
+        .expect("Rename failed");
+    assert!(fixture.config().packages["vim"].aliases.is_empty());
+}
+
+#[test]
+fn test_failed_rename_is_rolled_back() {
+    let fixture = TestFixture::new();
+    let mut conf = fixture.config();
+    let mut moves = conf
+        .rename_package(&fixture.cwd, "d_nvim", "d_neovim")
+        .expect("Planning failed")
+        .moves;
+    // A move that can't happen after the real ones
+    moves.push((
+        fixture.cwd.join("dotfiles/missing"),
+        fixture.cwd.join("dotfiles/elsewhere"),
+    ));
+    conf.save_renamed(&fixture.cwd, &moves)
+        .expect_err("Save should fail");
+
+    assert!(fixture.cwd.join("dotfiles/d_nvim/init.lua").is_file());
+    assert!(fixture.cwd.join("dotfiles/d_nvim##os.macos").is_file());
+    assert!(!fixture.cwd.join("dotfiles/d_neovim").exists());
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("config.toml")).unwrap(),
+        CONFIG
+    );
+}
+
+#[test]
+fn test_deploy_state_follows_the_package() {
+    let fixture = TestFixture::new();
+    fixture
+        .run(Command::Deploy(DeployUpdateArgs {
+            packages: Some(vec!["f_rc".to_string()]),
+            ..Default::default()
+        }))
+        .expect("Deploy failed");
+    fixture
+        .rename("f_rc", "f_shellrc", false)
+        .expect("Rename failed");
+    let state = DeployState::load(&fixture.cwd).unwrap();
+    assert!(state.last_deploy("f_rc").is_none());
+    assert!(state.last_deploy("f_shellrc").is_some());
+}
@@ -0,0 +1,269 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{Config, Context, DeployUpdateArgs, Package, Report, UpdateArgs};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_report_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Config::init(&temp_dir, Default::default()).expect("Init failed");
+        Self { cwd: temp_dir }
+    }
+
+    // f_app deploys to home/app.conf with a post action, d_tools is a
+    // directory deploying to home/tools
+    fn setup(&self) -> Config {
+        fs::write(self.cwd.join("dotfiles/f_app"), "app").unwrap();
+        fs::create_dir_all(self.cwd.join("dotfiles/d_tools")).unwrap();
+        fs::write(self.cwd.join("dotfiles/d_tools/a"), "a").unwrap();
+        fs::write(self.cwd.join("dotfiles/d_tools/b"), "b").unwrap();
+        let mut config = Config::from_path(&self.cwd).expect("Failed to load config");
+        config.shell = Some("/bin/sh".to_string());
+        self.add_package(&mut config, "f_app", "home/app.conf", &["true"]);
+        self.add_package(&mut config, "d_tools", "home/tools", &[]);
+        config
+    }
+
+    fn add_package(&self, config: &mut Config, name: &str, dest: &str, post_actions: &[&str]) {
+        let mut table = toml::Table::new();
+        table.insert("src".into(), format!("dotfiles/{}", name).into());
+        table.insert(
+            "dest".into(),
+            self.cwd.join(dest).to_string_lossy().as_ref().into(),
+        );
+        let actions: Vec<toml::Value> = post_actions.iter().map(|a| (*a).into()).collect();
+        table.insert("post_actions".into(), toml::Value::Array(actions));
+        let package = Package::from_table(name, &table).expect("Failed to build package");
+        config.packages.insert(name.to_string(), package);
+        config.save(&self.cwd).expect("Failed to save config");
+    }
+
+    fn ctx(&self, config: &Config) -> Context {
+        Context::from_config(&self.cwd, config).expect("Failed to build context")
+    }
+
+    fn home(&self, path: &str) -> PathBuf {
+        self.cwd.join("home").join(path)
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.cwd);
+    }
+}
+
+fn package<'a>(report: &'a Report, name: &str) -> &'a dotr::PackageReport {
+    report
+        .packages
+        .iter()
+        .find(|p| p.name == name)
+        .unwrap_or_else(|| panic!("No report for {}", name))
+}
+
+#[test]
+fn test_deploy_report() {
+    let fixture = TestFixture::new();
+    let config = fixture.setup();
+    let ctx = fixture.ctx(&config);
+
+    let report = config
+        .deploy_packages(&ctx, &DeployUpdateArgs::default())
+        .expect("Deploy failed");
+    assert!(!report.has_error());
+    let names: Vec<&str> = report.packages.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, vec!["d_tools", "f_app"]);
+    let app = package(&report, "f_app");
+    assert_eq!(app.deployed, vec![fixture.home("app.conf")]);
+    assert_eq!(app.actions, vec!["true".to_string()]);
+    let mut tools = package(&report, "d_tools").deployed.clone();
+    tools.sort();
+    assert_eq!(
+        tools,
+        vec![fixture.home("tools/a"), fixture.home("tools/b")]
+    );
+
+    // Everything is up to date the second time
+    let report = config
+        .deploy_packages(&ctx, &DeployUpdateArgs::default())
+        .expect("Deploy failed");
+    assert!(report.packages.iter().all(|p| p.deployed.is_empty()));
+    assert_eq!(
+        package(&report, "f_app").skipped,
+        vec![fixture.home("app.conf")]
+    );
+    assert_eq!(package(&report, "d_tools").skipped.len(), 2);
+}
+
+#[test]
+fn test_files_are_written_in_a_stable_order() {
+    let fixture = TestFixture::new();
+    let config = fixture.setup();
+    let tools = fixture.cwd.join("dotfiles/d_tools");
+    for path in ["z/inner", "m/deep/x", "c", "m/a"] {
+        let file = tools.join(path);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, path).unwrap();
+    }
+    let ctx = fixture.ctx(&config);
+    let expected: Vec<PathBuf> = ["a", "b", "c", "m/a", "m/deep/x", "z/inner"]
+        .iter()
+        .map(|path| fixture.home("tools").join(path))
+        .collect();
+
+    let report = config
+        .deploy_packages(&ctx, &DeployUpdateArgs::default())
+        .expect("Deploy failed");
+    assert_eq!(package(&report, "d_tools").deployed, expected);
+
+    for path in expected.iter() {
+        fs::write(path, "changed").unwrap();
+    }
+    let report = config
+        .backup_packages(&ctx, &UpdateArgs::default())
+        .expect("Update failed");
+    let sources: Vec<PathBuf> = expected
+        .iter()
+        .map(|path| tools.join(path.strip_prefix(fixture.home("tools")).unwrap()))
+        .collect();
+    assert_eq!(package(&report, "d_tools").backed_up, sources);
+}
+
+#[test]
+fn test_deploy_report_stops_at_failed_package() {
+    let fixture = TestFixture::new();
+    let mut config = fixture.setup();
+    fs::write(fixture.cwd.join("dotfiles/f_broken"), "broken").unwrap();
+    fixture.add_package(&mut config, "f_broken", "home/broken", &["exit 3"]);
+    let ctx = fixture.ctx(&config);
+
+    let report = config
+        .deploy_packages(&ctx, &DeployUpdateArgs::default())
+        .expect("Deploy should report the failure");
+    assert!(report.has_error());
+    let names: Vec<&str> = report.packages.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, vec!["d_tools", "f_app", "f_broken"]);
+    let broken = package(&report, "f_broken");
+    assert_eq!(broken.deployed, vec![fixture.home("broken")]);
+    assert!(broken.actions.is_empty());
+
+    let err = report.into_result().expect_err("into_result should fail");
+    assert!(err.to_string().contains("exit code"), "{}", err);
+}
+
+#[test]
+fn test_diff_report() {
+    let fixture = TestFixture::new();
+    let config = fixture.setup();
+    let ctx = fixture.ctx(&config);
+    config
+        .deploy_packages(&ctx, &DeployUpdateArgs::default())
+        .expect("Deploy failed");
+    fs::write(fixture.home("app.conf"), "edited").unwrap();
+
+    let report = config
+        .diff_packages(&ctx, &DeployUpdateArgs::default())
+        .expect("Diff failed");
+    let app = package(&report, "f_app");
+    assert_eq!(app.diffs.len(), 1);
+    assert!(app.diffs[0].is_changed());
+    assert_eq!(app.diffs[0].old.as_deref(), Some("edited"));
+    assert_eq!(app.diffs[0].new.as_deref(), Some("app"));
+    assert!(
+        package(&report, "d_tools")
+            .diffs
+            .iter()
+            .all(|d| !d.is_changed())
+    );
+    // Diff leaves dests alone
+    assert_eq!(
+        fs::read_to_string(fixture.home("app.conf")).unwrap(),
+        "edited"
+    );
+}
+
+#[test]
+fn test_backup_report() {
+    let fixture = TestFixture::new();
+    let config = fixture.setup();
+    let ctx = fixture.ctx(&config);
+    config
+        .deploy_packages(&ctx, &DeployUpdateArgs::default())
+        .expect("Deploy failed");
+    fs::write(fixture.home("tools/b"), "new b").unwrap();
+    let src_b = fixture.cwd.join("dotfiles/d_tools/b");
+
+    let dry_run = UpdateArgs {
+        dry_run: true,
+        ..Default::default()
+    };
+    let report = config
+        .backup_packages(&ctx, &dry_run)
+        .expect("Update failed");
+    assert_eq!(report.backed_up_count(), 1);
+    let tools = package(&report, "d_tools");
+    assert_eq!(tools.backed_up, vec![src_b.clone()]);
+    assert_eq!(tools.diffs[0].old.as_deref(), Some("b"));
+    assert_eq!(tools.diffs[0].new.as_deref(), Some("new b"));
+    assert_eq!(fs::read_to_string(&src_b).unwrap(), "b");
+
+    let report = config
+        .backup_packages(&ctx, &UpdateArgs::default())
+        .expect("Update failed");
+    assert_eq!(report.backed_up_count(), 1);
+    assert!(package(&report, "d_tools").diffs.is_empty());
+    assert_eq!(fs::read_to_string(&src_b).unwrap(), "new b");
+}
+
+#[test]
+fn test_deploy_report_lists_backups() {
+    let fixture = TestFixture::new();
+    let config = fixture.setup();
+    let ctx = fixture.ctx(&config);
+    fs::create_dir_all(fixture.home("")).unwrap();
+    fs::write(fixture.home("app.conf"), "local edit").unwrap();
+
+    let report = config
+        .deploy_packages(&ctx, &DeployUpdateArgs::default())
+        .expect("Deploy failed");
+    assert_eq!(
+        package(&report, "f_app").backups,
+        vec![fixture.home("app.conf.dotrbak")]
+    );
+    assert!(package(&report, "d_tools").backups.is_empty());
+}
+
+#[test]
+fn test_report_summary() {
+    let fixture = TestFixture::new();
+    let config = fixture.setup();
+    let ctx = fixture.ctx(&config);
+    config
+        .deploy_packages(&ctx, &DeployUpdateArgs::default())
+        .expect("Deploy failed");
+    fs::write(fixture.cwd.join("dotfiles/d_tools/b"), "b2").unwrap();
+
+    let report = config
+        .deploy_packages(&ctx, &DeployUpdateArgs::default())
+        .expect("Deploy failed");
+    let summary = report.summary("Deployed");
+    let lines: Vec<&str> = summary.lines().collect();
+    assert!(
+        lines[0].starts_with("Deployed 2 package(s) in "),
+        "{}",
+        summary
+    );
+    assert_eq!(
+        lines[1..],
+        [
+            "  package  written  unchanged  backed up  errors",
+            "  d_tools        1          1          1       0",
+            "  f_app          0          1          0       0",
+        ]
+    );
+}
@@ -0,0 +1,153 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    config::{
+        Config,
+        migrations::{SCHEMA_VERSION, migrate, schema_version},
+    },
+};
+use toml::Table;
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_schema_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fixture.write_file("dotfiles/f_a", "a\n");
+        fixture.write_file("dotfiles/f_b", "b\n");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn saved_table(&self) -> Table {
+        fs::read_to_string(self.cwd.join("config.toml"))
+            .expect("Failed to read config")
+            .parse()
+            .expect("Failed to parse config")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const V0_CONFIG: &str = r#"
+banner = false
+
+[variables]
+editor = "nvim"
+
+[variables.git]
+name = "Jane"
+signing = { key = "ABCD", sign = true }
+
+[packages.f_a]
+src = "dotfiles/f_a"
+dest = "home/a"
+dependencies = "f_b"
+prune = true
+
+[packages.f_b]
+src = "dotfiles/f_b"
+dest = "home/b"
+
+[profiles.work]
+dependencies = "f_a"
+"#;
+
+#[test]
+fn test_init_writes_the_schema_version() {
+    let fixture = TestFixture::new();
+    assert_eq!(
+        schema_version(&fixture.saved_table()).unwrap(),
+        SCHEMA_VERSION
+    );
+}
+
+#[test]
+fn test_v0_config_is_migrated() {
+    let fixture = TestFixture::new();
+    fixture.write_file("config.toml", V0_CONFIG);
+
+    let config = Config::from_path(&fixture.cwd).expect("Failed to load v0 config");
+    let package = &config.packages["f_a"];
+    assert_eq!(package.dependencies, Some(vec!["f_b".to_string()]));
+    assert!(package.prune_dest);
+    assert_eq!(config.profiles["work"].dependencies, vec!["f_a"]);
+
+    config.save(&fixture.cwd).expect("Failed to save config");
+    let saved = fixture.saved_table();
+    assert_eq!(schema_version(&saved).unwrap(), SCHEMA_VERSION);
+    let saved_package = saved["packages"]["f_a"].as_table().unwrap();
+    assert!(saved_package.get("prune").is_none());
+    assert_eq!(saved_package["prune_dest"].as_bool(), Some(true));
+    assert_eq!(
+        saved["variables"]["git"]["signing"]["key"].as_str(),
+        Some("ABCD")
+    );
+    assert_eq!(saved["variables"]["editor"].as_str(), Some("nvim"));
+}
+
+#[test]
+fn test_migrations_keep_unknown_data() {
+    // Top-level keys go before the first table
+    let mut table: Table = format!("future_key = {{ kept = 1 }}\n{}", V0_CONFIG)
+        .parse()
+        .unwrap();
+    let applied = migrate(&mut table, 0);
+
+    assert_eq!(applied.len(), 2);
+    assert_eq!(table["future_key"]["kept"].as_integer(), Some(1));
+    assert_eq!(
+        table["packages"]["f_a"]["dependencies"]
+            .as_array()
+            .map(Vec::len),
+        Some(1)
+    );
+    assert!(migrate(&mut table, SCHEMA_VERSION).is_empty());
+}
+
+#[test]
+fn test_newer_schema_is_read_only() {
+    let fixture = TestFixture::new();
+    fixture.write_file(
+        "config.toml",
+        "schema_version = 99\nbanner = false\n\n[packages.f_b]\nsrc = \"dotfiles/f_b\"\ndest = \"home/b\"\n",
+    );
+
+    let err = fixture
+        .run(Command::Deploy(DeployUpdateArgs::default()))
+        .expect_err("Deploy should refuse a newer schema");
+    assert!(err.to_string().contains("upgrade dotr"), "{}", err);
+    assert!(!fixture.cwd.join("home/b").exists());
+
+    fixture
+        .run(Command::Diff(DeployUpdateArgs::default()))
+        .expect("Read-only commands still run");
+}
@@ -0,0 +1,244 @@
+use std::{fs, path::PathBuf};
+
+use age::secrecy::ExposeSecret;
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, SecretsArgs, SecretsCommand, run_cli},
+    config::Config,
+    context::Context,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_secrets_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+
+    fn deploy(&self) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))))
+    }
+
+    fn set_secret(&self, key: &str, value: &str) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Secrets(SecretsArgs {
+            command: SecretsCommand::Set {
+                key: key.to_string(),
+                value: value.to_string(),
+            },
+        }))))
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+
+    // A fresh age key, pointed to by DOTR_AGE_KEY in .uservariables.toml
+    fn create_key(&self) {
+        let identity = age::x25519::Identity::generate();
+        self.write_file(
+            "age.key",
+            &format!("# test key\n{}\n", identity.to_string().expose_secret()),
+        );
+        self.write_file(
+            ".uservariables.toml",
+            &format!(
+                "DOTR_AGE_KEY = \"{}\"\n",
+                self.cwd.join("age.key").display()
+            ),
+        );
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+secrets_file = ".secrets.toml.age"
+
+[packages.f_netrc]
+src = "dotfiles/f_netrc"
+dest = "netrc"
+"#;
+
+fn setup() -> TestFixture {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("config.toml", CONFIG);
+    fixture
+}
+
+#[test]
+fn test_set_secret_and_render() {
+    let fixture = setup();
+    fixture.create_key();
+    fixture.write_file("dotfiles/f_netrc", "password {{ API_TOKEN }}\n");
+
+    fixture
+        .set_secret("API_TOKEN", "hunter2")
+        .expect("Setting a secret failed");
+    let encrypted = fs::read(fixture.cwd.join(".secrets.toml.age")).unwrap();
+    assert!(
+        !String::from_utf8_lossy(&encrypted).contains("hunter2"),
+        "The secrets file must not hold plaintext"
+    );
+
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(fixture.read_file("netrc"), "password hunter2\n");
+    assert!(
+        !fixture.read_file(".uservariables.toml").contains("hunter2"),
+        "Secrets must never be written back to .uservariables.toml"
+    );
+}
+
+#[test]
+fn test_set_secret_keeps_other_values() {
+    let fixture = setup();
+    fixture.create_key();
+    fixture.set_secret("A", "1").unwrap();
+    fixture.set_secret("B", "2").unwrap();
+    fixture.set_secret("A", "3").unwrap();
+
+    let config = Config::from_path(&fixture.cwd).unwrap();
+    let mut ctx = Context::new(&fixture.cwd).unwrap();
+    ctx.load_secrets(&config, true)
+        .expect("Secrets should decrypt");
+    assert_eq!(ctx.lookup_variable("A").unwrap().as_str(), Some("3"));
+    assert_eq!(ctx.lookup_variable("B").unwrap().as_str(), Some("2"));
+    assert_eq!(ctx.get_variable_source("A"), "secrets_file");
+}
+
+#[test]
+fn test_missing_key_only_fails_when_a_variable_is_needed() {
+    let fixture = setup();
+    fixture.create_key();
+    fixture.set_secret("API_TOKEN", "hunter2").unwrap();
+    // Lose the key
+    fs::remove_file(fixture.cwd.join("age.key")).unwrap();
+
+    fixture.write_file("dotfiles/f_netrc", "machine example.com\n");
+    fixture
+        .deploy()
+        .expect("Nothing references a secret, deploy should work");
+
+    fixture.write_file("dotfiles/f_netrc", "password {{ API_TOKEN }}\n");
+    let err = fixture.deploy().expect_err("The secret is needed now");
+    let message = err.to_string();
+    assert!(
+        message.contains("API_TOKEN"),
+        "Unexpected error: {}",
+        message
+    );
+    assert!(
+        message.contains("secrets could not be loaded"),
+        "Unexpected error: {}",
+        message
+    );
+}
+
+#[test]
+fn test_strict_secrets_fails_up_front() {
+    let fixture = setup();
+    fixture.write_file("dotfiles/f_netrc", "machine example.com\n");
+    fixture.write_file(".secrets.toml.age", "not really encrypted");
+
+    let cli = Cli {
+        strict_secrets: true,
+        ..fixture.get_cli(Some(Command::Deploy(DeployUpdateArgs::default())))
+    };
+    let err = run_cli(cli).expect_err("--strict-secrets should fail without a key");
+    assert!(err.to_string().contains("DOTR_AGE_KEY"));
+}
+
+#[test]
+fn test_secrets_are_masked_in_print_vars() {
+    let fixture = setup();
+    fixture.create_key();
+    fixture.set_secret("API_TOKEN", "hunter2").unwrap();
+
+    let config = Config::from_path(&fixture.cwd).unwrap();
+    let mut ctx = Context::new(&fixture.cwd).unwrap();
+    ctx.load_secrets(&config, false).unwrap();
+    assert!(ctx.get_secret_variables().contains_key("API_TOKEN"));
+
+    run_cli(fixture.get_cli(Some(Command::PrintVars(Default::default()))))
+        .expect("print-vars failed");
+}
+
+#[test]
+fn test_secrets_commands_need_secrets_file() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.create_key();
+    let err = fixture
+        .set_secret("A", "1")
+        .expect_err("No secrets_file configured");
+    assert!(err.to_string().contains("secrets_file is not set"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_edit_secrets_reencrypts_and_removes_plaintext() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let fixture = setup();
+    fixture.create_key();
+    fixture.set_secret("A", "1").unwrap();
+
+    // An "editor" that appends a variable and records the file it was given
+    let editor = fixture.cwd.join("editor.sh");
+    fs::write(
+        &editor,
+        format!(
+            "#!/bin/sh\necho \"$1\" > {}\necho 'B = \"2\"' >> \"$1\"\n",
+            fixture.cwd.join("edited_path").display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+    // Only this test reads EDITOR
+    unsafe { std::env::set_var("EDITOR", &editor) };
+
+    run_cli(fixture.get_cli(Some(Command::Secrets(SecretsArgs {
+        command: SecretsCommand::Edit,
+    }))))
+    .expect("Edit failed");
+
+    let plaintext_path = fixture.read_file("edited_path");
+    assert!(
+        !PathBuf::from(plaintext_path.trim()).exists(),
+        "The decrypted temp file must be removed"
+    );
+    let config = Config::from_path(&fixture.cwd).unwrap();
+    let mut ctx = Context::new(&fixture.cwd).unwrap();
+    ctx.load_secrets(&config, true).unwrap();
+    assert_eq!(ctx.lookup_variable("A").unwrap().as_str(), Some("1"));
+    assert_eq!(ctx.lookup_variable("B").unwrap().as_str(), Some("2"));
+}
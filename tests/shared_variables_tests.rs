@@ -0,0 +1,186 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, InitArgs, run_cli},
+    config::Config,
+    context::Context,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_shared_vars_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        run_cli(Cli {
+            command: Some(Command::Init(InitArgs::default())),
+            working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+        .expect("Init failed");
+        fs::write(fixture.cwd.join("config.toml"), CONFIG).expect("Failed to write config");
+        fs::write(
+            fixture.cwd.join(".uservariables.toml"),
+            "user_wins = \"user\"\n",
+        )
+        .expect("Failed to write user variables");
+        fixture
+    }
+
+    fn load(&self) -> (Config, Context) {
+        let conf = Config::from_path(&self.cwd).expect("Failed to load config");
+        let ctx = Context::from_config(&self.cwd, &conf).expect("Failed to create context");
+        (conf, ctx)
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[variables]
+config_only = "config"
+package_wins = "config"
+profile_wins = "config"
+user_wins = "config"
+header = "managed by {{ dotr_package.name }}"
+
+[packages.f_one]
+src = "dotfiles/f_one"
+dest = "home/.one"
+
+[packages.f_one.variables]
+package_wins = "one"
+profile_wins = "one"
+user_wins = "one"
+
+[packages.f_two]
+src = "dotfiles/f_two"
+dest = "home/.two"
+
+[profiles.work.variables]
+profile_wins = "work"
+"#;
+
+fn value(table: &toml::Table, key: &str) -> String {
+    table[key].as_str().unwrap().to_string()
+}
+
+#[test]
+fn test_layers_keep_their_precedence() {
+    let fixture = TestFixture::new();
+    let (conf, mut ctx) = fixture.load();
+    ctx.set_profile(Some(conf.profiles["work"].clone()))
+        .expect("Failed to set profile");
+
+    let one = conf.packages["f_one"]
+        .resolved_variables(&ctx)
+        .expect("Failed to resolve");
+    assert_eq!(value(&one, "config_only"), "config");
+    assert_eq!(value(&one, "package_wins"), "one");
+    assert_eq!(value(&one, "profile_wins"), "work");
+    assert_eq!(value(&one, "user_wins"), "user");
+
+    let two = conf.packages["f_two"]
+        .resolved_variables(&ctx)
+        .expect("Failed to resolve");
+    assert_eq!(value(&two, "package_wins"), "config");
+    assert_eq!(value(&two, "profile_wins"), "work");
+}
+
+#[test]
+fn test_each_package_resolves_its_own_metadata() {
+    let fixture = TestFixture::new();
+    let (conf, ctx) = fixture.load();
+    let one = conf.packages["f_one"]
+        .resolved_variables(&ctx)
+        .expect("Failed to resolve");
+    let two = conf.packages["f_two"]
+        .resolved_variables(&ctx)
+        .expect("Failed to resolve");
+    assert_eq!(value(&one, "header"), "managed by f_one");
+    assert_eq!(value(&two, "header"), "managed by f_two");
+    assert_eq!(
+        value(
+            &conf.packages["f_one"].resolved_variables(&ctx).unwrap(),
+            "header"
+        ),
+        "managed by f_one"
+    );
+}
+
+#[test]
+fn test_shared_variables_follow_context_changes() {
+    let fixture = TestFixture::new();
+    let (conf, mut ctx) = fixture.load();
+    let package = &conf.packages["f_two"];
+    assert_eq!(
+        value(&package.resolved_variables(&ctx).unwrap(), "profile_wins"),
+        "config"
+    );
+
+    ctx.set_profile(Some(conf.profiles["work"].clone()))
+        .expect("Failed to set profile");
+    assert_eq!(
+        value(&package.resolved_variables(&ctx).unwrap(), "profile_wins"),
+        "work"
+    );
+
+    let mut set = toml::Table::new();
+    set.insert(
+        "config_only".to_string(),
+        toml::Value::String("set".to_string()),
+    );
+    ctx.set_user_variables(set);
+    assert_eq!(
+        value(&package.resolved_variables(&ctx).unwrap(), "config_only"),
+        "set"
+    );
+    assert_eq!(value(&ctx.get_context_variables(), "config_only"), "set");
+}
+
+#[test]
+fn test_changed_package_variables_are_not_reused() {
+    let fixture = TestFixture::new();
+    let (conf, ctx) = fixture.load();
+    let mut package = conf.packages["f_one"].clone();
+    assert_eq!(
+        value(&package.resolved_variables(&ctx).unwrap(), "package_wins"),
+        "one"
+    );
+    package.variables.insert(
+        "package_wins".to_string(),
+        toml::Value::String("edited".to_string()),
+    );
+    assert_eq!(
+        value(&package.resolved_variables(&ctx).unwrap(), "package_wins"),
+        "edited"
+    );
+}
+
+#[test]
+fn test_package_tables_match_a_full_merge() {
+    let fixture = TestFixture::new();
+    let (conf, mut ctx) = fixture.load();
+    ctx.set_profile(Some(conf.profiles["work"].clone()))
+        .expect("Failed to set profile");
+    for package in conf.packages.values() {
+        let mut merged = toml::Table::new();
+        for (_, layer) in ctx.variable_layers(Some(package)) {
+            merged.extend(layer);
+        }
+        let mut layered = ctx.layered_variables(Some(package));
+        layered.remove("env");
+        layered.remove("dotr");
+        assert_eq!(layered, merged, "{}", package.name);
+    }
+}
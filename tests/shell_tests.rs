@@ -0,0 +1,189 @@
+use std::fs;
+
+use dotr::package::{Action, Package, shell_command};
+
+fn strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn test_configured_shells() {
+    assert_eq!(shell_command(Some("bash")), strings(&["bash", "-c"]));
+    assert_eq!(
+        shell_command(Some("/usr/local/bin/fish")),
+        strings(&["/usr/local/bin/fish", "-c"])
+    );
+    assert_eq!(shell_command(Some("cmd")), strings(&["cmd", "/C"]));
+    assert_eq!(
+        shell_command(Some("powershell")),
+        strings(&["powershell", "-NoProfile", "-Command"])
+    );
+    assert_eq!(
+        shell_command(Some("pwsh.exe")),
+        strings(&["pwsh.exe", "-NoProfile", "-Command"])
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_default_shell_on_unix() {
+    let command = shell_command(None);
+    let expected = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+    assert_eq!(command, vec![expected, "-c".to_string()]);
+}
+
+#[cfg(windows)]
+#[test]
+fn test_default_shell_on_windows() {
+    assert_eq!(shell_command(None), strings(&["cmd", "/C"]));
+}
+
+#[test]
+fn test_shell_round_trips_in_package_table() {
+    let table: toml::Table = r#"
+src = "dotfiles/f_rc"
+dest = "~/.rc"
+shell = "powershell"
+"#
+    .parse()
+    .expect("Invalid TOML");
+    let pkg = Package::from_table("f_rc", &table).expect("Failed to parse package");
+    assert_eq!(pkg.shell.as_deref(), Some("powershell"));
+    assert_eq!(
+        pkg.to_table().get("shell").and_then(|v| v.as_str()),
+        Some("powershell")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_package_shell_runs_actions() {
+    let dir = std::env::temp_dir().join(format!("dotr_shell_test_{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).expect("Failed to create temp dir");
+    let table: toml::Table = r#"
+shell = "/bin/sh"
+pre_actions = ["echo $0 > shell.txt"]
+"#
+    .parse()
+    .expect("Invalid TOML");
+    let pkg = Package::from_table("meta", &table).expect("Failed to parse package");
+
+    pkg.execute_action(&pkg.pre_actions[0], &toml::Table::new(), &[], &dir, None)
+        .expect("Action failed");
+    let used = fs::read_to_string(dir.join("shell.txt")).expect("Action didn't run");
+    fs::remove_dir_all(&dir).ok();
+    assert_eq!(used.trim(), "/bin/sh");
+}
+
+#[test]
+fn test_config_level_shell() {
+    let table: toml::Table = r#"
+banner = false
+shell = "pwsh"
+"#
+    .parse()
+    .expect("Invalid TOML");
+    let config = dotr::config::Config::from_table(&table).expect("Failed to parse config");
+    assert_eq!(config.shell.as_deref(), Some("pwsh"));
+    assert_eq!(
+        config.to_table().get("shell").and_then(|v| v.as_str()),
+        Some("pwsh")
+    );
+}
+
+#[cfg(unix)]
+fn sh_package() -> Package {
+    let table: toml::Table = "shell = \"/bin/sh\"\npost_actions = [\"true\"]"
+        .parse()
+        .expect("Invalid TOML");
+    Package::from_table("f_app", &table).expect("Failed to parse package")
+}
+
+#[cfg(unix)]
+#[test]
+fn test_failed_action_error_includes_output() {
+    let pkg = sh_package();
+    let err = pkg
+        .execute_action(
+            &Action::from("echo building; echo 'error: missing semicolon' >&2; exit 3"),
+            &toml::Table::new(),
+            &[],
+            &std::env::temp_dir(),
+            None,
+        )
+        .expect_err("Action should fail");
+    let message = err.to_string();
+    assert!(message.contains("exit code: Some(3)"), "{}", message);
+    assert!(message.contains("building"), "{}", message);
+    assert!(message.contains("error: missing semicolon"), "{}", message);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_failed_action_error_keeps_last_lines() {
+    let pkg = sh_package();
+    let err = pkg
+        .execute_action(
+            &Action::from("for i in $(seq 1 50); do echo line$i; done; exit 1"),
+            &toml::Table::new(),
+            &[],
+            &std::env::temp_dir(),
+            None,
+        )
+        .expect_err("Action should fail");
+    let message = err.to_string();
+    assert!(message.contains("line50"));
+    assert!(message.contains("line31"));
+    assert!(!message.contains("line30\n"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_action_timeout() {
+    let pkg = sh_package();
+    let started = std::time::Instant::now();
+    let err = pkg
+        .execute_action(
+            &Action::from("echo waiting; sleep 10"),
+            &toml::Table::new(),
+            &[],
+            &std::env::temp_dir(),
+            Some(std::time::Duration::from_secs(1)),
+        )
+        .expect_err("Action should time out");
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    let message = err.to_string();
+    assert!(message.contains("timed out after 1s"), "{}", message);
+    assert!(message.contains("waiting"), "{}", message);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_action_within_timeout_succeeds() {
+    let pkg = sh_package();
+    pkg.execute_action(
+        &Action::from("echo quick"),
+        &toml::Table::new(),
+        &[],
+        &std::env::temp_dir(),
+        Some(std::time::Duration::from_secs(5)),
+    )
+    .expect("Action should succeed");
+}
+
+#[test]
+fn test_config_action_timeout() {
+    let table: toml::Table = "action_timeout_secs = 30".parse().expect("Invalid TOML");
+    let config = dotr::config::Config::from_table(&table).expect("Failed to parse config");
+    assert_eq!(config.action_timeout_secs, Some(30));
+    assert_eq!(
+        config
+            .to_table()
+            .get("action_timeout_secs")
+            .and_then(|v| v.as_integer()),
+        Some(30)
+    );
+
+    let table: toml::Table = "action_timeout_secs = 0".parse().expect("Invalid TOML");
+    assert!(dotr::config::Config::from_table(&table).is_err());
+}
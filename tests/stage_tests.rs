@@ -0,0 +1,176 @@
+use std::{fs, path::PathBuf};
+
+use clap::Parser;
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, StatusArgs, UpdateArgs, run_cli},
+    config::Config,
+    context::Context,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_stage_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            "banner = false\n\n[packages.d_app]\nsrc = \"dotfiles/app\"\ndest = \"home/app\"\n\n[packages.d_rc]\nsrc = \"dotfiles/rc\"\ndest = \"home/.rc\"\n",
+        )
+        .expect("Failed to write config");
+        fixture.write("dotfiles/app/config", "setting = 1\n");
+        fixture.write("dotfiles/app/themes/dark", "dark\n");
+        fixture.write("dotfiles/rc", "rc = 1\n");
+        fixture
+            .run(Command::Deploy(DeployUpdateArgs::default()))
+            .expect("Deploy failed");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn stage(&self) {
+        self.run(Command::Update(UpdateArgs {
+            stage_to: Some(".dotr/staged".to_string()),
+            ..Default::default()
+        }))
+        .expect("Update failed");
+    }
+
+    fn apply(&self, force: bool) -> Result<(), anyhow::Error> {
+        self.run(Command::Update(UpdateArgs {
+            apply_staged: true,
+            force,
+            ..Default::default()
+        }))
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).unwrap()
+    }
+
+    fn status(&self) -> String {
+        let conf = Config::from_path(&self.cwd).expect("Failed to load config");
+        let ctx = Context::from_config(&self.cwd, &conf).expect("Failed to create context");
+        conf.render_status(&ctx, &StatusArgs::default(), false)
+            .expect("Status failed")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_stage_leaves_sources_alone() {
+    let fixture = TestFixture::new();
+    fixture.write("home/app/config", "setting = 2\n");
+    fixture.write("home/app/plugins/extra", "extra\n");
+    fixture.write("home/.rc", "rc = 2\n");
+    fixture.stage();
+
+    assert_eq!(fixture.read("dotfiles/app/config"), "setting = 1\n");
+    assert!(!fixture.cwd.join("dotfiles/app/plugins").exists());
+    assert_eq!(fixture.read("dotfiles/rc"), "rc = 1\n");
+    // Laid out like src, so `diff -r` compares them
+    assert_eq!(fixture.read(".dotr/staged/d_app/config"), "setting = 2\n");
+    assert_eq!(fixture.read(".dotr/staged/d_app/plugins/extra"), "extra\n");
+    assert_eq!(fixture.read(".dotr/staged/d_rc"), "rc = 2\n");
+    // Unchanged files aren't staged
+    assert!(!fixture.cwd.join(".dotr/staged/d_app/themes").exists());
+
+    let status = fixture.status();
+    assert!(status.contains("3 file(s) staged"), "{}", status);
+}
+
+#[test]
+fn test_apply_copies_and_clears_the_stage() {
+    let fixture = TestFixture::new();
+    fixture.write("home/app/config", "setting = 2\n");
+    fixture.write("home/app/plugins/extra", "extra\n");
+    fixture.stage();
+    // Dropped during review
+    fs::remove_file(fixture.cwd.join(".dotr/staged/d_app/plugins/extra")).unwrap();
+
+    fixture.apply(false).expect("Apply failed");
+    assert_eq!(fixture.read("dotfiles/app/config"), "setting = 2\n");
+    assert!(!fixture.cwd.join("dotfiles/app/plugins").exists());
+    assert!(!fixture.cwd.join(".dotr/staged").exists());
+    assert!(!fixture.status().contains("staged"));
+    // Nothing left to apply
+    fixture.apply(false).expect("Apply failed");
+}
+
+#[test]
+fn test_source_changed_since_staging_needs_force() {
+    let fixture = TestFixture::new();
+    fixture.write("home/app/config", "setting = 2\n");
+    fixture.stage();
+    fixture.write("dotfiles/app/config", "setting = 3\n");
+
+    let err = fixture.apply(false).expect_err("Apply should be refused");
+    assert!(err.to_string().contains("--force"), "{}", err);
+    assert!(err.to_string().contains("config"), "{}", err);
+    assert_eq!(fixture.read("dotfiles/app/config"), "setting = 3\n");
+
+    fixture.apply(true).expect("Apply failed");
+    assert_eq!(fixture.read("dotfiles/app/config"), "setting = 2\n");
+}
+
+#[test]
+fn test_stage_to_a_custom_directory() {
+    let fixture = TestFixture::new();
+    fixture.write("home/app/config", "setting = 2\n");
+    fixture
+        .run(Command::Update(UpdateArgs {
+            stage_to: Some("review".to_string()),
+            ..Default::default()
+        }))
+        .expect("Update failed");
+    assert_eq!(fixture.read("review/d_app/config"), "setting = 2\n");
+    // Only the default stage is mentioned by status
+    assert!(!fixture.status().contains("staged"));
+
+    fixture
+        .run(Command::Update(UpdateArgs {
+            apply_staged: true,
+            stage_to: Some("review".to_string()),
+            ..Default::default()
+        }))
+        .expect("Apply failed");
+    assert_eq!(fixture.read("dotfiles/app/config"), "setting = 2\n");
+}
+
+#[test]
+fn test_stage_flags_parse() {
+    let cli = Cli::try_parse_from(["dotr", "update", "--stage-to"]).expect("Should parse");
+    let Some(Command::Update(args)) = cli.command else {
+        panic!("Expected the update command");
+    };
+    assert_eq!(args.stage_to.as_deref(), Some(".dotr/staged"));
+    assert!(Cli::try_parse_from(["dotr", "update", "--force"]).is_err());
+    assert!(Cli::try_parse_from(["dotr", "update", "--apply-staged", "--dry-run"]).is_err());
+    assert!(Cli::try_parse_from(["dotr", "update", "--stage-to", "--prune-missing"]).is_err());
+    assert!(Cli::try_parse_from(["dotr", "update", "--apply-staged", "--force"]).is_ok());
+}
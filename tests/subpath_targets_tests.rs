@@ -0,0 +1,229 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+    context::Context,
+    package::Package,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_subpath_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(fixture.cwd.join("config.toml"), CONFIG).expect("Failed to write config");
+        fixture.write("dotfiles/d_config/kitty/kitty.conf", "font_size 12\n");
+        fixture.write("dotfiles/d_config/git/config", "[user]\n  name = me\n");
+        fixture.write("dotfiles/d_config/git/hooks/pre-commit", "#!/bin/sh\n");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self, profile: Option<&str>, prune: bool) {
+        self.run(Command::Deploy(DeployUpdateArgs {
+            profile: profile.map(str::to_string),
+            prune,
+            ..Default::default()
+        }))
+        .expect("Deploy failed");
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).unwrap()
+    }
+
+    fn work_context(&self) -> (Config, Context) {
+        let conf = Config::from_path(&self.cwd).expect("Failed to load config");
+        let mut ctx = Context::from_config(&self.cwd, &conf).expect("Failed to create context");
+        ctx.set_profile(Some(conf.profiles["work"].clone()))
+            .expect("Failed to set profile");
+        (conf, ctx)
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[packages.d_config]
+src = "dotfiles/d_config"
+dest = "home/.config"
+
+[packages.d_config.targets.work]
+"" = "home/.config"
+"git" = "home/work-configs/git"
+"git/hooks" = "home/hooks"
+
+[profiles.work]
+dependencies = ["d_config"]
+"#;
+
+#[test]
+fn test_subpaths_deploy_to_their_own_dests() {
+    let fixture = TestFixture::new();
+    fixture.deploy(Some("work"), false);
+    assert_eq!(
+        fixture.read("home/.config/kitty/kitty.conf"),
+        "font_size 12\n"
+    );
+    assert_eq!(
+        fixture.read("home/work-configs/git/config"),
+        "[user]\n  name = me\n"
+    );
+    // The longest subpath decides
+    assert_eq!(fixture.read("home/hooks/pre-commit"), "#!/bin/sh\n");
+    assert!(!fixture.cwd.join("home/.config/git").exists());
+    assert!(!fixture.cwd.join("home/work-configs/git/hooks").exists());
+}
+
+#[test]
+fn test_plain_dest_without_the_profile() {
+    let fixture = TestFixture::new();
+    fixture.deploy(None, false);
+    assert_eq!(
+        fixture.read("home/.config/git/config"),
+        "[user]\n  name = me\n"
+    );
+    assert_eq!(
+        fixture.read("home/.config/git/hooks/pre-commit"),
+        "#!/bin/sh\n"
+    );
+    assert!(!fixture.cwd.join("home/work-configs").exists());
+}
+
+#[test]
+fn test_update_maps_each_dest_back() {
+    let fixture = TestFixture::new();
+    fixture.deploy(Some("work"), false);
+    fixture.write("home/.config/kitty/kitty.conf", "font_size 14\n");
+    fixture.write("home/work-configs/git/config", "[user]\n  name = work\n");
+    fixture.write("home/hooks/pre-commit", "#!/bin/sh\nexit 0\n");
+    // Not the package's: under the profile, git/ deploys elsewhere
+    fixture.write("home/.config/git/extra", "someone else's\n");
+
+    fixture
+        .run(Command::Update(UpdateArgs {
+            profile: Some("work".to_string()),
+            ..Default::default()
+        }))
+        .expect("Update failed");
+    assert_eq!(
+        fixture.read("dotfiles/d_config/kitty/kitty.conf"),
+        "font_size 14\n"
+    );
+    assert_eq!(
+        fixture.read("dotfiles/d_config/git/config"),
+        "[user]\n  name = work\n"
+    );
+    assert_eq!(
+        fixture.read("dotfiles/d_config/git/hooks/pre-commit"),
+        "#!/bin/sh\nexit 0\n"
+    );
+    assert!(!fixture.cwd.join("dotfiles/d_config/git/extra").exists());
+}
+
+#[test]
+fn test_prune_leaves_what_the_subpaths_moved_away_from() {
+    let fixture = TestFixture::new();
+    fixture.write("home/.config/git/config", "someone else's\n");
+    fixture.write("home/work-configs/git/stale", "old\n");
+    fixture.deploy(Some("work"), true);
+    assert!(fixture.cwd.join("home/.config/git/config").exists());
+    assert!(!fixture.cwd.join("home/work-configs/git/stale").exists());
+    assert!(fixture.cwd.join("home/work-configs/git/config").exists());
+}
+
+#[test]
+fn test_file_pairs_and_resolve_dest_for() {
+    let fixture = TestFixture::new();
+    let (conf, ctx) = fixture.work_context();
+    let package = &conf.packages["d_config"];
+    let mut dests: Vec<PathBuf> = package
+        .file_pairs(&ctx)
+        .expect("Failed to list files")
+        .into_iter()
+        .map(|(_, dest)| dest.strip_prefix(&fixture.cwd).unwrap().to_path_buf())
+        .collect();
+    dests.sort();
+    assert_eq!(
+        dests,
+        vec![
+            PathBuf::from("home/.config/kitty/kitty.conf"),
+            PathBuf::from("home/hooks/pre-commit"),
+            PathBuf::from("home/work-configs/git/config"),
+        ]
+    );
+    let root = &package.resolve_dests(&ctx).unwrap()[0];
+    assert_eq!(
+        package
+            .resolve_dest_for(root, &PathBuf::from("git/config"), &ctx)
+            .unwrap(),
+        fixture.cwd.join("home/work-configs/git/config")
+    );
+}
+
+#[test]
+fn test_subpath_target_round_trips() {
+    let fixture = TestFixture::new();
+    let conf = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    let package = &conf.packages["d_config"];
+    let reparsed = Package::from_table("d_config", &package.to_table()).expect("Failed to parse");
+    assert_eq!(reparsed.targets, package.targets);
+    assert_eq!(reparsed.subpath_targets, package.subpath_targets);
+    assert_eq!(package.targets["work"], vec!["home/.config".to_string()]);
+}
+
+#[test]
+fn test_subpath_target_needs_a_root() {
+    let fixture = TestFixture::new();
+    fs::write(
+        fixture.cwd.join("config.toml"),
+        CONFIG.replace("\"\" = \"home/.config\"\n", ""),
+    )
+    .unwrap();
+    let err = Config::from_path(&fixture.cwd).expect_err("Config should fail");
+    assert!(
+        format!("{:#}", err).contains("needs a \"\" entry"),
+        "{:#}",
+        err
+    );
+
+    fs::write(
+        fixture.cwd.join("config.toml"),
+        CONFIG.replace("\"git\" =", "\"../git\" ="),
+    )
+    .unwrap();
+    let err = Config::from_path(&fixture.cwd).expect_err("Config should fail");
+    assert!(
+        format!("{:#}", err).contains("inside the package"),
+        "{:#}",
+        err
+    );
+}
@@ -0,0 +1,127 @@
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    config::Config,
+    context::Context,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_summary_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn init(&self) {
+        self.run(Some(Command::Init(InitArgs::default())))
+            .expect("Init failed");
+        fs::write(
+            self.cwd.join("config.toml"),
+            "banner = false\n\n[packages.f_bashrc]\nsrc = \"dotfiles/bashrc\"\ndest = \"home/.bashrc\"\n\n[packages.f_vimrc]\nsrc = \"dotfiles/vimrc\"\ndest = \"home/.vimrc\"\n\n[packages.f_work]\nsrc = \"dotfiles/work\"\ndest = \"home/.work\"\nskip = true\n\n[profiles.work]\ndependencies = [\"f_work\"]\n",
+        )
+        .expect("Failed to write config");
+        for name in ["bashrc", "vimrc", "work"] {
+            self.write(&format!("dotfiles/{}", name), "# config\n");
+        }
+    }
+
+    fn run(&self, command: Option<Command>) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn summary(&self) -> String {
+        let conf = Config::from_path(&self.cwd).expect("Failed to load config");
+        let ctx = Context::from_config(&self.cwd, &conf).expect("Failed to create context");
+        let selection = conf
+            .get_profile_details(&None, &ctx)
+            .expect("Failed to select profile");
+        conf.render_summary(&ctx, &selection, false)
+            .expect("Failed to render summary")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_summary_counts_packages() {
+    let fixture = TestFixture::new();
+    fixture.init();
+
+    let summary = fixture.summary();
+    assert!(
+        summary.contains(&fixture.cwd.display().to_string()),
+        "{}",
+        summary
+    );
+    assert!(
+        summary.contains("3, 1 only deployed through a profile"),
+        "{}",
+        summary
+    );
+    assert!(summary.contains("dotr --help"), "{}", summary);
+    // Nothing deployed yet, so nothing to be out of sync
+    assert!(!summary.contains("Out of sync"), "{}", summary);
+}
+
+#[test]
+fn test_summary_counts_drifted_packages() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture
+        .run(Some(Command::Deploy(DeployUpdateArgs::default())))
+        .expect("Deploy failed");
+    assert!(
+        fixture.summary().contains("none of 2 deployed package(s)"),
+        "{}",
+        fixture.summary()
+    );
+
+    // Edited after the deploy
+    fs::File::options()
+        .write(true)
+        .open(fixture.cwd.join("dotfiles/vimrc"))
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(5))
+        .unwrap();
+    let summary = fixture.summary();
+    assert!(
+        summary.contains("1 of 2 deployed package(s)"),
+        "{}",
+        summary
+    );
+}
+
+#[test]
+fn test_bare_dotr_runs_with_and_without_a_config() {
+    let fixture = TestFixture::new();
+    fixture
+        .run(None)
+        .expect("Bare dotr failed without a config");
+
+    fixture.init();
+    fixture.run(None).expect("Bare dotr failed with a config");
+}
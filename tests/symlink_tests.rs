@@ -0,0 +1,159 @@
+#![cfg(unix)]
+
+use std::{fs, os::unix::fs::symlink, path::PathBuf};
+
+use dotr::cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_symlink_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fixture.write_file("config.toml", CONFIG);
+        fixture.write_file("dotfiles/f_rc", "from dotr\n");
+        fixture.write_file("dotfiles/d_nvim/init.lua", "init\n");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self, overwrite_symlinks: bool) -> Result<(), anyhow::Error> {
+        self.run(Command::Deploy(DeployUpdateArgs {
+            overwrite_symlinks,
+            ..Default::default()
+        }))
+    }
+
+    fn update(&self) {
+        self.run(Command::Update(UpdateArgs::default()))
+            .expect("Update failed");
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+
+    fn link(&self, target: &str, path: &str) {
+        symlink(self.cwd.join(target), self.cwd.join(path)).expect("Failed to create symlink");
+    }
+
+    fn is_symlink(&self, path: &str) -> bool {
+        fs::symlink_metadata(self.cwd.join(path))
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false)
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "rc"
+
+[packages.d_nvim]
+src = "dotfiles/d_nvim"
+dest = "nvim"
+"#;
+
+#[test]
+fn test_symlinked_file_dest_is_refused() {
+    let fixture = TestFixture::new();
+    fixture.write_file("stow/rc", "from stow\n");
+    fixture.link("stow/rc", "rc");
+
+    let err = fixture
+        .deploy(false)
+        .expect_err("Writing through a symlink should be refused");
+    let message = format!("{:#}", err);
+    assert!(message.contains("--overwrite-symlinks"), "{}", message);
+    assert!(message.contains("stow/rc"), "{}", message);
+    assert_eq!(fixture.read_file("stow/rc"), "from stow\n");
+    assert!(fixture.is_symlink("rc"));
+}
+
+#[test]
+fn test_overwrite_symlinks_replaces_the_link() {
+    let fixture = TestFixture::new();
+    fixture.write_file("stow/rc", "from stow\n");
+    fixture.link("stow/rc", "rc");
+
+    fixture.deploy(true).expect("Deploy failed");
+
+    assert!(!fixture.is_symlink("rc"));
+    assert_eq!(fixture.read_file("rc"), "from dotr\n");
+    assert_eq!(fixture.read_file("stow/rc"), "from stow\n");
+    assert_eq!(fixture.read_file("stow/rc.dotrbak"), "from stow\n");
+}
+
+#[test]
+fn test_dangling_symlink_is_removed() {
+    let fixture = TestFixture::new();
+    fixture.link("gone/rc", "rc");
+
+    fixture.deploy(false).expect("Deploy failed");
+
+    assert!(!fixture.is_symlink("rc"));
+    assert_eq!(fixture.read_file("rc"), "from dotr\n");
+    assert!(!fixture.cwd.join("gone").exists());
+    assert!(!fixture.cwd.join("rc.dotrbak").exists());
+}
+
+#[test]
+fn test_symlinked_dir_inside_dest_is_refused() {
+    let fixture = TestFixture::new();
+    fixture.write_file("dotfiles/d_nvim/lua/plugins.lua", "plugins\n");
+    fixture.write_file("elsewhere/plugins.lua", "untouched\n");
+    fs::create_dir_all(fixture.cwd.join("nvim")).unwrap();
+    fixture.link("elsewhere", "nvim/lua");
+
+    fixture
+        .deploy(false)
+        .expect_err("A symlinked dir inside dest should be refused");
+    assert_eq!(fixture.read_file("elsewhere/plugins.lua"), "untouched\n");
+
+    fixture.deploy(true).expect("Deploy failed");
+    assert!(!fixture.is_symlink("nvim/lua"));
+    assert_eq!(fixture.read_file("nvim/lua/plugins.lua"), "plugins\n");
+    assert_eq!(fixture.read_file("elsewhere/plugins.lua"), "untouched\n");
+}
+
+#[test]
+fn test_update_skips_symlinked_dirs_inside_dest() {
+    let fixture = TestFixture::new();
+    fixture.deploy(false).expect("Deploy failed");
+    fixture.write_file("elsewhere/secret.txt", "outside\n");
+    fixture.link("elsewhere", "nvim/linked");
+
+    fixture.update();
+
+    assert!(!fixture.cwd.join("dotfiles/d_nvim/linked").exists());
+}
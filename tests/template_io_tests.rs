@@ -0,0 +1,144 @@
+use std::{fs, path::PathBuf};
+
+use dotr::cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(allow: bool, template: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_template_io_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            format!(
+                "banner = false\nallow_template_io = {}\nshell = \"/bin/sh\"\n\n[packages.f_conf]\nsrc = \"dotfiles/conf\"\ndest = \"home/conf\"\n",
+                allow
+            ),
+        )
+        .expect("Failed to write config");
+        fixture.write("dotfiles/conf", template);
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self) -> Result<(), anyhow::Error> {
+        self.run(Command::Deploy(DeployUpdateArgs::default()))
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).unwrap()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_read_file_reads_from_the_repository() {
+    let fixture = TestFixture::new(true, "email = {{ read_file(path=\"dotfiles/email\") }}\n");
+    fixture.write("dotfiles/email", "me@example.com");
+
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(fixture.read("home/conf"), "email = me@example.com\n");
+}
+
+#[test]
+fn test_templates_reading_files_are_never_cached() {
+    let fixture = TestFixture::new(true, "v={{ read_file(path=\"data.txt\") }}\n");
+    fixture.write("data.txt", "one");
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(fixture.read("home/conf"), "v=one\n");
+
+    // The template itself is untouched
+    fixture.write("data.txt", "two");
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(fixture.read("home/conf"), "v=two\n");
+    assert!(!fixture.read(".dotr/cache.toml").contains("home/conf"));
+}
+
+#[test]
+fn test_read_file_refuses_paths_outside_the_repository() {
+    let fixture = TestFixture::new(
+        true,
+        "{{ read_file(path=\"../outside\", default=\"fallback\") }}\n",
+    );
+    let err = format!("{:#}", fixture.deploy().unwrap_err());
+    assert!(err.contains("outside the repository"), "{}", err);
+    assert!(!fixture.cwd.join("home/conf").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_substitutes_trimmed_output() {
+    let fixture = TestFixture::new(true, "key = {{ cmd(command=\"echo '  abc  '\") }}\n");
+
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(fixture.read("home/conf"), "key = abc\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_runs_once_per_run() {
+    let fixture = TestFixture::new(
+        true,
+        "{{ cmd(command=\"echo x >> runs; wc -l < runs\") }} {{ cmd(command=\"echo x >> runs; wc -l < runs\") }}\n",
+    );
+
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(fixture.read("home/conf"), "1 1\n");
+    assert_eq!(fixture.read("runs"), "x\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_failures_fall_back_to_the_default() {
+    let fixture = TestFixture::new(
+        true,
+        "{{ read_file(path=\"missing\", default=\"none\") }} {{ cmd(command=\"exit 3\", default=\"unset\") }}\n",
+    );
+
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(fixture.read("home/conf"), "none unset\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_failures_without_a_default_are_errors() {
+    let fixture = TestFixture::new(true, "{{ cmd(command=\"echo oops >&2; exit 3\") }}\n");
+
+    let err = format!("{:#}", fixture.deploy().unwrap_err());
+    assert!(err.contains("oops"), "{}", err);
+    assert!(!fixture.cwd.join("home/conf").exists());
+}
+
+#[test]
+fn test_functions_are_off_unless_allowed() {
+    let fixture = TestFixture::new(false, "{{ cmd(command=\"touch ran\", default=\"\") }}\n");
+
+    let err = format!("{:#}", fixture.deploy().unwrap_err());
+    assert!(err.contains("allow_template_io"), "{}", err);
+    assert!(!fixture.cwd.join("ran").exists());
+}
@@ -0,0 +1,145 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+    package::TemplateMode,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(template: Option<&str>) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_template_mode_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        let mode = template
+            .map(|mode| format!("template = \"{}\"\n", mode))
+            .unwrap_or_default();
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            format!(
+                "banner = false\n\n[variables]\nname = \"world\"\n\n[packages.f_config]\nsrc = \"dotfiles/config\"\ndest = \"home/config\"\n{}",
+                mode
+            ),
+        )
+        .expect("Failed to write config");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self) -> Result<(), anyhow::Error> {
+        self.run(Command::Deploy(DeployUpdateArgs::default()))
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).unwrap()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+// Braces that look like markers but are no template
+const LITERAL_BRACES: &str =
+    "# bindings use {{ and }} in their docs\nset $mod Mod4\nconst o = {{a: 1}};\n";
+
+#[test]
+fn test_auto_copies_braces_that_do_not_parse() {
+    let fixture = TestFixture::new(None);
+    fixture.write("dotfiles/config", LITERAL_BRACES);
+
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(fixture.read("home/config"), LITERAL_BRACES);
+}
+
+#[test]
+fn test_auto_still_renders_templates() {
+    let fixture = TestFixture::new(None);
+    fixture.write("dotfiles/config", "hello {{ name }}\n");
+
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(fixture.read("home/config"), "hello world\n");
+}
+
+#[test]
+fn test_never_copies_valid_markers_as_they_are() {
+    let fixture = TestFixture::new(Some("never"));
+    fixture.write("dotfiles/config", "hello {{ name }}\n");
+
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(fixture.read("home/config"), "hello {{ name }}\n");
+}
+
+#[test]
+fn test_never_copies_literal_braces_byte_for_byte() {
+    let fixture = TestFixture::new(Some("never"));
+    fixture.write("dotfiles/config", LITERAL_BRACES);
+
+    fixture.deploy().expect("Deploy failed");
+    assert_eq!(fixture.read("home/config"), LITERAL_BRACES);
+}
+
+#[test]
+fn test_never_lets_update_copy_back() {
+    let fixture = TestFixture::new(Some("never"));
+    fixture.write("dotfiles/config", "hello {{ name }}\n");
+    fixture.deploy().expect("Deploy failed");
+    fixture.write("home/config", "hello {{ name }}\nedited\n");
+
+    fixture
+        .run(Command::Update(UpdateArgs::default()))
+        .expect("Update failed");
+    assert_eq!(
+        fixture.read("dotfiles/config"),
+        "hello {{ name }}\nedited\n"
+    );
+}
+
+#[test]
+fn test_always_renders_even_braces_that_do_not_parse() {
+    let fixture = TestFixture::new(Some("always"));
+    fixture.write("dotfiles/config", LITERAL_BRACES);
+
+    // No falling back to a copy when rendering is asked for
+    assert!(fixture.deploy().is_err());
+    assert!(!fixture.cwd.join("home/config").exists());
+}
+
+#[test]
+fn test_unknown_template_mode_is_an_error() {
+    let fixture = TestFixture::new(Some("sometimes"));
+    let err = Config::from_path(&fixture.cwd).unwrap_err().to_string();
+    assert!(err.contains("Unknown template mode 'sometimes'"), "{}", err);
+}
+
+#[test]
+fn test_template_mode_round_trips() {
+    let fixture = TestFixture::new(Some("never"));
+    let conf = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    conf.save(&fixture.cwd).expect("Failed to save config");
+    let conf = Config::from_path(&fixture.cwd).expect("Failed to reload config");
+    assert_eq!(conf.packages["f_config"].template, TemplateMode::Never);
+}
@@ -0,0 +1,167 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    package::{validate_template, validate_template_str},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_tplcheck_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+
+    fn deploy(&self) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Deploy(DeployUpdateArgs::default()))))
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+fn context() -> toml::Table {
+    r#"
+EDITOR = "nvim"
+SHELL = "zsh"
+hosts = ["a", "b"]
+
+[git]
+email = "me@example.com"
+name = "Me"
+"#
+    .parse::<toml::Table>()
+    .expect("Invalid TOML")
+}
+
+#[test]
+fn test_typo_suggests_closest_variable() {
+    let err = validate_template_str("rc", "editor={{ EDTIOR }}", &context())
+        .expect_err("Typo should be rejected");
+    assert_eq!(
+        err.to_string(),
+        "file rc references undefined variable 'EDTIOR'; did you mean 'EDITOR'?"
+    );
+}
+
+#[test]
+fn test_nested_path_typo_suggests_dotted_key() {
+    let err = validate_template_str("gitconfig", "{{ git.emial }}", &context())
+        .expect_err("Nested typo should be rejected");
+    assert!(err.to_string().contains("'git.emial'"));
+    assert!(err.to_string().contains("'git.email'"));
+}
+
+#[test]
+fn test_unrelated_name_has_no_suggestion() {
+    let err = validate_template_str("rc", "{{ COMPLETELY_DIFFERENT }}", &context())
+        .expect_err("Unknown variable should be rejected");
+    assert!(!err.to_string().contains("did you mean"));
+}
+
+#[test]
+fn test_defined_references_pass() {
+    let template = r#"
+{{ EDITOR }} {{ git.email }} {{ hosts[1] }}
+{% set greeting = "hi" %}{{ greeting }}
+{% for host in hosts %}{{ host }}{{ loop.index }}{% endfor %}
+{% if OPTIONAL is defined %}{{ OPTIONAL }}{% endif %}
+{{ MISSING | default(value="x") }}
+"#;
+    validate_template_str("rc", template, &context()).expect("Template should be valid");
+}
+
+#[test]
+fn test_undefined_in_condition_is_rejected() {
+    let err = validate_template_str("rc", "{% if WORK %}work{% endif %}", &context())
+        .expect_err("Undefined condition should be rejected in strict mode");
+    assert!(err.to_string().contains("'WORK'"));
+}
+
+#[test]
+fn test_validate_template_reads_file() {
+    let fixture = TestFixture::new();
+    fixture.write_file("rc", "{{ SHEL }}");
+    let err = validate_template(&fixture.cwd.join("rc"), &context())
+        .expect_err("Typo should be rejected");
+    assert!(err.to_string().contains("did you mean 'SHELL'?"));
+}
+
+#[test]
+fn test_deploy_fails_before_writing_on_undefined_variable() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file(
+        "dotfiles/f_rc",
+        "{% if WORK_EMAIL %}{{ WORK_EMAIL }}{% endif %}\n",
+    );
+    fixture.write_file(
+        "config.toml",
+        r#"
+banner = false
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "rc"
+"#,
+    );
+
+    let err = fixture.deploy().expect_err("Deploy should fail");
+    assert!(err.to_string().contains("'WORK_EMAIL'"));
+    assert!(
+        !fixture.cwd.join("rc").exists(),
+        "Nothing should be written"
+    );
+}
+
+#[test]
+fn test_lenient_package_keeps_old_behavior() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("dotfiles/f_rc", "{% if WORK_EMAIL %}work{% endif %}plain\n");
+    fixture.write_file(
+        "config.toml",
+        r#"
+banner = false
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "rc"
+lenient = true
+"#,
+    );
+
+    fixture.deploy().expect("Lenient deploy should succeed");
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("rc")).unwrap(),
+        "plain\n"
+    );
+}
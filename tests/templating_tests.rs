@@ -1,14 +1,13 @@
-use std::{collections::HashMap, fs, path::PathBuf};
+use std::{fs, path::PathBuf};
 
 use dotr::{
-    cli::{DeployUpdateArgs, ImportArgs, InitArgs, run_cli},
+    cli::{DeployUpdateArgs, ImportArgs, InitArgs, UpdateArgs, run_cli},
     config::Config,
     package::get_package_name,
 };
 
 mod common;
 
-const PLAYGROUND_DIR: &str = "tests/playground";
 const BASHRC_PATH: &str = "src/.bashrc";
 
 struct TestFixture {
@@ -17,27 +16,31 @@ struct TestFixture {
 
 impl TestFixture {
     fn new() -> Self {
-        Self {
-            cwd: PathBuf::from(PLAYGROUND_DIR),
-        }
+        let cwd =
+            std::env::temp_dir().join(format!("dotr_templating_test_{}", uuid::Uuid::new_v4()));
+        common::setup(&cwd);
+        Self { cwd }
     }
 
     fn get_cli(&self, command: Option<dotr::cli::Command>) -> dotr::cli::Cli {
         dotr::cli::Cli {
             command,
-            working_dir: Some(PLAYGROUND_DIR.to_string()),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
         }
     }
 
     fn init(&self) {
-        run_cli(self.get_cli(Some(dotr::cli::Command::Init(InitArgs {})))).expect("Init failed");
+        run_cli(self.get_cli(Some(dotr::cli::Command::Init(InitArgs::default()))))
+            .expect("Init failed");
     }
 
     fn import(&self, path: &str) {
         run_cli(self.get_cli(Some(dotr::cli::Command::Import(ImportArgs {
-            path: path.to_string(),
+            paths: vec![path.to_string()],
             name: None,
             profile: None,
+            ..Default::default()
         }))))
         .expect("Import failed");
     }
@@ -47,18 +50,18 @@ impl TestFixture {
             self.get_cli(Some(dotr::cli::Command::Deploy(DeployUpdateArgs {
                 packages,
                 profile: None,
+                ..Default::default()
             }))),
         )
         .expect("Deploy failed");
     }
 
     fn update(&self, packages: Option<Vec<String>>) {
-        run_cli(
-            self.get_cli(Some(dotr::cli::Command::Update(DeployUpdateArgs {
-                packages,
-                profile: None,
-            }))),
-        )
+        run_cli(self.get_cli(Some(dotr::cli::Command::Update(UpdateArgs {
+            packages,
+            profile: None,
+            ..Default::default()
+        }))))
         .expect("Update failed");
     }
 
@@ -67,12 +70,7 @@ impl TestFixture {
     }
 
     fn get_package_name(&self, path: &str) -> String {
-        let args = ImportArgs {
-            path: path.to_string(),
-            name: None,
-            profile: None,
-        };
-        get_package_name(&args, &self.cwd)
+        get_package_name(path, &None, &self.cwd).unwrap()
     }
 
     fn assert_file_exists(&self, path: &str, message: &str) {
@@ -82,7 +80,7 @@ impl TestFixture {
 
 impl Drop for TestFixture {
     fn drop(&mut self) {
-        common::teardown(&self.cwd);
+        fs::remove_dir_all(&self.cwd).ok();
     }
 }
 
@@ -139,16 +137,9 @@ fn test_template_deployment_with_variables() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_bashrc_template".to_string(),
-        src: "dotfiles/f_bashrc_template".to_string(),
-        dest: "src/.bashrc_output".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_bashrc_template".to_string()),
+        dest: Some("src/.bashrc_output".to_string()),
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -205,16 +196,9 @@ fn test_template_with_custom_variables() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_config_template".to_string(),
-        src: "dotfiles/f_config_template".to_string(),
-        dest: "src/.myconfig".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_config_template".to_string()),
+        dest: Some("src/.myconfig".to_string()),
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -267,16 +251,9 @@ fn test_template_not_backed_up_on_update() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_template_test".to_string(),
-        src: "dotfiles/f_template_test".to_string(),
-        dest: "src/.template_test".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_template_test".to_string()),
+        dest: Some("src/.template_test".to_string()),
+        ..dotr::package::Package::default()
     };
     config
         .packages
@@ -333,16 +310,9 @@ fn test_template_directory_deployment() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "d_config_dir".to_string(),
-        src: "dotfiles/d_config_dir".to_string(),
-        dest: "src/.config_output".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/d_config_dir".to_string()),
+        dest: Some("src/.config_output".to_string()),
+        ..dotr::package::Package::default()
     };
     config.packages.insert("d_config_dir".to_string(), package);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -395,16 +365,9 @@ fn test_mixed_template_and_regular_files() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_templated".to_string(),
-        src: "dotfiles/f_templated".to_string(),
-        dest: "src/.templated".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_templated".to_string()),
+        dest: Some("src/.templated".to_string()),
+        ..dotr::package::Package::default()
     };
     config.packages.insert("f_templated".to_string(), package);
     config.save(&fixture.cwd).expect("Failed to save config");
@@ -471,16 +434,9 @@ fn test_template_with_tera_statements() {
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_advanced_template".to_string(),
-        src: "dotfiles/f_advanced_template".to_string(),
-        dest: "src/.advanced".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_advanced_template".to_string()),
+        dest: Some("src/.advanced".to_string()),
+        ..dotr::package::Package::default()
     };
     config
         .packages
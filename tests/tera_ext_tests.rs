@@ -0,0 +1,177 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    package::{is_tera_file, strip_tera_ext},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_tera_ext_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    // d_nvim holds a .tera template and a plain file, f_rc is a single .tera file
+    fn setup(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+        fs::write(self.cwd.join("config.toml"), CONFIG).expect("Failed to write config");
+        self.write_file(
+            "dotfiles/d_nvim/init.lua.tera",
+            "vim.g.leader = '{{ LEADER }}'\n",
+        );
+        self.write_file("dotfiles/d_nvim/lua/plain.lua", "return {}\n");
+        self.write_file("dotfiles/f_rc.tera", "leader={{ LEADER }}\n");
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(command)))
+    }
+
+    fn deploy(&self, prune: bool) {
+        self.run(Command::Deploy(DeployUpdateArgs {
+            prune,
+            ..Default::default()
+        }))
+        .expect("Deploy failed");
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).unwrap_or_default()
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.cwd.join(path).exists()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[variables]
+LEADER = ","
+
+[packages.d_nvim]
+src = "dotfiles/d_nvim"
+dest = "nvim"
+
+[packages.f_rc]
+src = "dotfiles/f_rc.tera"
+dest = "rc"
+"#;
+
+#[test]
+fn test_tera_extension_helpers() {
+    assert!(is_tera_file(&PathBuf::from("init.lua.tera")));
+    assert!(!is_tera_file(&PathBuf::from("init.lua")));
+    assert!(!is_tera_file(&PathBuf::from(".tera")));
+    assert_eq!(
+        strip_tera_ext(&PathBuf::from("lua/init.lua.tera")),
+        PathBuf::from("lua/init.lua")
+    );
+    assert_eq!(
+        strip_tera_ext(&PathBuf::from("lua/init.lua")),
+        PathBuf::from("lua/init.lua")
+    );
+}
+
+#[test]
+fn test_deploy_strips_tera_extension() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+    fixture.deploy(false);
+
+    assert_eq!(fixture.read("nvim/init.lua"), "vim.g.leader = ','\n");
+    assert!(!fixture.exists("nvim/init.lua.tera"));
+    assert_eq!(fixture.read("nvim/lua/plain.lua"), "return {}\n");
+    assert_eq!(fixture.read("rc"), "leader=,\n");
+
+    // Pruning knows init.lua comes from init.lua.tera
+    fixture.deploy(true);
+    assert!(fixture.exists("nvim/init.lua"));
+}
+
+#[test]
+fn test_tera_file_is_always_validated() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+    fixture.write_file("dotfiles/d_nvim/init.lua.tera", "{{ MISSING }}\n");
+
+    let err = fixture
+        .run(Command::Deploy(DeployUpdateArgs::default()))
+        .expect_err("Undefined variable should fail");
+    assert!(err.to_string().contains("MISSING"), "{}", err);
+}
+
+#[test]
+fn test_update_skips_rendered_files() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+    fixture.deploy(false);
+    fixture.write_file("nvim/init.lua", "vim.g.leader = ' '\n");
+    fixture.write_file("nvim/lua/plain.lua", "return { edited = true }\n");
+    fixture.write_file("rc", "leader=edited\n");
+
+    fixture
+        .run(Command::Update(UpdateArgs::default()))
+        .expect("Update failed");
+    assert!(!fixture.exists("dotfiles/d_nvim/init.lua"));
+    assert_eq!(
+        fixture.read("dotfiles/d_nvim/init.lua.tera"),
+        "vim.g.leader = '{{ LEADER }}'\n"
+    );
+    assert_eq!(
+        fixture.read("dotfiles/d_nvim/lua/plain.lua"),
+        "return { edited = true }\n"
+    );
+    assert_eq!(fixture.read("dotfiles/f_rc.tera"), "leader={{ LEADER }}\n");
+}
+
+#[test]
+fn test_diff_compares_stripped_dest() {
+    let fixture = TestFixture::new();
+    fixture.setup();
+    fixture.deploy(false);
+    fixture.write_file("nvim/init.lua", "vim.g.leader = ' '\n");
+
+    let config = dotr::Config::from_path(&fixture.cwd).unwrap();
+    let ctx = dotr::Context::from_config(&fixture.cwd, &config).unwrap();
+    let report = config
+        .diff_packages(&ctx, &DeployUpdateArgs::default())
+        .expect("Diff failed");
+    let nvim = report.packages.iter().find(|p| p.name == "d_nvim").unwrap();
+    let init = nvim
+        .diffs
+        .iter()
+        .find(|d| d.dest == fixture.cwd.join("nvim/init.lua"))
+        .expect("init.lua should be compared");
+    assert!(init.is_changed());
+    assert_eq!(init.new.as_deref(), Some("vim.g.leader = ','\n"));
+}
@@ -0,0 +1,211 @@
+use std::{fs, path::Path, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, StatusArgs, UpdateArgs, run_cli},
+    config::Config,
+    context::Context,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let fixture = Self::empty();
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            "banner = false\n\n[packages.d_nvim]\nsrc = \"dotfiles/nvim\"\ndest = \"home/nvim\"\n",
+        )
+        .expect("Failed to write config");
+        fixture.write("dotfiles/nvim/init.lua", "-- init\n");
+        fixture.write("dotfiles/nvim/lua/old_plugin.lua", "-- old\n");
+        fixture
+    }
+
+    fn empty() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_tombstone_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    // Another machine with a checkout of this repository, and whatever it
+    // deployed so far
+    fn clone_machine(&self) -> Self {
+        let other = Self::empty();
+        copy_dir(&self.cwd, &other.cwd);
+        other
+    }
+
+    // Bring the repository of `from` over, as a git pull would
+    fn pull(&self, from: &TestFixture) {
+        fs::remove_dir_all(self.cwd.join("dotfiles")).unwrap();
+        copy_dir(&from.cwd.join("dotfiles"), &self.cwd.join("dotfiles"));
+        fs::copy(from.cwd.join("config.toml"), self.cwd.join("config.toml")).unwrap();
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self) {
+        self.run(Command::Deploy(DeployUpdateArgs::default()))
+            .expect("Deploy failed");
+    }
+
+    fn prune_missing(&self) {
+        self.run(Command::Update(UpdateArgs {
+            prune_missing: true,
+            ..Default::default()
+        }))
+        .expect("Update failed");
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn config(&self) -> Config {
+        Config::from_path(&self.cwd).expect("Failed to load config")
+    }
+
+    fn status(&self) -> String {
+        let conf = self.config();
+        let ctx = Context::from_config(&self.cwd, &conf).expect("Failed to create context");
+        conf.render_status(&ctx, &StatusArgs::default(), false)
+            .expect("Status failed")
+    }
+
+    fn stale_in_diff(&self) -> Vec<PathBuf> {
+        let conf = self.config();
+        let ctx = Context::from_config(&self.cwd, &conf).expect("Failed to create context");
+        let report = conf
+            .diff_packages(&ctx, &DeployUpdateArgs::default())
+            .expect("Diff failed");
+        report
+            .packages
+            .into_iter()
+            .flat_map(|p| p.removed)
+            .collect()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    for entry in walkdir::WalkDir::new(from) {
+        let entry = entry.unwrap();
+        let target = to.join(entry.path().strip_prefix(from).unwrap());
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target).unwrap();
+        } else {
+            fs::copy(entry.path(), &target).unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_deletion_reaches_the_other_machine() {
+    let laptop = TestFixture::new();
+    laptop.deploy();
+    let desktop = laptop.clone_machine();
+
+    // Deleted on the laptop and copied back with --prune-missing
+    fs::remove_file(laptop.cwd.join("home/nvim/lua/old_plugin.lua")).unwrap();
+    laptop.prune_missing();
+    assert!(!laptop.cwd.join("dotfiles/nvim/lua/old_plugin.lua").exists());
+    assert_eq!(
+        laptop.config().packages["d_nvim"].removed,
+        vec!["lua/old_plugin.lua".to_string()]
+    );
+
+    desktop.pull(&laptop);
+    let stale = desktop.cwd.join("home/nvim/lua/old_plugin.lua");
+    assert!(
+        desktop.status().contains("out of sync"),
+        "{}",
+        desktop.status()
+    );
+    assert_eq!(desktop.stale_in_diff(), vec![stale.clone()]);
+
+    desktop.deploy();
+    assert!(!stale.exists());
+    assert_eq!(
+        fs::read_to_string(desktop.cwd.join("home/nvim/lua/old_plugin.lua.dotrbak")).unwrap(),
+        "-- old\n"
+    );
+    assert!(desktop.cwd.join("home/nvim/init.lua").exists());
+    assert!(!desktop.status().contains("out of sync"));
+    assert!(desktop.stale_in_diff().is_empty());
+}
+
+#[test]
+fn test_update_does_not_bring_a_stale_file_back() {
+    let laptop = TestFixture::new();
+    laptop.deploy();
+    let desktop = laptop.clone_machine();
+    fs::remove_file(laptop.cwd.join("home/nvim/lua/old_plugin.lua")).unwrap();
+    laptop.prune_missing();
+
+    desktop.pull(&laptop);
+    desktop
+        .run(Command::Update(UpdateArgs::default()))
+        .expect("Update failed");
+    assert!(
+        !desktop
+            .cwd
+            .join("dotfiles/nvim/lua/old_plugin.lua")
+            .exists()
+    );
+    assert_eq!(desktop.config().packages["d_nvim"].removed.len(), 1);
+}
+
+#[test]
+fn test_file_back_in_src_is_dropped_on_save() {
+    let fixture = TestFixture::new();
+    fs::write(
+        fixture.cwd.join("config.toml"),
+        "banner = false\n\n[packages.d_nvim]\nsrc = \"dotfiles/nvim\"\ndest = \"home/nvim\"\nremoved = [\"gone.lua\", \"lua/old_plugin.lua\"]\n",
+    )
+    .unwrap();
+    fixture.deploy();
+    // Still in src, so deploy leaves it alone
+    assert!(fixture.cwd.join("home/nvim/lua/old_plugin.lua").exists());
+
+    fixture.config().save(&fixture.cwd).expect("Save failed");
+    assert_eq!(
+        fixture.config().packages["d_nvim"].removed,
+        vec!["gone.lua".to_string()]
+    );
+}
+
+#[test]
+fn test_removed_entries_must_stay_inside_dest() {
+    let fixture = TestFixture::new();
+    for entry in ["../outside", "/etc/passwd", ""] {
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            format!(
+                "[packages.d_nvim]\nsrc = \"dotfiles/nvim\"\ndest = \"home/nvim\"\nremoved = [\"{}\"]\n",
+                entry
+            ),
+        )
+        .unwrap();
+        let err = Config::from_path(&fixture.cwd).expect_err("Config should be refused");
+        assert!(err.to_string().contains("removed entry"), "{}", err);
+    }
+}
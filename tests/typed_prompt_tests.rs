@@ -0,0 +1,244 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, parse_set_values, run_cli},
+    config::Config,
+    context::Context,
+    prompts::{Prompt, PromptKind},
+    utils::set_prompt_answers,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(config: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_typed_prompt_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        run_cli(Cli {
+            command: Some(Command::Init(InitArgs::default())),
+            working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+        .expect("Init failed");
+        fs::write(fixture.cwd.join("config.toml"), config).expect("Failed to write config");
+        fixture
+    }
+
+    // Ask the config's prompts, answering with `answers` in order
+    fn ask(&self, answers: &[&str], no_input: bool) -> Result<toml::Table, anyhow::Error> {
+        let conf = Config::from_path(&self.cwd)?;
+        let mut ctx = Context::from_config(&self.cwd, &conf)?;
+        ctx.set_no_input(no_input);
+        set_prompt_answers(answers.iter().map(|a| a.to_string()).collect());
+        ctx.get_prompted_variables(&conf, &None)
+    }
+
+    fn saved(&self) -> toml::Table {
+        let content = fs::read_to_string(self.cwd.join(".uservariables.toml")).unwrap_or_default();
+        toml::from_str(&content).expect("Failed to parse .uservariables.toml")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[prompts]
+NAME = "Your name?"
+PORT = { message = "Port?", type = "int", default = 8080 }
+DARK = { message = "Dark theme?", type = "bool" }
+SHELL_NAME = { message = "Shell?", type = "choice", choices = ["zsh", "fish"], default = "zsh" }
+EMAIL = { message = "Email?", pattern = "[^@ ]+@[^@ ]+" }
+"#;
+
+#[test]
+fn test_answers_are_typed() {
+    let fixture = TestFixture::new(CONFIG);
+    // In key order: DARK, EMAIL, NAME, PORT, SHELL_NAME
+    let vars = fixture
+        .ask(&["yes", "me@example.com", "Me", "", "fish"], false)
+        .expect("Prompting failed");
+    assert_eq!(vars["DARK"], toml::Value::Boolean(true));
+    assert_eq!(vars["PORT"], toml::Value::Integer(8080));
+    assert_eq!(vars["SHELL_NAME"].as_str(), Some("fish"));
+
+    let saved = fixture.saved();
+    assert_eq!(saved["PORT"], toml::Value::Integer(8080));
+    assert_eq!(saved["DARK"], toml::Value::Boolean(true));
+    assert_eq!(saved["EMAIL"].as_str(), Some("me@example.com"));
+    assert_eq!(saved["NAME"].as_str(), Some("Me"));
+}
+
+#[test]
+fn test_invalid_answer_is_asked_again() {
+    let fixture = TestFixture::new(CONFIG);
+    let vars = fixture
+        .ask(
+            &[
+                "maybe",
+                "n",
+                "not-an-email",
+                "me@example.com",
+                "Me",
+                "80a",
+                "443",
+                "bash",
+                "",
+            ],
+            false,
+        )
+        .expect("Prompting failed");
+    assert_eq!(vars["DARK"], toml::Value::Boolean(false));
+    assert_eq!(vars["EMAIL"].as_str(), Some("me@example.com"));
+    assert_eq!(vars["PORT"], toml::Value::Integer(443));
+    assert_eq!(vars["SHELL_NAME"].as_str(), Some("zsh"));
+}
+
+#[test]
+fn test_gives_up_after_three_invalid_answers() {
+    let fixture = TestFixture::new(CONFIG);
+    let err = fixture
+        .ask(&["maybe", "perhaps", "dunno"], false)
+        .expect_err("Three invalid answers should fail");
+    assert!(
+        err.to_string().contains("'DARK' after 3 attempts"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_no_input_uses_defaults_or_lists_missing() {
+    let fixture = TestFixture::new(CONFIG);
+    let err = fixture
+        .ask(&[], true)
+        .expect_err("Prompts without a default should fail");
+    let message = err.to_string();
+    assert!(
+        message.contains("Can't ask for 3 variable(s)"),
+        "{}",
+        message
+    );
+    assert!(
+        message.contains("DARK = false  # Dark theme?"),
+        "{}",
+        message
+    );
+    assert!(message.contains("NAME = \"\"  # Your name?"), "{}", message);
+    // The defaults were still kept
+    assert_eq!(fixture.saved()["PORT"], toml::Value::Integer(8080));
+
+    let fixture = TestFixture::new(
+        "banner = false\n[prompts]\nPORT = { message = \"Port?\", type = \"int\", default = 22 }\n",
+    );
+    let vars = fixture.ask(&[], true).expect("Defaults should be enough");
+    assert_eq!(vars["PORT"], toml::Value::Integer(22));
+}
+
+#[test]
+fn test_invalid_prompt_definitions_are_rejected() {
+    for prompt in [
+        r#"{ message = "Port?", type = "int", default = "abc" }"#,
+        r#"{ message = "Shell?", type = "choice" }"#,
+        r#"{ message = "Shell?", type = "choice", choices = ["zsh"], default = "bash" }"#,
+        r#"{ message = "Port?", type = "float" }"#,
+        r#"{ message = "Port?", type = "int", pattern = "[0-9]+" }"#,
+        r#"{ message = "Email?", pattern = "(" }"#,
+        r#"{ message = "Email?", defualt = "x" }"#,
+    ] {
+        let value: toml::Table = toml::from_str(&format!("P = {}", prompt)).unwrap();
+        assert!(
+            Prompt::from_value("P", &value["P"]).is_err(),
+            "{} should be rejected",
+            prompt
+        );
+    }
+}
+
+#[test]
+fn test_prompts_round_trip() {
+    let fixture = TestFixture::new(CONFIG);
+    let conf = Config::from_path(&fixture.cwd).expect("Failed to load config");
+    assert_eq!(conf.prompts["PORT"].kind, PromptKind::Int);
+    conf.save(&fixture.cwd).expect("Failed to save config");
+
+    let reloaded = Config::from_path(&fixture.cwd).expect("Failed to reload config");
+    assert_eq!(reloaded.prompts, conf.prompts);
+    // A bare message stays a bare message
+    let saved = fs::read_to_string(fixture.cwd.join("config.toml")).unwrap();
+    assert!(saved.contains("NAME = \"Your name?\""), "{}", saved);
+}
+
+#[test]
+fn test_set_answers_prompts_without_saving() {
+    let fixture = TestFixture::new(&format!(
+        "{}\n[packages.f_rc]\nsrc = \"dotfiles/f_rc\"\ndest = \"rc\"\n",
+        CONFIG
+    ));
+    fs::write(
+        fixture.cwd.join("dotfiles/f_rc"),
+        "{{ NAME }}:{{ PORT + 1 }}:{{ DARK }}\n",
+    )
+    .expect("Failed to write src");
+    fs::write(
+        fixture.cwd.join(".uservariables.toml"),
+        "NAME = \"Saved\"\n",
+    )
+    .expect("Failed to write uservariables");
+
+    run_cli(Cli {
+        command: Some(Command::Deploy(DeployUpdateArgs {
+            set: vec![
+                "NAME=From set".to_string(),
+                "DARK=true".to_string(),
+                "EMAIL=me@example.com".to_string(),
+            ],
+            ..Default::default()
+        })),
+        working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+        no_input: true,
+        ..Default::default()
+    })
+    .expect("Deploy failed");
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("rc")).unwrap(),
+        "From set:8081:true\n"
+    );
+
+    // Only the default taken for PORT is saved, --set values are not
+    let saved = fixture.saved();
+    assert_eq!(saved["NAME"].as_str(), Some("Saved"));
+    assert_eq!(saved["PORT"], toml::Value::Integer(8080));
+    assert!(!saved.contains_key("DARK"));
+    assert!(!saved.contains_key("EMAIL"));
+}
+
+#[test]
+fn test_parse_set_values() {
+    let vars = parse_set_values(&[
+        "PORT=8080".to_string(),
+        "DARK=true".to_string(),
+        "NAME=Jane Doe".to_string(),
+        "QUOTED=\"42\"".to_string(),
+        "URL=https://x.org/?a=b".to_string(),
+    ])
+    .expect("Parse failed");
+    assert_eq!(vars["PORT"], toml::Value::Integer(8080));
+    assert_eq!(vars["DARK"], toml::Value::Boolean(true));
+    assert_eq!(vars["NAME"].as_str(), Some("Jane Doe"));
+    assert_eq!(vars["QUOTED"].as_str(), Some("42"));
+    assert_eq!(vars["URL"].as_str(), Some("https://x.org/?a=b"));
+
+    assert!(parse_set_values(&["NOVALUE".to_string()]).is_err());
+    assert!(parse_set_values(&["=x".to_string()]).is_err());
+    assert!(parse_set_values(&["dotr=x".to_string()]).is_err());
+}
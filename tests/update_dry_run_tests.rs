@@ -0,0 +1,173 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use clap::Parser;
+use dotr::{
+    cli::{Cli, Command, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+    lock::WorkdirLock,
+    package::Package,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_dry_run_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+    }
+
+    fn update(&self, dry_run: bool, diff: bool) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Update(UpdateArgs {
+            dry_run,
+            diff,
+            ..Default::default()
+        }))))
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+
+    fn add_package(&self, name: &str, dest: &str, ignore: &[&str]) {
+        let mut config = Config::from_path(&self.cwd).expect("Failed to load config");
+        let mut table = toml::Table::new();
+        table.insert(
+            "src".to_string(),
+            toml::Value::String(format!("dotfiles/{}", name)),
+        );
+        table.insert(
+            "dest".to_string(),
+            toml::Value::String(self.cwd.join(dest).to_str().unwrap().to_string()),
+        );
+        if !ignore.is_empty() {
+            table.insert(
+                "ignore".to_string(),
+                toml::Value::Array(
+                    ignore
+                        .iter()
+                        .map(|p| toml::Value::String(p.to_string()))
+                        .collect(),
+                ),
+            );
+        }
+        let package = Package::from_table(name, &table).expect("Failed to build package");
+        config.packages.insert(name.to_string(), package);
+        config.save(&self.cwd).expect("Failed to save config");
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.cwd);
+    }
+}
+
+// A directory package whose dest has one edited and one new file
+fn setup_changed(fixture: &TestFixture) {
+    fixture.init();
+    fixture.write_file("dotfiles/d_app/a.conf", "a = 1\n");
+    fixture.write_file("dotfiles/d_app/b.conf", "b = 1\n");
+    fixture.write_file("home/app/a.conf", "a = 2\n");
+    fixture.write_file("home/app/b.conf", "b = 1\n");
+    fixture.write_file("home/app/c.conf", "c = 1\n");
+    fixture.add_package("d_app", "home/app", &[]);
+}
+
+#[test]
+fn test_dry_run_leaves_source_untouched() {
+    let fixture = TestFixture::new();
+    setup_changed(&fixture);
+
+    let err = fixture
+        .update(true, false)
+        .expect_err("Dry run should fail when files would change");
+    assert!(
+        err.to_string().contains("would change 2 file(s)"),
+        "Unexpected error: {}",
+        err
+    );
+    assert_eq!(fixture.read_file("dotfiles/d_app/a.conf"), "a = 1\n");
+    assert!(!fixture.cwd.join("dotfiles/d_app/c.conf").exists());
+}
+
+#[test]
+fn test_dry_run_with_diff() {
+    let fixture = TestFixture::new();
+    setup_changed(&fixture);
+
+    assert!(fixture.update(true, true).is_err());
+    assert_eq!(fixture.read_file("dotfiles/d_app/a.conf"), "a = 1\n");
+}
+
+#[test]
+fn test_dry_run_clean_succeeds() {
+    let fixture = TestFixture::new();
+    setup_changed(&fixture);
+    fixture.update(false, false).expect("Update failed");
+
+    assert_eq!(fixture.read_file("dotfiles/d_app/a.conf"), "a = 2\n");
+    assert_eq!(fixture.read_file("dotfiles/d_app/c.conf"), "c = 1\n");
+    fixture
+        .update(true, false)
+        .expect("Dry run should succeed once everything is in sync");
+}
+
+#[test]
+fn test_dry_run_skips_ignored_and_templated() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.write_file("dotfiles/d_app/a.conf", "a = 1\n");
+    fixture.write_file("home/app/a.conf", "a = 1\n");
+    fixture.write_file("home/app/cache.log", "noise\n");
+    fixture.add_package("d_app", "home/app", &["*.log"]);
+    fixture.write_file("dotfiles/f_tpl", "home = {{ HOME }}\n");
+    fixture.write_file("home/tpl", "home = /somewhere\n");
+    fixture.add_package("f_tpl", "home/tpl", &[]);
+
+    fixture
+        .update(true, false)
+        .expect("Ignored and templated files should not count as changes");
+}
+
+#[test]
+fn test_dry_run_does_not_take_lock() {
+    let fixture = TestFixture::new();
+    setup_changed(&fixture);
+    fixture.update(false, false).expect("Update failed");
+    let _lock = WorkdirLock::acquire(&fixture.cwd, Duration::ZERO).expect("Lock failed");
+
+    fixture
+        .update(true, false)
+        .expect("Dry run should not need the lock");
+    assert!(fixture.update(false, false).is_err());
+}
+
+#[test]
+fn test_diff_requires_dry_run() {
+    assert!(Cli::try_parse_from(["dotr", "update", "--diff"]).is_err());
+    assert!(Cli::try_parse_from(["dotr", "update", "--dry-run", "--diff"]).is_ok());
+}
@@ -0,0 +1,149 @@
+use std::{fs, path::PathBuf};
+
+use clap::{CommandFactory, Parser};
+use dotr::cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_update_options_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            "banner = false\n\n[variables]\nname = \"dotr\"\n\n[packages.d_app]\nsrc = \"dotfiles/app\"\ndest = \"home/app\"\n",
+        )
+        .expect("Failed to write config");
+        fixture.write("dotfiles/app/config", "setting = 1\n");
+        fixture.write("dotfiles/app/themes/dark", "dark\n");
+        fixture.write("dotfiles/app/greeting.tera", "hello {{ name }}\n");
+        fixture
+            .run(Command::Deploy(DeployUpdateArgs::default()))
+            .expect("Deploy failed");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn update(&self, args: UpdateArgs) -> Result<(), anyhow::Error> {
+        self.run(Command::Update(args))
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.cwd.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.cwd.join(path).exists()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+#[test]
+fn test_new_files_are_included_by_default() {
+    let fixture = TestFixture::new();
+    fixture.write("home/app/plugins/extra", "extra\n");
+    fixture
+        .update(UpdateArgs::default())
+        .expect("Update failed");
+    assert!(fixture.exists("dotfiles/app/plugins/extra"));
+}
+
+#[test]
+fn test_no_include_new_only_updates_known_files() {
+    let fixture = TestFixture::new();
+    fixture.write("home/app/plugins/extra", "extra\n");
+    fixture.write("home/app/scratch", "scratch\n");
+    fixture.write("home/app/config", "setting = 2\n");
+    fixture
+        .update(UpdateArgs {
+            no_include_new: true,
+            ..Default::default()
+        })
+        .expect("Update failed");
+    assert!(!fixture.exists("dotfiles/app/plugins"));
+    assert!(!fixture.exists("dotfiles/app/scratch"));
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("dotfiles/app/config")).unwrap(),
+        "setting = 2\n"
+    );
+}
+
+#[test]
+fn test_prune_missing_removes_sources_gone_from_dest() {
+    let fixture = TestFixture::new();
+    fs::remove_dir_all(fixture.cwd.join("home/app/themes")).unwrap();
+    fs::remove_file(fixture.cwd.join("home/app/greeting")).unwrap();
+
+    // A dry run only lists them
+    let err = fixture
+        .update(UpdateArgs {
+            prune_missing: true,
+            dry_run: true,
+            ..Default::default()
+        })
+        .expect_err("A dry run with changes exits with an error");
+    assert!(err.to_string().contains("1 file(s)"), "{}", err);
+    assert!(fixture.exists("dotfiles/app/themes/dark"));
+
+    fixture
+        .update(UpdateArgs {
+            prune_missing: true,
+            ..Default::default()
+        })
+        .expect("Update failed");
+    assert!(!fixture.exists("dotfiles/app/themes"));
+    assert!(fixture.exists("dotfiles/app/config"));
+    // Templates are never pruned
+    assert!(fixture.exists("dotfiles/app/greeting.tera"));
+}
+
+#[test]
+fn test_sources_stay_without_prune_missing() {
+    let fixture = TestFixture::new();
+    fs::remove_dir_all(fixture.cwd.join("home/app/themes")).unwrap();
+    fixture
+        .update(UpdateArgs::default())
+        .expect("Update failed");
+    assert!(fixture.exists("dotfiles/app/themes/dark"));
+}
+
+#[test]
+fn test_update_flags_parse() {
+    let cli = Cli::try_parse_from(["dotr", "update", "--include-new", "--no-include-new"])
+        .expect("Update flags should parse");
+    let Some(Command::Update(args)) = cli.command else {
+        panic!("Expected the update command");
+    };
+    assert!(args.no_include_new);
+    // Update-only flags aren't offered to deploy
+    assert!(Cli::try_parse_from(["dotr", "deploy", "--prune-missing"]).is_err());
+    assert!(Cli::try_parse_from(["dotr", "deploy", "--dry-run"]).is_err());
+
+    let help = Cli::command()
+        .find_subcommand_mut("update")
+        .expect("update is a subcommand")
+        .render_long_help()
+        .to_string();
+    assert!(help.contains("copied over its src"), "{}", help);
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fs, path::PathBuf};
+use std::{fs, path::PathBuf};
 
 use dotr::{
     cli::{DeployUpdateArgs, InitArgs, run_cli},
@@ -8,28 +8,29 @@ use dotr::{
 
 mod common;
 
-const PLAYGROUND_DIR: &str = "tests/playground";
-
 struct TestFixture {
     cwd: PathBuf,
 }
 
 impl TestFixture {
     fn new() -> Self {
-        Self {
-            cwd: PathBuf::from(PLAYGROUND_DIR),
-        }
+        let cwd =
+            std::env::temp_dir().join(format!("dotr_uservariables_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&cwd).expect("Failed to create temp dir");
+        Self { cwd }
     }
 
     fn get_cli(&self, command: Option<dotr::cli::Command>) -> dotr::cli::Cli {
         dotr::cli::Cli {
             command,
-            working_dir: Some(PLAYGROUND_DIR.to_string()),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
         }
     }
 
     fn init(&self) {
-        run_cli(self.get_cli(Some(dotr::cli::Command::Init(InitArgs {})))).expect("Init failed");
+        run_cli(self.get_cli(Some(dotr::cli::Command::Init(InitArgs::default()))))
+            .expect("Init failed");
     }
 
     fn deploy(&self, packages: Option<Vec<String>>) {
@@ -37,6 +38,7 @@ impl TestFixture {
             self.get_cli(Some(dotr::cli::Command::Deploy(DeployUpdateArgs {
                 packages,
                 profile: None,
+                ..Default::default()
             }))),
         )
         .expect("Deploy failed");
@@ -60,7 +62,7 @@ impl TestFixture {
 
 impl Drop for TestFixture {
     fn drop(&mut self) {
-        common::teardown(&self.cwd);
+        fs::remove_dir_all(&self.cwd).ok();
     }
 }
 
@@ -230,16 +232,9 @@ DATABASE_NAME = "production-db"
     let mut config = fixture.get_config();
     let package = dotr::package::Package {
         name: "f_env_template".to_string(),
-        src: "dotfiles/f_env_template".to_string(),
-        dest: "src/.env".to_string(),
-        dependencies: None,
-        variables: toml::Table::new(),
-        pre_actions: Vec::new(),
-        post_actions: Vec::new(),
-        targets: std::collections::HashMap::new(),
-        skip: false,
-        prompts: HashMap::new(),
-        ignore: Vec::new(),
+        src: Some("dotfiles/f_env_template".to_string()),
+        dest: Some("src/.env".to_string()),
+        ..dotr::package::Package::default()
     };
     config
         .packages
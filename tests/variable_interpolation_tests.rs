@@ -0,0 +1,143 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, run_cli},
+    config::Config,
+    context::Context,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new(config: &str) -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_interpolation_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fs::write(fixture.cwd.join("config.toml"), config).expect("Failed to write config");
+        fs::write(fixture.cwd.join("dotfiles/f_rc"), "{{ visual }}\n").unwrap();
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self, profile: Option<&str>) -> Result<(), anyhow::Error> {
+        self.run(Command::Deploy(DeployUpdateArgs {
+            profile: profile.map(str::to_string),
+            ..Default::default()
+        }))
+    }
+
+    fn context(&self) -> Context {
+        let conf = Config::from_path(&self.cwd).expect("Failed to load config");
+        Context::from_config(&self.cwd, &conf).expect("Failed to create context")
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CHAIN_CONFIG: &str = r#"
+banner = false
+
+[variables]
+editor = "nvim"
+visual = "{{ pager }} -R"
+pager = "{{ editor }}"
+ports = [8080, "{{ editor }}"]
+
+[variables.git]
+editor = "{{ visual }} +1"
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "rc"
+
+[profiles.work]
+dependencies = ["f_rc"]
+
+[profiles.work.variables]
+editor = "vim"
+"#;
+
+#[test]
+fn test_variables_resolve_through_chains() {
+    let fixture = TestFixture::new(CHAIN_CONFIG);
+    let variables = fixture.context().get_context_variables();
+
+    assert_eq!(variables["visual"].as_str(), Some("nvim -R"));
+    assert_eq!(variables["git"]["editor"].as_str(), Some("nvim -R +1"));
+    assert_eq!(variables["ports"][0].as_integer(), Some(8080));
+    assert_eq!(variables["ports"][1].as_str(), Some("nvim"));
+}
+
+#[test]
+fn test_profile_variables_feed_resolution() {
+    let fixture = TestFixture::new(CHAIN_CONFIG);
+    fixture.deploy(Some("work")).expect("Deploy failed");
+    assert_eq!(fixture.read_file("rc"), "vim -R\n");
+}
+
+#[test]
+fn test_package_variables_see_the_package() {
+    let fixture = TestFixture::new(&format!(
+        "{}\n[packages.f_rc.variables]\nvisual = \"{{{{ dotr_package.name }}}} view\"\n",
+        CHAIN_CONFIG
+    ));
+    fixture.deploy(None).expect("Deploy failed");
+    assert_eq!(fixture.read_file("rc"), "f_rc view\n");
+}
+
+#[test]
+fn test_cycle_names_the_variables() {
+    let fixture = TestFixture::new(
+        "banner = false\n\n[variables]\nvisual = \"{{ pager }}\"\npager = \"{{ visual }}\"\n\n[packages.f_rc]\nsrc = \"dotfiles/f_rc\"\ndest = \"rc\"\n",
+    );
+    let err = fixture
+        .context()
+        .resolved_variables(None)
+        .expect_err("A cycle should not resolve");
+    let message = err.to_string();
+    assert!(message.contains("cycle"), "{}", message);
+    assert!(
+        message.contains("visual") && message.contains("pager"),
+        "{}",
+        message
+    );
+
+    assert!(fixture.deploy(None).is_err());
+    assert!(!fixture.cwd.join("rc").exists());
+}
+
+#[test]
+fn test_render_error_names_the_variable() {
+    let fixture = TestFixture::new(
+        "banner = false\n\n[variables]\nvisual = \"{{ missing }} -R\"\n\n[packages.f_rc]\nsrc = \"dotfiles/f_rc\"\ndest = \"rc\"\n",
+    );
+    let err = fixture
+        .deploy(None)
+        .expect_err("An undefined reference should fail the deploy");
+    assert!(err.to_string().contains("'visual'"), "{}", err);
+
+    // Elsewhere the value is kept as written
+    let variables = fixture.context().get_context_variables();
+    assert_eq!(variables["visual"].as_str(), Some("{{ missing }} -R"));
+}
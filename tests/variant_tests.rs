@@ -0,0 +1,170 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+    context::{Context, current_os, system_variables},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_variant_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fixture.write_file("config.toml", CONFIG);
+        fixture.write_file("dotfiles/kitty/kitty.conf", "font_size 11\n");
+        fixture.write_file("dotfiles/kitty/theme.conf", "light\n");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self, profile: Option<&str>) {
+        self.run(Command::Deploy(DeployUpdateArgs {
+            profile: profile.map(str::to_string),
+            ..Default::default()
+        }))
+        .expect("Deploy failed");
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn read_file(&self, path: &str) -> String {
+        fs::read_to_string(self.cwd.join(path)).expect("Failed to read file")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[packages.d_kitty]
+src = "dotfiles/kitty"
+dest = "home/kitty"
+prune_dest = true
+
+[profiles.work]
+dependencies = ["d_kitty"]
+"#;
+
+fn this_host() -> String {
+    system_variables()["hostname"].as_str().unwrap().to_string()
+}
+
+#[test]
+fn test_variants_for_other_hosts_are_skipped() {
+    let fixture = TestFixture::new();
+    fixture.write_file("dotfiles/kitty/kitty.conf##hostname.not-this-host", "big\n");
+    fixture.write_file("dotfiles/kitty/work.conf##profile.work", "work\n");
+    fixture.deploy(None);
+
+    assert_eq!(fixture.read_file("home/kitty/kitty.conf"), "font_size 11\n");
+    assert!(!fixture.cwd.join("home/kitty/work.conf").exists());
+    assert_eq!(
+        fs::read_dir(fixture.cwd.join("home/kitty"))
+            .unwrap()
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn test_host_beats_os_beats_profile_beats_base() {
+    let fixture = TestFixture::new();
+    fixture.write_file("dotfiles/kitty/theme.conf##profile.work", "profile\n");
+    fixture.deploy(Some("work"));
+    assert_eq!(fixture.read_file("home/kitty/theme.conf"), "profile\n");
+
+    fixture.write_file(
+        &format!("dotfiles/kitty/theme.conf##os.{}", current_os()),
+        "os\n",
+    );
+    fixture.deploy(Some("work"));
+    assert_eq!(fixture.read_file("home/kitty/theme.conf"), "os\n");
+
+    fixture.write_file(
+        &format!("dotfiles/kitty/theme.conf##hostname.{}", this_host()),
+        "host\n",
+    );
+    fixture.deploy(Some("work"));
+    assert_eq!(fixture.read_file("home/kitty/theme.conf"), "host\n");
+    // Pruning knows theme.conf came from a variant
+    assert_eq!(fixture.read_file("home/kitty/kitty.conf"), "font_size 11\n");
+    assert!(fixture.cwd.join("home/kitty/theme.conf").exists());
+}
+
+#[test]
+fn test_update_writes_back_to_the_deployed_variant() {
+    let fixture = TestFixture::new();
+    let variant = format!("dotfiles/kitty/theme.conf##hostname.{}", this_host());
+    fixture.write_file(&variant, "dark\n");
+    fixture.write_file("dotfiles/kitty/extra.conf##hostname.not-this-host", "x\n");
+    fixture.deploy(None);
+
+    fixture.write_file("home/kitty/theme.conf", "darker\n");
+    fixture.write_file("home/kitty/extra.conf", "local\n");
+    fixture
+        .run(Command::Update(UpdateArgs::default()))
+        .expect("Update failed");
+
+    assert_eq!(fixture.read_file(&variant), "darker\n");
+    assert_eq!(fixture.read_file("dotfiles/kitty/theme.conf"), "light\n");
+    assert!(!fixture.cwd.join("dotfiles/kitty/extra.conf").exists());
+}
+
+#[test]
+fn test_diff_compares_the_selected_variant() {
+    let fixture = TestFixture::new();
+    fixture.deploy(None);
+    let variant = format!("dotfiles/kitty/theme.conf##hostname.{}", this_host());
+    fixture.write_file(&variant, "dark\n");
+
+    let conf = Config::from_path(&fixture.cwd).unwrap();
+    let ctx = Context::from_config(&fixture.cwd, &conf).unwrap();
+    let report = conf
+        .diff_packages(&ctx, &DeployUpdateArgs::default())
+        .expect("Diff failed");
+    let diff = report.packages[0]
+        .diffs
+        .iter()
+        .find(|diff| diff.dest.ends_with("theme.conf"))
+        .expect("theme.conf should be diffed");
+    assert_eq!(diff.src, fixture.cwd.join(&variant));
+    assert_eq!(diff.new.as_deref(), Some("dark\n"));
+}
+
+#[test]
+fn test_invalid_variant_is_an_error() {
+    let fixture = TestFixture::new();
+    fixture.write_file("dotfiles/kitty/theme.conf##arch.arm64", "arm\n");
+    assert!(
+        fixture
+            .run(Command::Deploy(DeployUpdateArgs::default()))
+            .is_err()
+    );
+}
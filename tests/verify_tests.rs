@@ -0,0 +1,172 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{CleanArgs, Cli, Command, DeployUpdateArgs, InitArgs, VerifyArgs, run_cli},
+    manifest::{Manifest, manifest_path, sha256_file},
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_verify_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        fixture
+            .run(Command::Init(InitArgs::default()))
+            .expect("Init failed");
+        fixture.write_file("config.toml", CONFIG);
+        fixture.write_file("dotfiles/f_rc", "hello\n");
+        fixture.write_file("dotfiles/d_nvim/init.lua", "init\n");
+        fixture.write_file("dotfiles/d_nvim/lua/plugins.lua", "plugins\n");
+        fixture
+    }
+
+    fn run(&self, command: Command) -> Result<(), anyhow::Error> {
+        run_cli(Cli {
+            command: Some(command),
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    }
+
+    fn deploy(&self) {
+        self.run(Command::Deploy(DeployUpdateArgs::default()))
+            .expect("Deploy failed");
+    }
+
+    fn verify(&self, packages: Option<Vec<&str>>, update_manifest: bool) -> anyhow::Result<()> {
+        self.run(Command::Verify(VerifyArgs {
+            packages: packages.map(|names| names.iter().map(|n| n.to_string()).collect()),
+            update_manifest,
+            ..Default::default()
+        }))
+    }
+
+    fn write_file(&self, path: &str, content: &str) {
+        let file_path = self.cwd.join(path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create parent dir");
+        }
+        fs::write(file_path, content).expect("Failed to write file");
+    }
+
+    fn manifest(&self, package: &str) -> Option<Manifest> {
+        Manifest::load(&self.cwd, package).expect("Failed to load manifest")
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[packages.f_rc]
+src = "dotfiles/f_rc"
+dest = "rc"
+
+[packages.d_nvim]
+src = "dotfiles/d_nvim"
+dest = "nvim"
+"#;
+
+#[test]
+fn test_deploy_writes_sha256_manifests() {
+    let fixture = TestFixture::new();
+    fixture.deploy();
+
+    let manifest = fixture.manifest("f_rc").expect("No manifest for f_rc");
+    let dest = fixture.cwd.join("rc").to_string_lossy().to_string();
+    assert_eq!(
+        manifest.files.get(&dest).map(String::as_str),
+        Some("5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03")
+    );
+    assert_eq!(fixture.manifest("d_nvim").unwrap().files.len(), 2);
+    assert!(
+        !manifest_path(&fixture.cwd, "f_rc")
+            .with_extension("toml.tmp")
+            .exists()
+    );
+    fixture.verify(None, false).expect("Verify failed");
+}
+
+#[test]
+fn test_modified_and_missing_files_fail() {
+    let fixture = TestFixture::new();
+    fixture.deploy();
+    fixture.write_file("rc", "tampered\n");
+
+    fixture
+        .verify(None, false)
+        .expect_err("A modified file should fail verification");
+    fixture
+        .verify(Some(vec!["d_nvim"]), false)
+        .expect("Only the untouched package is verified");
+
+    fs::remove_file(fixture.cwd.join("nvim/init.lua")).unwrap();
+    let statuses = fixture.manifest("d_nvim").unwrap().verify().unwrap();
+    let missing: Vec<_> = statuses
+        .iter()
+        .filter(|(_, status)| *status == dotr::manifest::FileStatus::Missing)
+        .collect();
+    assert_eq!(missing.len(), 1);
+    assert!(missing[0].0.ends_with("nvim/init.lua"));
+}
+
+#[test]
+fn test_update_manifest_rebaselines() {
+    let fixture = TestFixture::new();
+    fixture.deploy();
+    fixture.write_file("rc", "tampered\n");
+
+    fixture
+        .verify(Some(vec!["f_rc"]), true)
+        .expect("Updating the manifest should succeed");
+    fixture.verify(None, false).expect("Verify failed");
+    let dest = fixture.cwd.join("rc");
+    assert_eq!(
+        fixture.manifest("f_rc").unwrap().files[&dest.to_string_lossy().to_string()],
+        sha256_file(&dest).unwrap()
+    );
+}
+
+#[test]
+fn test_unknown_package_has_no_manifest() {
+    let fixture = TestFixture::new();
+    fixture.deploy();
+    let err = fixture
+        .verify(Some(vec!["f_missing"]), false)
+        .expect_err("A package without manifest should fail");
+    assert!(err.to_string().contains("f_missing"), "{}", err);
+}
+
+#[test]
+fn test_manifests_are_removed_with_the_package() {
+    let fixture = TestFixture::new();
+    fixture.deploy();
+
+    fixture
+        .run(Command::Clean(CleanArgs {
+            packages: Some(vec!["f_rc".to_string()]),
+            ..Default::default()
+        }))
+        .expect("Clean failed");
+    assert!(fixture.manifest("f_rc").is_none());
+    assert!(fixture.manifest("d_nvim").is_some());
+
+    let config = CONFIG.replace(
+        "[packages.d_nvim]\nsrc = \"dotfiles/d_nvim\"\ndest = \"nvim\"\n",
+        "",
+    );
+    fixture.write_file("config.toml", &config);
+    fixture.deploy();
+    assert!(fixture.manifest("d_nvim").is_none());
+    assert!(fixture.manifest("f_rc").is_some());
+}
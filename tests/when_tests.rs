@@ -0,0 +1,197 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, DeployUpdateArgs, InitArgs, UpdateArgs, run_cli},
+    config::Config,
+    context::current_os,
+    package::Package,
+    profile::Profile,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir =
+            std::env::temp_dir().join(format!("dotr_when_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        Self { cwd: temp_dir }
+    }
+
+    fn get_cli(&self, command: Option<Command>) -> Cli {
+        Cli {
+            command,
+            working_dir: Some(self.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn init(&self) {
+        run_cli(self.get_cli(Some(Command::Init(InitArgs::default())))).expect("Init failed");
+        let mut config = self.get_config();
+        config.variables.insert(
+            "HAS_BREW".to_string(),
+            toml::Value::String("/opt/homebrew".to_string()),
+        );
+        config.save(&self.cwd).expect("Failed to save config");
+    }
+
+    fn get_config(&self) -> Config {
+        Config::from_path(&self.cwd).expect("Failed to load config")
+    }
+
+    fn deploy(&self, args: DeployUpdateArgs) -> Result<(), anyhow::Error> {
+        run_cli(self.get_cli(Some(Command::Deploy(args))))
+    }
+
+    // A file package deploying `name` to home/<name>
+    fn add_package(&self, name: &str, when: &str, skip: bool) {
+        fs::write(self.cwd.join("dotfiles").join(name), name).unwrap();
+        let mut config = self.get_config();
+        let mut table = toml::Table::new();
+        table.insert(
+            "src".to_string(),
+            toml::Value::String(format!("dotfiles/{}", name)),
+        );
+        table.insert(
+            "dest".to_string(),
+            toml::Value::String(
+                self.cwd
+                    .join("home")
+                    .join(name)
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            ),
+        );
+        table.insert("when".to_string(), toml::Value::String(when.to_string()));
+        table.insert("skip".to_string(), toml::Value::Boolean(skip));
+        let package = Package::from_table(name, &table).expect("Failed to build package");
+        config.packages.insert(name.to_string(), package);
+        config.save(&self.cwd).expect("Failed to save config");
+    }
+
+    fn deployed(&self, name: &str) -> bool {
+        self.cwd.join("home").join(name).exists()
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.cwd);
+    }
+}
+
+#[test]
+fn test_when_condition_filters_packages() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    let os = current_os();
+    fixture.add_package(
+        "f_here",
+        &format!("dotr.os == '{}' and HAS_BREW", os),
+        false,
+    );
+    fixture.add_package("f_elsewhere", "dotr.os == 'plan9'", false);
+    fixture.add_package("f_unset", "dotr.os == dotr.os and MISSING_VARIABLE", false);
+
+    fixture
+        .deploy(DeployUpdateArgs::default())
+        .expect("Deploy failed");
+    assert!(fixture.deployed("f_here"));
+    assert!(!fixture.deployed("f_elsewhere"));
+    assert!(!fixture.deployed("f_unset"));
+}
+
+#[test]
+fn test_invalid_condition_names_package() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.add_package("f_broken", "dotr.os ==", false);
+
+    let err = fixture
+        .deploy(DeployUpdateArgs::default())
+        .expect_err("Deploy should fail");
+    assert!(
+        err.to_string()
+            .contains("Invalid 'when' condition of package 'f_broken'"),
+        "Unexpected error: {}",
+        err
+    );
+}
+
+#[test]
+fn test_skip_wins_over_true_condition() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.add_package("f_skipped", "true", true);
+
+    fixture
+        .deploy(DeployUpdateArgs::default())
+        .expect("Deploy failed");
+    assert!(!fixture.deployed("f_skipped"));
+}
+
+#[test]
+fn test_condition_applies_to_profile_and_named_packages() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.add_package("f_no", "false", false);
+    fixture.add_package("f_yes", "true", false);
+    let mut config = fixture.get_config();
+    let mut profile = Profile::new("work");
+    profile.dependencies = vec!["f_no".to_string(), "f_yes".to_string()];
+    config.profiles.insert("work".to_string(), profile);
+    config.save(&fixture.cwd).expect("Failed to save config");
+
+    fixture
+        .deploy(DeployUpdateArgs {
+            profile: Some("work".to_string()),
+            ..Default::default()
+        })
+        .expect("Deploy failed");
+    assert!(fixture.deployed("f_yes"));
+    assert!(!fixture.deployed("f_no"));
+
+    fixture
+        .deploy(DeployUpdateArgs {
+            packages: Some(vec!["f_no".to_string()]),
+            ..Default::default()
+        })
+        .expect("Deploy failed");
+    assert!(!fixture.deployed("f_no"));
+}
+
+#[test]
+fn test_update_respects_condition() {
+    let fixture = TestFixture::new();
+    fixture.init();
+    fixture.add_package("f_no", "false", false);
+    fs::create_dir_all(fixture.cwd.join("home")).unwrap();
+    fs::write(fixture.cwd.join("home/f_no"), "edited").unwrap();
+
+    run_cli(fixture.get_cli(Some(Command::Update(UpdateArgs::default())))).expect("Update failed");
+    assert_eq!(
+        fs::read_to_string(fixture.cwd.join("dotfiles/f_no")).unwrap(),
+        "f_no"
+    );
+}
+
+#[test]
+fn test_when_round_trips() {
+    let table: toml::Table = r#"
+src = "dotfiles/f_app"
+dest = "~/.app"
+when = "dotr.os == 'linux'"
+"#
+    .parse()
+    .unwrap();
+    let package = Package::from_table("f_app", &table).unwrap();
+    assert_eq!(package.when.as_deref(), Some("dotr.os == 'linux'"));
+    assert_eq!(
+        package.to_table().get("when").and_then(|v| v.as_str()),
+        Some("dotr.os == 'linux'")
+    );
+}
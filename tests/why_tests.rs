@@ -0,0 +1,222 @@
+use std::{fs, path::PathBuf};
+
+use dotr::{
+    cli::{Cli, Command, InitArgs, WhyArgs, run_cli},
+    config::{Config, Exclusion, Inclusion},
+    context::Context,
+};
+
+struct TestFixture {
+    cwd: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let temp_dir = std::env::temp_dir().join(format!("dotr_why_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+        let fixture = Self { cwd: temp_dir };
+        run_cli(Cli {
+            command: Some(Command::Init(InitArgs::default())),
+            working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+        .expect("Init failed");
+        let other_os = if cfg!(target_os = "linux") {
+            "windows"
+        } else {
+            "linux"
+        };
+        fs::write(
+            fixture.cwd.join("config.toml"),
+            CONFIG.replace("{other_os}", other_os),
+        )
+        .expect("Failed to write config");
+        fixture
+    }
+
+    fn load(&self, profile: Option<&str>) -> (Config, Context) {
+        let conf = Config::from_path(&self.cwd).expect("Failed to load config");
+        let mut ctx = Context::from_config(&self.cwd, &conf).expect("Failed to create context");
+        ctx.set_profile(profile.map(|name| conf.profiles[name].clone()))
+            .expect("Failed to set profile");
+        (conf, ctx)
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.cwd).ok();
+    }
+}
+
+const CONFIG: &str = r#"
+banner = false
+
+[packages.f_zsh]
+src = "dotfiles/f_zsh"
+dest = "home/.zshrc"
+dependencies = ["starship"]
+
+[packages.f_starship]
+src = "dotfiles/f_starship"
+dest = "home/.config/starship.toml"
+aliases = ["starship"]
+dependencies = ["f_fonts"]
+
+[packages.f_fonts]
+src = "dotfiles/f_fonts"
+dest = "home/.fonts"
+
+[packages.f_kitty]
+src = "dotfiles/f_kitty"
+dest = "home/.config/kitty"
+skip = true
+
+[packages.f_other]
+src = "dotfiles/f_other"
+dest = "home/.other"
+only_on = ["{other_os}"]
+
+[packages.f_never]
+src = "dotfiles/f_never"
+dest = "home/.never"
+when = "false"
+
+[profiles.home]
+dependencies = ["f_zsh", "f_other"]
+"#;
+
+fn names(names: &[&str]) -> Option<Vec<String>> {
+    Some(names.iter().map(|name| name.to_string()).collect())
+}
+
+#[test]
+fn test_inclusion_reasons() {
+    let fixture = TestFixture::new();
+    let (conf, ctx) = fixture.load(Some("home"));
+    let selected = conf.select_packages(&ctx, &None).unwrap();
+    assert_eq!(
+        selected.included["f_zsh"],
+        Inclusion::Profile("home".to_string())
+    );
+    assert_eq!(
+        selected.included["f_starship"],
+        Inclusion::DependencyOf("f_zsh".to_string())
+    );
+    assert_eq!(
+        selected.included["f_fonts"],
+        Inclusion::DependencyOf("f_starship".to_string())
+    );
+
+    let selected = conf.select_packages(&ctx, &names(&["f_kitty"])).unwrap();
+    assert_eq!(selected.included["f_kitty"], Inclusion::Explicit);
+
+    let (conf, ctx) = fixture.load(None);
+    let selected = conf.select_packages(&ctx, &None).unwrap();
+    assert_eq!(selected.included["f_zsh"], Inclusion::Default);
+    assert_eq!(selected.included["f_fonts"], Inclusion::Default);
+}
+
+#[test]
+fn test_exclusion_reasons() {
+    let fixture = TestFixture::new();
+    let (conf, ctx) = fixture.load(None);
+    let selected = conf.select_packages(&ctx, &None).unwrap();
+    assert_eq!(selected.excluded["f_kitty"], Exclusion::Skipped);
+    assert!(matches!(selected.excluded["f_other"], Exclusion::Os(_)));
+    assert_eq!(selected.excluded["f_never"], Exclusion::Condition);
+    assert!(!selected.packages.contains_key("f_never"));
+
+    let selected = conf.select_packages(&ctx, &names(&["f_fonts"])).unwrap();
+    assert_eq!(selected.excluded["f_zsh"], Exclusion::NotRequested);
+
+    let (conf, ctx) = fixture.load(Some("home"));
+    let selected = conf.select_packages(&ctx, &None).unwrap();
+    assert_eq!(
+        selected.excluded["f_kitty"],
+        Exclusion::NotInProfile("home".to_string())
+    );
+}
+
+#[test]
+fn test_filter_packages_matches_the_selection() {
+    let fixture = TestFixture::new();
+    let (conf, ctx) = fixture.load(Some("home"));
+    let mut filtered: Vec<String> = conf
+        .filter_packages(&ctx, &None)
+        .unwrap()
+        .into_keys()
+        .collect();
+    filtered.sort();
+    assert_eq!(filtered, vec!["f_fonts", "f_starship", "f_zsh"]);
+}
+
+#[test]
+fn test_explanation_follows_the_dependency_chain() {
+    let fixture = TestFixture::new();
+    let (conf, ctx) = fixture.load(Some("home"));
+    let text = conf.explain_selection(&ctx, "f_fonts", &None).unwrap();
+    assert_eq!(
+        text,
+        "f_fonts: included\n  a dependency of f_starship\n    a dependency of f_zsh\n      a dependency of profile 'home'\n"
+    );
+}
+
+#[test]
+fn test_explanation_says_what_would_include() {
+    let fixture = TestFixture::new();
+    let (conf, ctx) = fixture.load(Some("home"));
+    let text = conf.explain_selection(&ctx, "f_kitty", &None).unwrap();
+    assert!(
+        text.starts_with(
+            "f_kitty: excluded, skip = true and not listed in the dependencies of profile 'home'\n"
+        ),
+        "{}",
+        text
+    );
+    assert!(
+        text.contains("would be included if passed with --packages or added to the dependencies of profile 'home'"),
+        "{}",
+        text
+    );
+
+    let text = conf.explain_selection(&ctx, "f_other", &None).unwrap();
+    assert!(
+        text.contains("  otherwise a dependency of profile 'home'\n"),
+        "{}",
+        text
+    );
+
+    let (conf, ctx) = fixture.load(None);
+    let text = conf.explain_selection(&ctx, "f_never", &None).unwrap();
+    assert!(
+        text.contains("its when condition `false` is false"),
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn test_why_command_resolves_aliases() {
+    let fixture = TestFixture::new();
+    run_cli(Cli {
+        command: Some(Command::Why(WhyArgs {
+            package: "starship".to_string(),
+            profile: Some("home".to_string()),
+            ..Default::default()
+        })),
+        working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+        ..Default::default()
+    })
+    .expect("Why failed");
+    let err = run_cli(Cli {
+        command: Some(Command::Why(WhyArgs {
+            package: "f_missing".to_string(),
+            ..Default::default()
+        })),
+        working_dir: Some(fixture.cwd.to_str().unwrap().to_string()),
+        ..Default::default()
+    })
+    .expect_err("Why should fail");
+    assert!(err.to_string().contains("f_missing"), "{}", err);
+}
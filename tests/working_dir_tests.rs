@@ -0,0 +1,98 @@
+use std::{fs, path::PathBuf};
+
+use dotr::cli::{Cli, Command, DOTR_DIR_VAR, DeployUpdateArgs, InitArgs, find_config_dir, run_cli};
+
+struct TestFixture {
+    root: PathBuf,
+}
+
+impl TestFixture {
+    fn new() -> Self {
+        let root =
+            std::env::temp_dir().join(format!("dotr_working_dir_test_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).expect("Failed to create temp dir");
+        Self {
+            root: root.canonicalize().unwrap(),
+        }
+    }
+
+    // A repository at `dir` with one package deploying to `deployed`
+    fn init_repo(&self, dir: &str) -> PathBuf {
+        let repo = self.root.join(dir);
+        run_cli(Cli {
+            command: Some(Command::Init(InitArgs::default())),
+            working_dir: Some(repo.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+        .expect("Init failed");
+        fs::write(repo.join("dotfiles/f_rc"), "rc\n").unwrap();
+        fs::write(
+            repo.join("config.toml"),
+            "banner = false\n\n[packages.f_rc]\nsrc = \"dotfiles/f_rc\"\ndest = \"deployed\"\n",
+        )
+        .unwrap();
+        repo
+    }
+}
+
+impl Drop for TestFixture {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.root).ok();
+    }
+}
+
+#[test]
+fn test_search_finds_the_closest_config() {
+    let fixture = TestFixture::new();
+    let repo = fixture.init_repo("repo");
+    let nested = repo.join("dotfiles/d_nvim/lua");
+    fs::create_dir_all(&nested).unwrap();
+
+    assert_eq!(find_config_dir(&nested, None), Some(repo.clone()));
+    assert_eq!(find_config_dir(&repo, None), Some(repo.clone()));
+    // A config.toml closer in wins, as a nested repository would
+    fs::write(nested.join("config.toml"), "").unwrap();
+    assert_eq!(find_config_dir(&nested, None), Some(nested.clone()));
+}
+
+#[test]
+fn test_search_stops_at_home() {
+    let fixture = TestFixture::new();
+    let repo = fixture.init_repo("repo");
+    let home = repo.join("home");
+    let below_home = home.join("projects");
+    fs::create_dir_all(&below_home).unwrap();
+
+    assert_eq!(find_config_dir(&below_home, Some(&home)), None);
+    fs::write(home.join("config.toml"), "").unwrap();
+    assert_eq!(find_config_dir(&below_home, Some(&home)), Some(home));
+}
+
+#[test]
+fn test_dotr_dir_pins_the_repository() {
+    let fixture = TestFixture::new();
+    let pinned = fixture.init_repo("pinned");
+    let flagged = fixture.init_repo("flagged");
+    // SAFETY: the only test in this file that reads DOTR_DIR
+    unsafe {
+        std::env::set_var(DOTR_DIR_VAR, &pinned);
+    }
+    let deploy = |working_dir: Option<&PathBuf>| {
+        run_cli(Cli {
+            command: Some(Command::Deploy(DeployUpdateArgs::default())),
+            working_dir: working_dir.map(|dir| dir.to_str().unwrap().to_string()),
+            ..Default::default()
+        })
+    };
+    let pinned_result = deploy(None);
+    // --working-dir beats DOTR_DIR
+    let flagged_result = deploy(Some(&flagged));
+    unsafe {
+        std::env::remove_var(DOTR_DIR_VAR);
+    }
+
+    pinned_result.expect("Deploy with DOTR_DIR failed");
+    flagged_result.expect("Deploy with --working-dir failed");
+    assert!(pinned.join("deployed").exists());
+    assert!(flagged.join("deployed").exists());
+}